@@ -0,0 +1,165 @@
+//! Benchmarks for the per-request proxy work: body preparation, SSE line
+//! reassembly and token-usage extraction.
+//!
+//! Run with `cargo bench`; compare against a baseline with
+//! `cargo bench -- --save-baseline before` and `--baseline before`.
+
+use std::hint::black_box;
+
+use aicore_router::constants::api::ANTHROPIC_DEFAULT_MAX_TOKENS;
+use aicore_router::proxy::{
+    LlmFamily, SseLines, TokenStats, extract_token_stats, format_sse_event, prepare_body,
+};
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use serde_json::{Value, json};
+
+const OPENAI_STREAM: &[u8] = include_bytes!("../tests/fixtures/sse/openai_chat.sse");
+const CLAUDE_STREAM: &[u8] = include_bytes!("../tests/fixtures/sse/claude_messages.sse");
+
+/// A chat request with a long conversation, the common case on the hot path.
+fn chat_body() -> Value {
+    let messages: Vec<Value> = (0..40)
+        .map(|i| {
+            json!({
+                "role": if i % 2 == 0 { "user" } else { "assistant" },
+                "content": "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(20),
+            })
+        })
+        .collect();
+    json!({
+        "model": "model",
+        "messages": messages,
+        "temperature": 0.7,
+        "max_tokens": 1024,
+        "stream": true,
+    })
+}
+
+fn anthropic_body() -> Value {
+    let mut body = chat_body();
+    body["system"] = json!("You are a helpful assistant.");
+    body
+}
+
+fn gemini_body() -> Value {
+    let contents: Vec<Value> = (0..40)
+        .map(|i| {
+            json!({
+                "role": if i % 2 == 0 { "user" } else { "model" },
+                "parts": [{"text": "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(20)}],
+            })
+        })
+        .collect();
+    json!({"contents": contents, "generationConfig": {"temperature": 0.7}})
+}
+
+fn bench_prepare_body(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prepare_body");
+    let cases = [
+        ("openai", LlmFamily::OpenAi, "gpt-4o", chat_body()),
+        (
+            "claude",
+            LlmFamily::Claude,
+            "anthropic--claude-4-sonnet",
+            anthropic_body(),
+        ),
+        ("gemini", LlmFamily::Gemini, "gemini-2.5-pro", gemini_body()),
+    ];
+    for (name, family, model, body) in cases {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || body.clone(),
+                |mut body| {
+                    prepare_body(
+                        &mut body,
+                        &family,
+                        true,
+                        model,
+                        model,
+                        &None,
+                        ANTHROPIC_DEFAULT_MAX_TOKENS,
+                    )
+                    .unwrap();
+                    body
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// `transcript` repeated `times`, as upstream sends a long answer.
+fn long_stream(transcript: &[u8], times: usize) -> Vec<u8> {
+    transcript.repeat(times)
+}
+
+fn bench_sse_lines(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sse_lines");
+    for (name, family, transcript) in [
+        ("openai", LlmFamily::OpenAi, OPENAI_STREAM),
+        ("claude", LlmFamily::Claude, CLAUDE_STREAM),
+    ] {
+        let stream = long_stream(transcript, 100);
+        let is_claude = matches!(family, LlmFamily::Claude);
+        group.throughput(Throughput::Bytes(stream.len() as u64));
+        // Upstream chunks rarely line up with events.
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut lines = SseLines::new(Vec::new());
+                let mut token_stats = TokenStats::default();
+                let mut forwarded = 0;
+                for chunk in stream.chunks(512) {
+                    lines.push(chunk);
+                    while let Some(data) = lines.next_data() {
+                        forwarded +=
+                            format_sse_event(&data, &family, is_claude, &mut token_stats, None)
+                                .len();
+                    }
+                }
+                black_box((forwarded, token_stats))
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_token_stats(c: &mut Criterion) {
+    let mut group = c.benchmark_group("extract_token_stats");
+    let cases = [
+        (
+            "openai_delta",
+            LlmFamily::OpenAi,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","model":"gpt-4o","choices":[{"index":0,"delta":{"content":"Hello"}}]}"#,
+        ),
+        (
+            "openai_usage",
+            LlmFamily::OpenAi,
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","model":"gpt-4o","choices":[],"usage":{"prompt_tokens":12,"completion_tokens":7,"total_tokens":19,"prompt_tokens_details":{"cached_tokens":0}}}"#,
+        ),
+        (
+            "claude_message_stop",
+            LlmFamily::Claude,
+            r#"{"type":"message_stop","amazon-bedrock-invocationMetrics":{"inputTokenCount":12,"outputTokenCount":9,"invocationLatency":640,"firstByteLatency":210,"cacheReadInputTokenCount":0,"cacheWriteInputTokenCount":0}}"#,
+        ),
+        (
+            "gemini_usage",
+            LlmFamily::Gemini,
+            r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"!"}]},"finishReason":"STOP"}],"usageMetadata":{"promptTokenCount":5,"candidatesTokenCount":4,"totalTokenCount":9}}"#,
+        ),
+    ];
+    for (name, family, data) in cases {
+        group.bench_function(name, |b| {
+            b.iter(|| extract_token_stats(black_box(data), &family))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_prepare_body,
+    bench_sse_lines,
+    bench_token_stats
+);
+criterion_main!(benches);
@@ -0,0 +1,475 @@
+//! Priority-aware admission control.
+//!
+//! Caps the number of requests in flight upstream. Once the cap is reached,
+//! new requests wait in a queue ordered by [`Priority`] (FIFO within a class)
+//! and take over freed slots highest-priority first. When the queue is full,
+//! a higher-priority arrival displaces the newest lowest-priority waiter;
+//! waiters that time out or are displaced are shed with 503. Under pressure,
+//! low-priority traffic absorbs the delay and the rejections, and
+//! high-priority traffic keeps flowing.
+//!
+//! Cost shedding goes further once the queue is overloaded — deeper than
+//! `cost_shed_queue_depth`, or its oldest waiter queued longer than
+//! `cost_shed_wait_ms`. Each arrival then sheds one request, chosen among
+//! the waiters and itself: the costliest by estimated tokens within the
+//! lowest priority class. Long-context and long-output requests give way,
+//! and small interactive requests keep flowing.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::http::HeaderMap;
+use serde_json::Value;
+use tokio::sync::oneshot;
+
+use crate::config::{AdmissionConfig, Priority};
+use crate::constants::api::ACR_PRIORITY_HEADER;
+
+/// Why a request was not admitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shed {
+    /// The queue was full of equal-or-higher priority requests.
+    QueueFull,
+    /// Displaced from the queue by a higher-priority request.
+    Displaced,
+    /// No slot freed up within `queue_timeout_secs`.
+    TimedOut,
+    /// The costliest request while the queue was overloaded.
+    Costly,
+}
+
+impl std::fmt::Display for Shed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::QueueFull => f.write_str("admission queue full"),
+            Self::Displaced => f.write_str("displaced by higher-priority requests"),
+            Self::TimedOut => f.write_str("timed out waiting for capacity"),
+            Self::Costly => f.write_str("largest estimated token cost under overload"),
+        }
+    }
+}
+
+/// Resolve the priority of a request: the `x-acr-priority` header can lower
+/// the key's configured priority but never raise it, so a client can mark
+/// its own background traffic without being able to jump the queue.
+pub fn request_priority(headers: &HeaderMap, key_priority: Priority) -> Priority {
+    let requested = headers
+        .get(ACR_PRIORITY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| match v.trim().to_ascii_lowercase().as_str() {
+            "low" => Some(Priority::Low),
+            "normal" => Some(Priority::Normal),
+            "high" => Some(Priority::High),
+            _ => None,
+        });
+    match requested {
+        Some(p) => p.min(key_priority),
+        None => key_priority,
+    }
+}
+
+/// Rough token cost of a request: its text at four characters per token,
+/// plus the output it asks for (`max_tokens` and its per-API spellings).
+pub fn estimate_tokens(body: &Value) -> u64 {
+    fn text_chars(value: &Value) -> usize {
+        match value {
+            Value::String(s) => s.len(),
+            Value::Array(items) => items.iter().map(text_chars).sum(),
+            Value::Object(map) => map.values().map(text_chars).sum(),
+            _ => 0,
+        }
+    }
+    let output = [
+        "/max_tokens",
+        "/max_completion_tokens",
+        "/max_output_tokens",
+        "/generationConfig/maxOutputTokens",
+    ]
+    .iter()
+    .find_map(|pointer| body.pointer(pointer).and_then(Value::as_u64))
+    .unwrap_or(0);
+    (text_chars(body) / 4) as u64 + output
+}
+
+/// Waiters ordered highest priority first, then by arrival.
+type QueueKey = (std::cmp::Reverse<Priority>, u64);
+
+#[derive(Debug)]
+struct Waiter {
+    tx: oneshot::Sender<Result<(), Shed>>,
+    cost: u64,
+    queued_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    in_flight: usize,
+    next_seq: u64,
+    waiters: BTreeMap<QueueKey, Waiter>,
+}
+
+/// When cost shedding starts (see the module docs).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CostShedding {
+    pub queue_depth: Option<usize>,
+    pub wait: Option<Duration>,
+}
+
+impl CostShedding {
+    pub fn enabled(&self) -> bool {
+        self.queue_depth.is_some() || self.wait.is_some()
+    }
+
+    fn overloaded(&self, state: &State) -> bool {
+        let deep = self
+            .queue_depth
+            .is_some_and(|depth| state.waiters.len() >= depth);
+        let slow = self.wait.is_some_and(|wait| {
+            state
+                .waiters
+                .values()
+                .map(|w| w.queued_at)
+                .min()
+                .is_some_and(|oldest| oldest.elapsed() >= wait)
+        });
+        deep || slow
+    }
+}
+
+/// Shared admission controller; cheap to clone.
+#[derive(Debug, Clone)]
+pub struct AdmissionController {
+    state: Arc<Mutex<State>>,
+    max_concurrent: usize,
+    max_queue: usize,
+    queue_timeout: Duration,
+    cost_shedding: CostShedding,
+}
+
+/// A held slot. Dropping it hands the slot to the next waiter.
+#[derive(Debug)]
+pub struct AdmissionPermit {
+    controller: AdmissionController,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        self.controller.release();
+    }
+}
+
+/// Removes a waiter from the queue if its future is dropped (client went
+/// away) and returns a slot that was granted but never picked up.
+struct WaitGuard {
+    controller: AdmissionController,
+    key: QueueKey,
+    rx: Option<oneshot::Receiver<Result<(), Shed>>>,
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        let removed = match self.controller.state.lock() {
+            Ok(mut state) => state.waiters.remove(&self.key).is_some(),
+            Err(_) => return,
+        };
+        if !removed
+            && let Some(mut rx) = self.rx.take()
+            && let Ok(Ok(())) = rx.try_recv()
+        {
+            self.controller.release();
+        }
+    }
+}
+
+impl AdmissionController {
+    /// Build a controller from config; `None` when admission control is off.
+    pub fn from_config(config: &AdmissionConfig) -> Option<Self> {
+        let max_concurrent = config.max_concurrent_requests?;
+        Some(
+            Self::new(
+                max_concurrent,
+                config.max_queue,
+                Duration::from_secs(config.queue_timeout_secs),
+            )
+            .with_cost_shedding(CostShedding {
+                queue_depth: config.cost_shed_queue_depth,
+                wait: config.cost_shed_wait_ms.map(Duration::from_millis),
+            }),
+        )
+    }
+
+    pub fn new(max_concurrent: usize, max_queue: usize, queue_timeout: Duration) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State::default())),
+            max_concurrent,
+            max_queue,
+            queue_timeout,
+            cost_shedding: CostShedding::default(),
+        }
+    }
+
+    pub fn with_cost_shedding(mut self, cost_shedding: CostShedding) -> Self {
+        self.cost_shedding = cost_shedding;
+        self
+    }
+
+    /// Whether `acquire` looks at request costs, so callers can skip
+    /// estimating them otherwise.
+    pub fn sheds_by_cost(&self) -> bool {
+        self.cost_shedding.enabled()
+    }
+
+    /// Wait for a slot at `priority` for a request estimated at `cost`
+    /// tokens.
+    pub async fn acquire(&self, priority: Priority, cost: u64) -> Result<AdmissionPermit, Shed> {
+        let (key, rx) = {
+            let mut state = self.state.lock().map_err(|_| Shed::QueueFull)?;
+            if state.in_flight < self.max_concurrent && state.waiters.is_empty() {
+                state.in_flight += 1;
+                return Ok(self.permit());
+            }
+
+            let key = (std::cmp::Reverse(priority), state.next_seq);
+            if self.cost_shedding.overloaded(&state) {
+                // Lowest priority first, then costliest, then newest.
+                let victim = state
+                    .waiters
+                    .iter()
+                    .map(|(key, waiter)| (*key, waiter.cost))
+                    .chain([(key, cost)])
+                    .min_by_key(|&((std::cmp::Reverse(priority), seq), cost)| {
+                        (priority, std::cmp::Reverse(cost), std::cmp::Reverse(seq))
+                    })
+                    .map(|(key, _)| key);
+                if victim == Some(key) {
+                    return Err(Shed::Costly);
+                }
+                if let Some(waiter) = victim.and_then(|victim| state.waiters.remove(&victim)) {
+                    let _ = waiter.tx.send(Err(Shed::Costly));
+                }
+            }
+
+            if state.waiters.len() >= self.max_queue {
+                // The last entry is the newest of the lowest priority class.
+                match state.waiters.last_key_value() {
+                    Some((&(std::cmp::Reverse(lowest), _), _)) if lowest < priority => {
+                        if let Some((_, waiter)) = state.waiters.pop_last() {
+                            let _ = waiter.tx.send(Err(Shed::Displaced));
+                        }
+                    }
+                    _ => return Err(Shed::QueueFull),
+                }
+            }
+
+            let (tx, rx) = oneshot::channel();
+            state.next_seq += 1;
+            state.waiters.insert(
+                key,
+                Waiter {
+                    tx,
+                    cost,
+                    queued_at: Instant::now(),
+                },
+            );
+            (key, rx)
+        };
+
+        let mut guard = WaitGuard {
+            controller: self.clone(),
+            key,
+            rx: Some(rx),
+        };
+        let rx = guard.rx.as_mut().expect("receiver is set above");
+        match tokio::time::timeout(self.queue_timeout, rx).await {
+            Ok(Ok(Ok(()))) => {
+                // The slot is ours now; don't let the guard return it.
+                guard.rx = None;
+                Ok(self.permit())
+            }
+            Ok(Ok(Err(shed))) => Err(shed),
+            Ok(Err(_)) => Err(Shed::Displaced),
+            // Dropping the guard dequeues us, or returns a slot granted
+            // between the timeout firing and now.
+            Err(_) => Err(Shed::TimedOut),
+        }
+    }
+
+    /// Requests currently holding a slot and requests waiting for one.
+    pub fn load(&self) -> (usize, usize) {
+        self.state
+            .lock()
+            .map(|s| (s.in_flight, s.waiters.len()))
+            .unwrap_or_default()
+    }
+
+    fn permit(&self) -> AdmissionPermit {
+        AdmissionPermit {
+            controller: self.clone(),
+        }
+    }
+
+    /// Hand a freed slot to the highest-priority live waiter, or give it back.
+    fn release(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        while let Some((_, waiter)) = state.waiters.pop_first() {
+            if waiter.tx.send(Ok(())).is_ok() {
+                return;
+            }
+        }
+        state.in_flight = state.in_flight.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller(max_concurrent: usize, max_queue: usize) -> AdmissionController {
+        AdmissionController::new(max_concurrent, max_queue, Duration::from_secs(5))
+    }
+
+    #[test]
+    fn header_can_lower_but_not_raise_priority() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            request_priority(&headers, Priority::Normal),
+            Priority::Normal
+        );
+        headers.insert(ACR_PRIORITY_HEADER, "low".parse().unwrap());
+        assert_eq!(request_priority(&headers, Priority::Normal), Priority::Low);
+        headers.insert(ACR_PRIORITY_HEADER, "HIGH".parse().unwrap());
+        assert_eq!(
+            request_priority(&headers, Priority::Normal),
+            Priority::Normal
+        );
+        assert_eq!(request_priority(&headers, Priority::High), Priority::High);
+        headers.insert(ACR_PRIORITY_HEADER, "urgent".parse().unwrap());
+        assert_eq!(request_priority(&headers, Priority::Low), Priority::Low);
+    }
+
+    #[tokio::test]
+    async fn freed_slots_go_to_higher_priority_first() {
+        let admission = controller(1, 8);
+        let held = admission.acquire(Priority::Normal, 0).await.unwrap();
+
+        let low = tokio::spawn({
+            let admission = admission.clone();
+            async move { admission.acquire(Priority::Low, 0).await.map(|_| ()) }
+        });
+        tokio::task::yield_now().await;
+        let high = tokio::spawn({
+            let admission = admission.clone();
+            async move {
+                let permit = admission.acquire(Priority::High, 0).await;
+                // Hold the slot until the low waiter can observe ordering.
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                permit.map(|_| ())
+            }
+        });
+        while admission.load().1 < 2 {
+            tokio::task::yield_now().await;
+        }
+
+        drop(held);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        // High took the slot; low is still queued behind it.
+        assert_eq!(admission.load(), (1, 1));
+        high.await.unwrap().unwrap();
+        low.await.unwrap().unwrap();
+        assert_eq!(admission.load(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn full_queue_sheds_lowest_priority() {
+        let admission = controller(1, 1);
+        let _held = admission.acquire(Priority::Normal, 0).await.unwrap();
+
+        let low = tokio::spawn({
+            let admission = admission.clone();
+            async move { admission.acquire(Priority::Low, 0).await.map(|_| ()) }
+        });
+        while admission.load().1 < 1 {
+            tokio::task::yield_now().await;
+        }
+
+        // Another low request can't displace its peer.
+        assert_eq!(
+            admission.acquire(Priority::Low, 0).await.err(),
+            Some(Shed::QueueFull)
+        );
+
+        // A normal one can.
+        let normal = tokio::spawn({
+            let admission = admission.clone();
+            async move { admission.acquire(Priority::Normal, 0).await.map(|_| ()) }
+        });
+        assert_eq!(low.await.unwrap(), Err(Shed::Displaced));
+        normal.abort();
+    }
+
+    #[tokio::test]
+    async fn overload_sheds_the_costliest_request_first() {
+        let admission = controller(1, 8).with_cost_shedding(CostShedding {
+            queue_depth: Some(2),
+            wait: None,
+        });
+        let _held = admission.acquire(Priority::Normal, 0).await.unwrap();
+        let wait = |cost| {
+            let admission = admission.clone();
+            tokio::spawn(async move { admission.acquire(Priority::Normal, cost).await.map(|_| ()) })
+        };
+        let large = wait(100_000);
+        let small = wait(500);
+        while admission.load().1 < 2 {
+            tokio::task::yield_now().await;
+        }
+
+        // At the threshold: the queued large request makes room for a small one.
+        let tiny = wait(50);
+        assert_eq!(large.await.unwrap(), Err(Shed::Costly));
+        while admission.load().1 < 2 {
+            tokio::task::yield_now().await;
+        }
+        // A new arrival costlier than everyone queued is shed itself.
+        assert_eq!(
+            admission.acquire(Priority::Normal, 10_000).await.err(),
+            Some(Shed::Costly)
+        );
+        small.abort();
+        tiny.abort();
+
+        let body = serde_json::json!({
+            "messages": [{"role": "user", "content": "x".repeat(4000)}],
+            "max_tokens": 1000,
+        });
+        assert_eq!(estimate_tokens(&body), 2001);
+    }
+
+    #[tokio::test]
+    async fn waiters_time_out_and_cancelled_waiters_leave_the_queue() {
+        let admission = AdmissionController::new(1, 8, Duration::from_millis(10));
+        let held = admission.acquire(Priority::High, 0).await.unwrap();
+        assert_eq!(
+            admission.acquire(Priority::Normal, 0).await.err(),
+            Some(Shed::TimedOut)
+        );
+        assert_eq!(admission.load(), (1, 0));
+
+        let waiter = tokio::spawn({
+            let admission = admission.clone();
+            async move { admission.acquire(Priority::Normal, 0).await.map(|_| ()) }
+        });
+        while admission.load().1 < 1 {
+            tokio::task::yield_now().await;
+        }
+        waiter.abort();
+        let _ = waiter.await;
+        assert_eq!(admission.load(), (1, 0));
+
+        drop(held);
+        assert_eq!(admission.load(), (0, 0));
+    }
+}
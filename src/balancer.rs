@@ -3,13 +3,16 @@
 //! Supports multiple strategies:
 //! - Round-robin: Distribute requests evenly across providers
 //! - Fallback: Always try the first provider, only switch on 429
+//!
+//! Provider `schedule` windows then move providers ahead of or behind the
+//! others by the local clock.
 
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 use anyhow::{Result, bail};
 
-use crate::config::{LoadBalancingStrategy, Provider};
+use crate::config::{LoadBalancingStrategy, Provider, ScheduleAction};
 
 /// An iterator over providers in load-balanced order (zero-allocation).
 pub struct OrderedProviders<'a> {
@@ -89,6 +92,67 @@ impl LoadBalancer {
             len,
         }
     }
+
+    /// Like [`get_ordered_providers`](Self::get_ordered_providers), but start
+    /// from `preferred` when it names an enabled provider — used to keep a
+    /// conversation on the provider holding its prompt cache. The remaining
+    /// providers follow in configured order as fallbacks, and the round-robin
+    /// position is left untouched.
+    pub fn get_ordered_providers_preferring(
+        &self,
+        preferred: Option<&str>,
+    ) -> OrderedProviders<'_> {
+        let start = preferred.and_then(|name| self.providers.iter().position(|p| p.name == name));
+        match start {
+            Some(start) => OrderedProviders {
+                providers: &self.providers,
+                start,
+                index: 0,
+                len: self.providers.len(),
+            },
+            None => self.get_ordered_providers(),
+        }
+    }
+
+    /// [`get_ordered_providers_preferring`](Self::get_ordered_providers_preferring)
+    /// with the providers' schedules applied at the local time.
+    pub fn get_scheduled_providers(&self, preferred: Option<&str>) -> Vec<&Provider> {
+        order_by_schedule(
+            self.get_ordered_providers_preferring(preferred),
+            chrono::Local::now().naive_local(),
+        )
+    }
+}
+
+/// Stable-sort `providers` so those inside a `prefer` window at `now` come
+/// first and those inside an `avoid` window last. An avoided provider is
+/// still tried as a last resort; when windows of both kinds match, avoid
+/// wins.
+pub fn order_by_schedule<'a>(
+    providers: impl Iterator<Item = &'a Provider>,
+    now: chrono::NaiveDateTime,
+) -> Vec<&'a Provider> {
+    let mut ordered: Vec<&Provider> = providers.collect();
+    if ordered.iter().any(|p| !p.schedule.is_empty()) {
+        ordered.sort_by_key(|p| schedule_rank(p, now));
+    }
+    ordered
+}
+
+fn schedule_rank(provider: &Provider, now: chrono::NaiveDateTime) -> u8 {
+    let active = |action| {
+        provider
+            .schedule
+            .iter()
+            .any(|w| w.action == action && w.contains(now))
+    };
+    if active(ScheduleAction::Avoid) {
+        2
+    } else if active(ScheduleAction::Prefer) {
+        0
+    } else {
+        1
+    }
 }
 
 #[cfg(test)]
@@ -105,6 +169,9 @@ mod tests {
             resource_group: "default".to_string(),
             weight: 1,
             enabled,
+            schedule: vec![],
+            headers: Default::default(),
+            quota: Default::default(),
         }
     }
 
@@ -174,6 +241,33 @@ mod tests {
         assert_eq!(names, vec!["provider1", "provider2", "provider3"]);
     }
 
+    #[test]
+    fn test_preferred_provider_goes_first_without_rotating() {
+        let providers = vec![
+            create_test_provider("provider1", true),
+            create_test_provider("provider2", true),
+            create_test_provider("provider3", true),
+        ];
+        let balancer = LoadBalancer::new(providers, LoadBalancingStrategy::RoundRobin).unwrap();
+
+        let names: Vec<&str> = balancer
+            .get_ordered_providers_preferring(Some("provider2"))
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["provider2", "provider3", "provider1"]);
+
+        // The affinity hit didn't consume a round-robin slot.
+        let first = balancer.get_ordered_providers().next().unwrap();
+        assert_eq!(first.name, "provider1");
+
+        // Unknown names fall back to the normal strategy.
+        let first = balancer
+            .get_ordered_providers_preferring(Some("gone"))
+            .next()
+            .unwrap();
+        assert_eq!(first.name, "provider2");
+    }
+
     #[test]
     fn test_new_rejects_when_no_enabled_providers() {
         // Empty list rejected outright.
@@ -186,4 +280,49 @@ mod tests {
         ];
         assert!(LoadBalancer::new(all_disabled, LoadBalancingStrategy::RoundRobin).is_err());
     }
+
+    #[test]
+    fn test_schedule_windows_reorder_providers() {
+        use crate::config::ScheduleWindow;
+        use chrono::{NaiveDate, NaiveTime, Weekday};
+
+        let window = |action, start: &str, end: &str, days: Vec<Weekday>| ScheduleWindow {
+            action,
+            start: NaiveTime::parse_from_str(start, "%H:%M").unwrap(),
+            end: NaiveTime::parse_from_str(end, "%H:%M").unwrap(),
+            days,
+        };
+        let mut eu = create_test_provider("eu", true);
+        // Nightly maintenance, Saturday night into Sunday morning.
+        eu.schedule = vec![window(
+            ScheduleAction::Avoid,
+            "22:00",
+            "04:00",
+            vec![Weekday::Sat],
+        )];
+        let mut us = create_test_provider("us", true);
+        us.schedule = vec![window(ScheduleAction::Prefer, "14:00", "18:00", vec![])];
+        let other = create_test_provider("other", true);
+        let providers = [eu, other, us];
+        // 2026-10-17 is a Saturday.
+        let at = |day: u32, time: &str| {
+            NaiveDate::from_ymd_opt(2026, 10, day)
+                .unwrap()
+                .and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap())
+        };
+        let names = |now| -> Vec<String> {
+            order_by_schedule(providers.iter(), now)
+                .into_iter()
+                .map(|p| p.name.clone())
+                .collect()
+        };
+
+        assert_eq!(names(at(17, "12:00")), vec!["eu", "other", "us"]);
+        assert_eq!(names(at(17, "15:00")), vec!["us", "eu", "other"]);
+        assert_eq!(names(at(17, "23:00")), vec!["other", "us", "eu"]);
+        assert_eq!(names(at(18, "03:59")), vec!["other", "us", "eu"]);
+        assert_eq!(names(at(18, "04:00")), vec!["eu", "other", "us"]);
+        // Friday night is outside the Saturday window.
+        assert_eq!(names(at(16, "23:00")), vec!["eu", "other", "us"]);
+    }
 }
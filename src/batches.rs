@@ -0,0 +1,615 @@
+//! Anthropic Message Batches facade (`/v1/messages/batches`).
+//!
+//! AI Core has no batch endpoint, so acr accepts a batch, answers at once with
+//! an `in_progress` [`MessageBatch`], and works through the contained requests
+//! in the background by sending each one through the regular `/v1/messages`
+//! pipeline (auth, quotas, provider fallback, translation). A process-wide
+//! semaphore caps how many batch requests are in flight, so one large batch
+//! can't crowd out interactive traffic.
+//!
+//! Finished batches are written to `batches.dir` (one JSON file per batch) and
+//! reloaded on startup. Batches still processing when acr stops are lost.
+//! Every batch is visible only to the API key that created it.
+//!
+//! Reference: <https://docs.claude.com/en/api/creating-message-batches>
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::{OwnedSemaphorePermit, RwLock, Semaphore};
+
+use crate::config::BatchesConfig;
+use crate::constants::batches::{
+    DEFAULT_LIST_LIMIT, EXPIRY_HOURS, ID_PREFIX, MAX_LIST_LIMIT, MAX_REQUESTS_PER_BATCH,
+};
+
+/// One entry of a create request's `requests` array.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchRequest {
+    pub custom_id: String,
+    /// A complete Messages API request body.
+    pub params: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateBatchBody {
+    requests: Vec<BatchRequest>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessingStatus {
+    InProgress,
+    Ended,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestCounts {
+    pub processing: u64,
+    pub succeeded: u64,
+    pub errored: u64,
+    pub canceled: u64,
+    pub expired: u64,
+}
+
+/// The batch object returned by every batch endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageBatch {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub object_type: String,
+    pub processing_status: ProcessingStatus,
+    pub request_counts: RequestCounts,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub cancel_initiated_at: Option<DateTime<Utc>>,
+    /// Relative to the router's base URL; the Anthropic SDKs resolve it
+    /// against their configured `base_url`.
+    pub results_url: Option<String>,
+}
+
+/// Outcome of one request in a batch.
+#[derive(Debug, Clone)]
+pub enum BatchOutcome {
+    /// The Messages API response body.
+    Succeeded(Value),
+    /// An Anthropic `{"type":"error","error":{...}}` envelope.
+    Errored(Value),
+    /// The batch's 24h window closed before the request was sent.
+    Expired,
+}
+
+impl BatchOutcome {
+    fn to_result(&self) -> Value {
+        match self {
+            BatchOutcome::Succeeded(message) => json!({"type": "succeeded", "message": message}),
+            BatchOutcome::Errored(error) => json!({"type": "errored", "error": error}),
+            BatchOutcome::Expired => json!({"type": "expired"}),
+        }
+    }
+}
+
+/// Query parameters of the list endpoint.
+#[derive(Debug, Default, Deserialize)]
+pub struct ListParams {
+    pub limit: Option<usize>,
+    pub after_id: Option<String>,
+    pub before_id: Option<String>,
+}
+
+/// One page of the list endpoint, newest batch first.
+#[derive(Debug, Serialize)]
+pub struct BatchPage {
+    pub data: Vec<MessageBatch>,
+    pub has_more: bool,
+    pub first_id: Option<String>,
+    pub last_id: Option<String>,
+}
+
+/// A batch as kept in memory and on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredBatch {
+    /// Creation order; breaks ties between batches created in the same instant.
+    seq: u64,
+    /// `quota::hash_api_key` of the creating key.
+    owner: String,
+    batch: MessageBatch,
+    /// `{"custom_id", "result"}` lines in request order; `result` stays null
+    /// until the request finishes.
+    results: Vec<Value>,
+}
+
+/// Shared batch registry plus the concurrency limit for batch execution.
+#[derive(Debug, Clone)]
+pub struct BatchStore {
+    batches: Arc<RwLock<HashMap<String, StoredBatch>>>,
+    permits: Arc<Semaphore>,
+    dir: Option<PathBuf>,
+}
+
+impl BatchStore {
+    /// A store that never touches disk.
+    pub fn in_memory(max_concurrency: usize) -> Self {
+        Self {
+            batches: Arc::new(RwLock::new(HashMap::new())),
+            permits: Arc::new(Semaphore::new(max_concurrency)),
+            dir: None,
+        }
+    }
+
+    /// Open the on-disk store at `config.dir`, creating the directory if
+    /// needed and loading every previously finished batch. Unreadable files
+    /// are skipped with a warning rather than failing startup.
+    pub fn open(config: &BatchesConfig) -> Result<Self> {
+        let dir = PathBuf::from(&config.dir);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create batch directory: {}", dir.display()))?;
+
+        let mut batches = HashMap::new();
+        let entries = std::fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read batch directory: {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let loaded = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|s| Ok(serde_json::from_str::<StoredBatch>(&s)?));
+            match loaded {
+                Ok(stored) => {
+                    batches.insert(stored.batch.id.clone(), stored);
+                }
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable batch file {}: {:#}", path.display(), e)
+                }
+            }
+        }
+
+        Ok(Self {
+            batches: Arc::new(RwLock::new(batches)),
+            permits: Arc::new(Semaphore::new(config.max_concurrency)),
+            dir: Some(dir),
+        })
+    }
+
+    /// Register a new in-progress batch for `owner` and return it.
+    pub async fn create(&self, owner: &str, requests: &[BatchRequest]) -> MessageBatch {
+        let now = Utc::now();
+        let batch = MessageBatch {
+            id: format!("{ID_PREFIX}{}", uuid::Uuid::new_v4().simple()),
+            object_type: "message_batch".to_string(),
+            processing_status: ProcessingStatus::InProgress,
+            request_counts: RequestCounts {
+                processing: requests.len() as u64,
+                ..Default::default()
+            },
+            ended_at: None,
+            created_at: now,
+            expires_at: now + chrono::Duration::hours(EXPIRY_HOURS),
+            archived_at: None,
+            cancel_initiated_at: None,
+            results_url: None,
+        };
+        let results = requests
+            .iter()
+            .map(|r| json!({"custom_id": r.custom_id, "result": null}))
+            .collect();
+
+        let mut batches = self.batches.write().await;
+        let seq = batches.values().map(|b| b.seq + 1).max().unwrap_or(0);
+        batches.insert(
+            batch.id.clone(),
+            StoredBatch {
+                seq,
+                owner: owner.to_string(),
+                batch: batch.clone(),
+                results,
+            },
+        );
+        batch
+    }
+
+    /// Wait for a free execution slot. Hold the permit while the request runs.
+    pub async fn acquire_slot(&self) -> OwnedSemaphorePermit {
+        self.permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("batch semaphore is never closed")
+    }
+
+    /// Record the outcome of the request at `index`.
+    pub async fn record(&self, id: &str, index: usize, outcome: BatchOutcome) {
+        let mut batches = self.batches.write().await;
+        let Some(stored) = batches.get_mut(id) else {
+            return;
+        };
+        let Some(line) = stored.results.get_mut(index) else {
+            return;
+        };
+        if !line["result"].is_null() {
+            return;
+        }
+        line["result"] = outcome.to_result();
+        let counts = &mut stored.batch.request_counts;
+        counts.processing = counts.processing.saturating_sub(1);
+        match outcome {
+            BatchOutcome::Succeeded(_) => counts.succeeded += 1,
+            BatchOutcome::Errored(_) => counts.errored += 1,
+            BatchOutcome::Expired => counts.expired += 1,
+        }
+    }
+
+    /// Mark a batch as ended and persist it. Requests that never recorded an
+    /// outcome (their task panicked) are reported as errored.
+    pub async fn finish(&self, id: &str) {
+        let snapshot = {
+            let mut batches = self.batches.write().await;
+            let Some(stored) = batches.get_mut(id) else {
+                return;
+            };
+            let lost = BatchOutcome::Errored(json!({
+                "type": "error",
+                "error": {"type": "api_error", "message": "Batch request did not complete"},
+            }));
+            for line in stored.results.iter_mut() {
+                if line["result"].is_null() {
+                    line["result"] = lost.to_result();
+                    stored.batch.request_counts.errored += 1;
+                }
+            }
+            stored.batch.request_counts.processing = 0;
+            stored.batch.processing_status = ProcessingStatus::Ended;
+            stored.batch.ended_at = Some(Utc::now());
+            stored.batch.results_url = Some(format!("/v1/messages/batches/{id}/results"));
+            stored.clone()
+        };
+
+        if let Some(ref dir) = self.dir {
+            let dir = dir.clone();
+            let id = id.to_string();
+            let written = tokio::task::spawn_blocking(move || persist(&dir, &snapshot)).await;
+            match written {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!("Failed to persist batch {}: {:#}", id, e),
+                Err(e) => tracing::warn!("Failed to persist batch {}: {}", id, e),
+            }
+        }
+    }
+
+    /// Look up a batch owned by `owner`.
+    pub async fn get(&self, owner: &str, id: &str) -> Option<MessageBatch> {
+        let batches = self.batches.read().await;
+        batches
+            .get(id)
+            .filter(|b| b.owner == owner)
+            .map(|b| b.batch.clone())
+    }
+
+    /// The batch plus its JSONL results body, for a batch owned by `owner`.
+    /// The body is empty until the batch has ended.
+    pub async fn results(&self, owner: &str, id: &str) -> Option<(MessageBatch, String)> {
+        let batches = self.batches.read().await;
+        let stored = batches.get(id).filter(|b| b.owner == owner)?;
+        let body = match stored.batch.processing_status {
+            ProcessingStatus::Ended => stored
+                .results
+                .iter()
+                .map(|line| format!("{line}\n"))
+                .collect(),
+            ProcessingStatus::InProgress => String::new(),
+        };
+        Some((stored.batch.clone(), body))
+    }
+
+    /// One page of `owner`'s batches, newest first. `after_id` pages towards
+    /// older batches and `before_id` towards newer ones; an unknown cursor
+    /// yields an empty page.
+    pub async fn list(&self, owner: &str, params: &ListParams) -> BatchPage {
+        let batches = self.batches.read().await;
+        let mut owned: Vec<&StoredBatch> = batches.values().filter(|b| b.owner == owner).collect();
+        owned.sort_by_key(|b| std::cmp::Reverse(b.seq));
+
+        let limit = params
+            .limit
+            .unwrap_or(DEFAULT_LIST_LIMIT)
+            .clamp(1, MAX_LIST_LIMIT);
+        let position = |id: &str| owned.iter().position(|b| b.batch.id == id);
+
+        let (start, end, has_more) = if let Some(ref before) = params.before_id {
+            let end = position(before).unwrap_or(0);
+            let start = end.saturating_sub(limit);
+            (start, end, start > 0)
+        } else {
+            let start = match params.after_id {
+                Some(ref after) => position(after).map_or(owned.len(), |p| p + 1),
+                None => 0,
+            };
+            let end = (start + limit).min(owned.len());
+            (start, end, end < owned.len())
+        };
+
+        let data: Vec<MessageBatch> = owned[start..end].iter().map(|b| b.batch.clone()).collect();
+        BatchPage {
+            first_id: data.first().map(|b| b.id.clone()),
+            last_id: data.last().map(|b| b.id.clone()),
+            has_more,
+            data,
+        }
+    }
+}
+
+/// Write a finished batch atomically (temp file + rename).
+fn persist(dir: &std::path::Path, stored: &StoredBatch) -> Result<()> {
+    let path = dir.join(format!("{}.json", stored.batch.id));
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(stored)?)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, &path)
+        .with_context(|| format!("Failed to rename {} into place", tmp.display()))?;
+    Ok(())
+}
+
+/// Parse and validate a create-batch body. Problems name the offending index
+/// so clients can locate them in large batches.
+pub fn parse_requests(body: Value) -> Result<Vec<BatchRequest>, String> {
+    let parsed: CreateBatchBody =
+        serde_json::from_value(body).map_err(|e| format!("Invalid batch body: {e}"))?;
+    let requests = parsed.requests;
+    if requests.is_empty() {
+        return Err("requests must contain at least one request".to_string());
+    }
+    if requests.len() > MAX_REQUESTS_PER_BATCH {
+        return Err(format!(
+            "A batch may contain at most {MAX_REQUESTS_PER_BATCH} requests"
+        ));
+    }
+
+    let mut seen = HashSet::new();
+    for (i, request) in requests.iter().enumerate() {
+        if !is_valid_custom_id(&request.custom_id) {
+            return Err(format!(
+                "requests[{i}].custom_id must be 1-64 characters of [A-Za-z0-9_-]"
+            ));
+        }
+        if !seen.insert(request.custom_id.as_str()) {
+            return Err(format!(
+                "requests[{i}].custom_id '{}' is not unique within the batch",
+                request.custom_id
+            ));
+        }
+        if !request.params.is_object() {
+            return Err(format!("requests[{i}].params must be an object"));
+        }
+        if request
+            .params
+            .get("model")
+            .and_then(|m| m.as_str())
+            .is_none()
+        {
+            return Err(format!("requests[{i}].params.model is required"));
+        }
+        if request.params.get("stream").and_then(|s| s.as_bool()) == Some(true) {
+            return Err(format!(
+                "requests[{i}].params.stream is not supported in batches"
+            ));
+        }
+    }
+    Ok(requests)
+}
+
+fn is_valid_custom_id(id: &str) -> bool {
+    (1..=64).contains(&id.len())
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn requests(ids: &[&str]) -> Vec<BatchRequest> {
+        ids.iter()
+            .map(|id| BatchRequest {
+                custom_id: id.to_string(),
+                params: json!({"model": "claude-sonnet-4-5", "max_tokens": 16, "messages": []}),
+            })
+            .collect()
+    }
+
+    fn body(ids: &[&str]) -> Value {
+        let requests: Vec<Value> = ids
+            .iter()
+            .map(|id| json!({"custom_id": id, "params": {"model": "m", "messages": []}}))
+            .collect();
+        json!({ "requests": requests })
+    }
+
+    #[test]
+    fn parse_requests_validates_each_entry() {
+        assert_eq!(parse_requests(body(&["a", "b-2"])).unwrap().len(), 2);
+        assert!(parse_requests(json!({"requests": []})).is_err());
+        assert!(parse_requests(json!({})).is_err());
+
+        let dup = parse_requests(body(&["a", "a"])).unwrap_err();
+        assert!(dup.contains("requests[1]"), "{dup}");
+
+        let bad_id = parse_requests(body(&["has space"])).unwrap_err();
+        assert!(bad_id.contains("custom_id"), "{bad_id}");
+        assert!(parse_requests(body(&[&"x".repeat(65)])).is_err());
+
+        let no_model = json!({"requests": [{"custom_id": "a", "params": {"messages": []}}]});
+        assert!(parse_requests(no_model).unwrap_err().contains("model"));
+
+        let streaming =
+            json!({"requests": [{"custom_id": "a", "params": {"model": "m", "stream": true}}]});
+        assert!(parse_requests(streaming).unwrap_err().contains("stream"));
+    }
+
+    #[tokio::test]
+    async fn outcomes_update_counts_and_results_keep_request_order() {
+        let store = BatchStore::in_memory(2);
+        let batch = store
+            .create("owner", &requests(&["first", "second", "third"]))
+            .await;
+        assert_eq!(batch.processing_status, ProcessingStatus::InProgress);
+        assert_eq!(batch.request_counts.processing, 3);
+
+        // Results aren't served until the batch ends.
+        let (_, pending) = store.results("owner", &batch.id).await.unwrap();
+        assert!(pending.is_empty());
+
+        store
+            .record(
+                &batch.id,
+                2,
+                BatchOutcome::Errored(json!({"type": "error"})),
+            )
+            .await;
+        store
+            .record(
+                &batch.id,
+                0,
+                BatchOutcome::Succeeded(json!({"id": "msg_1"})),
+            )
+            .await;
+        let mid = store.get("owner", &batch.id).await.unwrap();
+        assert_eq!(mid.request_counts.processing, 1);
+        assert_eq!(mid.request_counts.succeeded, 1);
+        assert_eq!(mid.request_counts.errored, 1);
+
+        // Index 1 never reports (its task died) — finish() fills it in.
+        store.finish(&batch.id).await;
+        let (ended, body) = store.results("owner", &batch.id).await.unwrap();
+        assert_eq!(ended.processing_status, ProcessingStatus::Ended);
+        assert!(ended.ended_at.is_some());
+        assert_eq!(
+            ended.results_url.as_deref(),
+            Some(format!("/v1/messages/batches/{}/results", batch.id).as_str())
+        );
+        assert_eq!(
+            ended.request_counts,
+            RequestCounts {
+                succeeded: 1,
+                errored: 2,
+                ..Default::default()
+            }
+        );
+
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let ids: Vec<&str> = lines
+            .iter()
+            .map(|l| l["custom_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["first", "second", "third"]);
+        assert_eq!(lines[0]["result"]["type"], json!("succeeded"));
+        assert_eq!(lines[0]["result"]["message"]["id"], json!("msg_1"));
+        assert_eq!(lines[1]["result"]["type"], json!("errored"));
+        assert_eq!(
+            lines[1]["result"]["error"]["error"]["type"],
+            json!("api_error")
+        );
+    }
+
+    #[tokio::test]
+    async fn batches_are_scoped_to_their_owner() {
+        let store = BatchStore::in_memory(1);
+        let batch = store.create("alice", &requests(&["a"])).await;
+        assert!(store.get("alice", &batch.id).await.is_some());
+        assert!(store.get("bob", &batch.id).await.is_none());
+        assert!(store.results("bob", &batch.id).await.is_none());
+        assert!(
+            store
+                .list("bob", &ListParams::default())
+                .await
+                .data
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn list_pages_newest_first() {
+        let store = BatchStore::in_memory(1);
+        let mut ids = Vec::new();
+        for _ in 0..5 {
+            ids.push(store.create("owner", &requests(&["a"])).await.id);
+        }
+        ids.reverse(); // newest first
+
+        let page = store
+            .list(
+                "owner",
+                &ListParams {
+                    limit: Some(2),
+                    ..Default::default()
+                },
+            )
+            .await;
+        let got: Vec<&str> = page.data.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(got, vec![ids[0].as_str(), ids[1].as_str()]);
+        assert!(page.has_more);
+        assert_eq!(page.last_id.as_deref(), Some(ids[1].as_str()));
+
+        let next = store
+            .list(
+                "owner",
+                &ListParams {
+                    limit: Some(2),
+                    after_id: page.last_id.clone(),
+                    ..Default::default()
+                },
+            )
+            .await;
+        let got: Vec<&str> = next.data.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(got, vec![ids[2].as_str(), ids[3].as_str()]);
+        assert!(next.has_more);
+
+        let back = store
+            .list(
+                "owner",
+                &ListParams {
+                    limit: Some(2),
+                    before_id: next.first_id.clone(),
+                    ..Default::default()
+                },
+            )
+            .await;
+        let got: Vec<&str> = back.data.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(got, vec![ids[0].as_str(), ids[1].as_str()]);
+        assert!(!back.has_more);
+    }
+
+    #[tokio::test]
+    async fn finished_batches_survive_a_reopen() {
+        let dir = TempDir::new().unwrap();
+        let config = BatchesConfig {
+            max_concurrency: 1,
+            dir: dir.path().join("batches").to_string_lossy().into_owned(),
+            unknown: HashMap::new(),
+        };
+
+        let store = BatchStore::open(&config).unwrap();
+        let batch = store.create("owner", &requests(&["only"])).await;
+        store.record(&batch.id, 0, BatchOutcome::Expired).await;
+        store.finish(&batch.id).await;
+
+        let reopened = BatchStore::open(&config).unwrap();
+        let (loaded, body) = reopened.results("owner", &batch.id).await.unwrap();
+        assert_eq!(loaded.processing_status, ProcessingStatus::Ended);
+        assert_eq!(loaded.request_counts.expired, 1);
+        assert!(body.contains(r#""type":"expired""#));
+    }
+}
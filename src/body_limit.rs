@@ -0,0 +1,79 @@
+//! Early rejection of oversized request bodies.
+//!
+//! `DefaultBodyLimit` only trips once the JSON extractor has read up to the
+//! limit, so a client announcing a huge upload still gets `max_request_body_mb`
+//! of it buffered first. This middleware answers from `Content-Length` alone,
+//! before any of the body is read. Chunked bodies without a length still hit
+//! `DefaultBodyLimit` while they are read.
+
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{StatusCode, header::CONTENT_LENGTH},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+
+/// Middleware rejecting requests whose `Content-Length` exceeds the limit.
+pub async fn reject_oversized(
+    State(max_bytes): State<usize>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if length.is_some_and(|len| len > max_bytes as u64) {
+        tracing::warn!(
+            "Rejected {} {}: body of {} bytes exceeds the {} byte limit",
+            request.method(),
+            request.uri().path(),
+            length.unwrap_or_default(),
+            max_bytes
+        );
+        return too_large(max_bytes);
+    }
+    next.run(request).await
+}
+
+/// The 413 response for a body over `max_bytes`.
+pub fn too_large(max_bytes: usize) -> Response {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Json(json!({
+            "error": format!("Request body exceeds the {} byte limit", max_bytes),
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::post};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn rejects_on_content_length_before_reading() {
+        let app = Router::new()
+            .route(
+                "/",
+                post(|body: String| async move { body.len().to_string() }),
+            )
+            .layer(axum::middleware::from_fn_with_state(8, reject_oversized));
+        let request = |len: usize| {
+            Request::post("/")
+                .header(CONTENT_LENGTH, len)
+                .body(Body::from("x".repeat(len)))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request(8)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.oneshot(request(9)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,293 @@
+//! Gemini context caching (`cachedContents`).
+//!
+//! A cached content lives on the deployment that created it, so for every
+//! cache created through acr the registry remembers the provider, the
+//! deployment and the API key that owns it. `get` and `delete` go straight
+//! to that deployment, and a `generateContent` request whose body references
+//! the cache (`cachedContent`) is routed only to that provider. Caches are
+//! looked up by their ID, the last segment of the resource name, so both
+//! `cachedContents/{id}` and Vertex's `projects/…/cachedContents/{id}` work.
+//!
+//! The registry lives in memory: after a restart, caches created earlier
+//! can't be reached through acr and are left to expire upstream.
+//!
+//! Reference: <https://ai.google.dev/api/caching>
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::body::Body;
+use axum::http::{Method, StatusCode};
+use axum::response::Response;
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+
+use crate::config::Provider;
+use crate::constants::api::{CACHED_CONTENTS_PATH, INFERENCE_DEPLOYMENTS_PATH};
+use crate::constants::cached_content::DEFAULT_TTL_SECS;
+use crate::proxy::{normalize_model, upstream_headers};
+use crate::routes::{AppError, AppState, classify_upstream_error};
+
+/// Where a cache created through acr lives, and who may use it.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub provider: String,
+    pub deployment_id: String,
+    /// Hash of the API key that created the cache.
+    pub owner: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Caches created through acr, by ID; cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct CachedContents {
+    entries: Arc<Mutex<HashMap<String, CacheEntry>>>,
+}
+
+impl CachedContents {
+    /// Remember a new cache, forgetting expired ones.
+    pub fn record(&self, id: &str, entry: CacheEntry) {
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        let now = Utc::now();
+        entries.retain(|_, e| e.expires_at > now);
+        entries.insert(id.to_string(), entry);
+    }
+
+    /// The unexpired cache `id`.
+    pub fn get(&self, id: &str) -> Option<CacheEntry> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .get(id)
+            .filter(|e| e.expires_at > Utc::now())
+            .cloned()
+    }
+
+    pub fn remove(&self, id: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(id);
+        }
+    }
+
+    /// Provider holding the cache a request body references, if acr
+    /// created it.
+    pub fn provider_for(&self, body: &Value) -> Option<String> {
+        let name = body.get("cachedContent")?.as_str()?;
+        self.get(cache_id(name)).map(|e| e.provider)
+    }
+}
+
+/// `abc` from `cachedContents/abc` or `projects/p/locations/l/cachedContents/abc`.
+pub fn cache_id(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// Model ID from `models/gemini-2.5-flash`, a Vertex publisher model path, or
+/// a bare name.
+fn model_id(model: &str) -> &str {
+    model.rsplit("models/").next().unwrap_or(model)
+}
+
+/// When the cache in a create response expires.
+fn expires_at(response: &Value) -> DateTime<Utc> {
+    response
+        .get("expireTime")
+        .and_then(Value::as_str)
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc::now() + chrono::Duration::seconds(DEFAULT_TTL_SECS))
+}
+
+fn cache_url(provider: &Provider, deployment_id: &str, id: Option<&str>) -> String {
+    let base = format!(
+        "{}{INFERENCE_DEPLOYMENTS_PATH}/{deployment_id}{CACHED_CONTENTS_PATH}",
+        provider.genai_api_url
+    );
+    match id {
+        Some(id) => format!("{base}/{id}"),
+        None => base,
+    }
+}
+
+/// Send one cache call to `provider`, returning the upstream status and
+/// body.
+async fn send(
+    state: &AppState,
+    api_key: &str,
+    provider: &Provider,
+    method: Method,
+    url: &str,
+    body: Option<&Value>,
+) -> Result<(StatusCode, Value), AppError> {
+    let token = state
+        .token_manager
+        .get_token_for_provider(api_key, provider)
+        .await
+        .map_err(AppError::Internal)?
+        .ok_or(AppError::InvalidApiKey)?;
+    let mut request = state.client.request(method, url).headers(upstream_headers(
+        &token,
+        &provider.resource_group,
+        &provider.headers,
+    )?);
+    if let Some(body) = body {
+        request = request.json(body);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| classify_upstream_error(anyhow::Error::new(e), &provider.name))?;
+    let status = StatusCode::from_u16(response.status().as_u16())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let text = response.text().await.unwrap_or_default();
+    let body = serde_json::from_str(&text).unwrap_or_else(|_| json!({ "error": text }));
+    Ok((status, body))
+}
+
+fn passthrough(status: StatusCode, body: &Value) -> Result<Response, AppError> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .map_err(|e| AppError::Internal(e.into()))
+}
+
+/// Create a cache on the first provider, in load-balanced order, that serves
+/// the body's `model` and isn't rate limited.
+pub async fn create(
+    state: &AppState,
+    api_key: &str,
+    owner: &str,
+    mut body: Value,
+) -> Result<Response, AppError> {
+    let requested = body
+        .get("model")
+        .and_then(Value::as_str)
+        .ok_or_else(|| AppError::BadRequest("Missing 'model' field in request body".into()))?;
+    let model = normalize_model(model_id(requested), &state.model_registry)
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    let upstream_model = format!(
+        "{}{model}",
+        requested.strip_suffix(model_id(requested)).unwrap_or("")
+    );
+    body["model"] = json!(upstream_model);
+
+    let mut last_error = None;
+    for provider in state.load_balancer.get_scheduled_providers(None) {
+        let Some(deployment_id) = state
+            .model_registry
+            .get_deployment_for_provider(&model, &provider.name)
+            .await
+        else {
+            continue;
+        };
+        let url = cache_url(provider, &deployment_id, None);
+        let (status, response) =
+            send(state, api_key, provider, Method::POST, &url, Some(&body)).await?;
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            tracing::warn!(
+                "Provider '{}' returned 429 creating a cache, trying next provider",
+                provider.name
+            );
+            last_error = Some(AppError::RateLimited {
+                provider: provider.name.clone(),
+                retry_after_secs: None,
+            });
+            continue;
+        }
+        if status.is_success()
+            && let Some(name) = response.get("name").and_then(Value::as_str)
+        {
+            tracing::info!(
+                "Created cached content {} for model '{}' on provider '{}'",
+                name,
+                model,
+                provider.name
+            );
+            state.cached_contents.record(
+                cache_id(name),
+                CacheEntry {
+                    provider: provider.name.clone(),
+                    deployment_id,
+                    owner: owner.to_string(),
+                    expires_at: expires_at(&response),
+                },
+            );
+        }
+        return passthrough(status, &response);
+    }
+    Err(last_error.unwrap_or_else(|| {
+        AppError::BadRequest(format!("Model '{model}' is not available on any provider"))
+    }))
+}
+
+/// Fetch or delete the cache `id` on the deployment holding it.
+pub async fn forward(
+    state: &AppState,
+    api_key: &str,
+    entry: &CacheEntry,
+    id: &str,
+    method: Method,
+) -> Result<Response, AppError> {
+    let provider = state
+        .config
+        .providers
+        .iter()
+        .find(|p| p.name == entry.provider)
+        .ok_or_else(|| AppError::NotFound(format!("Cached content '{id}' not found")))?;
+    let url = cache_url(provider, &entry.deployment_id, Some(id));
+    let deleting = method == Method::DELETE;
+    let (status, response) = send(state, api_key, provider, method, &url, None).await?;
+    if deleting && (status.is_success() || status == StatusCode::NOT_FOUND) {
+        state.cached_contents.remove(id);
+    }
+    passthrough(status, &response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_and_models_come_from_any_resource_name_form() {
+        assert_eq!(cache_id("cachedContents/abc"), "abc");
+        assert_eq!(
+            cache_id("projects/1/locations/us-central1/cachedContents/456"),
+            "456"
+        );
+        assert_eq!(model_id("models/gemini-2.5-flash"), "gemini-2.5-flash");
+        assert_eq!(
+            model_id("projects/p/locations/l/publishers/google/models/gemini-2.5-pro"),
+            "gemini-2.5-pro"
+        );
+        assert_eq!(model_id("gemini-2.5-pro"), "gemini-2.5-pro");
+    }
+
+    #[test]
+    fn requests_referencing_a_live_cache_are_pinned_to_its_provider() {
+        let caches = CachedContents::default();
+        let entry = |provider: &str, expires_at| CacheEntry {
+            provider: provider.to_string(),
+            deployment_id: "d1".to_string(),
+            owner: "owner".to_string(),
+            expires_at,
+        };
+        caches.record("abc", entry("eu", expires_at(&json!({}))));
+        caches.record(
+            "old",
+            entry("us", Utc::now() - chrono::Duration::seconds(1)),
+        );
+
+        let body = |name: &str| json!({"contents": [], "cachedContent": name});
+        assert_eq!(
+            caches.provider_for(&body("cachedContents/abc")).as_deref(),
+            Some("eu")
+        );
+        assert_eq!(caches.provider_for(&body("cachedContents/old")), None);
+        assert_eq!(caches.provider_for(&json!({"contents": []})), None);
+
+        let expiry = expires_at(&json!({"expireTime": "2030-01-01T00:00:00.5Z"}));
+        assert_eq!(expiry.to_rfc3339(), "2030-01-01T00:00:00.500+00:00");
+    }
+}
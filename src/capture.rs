@@ -0,0 +1,255 @@
+//! Per-request transcripts of streamed responses.
+//!
+//! A client sends `x-acr-capture: true` (honored only when `capture.enabled`
+//! is set) and acr writes `<capture.dir>/<id>.json` once the stream ends:
+//! every upstream `data:` payload, the exact SSE bytes the client received,
+//! and the text reconstructed from those bytes. Comparing the three shows
+//! whether a garbled rendering came from the model, from acr's translation,
+//! or from the client. The ID is returned in `x-acr-capture-id`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::CaptureConfig;
+use crate::constants::api::{ACR_CAPTURE_HEADER, STREAM_DATA_PREFIX};
+use crate::constants::capture::{ID_PREFIX, MAX_CAPTURE_BYTES};
+use crate::metrics::RequestLabels;
+use crate::proxy::LlmFamily;
+
+/// Where a requested capture goes. Created per request by [`requested`].
+#[derive(Debug, Clone)]
+pub struct CaptureTarget {
+    pub id: String,
+    dir: PathBuf,
+}
+
+/// The capture target for a request, if capture is enabled and the client
+/// asked for it.
+pub fn requested(config: &CaptureConfig, headers: &HeaderMap) -> Option<CaptureTarget> {
+    if !crate::proxy::header_flag(headers, ACR_CAPTURE_HEADER) {
+        return None;
+    }
+    if !config.enabled {
+        tracing::debug!(
+            "Ignoring {}: capture is not enabled in config",
+            ACR_CAPTURE_HEADER
+        );
+        return None;
+    }
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    Some(CaptureTarget {
+        id: format!(
+            "{ID_PREFIX}{}_{}",
+            Utc::now().format("%Y%m%dT%H%M%S"),
+            &suffix[..8]
+        ),
+        dir: PathBuf::from(&config.dir),
+    })
+}
+
+/// A transcript being recorded by the streaming forwarder.
+#[derive(Debug, Serialize)]
+pub struct StreamCapture {
+    id: String,
+    #[serde(skip)]
+    dir: PathBuf,
+    created_at: DateTime<Utc>,
+    model: String,
+    provider: String,
+    route: String,
+    client_family: LlmFamily,
+    upstream_family: LlmFamily,
+    success: Option<bool>,
+    /// Set once either buffer hit `MAX_CAPTURE_BYTES`; later data is dropped.
+    truncated: bool,
+    /// Upstream `data:` payloads, parsed when they are JSON.
+    upstream_events: Vec<Value>,
+    /// SSE bytes exactly as sent to the client (lossy UTF-8).
+    client_sse: String,
+    /// Text content reconstructed from `client_sse`.
+    text: String,
+    #[serde(skip)]
+    bytes: usize,
+}
+
+impl StreamCapture {
+    pub fn new(target: CaptureTarget, labels: &RequestLabels, client_family: LlmFamily) -> Self {
+        Self {
+            id: target.id,
+            dir: target.dir,
+            created_at: Utc::now(),
+            model: labels.model.clone(),
+            provider: labels.provider.clone(),
+            route: labels.route.clone(),
+            client_family,
+            upstream_family: labels.family,
+            success: None,
+            truncated: false,
+            upstream_events: Vec::new(),
+            client_sse: String::new(),
+            text: String::new(),
+            bytes: 0,
+        }
+    }
+
+    fn has_room(&mut self, len: usize) -> bool {
+        if self.truncated || self.bytes + len > MAX_CAPTURE_BYTES {
+            self.truncated = true;
+            return false;
+        }
+        self.bytes += len;
+        true
+    }
+
+    /// Record one upstream `data:` payload.
+    pub fn upstream(&mut self, data: &str) {
+        if self.has_room(data.len()) {
+            self.upstream_events.push(
+                serde_json::from_str(data).unwrap_or_else(|_| Value::String(data.to_string())),
+            );
+        }
+    }
+
+    /// Record bytes sent to the client.
+    pub fn client(&mut self, bytes: &[u8]) {
+        if self.has_room(bytes.len()) {
+            self.client_sse.push_str(&String::from_utf8_lossy(bytes));
+        }
+    }
+
+    /// Write the transcript. Errors are logged, never surfaced to the client.
+    pub async fn finish(mut self, success: bool) {
+        self.success = Some(success);
+        self.text = reconstruct_text(&self.client_sse, self.client_family);
+        let id = self.id.clone();
+        let result = tokio::task::spawn_blocking(move || write_capture(&self.dir, &self)).await;
+        match result {
+            Ok(Ok(path)) => tracing::info!("Wrote stream capture {} to {}", id, path.display()),
+            Ok(Err(e)) => tracing::warn!("Failed to write stream capture {}: {:#}", id, e),
+            Err(e) => tracing::warn!("Stream capture writer for {} panicked: {}", id, e),
+        }
+    }
+}
+
+fn write_capture(dir: &Path, capture: &StreamCapture) -> Result<PathBuf> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create capture directory {}", dir.display()))?;
+    let path = dir.join(format!("{}.json", capture.id));
+    std::fs::write(&path, serde_json::to_vec_pretty(capture)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Concatenate the text deltas of an SSE stream in `family`'s schema.
+pub fn reconstruct_text(sse: &str, family: LlmFamily) -> String {
+    let mut text = String::new();
+    for line in sse.lines() {
+        let Some(data) = line.trim().strip_prefix(STREAM_DATA_PREFIX) else {
+            continue;
+        };
+        let Ok(event) = serde_json::from_str::<Value>(data) else {
+            continue;
+        };
+        let delta = match family {
+            LlmFamily::Claude => event
+                .get("delta")
+                .filter(|d| d.get("type").and_then(|t| t.as_str()) == Some("text_delta"))
+                .and_then(|d| d.get("text")),
+            LlmFamily::OpenAi | LlmFamily::OpenWeight => event.pointer("/choices/0/delta/content"),
+            LlmFamily::OpenAiResponses => event.get("delta").filter(|_| {
+                event.get("type").and_then(|t| t.as_str()) == Some("response.output_text.delta")
+            }),
+            LlmFamily::Gemini => {
+                if let Some(parts) = event
+                    .pointer("/candidates/0/content/parts")
+                    .and_then(|p| p.as_array())
+                {
+                    for part in parts {
+                        if let Some(t) = part.get("text").and_then(|t| t.as_str()) {
+                            text.push_str(t);
+                        }
+                    }
+                }
+                None
+            }
+            LlmFamily::Passthrough => None,
+        };
+        if let Some(t) = delta.and_then(|t| t.as_str()) {
+            text.push_str(t);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(dir: &Path, enabled: bool) -> CaptureConfig {
+        CaptureConfig {
+            enabled,
+            dir: dir.to_string_lossy().into_owned(),
+            unknown: Default::default(),
+        }
+    }
+
+    #[test]
+    fn header_only_counts_when_capture_is_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut headers = HeaderMap::new();
+        assert!(requested(&config(dir.path(), true), &headers).is_none());
+        headers.insert(ACR_CAPTURE_HEADER, "true".parse().unwrap());
+        assert!(requested(&config(dir.path(), false), &headers).is_none());
+        let target = requested(&config(dir.path(), true), &headers).unwrap();
+        assert!(target.id.starts_with(ID_PREFIX));
+    }
+
+    #[test]
+    fn text_is_reconstructed_per_family() {
+        let claude = "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"Hel\"}}\n\n\
+                      event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"lo\"}}\n\n";
+        assert_eq!(reconstruct_text(claude, LlmFamily::Claude), "Hello");
+
+        let openai = "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\ndata: [DONE]\n\n";
+        assert_eq!(reconstruct_text(openai, LlmFamily::OpenAi), "Hi");
+
+        let gemini = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"a\"},{\"text\":\"b\"}]}}]}\n\n";
+        assert_eq!(reconstruct_text(gemini, LlmFamily::Gemini), "ab");
+    }
+
+    #[tokio::test]
+    async fn finished_capture_is_written_with_its_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(ACR_CAPTURE_HEADER, "true".parse().unwrap());
+        let target = requested(&config(dir.path(), true), &headers).unwrap();
+        let id = target.id.clone();
+        let labels = RequestLabels {
+            model: "gpt-4.1".to_string(),
+            family: LlmFamily::OpenAi,
+            provider: "primary".to_string(),
+            route: "/v1/chat/completions".to_string(),
+            stream: true,
+        };
+        let mut capture = StreamCapture::new(target, &labels, LlmFamily::OpenAi);
+        let data = "{\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}";
+        capture.upstream(data);
+        capture.client(format!("data: {data}\n\n").as_bytes());
+        capture.finish(true).await;
+
+        let written: Value =
+            serde_json::from_slice(&std::fs::read(dir.path().join(format!("{id}.json"))).unwrap())
+                .unwrap();
+        assert_eq!(written["text"], "Hi");
+        assert_eq!(written["success"], true);
+        assert_eq!(
+            written["upstream_events"][0]["choices"][0]["delta"]["content"],
+            "Hi"
+        );
+    }
+}
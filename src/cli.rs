@@ -1,21 +1,24 @@
 use anyhow::{Context, Result};
+use axum::Router;
 use clap::{Arg, Command};
 use std::net::SocketAddr;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
-use tracing_subscriber::{EnvFilter, fmt};
+use tracing_subscriber::fmt;
 
-#[cfg(feature = "db")]
-use crate::database::Database;
 use crate::{
     balancer::LoadBalancer,
+    client::AiCoreClient,
     commands::CommandHandler,
-    config::Config,
+    config::{Config, Provider},
     metrics::MetricsService,
     rate_limit::AuthRateLimiter,
     registry::ModelRegistry,
-    routes::{AppState, create_router},
+    reload::{ConfigReloader, LiveRouter},
+    routes::{AppState, create_admin_router, create_router},
     token::TokenManager,
 };
+#[cfg(feature = "db")]
+use crate::{config::LogRequestsBackend, database::Database};
 
 pub struct Cli;
 
@@ -24,11 +27,78 @@ impl Cli {
         let matches = Self::build_command().get_matches();
 
         let config_path = matches.get_one::<String>("config").map(|s| s.as_str());
+        // Only need the files next to the config, not the config itself.
+        match matches.subcommand() {
+            Some(("status", _)) => {
+                return Self::daemon_status(&Config::path(config_path)?).await;
+            }
+            Some(("stop", _)) => {
+                let paths = crate::daemon::DaemonPaths::next_to(&Config::path(config_path)?);
+                match crate::daemon::stop(&paths).await? {
+                    Some(pid) => println!("acr stopped (pid {pid})"),
+                    None => println!("acr is not running"),
+                }
+                return Ok(());
+            }
+            Some(("upgrade", _)) => {
+                let paths = crate::daemon::DaemonPaths::next_to(&Config::path(config_path)?);
+                let exe = std::env::current_exe().context("Failed to find the acr executable")?;
+                let (old, new) = crate::daemon::upgrade(&paths, &exe).await?;
+                println!(
+                    "acr upgraded to {} (pid {new}); pid {old} exits once its open requests finish",
+                    env!("CARGO_PKG_VERSION")
+                );
+                return Ok(());
+            }
+            _ => {}
+        }
+        // Runs before loading, on a file the current layout may not parse.
+        if let Some(("config", config_matches)) = matches.subcommand() {
+            return match config_matches.subcommand() {
+                Some(("migrate", migrate_matches)) => crate::config_migrate::migrate_file(
+                    &Config::path(config_path)?,
+                    migrate_matches.get_flag("dry-run"),
+                ),
+                Some(("encrypt", _)) => crate::secrets::encrypt_stdin(),
+                #[cfg(feature = "keychain")]
+                Some(("set-passphrase", _)) => crate::secrets::set_passphrase_stdin(),
+                #[cfg(feature = "keychain")]
+                Some(("store-secret", secret_matches)) => crate::secrets::store_secret_stdin(
+                    secret_matches
+                        .get_one::<String>("entry")
+                        .map(|s| s.as_str())
+                        .unwrap_or_default(),
+                ),
+                _ => {
+                    eprintln!(
+                        "Unknown config subcommand. Use 'acr config migrate' or 'acr config encrypt'"
+                    );
+                    std::process::exit(1);
+                }
+            };
+        }
         #[allow(unused_mut)]
         let mut config = Config::load(config_path).context("Failed to load configuration")?;
 
+        if let Some(("serve", serve_matches)) = matches.subcommand()
+            && serve_matches.get_flag("daemon")
+        {
+            #[cfg(feature = "tui")]
+            if matches.get_flag("tui") {
+                anyhow::bail!("--daemon can't be combined with --tui");
+            }
+            // The background router runs the same command line, minus
+            // `--daemon`.
+            let args = std::env::args_os()
+                .skip(1)
+                .filter(|arg| arg != "--daemon" && arg != "-d")
+                .collect();
+            let paths = crate::daemon::DaemonPaths::next_to(&Config::path(config_path)?);
+            return crate::daemon::start(&paths, args).await;
+        }
+
         // Handle CLI commands
-        if let Some(subcommand) = matches.subcommand() {
+        if let Some(subcommand) = matches.subcommand().filter(|(name, _)| *name != "serve") {
             let handler =
                 CommandHandler::new(config.clone()).context("Failed to create command handler")?;
 
@@ -55,7 +125,86 @@ impl Cli {
                     std::process::exit(1);
                 }
                 ("diagnose", _) => {
-                    return handler.diagnose(config_path);
+                    return handler.diagnose(config_path).await;
+                }
+                ("models", models_matches) => {
+                    if let Some(("export", export_matches)) = models_matches.subcommand() {
+                        let format = export_matches
+                            .get_one::<String>("format")
+                            .map(|s| s.as_str())
+                            .unwrap_or_default();
+                        return handler.models_export(format).await;
+                    }
+                    eprintln!("Unknown models subcommand. Use 'acr models export'");
+                    std::process::exit(1);
+                }
+                ("keys", keys_matches) => {
+                    return match keys_matches.subcommand() {
+                        Some(("add", add_matches)) => {
+                            let arg =
+                                |name| add_matches.get_one::<String>(name).map(|s| s.as_str());
+                            let valid_until = arg("valid-until")
+                                .map(|t| {
+                                    chrono::DateTime::parse_from_rfc3339(t)
+                                        .map(|t| t.with_timezone(&chrono::Utc))
+                                        .with_context(|| format!("Invalid --valid-until '{t}'"))
+                                })
+                                .transpose()?;
+                            handler.keys_add(&crate::keys::NewKey {
+                                client: arg("client"),
+                                tenant: arg("tenant"),
+                                valid_until,
+                            })
+                        }
+                        Some(("list", _)) => handler.keys_list(),
+                        Some(("revoke", revoke_matches)) => {
+                            let id = revoke_matches
+                                .get_one::<String>("id")
+                                .map(|s| s.as_str())
+                                .unwrap_or_default();
+                            handler.keys_revoke(id)
+                        }
+                        _ => {
+                            eprintln!(
+                                "Unknown keys subcommand. Use 'acr keys add', 'acr keys list' or 'acr keys revoke'"
+                            );
+                            std::process::exit(1);
+                        }
+                    };
+                }
+                ("providers", providers_matches) => {
+                    return match providers_matches.subcommand() {
+                        Some(("list", _)) => handler.providers_list().await,
+                        Some(("test", test_matches)) => {
+                            let name = test_matches
+                                .get_one::<String>("name")
+                                .map(|s| s.as_str())
+                                .unwrap_or_default();
+                            handler.providers_test(name).await
+                        }
+                        _ => {
+                            eprintln!(
+                                "Unknown providers subcommand. Use 'acr providers list' or 'acr providers test <name>'"
+                            );
+                            std::process::exit(1);
+                        }
+                    };
+                }
+                ("send", send_matches) => {
+                    let arg = |name| send_matches.get_one::<String>(name).map(|s| s.as_str());
+                    return handler
+                        .send(
+                            arg("file").unwrap_or_default(),
+                            arg("model"),
+                            arg("route").unwrap_or_default(),
+                            arg("key"),
+                            send_matches.get_flag("execute"),
+                        )
+                        .await;
+                }
+                ("replay", replay_matches) => {
+                    let id = replay_matches.get_one::<String>("id").map(|s| s.as_str());
+                    return handler.replay(id).await;
                 }
                 #[cfg(feature = "db")]
                 ("usage", usage_matches) => {
@@ -86,12 +235,6 @@ impl Cli {
             }
         }
 
-        // Handle --log-requests flag: override config
-        #[cfg(feature = "db")]
-        if matches.get_flag("log-requests") {
-            config.log_requests.enabled = true;
-        }
-
         // Continue with server startup if no CLI command was provided
         Self::run_server(matches, config).await
     }
@@ -105,13 +248,22 @@ impl Cli {
                     .short('b')
                     .long("bind")
                     .value_name("ADDR")
+                    .global(true)
                     .help("Bind address (e.g. 127.0.0.1, 0.0.0.0:9000)"),
             )
+            .arg(
+                Arg::new("admin-bind")
+                    .long("admin-bind")
+                    .value_name("ADDR")
+                    .global(true)
+                    .help("Serve /metrics and /admin/* on this address instead of --bind"),
+            )
             .arg(
                 Arg::new("config")
                     .short('c')
                     .long("config")
                     .value_name("FILE")
+                    .global(true)
                     .help("Path to configuration file"),
             )
             .arg(
@@ -119,14 +271,41 @@ impl Cli {
                     .short('l')
                     .long("log-level")
                     .value_name("LEVEL")
+                    .global(true)
                     .help("Log level (trace, debug, info, warn, error)"),
-            );
+            )
+            .arg(
+                Arg::new("check")
+                    .long("check")
+                    .help("Verify provider credentials at startup and exit on failure")
+                    .global(true)
+                    .action(clap::ArgAction::SetTrue),
+            )
+            .subcommand(
+                Command::new("serve")
+                    .about("Run the router (the default without a subcommand)")
+                    .arg(
+                        Arg::new("daemon")
+                            .short('d')
+                            .long("daemon")
+                            .help("Run in the background; see 'acr status' and 'acr stop'")
+                            .action(clap::ArgAction::SetTrue),
+                    ),
+            )
+            .subcommand(
+                Command::new("status").about("Show whether the background router is running"),
+            )
+            .subcommand(Command::new("stop").about("Stop the background router gracefully"))
+            .subcommand(Command::new("upgrade").about(
+                "Hand the background router's listener to this acr binary without dropping connections",
+            ));
 
         #[cfg(feature = "tui")]
         let cmd = cmd.arg(
             Arg::new("tui")
                 .long("tui")
                 .help("Enable terminal UI dashboard")
+                .global(true)
                 .action(clap::ArgAction::SetTrue),
         );
 
@@ -136,6 +315,7 @@ impl Cli {
                 Arg::new("log-requests")
                     .long("log-requests")
                     .help("Enable request logging to SQLite database")
+                    .global(true)
                     .action(clap::ArgAction::SetTrue),
             )
             .subcommand(
@@ -193,6 +373,37 @@ impl Cli {
                     ),
             );
 
+        let config_cmd = Command::new("config")
+            .about("Manage the config file")
+            .subcommand(
+                Command::new("migrate")
+                    .about("Rewrite the config file in the current layout")
+                    .arg(
+                        Arg::new("dry-run")
+                            .long("dry-run")
+                            .help("Print the migrated config instead of writing it")
+                            .action(clap::ArgAction::SetTrue),
+                    ),
+            )
+            .subcommand(Command::new("encrypt").about(
+                "Encrypt a secret read from stdin with the secrets passphrase, for the config",
+            ));
+        #[cfg(feature = "keychain")]
+        let config_cmd = config_cmd
+            .subcommand(
+                Command::new("set-passphrase")
+                    .about("Store the secrets passphrase read from stdin in the OS keychain"),
+            )
+            .subcommand(
+                Command::new("store-secret")
+                    .about("Store a secret read from stdin in the OS keychain, for a keychain: reference")
+                    .arg(
+                        Arg::new("entry")
+                            .help("Keychain entry name, e.g. uaa-primary")
+                            .required(true),
+                    ),
+            );
+
         cmd.subcommand(Command::new("resource-groups").about("List all resource groups"))
             .subcommand(
                 Command::new("deployments").about("List deployments").arg(
@@ -219,48 +430,181 @@ impl Cli {
                 Command::new("diagnose")
                     .about("Print diagnostic information about the router configuration"),
             )
+            .subcommand(
+                Command::new("models")
+                    .about("Work with the model catalog")
+                    .subcommand(
+                        Command::new("export")
+                            .about("Print a client tool configuration for the models acr serves")
+                            .arg(
+                                Arg::new("format")
+                                    .long("format")
+                                    .value_name("FORMAT")
+                                    .help("Client tool to configure")
+                                    .value_parser(["continue", "aider", "litellm"])
+                                    .required(true),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("providers")
+                    .about("Check configured providers' credentials and API latency")
+                    .subcommand(
+                        Command::new("list")
+                            .about("Probe every provider: token, API round-trip, state"),
+                    )
+                    .subcommand(
+                        Command::new("test")
+                            .about("Probe one provider; exits non-zero when it fails")
+                            .arg(
+                                Arg::new("name")
+                                    .help("Provider name from the config")
+                                    .required(true)
+                                    .index(1),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("keys")
+                    .about("Manage API keys stored hashed in the keys file")
+                    .subcommand(
+                        Command::new("add")
+                            .about("Generate a key and print it once")
+                            .arg(
+                                Arg::new("client")
+                                    .long("client")
+                                    .value_name("NAME")
+                                    .help("Client identity the key belongs to"),
+                            )
+                            .arg(
+                                Arg::new("tenant")
+                                    .long("tenant")
+                                    .value_name("NAME")
+                                    .help("Tenant the key belongs to"),
+                            )
+                            .arg(
+                                Arg::new("valid-until")
+                                    .long("valid-until")
+                                    .value_name("RFC3339")
+                                    .help("Expiry time, e.g. 2026-12-31T00:00:00Z"),
+                            ),
+                    )
+                    .subcommand(Command::new("list").about("List keys by ID"))
+                    .subcommand(
+                        Command::new("revoke")
+                            .about("Remove a key from the keys file")
+                            .arg(
+                                Arg::new("id")
+                                    .help("Key ID from 'acr keys list', or a unique prefix")
+                                    .required(true)
+                                    .index(1),
+                            ),
+                    ),
+            )
+            .subcommand(config_cmd)
+            .subcommand(
+                Command::new("send")
+                    .about("Run a request file through the running router; dry run by default")
+                    .arg(
+                        Arg::new("file")
+                            .short('f')
+                            .long("file")
+                            .value_name("FILE")
+                            .help("JSON request body")
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::new("model")
+                            .short('m')
+                            .long("model")
+                            .value_name("MODEL")
+                            .help("Model to request; overrides the file's `model`"),
+                    )
+                    .arg(
+                        Arg::new("route")
+                            .long("route")
+                            .value_name("PATH")
+                            .default_value("/v1/chat/completions")
+                            .help("Router route to call; `{model}` is replaced with the model"),
+                    )
+                    .arg(
+                        Arg::new("key")
+                            .long("key")
+                            .value_name("KEY")
+                            .help("API key to send (default: the loopback-only internal key)"),
+                    )
+                    .arg(
+                        Arg::new("dry-run")
+                            .long("dry-run")
+                            .help("Print the prepared upstream request without sending it")
+                            .action(clap::ArgAction::SetTrue)
+                            .conflicts_with("execute"),
+                    )
+                    .arg(
+                        Arg::new("execute")
+                            .long("execute")
+                            .help("Send the request upstream and print the response")
+                            .action(clap::ArgAction::SetTrue),
+                    ),
+            )
+            .subcommand(
+                Command::new("replay")
+                    .about("List failed requests captured as dead letters, or replay one")
+                    .arg(
+                        Arg::new("id")
+                            .help("Dead letter to replay through the running router")
+                            .index(1),
+                    ),
+            )
     }
 
-    async fn run_server(matches: clap::ArgMatches, mut config: Config) -> Result<()> {
-        // Apply CLI overrides before tracing init
+    /// Command-line flags that take precedence over the config file, for
+    /// startup and for every reload.
+    fn apply_overrides(matches: &clap::ArgMatches, config: &mut Config) {
         if let Some(bind) = matches.get_one::<String>("bind") {
             config.bind = bind.clone();
         }
+        if let Some(admin_bind) = matches.get_one::<String>("admin-bind") {
+            config.admin_bind = Some(admin_bind.clone());
+        }
         if let Some(log_level) = matches.get_one::<String>("log-level") {
             config.log_level = log_level.clone();
         }
+        if matches.get_flag("check") {
+            config.verify_on_startup = true;
+        }
+        #[cfg(feature = "db")]
+        if matches.get_flag("log-requests") {
+            config.log_requests.enabled = true;
+        }
+    }
 
-        // Initialize tracing
-        let filter_directive = format!(
-            "aicore_router={},acr={},info",
-            config.log_level, config.log_level
-        );
-        let env_filter = EnvFilter::try_new(&filter_directive).with_context(|| {
-            format!(
-                "Invalid log_level '{}'. Valid options: trace, debug, info, warn, error",
-                config.log_level
-            )
-        })?;
+    async fn run_server(matches: clap::ArgMatches, mut config: Config) -> Result<()> {
+        // Apply CLI overrides before tracing init
+        Self::apply_overrides(&matches, &mut config);
+
+        // Initialize tracing. The filter sits behind a reload layer so
+        // `PUT /admin/log-level` can change it at runtime.
+        use tracing_subscriber::layer::SubscriberExt;
+        use tracing_subscriber::util::SubscriberInitExt;
+        let (log_level, filter_layer) = crate::log_level::LogLevelControl::new(&config.log_level)?;
+        let subscriber = tracing_subscriber::registry().with(filter_layer);
 
         #[cfg(feature = "tui")]
         let tui_log_tx = if matches.get_flag("tui") {
             let (tx, rx) = tokio::sync::mpsc::channel(1024);
             // TUI path: use custom layer
-            use tracing_subscriber::layer::SubscriberExt;
             let tui_layer = crate::tui::TuiLogLayer::new(tx.clone());
-            let subscriber = tracing_subscriber::registry()
-                .with(env_filter)
-                .with(tui_layer);
-            tracing::subscriber::set_global_default(subscriber)
+            tracing::subscriber::set_global_default(subscriber.with(tui_layer))
                 .context("Failed to set tracing subscriber")?;
             Some((tx, rx))
         } else {
-            fmt().with_env_filter(env_filter).init();
+            subscriber.with(fmt::layer()).init();
             None
         };
 
         #[cfg(not(feature = "tui"))]
-        fmt().with_env_filter(env_filter).init();
+        subscriber.with(fmt::layer()).init();
 
         tracing::info!("Starting AI Core Router on {}", config.bind);
         tracing::info!("Configured providers: {}", config.providers.len());
@@ -275,8 +619,37 @@ impl Cli {
         }
         tracing::info!("Configured API keys: {}", config.api_keys.len());
 
+        #[cfg(feature = "cluster")]
+        let cluster = match config.cluster.redis_url {
+            Some(ref url) => {
+                let cluster = crate::cluster::Cluster::connect(url, &config.cluster.key_prefix)
+                    .await
+                    .context("Failed to start cluster mode")?;
+                tracing::info!(
+                    "Cluster mode: request rates, token quotas and OAuth tokens shared through Redis (key prefix '{}')",
+                    config.cluster.key_prefix
+                );
+                Some(cluster)
+            }
+            None => None,
+        };
+
         // Create token manager with API keys
-        let token_manager = TokenManager::new(config.api_key_strings());
+        let mut token_manager = TokenManager::from_api_keys(&config.api_keys);
+        if config.token_cache.enabled {
+            let passphrase =
+                crate::secrets::passphrase().context("token_cache needs the secrets passphrase")?;
+            token_manager = token_manager.with_cache_file(&config.token_cache.path, passphrase);
+            tracing::info!("Token cache: {}", config.token_cache.path);
+        }
+        #[cfg(feature = "cluster")]
+        if let Some(ref cluster) = cluster {
+            token_manager = token_manager.with_cluster(cluster.clone());
+        }
+
+        if config.verify_on_startup {
+            Self::verify_providers(&config.providers, &token_manager).await?;
+        }
 
         // Create load balancer with providers and configured strategy.
         // Construction fails fast when no enabled providers remain — the
@@ -286,11 +659,27 @@ impl Cli {
                 .context("Failed to construct load balancer")?;
         tracing::info!("Load balancing strategy: {:?}", config.load_balancing);
 
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(600))
-            .connect_timeout(std::time::Duration::from_secs(10))
-            .build()
-            .context("Failed to build HTTP client")?;
+        // Create metrics service
+        let metrics = MetricsService::new();
+
+        let client = crate::http2::configure_client(
+            reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(600))
+                .connect_timeout(std::time::Duration::from_secs(10))
+                .connector_layer(crate::connections::CountUpstreamConnects::new(&metrics)),
+            &config.http2,
+        )
+        .build()
+        .context("Failed to build HTTP client")?;
+
+        crate::redact::init(&config.log_redaction);
+
+        if crate::sentry::init(&config.sentry)? {
+            tracing::info!(
+                "Reporting errors to Sentry (environment: {})",
+                config.sentry.environment.as_deref().unwrap_or("-")
+            );
+        }
 
         // Create and start model registry
         tracing::info!(
@@ -303,20 +692,25 @@ impl Cli {
             config.providers.clone(),
             token_manager.clone(),
             config.refresh_interval_secs,
-        );
-        let _registry_handle = model_registry
+        )
+        .with_auto_discover(config.auto_discover.clone());
+        let registry_task = model_registry
             .start()
             .await
             .context("Failed to start model registry")?;
 
-        // Create metrics service
-        let metrics = MetricsService::new();
-
         // Create database for request logging
         #[cfg(feature = "db")]
         let database = if config.log_requests.enabled {
-            tracing::info!("Request logging enabled: {}", config.log_requests.db_path);
-            let db = Database::open(config.log_requests.db_path.clone().into())
+            match config.log_requests.backend {
+                LogRequestsBackend::Sqlite => {
+                    tracing::info!("Request logging enabled: {}", config.log_requests.db_path)
+                }
+                LogRequestsBackend::Postgres => {
+                    tracing::info!("Request logging enabled: PostgreSQL")
+                }
+            }
+            let db = Database::connect(&config.log_requests)
                 .await
                 .context("Failed to open database")?;
             Some(db)
@@ -365,12 +759,18 @@ impl Cli {
         });
 
         // Create quota manager if enabled
+        let mut quota_state: Option<(crate::quota::QuotaManager, std::path::PathBuf)> = None;
         let quota_manager = if config.quotas.enabled {
             #[cfg(feature = "db")]
             let qm =
                 crate::quota::QuotaManager::new(&config.api_keys, &config.quotas, database.clone());
             #[cfg(not(feature = "db"))]
             let qm = crate::quota::QuotaManager::new(&config.api_keys, &config.quotas);
+            #[cfg(feature = "cluster")]
+            let qm = match cluster {
+                Some(ref cluster) => qm.with_cluster(cluster.clone()),
+                None => qm,
+            };
 
             // Load baseline usage from requests table
             #[cfg(feature = "db")]
@@ -394,16 +794,33 @@ impl Cli {
                     .unwrap_or_else(|| "unlimited".to_string()),
             );
 
-            #[cfg(not(feature = "db"))]
-            tracing::warn!(
-                "Quotas running in-memory only (no 'db' feature); usage resets on restart"
-            );
-
+            // Without the request log to rebuild counters from, fall back to
+            // the quota state file.
             #[cfg(feature = "db")]
-            if !config.log_requests.enabled {
-                tracing::warn!(
-                    "Quotas running in-memory only (log_requests disabled); usage resets on restart"
-                );
+            let db_backed = database.is_some();
+            #[cfg(not(feature = "db"))]
+            let db_backed = false;
+            // Redis keeps shared counters across restarts.
+            #[cfg(feature = "cluster")]
+            let db_backed = db_backed || cluster.is_some();
+            if !db_backed {
+                match config.quotas.state_file {
+                    Some(ref path) => {
+                        let path = std::path::PathBuf::from(path);
+                        match qm.load_state(&path).await {
+                            Ok(n) => tracing::info!(
+                                "Quota usage persisted to {} ({} keys restored)",
+                                path.display(),
+                                n
+                            ),
+                            Err(e) => tracing::warn!("Failed to load quota state: {:#}", e),
+                        }
+                        quota_state = Some((qm.clone(), path));
+                    }
+                    None => tracing::warn!(
+                        "Quotas running in-memory only (no request log or state_file); usage resets on restart"
+                    ),
+                }
             }
 
             Some(qm)
@@ -411,11 +828,30 @@ impl Cli {
             None
         };
 
+        if let Some((qm, path)) = quota_state.clone() {
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+                    crate::constants::config::QUOTA_STATE_SAVE_INTERVAL_SECS,
+                ));
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = qm.save_state(&path).await {
+                        tracing::warn!("Failed to save quota state: {:#}", e);
+                    }
+                }
+            });
+        }
+
         // Build per-API-key request-rate limiter (separate from token quotas above).
         // Returns None if no requests_per_minute is configured anywhere.
         let request_limiter =
-            crate::request_limiter::RequestLimiter::from_config(&config.api_keys, &config.quotas)
-                .map(std::sync::Arc::new);
+            crate::request_limiter::RequestLimiter::from_config(&config.api_keys, &config.quotas);
+        #[cfg(feature = "cluster")]
+        let request_limiter = match cluster {
+            Some(ref cluster) => request_limiter.map(|rl| rl.with_cluster(cluster.clone())),
+            None => request_limiter,
+        };
+        let request_limiter = request_limiter.map(std::sync::Arc::new);
         if let Some(ref rl) = request_limiter {
             tracing::info!(
                 "Per-key request rate limiting enabled (default: {})",
@@ -428,6 +864,155 @@ impl Cli {
             let _ = rl; // suppress unused-variable warning when feature combos exclude usage
         }
 
+        let stream_limiter =
+            crate::stream_limit::StreamLimiter::from_config(&config.api_keys, &config.streams);
+        if stream_limiter.is_some() {
+            let limit = |n: Option<usize>| n.map_or("unlimited".to_string(), |n| n.to_string());
+            tracing::info!(
+                "Open streams capped (total: {}, per key: {})",
+                limit(config.streams.max_open),
+                limit(config.streams.max_open_per_key)
+            );
+        }
+
+        let output_pacer =
+            crate::stream_pace::OutputPacer::from_config(&config.api_keys, &config.streams);
+        if output_pacer.is_some() {
+            tracing::info!(
+                "Streamed output paced per key (default: {})",
+                config
+                    .streams
+                    .output_tokens_per_sec_per_key
+                    .map_or("unlimited".to_string(), |n| format!("{n} tokens/s"))
+            );
+        }
+
+        let retry_budget = crate::retry_budget::RetryBudget::from_config(&config.retry_budget);
+        if let Some(ratio) = config.retry_budget.ratio {
+            tracing::info!(
+                "Failover retries capped at {} per request (plus {}/s) over {}s",
+                ratio,
+                config.retry_budget.min_per_sec,
+                config.retry_budget.window_secs
+            );
+        }
+
+        let batches = crate::batches::BatchStore::open(&config.batches)?;
+        tracing::info!(
+            "Message batches stored in {} (max {} concurrent request(s))",
+            config.batches.dir,
+            config.batches.max_concurrency
+        );
+
+        let dead_letters = crate::dead_letter::DeadLetterStore::open(&config.dead_letter)?;
+        if dead_letters.is_some() {
+            tracing::info!(
+                "Capturing failed requests to {} (keeping {})",
+                config.dead_letter.dir,
+                config.dead_letter.max_entries
+            );
+        }
+
+        let image_fetcher = crate::image_fetch::ImageFetcher::from_config(&config.image_fetch)?;
+        if image_fetcher.is_some() {
+            tracing::info!(
+                "Fetching remote images for translated requests (max {} bytes, timeout {}s)",
+                config.image_fetch.max_bytes,
+                config.image_fetch.timeout_secs
+            );
+        }
+
+        let response_cache = crate::response_cache::ResponseCache::open(&config.response_cache)?;
+        if response_cache.is_some() {
+            tracing::info!(
+                "Caching non-streaming responses in {:?} for {}s (keeping {})",
+                config.response_cache.backend,
+                config.response_cache.ttl_secs,
+                config.response_cache.max_entries
+            );
+        }
+
+        let upstream_limits =
+            crate::upstream_limits::UpstreamLimits::from_config(&config.upstream_limits);
+        let tenant_quotas = crate::tenant_quota::TenantQuotas::from_config(&config.providers);
+        for provider in &config.providers {
+            let quota = &provider.quota;
+            if quota.requests_per_minute.is_some() || quota.tokens_per_minute.is_some() {
+                tracing::info!(
+                    "Holding back traffic to provider '{}' near its tenant quota ({} requests, {} tokens per minute)",
+                    provider.name,
+                    quota
+                        .requests_per_minute
+                        .map_or("unlimited".to_string(), |n| n.to_string()),
+                    quota
+                        .tokens_per_minute
+                        .map_or("unlimited".to_string(), |n| n.to_string())
+                );
+            }
+        }
+        let deployment_health =
+            crate::deployment_health::DeploymentHealth::from_config(&config.deployment_health);
+        if deployment_health.is_some() {
+            tracing::info!(
+                "Ordering providers by deployment health (window {}s, max error rate {})",
+                config.deployment_health.window_secs,
+                config.deployment_health.max_error_rate
+            );
+        }
+
+        if crate::statsd::spawn(&config.statsd, &metrics)
+            .await?
+            .is_some()
+        {
+            tracing::info!(
+                "Sending StatsD metrics to {}:{} (prefix '{}')",
+                config.statsd.host,
+                config.statsd.port,
+                config.statsd.prefix
+            );
+        }
+
+        let slo = crate::slo::SloMonitor::new(&config.slo);
+        if let Some(ref monitor) = slo {
+            monitor.spawn(&metrics, client.clone());
+            tracing::info!(
+                "SLO alerts: error rate objective {}%{}{}",
+                config.slo.error_rate_objective * 100.0,
+                config
+                    .slo
+                    .latency_threshold_ms
+                    .map(|ms| format!(
+                        ", {}% of attempts under {}ms",
+                        config.slo.latency_objective * 100.0,
+                        ms
+                    ))
+                    .unwrap_or_default(),
+                if config.slo.webhook_url.is_some() {
+                    ", posted to the webhook"
+                } else {
+                    ""
+                }
+            );
+        }
+
+        let admission = crate::admission::AdmissionController::from_config(&config.admission);
+        if let Some(max) = config.admission.max_concurrent_requests {
+            tracing::info!(
+                "Admission control enabled (max {} concurrent, queue {}, timeout {}s)",
+                max,
+                config.admission.max_queue,
+                config.admission.queue_timeout_secs
+            );
+        }
+
+        let hmac_auth = crate::hmac_auth::HmacAuth::from_config(&config.hmac_auth);
+        if hmac_auth.is_some() {
+            tracing::info!(
+                "Signed request authentication enabled for {} client(s)",
+                config.hmac_auth.clients.len()
+            );
+        }
+
         let state = AppState {
             config: config.clone(),
             model_registry: model_registry.clone(),
@@ -440,19 +1025,128 @@ impl Cli {
             rate_limiter,
             quota_manager: quota_manager.clone(),
             request_limiter,
+            batches,
+            session_affinity: crate::session::SessionAffinity::default(),
+            cached_contents: crate::cached_content::CachedContents::default(),
+            admission,
+            stream_limiter,
+            output_pacer,
+            retry_budget,
+            dead_letters,
+            image_fetcher,
+            response_cache,
+            slo,
+            upstream_limits,
+            tenant_quotas,
+            deployment_health,
+            log_level: Some(log_level),
+            warmup: crate::warmup::Warmup::from_config(&config),
+            hmac_auth,
+            disabled_models: crate::model_switch::DisabledModels::from_config(&config.models),
         };
 
-        let app = create_router(state)
-            .layer(axum::extract::DefaultBodyLimit::max(10 * 1024 * 1024)) // 10 MB
+        // Warm up in the background: the server is already serving, and
+        // `/ready` reports when the warm-up is done.
+        if let Some(warmup) = state.warmup.clone() {
+            tracing::info!("Warming up model deployments");
+            let state = state.clone();
+            tokio::spawn(async move { warmup.run(&state).await });
+        }
+
+        // Routers a config reload can replace while serving.
+        let live_app = LiveRouter::new(create_router(state.clone()));
+        let live_admin = config
+            .admin_bind
+            .is_some()
+            .then(|| LiveRouter::new(create_admin_router(state.clone())));
+        let admin_app = live_admin.clone().map(|admin| {
+            Router::new()
+                .fallback_service(admin)
+                .layer(TraceLayer::new_for_http())
+        });
+        let config_path = Config::path(matches.get_one::<String>("config").map(|s| s.as_str()))?;
+        let overrides = matches.clone();
+        let reloader = ConfigReloader::new(
+            config_path.clone(),
+            move |config| Self::apply_overrides(&overrides, config),
+            state,
+            registry_task,
+            live_app.clone(),
+            live_admin,
+        );
+        #[cfg(feature = "cluster")]
+        let reloader = match cluster {
+            Some(ref cluster) => reloader.with_cluster(cluster.clone()),
+            None => reloader,
+        };
+        let reloader = std::sync::Arc::new(reloader);
+        #[cfg(unix)]
+        if let Err(e) = reloader.clone().reload_on_hangup() {
+            tracing::warn!("{:#}", e);
+        }
+        if config.watch_config {
+            match reloader.watch().await {
+                Ok(()) => tracing::info!("Watching {} for changes", config_path),
+                Err(e) => tracing::warn!("Config changes need a restart: {:#}", e),
+            }
+        }
+        let max_body_bytes = config.max_request_body_bytes();
+        let app = Router::new()
+            .fallback_service(live_app)
+            .layer(axum::extract::DefaultBodyLimit::max(max_body_bytes))
+            .layer(axum::middleware::from_fn_with_state(
+                max_body_bytes,
+                crate::body_limit::reject_oversized,
+            ))
+            .layer(axum::middleware::from_fn_with_state(
+                config.http2.server,
+                crate::http2::refuse_when_disabled,
+            ))
             .layer(CorsLayer::permissive())
             .layer(TraceLayer::new_for_http());
+        let make_service = crate::connections::CountConnections::new(
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+            &metrics,
+        );
 
-        let addr = crate::config::parse_bind_address(&config.bind)?;
-        let listener = tokio::net::TcpListener::bind(addr)
-            .await
-            .context("Failed to bind to address")?;
+        let listener = if let Some(listener) = crate::systemd::inherited_listener()? {
+            tracing::info!("Using the listener passed by systemd; `bind` doesn't apply");
+            listener
+        } else if let Some(listener) = crate::daemon::handed_over_listener()? {
+            tracing::info!("Taking over the listener of the previous router");
+            listener
+        } else {
+            tokio::net::TcpListener::bind(crate::config::parse_bind_address(&config.bind)?)
+                .await
+                .context("Failed to bind to address")?
+        };
+        let addr = listener.local_addr()?;
 
-        tracing::info!("Server listening on {}", addr);
+        tracing::info!(
+            "Server listening on {} (HTTP/2: {})",
+            addr,
+            if config.http2.server { "on" } else { "off" }
+        );
+
+        if let (Some(admin_bind), Some(admin_app)) = (&config.admin_bind, admin_app) {
+            let admin_listener = Self::bind_admin(crate::config::parse_bind_address(admin_bind)?)
+                .context("Failed to bind admin_bind")?;
+            tracing::info!(
+                "Admin routes (/metrics, /admin/*) listening on {}",
+                admin_listener.local_addr()?
+            );
+            // In-flight admin requests are short; they end with the process.
+            tokio::spawn(async move {
+                if let Err(e) = axum::serve(
+                    admin_listener,
+                    admin_app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .await
+                {
+                    tracing::error!("Admin server error: {}", e);
+                }
+            });
+        }
 
         // TUI mode: run server in background, TUI in foreground
         #[cfg(feature = "tui")]
@@ -461,17 +1155,14 @@ impl Cli {
             let tui_quota_manager = quota_manager.clone();
 
             tokio::spawn(async move {
-                axum::serve(
-                    listener,
-                    app.into_make_service_with_connect_info::<SocketAddr>(),
-                )
-                .with_graceful_shutdown(async {
-                    let _ = shutdown_rx.await;
-                    tracing::info!("TUI exited, shutting down server gracefully...");
-                })
-                .await
-                .inspect_err(|e| tracing::error!("Server error during TUI shutdown: {}", e))
-                .ok();
+                axum::serve(listener, make_service)
+                    .with_graceful_shutdown(async {
+                        let _ = shutdown_rx.await;
+                        tracing::info!("TUI exited, shutting down server gracefully...");
+                    })
+                    .await
+                    .inspect_err(|e| tracing::error!("Server error during TUI shutdown: {}", e))
+                    .ok();
             });
 
             let api_keys = config.api_key_strings();
@@ -490,21 +1181,145 @@ impl Cli {
             let _ = shutdown_tx.send(());
             // Give the server a moment to finish in-flight requests
             tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            Self::save_quota_state(quota_state).await;
             return Ok(());
         }
 
-        axum::serve(
-            listener,
-            app.into_make_service_with_connect_info::<SocketAddr>(),
-        )
-        .with_graceful_shutdown(Self::shutdown_signal())
-        .await
-        .context("Server error")?;
+        // The model registry has resolved the deployments by now.
+        crate::systemd::notify(&format!("READY=1\nSTATUS=Listening on {addr}"));
 
+        // Started by `acr serve --daemon`: answer `acr status` and `acr stop`
+        // until shut down.
+        #[cfg(unix)]
+        let control = if crate::daemon::is_daemon_child() {
+            let config_path = matches.get_one::<String>("config").map(|s| s.as_str());
+            Some(crate::daemon::ControlSocket::open(
+                crate::daemon::DaemonPaths::next_to(&Config::path(config_path)?),
+                &listener,
+                metrics.clone(),
+            )?)
+        } else {
+            None
+        };
+        #[cfg(unix)]
+        let shutdown = {
+            let stopped = control.as_ref().map(|control| control.stopped());
+            async move {
+                match stopped {
+                    Some(stopped) => tokio::select! {
+                        _ = Self::shutdown_signal() => {}
+                        _ = stopped => {}
+                    },
+                    None => Self::shutdown_signal().await,
+                }
+                crate::systemd::notify("STOPPING=1");
+            }
+        };
+        #[cfg(not(unix))]
+        let shutdown = async {
+            Self::shutdown_signal().await;
+            crate::systemd::notify("STOPPING=1");
+        };
+
+        axum::serve(listener, make_service)
+            .with_graceful_shutdown(shutdown)
+            .await
+            .context("Server error")?;
+
+        Self::save_quota_state(quota_state).await;
         tracing::info!("Server shut down gracefully");
+        // Removing the pid file tells `acr stop` the router is gone.
+        #[cfg(unix)]
+        drop(control);
         Ok(())
     }
 
+    /// `acr status`: exits with 1 when the background router isn't running.
+    async fn daemon_status(config_file: &str) -> Result<()> {
+        let paths = crate::daemon::DaemonPaths::next_to(config_file);
+        let Some(status) = crate::daemon::status(&paths).await? else {
+            println!("acr is not running");
+            std::process::exit(1);
+        };
+        let uptime = (chrono::Utc::now() - status.started_at)
+            .num_seconds()
+            .max(0) as u64;
+        println!("acr is running (pid {})", status.pid);
+        println!("  version:   {}", status.version);
+        println!("  listening: {}", status.bind);
+        println!("  uptime:    {}", crate::daemon::format_uptime(uptime));
+        println!(
+            "  requests:  {} total, {} active",
+            status.total_requests, status.active_requests
+        );
+        println!("  log:       {}", paths.log_file.display());
+        Ok(())
+    }
+
+    /// Startup credential check (`--check` / `verify_on_startup`): fetch a
+    /// token and list deployments for every enabled provider, so bad
+    /// credentials stop the router here rather than on the first proxied
+    /// request. All providers are checked before failing, and the error
+    /// names each one that failed.
+    async fn verify_providers(providers: &[Provider], token_manager: &TokenManager) -> Result<()> {
+        let timeout =
+            std::time::Duration::from_secs(crate::constants::config::STARTUP_CHECK_TIMEOUT_SECS);
+        let mut failures = Vec::new();
+        for provider in providers.iter().filter(|p| p.enabled) {
+            let client = AiCoreClient::from_provider(provider.clone(), token_manager.clone());
+            let result = match tokio::time::timeout(timeout, client.verify()).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!(
+                    "no response from AI Core within {}s",
+                    timeout.as_secs()
+                )),
+            };
+            match result {
+                Ok(count) => tracing::info!(
+                    "Provider '{}' verified: {} deployment(s) in resource group '{}'",
+                    provider.name,
+                    count,
+                    provider.resource_group
+                ),
+                Err(e) => failures.push(format!("  provider '{}': {:#}", provider.name, e)),
+            }
+        }
+        if !failures.is_empty() {
+            anyhow::bail!("Startup check failed:\n{}", failures.join("\n"));
+        }
+        Ok(())
+    }
+
+    /// Listener for `admin_bind`. On Unix it is bound with `SO_REUSEPORT`,
+    /// so the router taking over in `acr upgrade` can bind it while the old
+    /// one drains.
+    fn bind_admin(addr: SocketAddr) -> Result<tokio::net::TcpListener> {
+        let socket = if addr.is_ipv4() {
+            tokio::net::TcpSocket::new_v4()?
+        } else {
+            tokio::net::TcpSocket::new_v6()?
+        };
+        #[cfg(unix)]
+        {
+            socket.set_reuseaddr(true)?;
+            socket.set_reuseport(true)?;
+        }
+        socket.bind(addr)?;
+        Ok(socket.listen(1024)?)
+    }
+
+    /// Final save of quota counters on shutdown; the periodic task may be up
+    /// to one interval behind.
+    async fn save_quota_state(
+        quota_state: Option<(crate::quota::QuotaManager, std::path::PathBuf)>,
+    ) {
+        if let Some((qm, path)) = quota_state
+            && let Err(e) = qm.save_state(&path).await
+        {
+            tracing::warn!("Failed to save quota state: {:#}", e);
+        }
+    }
+
     async fn shutdown_signal() {
         let ctrl_c = async {
             if let Err(e) = tokio::signal::ctrl_c().await {
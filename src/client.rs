@@ -108,13 +108,25 @@ impl AiCoreClient {
         }
     }
 
-    async fn get_token(&self) -> Result<String> {
+    pub(crate) async fn get_token(&self) -> Result<String> {
         self.token_manager
             .get_token_for_provider("internal", &self.provider)
             .await?
             .ok_or_else(|| anyhow::anyhow!("Failed to get authentication token"))
     }
 
+    /// The provider's static headers (`providers[].headers`).
+    fn provider_headers(&self) -> Result<reqwest::header::HeaderMap> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in &self.provider.headers {
+            headers.insert(
+                reqwest::header::HeaderName::from_bytes(name.as_bytes())?,
+                reqwest::header::HeaderValue::from_str(value)?,
+            );
+        }
+        Ok(headers)
+    }
+
     pub async fn list_resource_groups(&self) -> Result<ResourceGroupList> {
         let token = self.get_token().await?;
         let url = format!("{}/v2/admin/resourceGroups", self.provider.genai_api_url);
@@ -122,6 +134,7 @@ impl AiCoreClient {
         let response = self
             .client
             .get(&url)
+            .headers(self.provider_headers()?)
             .header("Authorization", format!("Bearer {token}"))
             .header("Content-Type", "application/json")
             .send()
@@ -153,6 +166,7 @@ impl AiCoreClient {
         let mut request = self
             .client
             .get(&url)
+            .headers(self.provider_headers()?)
             .header("Authorization", format!("Bearer {token}"))
             .header("Content-Type", "application/json");
 
@@ -181,4 +195,24 @@ impl AiCoreClient {
 
         Ok(deployments)
     }
+
+    /// Fetch a token and list the provider's deployments once, returning
+    /// how many there are. Each step fails with its own message, so a bad
+    /// client secret reads differently from a wrong API URL or resource
+    /// group.
+    pub async fn verify(&self) -> Result<usize> {
+        self.get_token().await.with_context(|| {
+            format!(
+                "could not fetch a token from {}",
+                self.provider.uaa_token_url
+            )
+        })?;
+        let deployments = self.list_deployments(None).await.with_context(|| {
+            format!(
+                "could not list deployments in resource group '{}' at {}",
+                self.provider.resource_group, self.provider.genai_api_url
+            )
+        })?;
+        Ok(deployments.resources.len())
+    }
 }
@@ -0,0 +1,229 @@
+//! Client IP behind reverse proxies.
+//!
+//! Requests from an address in `trusted_proxies` carry the client's address
+//! in `Forwarded` (RFC 7239) or, without it, `X-Forwarded-For`. The
+//! middleware here reads it and replaces the request's `ConnectInfo`, so auth
+//! rate limiting, the loopback-only `internal` key and the log span all see
+//! the client instead of the proxy. Headers from any other peer are ignored,
+//! since a client can send whatever it likes.
+//!
+//! The forwarded chain is read right to left: each trusted proxy appended the
+//! address it received from, so the first untrusted entry is the client.
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use crate::constants::api::{FORWARDED_HEADER, X_FORWARDED_FOR_HEADER};
+
+/// Parse a `trusted_proxies` entry: an address or a CIDR range.
+pub fn parse_proxy(entry: &str) -> anyhow::Result<IpNet> {
+    let entry = entry.trim();
+    if let Ok(ip) = entry.parse::<IpAddr>() {
+        return Ok(IpNet::from(ip));
+    }
+    entry.parse::<IpNet>().map_err(|_| {
+        anyhow::anyhow!("trusted_proxies entry '{entry}' is not an IP address or CIDR range")
+    })
+}
+
+/// The parsed `trusted_proxies`.
+#[derive(Clone, Default)]
+pub struct TrustedProxies(Arc<[IpNet]>);
+
+impl TrustedProxies {
+    /// Entries that don't parse are skipped; `Config::validate` rejects them.
+    pub fn new(entries: &[String]) -> Self {
+        Self(entries.iter().filter_map(|e| parse_proxy(e).ok()).collect())
+    }
+
+    fn trusts(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|net| net.contains(&ip))
+    }
+
+    /// The client's address for a request received from `peer`.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.trusts(peer) {
+            return peer;
+        }
+        let chain = forwarded_for(headers);
+        let mut client = peer;
+        for hop in chain.iter().rev() {
+            // An obfuscated or malformed hop ends the chain we can vouch for.
+            let Some(ip) = hop else {
+                break;
+            };
+            client = *ip;
+            if !self.trusts(*ip) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// The addresses in `Forwarded` `for=` parameters or, without that header,
+/// `X-Forwarded-For`, oldest first; `None` for entries that aren't an IP
+/// address (`unknown`, `_hidden`).
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<_>>()
+    };
+    let forwarded = values(FORWARDED_HEADER);
+    if !forwarded.is_empty() {
+        return forwarded
+            .into_iter()
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| parse_node(value))
+            })
+            .collect();
+    }
+    values(X_FORWARDED_FOR_HEADER)
+        .into_iter()
+        .map(parse_node)
+        .collect()
+}
+
+/// An address with an optional port: `192.0.2.1`, `192.0.2.1:4711`,
+/// `2001:db8::1` or `[2001:db8::1]:4711`, optionally quoted.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
+
+/// Middleware replacing `ConnectInfo` with the client's address when the
+/// peer is a trusted proxy.
+pub async fn resolve(
+    State(trusted): State<TrustedProxies>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(ConnectInfo(peer)) = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .copied()
+    {
+        let client = trusted.client_ip(peer.ip(), request.headers());
+        if client != peer.ip() {
+            // The client's port isn't forwarded reliably; nothing reads it.
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::new(client, 0)));
+        }
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trusted(entries: &[&str]) -> TrustedProxies {
+        TrustedProxies::new(&entries.iter().map(|e| e.to_string()).collect::<Vec<_>>())
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(
+                axum::http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_headers_count_only_from_trusted_peers() {
+        let proxies = trusted(&["10.0.0.0/8", "192.0.2.7"]);
+        let xff = headers(&[("x-forwarded-for", "203.0.113.9")]);
+        assert_eq!(proxies.client_ip(ip("10.1.2.3"), &xff), ip("203.0.113.9"));
+        assert_eq!(proxies.client_ip(ip("192.0.2.7"), &xff), ip("203.0.113.9"));
+        // Anyone else could have written the header themselves.
+        assert_eq!(
+            proxies.client_ip(ip("198.51.100.1"), &xff),
+            ip("198.51.100.1")
+        );
+        // IPv4-mapped IPv6 peers match IPv4 ranges.
+        assert_eq!(
+            proxies.client_ip(ip("::ffff:10.1.2.3"), &xff),
+            ip("203.0.113.9")
+        );
+        // No header: the proxy itself.
+        assert_eq!(
+            proxies.client_ip(ip("10.1.2.3"), &HeaderMap::new()),
+            ip("10.1.2.3")
+        );
+    }
+
+    #[test]
+    fn the_chain_is_read_from_the_right() {
+        let proxies = trusted(&["10.0.0.0/8"]);
+        // A client-supplied first entry is skipped over, trusted hops too.
+        let xff = headers(&[("x-forwarded-for", "1.1.1.1, 203.0.113.9, 10.0.0.5")]);
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &xff), ip("203.0.113.9"));
+        // Several headers form one list.
+        let split = headers(&[
+            ("x-forwarded-for", "1.1.1.1"),
+            ("x-forwarded-for", "203.0.113.9:5151"),
+        ]);
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &split), ip("203.0.113.9"));
+        // A malformed hop stops at the last address a trusted proxy vouched for.
+        let garbled = headers(&[("x-forwarded-for", "203.0.113.9, unknown, 10.0.0.5")]);
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &garbled), ip("10.0.0.5"));
+    }
+
+    #[test]
+    fn forwarded_takes_precedence_over_x_forwarded_for() {
+        let proxies = trusted(&["10.0.0.1"]);
+        let both = headers(&[
+            ("x-forwarded-for", "198.51.100.1"),
+            (
+                "forwarded",
+                r#"for=192.0.2.60;proto=http, For="[2001:db8:cafe::17]:4711""#,
+            ),
+        ]);
+        assert_eq!(
+            proxies.client_ip(ip("10.0.0.1"), &both),
+            ip("2001:db8:cafe::17")
+        );
+        let hidden = headers(&[("forwarded", "for=_hidden")]);
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &hidden), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn entries_are_addresses_or_ranges() {
+        assert!(parse_proxy("127.0.0.1").is_ok());
+        assert!(parse_proxy("fd00::/8").is_ok());
+        assert!(parse_proxy("10.0.0.0/33").is_err());
+        assert!(parse_proxy("proxy.internal").is_err());
+    }
+}
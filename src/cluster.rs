@@ -0,0 +1,385 @@
+//! State shared by several routers through Redis.
+//!
+//! Routers behind one load balancer each see only the requests they serve,
+//! so per-key limits kept in memory let a key through once per router. With
+//! `cluster.redis_url` set, the `requests_per_minute` window and the daily
+//! and monthly token counters live in Redis instead, and every router checks
+//! and updates the same ones.
+//!
+//! The request window is a sorted set of request timestamps per key: each
+//! request drops the entries older than a minute and adds its own, in one
+//! transaction, and is rejected (and removed again) if that makes more than
+//! the limit. Timestamps come from each router's clock, so routers need
+//! their clocks in sync to within a small part of a minute. Token counters
+//! are one integer per key and day and per key and month, expiring after
+//! their period is over.
+//!
+//! OAuth tokens are shared too, so a fleet that starts at once asks UAA for
+//! one token per set of credentials rather than one per router. A router
+//! that needs a token takes it from Redis, or takes a short lock and fetches
+//! it; routers that find the lock taken wait for the token to appear. A
+//! fetched token replaces the shared one only if it expires later, checked
+//! and set in one script.
+//!
+//! When Redis can't be reached, limits fall back to the router's own counts,
+//! and each router fetches its own tokens, until it is back; the outage is
+//! logged once a minute. After a failed call Redis is left alone for a few
+//! seconds, so an outage doesn't add a timeout to every request.
+
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+
+use crate::constants::cluster::{
+    DAILY_COUNTER_TTL_SECS, ERROR_LOG_INTERVAL_SECS, MONTHLY_COUNTER_TTL_SECS, RATE_WINDOW_MS,
+    RETRY_PAUSE_MS, TIMEOUT_MS, TOKEN_REFRESH_LOCK_MS,
+};
+use crate::request_limiter::{RequestLimitResult, RequestRate};
+
+/// A connection to the Redis shared by the cluster. Cheap to clone; clones
+/// share the connection.
+#[derive(Clone)]
+pub struct Cluster {
+    conn: ConnectionManager,
+    prefix: Arc<str>,
+    /// Unix seconds of the last logged Redis error
+    last_error_log: Arc<AtomicU64>,
+    /// Unix milliseconds until which Redis isn't tried, after a failure
+    paused_until: Arc<AtomicU64>,
+}
+
+impl std::fmt::Debug for Cluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cluster")
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Cluster {
+    /// Connect to the Redis at `url`. Keys are prefixed with `prefix:`.
+    pub async fn connect(url: &str, prefix: &str) -> Result<Self> {
+        let client = redis::Client::open(url).context("Invalid cluster.redis_url")?;
+        let timeout = Duration::from_millis(TIMEOUT_MS);
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(timeout)
+            .set_response_timeout(timeout)
+            .set_number_of_retries(1);
+        let conn = client
+            .get_connection_manager_with_config(config)
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(Self {
+            conn,
+            prefix: prefix.into(),
+            last_error_log: Arc::new(AtomicU64::new(0)),
+            paused_until: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    async fn query<T: redis::FromRedisValue>(&self, pipe: &redis::Pipeline) -> Result<T> {
+        let mut conn = self.conn.clone();
+        self.call(pipe.query_async(&mut conn)).await
+    }
+
+    /// Run a Redis call, unless one failed moments ago.
+    async fn call<T>(&self, call: impl Future<Output = redis::RedisResult<T>>) -> Result<T> {
+        let now = now_ms();
+        if now < self.paused_until.load(Ordering::Relaxed) {
+            anyhow::bail!("Redis failed moments ago");
+        }
+        call.await
+            .inspect_err(|_| {
+                self.paused_until
+                    .store(now + RETRY_PAUSE_MS, Ordering::Relaxed)
+            })
+            .map_err(Into::into)
+    }
+
+    fn key(&self, parts: &[&str]) -> String {
+        std::iter::once(&*self.prefix)
+            .chain(parts.iter().copied())
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    fn token_keys(&self, key_hash: &str, today: NaiveDate) -> (String, String) {
+        (
+            self.key(&["tokens", key_hash, &today.format("%Y-%m-%d").to_string()]),
+            self.key(&["tokens", key_hash, &today.format("%Y-%m").to_string()]),
+        )
+    }
+
+    /// Count a request from `key_hash` against its shared window of `limit`
+    /// requests per minute.
+    pub async fn take_request(
+        &self,
+        key_hash: &str,
+        limit: NonZeroU32,
+    ) -> Result<RequestLimitResult> {
+        let key = self.key(&["requests", key_hash]);
+        let now = now_ms();
+        let member = uuid::Uuid::new_v4().to_string();
+        let (count, oldest): (u32, Vec<(String, f64)>) = self
+            .query(
+                redis::pipe()
+                    .atomic()
+                    .zrembyscore(&key, "-inf", now.saturating_sub(RATE_WINDOW_MS) as f64)
+                    .ignore()
+                    .zadd(&key, &member, now as f64)
+                    .ignore()
+                    .zcard(&key)
+                    .zrange_withscores(&key, 0, 0)
+                    .pexpire(&key, RATE_WINDOW_MS as i64)
+                    .ignore(),
+            )
+            .await
+            .context("Failed to count the request in Redis")?;
+
+        let limit = limit.get();
+        if count <= limit {
+            return Ok(RequestLimitResult::Allowed {
+                rate: Some(RequestRate {
+                    limit,
+                    remaining: limit - count,
+                    // This request leaves the window last.
+                    reset: Duration::from_millis(RATE_WINDOW_MS),
+                }),
+            });
+        }
+        // Rejected requests don't take a place in the window.
+        let _: () = self
+            .query(redis::pipe().zrem(&key, &member).ignore())
+            .await
+            .context("Failed to remove the rejected request from Redis")?;
+        let wait_ms = oldest.first().map_or(RATE_WINDOW_MS, |(_, at)| {
+            (*at as u64 + RATE_WINDOW_MS).saturating_sub(now)
+        });
+        Ok(RequestLimitResult::Exceeded {
+            retry_after_secs: wait_ms.div_ceil(1000).max(1),
+            limit,
+        })
+    }
+
+    /// Tokens `key_hash` has used today and this month, UTC.
+    pub async fn token_usage(&self, key_hash: &str, today: NaiveDate) -> Result<(u64, u64)> {
+        let (daily, monthly) = self.token_keys(key_hash, today);
+        let (daily, monthly): (Option<u64>, Option<u64>) = self
+            .query(redis::pipe().get(daily).get(monthly))
+            .await
+            .context("Failed to read token usage from Redis")?;
+        Ok((daily.unwrap_or(0), monthly.unwrap_or(0)))
+    }
+
+    /// Add `tokens` to today's and this month's counters for `key_hash`,
+    /// returning the new totals.
+    pub async fn add_tokens(
+        &self,
+        key_hash: &str,
+        today: NaiveDate,
+        tokens: u64,
+    ) -> Result<(u64, u64)> {
+        let (daily, monthly) = self.token_keys(key_hash, today);
+        self.query(
+            redis::pipe()
+                .atomic()
+                .incr(&daily, tokens)
+                .expire(&daily, DAILY_COUNTER_TTL_SECS)
+                .ignore()
+                .incr(&monthly, tokens)
+                .expire(&monthly, MONTHLY_COUNTER_TTL_SECS)
+                .ignore(),
+        )
+        .await
+        .context("Failed to add token usage in Redis")
+    }
+
+    /// Start today's and this month's counters for `key_hash` at the given
+    /// totals, unless another router already has.
+    pub async fn seed_tokens(
+        &self,
+        key_hash: &str,
+        today: NaiveDate,
+        daily: u64,
+        monthly: u64,
+    ) -> Result<()> {
+        let (daily_key, monthly_key) = self.token_keys(key_hash, today);
+        let set = |ttl: i64| {
+            redis::SetOptions::default()
+                .conditional_set(redis::ExistenceCheck::NX)
+                .with_expiration(redis::SetExpiry::EX(ttl as u64))
+        };
+        self.query(
+            redis::pipe()
+                .set_options(daily_key, daily, set(DAILY_COUNTER_TTL_SECS))
+                .ignore()
+                .set_options(monthly_key, monthly, set(MONTHLY_COUNTER_TTL_SECS))
+                .ignore(),
+        )
+        .await
+        .context("Failed to seed token usage in Redis")
+    }
+
+    /// The OAuth token another router published for the credentials
+    /// hashed to `token_key`, and when it expires.
+    pub async fn shared_token(&self, token_key: &str) -> Result<Option<(String, DateTime<Utc>)>> {
+        let key = self.key(&["oauth", token_key]);
+        let ((token, expires_at),): ((Option<String>, Option<i64>),) = self
+            .query(redis::pipe().hmget(&key, &["token", "expires_at"]))
+            .await
+            .context("Failed to read the shared OAuth token from Redis")?;
+        Ok(token.zip(expires_at.and_then(DateTime::from_timestamp_millis)))
+    }
+
+    /// Take the lock on refreshing the token for `token_key` as `owner`,
+    /// unless another router holds it. The lock lapses after
+    /// [`TOKEN_REFRESH_LOCK_MS`] if its holder never publishes.
+    pub async fn claim_token_refresh(&self, token_key: &str, owner: &str) -> Result<bool> {
+        let lock = self.key(&["oauth-refresh", token_key]);
+        let options = redis::SetOptions::default()
+            .conditional_set(redis::ExistenceCheck::NX)
+            .with_expiration(redis::SetExpiry::PX(TOKEN_REFRESH_LOCK_MS));
+        let (claimed,): (Option<String>,) = self
+            .query(redis::pipe().set_options(lock, owner, options))
+            .await
+            .context("Failed to lock the OAuth token refresh in Redis")?;
+        Ok(claimed.is_some())
+    }
+
+    /// Share a token for `token_key` with the other routers, unless the one
+    /// in Redis lasts at least as long, and release `owner`'s refresh lock.
+    /// Returns whether the token replaced the shared one.
+    pub async fn publish_token(
+        &self,
+        token_key: &str,
+        token: &str,
+        expires_at: DateTime<Utc>,
+        owner: &str,
+    ) -> Result<bool> {
+        let script = redis::Script::new(PUBLISH_TOKEN_SCRIPT);
+        let mut invocation = script.prepare_invoke();
+        invocation
+            .key(self.key(&["oauth", token_key]))
+            .key(self.key(&["oauth-refresh", token_key]))
+            .arg(token)
+            .arg(expires_at.timestamp_millis())
+            .arg(owner);
+        let mut conn = self.conn.clone();
+        self.call(invocation.invoke_async(&mut conn))
+            .await
+            .context("Failed to publish the OAuth token to Redis")
+    }
+
+    /// Log a failed Redis call, at most once per
+    /// [`ERROR_LOG_INTERVAL_SECS`].
+    pub fn report_error(&self, error: &anyhow::Error) {
+        let now = now_ms() / 1000;
+        let last = self.last_error_log.load(Ordering::Relaxed);
+        if now.saturating_sub(last) >= ERROR_LOG_INTERVAL_SECS
+            && self
+                .last_error_log
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            tracing::warn!(
+                "Redis unavailable, limits and OAuth tokens are this router's own: {:#}",
+                error
+            );
+        }
+    }
+}
+
+/// Replaces the shared token only with one that expires later, so a router
+/// finishing a slow refresh can't overwrite a newer token, and releases the
+/// refresh lock if the caller still holds it.
+const PUBLISH_TOKEN_SCRIPT: &str = r"
+local current = tonumber(redis.call('HGET', KEYS[1], 'expires_at'))
+local replaced = 0
+if not current or current < tonumber(ARGV[2]) then
+    redis.call('HSET', KEYS[1], 'token', ARGV[1], 'expires_at', ARGV[2])
+    redis.call('PEXPIREAT', KEYS[1], ARGV[2])
+    replaced = 1
+end
+if redis.call('GET', KEYS[2]) == ARGV[3] then
+    redis.call('DEL', KEYS[2])
+end
+return replaced
+";
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs against the Redis in `ACR_TEST_REDIS_URL`, e.g.
+    /// `redis://127.0.0.1:6379`.
+    #[tokio::test]
+    #[ignore = "needs a Redis server in ACR_TEST_REDIS_URL"]
+    async fn test_routers_share_windows_and_counters() {
+        let url = std::env::var("ACR_TEST_REDIS_URL").unwrap();
+        let prefix = format!("acr-test-{}", uuid::Uuid::new_v4());
+        let (a, b) = (
+            Cluster::connect(&url, &prefix).await.unwrap(),
+            Cluster::connect(&url, &prefix).await.unwrap(),
+        );
+        let limit = NonZeroU32::new(3).unwrap();
+
+        for (router, remaining) in [(&a, 2), (&b, 1), (&a, 0)] {
+            match router.take_request("key", limit).await.unwrap() {
+                RequestLimitResult::Allowed { rate: Some(rate) } => {
+                    assert_eq!(rate.remaining, remaining)
+                }
+                _ => panic!("expected an allowed request"),
+            }
+        }
+        for router in [&b, &a] {
+            match router.take_request("key", limit).await.unwrap() {
+                RequestLimitResult::Exceeded {
+                    retry_after_secs, ..
+                } => assert!((1..=60).contains(&retry_after_secs)),
+                RequestLimitResult::Allowed { .. } => panic!("expected a rejection"),
+            }
+        }
+
+        let today = chrono::Utc::now().date_naive();
+        a.seed_tokens("key", today, 100, 1000).await.unwrap();
+        b.seed_tokens("key", today, 5, 5).await.unwrap();
+        assert_eq!(a.add_tokens("key", today, 10).await.unwrap(), (110, 1010));
+        assert_eq!(b.token_usage("key", today).await.unwrap(), (110, 1010));
+        assert_eq!(b.token_usage("other", today).await.unwrap(), (0, 0));
+
+        assert_eq!(a.shared_token("creds").await.unwrap(), None);
+        assert!(a.claim_token_refresh("creds", "a").await.unwrap());
+        assert!(!b.claim_token_refresh("creds", "b").await.unwrap());
+        let expires_at = DateTime::from_timestamp_millis(now_ms() as i64 + 3_600_000).unwrap();
+        assert!(
+            a.publish_token("creds", "token-a", expires_at, "a")
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            b.shared_token("creds").await.unwrap(),
+            Some(("token-a".to_string(), expires_at))
+        );
+        // The lock is released, and an older token doesn't replace a newer one.
+        assert!(b.claim_token_refresh("creds", "b").await.unwrap());
+        let older = expires_at - chrono::Duration::minutes(1);
+        assert!(
+            !b.publish_token("creds", "token-b", older, "b")
+                .await
+                .unwrap()
+        );
+        assert_eq!(a.shared_token("creds").await.unwrap().unwrap().0, "token-a");
+    }
+}
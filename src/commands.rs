@@ -1,5 +1,6 @@
 //! CLI command handlers for administrative operations.
 
+use crate::proxy::LlmFamily;
 #[cfg(feature = "db")]
 use crate::table::format_number;
 use crate::table::{Align, CliTable, Col};
@@ -12,6 +13,117 @@ pub struct CommandHandler {
     config: Config,
 }
 
+/// The router path and body `acr send` posts: `{model}` in the route is
+/// replaced with the model, and otherwise `--model` overrides the body's.
+fn send_target(
+    route: &str,
+    model: Option<&str>,
+    mut body: serde_json::Value,
+) -> Result<(String, serde_json::Value)> {
+    if !route.starts_with('/') {
+        anyhow::bail!("Route '{route}' must start with '/'");
+    }
+    if route.contains("{model}") {
+        let model = model
+            .map(str::to_string)
+            .or_else(|| body["model"].as_str().map(str::to_string))
+            .with_context(|| format!("Route '{route}' needs a model; pass --model"))?;
+        return Ok((route.replace("{model}", &model), body));
+    }
+    if let Some(model) = model {
+        let Some(object) = body.as_object_mut() else {
+            anyhow::bail!("The request file must hold a JSON object");
+        };
+        object.insert("model".to_string(), model.into());
+    }
+    Ok((route.to_string(), body))
+}
+
+/// The `acr models export` snippet for `format`: every model behind acr's
+/// OpenAI-compatible `base_url`, which translates for Claude and Gemini, and
+/// a placeholder where the API key goes.
+fn client_config(format: &str, base_url: &str, models: &[String]) -> Result<String> {
+    let yaml = |value: serde_json::Value| {
+        serde_yaml_ng::to_string(&value).context("Failed to serialize client configuration")
+    };
+    match format {
+        // Continue's config.yaml
+        "continue" => yaml(serde_json::json!({
+            "name": "acr",
+            "version": "1.0.0",
+            "schema": "v1",
+            "models": models.iter().map(|model| serde_json::json!({
+                "name": model,
+                "provider": "openai",
+                "model": model,
+                "apiBase": base_url,
+                "apiKey": "${{ secrets.ACR_API_KEY }}",
+                "roles": ["chat", "edit", "apply"],
+            })).collect::<Vec<_>>(),
+        })),
+        // aider's .aider.conf.yml; LiteLLM's `openai/` prefix routes the
+        // model to the OpenAI-compatible base.
+        "aider" => {
+            let mut out = format!(
+                "openai-api-base: {base_url}\nopenai-api-key: <ACR_API_KEY>\nmodel: openai/{}\n",
+                models[0]
+            );
+            if models.len() > 1 {
+                out.push_str("# Other models served by acr:\n");
+                for model in &models[1..] {
+                    out.push_str(&format!("#   model: openai/{model}\n"));
+                }
+            }
+            Ok(out)
+        }
+        // LiteLLM proxy's config.yaml
+        "litellm" => yaml(serde_json::json!({
+            "model_list": models.iter().map(|model| serde_json::json!({
+                "model_name": model,
+                "litellm_params": {
+                    "model": format!("openai/{model}"),
+                    "api_base": base_url,
+                    "api_key": "os.environ/ACR_API_KEY",
+                },
+            })).collect::<Vec<_>>(),
+        })),
+        other => anyhow::bail!("Unknown format '{other}'; use continue, aider or litellm"),
+    }
+}
+
+/// Result of `acr providers` probing one provider: how long a token took,
+/// then how long listing its deployments took and how many there were. No
+/// API call is made when the token fails.
+struct ProviderProbe {
+    token: Result<std::time::Duration>,
+    api: Option<Result<(std::time::Duration, usize)>>,
+}
+
+impl ProviderProbe {
+    fn error(&self) -> Option<&anyhow::Error> {
+        match (&self.token, &self.api) {
+            (Err(e), _) | (_, Some(Err(e))) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// The TOKEN and API ROUND-TRIP cells.
+    fn cells(&self) -> [String; 2] {
+        let token = match &self.token {
+            Ok(elapsed) => format!("ok ({}ms)", elapsed.as_millis()),
+            Err(_) => "failed".to_string(),
+        };
+        let api = match &self.api {
+            None => "-".to_string(),
+            Some(Ok((elapsed, deployments))) => {
+                format!("{}ms ({deployments} deployments)", elapsed.as_millis())
+            }
+            Some(Err(_)) => "failed".to_string(),
+        };
+        [token, api]
+    }
+}
+
 /// Picked Claude models for the per-family `ANTHROPIC_*_MODEL` env vars that
 /// `acr configure claude` writes into `~/.claude/settings.json`.
 ///
@@ -124,7 +236,7 @@ fn pick_newest_in_family(models: &[crate::config::Model], family: &str) -> Optio
 impl CommandHandler {
     pub fn new(config: Config) -> Result<Self> {
         // Create a token manager for CLI operations
-        let token_manager = TokenManager::new(config.api_key_strings());
+        let token_manager = TokenManager::from_api_keys(&config.api_keys);
 
         // Use the first provider for CLI commands
         let provider = config
@@ -146,10 +258,21 @@ impl CommandHandler {
             .find(|p| p.resource_group == resource_group)
             .unwrap_or_else(|| self.config.providers.first().unwrap());
 
-        let token_manager = TokenManager::new(self.config.api_key_strings());
+        let token_manager = TokenManager::from_api_keys(&self.config.api_keys);
         AiCoreClient::from_provider(provider.clone(), token_manager)
     }
 
+    /// Base URL of the local router, including `mount.path_prefix`.
+    fn router_url(&self) -> Result<String> {
+        let addr =
+            crate::config::parse_bind_address(&self.config.bind).context("Invalid bind address")?;
+        Ok(format!(
+            "http://localhost:{}{}",
+            addr.port(),
+            self.config.path_prefix()
+        ))
+    }
+
     pub async fn list_resource_groups(&self) -> Result<()> {
         println!("Fetching resource groups...");
         let resource_groups = self.client.list_resource_groups().await?;
@@ -330,8 +453,7 @@ impl CommandHandler {
         let settings_path = claude_dir.join("settings.json");
         let onboarding_path = home_path.join(".claude.json");
 
-        let addr =
-            crate::config::parse_bind_address(&self.config.bind).context("Invalid bind address")?;
+        let router_url = self.router_url()?;
         let api_key = &self
             .config
             .api_keys
@@ -339,7 +461,7 @@ impl CommandHandler {
             .context("No API keys configured")?
             .key;
 
-        let base_url = format!("http://localhost:{}/v1", addr.port());
+        let base_url = format!("{router_url}/v1");
 
         // Derive per-family Claude model env vars from the configured models.
         let claude_models = ClaudeModelChoices::from_models(&self.config.models);
@@ -551,8 +673,7 @@ impl CommandHandler {
     /// Writes opencode.jsonc with providers for Anthropic, OpenAI, and Gemini
     /// all pointing at this router's endpoints.
     pub fn configure_opencode(&self) -> Result<()> {
-        let addr =
-            crate::config::parse_bind_address(&self.config.bind).context("Invalid bind address")?;
+        let router_url = self.router_url()?;
         let api_key = &self
             .config
             .api_keys
@@ -571,7 +692,7 @@ impl CommandHandler {
                     .join("opencode.jsonc")
             });
 
-        let base_url = format!("http://localhost:{}", addr.port());
+        let base_url = router_url;
 
         // Read existing config or start fresh
         let mut config: serde_json::Value = if config_path.exists() {
@@ -740,21 +861,24 @@ impl CommandHandler {
         monthly: Option<u32>,
         show_cost: bool,
     ) -> Result<()> {
+        use crate::config::LogRequestsBackend;
         use crate::database::Database;
         use crate::quota::hash_api_key;
         use chrono::Local;
 
-        // Resolve database path
-        let db_path = &self.config.log_requests.db_path;
-
-        if !std::path::Path::new(db_path).exists() {
-            return Err(anyhow::anyhow!(
-                "Database not found: {}. Enable log_requests in config.",
-                db_path
-            ));
-        }
-
-        let db = Database::open_readonly(db_path)?;
+        let db = match self.config.log_requests.backend {
+            LogRequestsBackend::Sqlite => {
+                let db_path = &self.config.log_requests.db_path;
+                if !std::path::Path::new(db_path).exists() {
+                    return Err(anyhow::anyhow!(
+                        "Database not found: {}. Enable log_requests in config.",
+                        db_path
+                    ));
+                }
+                Database::open_readonly(db_path)?
+            }
+            LogRequestsBackend::Postgres => Database::connect(&self.config.log_requests).await?,
+        };
 
         // Resolve api_key_hash filter
         let key_hash_filter = api_key.map(hash_api_key);
@@ -919,6 +1043,7 @@ impl CommandHandler {
                     output: row.output_tokens,
                     cache_read: row.cache_read_tokens,
                     cache_write: row.cache_write_tokens,
+                    ..Default::default()
                 };
                 Self::format_cost_cell(
                     &row.model,
@@ -987,6 +1112,7 @@ impl CommandHandler {
                 output: row.output_tokens,
                 cache_read: row.cache_read_tokens,
                 cache_write: row.cache_write_tokens,
+                ..Default::default()
             };
             if let Some(pricing) = config.get_model_pricing(&row.model)
                 && pricing.is_partial(&tokens)
@@ -1071,17 +1197,20 @@ impl CommandHandler {
     /// Delete request logs older than N days.
     #[cfg(feature = "db")]
     pub async fn logs_clean(&self, days: Option<u32>) -> Result<()> {
+        use crate::config::LogRequestsBackend;
         use crate::database::Database;
 
         let retention_days = days.unwrap_or(self.config.log_requests.retention_days);
         let db_path = &self.config.log_requests.db_path;
 
-        if !std::path::Path::new(db_path).exists() {
+        if self.config.log_requests.backend == LogRequestsBackend::Sqlite
+            && !std::path::Path::new(db_path).exists()
+        {
             println!("No database found at: {db_path}");
             return Ok(());
         }
 
-        let db = Database::open(db_path.into()).await?;
+        let db = Database::connect(&self.config.log_requests).await?;
         let deleted = db.cleanup_old_requests(retention_days).await?;
 
         if deleted > 0 {
@@ -1096,8 +1225,452 @@ impl CommandHandler {
         Ok(())
     }
 
-    /// Print diagnostic information about the router configuration.
-    pub fn diagnose(&self, config_path: Option<&str>) -> Result<()> {
+    fn keys_file(&self) -> Result<crate::keys::KeysFile> {
+        let path = self
+            .config
+            .keys_file
+            .as_deref()
+            .context("No keys file: set keys_file in the config")?;
+        crate::keys::KeysFile::open(path)
+    }
+
+    /// Generate a key, store its hash in the keys file and print it once.
+    pub fn keys_add(&self, new: &crate::keys::NewKey<'_>) -> Result<()> {
+        if let Some(tenant) = new.tenant
+            && !self.config.tenants.iter().any(|t| t.name == tenant)
+        {
+            anyhow::bail!("Unknown tenant '{tenant}'");
+        }
+        if let Some(client) = new.client
+            && self
+                .config
+                .api_keys
+                .iter()
+                .filter(|k| k.client.as_deref() == Some(client))
+                .count()
+                >= 2
+        {
+            anyhow::bail!("Client '{client}' already has two keys; revoke the old one first");
+        }
+
+        let mut keys = self.keys_file()?;
+        let key = crate::keys::generate();
+        keys.add(&key, new);
+        keys.save()?;
+
+        println!("Created key {}", crate::quota::hash_api_key(&key));
+        println!("\n  {key}\n");
+        println!(
+            "This is the only time the key is shown; {} stores only its hash.",
+            keys.path().display()
+        );
+        println!("{}", self.keys_pickup("start accepting it"));
+        Ok(())
+    }
+
+    /// List configured keys by ID, without revealing them.
+    pub fn keys_list(&self) -> Result<()> {
+        let keys = self.keys_file()?;
+        let now = chrono::Utc::now();
+        let time = |t: Option<chrono::DateTime<chrono::Utc>>| {
+            t.map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "-".to_string())
+        };
+        let col = |header, align| Col { header, align };
+        CliTable::new(vec![
+            col("ID", Align::Left),
+            col("CLIENT", Align::Left),
+            col("TENANT", Align::Left),
+            col("VALID FROM (UTC)", Align::Left),
+            col("VALID UNTIL (UTC)", Align::Left),
+            col("STATUS", Align::Left),
+            col("SOURCE", Align::Left),
+        ])
+        .rows(
+            self.config
+                .api_keys
+                .iter()
+                .map(|k| {
+                    let id = k.key_hash();
+                    let status = if k.valid_from.is_some_and(|from| now < from) {
+                        "pending"
+                    } else if k.is_active_at(now) {
+                        "active"
+                    } else {
+                        "expired"
+                    };
+                    let source = if keys.contains(&id) {
+                        "keys file"
+                    } else {
+                        "config"
+                    };
+                    vec![
+                        id,
+                        k.client.clone().unwrap_or_else(|| "-".to_string()),
+                        k.tenant.clone().unwrap_or_else(|| "-".to_string()),
+                        time(k.valid_from),
+                        time(k.valid_until),
+                        status.to_string(),
+                        source.to_string(),
+                    ]
+                })
+                .collect(),
+        )
+        .print();
+        Ok(())
+    }
+
+    /// Remove a key from the keys file by its ID (or a unique prefix).
+    pub fn keys_revoke(&self, id: &str) -> Result<()> {
+        let mut keys = self.keys_file()?;
+        let revoked = match keys.revoke(id) {
+            Ok(revoked) => revoked,
+            Err(e) => {
+                if self
+                    .config
+                    .api_keys
+                    .iter()
+                    .any(|k| !id.is_empty() && k.key_hash().starts_with(id))
+                {
+                    anyhow::bail!(
+                        "Key '{id}' is set in the config file, not the keys file; remove it there"
+                    );
+                }
+                return Err(e);
+            }
+        };
+        keys.save()?;
+        println!(
+            "Revoked key {revoked}. {}",
+            self.keys_pickup("stop accepting it")
+        );
+        Ok(())
+    }
+
+    /// When a running acr applies a keys file edit: right away when it
+    /// watches its files, otherwise after a restart or SIGHUP.
+    fn keys_pickup(&self, action: &str) -> String {
+        if self.config.watch_config {
+            format!("A running acr picks up the change and will {action}.")
+        } else {
+            format!("Restart acr or send it SIGHUP to {action} (watch_config is off).")
+        }
+    }
+
+    /// Fetch a token for `provider`, then time one deployments listing, each
+    /// step bounded by the startup check's timeout.
+    async fn probe_provider(&self, provider: &crate::config::Provider) -> ProviderProbe {
+        let timeout =
+            std::time::Duration::from_secs(crate::constants::config::STARTUP_CHECK_TIMEOUT_SECS);
+        let timed_out = || anyhow::anyhow!("no response within {}s", timeout.as_secs());
+        let client = AiCoreClient::from_provider(
+            provider.clone(),
+            TokenManager::from_api_keys(&self.config.api_keys),
+        );
+
+        let start = std::time::Instant::now();
+        let token = match tokio::time::timeout(timeout, client.get_token()).await {
+            Ok(result) => result.map(|_| start.elapsed()),
+            Err(_) => Err(timed_out()),
+        };
+        if token.is_err() {
+            return ProviderProbe { token, api: None };
+        }
+        let start = std::time::Instant::now();
+        let api = match tokio::time::timeout(timeout, client.list_deployments(None)).await {
+            Ok(result) => result.map(|d| (start.elapsed(), d.resources.len())),
+            Err(_) => Err(timed_out()),
+        };
+        ProviderProbe {
+            token,
+            api: Some(api),
+        }
+    }
+
+    /// Probe every configured provider, enabled or not, and print one row each.
+    pub async fn providers_list(&self) -> Result<()> {
+        println!("Probing {} provider(s)...", self.config.providers.len());
+        let probes =
+            futures::future::join_all(self.config.providers.iter().map(|p| self.probe_provider(p)))
+                .await;
+
+        let col = |header, align| Col { header, align };
+        let mut errors = Vec::new();
+        let rows = self
+            .config
+            .providers
+            .iter()
+            .zip(&probes)
+            .map(|(p, probe)| {
+                if let Some(e) = probe.error() {
+                    errors.push(format!("  {}: {e:#}", p.name));
+                }
+                let [token, api] = probe.cells();
+                vec![
+                    p.name.clone(),
+                    if p.enabled { "yes" } else { "no" }.to_string(),
+                    p.resource_group.clone(),
+                    p.weight.to_string(),
+                    token,
+                    api,
+                    p.genai_api_url.clone(),
+                ]
+            })
+            .collect();
+        CliTable::new(vec![
+            col("NAME", Align::Left),
+            col("ENABLED", Align::Left),
+            col("RESOURCE GROUP", Align::Left),
+            col("WEIGHT", Align::Right),
+            col("TOKEN", Align::Left),
+            col("API ROUND-TRIP", Align::Left),
+            col("API URL", Align::Left),
+        ])
+        .title(format!("Providers ({} total)", self.config.providers.len()))
+        .rows(rows)
+        .print();
+
+        if !errors.is_empty() {
+            println!(
+                "
+Errors:"
+            );
+            for error in errors {
+                println!("{error}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Probe one provider by name; fails when it isn't healthy, so it can
+    /// gate scripts.
+    pub async fn providers_test(&self, name: &str) -> Result<()> {
+        let provider = self
+            .config
+            .providers
+            .iter()
+            .find(|p| p.name == name)
+            .with_context(|| {
+                let names: Vec<&str> = self
+                    .config
+                    .providers
+                    .iter()
+                    .map(|p| p.name.as_str())
+                    .collect();
+                format!(
+                    "Unknown provider '{name}'; configured: {}",
+                    names.join(", ")
+                )
+            })?;
+
+        println!("Provider '{}'", provider.name);
+        println!("  Enabled:        {}", provider.enabled);
+        println!("  Token URL:      {}", provider.uaa_token_url);
+        println!("  API URL:        {}", provider.genai_api_url);
+        println!("  Resource group: {}", provider.resource_group);
+        let probe = self.probe_provider(provider).await;
+        let [token, api] = probe.cells();
+        println!("  Token:          {token}");
+        println!("  API round-trip: {api}");
+
+        match probe.error() {
+            Some(e) => Err(anyhow::anyhow!("Provider '{name}' is unhealthy: {e:#}")),
+            None => Ok(()),
+        }
+    }
+
+    /// Print a configuration snippet for a client tool (`continue`, `aider`
+    /// or `litellm`) listing the chat models acr currently resolves.
+    pub async fn models_export(&self, format: &str) -> Result<()> {
+        let registry = crate::registry::ModelRegistry::new(
+            self.config.models.clone(),
+            self.config.fallback_models.clone(),
+            self.config.providers.clone(),
+            TokenManager::from_api_keys(&self.config.api_keys),
+            self.config.refresh_interval_secs,
+        )
+        .with_auto_discover(self.config.auto_discover.clone());
+        registry
+            .check_deployments()
+            .await
+            .context("Failed to resolve models")?;
+        // Aliases point at models already listed; embedding models and
+        // disabled ones are no use to a coding assistant.
+        let models: Vec<String> = registry
+            .list_models()
+            .await
+            .into_iter()
+            .map(|m| m.id)
+            .filter(|id| {
+                registry
+                    .find_model_config(id)
+                    .map_or(registry.is_discovered(id), |m| m.disabled.is_none())
+            })
+            .filter(|id| {
+                !id.starts_with(crate::constants::models::TEXT_PREFIX)
+                    && crate::proxy::determine_family(id, &self.config.model_families).is_ok_and(
+                        |family| {
+                            matches!(
+                                family,
+                                LlmFamily::OpenAi
+                                    | LlmFamily::Claude
+                                    | LlmFamily::Gemini
+                                    | LlmFamily::OpenWeight
+                            )
+                        },
+                    )
+            })
+            .collect();
+        if models.is_empty() {
+            anyhow::bail!("No chat models resolve to a running deployment");
+        }
+        print!(
+            "{}",
+            client_config(format, &format!("{}/v1", self.router_url()?), &models)?
+        );
+        Ok(())
+    }
+
+    /// List captured dead letters, or replay one through the running router.
+    ///
+    /// Replays go through `POST /admin/dead-letters/{id}/replay` with the
+    /// loopback-only `internal` key, so the router's own credentials, load
+    /// balancing and quota bookkeeping apply.
+    pub async fn replay(&self, id: Option<&str>) -> Result<()> {
+        let Some(id) = id else {
+            return self.list_dead_letters().await;
+        };
+
+        let url = format!("{}/admin/dead-letters/{id}/replay", self.router_url()?);
+        let response = reqwest::Client::new()
+            .post(&url)
+            .header("x-api-key", "internal")
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach the router at {url}; is `acr` running?"))?;
+
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        println!("Replayed {id}: {status}");
+        if !body.is_empty() {
+            println!("{body}");
+        }
+        if !status.is_success() {
+            anyhow::bail!("Replay of {id} failed with {status}");
+        }
+        Ok(())
+    }
+
+    /// Run a request file through the running router's pipeline, sent with
+    /// `--key` or the loopback-only `internal` key.
+    ///
+    /// Unless `execute` is set the request carries `x-acr-dry-run`, and the
+    /// router answers with the upstream request it prepared (provider and
+    /// translated body, and the URL for the `internal` key) instead of
+    /// sending it.
+    pub async fn send(
+        &self,
+        file: &str,
+        model: Option<&str>,
+        route: &str,
+        key: Option<&str>,
+        execute: bool,
+    ) -> Result<()> {
+        let content =
+            std::fs::read_to_string(file).with_context(|| format!("Failed to read {file}"))?;
+        let body: serde_json::Value =
+            serde_json::from_str(&content).with_context(|| format!("{file} is not valid JSON"))?;
+        let (path, body) = send_target(route, model, body)?;
+
+        let url = format!("{}{path}", self.router_url()?);
+        let mut request = reqwest::Client::new()
+            .post(&url)
+            .header("x-api-key", key.unwrap_or("internal"))
+            .json(&body);
+        if !execute {
+            request = request.header(crate::constants::api::ACR_DRY_RUN_HEADER, "true");
+        }
+        let mut response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach the router at {url}; is `acr` running?"))?;
+
+        let status = response.status();
+        println!("{status}");
+        for (name, value) in response.headers() {
+            if name.as_str().starts_with("x-acr-") {
+                println!("{}: {}", name, value.to_str().unwrap_or_default());
+            }
+        }
+        println!();
+        let event_stream = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+        if event_stream {
+            while let Some(chunk) = response.chunk().await? {
+                print!("{}", String::from_utf8_lossy(&chunk));
+            }
+        } else {
+            let text = response.text().await.unwrap_or_default();
+            match serde_json::from_str::<serde_json::Value>(&text) {
+                Ok(json) => println!("{}", serde_json::to_string_pretty(&json)?),
+                Err(_) => println!("{text}"),
+            }
+        }
+        if !status.is_success() {
+            anyhow::bail!("Request failed with {status}");
+        }
+        Ok(())
+    }
+
+    async fn list_dead_letters(&self) -> Result<()> {
+        let Some(store) = crate::dead_letter::DeadLetterStore::open(&self.config.dead_letter)?
+        else {
+            println!("Dead-letter capture is disabled (set dead_letter.enabled: true).");
+            return Ok(());
+        };
+        let entries = store.list().await?;
+        if entries.is_empty() {
+            println!("No dead letters in {}", self.config.dead_letter.dir);
+            return Ok(());
+        }
+
+        let col = |header, align| Col { header, align };
+        CliTable::new(vec![
+            col("ID", Align::Left),
+            col("CREATED (UTC)", Align::Left),
+            col("STATUS", Align::Right),
+            col("MODEL", Align::Left),
+            col("PATH", Align::Left),
+            col("REPLAYS", Align::Right),
+            col("RESOLVED", Align::Left),
+        ])
+        .rows(
+            entries
+                .iter()
+                .map(|entry| {
+                    vec![
+                        entry.id.clone(),
+                        entry.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                        entry.status.to_string(),
+                        entry.request.model.clone(),
+                        entry.request.path.clone(),
+                        entry.replays.len().to_string(),
+                        if entry.resolved() { "yes" } else { "no" }.to_string(),
+                    ]
+                })
+                .collect(),
+        )
+        .print();
+        println!("\nReplay one with: acr replay <id>");
+        Ok(())
+    }
+
+    /// Print the configuration and check it against the live deployments.
+    /// Fails when a deployment conflict is found, so scripts can gate on it.
+    pub async fn diagnose(&self, config_path: Option<&str>) -> Result<()> {
         println!("AI Core Router Diagnostics");
         println!("{}", "=".repeat(50));
 
@@ -1115,6 +1688,9 @@ impl CommandHandler {
         // Server config
         println!("\nServer:");
         println!("  Bind:       {}", self.config.bind);
+        if let Some(ref admin_bind) = self.config.admin_bind {
+            println!("  Admin Bind: {}", admin_bind);
+        }
         println!("  Log Level:  {}", self.config.log_level);
         println!("  Refresh:    {}s", self.config.refresh_interval_secs);
         println!("  LB Strategy:{:?}", self.config.load_balancing);
@@ -1184,14 +1760,21 @@ impl CommandHandler {
         // Database
         println!("\nRequest Logging:");
         if self.config.log_requests.enabled {
-            let db_path = &self.config.log_requests.db_path;
-            let exists = std::path::Path::new(db_path).exists();
             println!("  Enabled:    true");
-            println!("  Path:       {}", db_path);
-            println!(
-                "  Status:     {}",
-                if exists { "exists" } else { "not created yet" }
-            );
+            match self.config.log_requests.backend {
+                crate::config::LogRequestsBackend::Sqlite => {
+                    let db_path = &self.config.log_requests.db_path;
+                    let exists = std::path::Path::new(db_path).exists();
+                    println!("  Path:       {}", db_path);
+                    println!(
+                        "  Status:     {}",
+                        if exists { "exists" } else { "not created yet" }
+                    );
+                }
+                crate::config::LogRequestsBackend::Postgres => {
+                    println!("  Backend:    postgres");
+                }
+            }
             println!(
                 "  Retention:  {} days",
                 self.config.log_requests.retention_days
@@ -1200,7 +1783,28 @@ impl CommandHandler {
             println!("  Enabled:    false");
         }
 
+        // Deployment conflicts
+        println!("\nDeployments:");
+        let registry = crate::registry::ModelRegistry::new(
+            self.config.models.clone(),
+            self.config.fallback_models.clone(),
+            self.config.providers.clone(),
+            TokenManager::from_api_keys(&self.config.api_keys),
+            self.config.refresh_interval_secs,
+        )
+        .with_auto_discover(self.config.auto_discover.clone());
+        let conflicts = registry.check_deployments().await?;
+        if conflicts.is_empty() {
+            println!("  No conflicts");
+        }
+        for conflict in &conflicts {
+            println!("  {conflict}");
+        }
+
         println!("\n{}", "=".repeat(50));
+        if !conflicts.is_empty() {
+            anyhow::bail!("{} deployment conflict(s) found", conflicts.len());
+        }
         println!("Diagnostics complete.");
 
         Ok(())
@@ -1209,7 +1813,10 @@ impl CommandHandler {
 
 #[cfg(test)]
 mod tests {
-    use super::{ClaudeModelChoices, CommandHandler, pick_newest_in_family};
+    use super::{
+        ClaudeModelChoices, CommandHandler, ProviderProbe, client_config, pick_newest_in_family,
+        send_target,
+    };
     use crate::config::Model;
     use tempfile::TempDir;
 
@@ -1219,6 +1826,11 @@ mod tests {
             aicore_model_name: None,
             aliases: Vec::new(),
             pricing: None,
+            default_max_tokens: None,
+            deprecation: None,
+            safety_settings: vec![],
+            url_template: None,
+            disabled: None,
         }
     }
 
@@ -1524,22 +2136,67 @@ mod tests {
                 resource_group: "default".to_string(),
                 weight: 1,
                 enabled: true,
+                schedule: vec![],
+                headers: Default::default(),
+                quota: Default::default(),
             }],
             api_keys: vec![crate::config::ApiKeyConfig {
                 key: "test-key".to_string(),
                 daily_token_limit: None,
                 monthly_token_limit: None,
                 requests_per_minute: None,
+                max_open_streams: None,
+                output_tokens_per_sec: None,
+                allowed_models: None,
+                model_aliases: Default::default(),
+                priority: Default::default(),
+                reasoning: Default::default(),
+                tenant: None,
+                client: None,
+                valid_from: None,
+                valid_until: None,
             }],
             bind: "127.0.0.1:8900".to_string(),
+            admin_bind: None,
             models: vec![],
             log_level: "info".to_string(),
             refresh_interval_secs: 300,
+            verify_on_startup: false,
+            max_request_body_mb: 10,
             fallback_models: crate::config::FallbackModels::default(),
+            model_families: vec![],
+            auto_discover: crate::config::AutoDiscoverConfig::default(),
             load_balancing: crate::config::LoadBalancingStrategy::default(),
             log_requests: crate::config::LogRequestsConfig::default(),
             openai_api_version: crate::constants::api::DEFAULT_API_VERSION.to_string(),
             quotas: crate::config::QuotaConfig::default(),
+            batches: crate::config::BatchesConfig::default(),
+            admission: crate::config::AdmissionConfig::default(),
+            streams: crate::config::StreamsConfig::default(),
+            retry_budget: crate::config::RetryBudgetConfig::default(),
+            http2: crate::config::Http2Config::default(),
+            dead_letter: crate::config::DeadLetterConfig::default(),
+            statsd: crate::config::StatsdConfig::default(),
+            slo: crate::config::SloConfig::default(),
+            sentry: crate::config::SentryConfig::default(),
+            capture: crate::config::CaptureConfig::default(),
+            image_fetch: crate::config::ImageFetchConfig::default(),
+            continuation: crate::config::ContinuationConfig::default(),
+            response_cache: crate::config::ResponseCacheConfig::default(),
+            upstream_limits: crate::config::UpstreamLimitsConfig::default(),
+            deployment_health: crate::config::DeploymentHealthConfig::default(),
+            token_cache: crate::config::TokenCacheConfig::default(),
+            cluster: crate::config::ClusterConfig::default(),
+            timeouts: crate::config::TimeoutsConfig::default(),
+            log_redaction: crate::config::LogRedactionConfig::default(),
+            warmup: crate::config::WarmupConfig::default(),
+            tenants: vec![],
+            templates: vec![],
+            hmac_auth: Default::default(),
+            keys_file: None,
+            mount: Default::default(),
+            trusted_proxies: vec![],
+            watch_config: true,
         };
 
         let handler = CommandHandler::new(config).unwrap();
@@ -1576,6 +2233,85 @@ mod tests {
         unsafe { std::env::remove_var("OPENCODE_CONFIG") };
     }
 
+    #[test]
+    fn test_send_target_places_the_model() {
+        let body = serde_json::json!({"model": "gpt-4o", "messages": []});
+        let (path, sent) =
+            send_target("/v1/chat/completions", Some("gpt-5"), body.clone()).unwrap();
+        assert_eq!(path, "/v1/chat/completions");
+        assert_eq!(sent["model"], "gpt-5");
+
+        let (path, sent) =
+            send_target("/v1beta/models/{model}:generateContent", None, body.clone()).unwrap();
+        assert_eq!(path, "/v1beta/models/gpt-4o:generateContent");
+        assert_eq!(sent, body);
+
+        assert!(
+            send_target(
+                "/v1beta/models/{model}:generateContent",
+                None,
+                serde_json::json!([])
+            )
+            .is_err()
+        );
+        assert!(send_target("v1/messages", None, body).is_err());
+    }
+
+    #[test]
+    fn client_config_lists_models_for_each_tool() {
+        let models = vec!["claude-sonnet-4-6".to_string(), "gpt-4o".to_string()];
+        let base = "http://localhost:8900/llm/v1";
+
+        let continue_config: serde_yaml_ng::Value =
+            serde_yaml_ng::from_str(&client_config("continue", base, &models).unwrap()).unwrap();
+        assert_eq!(
+            continue_config["models"][1]["model"].as_str(),
+            Some("gpt-4o")
+        );
+        assert_eq!(continue_config["models"][0]["apiBase"].as_str(), Some(base));
+
+        let litellm: serde_yaml_ng::Value =
+            serde_yaml_ng::from_str(&client_config("litellm", base, &models).unwrap()).unwrap();
+        let entry = &litellm["model_list"][0];
+        assert_eq!(entry["model_name"].as_str(), Some("claude-sonnet-4-6"));
+        assert_eq!(
+            entry["litellm_params"]["model"].as_str(),
+            Some("openai/claude-sonnet-4-6")
+        );
+
+        let aider = client_config("aider", base, &models).unwrap();
+        assert!(aider.starts_with("openai-api-base: http://localhost:8900/llm/v1\n"));
+        assert!(aider.contains("model: openai/claude-sonnet-4-6\n"));
+        assert!(aider.contains("#   model: openai/gpt-4o\n"));
+
+        assert!(client_config("cursor", base, &models).is_err());
+    }
+
+    #[test]
+    fn provider_probe_cells_and_error() {
+        use std::time::Duration;
+        let healthy = ProviderProbe {
+            token: Ok(Duration::from_millis(120)),
+            api: Some(Ok((Duration::from_millis(45), 3))),
+        };
+        assert_eq!(healthy.cells(), ["ok (120ms)", "45ms (3 deployments)"]);
+        assert!(healthy.error().is_none());
+
+        let no_token = ProviderProbe {
+            token: Err(anyhow::anyhow!("401 Unauthorized")),
+            api: None,
+        };
+        assert_eq!(no_token.cells(), ["failed", "-"]);
+        assert_eq!(no_token.error().unwrap().to_string(), "401 Unauthorized");
+
+        let bad_api = ProviderProbe {
+            token: Ok(Duration::from_millis(80)),
+            api: Some(Err(anyhow::anyhow!("404 Not Found"))),
+        };
+        assert_eq!(bad_api.cells(), ["ok (80ms)", "failed"]);
+        assert_eq!(bad_api.error().unwrap().to_string(), "404 Not Found");
+    }
+
     #[test]
     fn test_strip_jsonc_comments() {
         let input = r#"{
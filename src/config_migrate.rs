@@ -0,0 +1,339 @@
+//! Config file versions and migrations.
+//!
+//! The config's top-level `version` names its layout. Version 1 is the
+//! original single-provider layout:
+//!
+//! ```yaml
+//! uaa_token_url: https://...
+//! uaa_client_id: ...
+//! uaa_client_secret: ...
+//! genai_api_url: https://...
+//! resource_group: default
+//! api_key: my-key
+//! models:
+//!   claude-sonnet-4: anthropic--claude-4-sonnet
+//! ```
+//!
+//! Version 2 is the current one, with `providers`, `api_keys` and a `models`
+//! list. Old files keep loading: they're migrated in memory at startup with a
+//! warning, and `acr config migrate` rewrites them. A file without `version`
+//! is read as version 1, which is a no-op when it already uses the current
+//! layout.
+
+use anyhow::{Context, Result, bail};
+use serde_yaml_ng::{Mapping, Value};
+
+use crate::config::ConfigFile;
+use crate::constants::config::CURRENT_CONFIG_VERSION;
+
+/// Top-level keys of the version 1 layout's only provider.
+const LEGACY_PROVIDER_KEYS: [&str; 5] = [
+    "uaa_token_url",
+    "uaa_client_id",
+    "uaa_client_secret",
+    "genai_api_url",
+    "resource_group",
+];
+/// Name given to that provider in `providers`.
+const LEGACY_PROVIDER_NAME: &str = "default";
+
+/// A config document upgraded to the current version.
+#[derive(Debug)]
+pub struct Migrated {
+    pub doc: Value,
+    /// The version the document was written for
+    pub from_version: u64,
+    /// What was changed, one line each; empty when the layout was current
+    pub changes: Vec<String>,
+}
+
+/// Upgrade a parsed config file to the current version.
+pub fn migrate(doc: Value) -> Result<Migrated> {
+    let Value::Mapping(mut map) = doc else {
+        // Not a mapping: nothing to migrate, parsing reports the problem.
+        return Ok(Migrated {
+            doc,
+            from_version: CURRENT_CONFIG_VERSION,
+            changes: Vec::new(),
+        });
+    };
+    let from_version = match map.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .filter(|v| *v >= 1)
+            .with_context(|| format!("version must be a positive integer, got {version:?}"))?,
+    };
+    if from_version > CURRENT_CONFIG_VERSION {
+        bail!(
+            "config version {from_version} is newer than this acr supports \
+             ({CURRENT_CONFIG_VERSION}); upgrade acr"
+        );
+    }
+
+    let mut changes = Vec::new();
+    if from_version < 2 {
+        map = v1_to_v2(map, &mut changes)?;
+    }
+    Ok(Migrated {
+        doc: Value::Mapping(map),
+        from_version,
+        changes,
+    })
+}
+
+/// Single provider and `api_key` to lists, `models` map to a list.
+fn v1_to_v2(map: Mapping, changes: &mut Vec<String>) -> Result<Mapping> {
+    let has_legacy_provider = LEGACY_PROVIDER_KEYS.iter().any(|k| map.contains_key(*k));
+    if has_legacy_provider && map.contains_key("providers") {
+        bail!(
+            "both `providers` and top-level provider fields ({}) are set; keep one",
+            LEGACY_PROVIDER_KEYS.join(", ")
+        );
+    }
+    let legacy_key = match map.get("api_key") {
+        None => None,
+        Some(Value::String(key)) => Some(key.clone()),
+        Some(_) => bail!("api_key must be a string"),
+    };
+
+    let mut provider = Mapping::new();
+    provider.insert("name".into(), LEGACY_PROVIDER_NAME.into());
+    let mut out = Mapping::new();
+    out.insert("version".into(), CURRENT_CONFIG_VERSION.into());
+    for (key, value) in map {
+        match key.as_str() {
+            Some("version") => {}
+            Some(k) if LEGACY_PROVIDER_KEYS.contains(&k) => {
+                // The provider takes the place of its first field.
+                if !out.contains_key("providers") {
+                    out.insert("providers".into(), Value::Sequence(Vec::new()));
+                }
+                provider.insert(key, value);
+            }
+            Some("api_key") => {
+                out.entry("api_keys".into())
+                    .or_insert_with(|| Value::Sequence(Vec::new()));
+            }
+            Some("api_keys") => {
+                let existing = out
+                    .entry("api_keys".into())
+                    .or_insert_with(|| Value::Sequence(Vec::new()));
+                match (existing, value) {
+                    (Value::Sequence(keys), Value::Sequence(more)) => keys.extend(more),
+                    (existing, value) => *existing = value,
+                }
+            }
+            Some("models") if value.is_mapping() => {
+                out.insert(key, models_list(value)?);
+                changes.push("converted the `models` map to a list".to_string());
+            }
+            _ => {
+                out.insert(key, value);
+            }
+        }
+    }
+
+    if has_legacy_provider {
+        out.insert("providers".into(), Value::Sequence(vec![provider.into()]));
+        changes.push(format!(
+            "moved the top-level provider fields into `providers` as '{LEGACY_PROVIDER_NAME}'"
+        ));
+    }
+    if let Some(key) = legacy_key {
+        if let Some(Value::Sequence(keys)) = out.get_mut("api_keys") {
+            keys.insert(0, key.into());
+        }
+        changes.push("moved `api_key` into `api_keys`".to_string());
+    }
+    Ok(out)
+}
+
+/// `{name: aicore_model_name}` or `{name: {fields}}` entries as `models[]`.
+fn models_list(models: Value) -> Result<Value> {
+    let Value::Mapping(models) = models else {
+        unreachable!("checked by the caller");
+    };
+    let mut list = Vec::new();
+    for (name, value) in models {
+        let name = name
+            .as_str()
+            .context("model names in the `models` map must be strings")?
+            .to_string();
+        let mut model = Mapping::new();
+        model.insert("name".into(), name.clone().into());
+        match value {
+            Value::Null => {}
+            Value::String(aicore) if aicore == name => {}
+            Value::String(aicore) => {
+                model.insert("aicore_model_name".into(), aicore.into());
+            }
+            Value::Mapping(fields) => {
+                for (key, value) in fields {
+                    if key.as_str() != Some("name") {
+                        model.insert(key, value);
+                    }
+                }
+            }
+            _ => bail!("models.{name} must be an AI Core model name or a mapping"),
+        }
+        list.push(Value::Mapping(model));
+    }
+    Ok(Value::Sequence(list))
+}
+
+/// `acr config migrate`: rewrite the config file at `path` in the current
+/// layout, keeping the original next to it. With `dry_run`, print the result
+/// instead.
+pub fn migrate_file(path: &str, dry_run: bool) -> Result<()> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {path}"))?;
+    let doc: Value = serde_yaml_ng::from_str(&content)
+        .with_context(|| format!("Failed to parse config file: {path}"))?;
+    let already_versioned = doc.get("version").is_some();
+    let migrated = migrate(doc).with_context(|| format!("Failed to migrate {path}"))?;
+    if already_versioned && migrated.from_version == CURRENT_CONFIG_VERSION {
+        println!("{path} is already at config version {CURRENT_CONFIG_VERSION}");
+        return Ok(());
+    }
+
+    serde_yaml_ng::from_value::<ConfigFile>(migrated.doc.clone())
+        .context("Migrated config doesn't parse; the file was left unchanged")?;
+    let output = serde_yaml_ng::to_string(&migrated.doc)?;
+    if dry_run {
+        print!("{output}");
+        return Ok(());
+    }
+
+    let backup = format!("{path}.v{}.bak", migrated.from_version);
+    std::fs::copy(path, &backup).with_context(|| format!("Failed to write {backup}"))?;
+    std::fs::write(path, output).with_context(|| format!("Failed to write {path}"))?;
+    println!(
+        "Migrated {path} from config version {} to {CURRENT_CONFIG_VERSION}",
+        migrated.from_version
+    );
+    for change in &migrated.changes {
+        println!("  - {change}");
+    }
+    println!("The original, with its comments, is in {backup}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrate_yaml(yaml: &str) -> Result<Migrated> {
+        migrate(serde_yaml_ng::from_str(yaml).unwrap())
+    }
+
+    #[test]
+    fn version_1_layout_becomes_providers_api_keys_and_a_models_list() {
+        let migrated = migrate_yaml(
+            r#"
+uaa_token_url: https://auth.example.com/oauth/token
+uaa_client_id: client
+uaa_client_secret: secret
+genai_api_url: https://api.example.com
+resource_group: team-a
+api_key: old-key
+api_keys: [second-key]
+models:
+  claude-sonnet-4: anthropic--claude-4-sonnet
+  gpt-4o: gpt-4o
+  gemini-2.5-pro:
+    default_max_tokens: 8192
+"#,
+        )
+        .unwrap();
+        assert_eq!(migrated.from_version, 1);
+        assert_eq!(migrated.changes.len(), 3);
+
+        let expected: Value = serde_yaml_ng::from_str(
+            r#"
+version: 2
+providers:
+  - name: default
+    uaa_token_url: https://auth.example.com/oauth/token
+    uaa_client_id: client
+    uaa_client_secret: secret
+    genai_api_url: https://api.example.com
+    resource_group: team-a
+api_keys: [old-key, second-key]
+models:
+  - name: claude-sonnet-4
+    aicore_model_name: anthropic--claude-4-sonnet
+  - name: gpt-4o
+  - name: gemini-2.5-pro
+    default_max_tokens: 8192
+"#,
+        )
+        .unwrap();
+        assert_eq!(migrated.doc, expected);
+        let file: ConfigFile = serde_yaml_ng::from_value(migrated.doc).unwrap();
+        assert!(file.unknown.is_empty());
+        assert_eq!(file.providers[0].resource_group.as_deref(), Some("team-a"));
+    }
+
+    #[test]
+    fn current_layouts_only_gain_a_version() {
+        let current = "providers: []\napi_keys: [k]\nmodels:\n  - name: gpt-4o\n";
+        let migrated = migrate_yaml(current).unwrap();
+        assert!(migrated.changes.is_empty());
+        assert_eq!(migrated.doc["version"], Value::from(CURRENT_CONFIG_VERSION));
+        assert_eq!(migrated.doc["models"][0]["name"], Value::from("gpt-4o"));
+
+        let err = migrate_yaml("version: 99\n").unwrap_err();
+        assert!(err.to_string().contains("upgrade acr"), "{err}");
+        let err = migrate_yaml("genai_api_url: x\nproviders: []\n").unwrap_err();
+        assert!(err.to_string().contains("keep one"), "{err}");
+    }
+
+    #[test]
+    fn version_1_files_still_load() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::fs::write(
+            &path,
+            "uaa_token_url: https://auth.example.com\nuaa_client_id: c\n\
+             uaa_client_secret: s\ngenai_api_url: https://api.example.com\n\
+             api_key: old-key\nmodels:\n  gpt-4o: gpt-4o\n",
+        )
+        .unwrap();
+        let config = crate::config::Config::load(path.to_str()).unwrap();
+        assert_eq!(config.providers[0].name, LEGACY_PROVIDER_NAME);
+        assert!(config.api_keys[0].matches("old-key"));
+        assert_eq!(config.models[0].name, "gpt-4o");
+
+        migrate_file(path.to_str().unwrap(), false).unwrap();
+        let rewritten = std::fs::read_to_string(&path).unwrap();
+        assert!(rewritten.starts_with("version: 2\n"), "{rewritten}");
+        assert!(dir.path().join("config.yaml.v1.bak").exists());
+    }
+
+    #[test]
+    fn legacy_credentials_become_an_ordinary_provider() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::fs::write(
+            &path,
+            "uaa_token_url: https://auth.example.com\nuaa_client_id: c\n\
+             uaa_client_secret: s\ngenai_api_url: https://api.example.com\n\
+             api_key: shared\napi_keys: [shared, other]\n",
+        )
+        .unwrap();
+        let config = crate::config::Config::load(path.to_str()).unwrap();
+
+        // Same defaults and normalization as a listed provider.
+        let provider = &config.providers[0];
+        assert_eq!(
+            provider.uaa_token_url,
+            "https://auth.example.com/oauth/token"
+        );
+        assert_eq!(provider.resource_group, "default");
+        assert!(provider.enabled);
+        // The single key joins api_keys once.
+        let keys: Vec<&str> = config.api_keys.iter().map(|k| k.key.as_str()).collect();
+        assert_eq!(keys, ["shared", "other"]);
+    }
+}
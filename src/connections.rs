@@ -0,0 +1,188 @@
+//! Connection counting for the listener and the upstream client.
+//!
+//! `axum::serve` asks the make-service for one service per accepted
+//! connection and drops it when the connection closes, so wrapping the
+//! make-service is enough to count client connections: each connection's
+//! service holds a `ClientConnectionGuard`. Upstream, a connector layer
+//! counts every connection reqwest opens to AI Core. reqwest doesn't report
+//! when a pooled connection closes, so only opens are counted there; with the
+//! request rate it shows how well connections are reused.
+
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+
+use crate::metrics::{ClientConnectionGuard, MetricsService};
+
+/// Make-service wrapper counting client connections.
+#[derive(Clone)]
+pub struct CountConnections<M> {
+    inner: M,
+    metrics: MetricsService,
+}
+
+impl<M> CountConnections<M> {
+    pub fn new(inner: M, metrics: &MetricsService) -> Self {
+        Self {
+            inner,
+            metrics: metrics.clone(),
+        }
+    }
+}
+
+impl<M, T> Service<T> for CountConnections<M>
+where
+    M: Service<T>,
+    M::Future: Send + 'static,
+    M::Error: Send + 'static,
+{
+    type Response = Counted<M::Response>;
+    type Error = M::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let connection = Arc::new(ClientConnectionGuard::new(&self.metrics));
+        let future = self.inner.call(target);
+        Box::pin(async move {
+            future.await.map(|inner| Counted {
+                inner,
+                _connection: connection,
+            })
+        })
+    }
+}
+
+/// A connection's service; the connection counts as open while any clone
+/// of it is alive.
+#[derive(Clone)]
+pub struct Counted<S> {
+    inner: S,
+    _connection: Arc<ClientConnectionGuard>,
+}
+
+impl<S, R> Service<R> for Counted<S>
+where
+    S: Service<R>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        self.inner.call(request)
+    }
+}
+
+/// Connector layer counting connections opened to AI Core.
+#[derive(Clone)]
+pub struct CountUpstreamConnects {
+    metrics: MetricsService,
+}
+
+impl CountUpstreamConnects {
+    pub fn new(metrics: &MetricsService) -> Self {
+        Self {
+            metrics: metrics.clone(),
+        }
+    }
+}
+
+impl<S> Layer<S> for CountUpstreamConnects {
+    type Service = UpstreamConnects<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        UpstreamConnects {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct UpstreamConnects<S> {
+    inner: S,
+    metrics: MetricsService,
+}
+
+impl<S, R> Service<R> for UpstreamConnects<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+    S::Response: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: R) -> Self::Future {
+        let metrics = self.metrics.clone();
+        let future = self.inner.call(target);
+        Box::pin(async move {
+            let connection = future.await?;
+            metrics.record_upstream_connect();
+            Ok(connection)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, routing::get};
+    use std::net::SocketAddr;
+
+    #[tokio::test]
+    async fn counts_client_and_upstream_connections() {
+        let metrics = MetricsService::new();
+        let app = Router::new().route("/health", get(|| async { "OK" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let make_service = CountConnections::new(
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+            &metrics,
+        );
+        tokio::spawn(async move { axum::serve(listener, make_service).await.unwrap() });
+
+        let client = reqwest::Client::builder()
+            .connector_layer(CountUpstreamConnects::new(&metrics))
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            let response = client
+                .get(format!("http://{addr}/health"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.text().await.unwrap(), "OK");
+        }
+        let stats = metrics.connection_stats();
+        assert_eq!(stats.client_accepted, 1);
+        assert_eq!(stats.client_open, 1);
+        assert_eq!(stats.upstream_opened, 1);
+
+        drop(client);
+        for _ in 0..50 {
+            if metrics.connection_stats().client_closed == 1 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let stats = metrics.connection_stats();
+        assert_eq!((stats.client_open, stats.client_closed), (0, 1));
+    }
+}
@@ -6,9 +6,17 @@ pub mod deployment {
 
 pub mod models {
     pub const CLAUDE_PREFIX: &str = "claude";
+    /// Claude models as AI Core names them (`anthropic--claude-4.6-sonnet`).
+    pub const ANTHROPIC_PREFIX: &str = "anthropic--";
     pub const GEMINI_PREFIX: &str = "gemini";
     pub const GPT_PREFIX: &str = "gpt";
     pub const TEXT_PREFIX: &str = "text";
+    /// Mistral models: AI Core's `mistralai--*` names and bare `mistral-*`
+    /// aliases.
+    pub const MISTRAL_PREFIX: &str = "mistral";
+    /// Llama models as AI Core names them (`meta--llama3.1-70b-instruct`).
+    pub const META_LLAMA_PREFIX: &str = "meta--llama";
+    pub const LLAMA_PREFIX: &str = "llama";
 
     /// Resolved client-facing name for Claude Opus 4.7. Used by
     /// `transforms::anthropic::requires_adaptive_thinking` to gate request-shape
@@ -20,6 +28,9 @@ pub mod models {
     /// Bedrock rejects `thinking.type.enabled` and non-1 sampling params on this
     /// model. See `transforms::anthropic::requires_adaptive_thinking`.
     pub const CLAUDE_OPUS_4_8: &str = "claude-opus-4-8";
+
+    /// Explanation for a model disabled through the admin API without one.
+    pub const DEFAULT_DISABLED_REASON: &str = "disabled by the operator";
 }
 
 pub mod api {
@@ -32,19 +43,119 @@ pub mod api {
     pub const INVOKE_STREAM_ACTION: &str = "invoke-with-response-stream";
     pub const GENERATE_CONTENT_ACTION: &str = "generateContent";
     pub const STREAM_GENERATE_CONTENT_ACTION: &str = "streamGenerateContent";
+    /// Route-set action selecting the legacy Azure OpenAI `/completions` API.
+    pub const LEGACY_COMPLETIONS_ACTION: &str = "completions";
 
     // API paths
     pub const INFERENCE_DEPLOYMENTS_PATH: &str = "/v2/inference/deployments";
     pub const EMBEDDINGS_PATH: &str = "/embeddings";
     pub const CHAT_COMPLETIONS_PATH: &str = "/chat/completions";
+    pub const COMPLETIONS_PATH: &str = "/completions";
     pub const RESPONSES_PATH: &str = "/responses";
     pub const RESPONSES_COMPACT_PATH: &str = "/responses/compact";
     pub const MODELS_PATH: &str = "/models";
+    pub const CACHED_CONTENTS_PATH: &str = "/cachedContents";
+
+    /// Upper bound of Chat Completions `top_logprobs`.
+    pub const MAX_TOP_LOGPROBS: u64 = 20;
+
+    /// Request bodies logged at debug level are cut off after this many bytes.
+    pub const DEBUG_BODY_PREVIEW_BYTES: usize = 2048;
 
     // AI-Client-Type header
     pub const AI_CLIENT_TYPE_HEADER: &str = "ai-client-type";
     pub const AI_CLIENT_TYPE_VALUE: &str = "aicore-router";
 
+    // Client address headers honored from `trusted_proxies`
+    pub const FORWARDED_HEADER: &str = "forwarded";
+    pub const X_FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+    /// Headers acr sets on upstream calls, which `providers[].headers` can't
+    /// override.
+    pub const RESERVED_UPSTREAM_HEADERS: &[&str] = &[
+        "authorization",
+        "ai-resource-group",
+        "content-type",
+        AI_CLIENT_TYPE_HEADER,
+    ];
+
+    // Azure OpenAI sends a millisecond-precision companion to `Retry-After`
+    // on 429s; used as a fallback when the standard header is absent.
+    pub const RETRY_AFTER_MS_HEADER: &str = "retry-after-ms";
+
+    // Diagnostic header on the aggregate 429: comma-separated names of the
+    // providers a request was actually sent to before giving up.
+    pub const ACR_PROVIDERS_TRIED_HEADER: &str = "x-acr-providers-tried";
+
+    // Estimated request cost (from `models[].pricing`) on non-streaming responses.
+    pub const ACR_COST_USD_HEADER: &str = "x-acr-cost-usd";
+
+    // Opt-in per-request stream transcript (see `capture`), and the ID of the
+    // transcript written for it.
+    pub const ACR_CAPTURE_HEADER: &str = "x-acr-capture";
+    pub const ACR_CAPTURE_ID_HEADER: &str = "x-acr-capture-id";
+
+    // Answer with the prepared upstream request instead of sending it
+    // (`acr send --dry-run`).
+    pub const ACR_DRY_RUN_HEADER: &str = "x-acr-dry-run";
+
+    // Opt-in routing metadata (provider, deployment, cache, retries) added to
+    // non-streaming JSON responses under the `x-acr` key.
+    pub const ACR_METADATA_HEADER: &str = "x-acr-metadata";
+    pub const ACR_METADATA_KEY: &str = "x-acr";
+
+    // Number of continuation requests stitched into a response that ran
+    // into its output token limit (see `continuation`).
+    pub const ACR_CONTINUATIONS_HEADER: &str = "x-acr-continuations";
+
+    // Whether a non-streaming response came from the response cache
+    // (`hit`) or was fetched and stored (`miss`); see `response_cache`.
+    pub const ACR_CACHE_HEADER: &str = "x-acr-cache";
+
+    // Comma-separated client parameters dropped while translating a request
+    // to a family that has no equivalent (e.g. `frequency_penalty` on Claude).
+    pub const ACR_DROPPED_PARAMS_HEADER: &str = "x-acr-dropped-params";
+
+    // Client-supplied conversation ID used for logging and provider affinity.
+    pub const ACR_SESSION_ID_HEADER: &str = "x-acr-session-id";
+
+    // Client-requested admission priority (`low` / `normal` / `high`); can
+    // only lower the priority configured for the API key.
+    pub const ACR_PRIORITY_HEADER: &str = "x-acr-priority";
+
+    // Identity of the request and of the upstream that served it, for
+    // matching client logs with acr's logs and AI Core deployments.
+    pub const ACR_REQUEST_ID_HEADER: &str = "x-acr-request-id";
+    pub const ACR_MODEL_HEADER: &str = "x-acr-model";
+    pub const ACR_DEPLOYMENT_ID_HEADER: &str = "x-acr-deployment-id";
+    pub const ACR_PROVIDER_HEADER: &str = "x-acr-provider";
+    /// Per-phase timings of the request (see `timing`).
+    pub const SERVER_TIMING_HEADER: &str = "server-timing";
+    pub const REQUEST_ID_PREFIX: &str = "req_";
+
+    // Signed-request headers (see `hmac_auth`).
+    pub const ACR_KEY_ID_HEADER: &str = "x-acr-key-id";
+    pub const ACR_TIMESTAMP_HEADER: &str = "x-acr-timestamp";
+    pub const ACR_SIGNATURE_HEADER: &str = "x-acr-signature";
+
+    // OpenAI-style rate-limit headers: the key's requests-per-minute window,
+    // and its tightest token quota window. Resets are durations like `6m0s`.
+    pub const RATELIMIT_LIMIT_REQUESTS_HEADER: &str = "x-ratelimit-limit-requests";
+    pub const RATELIMIT_REMAINING_REQUESTS_HEADER: &str = "x-ratelimit-remaining-requests";
+    pub const RATELIMIT_RESET_REQUESTS_HEADER: &str = "x-ratelimit-reset-requests";
+    pub const RATELIMIT_LIMIT_TOKENS_HEADER: &str = "x-ratelimit-limit-tokens";
+    pub const RATELIMIT_REMAINING_TOKENS_HEADER: &str = "x-ratelimit-remaining-tokens";
+    pub const RATELIMIT_RESET_TOKENS_HEADER: &str = "x-ratelimit-reset-tokens";
+
+    // Token quota windows (`quotas.daily_token_limit` / `monthly_token_limit`).
+    // Reset values are seconds until the window rolls over.
+    pub const RATELIMIT_LIMIT_TOKENS_DAY_HEADER: &str = "x-ratelimit-limit-tokens-day";
+    pub const RATELIMIT_REMAINING_TOKENS_DAY_HEADER: &str = "x-ratelimit-remaining-tokens-day";
+    pub const RATELIMIT_RESET_TOKENS_DAY_HEADER: &str = "x-ratelimit-reset-tokens-day";
+    pub const RATELIMIT_LIMIT_TOKENS_MONTH_HEADER: &str = "x-ratelimit-limit-tokens-month";
+    pub const RATELIMIT_REMAINING_TOKENS_MONTH_HEADER: &str = "x-ratelimit-remaining-tokens-month";
+    pub const RATELIMIT_RESET_TOKENS_MONTH_HEADER: &str = "x-ratelimit-reset-tokens-month";
+
     // Anthropic-Beta header and Anthropic→Bedrock beta-name remap
     pub const ANTHROPIC_BETA_HEADER: &str = "anthropic-beta";
 
@@ -218,11 +329,331 @@ pub fn get_extended_context_beta(model: &str) -> Option<&'static str> {
     get_context_caps(model).and_then(|c| c.beta)
 }
 
+pub mod session {
+    /// Longer session IDs (from `metadata.user_id`) are hashed; longer
+    /// `x-acr-session-id` headers are ignored.
+    pub const MAX_SESSION_ID_LEN: usize = 128;
+    /// How long a session stays pinned to the provider that last served it.
+    /// Matches the 1h `cache_control` TTL acr injects for Claude.
+    pub const AFFINITY_TTL_SECS: u64 = 3600;
+    /// Upper bound on remembered sessions (affinity map and per-session metrics).
+    pub const AFFINITY_CAPACITY: usize = 10_000;
+}
+
+pub mod metrics {
+    /// Upper bounds (seconds) of the request-duration histogram buckets.
+    /// Spans quick embeddings through long reasoning streams.
+    pub const LATENCY_BUCKETS_SECS: &[f64] = &[
+        0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+    ];
+    pub const DEFAULT_STATSD_HOST: &str = "127.0.0.1";
+    pub const DEFAULT_STATSD_PORT: u16 = 8125;
+    pub const DEFAULT_STATSD_PREFIX: &str = "acr";
+    /// Upper bounds of the attempts-per-request histogram buckets.
+    pub const ATTEMPT_BUCKETS: &[u64] = &[1, 2, 3, 5];
+    /// Upstream attempts kept for `/admin/recent`.
+    pub const RECENT_REQUESTS_CAPACITY: usize = 200;
+    /// Longer error messages are truncated in request summaries.
+    pub const MAX_SUMMARY_ERROR_LEN: usize = 500;
+    /// How often the StatsD exporter reports the in-flight request gauge.
+    pub const STATSD_GAUGE_INTERVAL_SECS: u64 = 10;
+}
+
+pub mod slo {
+    pub const DEFAULT_ERROR_RATE_OBJECTIVE: f64 = 0.99;
+    pub const DEFAULT_LATENCY_OBJECTIVE: f64 = 0.95;
+    /// Attempts are counted per minute; the longest window needs this many.
+    pub const BUCKET_SECS: u64 = 60;
+    pub const BUCKETS: usize = 360;
+    /// Multi-window burn-rate alerts: (severity, long window, short window,
+    /// burn rate), in minutes. An alert fires while both windows burn the
+    /// error budget faster than the rate; at 14.4 a 30-day budget lasts two
+    /// days, at 6 five days.
+    pub const ALERTS: &[(&str, u64, u64, f64)] = &[("page", 60, 5, 14.4), ("ticket", 360, 30, 6.0)];
+    pub const EVALUATE_INTERVAL_SECS: u64 = 30;
+    pub const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+}
+
+pub mod sentry {
+    /// Events waiting to be sent; further events are dropped.
+    pub const QUEUE_CAPACITY: usize = 100;
+    pub const SEND_TIMEOUT_SECS: u64 = 10;
+}
+
+pub mod admission {
+    /// `Retry-After` on a shed request: capacity frees up as soon as any
+    /// in-flight request finishes, so clients should come back quickly.
+    pub const SHED_RETRY_AFTER_SECS: u64 = 1;
+}
+
+pub mod retry_budget {
+    pub const DEFAULT_MIN_PER_SEC: u32 = 10;
+    pub const DEFAULT_WINDOW_SECS: u64 = 10;
+}
+
+pub mod streams {
+    /// `Retry-After` on a stream rejected by a cap. Streams run for seconds
+    /// to minutes, so an immediate retry would most likely be rejected too.
+    pub const CAP_RETRY_AFTER_SECS: u64 = 5;
+    /// How far ahead of its output rate a key may run before events are held
+    /// back.
+    pub const PACE_BURST: std::time::Duration = std::time::Duration::from_secs(1);
+    /// Characters of streamed text counted as one output token when pacing.
+    pub const PACE_CHARS_PER_TOKEN: u64 = 4;
+}
+
+pub mod redaction {
+    pub const MASK: &str = "[REDACTED]";
+    pub const MASK_BEARER: &str = "Bearer [REDACTED]";
+    /// Body paths masked in debug logs unless `log_redaction.paths` is set:
+    /// prompts and completions across the OpenAI, Anthropic and Gemini schemas.
+    pub const DEFAULT_PATHS: &[&str] = &[
+        "messages.*.content",
+        "system",
+        "instructions",
+        "input",
+        "prompt",
+        "contents.*.parts",
+        "systemInstruction",
+        "content",
+        "output",
+        "choices.*.message.content",
+        "choices.*.text",
+        "candidates.*.content",
+    ];
+    /// Fields masked wherever they appear, compared case-insensitively.
+    pub const SECRET_KEYS: &[&str] = &[
+        "api_key",
+        "api-key",
+        "apikey",
+        "x-api-key",
+        "authorization",
+        "access_token",
+        "refresh_token",
+        "client_secret",
+        "password",
+        "secret",
+        "token",
+    ];
+}
+
+pub mod capture {
+    /// Prefix of stream capture IDs; the rest is a timestamp and a random suffix.
+    pub const ID_PREFIX: &str = "cap_";
+    /// Upper bound on captured upstream plus client bytes per request.
+    pub const MAX_CAPTURE_BYTES: usize = 16 * 1024 * 1024;
+}
+
+pub mod continuation {
+    pub const DEFAULT_MAX_CONTINUATIONS: u32 = 3;
+    /// User turn asking OpenAI and Gemini models, which can't resume their
+    /// own turn, to go on where the previous answer stopped.
+    pub const CONTINUE_PROMPT: &str =
+        "Continue exactly where your previous message stopped. Do not repeat anything.";
+}
+
+pub mod image_fetch {
+    /// Anthropic's per-image limit for base64 images.
+    pub const DEFAULT_MAX_BYTES: usize = 5 * 1024 * 1024;
+    pub const DEFAULT_TIMEOUT_SECS: u64 = 10;
+    pub const DEFAULT_CACHE_TTL_SECS: u64 = 300;
+    pub const DEFAULT_CACHE_MAX_ENTRIES: usize = 32;
+    /// Redirects followed per image; each hop is checked against the allowlists.
+    pub const MAX_REDIRECTS: usize = 5;
+}
+
+pub mod deployment_health {
+    pub const DEFAULT_WINDOW_SECS: u64 = 60;
+    pub const DEFAULT_MIN_REQUESTS: usize = 5;
+    pub const DEFAULT_MAX_ERROR_RATE: f64 = 0.5;
+    pub const DEFAULT_SLOW_FACTOR: f64 = 2.0;
+    /// Weight of the newest sample in a deployment's smoothed latency.
+    pub const LATENCY_SMOOTHING: f64 = 0.3;
+}
+
+pub mod secrets {
+    /// Marks a config value as age-encrypted; the rest is base64.
+    pub const PREFIX: &str = "age:";
+    /// Marks a config value as the name of an OS keychain entry.
+    pub const KEYCHAIN_PREFIX: &str = "keychain:";
+    pub const PASSPHRASE_ENV: &str = "ACR_SECRETS_PASSPHRASE";
+    pub const KEYCHAIN_SERVICE: &str = "aicore-router";
+    /// Keychain entry holding the passphrase; `keychain:` references use
+    /// their own entry names under the same service.
+    pub const KEYCHAIN_USER: &str = "secrets";
+}
+
+pub mod tenant_quota {
+    /// The period `providers[].quota` limits apply to.
+    pub const WINDOW: std::time::Duration = std::time::Duration::from_secs(60);
+    /// Share of a quota after which a provider is tried after the others.
+    pub const LOW_SHARE: f64 = 0.9;
+}
+
+pub mod upstream_limits {
+    pub const DEFAULT_MIN_REMAINING_REQUESTS: u64 = 2;
+    pub const DEFAULT_MIN_REMAINING_TOKENS: u64 = 2000;
+    pub const DEFAULT_COOLDOWN_SECS: u64 = 5;
+    pub const DEFAULT_MAX_COOLDOWN_SECS: u64 = 60;
+    /// How long remaining-quota figures count when the upstream sends no
+    /// reset time; after that the deployment is treated as clear again.
+    pub const OBSERVATION_TTL_SECS: u64 = 60;
+    pub const REMAINING_REQUESTS_HEADER: &str = "x-ratelimit-remaining-requests";
+    pub const REMAINING_TOKENS_HEADER: &str = "x-ratelimit-remaining-tokens";
+    pub const REMAINING_HEADER: &str = "x-ratelimit-remaining";
+    pub const RESET_REQUESTS_HEADER: &str = "x-ratelimit-reset-requests";
+    pub const RESET_TOKENS_HEADER: &str = "x-ratelimit-reset-tokens";
+    pub const RESET_HEADER: &str = "x-ratelimit-reset";
+}
+
+pub mod timeouts {
+    /// Matches the HTTP client's own timeout, so by default acr answers
+    /// before reqwest gives up on the upstream.
+    pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 600;
+    pub const DEFAULT_EMBEDDINGS_TIMEOUT_SECS: u64 = 60;
+    /// Routes that get `DEFAULT_EMBEDDINGS_TIMEOUT_SECS` unless
+    /// `timeouts.routes` is set.
+    pub const EMBEDDING_ROUTES: &[&str] = &[
+        "/v1/embeddings",
+        "/openai/deployments/{model}/embedding",
+        "/openai/deployments/{model}/embeddings",
+    ];
+}
+
+pub mod warmup {
+    pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+    /// Route label of warm-up requests, which never come from a client.
+    pub const ROUTE: &str = "warmup";
+    pub const PROMPT: &str = "ping";
+}
+
+pub mod response_cache {
+    pub const DEFAULT_TTL_SECS: u64 = 300;
+    pub const DEFAULT_MAX_ENTRIES: usize = 1000;
+    pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024;
+    /// Top-level request fields that don't change the answer, left out of
+    /// the cache key.
+    pub const IGNORED_FIELDS: &[&str] = &["stream", "user", "metadata"];
+}
+
+pub mod daemon {
+    /// Set on the router `acr serve --daemon` starts in the background.
+    pub const CHILD_ENV: &str = "ACR_DAEMONIZED";
+    /// Set on the router `acr upgrade` starts; its stdin is the listener.
+    pub const HANDOFF_ENV: &str = "ACR_HANDOFF";
+    // Next to the config file.
+    pub const PID_FILE: &str = "acr.pid";
+    pub const SOCKET_FILE: &str = "acr.sock";
+    pub const LOG_FILE: &str = "acr.log";
+    /// Covers the startup checks and the initial deployment resolution.
+    pub const STARTUP_TIMEOUT_SECS: u64 = 60;
+    /// How long `acr stop` waits for in-flight requests to finish.
+    pub const STOP_TIMEOUT_SECS: u64 = 60;
+    pub const POLL_INTERVAL_MS: u64 = 100;
+}
+
+pub mod systemd {
+    pub const LISTEN_PID_ENV: &str = "LISTEN_PID";
+    pub const LISTEN_FDS_ENV: &str = "LISTEN_FDS";
+    pub const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+    /// First file descriptor of the sockets systemd passes.
+    pub const LISTEN_FDS_START: i32 = 3;
+}
+
+pub mod usage_store {
+    /// Connections each router keeps to a PostgreSQL usage store.
+    pub const POSTGRES_POOL_SIZE: usize = 8;
+    /// Advisory lock held while creating the `requests` table, so routers
+    /// starting together don't race.
+    pub const POSTGRES_MIGRATION_LOCK: i64 = 0x6163_725f_7573_6167;
+}
+
+pub mod reload {
+    /// Quiet time after a config file change before it is read, so a save
+    /// made in several writes is read once, complete.
+    pub const DEBOUNCE_MS: u64 = 500;
+}
+
+pub mod cluster {
+    pub const DEFAULT_KEY_PREFIX: &str = "acr";
+    /// How long a Redis call may take before limits fall back to this
+    /// router's own counts.
+    pub const TIMEOUT_MS: u64 = 1_000;
+    /// How long Redis is left alone after a failed call.
+    pub const RETRY_PAUSE_MS: u64 = 5_000;
+    /// The window `requests_per_minute` is counted over.
+    pub const RATE_WINDOW_MS: u64 = 60_000;
+    /// Token counters outlive their day or month by a day, for routers
+    /// whose clocks run a little behind.
+    pub const DAILY_COUNTER_TTL_SECS: i64 = 2 * 86_400;
+    pub const MONTHLY_COUNTER_TTL_SECS: i64 = 32 * 86_400;
+    /// How often an unreachable Redis is reported while limits fall back
+    /// to this router's own counts.
+    pub const ERROR_LOG_INTERVAL_SECS: u64 = 60;
+    /// How long a router may take to fetch an OAuth token before another
+    /// one may try.
+    pub const TOKEN_REFRESH_LOCK_MS: u64 = 10_000;
+    /// How long a router waits for another one's token fetch before
+    /// fetching its own.
+    pub const TOKEN_WAIT_MS: u64 = 5_000;
+    pub const TOKEN_POLL_INTERVAL_MS: u64 = 100;
+}
+
+pub mod dead_letter {
+    /// Prefix of dead-letter IDs; the rest is a timestamp and a random suffix.
+    pub const ID_PREFIX: &str = "dl_";
+    pub const DEFAULT_MAX_ENTRIES: usize = 1000;
+}
+
+pub mod cached_content {
+    /// Lifetime assumed for a cache whose create response carries no
+    /// `expireTime`; Gemini's own default TTL.
+    pub const DEFAULT_TTL_SECS: i64 = 3600;
+}
+
+pub mod hmac_auth {
+    /// How far a signed request's timestamp may be from the server clock.
+    pub const DEFAULT_MAX_SKEW_SECS: u64 = 300;
+}
+
+pub mod usage_export {
+    /// Rows read from the database per query while streaming an export.
+    pub const PAGE_SIZE: usize = 1000;
+}
+
+pub mod batches {
+    /// Prefix of generated batch IDs, matching Anthropic's `msgbatch_…` IDs.
+    pub const ID_PREFIX: &str = "msgbatch_";
+    /// Anthropic's per-batch request cap; larger batches are rejected up front.
+    pub const MAX_REQUESTS_PER_BATCH: usize = 100_000;
+    /// Requests not started this long after batch creation are reported as
+    /// `expired` instead of being sent upstream.
+    pub const EXPIRY_HOURS: i64 = 24;
+    pub const DEFAULT_LIST_LIMIT: usize = 20;
+    pub const MAX_LIST_LIMIT: usize = 1000;
+}
+
 pub mod config {
     pub const DEFAULT_BIND: &str = "127.0.0.1:8900";
     pub const DEFAULT_LOG_LEVEL: &str = "info";
     pub const DEFAULT_RESOURCE_GROUP: &str = "default";
     pub const DEFAULT_REFRESH_INTERVAL_SECS: u64 = 300; // 5 minutes
+    pub const DEFAULT_BATCH_MAX_CONCURRENCY: usize = 4;
+    /// How often changed quota counters are written to `quotas.state_file`.
+    pub const QUOTA_STATE_SAVE_INTERVAL_SECS: u64 = 30;
+    pub const DEFAULT_ADMISSION_MAX_QUEUE: usize = 256;
+    pub const DEFAULT_ADMISSION_QUEUE_TIMEOUT_SECS: u64 = 30;
+    pub const DEFAULT_MAX_REQUEST_BODY_MB: usize = 10;
+    /// Per-provider budget for the startup credential check (`--check`).
+    pub const STARTUP_CHECK_TIMEOUT_SECS: u64 = 30;
+    /// Keys file read next to the config file when `keys_file` isn't set.
+    pub const DEFAULT_KEYS_FILE_NAME: &str = "keys.yaml";
+    /// Marks an API key stored as the hex SHA-256 of the key.
+    pub const HASHED_KEY_PREFIX: &str = "sha256:";
+    /// Prefix of keys generated by `acr keys add`.
+    pub const GENERATED_KEY_PREFIX: &str = "acr-";
+    /// Config layout written by `acr config migrate` (see `config_migrate`).
+    pub const CURRENT_CONFIG_VERSION: u64 = 2;
 }
 
 #[cfg(test)]
@@ -0,0 +1,281 @@
+//! Automatic continuation of truncated responses.
+//!
+//! A non-streaming answer that stops because it ran into `max_tokens`
+//! (`finish_reason: length`, `stop_reason: max_tokens`, `finishReason:
+//! MAX_TOKENS`) is continued with `continuation.enabled`: acr sends the
+//! conversation again with the partial answer appended, and stitches the
+//! pieces into one response, until the model stops on its own or
+//! `continuation.max_continuations` is reached.
+//!
+//! Everything here works on the upstream family's shapes, before the
+//! response is translated for the client. Claude resumes its own turn from an
+//! assistant prefill; OpenAI-style and Gemini models get the partial answer
+//! as their turn followed by a user turn asking them to go on. Only text
+//! answers are continued: a truncated tool call or thinking block is
+//! returned as it is.
+
+use serde_json::{Map, Value, json};
+
+use crate::constants::continuation::CONTINUE_PROMPT;
+use crate::proxy::LlmFamily;
+
+/// The request continuing `response`, a reply to `request`, or `None` when
+/// the response is complete or can't be continued.
+pub fn next_request(family: LlmFamily, request: &Value, response: &Value) -> Option<Value> {
+    let text = truncated_text(family, response)?;
+    let mut request = request.clone();
+    match family {
+        LlmFamily::Claude => {
+            let messages = request.get_mut("messages")?.as_array_mut()?;
+            // An existing prefill isn't part of the response text, so the
+            // continuation's prefill carries both. Claude rejects a prefill
+            // ending in whitespace.
+            let prefill = match messages.last() {
+                Some(last) if last["role"] == "assistant" => {
+                    let prefill = message_text(&last["content"]);
+                    messages.pop();
+                    prefill
+                }
+                _ => String::new(),
+            };
+            let prefill = format!("{prefill}{text}");
+            messages.push(json!({"role": "assistant", "content": prefill.trim_end()}));
+        }
+        LlmFamily::OpenAi | LlmFamily::OpenWeight => {
+            let messages = request.get_mut("messages")?.as_array_mut()?;
+            messages.push(json!({"role": "assistant", "content": text}));
+            messages.push(json!({"role": "user", "content": CONTINUE_PROMPT}));
+        }
+        LlmFamily::Gemini => {
+            let contents = request.get_mut("contents")?.as_array_mut()?;
+            contents.push(json!({"role": "model", "parts": [{"text": text}]}));
+            contents.push(json!({"role": "user", "parts": [{"text": CONTINUE_PROMPT}]}));
+        }
+        _ => return None,
+    }
+    Some(request)
+}
+
+/// Append continuation `next` to the response stitched so far: its text,
+/// its stop reason and its usage.
+pub fn stitch(family: LlmFamily, stitched: &mut Value, next: &Value) {
+    let next_text = response_text(family, next).unwrap_or_default();
+    match family {
+        LlmFamily::Claude => {
+            if let Some(block) = stitched["content"]
+                .as_array_mut()
+                .and_then(|blocks| blocks.last_mut())
+            {
+                // The continuation picks up after the trimmed prefill.
+                let text = block["text"].as_str().unwrap_or_default().trim_end();
+                block["text"] = Value::String(format!("{text}{next_text}"));
+            }
+            stitched["stop_reason"] = next["stop_reason"].clone();
+            stitched["stop_sequence"] = next["stop_sequence"].clone();
+            add_usage(&mut stitched["usage"], &next["usage"]);
+        }
+        LlmFamily::OpenAi | LlmFamily::OpenWeight => {
+            let message = &mut stitched["choices"][0]["message"];
+            let text = message["content"].as_str().unwrap_or_default();
+            message["content"] = Value::String(format!("{text}{next_text}"));
+            stitched["choices"][0]["finish_reason"] = next["choices"][0]["finish_reason"].clone();
+            add_usage(&mut stitched["usage"], &next["usage"]);
+        }
+        LlmFamily::Gemini => {
+            if let Some(part) = stitched["candidates"][0]["content"]["parts"]
+                .as_array_mut()
+                .and_then(|parts| parts.last_mut())
+            {
+                let text = part["text"].as_str().unwrap_or_default();
+                part["text"] = Value::String(format!("{text}{next_text}"));
+            }
+            stitched["candidates"][0]["finishReason"] =
+                next["candidates"][0]["finishReason"].clone();
+            add_usage(&mut stitched["usageMetadata"], &next["usageMetadata"]);
+        }
+        _ => {}
+    }
+}
+
+/// The answer's text when the response was cut off at the token limit and
+/// holds nothing but text.
+fn truncated_text(family: LlmFamily, response: &Value) -> Option<String> {
+    let truncated = match family {
+        LlmFamily::Claude => response["stop_reason"] == "max_tokens",
+        LlmFamily::OpenAi | LlmFamily::OpenWeight => {
+            response["choices"].as_array().map(Vec::len) == Some(1)
+                && response["choices"][0]["finish_reason"] == "length"
+        }
+        LlmFamily::Gemini => {
+            response["candidates"].as_array().map(Vec::len) == Some(1)
+                && response["candidates"][0]["finishReason"] == "MAX_TOKENS"
+        }
+        _ => false,
+    };
+    if !truncated {
+        return None;
+    }
+    response_text(family, response).filter(|text| !text.trim().is_empty())
+}
+
+/// The text of a text-only answer; `None` if it holds anything else.
+fn response_text(family: LlmFamily, response: &Value) -> Option<String> {
+    match family {
+        LlmFamily::Claude => {
+            let blocks = response["content"].as_array()?;
+            blocks
+                .iter()
+                .all(|block| block["type"] == "text")
+                .then(|| message_text(&response["content"]))
+        }
+        LlmFamily::OpenAi | LlmFamily::OpenWeight => {
+            let message = &response["choices"][0]["message"];
+            if message
+                .get("tool_calls")
+                .is_some_and(|calls| !calls.is_null())
+            {
+                return None;
+            }
+            message["content"].as_str().map(str::to_string)
+        }
+        LlmFamily::Gemini => {
+            let parts = response["candidates"][0]["content"]["parts"].as_array()?;
+            parts
+                .iter()
+                .map(|part| match part.get("thought") {
+                    Some(Value::Bool(true)) => None,
+                    _ => part["text"].as_str(),
+                })
+                .collect()
+        }
+        _ => None,
+    }
+}
+
+/// A Claude message's content as text: the string, or its text blocks.
+fn message_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter_map(|block| block["text"].as_str())
+            .collect(),
+        _ => String::new(),
+    }
+}
+
+/// Add the counts in `next` to `total`; every call of the stitched answer
+/// was billed.
+fn add_usage(total: &mut Value, next: &Value) {
+    let (Some(total), Some(next)) = (total.as_object_mut(), next.as_object()) else {
+        return;
+    };
+    add_counts(total, next);
+}
+
+fn add_counts(total: &mut Map<String, Value>, next: &Map<String, Value>) {
+    for (key, value) in next {
+        match (total.get_mut(key), value) {
+            (Some(Value::Number(sum)), Value::Number(n)) => {
+                if let (Some(a), Some(b)) = (sum.as_u64(), n.as_u64()) {
+                    *sum = (a + b).into();
+                }
+            }
+            (Some(Value::Object(sum)), Value::Object(n)) => add_counts(sum, n),
+            (None, Value::Number(_) | Value::Object(_)) => {
+                total.insert(key.clone(), value.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claude_resumes_its_own_turn() {
+        let request = json!({"max_tokens": 5, "messages": [
+            {"role": "user", "content": "Write a poem"},
+            {"role": "assistant", "content": [{"type": "text", "text": "Roses "}]},
+        ]});
+        let mut stitched = json!({
+            "content": [{"type": "text", "text": "are red, \n"}],
+            "stop_reason": "max_tokens",
+            "usage": {"input_tokens": 10, "output_tokens": 5}
+        });
+        let next = next_request(LlmFamily::Claude, &request, &stitched).unwrap();
+        let messages = next["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[1],
+            json!({"role": "assistant", "content": "Roses are red,"})
+        );
+
+        let continued = json!({
+            "content": [{"type": "text", "text": "\nviolets are blue."}],
+            "stop_reason": "end_turn",
+            "stop_sequence": null,
+            "usage": {"input_tokens": 15, "output_tokens": 6, "cache_read_input_tokens": 8}
+        });
+        stitch(LlmFamily::Claude, &mut stitched, &continued);
+        assert_eq!(
+            stitched["content"][0]["text"],
+            "are red,\nviolets are blue."
+        );
+        assert_eq!(stitched["stop_reason"], "end_turn");
+        assert_eq!(
+            stitched["usage"],
+            json!({"input_tokens": 25, "output_tokens": 11, "cache_read_input_tokens": 8})
+        );
+        assert!(next_request(LlmFamily::Claude, &request, &stitched).is_none());
+    }
+
+    #[test]
+    fn openai_and_gemini_are_asked_to_go_on() {
+        let request = json!({"messages": [{"role": "user", "content": "Count"}]});
+        let mut stitched = json!({
+            "choices": [{"message": {"role": "assistant", "content": "1, 2,"}, "finish_reason": "length"}],
+            "usage": {"prompt_tokens": 3, "completion_tokens": 4, "total_tokens": 7}
+        });
+        let next = next_request(LlmFamily::OpenAi, &request, &stitched).unwrap();
+        assert_eq!(next["messages"][1]["content"], "1, 2,");
+        assert_eq!(next["messages"][2]["role"], "user");
+        let continued = json!({
+            "choices": [{"message": {"role": "assistant", "content": " 3."}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 20, "completion_tokens": 2, "total_tokens": 22}
+        });
+        stitch(LlmFamily::OpenAi, &mut stitched, &continued);
+        assert_eq!(stitched["choices"][0]["message"]["content"], "1, 2, 3.");
+        assert_eq!(stitched["choices"][0]["finish_reason"], "stop");
+        assert_eq!(stitched["usage"]["total_tokens"], 29);
+
+        let request = json!({"contents": [{"role": "user", "parts": [{"text": "Count"}]}]});
+        let response = json!({
+            "candidates": [{"content": {"role": "model", "parts": [{"text": "1, 2,"}]}, "finishReason": "MAX_TOKENS"}]
+        });
+        let next = next_request(LlmFamily::Gemini, &request, &response).unwrap();
+        assert_eq!(next["contents"][1]["role"], "model");
+        assert_eq!(next["contents"][2]["parts"][0]["text"], CONTINUE_PROMPT);
+    }
+
+    #[test]
+    fn only_truncated_text_answers_are_continued() {
+        let request = json!({"messages": []});
+        let tool_call = json!({
+            "choices": [{"message": {"content": null, "tool_calls": [{"id": "call_1"}]}, "finish_reason": "length"}]
+        });
+        assert!(next_request(LlmFamily::OpenAi, &request, &tool_call).is_none());
+        let thinking = json!({
+            "content": [{"type": "thinking", "thinking": "Hmm"}],
+            "stop_reason": "max_tokens"
+        });
+        assert!(next_request(LlmFamily::Claude, &request, &thinking).is_none());
+        let complete =
+            json!({"choices": [{"message": {"content": "Done."}, "finish_reason": "stop"}]});
+        assert!(next_request(LlmFamily::OpenAi, &request, &complete).is_none());
+        let responses = json!({"status": "incomplete", "output": []});
+        assert!(next_request(LlmFamily::OpenAiResponses, &request, &responses).is_none());
+    }
+}
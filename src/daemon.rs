@@ -0,0 +1,513 @@
+//! Running the router in the background without a service manager.
+//!
+//! `acr serve --daemon` starts this binary again as `acr serve`, detached
+//! from the terminal, with its output going to a log file, and returns once
+//! the background router is listening. The background router writes a pid
+//! file and answers `status` and `stop` on a local control socket, which
+//! `acr status` and `acr stop` use. The pid file, socket and log sit next to
+//! the config file, so routers with different config directories don't
+//! collide.
+//!
+//! The control socket is a Unix domain socket, readable only by the owner;
+//! on other platforms the daemon commands report that they aren't supported.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::constants::daemon::{LOG_FILE, PID_FILE, SOCKET_FILE};
+
+/// Files of the background router for one config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DaemonPaths {
+    pub pid_file: PathBuf,
+    pub socket: PathBuf,
+    pub log_file: PathBuf,
+}
+
+impl DaemonPaths {
+    /// The files next to `config_file`.
+    pub fn next_to(config_file: &str) -> Self {
+        let dir = Path::new(config_file)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        Self {
+            pid_file: dir.join(PID_FILE),
+            socket: dir.join(SOCKET_FILE),
+            log_file: dir.join(LOG_FILE),
+        }
+    }
+
+    /// The pid recorded in the pid file, if there is one.
+    pub fn recorded_pid(&self) -> Option<u32> {
+        std::fs::read_to_string(&self.pid_file)
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+}
+
+/// What `status` answers on the control socket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    pub version: String,
+    pub bind: String,
+    pub started_at: DateTime<Utc>,
+    pub total_requests: u64,
+    pub active_requests: u64,
+}
+
+/// Whether this process was started by `acr serve --daemon`.
+pub fn is_daemon_child() -> bool {
+    std::env::var_os(crate::constants::daemon::CHILD_ENV).is_some()
+}
+
+/// `2h 5m`, `3m 12s`, `42s`.
+pub fn format_uptime(secs: u64) -> String {
+    let (days, hours, minutes, seconds) =
+        (secs / 86_400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{ControlSocket, handed_over_listener, start, status, stop, upgrade};
+
+#[cfg(unix)]
+mod unix {
+    use std::os::fd::{AsFd, OwnedFd};
+    use std::os::unix::process::CommandExt;
+    use std::process::Stdio;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{UnixListener, UnixStream};
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::constants::daemon::{
+        CHILD_ENV, HANDOFF_ENV, POLL_INTERVAL_MS, STARTUP_TIMEOUT_SECS, STOP_TIMEOUT_SECS,
+    };
+    use crate::metrics::MetricsService;
+
+    /// `acr serve --daemon`: start `acr` with `args` (the command line
+    /// without `--daemon`) in the background and wait until it answers on
+    /// its control socket.
+    pub async fn start(paths: &DaemonPaths, args: Vec<std::ffi::OsString>) -> Result<()> {
+        if let Ok(running) = query(&paths.socket).await {
+            anyhow::bail!("acr is already running (pid {})", running.pid);
+        }
+        let log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&paths.log_file)
+            .with_context(|| format!("Failed to open {}", paths.log_file.display()))?;
+        let exe = std::env::current_exe().context("Failed to find the acr executable")?;
+        let mut child = std::process::Command::new(exe)
+            .args(args)
+            .env(CHILD_ENV, "1")
+            .env("NO_COLOR", "1")
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            // Its own process group, so Ctrl+C in this terminal doesn't
+            // reach it.
+            .process_group(0)
+            .spawn()
+            .context("Failed to start acr in the background")?;
+
+        let running = wait_until_answering(paths, &mut child).await?;
+        println!(
+            "acr started (pid {}), listening on {}, logging to {}",
+            running.pid,
+            running.bind,
+            paths.log_file.display()
+        );
+        Ok(())
+    }
+
+    /// Poll the control socket until `child` answers on it.
+    async fn wait_until_answering(
+        paths: &DaemonPaths,
+        child: &mut std::process::Child,
+    ) -> Result<DaemonStatus> {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(STARTUP_TIMEOUT_SECS);
+        loop {
+            if let Some(exit) = child.try_wait()? {
+                anyhow::bail!(
+                    "acr exited during startup ({exit}); see {}",
+                    paths.log_file.display()
+                );
+            }
+            if let Ok(running) = query(&paths.socket).await
+                && running.pid == child.id()
+            {
+                return Ok(running);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                anyhow::bail!(
+                    "acr (pid {}) didn't start listening within {}s; see {}",
+                    child.id(),
+                    STARTUP_TIMEOUT_SECS,
+                    paths.log_file.display()
+                );
+            }
+            tokio::time::sleep(Duration::from_millis(POLL_INTERVAL_MS)).await;
+        }
+    }
+
+    /// `acr status`: the background router's status, or `None` when none is
+    /// running. A stale pid file is removed.
+    pub async fn status(paths: &DaemonPaths) -> Result<Option<DaemonStatus>> {
+        match query(&paths.socket).await {
+            Ok(status) => Ok(Some(status)),
+            Err(_) => {
+                if let Some(pid) = paths.recorded_pid() {
+                    tracing::debug!("Removing stale pid file for pid {}", pid);
+                    let _ = std::fs::remove_file(&paths.pid_file);
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// `acr stop`: ask the background router to shut down and wait until it
+    /// has finished its requests. Returns the stopped router's pid, or
+    /// `None` when none was running.
+    pub async fn stop(paths: &DaemonPaths) -> Result<Option<u32>> {
+        let Some(running) = status(paths).await? else {
+            return Ok(None);
+        };
+        request(&paths.socket, "stop").await?;
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(STOP_TIMEOUT_SECS);
+        while paths.recorded_pid() == Some(running.pid) {
+            if tokio::time::Instant::now() >= deadline {
+                anyhow::bail!(
+                    "acr (pid {}) is still shutting down after {}s",
+                    running.pid,
+                    STOP_TIMEOUT_SECS
+                );
+            }
+            tokio::time::sleep(Duration::from_millis(POLL_INTERVAL_MS)).await;
+        }
+        Ok(Some(running.pid))
+    }
+
+    /// `acr upgrade`: have the background router hand its listener to a new
+    /// router started from `exe`, then drain. Returns the old and new pids.
+    pub async fn upgrade(paths: &DaemonPaths, exe: &Path) -> Result<(u32, u32)> {
+        let Some(running) = status(paths).await? else {
+            anyhow::bail!("acr is not running");
+        };
+        let reply = request(&paths.socket, &format!("upgrade {}", exe.display())).await?;
+        match reply.strip_prefix("ok ") {
+            Some(pid) => Ok((
+                running.pid,
+                pid.parse().context("Unexpected upgrade reply")?,
+            )),
+            None => anyhow::bail!("{}", reply.strip_prefix("error: ").unwrap_or(&reply)),
+        }
+    }
+
+    /// The listener an upgrading router handed to this one, if any.
+    pub fn handed_over_listener() -> Result<Option<tokio::net::TcpListener>> {
+        use std::os::fd::AsFd;
+
+        if std::env::var_os(HANDOFF_ENV).is_none() {
+            return Ok(None);
+        }
+        // The old router passes the listener as stdin.
+        let listener = std::net::TcpListener::from(std::io::stdin().as_fd().try_clone_to_owned()?);
+        listener
+            .local_addr()
+            .context("The listener handed over by the previous router isn't a TCP listener")?;
+        listener.set_nonblocking(true)?;
+        Ok(Some(tokio::net::TcpListener::from_std(listener)?))
+    }
+
+    async fn query(socket: &Path) -> Result<DaemonStatus> {
+        let reply = request(socket, "status").await?;
+        serde_json::from_str(&reply).context("Unexpected status reply")
+    }
+
+    /// Send one command and read the one-line reply.
+    async fn request(socket: &Path, command: &str) -> Result<String> {
+        let stream = tokio::time::timeout(
+            Duration::from_secs(STOP_TIMEOUT_SECS),
+            UnixStream::connect(socket),
+        )
+        .await
+        .context("Timed out connecting to the control socket")?
+        .with_context(|| format!("Failed to connect to {}", socket.display()))?;
+        let (reader, mut writer) = stream.into_split();
+        writer.write_all(format!("{command}\n").as_bytes()).await?;
+        let mut reply = String::new();
+        BufReader::new(reader).read_line(&mut reply).await?;
+        Ok(reply.trim_end().to_string())
+    }
+
+    /// The background router's pid file and control socket, removed when
+    /// dropped unless a successor has taken them over.
+    pub struct ControlSocket {
+        shared: Arc<Shared>,
+    }
+
+    struct Shared {
+        paths: DaemonPaths,
+        status: DaemonStatus,
+        metrics: MetricsService,
+        /// Duplicate of the HTTP listener, for handing it to a successor.
+        listener: OwnedFd,
+        stop: Notify,
+        upgrading: AtomicBool,
+    }
+
+    impl ControlSocket {
+        /// Write the pid file and answer on the control socket.
+        pub fn open(
+            paths: DaemonPaths,
+            listener: &tokio::net::TcpListener,
+            metrics: MetricsService,
+        ) -> Result<Self> {
+            let shared = Arc::new(Shared {
+                status: DaemonStatus {
+                    pid: std::process::id(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    bind: listener.local_addr()?.to_string(),
+                    started_at: Utc::now(),
+                    total_requests: 0,
+                    active_requests: 0,
+                },
+                listener: listener.as_fd().try_clone_to_owned()?,
+                paths,
+                metrics,
+                stop: Notify::new(),
+                upgrading: AtomicBool::new(false),
+            });
+            listen(&shared)?;
+            tracing::info!(
+                "Running in the background (pid file {}, control socket {})",
+                shared.paths.pid_file.display(),
+                shared.paths.socket.display()
+            );
+            Ok(Self { shared })
+        }
+
+        /// Resolves when `acr stop` asks the router to shut down, or when a
+        /// successor has taken over its listener.
+        pub fn stopped(&self) -> impl Future<Output = ()> + Send + 'static {
+            let shared = self.shared.clone();
+            async move { shared.stop.notified().await }
+        }
+    }
+
+    /// Bind the control socket, write the pid file and answer commands.
+    fn listen(shared: &Arc<Shared>) -> Result<()> {
+        let paths = &shared.paths;
+        // A socket file left behind by a router that didn't shut down
+        // cleanly would fail the bind; one of a router being upgraded now
+        // belongs to this one.
+        let _ = std::fs::remove_file(&paths.socket);
+        let control = UnixListener::bind(&paths.socket)
+            .with_context(|| format!("Failed to bind {}", paths.socket.display()))?;
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&paths.socket, std::fs::Permissions::from_mode(0o600))?;
+        }
+        std::fs::write(&paths.pid_file, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write {}", paths.pid_file.display()))?;
+
+        let shared = shared.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = control.accept().await {
+                let shared = shared.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_command(stream, &shared).await {
+                        tracing::debug!("Control socket request failed: {:#}", e);
+                    }
+                });
+            }
+        });
+        Ok(())
+    }
+
+    impl Drop for ControlSocket {
+        fn drop(&mut self) {
+            let paths = &self.shared.paths;
+            if paths.recorded_pid() == Some(std::process::id()) {
+                let _ = std::fs::remove_file(&paths.socket);
+                let _ = std::fs::remove_file(&paths.pid_file);
+            }
+        }
+    }
+
+    async fn serve_command(stream: UnixStream, shared: &Arc<Shared>) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut command = String::new();
+        BufReader::new(reader).read_line(&mut command).await?;
+        let reply = match command
+            .trim()
+            .split_once(' ')
+            .unwrap_or((command.trim(), ""))
+        {
+            ("status", _) => {
+                let snapshot = shared.metrics.snapshot_sync();
+                let status = DaemonStatus {
+                    total_requests: snapshot.total_requests,
+                    active_requests: snapshot.active_requests,
+                    ..shared.status.clone()
+                };
+                serde_json::to_string(&status)?
+            }
+            ("stop", _) => {
+                tracing::info!("Stop requested on the control socket, shutting down...");
+                shared.stop.notify_one();
+                "stopping".to_string()
+            }
+            ("upgrade", exe) if !exe.is_empty() => {
+                if shared.upgrading.swap(true, Ordering::SeqCst) {
+                    "error: an upgrade is already in progress".to_string()
+                } else {
+                    match hand_over(shared, Path::new(exe)).await {
+                        Ok(pid) => {
+                            tracing::info!(
+                                "Router pid {} took over the listener, finishing open requests...",
+                                pid
+                            );
+                            shared.stop.notify_one();
+                            format!("ok {pid}")
+                        }
+                        Err(e) => {
+                            tracing::error!("Upgrade failed: {:#}", e);
+                            shared.upgrading.store(false, Ordering::SeqCst);
+                            format!("error: {e:#}")
+                        }
+                    }
+                }
+            }
+            (other, _) => format!("unknown command '{other}'"),
+        };
+        writer.write_all(format!("{reply}\n").as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Start `exe` with this router's command line and its listener as
+    /// stdin, and wait until it answers on the control socket. Until this
+    /// router stops accepting, both take connections from the same queue, so
+    /// none are refused.
+    async fn hand_over(shared: &Arc<Shared>, exe: &Path) -> Result<u32> {
+        tracing::info!("Handing the listener to {}", exe.display());
+        let mut child = std::process::Command::new(exe)
+            .args(std::env::args_os().skip(1))
+            .env(CHILD_ENV, "1")
+            .env(HANDOFF_ENV, "1")
+            .env("NO_COLOR", "1")
+            .stdin(Stdio::from(shared.listener.try_clone()?))
+            .process_group(0)
+            .spawn()
+            .with_context(|| format!("Failed to start {}", exe.display()))?;
+        match wait_until_answering(&shared.paths, &mut child).await {
+            Ok(running) => Ok(running.pid),
+            Err(e) => {
+                // Don't leave a half-started successor serving alongside.
+                let _ = child.kill();
+                let _ = child.wait();
+                // It may have taken over the control socket and pid file.
+                if shared.paths.recorded_pid() != Some(std::process::id()) {
+                    listen(shared)?;
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn start(_paths: &DaemonPaths, _args: Vec<std::ffi::OsString>) -> Result<()> {
+    anyhow::bail!("acr serve --daemon is only supported on Unix")
+}
+
+#[cfg(not(unix))]
+pub async fn status(_paths: &DaemonPaths) -> Result<Option<DaemonStatus>> {
+    anyhow::bail!("acr status is only supported on Unix")
+}
+
+#[cfg(not(unix))]
+pub async fn stop(_paths: &DaemonPaths) -> Result<Option<u32>> {
+    anyhow::bail!("acr stop is only supported on Unix")
+}
+
+#[cfg(not(unix))]
+pub async fn upgrade(_paths: &DaemonPaths, _exe: &Path) -> Result<(u32, u32)> {
+    anyhow::bail!("acr upgrade is only supported on Unix")
+}
+
+#[cfg(not(unix))]
+pub fn handed_over_listener() -> Result<Option<tokio::net::TcpListener>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_sit_next_to_the_config_file() {
+        let paths = DaemonPaths::next_to("/home/me/.aicore/config.yaml");
+        assert_eq!(paths.pid_file, Path::new("/home/me/.aicore/acr.pid"));
+        assert_eq!(paths.socket, Path::new("/home/me/.aicore/acr.sock"));
+        assert_eq!(paths.log_file, Path::new("/home/me/.aicore/acr.log"));
+        assert_eq!(
+            DaemonPaths::next_to("config.yaml").pid_file,
+            Path::new("./acr.pid")
+        );
+    }
+
+    #[test]
+    fn uptime_is_formatted_with_two_units() {
+        assert_eq!(format_uptime(42), "42s");
+        assert_eq!(format_uptime(192), "3m 12s");
+        assert_eq!(format_uptime(7500), "2h 5m");
+        assert_eq!(format_uptime(90_000), "1d 1h");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn control_socket_answers_status_and_stop() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let paths = DaemonPaths::next_to(dir.path().join("config.yaml").to_str().unwrap());
+        let metrics = crate::metrics::MetricsService::new();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let bind = listener.local_addr().unwrap().to_string();
+        let control = ControlSocket::open(paths.clone(), &listener, metrics).unwrap();
+        assert_eq!(paths.recorded_pid(), Some(std::process::id()));
+
+        let running = status(&paths).await.unwrap().expect("running");
+        assert_eq!(running.pid, std::process::id());
+        assert_eq!(running.bind, bind);
+
+        let stopper = tokio::spawn({
+            let paths = paths.clone();
+            async move { stop(&paths).await }
+        });
+        control.stopped().await;
+        drop(control);
+        assert_eq!(stopper.await.unwrap().unwrap(), Some(std::process::id()));
+        assert!(!paths.socket.exists());
+        assert!(status(&paths).await.unwrap().is_none());
+    }
+}
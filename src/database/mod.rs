@@ -0,0 +1,432 @@
+//! Request logging and the usage read back from it.
+//!
+//! Records go to a usage store: SQLite by default, one file per router, or
+//! with the `postgres` feature a PostgreSQL database that several routers
+//! write to, so usage, exports and quota baselines cover all of them.
+//! `Database` is the handle the rest of acr uses, whichever store is
+//! configured.
+
+use anyhow::Result;
+use std::future::Future;
+use std::path::PathBuf;
+
+use crate::config::{LogRequestsBackend, LogRequestsConfig};
+
+#[cfg(feature = "postgres")]
+mod postgres;
+mod sqlite;
+
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+
+/// A request record to be persisted.
+#[derive(Debug, Clone)]
+pub struct RequestRecord {
+    pub correlation_id: String,
+    pub method: String,
+    pub path: String,
+    pub model: String,
+    pub provider: String,
+    pub duration_ms: f64,
+    pub response_status: u16,
+    pub streaming: bool,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    pub cache_read_tokens: Option<u64>,
+    pub cache_write_tokens: Option<u64>,
+    pub api_key_hash: Option<String>,
+    /// Conversation the request belongs to (see `session`).
+    pub session_id: Option<String>,
+    /// Every provider the request was sent to, in order; stored as JSON.
+    pub attempts: Vec<crate::metrics::UpstreamAttempt>,
+}
+
+impl RequestRecord {
+    /// Create a new request record with common fields populated.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        path: String,
+        model: String,
+        provider: String,
+        duration: std::time::Duration,
+        response_status: u16,
+        streaming: bool,
+        token_stats: &crate::proxy::TokenStats,
+        api_key_hash: Option<String>,
+    ) -> Self {
+        Self {
+            correlation_id: uuid::Uuid::new_v4().to_string(),
+            method: "POST".to_string(),
+            path,
+            model,
+            provider,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            response_status,
+            streaming,
+            input_tokens: token_stats.input_tokens,
+            output_tokens: token_stats.output_tokens,
+            cache_read_tokens: token_stats.cache_read,
+            cache_write_tokens: token_stats.cache_write,
+            api_key_hash,
+            session_id: None,
+            attempts: Vec::new(),
+        }
+    }
+
+    /// Attach the request's session ID.
+    pub fn with_session_id(mut self, session_id: Option<String>) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    /// Attach the upstream attempts behind this request.
+    pub fn with_attempts(mut self, attempts: Vec<crate::metrics::UpstreamAttempt>) -> Self {
+        self.attempts = attempts;
+        self
+    }
+}
+
+/// The usage store's row for the attempt that answered the client: the same
+/// summary that feeds the log line, metrics and `/admin/recent`.
+impl From<&crate::metrics::RequestSummary> for RequestRecord {
+    fn from(summary: &crate::metrics::RequestSummary) -> Self {
+        // Counts the upstream didn't report are stored as NULL.
+        let reported = |count: u64| (count > 0).then_some(count);
+        Self {
+            correlation_id: uuid::Uuid::new_v4().to_string(),
+            method: "POST".to_string(),
+            path: summary.labels.route.clone(),
+            model: summary.labels.model.clone(),
+            provider: summary.labels.provider.clone(),
+            duration_ms: summary.latency().as_secs_f64() * 1000.0,
+            response_status: summary.status,
+            streaming: summary.labels.stream,
+            input_tokens: reported(summary.tokens.input),
+            output_tokens: reported(summary.tokens.output),
+            cache_read_tokens: reported(summary.tokens.cache_read),
+            cache_write_tokens: reported(summary.tokens.cache_write),
+            api_key_hash: summary.owner.clone(),
+            session_id: summary.session_id.clone(),
+            attempts: summary.attempts.clone(),
+        }
+    }
+}
+
+/// A usage row returned from aggregation queries.
+#[derive(Debug, Clone)]
+pub struct UsageRow {
+    pub api_key_hash: String,
+    pub model: String,
+    pub period: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_write_tokens: u64,
+    pub request_count: u64,
+}
+
+/// One logged request, as exported by `/usage/export`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ExportRow {
+    pub id: i64,
+    /// UTC, `YYYY-MM-DD HH:MM:SS`
+    pub created_at: String,
+    pub correlation_id: String,
+    pub path: String,
+    pub model: String,
+    pub provider: String,
+    pub duration_ms: f64,
+    pub response_status: u16,
+    pub streaming: bool,
+    pub input_tokens: Option<u64>,
+    pub output_tokens: Option<u64>,
+    pub cache_read_tokens: Option<u64>,
+    pub cache_write_tokens: Option<u64>,
+    pub api_key_hash: Option<String>,
+    pub session_id: Option<String>,
+}
+
+/// Time grouping for usage queries.
+#[derive(Debug, Clone, Copy)]
+pub enum GroupBy {
+    /// `YYYY-MM-DD`
+    Day,
+    /// `YYYY-Www`: weeks start on Monday, and days before the first Monday
+    /// of the year are week 00
+    Week,
+    /// `YYYY-MM`
+    Month,
+}
+
+/// Where request records are kept and usage is read back from.
+pub trait UsageStore: Send + Sync {
+    /// Insert a request record.
+    fn insert_request(&self, record: RequestRecord) -> impl Future<Output = Result<()>> + Send;
+
+    /// Usage grouped by API key hash, model and period, from `since` (local
+    /// time, `YYYY-MM-DD HH:MM:SS`) on. Periods are local too.
+    fn query_usage(
+        &self,
+        api_key_hash: Option<&str>,
+        since: &str,
+        group_by: GroupBy,
+    ) -> impl Future<Output = Result<Vec<UsageRow>>> + Send;
+
+    /// Up to `limit` logged requests with an ID above `after_id`, created at
+    /// or after `since` (UTC, `YYYY-MM-DD HH:MM:SS`, or empty for all),
+    /// oldest first. Exports page through the table by passing the last
+    /// row's ID back in.
+    fn export_requests(
+        &self,
+        api_key_hash: Option<&str>,
+        since: &str,
+        after_id: i64,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<ExportRow>>> + Send;
+
+    /// Tokens used today and this month (UTC) by every key with activity,
+    /// as `(api_key_hash, daily_tokens, monthly_tokens)`.
+    fn load_quota_baselines(&self) -> impl Future<Output = Result<Vec<(String, u64, u64)>>> + Send;
+
+    /// Tokens used today and this month (UTC) by one key.
+    fn load_quota_baseline_for_key(
+        &self,
+        key_hash: &str,
+    ) -> impl Future<Output = Result<(u64, u64)>> + Send;
+
+    /// Delete request logs older than `retention_days`; returns the number
+    /// of rows deleted.
+    fn cleanup_old_requests(&self, retention_days: u32)
+    -> impl Future<Output = Result<u64>> + Send;
+
+    /// Check connectivity; returns the server name and version.
+    fn health_check(&self) -> impl Future<Output = Result<String>> + Send;
+}
+
+/// The configured usage store.
+#[derive(Debug, Clone)]
+pub struct Database {
+    store: Store,
+}
+
+#[derive(Debug, Clone)]
+enum Store {
+    Sqlite(SqliteStore),
+    #[cfg(feature = "postgres")]
+    Postgres(PostgresStore),
+}
+
+/// Run `$call` on whichever store `$db` holds.
+macro_rules! dispatch {
+    ($db:expr, $store:ident => $call:expr) => {
+        match &$db.store {
+            Store::Sqlite($store) => $call,
+            #[cfg(feature = "postgres")]
+            Store::Postgres($store) => $call,
+        }
+    };
+}
+
+impl Database {
+    /// Open (or create) the SQLite database at `path`.
+    pub async fn open(path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            store: Store::Sqlite(SqliteStore::open(path).await?),
+        })
+    }
+
+    /// Open the SQLite database at `path` read-only (for CLI queries).
+    pub fn open_readonly(path: &str) -> Result<Self> {
+        Ok(Self {
+            store: Store::Sqlite(SqliteStore::open_readonly(path)?),
+        })
+    }
+
+    /// Open the store `log_requests` names, creating its table if needed.
+    pub async fn connect(config: &LogRequestsConfig) -> Result<Self> {
+        match config.backend {
+            LogRequestsBackend::Sqlite => Self::open(config.db_path.clone().into()).await,
+            #[cfg(feature = "postgres")]
+            LogRequestsBackend::Postgres => {
+                let url = config.postgres_url.as_deref().ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "log_requests.backend: postgres requires log_requests.postgres_url"
+                    )
+                })?;
+                Ok(Self {
+                    store: Store::Postgres(PostgresStore::connect(url).await?),
+                })
+            }
+            #[cfg(not(feature = "postgres"))]
+            LogRequestsBackend::Postgres => anyhow::bail!(
+                "log_requests.backend: postgres requires the 'postgres' feature. Rebuild with: cargo build --features postgres"
+            ),
+        }
+    }
+
+    pub async fn insert_request(&self, record: RequestRecord) -> Result<()> {
+        dispatch!(self, store => store.insert_request(record).await)
+    }
+
+    pub async fn query_usage(
+        &self,
+        api_key_hash: Option<&str>,
+        since: &str,
+        group_by: GroupBy,
+    ) -> Result<Vec<UsageRow>> {
+        dispatch!(self, store => store.query_usage(api_key_hash, since, group_by).await)
+    }
+
+    pub async fn export_requests(
+        &self,
+        api_key_hash: Option<&str>,
+        since: &str,
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<ExportRow>> {
+        dispatch!(self, store => store.export_requests(api_key_hash, since, after_id, limit).await)
+    }
+
+    pub async fn load_quota_baselines(&self) -> Result<Vec<(String, u64, u64)>> {
+        dispatch!(self, store => store.load_quota_baselines().await)
+    }
+
+    pub async fn load_quota_baseline_for_key(&self, key_hash: &str) -> Result<(u64, u64)> {
+        dispatch!(self, store => store.load_quota_baseline_for_key(key_hash).await)
+    }
+
+    pub async fn cleanup_old_requests(&self, retention_days: u32) -> Result<u64> {
+        dispatch!(self, store => store.cleanup_old_requests(retention_days).await)
+    }
+
+    pub async fn health_check(&self) -> Result<String> {
+        dispatch!(self, store => store.health_check().await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_database_open_and_migrate() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = Database::open(db_path).await.unwrap();
+        let health = db.health_check().await.unwrap();
+        assert!(health.starts_with("SQLite"));
+    }
+
+    #[tokio::test]
+    async fn test_multiple_inserts() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("test.db");
+        let db = Database::open(db_path).await.unwrap();
+
+        for i in 0..5 {
+            let record = RequestRecord {
+                correlation_id: format!("req-{i}"),
+                method: "POST".to_string(),
+                path: "/v1/chat/completions".to_string(),
+                model: "gpt-4o".to_string(),
+                provider: "default".to_string(),
+                duration_ms: 100.0 + i as f64,
+                response_status: 200,
+                streaming: false,
+                input_tokens: Some(50),
+                output_tokens: Some(25),
+                cache_read_tokens: None,
+                cache_write_tokens: None,
+                api_key_hash: Some("abc123def456".to_string()),
+                session_id: None,
+                attempts: Vec::new(),
+            };
+            db.insert_request(record).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_requests_pages_by_id_and_filters_by_key() {
+        let dir = TempDir::new().unwrap();
+        let db = Database::open(dir.path().join("test.db")).await.unwrap();
+        for i in 0..5 {
+            let key = if i % 2 == 0 { "even" } else { "odd" };
+            let record = RequestRecord::new(
+                "/v1/messages".to_string(),
+                "claude-sonnet-4-6".to_string(),
+                "default".to_string(),
+                std::time::Duration::from_millis(10),
+                200,
+                false,
+                &crate::proxy::TokenStats {
+                    input_tokens: Some(i),
+                    ..Default::default()
+                },
+                Some(key.to_string()),
+            );
+            db.insert_request(record).await.unwrap();
+        }
+
+        let first = db.export_requests(None, "", 0, 3).await.unwrap();
+        assert_eq!(first.len(), 3);
+        let rest = db.export_requests(None, "", first[2].id, 3).await.unwrap();
+        let inputs: Vec<_> = first.iter().chain(&rest).map(|r| r.input_tokens).collect();
+        assert_eq!(inputs, [0, 1, 2, 3, 4].map(Some));
+
+        let odd = db.export_requests(Some("odd"), "", 0, 10).await.unwrap();
+        assert_eq!(odd.len(), 2);
+        assert!(
+            db.export_requests(None, "2999-01-01 00:00:00", 0, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn request_records_come_from_the_attempt_summary() {
+        use crate::metrics::{RequestLabels, RequestSummary, TokenCounts, UpstreamAttempt};
+
+        let dir = TempDir::new().unwrap();
+        let db = Database::open(dir.path().join("test.db")).await.unwrap();
+        let labels = RequestLabels {
+            model: "claude-sonnet-4-6".to_string(),
+            family: crate::proxy::LlmFamily::Claude,
+            provider: "backup".to_string(),
+            route: "/v1/messages".to_string(),
+            stream: true,
+        };
+        let prior = [UpstreamAttempt {
+            provider: "default".to_string(),
+            status: 429,
+            latency_ms: 5,
+        }];
+        let summary = RequestSummary::new(labels, 200, std::time::Duration::from_millis(40))
+            .with_tokens(TokenCounts {
+                input: 12,
+                output: 3,
+                ..Default::default()
+            })
+            .with_session_id(Some("conv-1".to_string()))
+            .with_owner(Some("abc123".to_string()))
+            .with_prior_attempts(&prior);
+        db.insert_request(RequestRecord::from(&summary))
+            .await
+            .unwrap();
+
+        let rows = db.export_requests(None, "", 0, 10).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(row.path, "/v1/messages");
+        assert_eq!(row.provider, "backup");
+        assert_eq!(row.response_status, 200);
+        assert!(row.streaming);
+        assert_eq!(row.duration_ms, 40.0);
+        assert_eq!((row.input_tokens, row.output_tokens), (Some(12), Some(3)));
+        assert_eq!(row.cache_read_tokens, None);
+        assert_eq!(row.api_key_hash.as_deref(), Some("abc123"));
+        assert_eq!(row.session_id.as_deref(), Some("conv-1"));
+    }
+}
@@ -0,0 +1,448 @@
+//! PostgreSQL usage store, shared by several routers.
+//!
+//! Every router writes to the same `requests` table, so `acr usage`, usage
+//! exports and the quota baselines loaded at startup cover the whole fleet.
+//! Timestamps are stored in UTC like in SQLite. TLS is used when the server
+//! offers it, verified against the Mozilla root certificates; add
+//! `sslmode=require` to the URL to insist on it, or `sslmode=disable` for a
+//! server with a self-signed certificate on a private network.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDateTime, TimeZone, Utc};
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+
+use super::{ExportRow, GroupBy, RequestRecord, UsageRow, UsageStore};
+use crate::constants::usage_store::{POSTGRES_MIGRATION_LOCK, POSTGRES_POOL_SIZE};
+
+/// Format of the timestamps the store takes and returns.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// SQL expression for summing all token columns.
+const TOTAL_TOKENS_EXPR: &str = "COALESCE(SUM(COALESCE(input_tokens,0)+COALESCE(output_tokens,0)+COALESCE(cache_read_tokens,0)+COALESCE(cache_write_tokens,0)), 0)::BIGINT";
+
+/// SQL expression for the period of `created_at`, shifted to local time by
+/// `$2` seconds, the UTC offset at the start of the range. Weeks match
+/// SQLite's `%W`.
+fn period_expr(group_by: GroupBy) -> &'static str {
+    match group_by {
+        GroupBy::Day => "to_char(created_at + $2::INT * INTERVAL '1 second', 'YYYY-MM-DD')",
+        GroupBy::Week => {
+            "to_char(created_at + $2::INT * INTERVAL '1 second', 'YYYY') || '-W' || lpad(((EXTRACT(DOY FROM created_at + $2::INT * INTERVAL '1 second')::INT + 7 - EXTRACT(ISODOW FROM created_at + $2::INT * INTERVAL '1 second')::INT) / 7)::TEXT, 2, '0')"
+        }
+        GroupBy::Month => "to_char(created_at + $2::INT * INTERVAL '1 second', 'YYYY-MM')",
+    }
+}
+
+/// PostgreSQL database for persisting request logs, through a small
+/// connection pool.
+#[derive(Clone)]
+pub struct PostgresStore {
+    pool: Pool,
+}
+
+impl std::fmt::Debug for PostgresStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostgresStore").finish_non_exhaustive()
+    }
+}
+
+impl PostgresStore {
+    /// Connect to the database at `url` and create the `requests` table if
+    /// it doesn't exist.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let config: tokio_postgres::Config =
+            url.parse().context("Invalid log_requests.postgres_url")?;
+        let manager = Manager::from_config(
+            config,
+            tls()?,
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Fast,
+            },
+        );
+        let pool = Pool::builder(manager)
+            .max_size(POSTGRES_POOL_SIZE)
+            .build()
+            .context("Failed to create PostgreSQL pool")?;
+        let store = Self { pool };
+        store.migrate().await?;
+        Ok(store)
+    }
+
+    async fn client(&self) -> Result<deadpool_postgres::Object> {
+        self.pool
+            .get()
+            .await
+            .context("Failed to connect to PostgreSQL")
+    }
+
+    /// Routers starting together would race to create the table; the
+    /// advisory lock lets one of them do it.
+    async fn migrate(&self) -> Result<()> {
+        self.client()
+            .await?
+            .batch_execute(&format!(
+                "BEGIN;
+                 SET LOCAL client_min_messages = warning;
+                 SELECT pg_advisory_xact_lock({POSTGRES_MIGRATION_LOCK});
+                 CREATE TABLE IF NOT EXISTS requests (
+                    id BIGSERIAL PRIMARY KEY,
+                    correlation_id TEXT NOT NULL,
+                    method TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    model TEXT NOT NULL DEFAULT '',
+                    provider TEXT NOT NULL DEFAULT '',
+                    duration_ms DOUBLE PRECISION NOT NULL DEFAULT 0,
+                    response_status INTEGER NOT NULL DEFAULT 0,
+                    streaming BOOLEAN NOT NULL DEFAULT FALSE,
+                    input_tokens BIGINT,
+                    output_tokens BIGINT,
+                    cache_read_tokens BIGINT,
+                    cache_write_tokens BIGINT,
+                    api_key_hash TEXT,
+                    session_id TEXT,
+                    attempts TEXT,
+                    created_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'UTC')
+                 );
+                 CREATE INDEX IF NOT EXISTS idx_requests_correlation_id ON requests(correlation_id);
+                 CREATE INDEX IF NOT EXISTS idx_requests_api_key ON requests(api_key_hash);
+                 CREATE INDEX IF NOT EXISTS idx_requests_created_at ON requests(created_at);
+                 CREATE INDEX IF NOT EXISTS idx_requests_session_id ON requests(session_id);
+                 COMMIT;"
+            ))
+            .await
+            .context("Failed to run database migrations")
+    }
+
+    /// Total tokens per API key hash since `since` (UTC), for one key or
+    /// all keys.
+    async fn total_tokens_since(
+        &self,
+        since: NaiveDateTime,
+        key_hash: Option<&str>,
+    ) -> Result<Vec<(String, u64)>> {
+        let rows = self
+            .client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT api_key_hash, {TOTAL_TOKENS_EXPR}
+                     FROM requests
+                     WHERE created_at >= $1 AND api_key_hash IS NOT NULL
+                        AND ($2::TEXT IS NULL OR api_key_hash = $2)
+                     GROUP BY api_key_hash"
+                ),
+                &[&since, &key_hash],
+            )
+            .await
+            .context("Failed to query baselines")?;
+        Ok(rows
+            .iter()
+            .map(|row| (row.get(0), row.get::<_, i64>(1).max(0) as u64))
+            .collect())
+    }
+}
+
+impl UsageStore for PostgresStore {
+    async fn insert_request(&self, record: RequestRecord) -> Result<()> {
+        let attempts = (!record.attempts.is_empty())
+            .then(|| serde_json::to_string(&record.attempts))
+            .transpose()
+            .context("Failed to serialize request attempts")?;
+        let tokens = |t: Option<u64>| t.map(|t| t as i64);
+        self.client()
+            .await?
+            .execute(
+                "INSERT INTO requests (correlation_id, method, path, model, provider,
+                    duration_ms, response_status, streaming, input_tokens, output_tokens,
+                    cache_read_tokens, cache_write_tokens, api_key_hash, session_id, attempts)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+                &[
+                    &record.correlation_id,
+                    &record.method,
+                    &record.path,
+                    &record.model,
+                    &record.provider,
+                    &record.duration_ms,
+                    &i32::from(record.response_status),
+                    &record.streaming,
+                    &tokens(record.input_tokens),
+                    &tokens(record.output_tokens),
+                    &tokens(record.cache_read_tokens),
+                    &tokens(record.cache_write_tokens),
+                    &record.api_key_hash,
+                    &record.session_id,
+                    &attempts,
+                ],
+            )
+            .await
+            .context("Failed to insert request record")?;
+        Ok(())
+    }
+
+    async fn query_usage(
+        &self,
+        api_key_hash: Option<&str>,
+        since: &str,
+        group_by: GroupBy,
+    ) -> Result<Vec<UsageRow>> {
+        let since = NaiveDateTime::parse_from_str(since, TIMESTAMP_FORMAT)
+            .with_context(|| format!("Invalid usage start '{since}'"))?;
+        let since = Local
+            .from_local_datetime(&since)
+            .earliest()
+            .with_context(|| format!("Usage start {since} doesn't exist in local time"))?;
+        let offset_secs = since.offset().local_minus_utc();
+        let since = since.with_timezone(&Utc).naive_utc();
+
+        let sql = format!(
+            "SELECT COALESCE(api_key_hash, '') AS key_hash, model, {} AS period,
+                COALESCE(SUM(input_tokens), 0)::BIGINT,
+                COALESCE(SUM(output_tokens), 0)::BIGINT,
+                COALESCE(SUM(cache_read_tokens), 0)::BIGINT,
+                COALESCE(SUM(cache_write_tokens), 0)::BIGINT,
+                COUNT(*)
+             FROM requests
+             WHERE created_at >= $1 AND ($3::TEXT IS NULL OR api_key_hash = $3)
+             GROUP BY key_hash, model, period
+             ORDER BY period DESC, key_hash, model",
+            period_expr(group_by)
+        );
+        let rows = self
+            .client()
+            .await?
+            .query(&sql, &[&since, &offset_secs, &api_key_hash])
+            .await
+            .context("Failed to query usage")?;
+        let count = |row: &tokio_postgres::Row, i| row.get::<_, i64>(i).max(0) as u64;
+        Ok(rows
+            .iter()
+            .map(|row| UsageRow {
+                api_key_hash: row.get(0),
+                model: row.get(1),
+                period: row.get(2),
+                input_tokens: count(row, 3),
+                output_tokens: count(row, 4),
+                cache_read_tokens: count(row, 5),
+                cache_write_tokens: count(row, 6),
+                request_count: count(row, 7),
+            })
+            .collect())
+    }
+
+    async fn export_requests(
+        &self,
+        api_key_hash: Option<&str>,
+        since: &str,
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<ExportRow>> {
+        let since = (!since.is_empty())
+            .then(|| NaiveDateTime::parse_from_str(since, TIMESTAMP_FORMAT))
+            .transpose()
+            .with_context(|| format!("Invalid export start '{since}'"))?;
+        let rows = self
+            .client()
+            .await?
+            .query(
+                "SELECT id, to_char(created_at, 'YYYY-MM-DD HH24:MI:SS'), correlation_id, path,
+                    model, provider, duration_ms, response_status, streaming, input_tokens,
+                    output_tokens, cache_read_tokens, cache_write_tokens, api_key_hash,
+                    session_id
+                 FROM requests
+                 WHERE id > $1 AND ($2::TIMESTAMP IS NULL OR created_at >= $2)
+                    AND ($4::TEXT IS NULL OR api_key_hash = $4)
+                 ORDER BY id
+                 LIMIT $3",
+                &[&after_id, &since, &(limit as i64), &api_key_hash],
+            )
+            .await
+            .context("Failed to query requests for export")?;
+        let tokens =
+            |row: &tokio_postgres::Row, i| row.get::<_, Option<i64>>(i).map(|t| t.max(0) as u64);
+        Ok(rows
+            .iter()
+            .map(|row| ExportRow {
+                id: row.get(0),
+                created_at: row.get(1),
+                correlation_id: row.get(2),
+                path: row.get(3),
+                model: row.get(4),
+                provider: row.get(5),
+                duration_ms: row.get(6),
+                response_status: row.get::<_, i32>(7).clamp(0, u16::MAX as i32) as u16,
+                streaming: row.get(8),
+                input_tokens: tokens(row, 9),
+                output_tokens: tokens(row, 10),
+                cache_read_tokens: tokens(row, 11),
+                cache_write_tokens: tokens(row, 12),
+                api_key_hash: row.get(13),
+                session_id: row.get(14),
+            })
+            .collect())
+    }
+
+    async fn load_quota_baselines(&self) -> Result<Vec<(String, u64, u64)>> {
+        let (day_start, month_start) = quota_periods();
+        let daily = self.total_tokens_since(day_start, None).await?;
+        let monthly = self.total_tokens_since(month_start, None).await?;
+
+        let mut map: std::collections::HashMap<String, (u64, u64)> =
+            std::collections::HashMap::new();
+        for (key, tokens) in daily {
+            map.entry(key).or_insert((0, 0)).0 = tokens;
+        }
+        for (key, tokens) in monthly {
+            map.entry(key).or_insert((0, 0)).1 = tokens;
+        }
+        Ok(map
+            .into_iter()
+            .map(|(key, (daily, monthly))| (key, daily, monthly))
+            .collect())
+    }
+
+    async fn load_quota_baseline_for_key(&self, key_hash: &str) -> Result<(u64, u64)> {
+        let (day_start, month_start) = quota_periods();
+        let total = |rows: Vec<(String, u64)>| rows.first().map(|(_, v)| *v).unwrap_or(0);
+        let daily = total(self.total_tokens_since(day_start, Some(key_hash)).await?);
+        let monthly = total(self.total_tokens_since(month_start, Some(key_hash)).await?);
+        Ok((daily, monthly))
+    }
+
+    async fn cleanup_old_requests(&self, retention_days: u32) -> Result<u64> {
+        let days = i32::try_from(retention_days).unwrap_or(i32::MAX);
+        self.client()
+            .await?
+            .execute(
+                "DELETE FROM requests
+                 WHERE created_at < (now() AT TIME ZONE 'UTC') - make_interval(days => $1)",
+                &[&days],
+            )
+            .await
+            .context("Failed to delete old requests")
+    }
+
+    async fn health_check(&self) -> Result<String> {
+        let row = self
+            .client()
+            .await?
+            .query_one("SHOW server_version", &[])
+            .await
+            .context("Failed to query PostgreSQL version")?;
+        Ok(format!("PostgreSQL {}", row.get::<_, String>(0)))
+    }
+}
+
+/// Starts of today and of this month, UTC, the periods quotas count.
+fn quota_periods() -> (NaiveDateTime, NaiveDateTime) {
+    let today = Utc::now().date_naive();
+    (
+        today.and_time(chrono::NaiveTime::MIN),
+        crate::quota::start_of_month(today).and_time(chrono::NaiveTime::MIN),
+    )
+}
+
+/// TLS for connections, verified against the Mozilla root certificates.
+fn tls() -> Result<tokio_postgres_rustls::MakeRustlsConnect> {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .context("Failed to configure TLS")?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    Ok(tokio_postgres_rustls::MakeRustlsConnect::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs against the database in `ACR_TEST_POSTGRES_URL`, e.g.
+    /// `postgres://acr@localhost/acr_test`.
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server in ACR_TEST_POSTGRES_URL"]
+    async fn test_postgres_store_round_trip() {
+        let url = std::env::var("ACR_TEST_POSTGRES_URL").unwrap();
+        let store = PostgresStore::connect(&url).await.unwrap();
+        // Connecting again finds the table already there.
+        PostgresStore::connect(&url).await.unwrap();
+        assert!(
+            store
+                .health_check()
+                .await
+                .unwrap()
+                .starts_with("PostgreSQL")
+        );
+
+        let key = format!("test-{}", uuid::Uuid::new_v4());
+        for i in 0..3 {
+            let record = RequestRecord::new(
+                "/v1/messages".to_string(),
+                "claude-sonnet-4-6".to_string(),
+                "default".to_string(),
+                std::time::Duration::from_millis(10),
+                200,
+                i == 0,
+                &crate::proxy::TokenStats {
+                    input_tokens: Some(10),
+                    output_tokens: Some(i),
+                    ..Default::default()
+                },
+                Some(key.clone()),
+            );
+            store.insert_request(record).await.unwrap();
+        }
+
+        let today = format!("{} 00:00:00", Local::now().date_naive());
+        for group_by in [GroupBy::Day, GroupBy::Week, GroupBy::Month] {
+            let rows = store
+                .query_usage(Some(&key), &today, group_by)
+                .await
+                .unwrap();
+            assert_eq!(rows.len(), 1);
+            assert_eq!(rows[0].input_tokens, 30);
+            assert_eq!(rows[0].output_tokens, 3);
+            assert_eq!(rows[0].request_count, 3);
+        }
+        let day = &store
+            .query_usage(Some(&key), &today, GroupBy::Day)
+            .await
+            .unwrap()[0];
+        assert_eq!(day.period, Local::now().format("%Y-%m-%d").to_string());
+
+        let first = store.export_requests(Some(&key), "", 0, 2).await.unwrap();
+        assert_eq!(first.len(), 2);
+        assert!(first[0].streaming);
+        let rest = store
+            .export_requests(Some(&key), "", first[1].id, 2)
+            .await
+            .unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].output_tokens, Some(2));
+
+        assert_eq!(
+            store.load_quota_baseline_for_key(&key).await.unwrap(),
+            (33, 33)
+        );
+        assert!(
+            store
+                .load_quota_baselines()
+                .await
+                .unwrap()
+                .contains(&(key.clone(), 33, 33))
+        );
+        store.cleanup_old_requests(0).await.unwrap();
+        assert!(
+            store
+                .export_requests(Some(&key), "", 0, 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
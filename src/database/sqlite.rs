@@ -1,99 +1,34 @@
-//! SQLite-based request logging.
+//! SQLite usage store: one database file on the router's host.
 
 use anyhow::{Context, Result};
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use chrono::Local;
+use chrono::Utc;
 
-/// A request record to be persisted.
-#[derive(Debug, Clone)]
-pub struct RequestRecord {
-    pub correlation_id: String,
-    pub method: String,
-    pub path: String,
-    pub model: String,
-    pub provider: String,
-    pub duration_ms: f64,
-    pub response_status: u16,
-    pub streaming: bool,
-    pub input_tokens: Option<u64>,
-    pub output_tokens: Option<u64>,
-    pub cache_read_tokens: Option<u64>,
-    pub cache_write_tokens: Option<u64>,
-    pub api_key_hash: Option<String>,
-}
-
-impl RequestRecord {
-    /// Create a new request record with common fields populated.
-    #[allow(clippy::too_many_arguments)]
-    pub fn new(
-        path: String,
-        model: String,
-        provider: String,
-        duration: std::time::Duration,
-        response_status: u16,
-        streaming: bool,
-        token_stats: &crate::proxy::TokenStats,
-        api_key_hash: Option<String>,
-    ) -> Self {
-        Self {
-            correlation_id: uuid::Uuid::new_v4().to_string(),
-            method: "POST".to_string(),
-            path,
-            model,
-            provider,
-            duration_ms: duration.as_secs_f64() * 1000.0,
-            response_status,
-            streaming,
-            input_tokens: token_stats.input_tokens,
-            output_tokens: token_stats.output_tokens,
-            cache_read_tokens: token_stats.cache_read,
-            cache_write_tokens: token_stats.cache_write,
-            api_key_hash,
-        }
-    }
-}
-
-/// A usage row returned from aggregation queries.
-#[derive(Debug, Clone)]
-pub struct UsageRow {
-    pub api_key_hash: String,
-    pub model: String,
-    pub period: String,
-    pub input_tokens: u64,
-    pub output_tokens: u64,
-    pub cache_read_tokens: u64,
-    pub cache_write_tokens: u64,
-    pub request_count: u64,
-}
+use super::{ExportRow, GroupBy, RequestRecord, UsageRow, UsageStore};
 
-/// Time grouping for usage queries.
-#[derive(Debug, Clone, Copy)]
-pub enum GroupBy {
-    Day,
-    Week,
-    Month,
-}
+/// SQL expression for summing all token columns.
+const TOTAL_TOKENS_EXPR: &str = "COALESCE(SUM(COALESCE(input_tokens,0)+COALESCE(output_tokens,0)+COALESCE(cache_read_tokens,0)+COALESCE(cache_write_tokens,0)), 0)";
 
-impl GroupBy {
-    fn date_expr(&self) -> &'static str {
-        match self {
-            Self::Day => "date(created_at, 'localtime')",
-            Self::Week => "strftime('%Y-W%W', created_at, 'localtime')",
-            Self::Month => "strftime('%Y-%m', created_at, 'localtime')",
-        }
+/// SQL expression for the period of `created_at`, in local time.
+fn date_expr(group_by: GroupBy) -> &'static str {
+    match group_by {
+        GroupBy::Day => "date(created_at, 'localtime')",
+        GroupBy::Week => "strftime('%Y-W%W', created_at, 'localtime')",
+        GroupBy::Month => "strftime('%Y-%m', created_at, 'localtime')",
     }
 }
 
-/// SQLite database for persisting request logs.
+/// SQLite database for persisting request logs. Queries run on the
+/// blocking thread pool, one at a time.
 #[derive(Debug, Clone)]
-pub struct Database {
+pub struct SqliteStore {
     conn: Arc<Mutex<rusqlite::Connection>>,
 }
 
-impl Database {
+impl SqliteStore {
     /// Open (or create) the database at the given path and run migrations.
     pub async fn open(path: PathBuf) -> Result<Self> {
         // Ensure parent directory exists
@@ -154,19 +89,54 @@ impl Database {
             CREATE INDEX IF NOT EXISTS idx_requests_created_at ON requests(created_at);",
         )
         .context("Failed to run database migrations")?;
+
+        // Columns added after the initial schema. `CREATE TABLE IF NOT EXISTS`
+        // leaves existing databases untouched, so add them in place.
+        for column in ["session_id", "attempts"] {
+            let exists = conn
+                .prepare("SELECT 1 FROM pragma_table_info('requests') WHERE name = ?1")
+                .and_then(|mut stmt| stmt.exists([column]))
+                .context("Failed to inspect requests table")?;
+            if !exists {
+                conn.execute_batch(&format!("ALTER TABLE requests ADD COLUMN {column} TEXT;"))
+                    .with_context(|| format!("Failed to add {column} column"))?;
+            }
+        }
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_requests_session_id ON requests(session_id);",
+        )
+        .context("Failed to create session_id index")?;
         Ok(())
     }
 
-    /// Insert a request record. Runs on the blocking thread pool.
-    pub async fn insert_request(&self, record: RequestRecord) -> Result<()> {
+    /// Open the database in read-only mode (for CLI queries).
+    pub fn open_readonly(path: &str) -> Result<Self> {
+        let conn = rusqlite::Connection::open_with_flags(
+            path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .with_context(|| format!("Failed to open database: {path}"))?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+}
+
+impl UsageStore for SqliteStore {
+    async fn insert_request(&self, record: RequestRecord) -> Result<()> {
         let conn = self.conn.clone();
+        let attempts = (!record.attempts.is_empty())
+            .then(|| serde_json::to_string(&record.attempts))
+            .transpose()
+            .context("Failed to serialize request attempts")?;
         tokio::task::spawn_blocking(move || {
             let conn = conn.blocking_lock();
             conn.execute(
                 "INSERT INTO requests (correlation_id, method, path, model, provider,
                     duration_ms, response_status, streaming, input_tokens, output_tokens,
-                    cache_read_tokens, cache_write_tokens, api_key_hash)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                    cache_read_tokens, cache_write_tokens, api_key_hash, session_id, attempts)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
                 rusqlite::params![
                     record.correlation_id,
                     record.method,
@@ -181,6 +151,8 @@ impl Database {
                     record.cache_read_tokens.map(|t| t as i64),
                     record.cache_write_tokens.map(|t| t as i64),
                     record.api_key_hash,
+                    record.session_id,
+                    attempts,
                 ],
             )
             .context("Failed to insert request record")?;
@@ -192,21 +164,7 @@ impl Database {
         Ok(())
     }
 
-    /// Open the database in read-only mode (for CLI queries).
-    pub fn open_readonly(path: &str) -> Result<Self> {
-        let conn = rusqlite::Connection::open_with_flags(
-            path,
-            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
-        )
-        .with_context(|| format!("Failed to open database: {path}"))?;
-
-        Ok(Self {
-            conn: Arc::new(Mutex::new(conn)),
-        })
-    }
-
-    /// Query usage grouped by api_key_hash, model, and time period.
-    pub async fn query_usage(
+    async fn query_usage(
         &self,
         api_key_hash: Option<&str>,
         since: &str,
@@ -219,7 +177,7 @@ impl Database {
         tokio::task::spawn_blocking(move || {
             let conn = conn.blocking_lock();
 
-            let date_expr = group_by.date_expr();
+            let date_expr = date_expr(group_by);
             let key_clause = if api_key_hash.is_some() {
                 "AND api_key_hash = ?2"
             } else {
@@ -276,26 +234,88 @@ impl Database {
         .context("Usage query task panicked")?
     }
 
-    /// SQL expression for summing all token columns.
-    const TOTAL_TOKENS_EXPR: &'static str = "COALESCE(SUM(COALESCE(input_tokens,0)+COALESCE(output_tokens,0)+COALESCE(cache_read_tokens,0)+COALESCE(cache_write_tokens,0)), 0)";
+    async fn export_requests(
+        &self,
+        api_key_hash: Option<&str>,
+        since: &str,
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<ExportRow>> {
+        let conn = self.conn.clone();
+        let api_key_hash = api_key_hash.map(String::from);
+        let since = since.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            let key_clause = if api_key_hash.is_some() {
+                "AND api_key_hash = ?4"
+            } else {
+                ""
+            };
+            let sql = format!(
+                "SELECT id, created_at, correlation_id, path, model, provider, duration_ms,
+                    response_status, streaming, input_tokens, output_tokens,
+                    cache_read_tokens, cache_write_tokens, api_key_hash, session_id
+                 FROM requests
+                 WHERE id > ?1 AND created_at >= ?2 {key_clause}
+                 ORDER BY id
+                 LIMIT ?3"
+            );
+            let mut stmt = conn
+                .prepare(&sql)
+                .context("Failed to prepare export query")?;
+            let tokens = |row: &rusqlite::Row, i| -> rusqlite::Result<Option<u64>> {
+                Ok(row.get::<_, Option<i64>>(i)?.map(|t| t.max(0) as u64))
+            };
+            let row_mapper = |row: &rusqlite::Row| -> rusqlite::Result<ExportRow> {
+                Ok(ExportRow {
+                    id: row.get(0)?,
+                    created_at: row.get(1)?,
+                    correlation_id: row.get(2)?,
+                    path: row.get(3)?,
+                    model: row.get(4)?,
+                    provider: row.get(5)?,
+                    duration_ms: row.get(6)?,
+                    response_status: row.get(7)?,
+                    streaming: row.get::<_, i64>(8)? != 0,
+                    input_tokens: tokens(row, 9)?,
+                    output_tokens: tokens(row, 10)?,
+                    cache_read_tokens: tokens(row, 11)?,
+                    cache_write_tokens: tokens(row, 12)?,
+                    api_key_hash: row.get(13)?,
+                    session_id: row.get(14)?,
+                })
+            };
+            let limit = limit as i64;
+            let rows = if let Some(ref kh) = api_key_hash {
+                stmt.query_map(rusqlite::params![after_id, since, limit, kh], row_mapper)
+            } else {
+                stmt.query_map(rusqlite::params![after_id, since, limit], row_mapper)
+            }
+            .context("Failed to query requests for export")?;
+
+            let mut results = Vec::new();
+            for row in rows {
+                results.push(row.context("Failed to read request row")?);
+            }
+            Ok::<_, anyhow::Error>(results)
+        })
+        .await
+        .context("Export query task panicked")?
+    }
 
-    /// Load quota baselines by aggregating the requests table.
-    /// Returns Vec<(api_key_hash, daily_tokens, monthly_tokens)> for all keys with activity.
-    pub async fn load_quota_baselines(&self) -> Result<Vec<(String, u64, u64)>> {
+    async fn load_quota_baselines(&self) -> Result<Vec<(String, u64, u64)>> {
         let conn = self.conn.clone();
 
-        let now = Local::now();
-        let local_day_start = format!("{} 00:00:00", now.date_naive());
-        let local_month_start = format!(
-            "{} 00:00:00",
-            crate::quota::start_of_month(now.date_naive())
-        );
+        let today = Utc::now().date_naive();
+        let day_start = format!("{today} 00:00:00");
+        let month_start = format!("{} 00:00:00", crate::quota::start_of_month(today));
 
         tokio::task::spawn_blocking(move || {
             let conn = conn.blocking_lock();
 
-            let daily_rows = Self::query_total_tokens_since(&conn, &local_day_start, None)?;
-            let monthly_rows = Self::query_total_tokens_since(&conn, &local_month_start, None)?;
+            let daily_rows = query_total_tokens_since(&conn, &day_start, None)?;
+            let monthly_rows = query_total_tokens_since(&conn, &month_start, None)?;
 
             drop(conn);
 
@@ -320,26 +340,19 @@ impl Database {
         .context("Quota baselines load task panicked")?
     }
 
-    /// Load quota baseline for a single API key hash.
-    /// Returns (daily_tokens, monthly_tokens).
-    pub async fn load_quota_baseline_for_key(&self, key_hash: &str) -> Result<(u64, u64)> {
+    async fn load_quota_baseline_for_key(&self, key_hash: &str) -> Result<(u64, u64)> {
         let conn = self.conn.clone();
         let key_hash = key_hash.to_string();
 
-        let now = Local::now();
-        let local_day_start = format!("{} 00:00:00", now.date_naive());
-        let local_month_start = format!(
-            "{} 00:00:00",
-            crate::quota::start_of_month(now.date_naive())
-        );
+        let today = Utc::now().date_naive();
+        let day_start = format!("{today} 00:00:00");
+        let month_start = format!("{} 00:00:00", crate::quota::start_of_month(today));
 
         tokio::task::spawn_blocking(move || {
             let conn = conn.blocking_lock();
 
-            let daily_rows =
-                Self::query_total_tokens_since(&conn, &local_day_start, Some(&key_hash))?;
-            let monthly_rows =
-                Self::query_total_tokens_since(&conn, &local_month_start, Some(&key_hash))?;
+            let daily_rows = query_total_tokens_since(&conn, &day_start, Some(&key_hash))?;
+            let monthly_rows = query_total_tokens_since(&conn, &month_start, Some(&key_hash))?;
 
             let daily = daily_rows.first().map(|(_, v)| *v).unwrap_or(0);
             let monthly = monthly_rows.first().map(|(_, v)| *v).unwrap_or(0);
@@ -352,50 +365,7 @@ impl Database {
         .context("Single-key baseline query panicked")?
     }
 
-    /// Shared helper: query total tokens per api_key_hash since a given timestamp.
-    /// If `key_hash` is Some, filters to that specific key; otherwise returns all keys.
-    fn query_total_tokens_since(
-        conn: &rusqlite::Connection,
-        since: &str,
-        key_hash: Option<&str>,
-    ) -> Result<Vec<(String, u64)>> {
-        let key_clause = if key_hash.is_some() {
-            "AND api_key_hash = ?2"
-        } else {
-            "AND api_key_hash IS NOT NULL"
-        };
-        let sql = format!(
-            "SELECT api_key_hash, {}
-             FROM requests
-             WHERE created_at >= datetime(?1, 'utc') {key_clause}
-             GROUP BY api_key_hash",
-            Self::TOTAL_TOKENS_EXPR,
-        );
-
-        let mut stmt = conn
-            .prepare(&sql)
-            .context("Failed to prepare baseline query")?;
-        let row_mapper = |row: &rusqlite::Row| -> rusqlite::Result<(String, u64)> {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, i64>(1)?.max(0) as u64,
-            ))
-        };
-        let rows: Vec<(String, u64)> = if let Some(kh) = key_hash {
-            stmt.query_map(rusqlite::params![since, kh], row_mapper)
-        } else {
-            stmt.query_map(rusqlite::params![since], row_mapper)
-        }
-        .context("Failed to query baselines")?
-        .filter_map(|r| r.ok())
-        .collect();
-
-        Ok(rows)
-    }
-
-    /// Delete request logs older than the specified number of days.
-    /// Returns the number of rows deleted.
-    pub async fn cleanup_old_requests(&self, retention_days: u32) -> Result<u64> {
+    async fn cleanup_old_requests(&self, retention_days: u32) -> Result<u64> {
         let conn = self.conn.clone();
         tokio::task::spawn_blocking(move || {
             let conn = conn.blocking_lock();
@@ -412,8 +382,7 @@ impl Database {
         .context("Cleanup task panicked")?
     }
 
-    /// Check database connectivity.
-    pub async fn health_check(&self) -> Result<String> {
+    async fn health_check(&self) -> Result<String> {
         let conn = self.conn.clone();
         tokio::task::spawn_blocking(move || {
             let conn = conn.blocking_lock();
@@ -428,43 +397,117 @@ impl Database {
     }
 }
 
+/// Shared helper: query total tokens per api_key_hash since a given UTC timestamp.
+/// If `key_hash` is Some, filters to that specific key; otherwise returns all keys.
+fn query_total_tokens_since(
+    conn: &rusqlite::Connection,
+    since: &str,
+    key_hash: Option<&str>,
+) -> Result<Vec<(String, u64)>> {
+    let key_clause = if key_hash.is_some() {
+        "AND api_key_hash = ?2"
+    } else {
+        "AND api_key_hash IS NOT NULL"
+    };
+    let sql = format!(
+        "SELECT api_key_hash, {}
+         FROM requests
+         WHERE created_at >= datetime(?1) {key_clause}
+         GROUP BY api_key_hash",
+        TOTAL_TOKENS_EXPR,
+    );
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .context("Failed to prepare baseline query")?;
+    let row_mapper = |row: &rusqlite::Row| -> rusqlite::Result<(String, u64)> {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, i64>(1)?.max(0) as u64,
+        ))
+    };
+    let rows: Vec<(String, u64)> = if let Some(kh) = key_hash {
+        stmt.query_map(rusqlite::params![since, kh], row_mapper)
+    } else {
+        stmt.query_map(rusqlite::params![since], row_mapper)
+    }
+    .context("Failed to query baselines")?
+    .filter_map(|r| r.ok())
+    .collect();
+
+    Ok(rows)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::TempDir;
 
     #[tokio::test]
-    async fn test_database_open_and_migrate() {
+    async fn test_session_id_column_added_to_existing_database() {
         let dir = TempDir::new().unwrap();
-        let db_path = dir.path().join("test.db");
-        let db = Database::open(db_path).await.unwrap();
-        let health = db.health_check().await.unwrap();
-        assert!(health.starts_with("SQLite"));
-    }
+        let db_path = dir.path().join("old.db");
+        {
+            // Schema as created by releases before session tracking.
+            let conn = rusqlite::Connection::open(&db_path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE requests (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    correlation_id TEXT NOT NULL,
+                    method TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    model TEXT NOT NULL DEFAULT '',
+                    provider TEXT NOT NULL DEFAULT '',
+                    duration_ms REAL NOT NULL DEFAULT 0,
+                    response_status INTEGER NOT NULL DEFAULT 0,
+                    streaming INTEGER NOT NULL DEFAULT 0,
+                    input_tokens INTEGER,
+                    output_tokens INTEGER,
+                    cache_read_tokens INTEGER,
+                    cache_write_tokens INTEGER,
+                    api_key_hash TEXT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );",
+            )
+            .unwrap();
+        }
 
-    #[tokio::test]
-    async fn test_multiple_inserts() {
-        let dir = TempDir::new().unwrap();
-        let db_path = dir.path().join("test.db");
-        let db = Database::open(db_path).await.unwrap();
-
-        for i in 0..5 {
-            let record = RequestRecord {
-                correlation_id: format!("req-{i}"),
-                method: "POST".to_string(),
-                path: "/v1/chat/completions".to_string(),
-                model: "gpt-4o".to_string(),
+        let db = SqliteStore::open(db_path).await.unwrap();
+        let record = RequestRecord::new(
+            "/v1/messages".to_string(),
+            "claude-sonnet-4-5".to_string(),
+            "default".to_string(),
+            std::time::Duration::from_millis(5),
+            200,
+            false,
+            &crate::proxy::TokenStats::default(),
+            None,
+        )
+        .with_session_id(Some("conv-1".to_string()))
+        .with_attempts(vec![
+            crate::metrics::UpstreamAttempt {
+                provider: "primary".to_string(),
+                status: 429,
+                latency_ms: 12,
+            },
+            crate::metrics::UpstreamAttempt {
                 provider: "default".to_string(),
-                duration_ms: 100.0 + i as f64,
-                response_status: 200,
-                streaming: false,
-                input_tokens: Some(50),
-                output_tokens: Some(25),
-                cache_read_tokens: None,
-                cache_write_tokens: None,
-                api_key_hash: Some("abc123def456".to_string()),
-            };
-            db.insert_request(record).await.unwrap();
-        }
+                status: 200,
+                latency_ms: 5,
+            },
+        ]);
+        db.insert_request(record).await.unwrap();
+
+        let conn = db.conn.lock().await;
+        let (session_id, attempts): (String, String) = conn
+            .query_row("SELECT session_id, attempts FROM requests", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(session_id, "conv-1");
+        let attempts: Vec<crate::metrics::UpstreamAttempt> =
+            serde_json::from_str(&attempts).unwrap();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].status, 429);
     }
 }
@@ -0,0 +1,335 @@
+//! Dead-letter store for requests that failed upstream.
+//!
+//! When enabled, requests that end in a 5xx — an upstream server error, a
+//! timeout, or an unreachable provider — are written to `dead_letter.dir` as
+//! one JSON file each, with everything needed to run them through the proxy
+//! again: route, model, body and the handful of protocol headers that change
+//! upstream behavior. Credentials are never stored; the owning API key is
+//! recorded only as its hash. Entries are replayed through
+//! `POST /admin/dead-letters/{id}/replay` (or `acr replay <id>`, which calls
+//! it) once the upstream has recovered.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::DeadLetterConfig;
+use crate::constants::api::{ACR_PRIORITY_HEADER, ACR_SESSION_ID_HEADER, ANTHROPIC_BETA_HEADER};
+use crate::constants::dead_letter::ID_PREFIX;
+use crate::proxy::LlmFamily;
+
+/// Request headers acr itself acts on, kept for a faithful replay. Everything
+/// else — notably `authorization`, `x-api-key` and cookies — is dropped.
+const CAPTURED_HEADERS: &[&str] = &[
+    ANTHROPIC_BETA_HEADER,
+    ACR_SESSION_ID_HEADER,
+    ACR_PRIORITY_HEADER,
+];
+
+/// The proxy inputs of a failed request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedRequest {
+    pub path: String,
+    pub model: String,
+    pub action: Option<String>,
+    pub force_family: Option<LlmFamily>,
+    pub client_family: LlmFamily,
+    pub api_version: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    pub body: Value,
+}
+
+impl CapturedRequest {
+    /// Keep only the [`CAPTURED_HEADERS`] of a client request.
+    pub fn capture_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+        CAPTURED_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = headers.get(*name)?.to_str().ok()?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect()
+    }
+}
+
+/// One replay of a dead letter and its outcome.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayAttempt {
+    pub at: DateTime<Utc>,
+    pub status: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    /// Hash of the API key that sent the request (see `quota::hash_api_key`).
+    pub owner: Option<String>,
+    pub status: u16,
+    pub error: String,
+    pub request: CapturedRequest,
+    #[serde(default)]
+    pub replays: Vec<ReplayAttempt>,
+}
+
+impl DeadLetter {
+    pub fn new(
+        owner: Option<String>,
+        status: u16,
+        error: String,
+        request: CapturedRequest,
+    ) -> Self {
+        let created_at = Utc::now();
+        let suffix = uuid::Uuid::new_v4().simple().to_string();
+        Self {
+            // Timestamp first so file names sort oldest → newest.
+            id: format!(
+                "{ID_PREFIX}{}_{}",
+                created_at.format("%Y%m%dT%H%M%S"),
+                &suffix[..8]
+            ),
+            created_at,
+            owner,
+            status,
+            error,
+            request,
+            replays: Vec::new(),
+        }
+    }
+
+    /// Whether the last replay succeeded.
+    pub fn resolved(&self) -> bool {
+        self.replays
+            .last()
+            .is_some_and(|r| (200..300).contains(&r.status))
+    }
+}
+
+/// File-backed dead-letter store; cheap to clone.
+#[derive(Debug, Clone)]
+pub struct DeadLetterStore {
+    dir: PathBuf,
+    max_entries: usize,
+}
+
+impl DeadLetterStore {
+    /// Open the store, creating its directory. `None` when capture is disabled.
+    pub fn open(config: &DeadLetterConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        std::fs::create_dir_all(&config.dir)
+            .with_context(|| format!("Failed to create dead-letter directory {}", config.dir))?;
+        Ok(Some(Self {
+            dir: PathBuf::from(&config.dir),
+            max_entries: config.max_entries,
+        }))
+    }
+
+    /// Persist a new entry, pruning the oldest ones beyond `max_entries`.
+    pub async fn record(&self, entry: DeadLetter) -> Result<()> {
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || {
+            write_entry(&store.dir, &entry)?;
+            store.prune()
+        })
+        .await
+        .context("Dead-letter writer panicked")?
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<DeadLetter>> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+        let path = self.dir.join(format!("{id}.json"));
+        tokio::task::spawn_blocking(move || read_entry(&path))
+            .await
+            .context("Dead-letter reader panicked")?
+    }
+
+    /// All entries, newest first.
+    pub async fn list(&self) -> Result<Vec<DeadLetter>> {
+        let dir = self.dir.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut entries = Vec::new();
+            for path in entry_paths(&dir)?.into_iter().rev() {
+                match read_entry(&path) {
+                    Ok(Some(entry)) => entries.push(entry),
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Skipping unreadable dead letter: {:#}", e),
+                }
+            }
+            Ok(entries)
+        })
+        .await
+        .context("Dead-letter reader panicked")?
+    }
+
+    /// Append a replay outcome to an entry.
+    pub async fn record_replay(&self, id: &str, status: u16) -> Result<()> {
+        let Some(mut entry) = self.get(id).await? else {
+            return Ok(());
+        };
+        entry.replays.push(ReplayAttempt {
+            at: Utc::now(),
+            status,
+        });
+        let dir = self.dir.clone();
+        tokio::task::spawn_blocking(move || write_entry(&dir, &entry))
+            .await
+            .context("Dead-letter writer panicked")?
+    }
+
+    fn prune(&self) -> Result<()> {
+        let paths = entry_paths(&self.dir)?;
+        let excess = paths.len().saturating_sub(self.max_entries);
+        for path in &paths[..excess] {
+            if let Err(e) = std::fs::remove_file(path) {
+                tracing::warn!("Failed to prune dead letter {}: {}", path.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// IDs are generated by [`DeadLetter::new`]; anything else (in particular
+/// path separators) never names a stored entry.
+fn is_valid_id(id: &str) -> bool {
+    id.starts_with(ID_PREFIX)
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// Entry files in the store, oldest first.
+fn entry_paths(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.extension().is_some_and(|ext| ext == "json")
+                && p.file_stem()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(is_valid_id)
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+fn read_entry(path: &Path) -> Result<Option<DeadLetter>> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let entry = serde_json::from_slice(&data)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(entry))
+}
+
+fn write_entry(dir: &Path, entry: &DeadLetter) -> Result<()> {
+    let path = dir.join(format!("{}.json", entry.id));
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(entry)?)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, &path)
+        .with_context(|| format!("Failed to rename {} into place", tmp.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn store(dir: &Path, max_entries: usize) -> DeadLetterStore {
+        DeadLetterStore::open(&DeadLetterConfig {
+            enabled: true,
+            dir: dir.to_string_lossy().into_owned(),
+            max_entries,
+            unknown: Default::default(),
+        })
+        .unwrap()
+        .unwrap()
+    }
+
+    fn entry() -> DeadLetter {
+        DeadLetter::new(
+            Some("abc123".to_string()),
+            504,
+            "Upstream provider 'primary' timed out".to_string(),
+            CapturedRequest {
+                path: "/v1/messages".to_string(),
+                model: "claude-sonnet-4-5".to_string(),
+                action: None,
+                force_family: None,
+                client_family: LlmFamily::Claude,
+                api_version: None,
+                headers: BTreeMap::new(),
+                body: json!({"model": "claude-sonnet-4-5", "messages": []}),
+            },
+        )
+    }
+
+    #[test]
+    fn only_protocol_headers_are_captured() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", "secret".parse().unwrap());
+        headers.insert("authorization", "Bearer secret".parse().unwrap());
+        headers.insert(
+            ANTHROPIC_BETA_HEADER,
+            "context-1m-2025-08-07".parse().unwrap(),
+        );
+        let captured = CapturedRequest::capture_headers(&headers);
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[ANTHROPIC_BETA_HEADER], "context-1m-2025-08-07");
+    }
+
+    #[tokio::test]
+    async fn record_get_and_replay_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(dir.path(), 10);
+        let entry = entry();
+        let id = entry.id.clone();
+        store.record(entry).await.unwrap();
+
+        let loaded = store.get(&id).await.unwrap().unwrap();
+        assert_eq!(loaded.status, 504);
+        assert_eq!(loaded.request.client_family, LlmFamily::Claude);
+        assert!(!loaded.resolved());
+
+        store.record_replay(&id, 200).await.unwrap();
+        assert!(store.get(&id).await.unwrap().unwrap().resolved());
+
+        assert!(store.get("../../etc/passwd").await.unwrap().is_none());
+        assert!(store.get("dl_missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn oldest_entries_are_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(dir.path(), 2);
+        let mut ids = Vec::new();
+        for i in 0..3 {
+            let mut entry = entry();
+            // Distinct, ordered timestamps without sleeping.
+            entry.id = format!("{ID_PREFIX}2026010{i}T000000_0000000{i}");
+            ids.push(entry.id.clone());
+            store.record(entry).await.unwrap();
+        }
+        let listed: Vec<String> = store
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.id)
+            .collect();
+        assert_eq!(listed, vec![ids[2].clone(), ids[1].clone()]);
+    }
+}
@@ -0,0 +1,265 @@
+//! Deployment health tracking.
+//!
+//! When several providers serve a model, the load-balancing strategy treats
+//! them as equals. With `deployment_health.enabled` acr also remembers how
+//! each `(provider, deployment)` has been doing lately and puts the healthy
+//! ones first: a deployment whose recent requests failed with 5xx or timed
+//! out above `max_error_rate` goes to the back, and one that answers more
+//! than `slow_factor` times slower than the fastest alternative goes behind
+//! the others. Demoted deployments still serve when nothing better is left.
+//!
+//! Outcomes count for `window_secs`, so a deployment that stops failing is
+//! trusted again once its failures age out. Latency is smoothed per
+//! deployment, separately for streaming (time to first byte) and
+//! non-streaming (whole response) requests so the two aren't compared.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::DeploymentHealthConfig;
+use crate::constants::deployment_health::LATENCY_SMOOTHING;
+
+/// How a deployment has been doing lately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    /// Too few recent requests to tell, or nothing wrong.
+    Healthy,
+    /// Answering more than `slow_factor` times slower than the fastest
+    /// alternative.
+    Slow,
+    /// More than `max_error_rate` of recent requests failed.
+    Failing,
+}
+
+impl Health {
+    /// Sort key for provider ordering: healthy deployments first.
+    pub fn rank(&self) -> u8 {
+        match self {
+            Health::Healthy => 0,
+            Health::Slow => 1,
+            Health::Failing => 2,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    /// Outcomes inside the window: when, and whether it succeeded.
+    outcomes: VecDeque<(Instant, bool)>,
+    /// Smoothed latency in seconds: non-streaming, streaming.
+    latency: [Option<f64>; 2],
+}
+
+impl Stats {
+    fn expire(&mut self, now: Instant, window: Duration) {
+        while let Some(&(at, _)) = self.outcomes.front() {
+            if now.saturating_duration_since(at) < window {
+                break;
+            }
+            self.outcomes.pop_front();
+        }
+    }
+
+    fn failing(&self, min_requests: usize, max_error_rate: f64) -> bool {
+        let total = self.outcomes.len();
+        if total < min_requests.max(1) {
+            return false;
+        }
+        let failed = self.outcomes.iter().filter(|(_, ok)| !ok).count();
+        failed as f64 / total as f64 > max_error_rate
+    }
+}
+
+/// Recent outcomes and latency per `(provider, deployment)`.
+#[derive(Debug, Clone)]
+pub struct DeploymentHealth {
+    entries: Arc<Mutex<HashMap<(String, String), Stats>>>,
+    window: Duration,
+    min_requests: usize,
+    max_error_rate: f64,
+    slow_factor: f64,
+}
+
+impl DeploymentHealth {
+    /// `None` when deployment health is disabled.
+    pub fn from_config(config: &DeploymentHealthConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Self {
+            entries: Arc::default(),
+            window: Duration::from_secs(config.window_secs),
+            min_requests: config.min_requests,
+            max_error_rate: config.max_error_rate,
+            slow_factor: config.slow_factor,
+        })
+    }
+
+    /// Record a request the deployment answered, and how long it took.
+    pub fn record_success(&self, provider: &str, deployment: &str, stream: bool, took: Duration) {
+        self.record_success_at(provider, deployment, stream, took, Instant::now());
+    }
+
+    /// Record a 5xx, timeout or connection failure.
+    pub fn record_failure(&self, provider: &str, deployment: &str) {
+        self.record_failure_at(provider, deployment, Instant::now());
+    }
+
+    fn record_success_at(
+        &self,
+        provider: &str,
+        deployment: &str,
+        stream: bool,
+        took: Duration,
+        now: Instant,
+    ) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = entries
+            .entry((provider.to_string(), deployment.to_string()))
+            .or_default();
+        stats.expire(now, self.window);
+        stats.outcomes.push_back((now, true));
+        let sample = took.as_secs_f64();
+        let latency = &mut stats.latency[usize::from(stream)];
+        *latency = Some(match *latency {
+            Some(smoothed) => smoothed + LATENCY_SMOOTHING * (sample - smoothed),
+            None => sample,
+        });
+    }
+
+    fn record_failure_at(&self, provider: &str, deployment: &str, now: Instant) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = entries
+            .entry((provider.to_string(), deployment.to_string()))
+            .or_default();
+        stats.expire(now, self.window);
+        let was_failing = stats.failing(self.min_requests, self.max_error_rate);
+        stats.outcomes.push_back((now, false));
+        if !was_failing && stats.failing(self.min_requests, self.max_error_rate) {
+            tracing::warn!(
+                "Deployment '{}' on provider '{}' is failing, trying it last for the next {}s",
+                deployment,
+                provider,
+                self.window.as_secs()
+            );
+        }
+    }
+
+    /// The health of each `(provider, deployment)` candidate for a request,
+    /// in the order given. Latency is compared among the candidates only.
+    pub fn assess(&self, candidates: &[(&str, &str)], stream: bool) -> Vec<Health> {
+        self.assess_at(candidates, stream, Instant::now())
+    }
+
+    fn assess_at(&self, candidates: &[(&str, &str)], stream: bool, now: Instant) -> Vec<Health> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let observed: Vec<Option<(bool, Option<f64>)>> = candidates
+            .iter()
+            .map(|(provider, deployment)| {
+                let stats = entries.get_mut(&(provider.to_string(), deployment.to_string()))?;
+                stats.expire(now, self.window);
+                if stats.outcomes.len() < self.min_requests.max(1) {
+                    return None;
+                }
+                let failing = stats.failing(self.min_requests, self.max_error_rate);
+                Some((failing, stats.latency[usize::from(stream)]))
+            })
+            .collect();
+        let fastest = observed
+            .iter()
+            .filter_map(|o| match o {
+                Some((false, latency)) => *latency,
+                _ => None,
+            })
+            .reduce(f64::min);
+        observed
+            .into_iter()
+            .map(|o| match (o, fastest) {
+                (Some((true, _)), _) => Health::Failing,
+                (Some((false, Some(latency))), Some(fastest))
+                    if self.slow_factor > 0.0 && latency > fastest * self.slow_factor =>
+                {
+                    Health::Slow
+                }
+                _ => Health::Healthy,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health() -> DeploymentHealth {
+        DeploymentHealth::from_config(&DeploymentHealthConfig {
+            enabled: true,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        assert!(DeploymentHealth::from_config(&DeploymentHealthConfig::default()).is_none());
+    }
+
+    #[test]
+    fn failures_demote_a_deployment_until_they_age_out() {
+        let health = health();
+        let now = Instant::now();
+        let ok = Duration::from_millis(400);
+        for _ in 0..4 {
+            health.record_success_at("eu", "d1", false, ok, now);
+            health.record_failure_at("us", "d2", now);
+        }
+        let candidates = [("eu", "d1"), ("us", "d2")];
+        // Below min_requests nothing is judged yet.
+        assert_eq!(
+            health.assess_at(&candidates, false, now),
+            [Health::Healthy, Health::Healthy]
+        );
+        health.record_success_at("eu", "d1", false, ok, now);
+        health.record_failure_at("us", "d2", now);
+        assert_eq!(
+            health.assess_at(&candidates, false, now),
+            [Health::Healthy, Health::Failing]
+        );
+
+        let later = now + Duration::from_secs(120);
+        assert_eq!(
+            health.assess_at(&candidates, false, later),
+            [Health::Healthy, Health::Healthy]
+        );
+    }
+
+    #[test]
+    fn slow_deployments_are_compared_per_request_kind() {
+        let health = health();
+        let now = Instant::now();
+        for _ in 0..5 {
+            health.record_success_at("eu", "d1", false, Duration::from_secs(2), now);
+            health.record_success_at("us", "d2", false, Duration::from_secs(5), now);
+            health.record_success_at("us", "d2", true, Duration::from_millis(300), now);
+        }
+        let candidates = [("eu", "d1"), ("us", "d2"), ("ap", "d3")];
+        assert_eq!(
+            health.assess_at(&candidates, false, now),
+            [Health::Healthy, Health::Slow, Health::Healthy]
+        );
+        // No streaming latency for eu yet: nothing to compare us against.
+        assert_eq!(
+            health.assess_at(&candidates, true, now),
+            [Health::Healthy, Health::Healthy, Health::Healthy]
+        );
+        // A slow deployment catches up as its latency improves.
+        for _ in 0..10 {
+            health.record_success_at("us", "d2", false, Duration::from_secs(2), now);
+        }
+        assert_eq!(
+            health.assess_at(&candidates, false, now)[1],
+            Health::Healthy
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Model deprecation notices.
+//!
+//! A model with a `deprecation` block in config is still served, but every
+//! response for it carries `Deprecation` (RFC 9745) and `Sunset` (RFC 8594)
+//! headers plus a `Warning` naming the replacement, so clients and their
+//! operators notice before the model goes away. With `rewrite_after_sunset`,
+//! requests that arrive after the sunset date are sent to the replacement
+//! instead of failing once the deployment is removed.
+
+use axum::http::{HeaderMap, HeaderValue};
+use chrono::NaiveDate;
+
+use crate::config::ModelDeprecation;
+use crate::registry::ModelRegistry;
+
+/// Deprecation status of the model a request resolved to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeprecationNotice {
+    /// The configured (deprecated) model.
+    pub model: String,
+    pub since: Option<NaiveDate>,
+    pub sunset: Option<NaiveDate>,
+    pub replacement: Option<String>,
+    /// The sunset has passed and the request goes to `replacement`.
+    pub rewritten: bool,
+}
+
+impl DeprecationNotice {
+    /// The notice for a request for `requested` on `today`, when the model it
+    /// resolves to (by name or alias) is marked deprecated.
+    pub fn check(registry: &ModelRegistry, requested: &str, today: NaiveDate) -> Option<Self> {
+        let model = crate::proxy::normalize_model(requested, registry).ok()?;
+        let deprecation = registry.find_model_config(&model)?.deprecation.as_ref()?;
+        Some(Self::new(model, deprecation, today))
+    }
+
+    fn new(model: String, deprecation: &ModelDeprecation, today: NaiveDate) -> Self {
+        let rewritten = deprecation.rewrite_after_sunset
+            && deprecation.replacement.is_some()
+            && deprecation.sunset.is_some_and(|sunset| sunset <= today);
+        Self {
+            model,
+            since: deprecation.since,
+            sunset: deprecation.sunset,
+            replacement: deprecation.replacement.clone(),
+            rewritten,
+        }
+    }
+
+    /// The model to send the request to instead, once rewriting applies.
+    pub fn rewrite_to(&self) -> Option<&str> {
+        self.replacement.as_deref().filter(|_| self.rewritten)
+    }
+
+    /// Human-readable notice for the `Warning` header and logs.
+    pub fn message(&self) -> String {
+        let sunset = self
+            .sunset
+            .map(|date| format!(" on {date}"))
+            .unwrap_or_default();
+        match (&self.replacement, self.rewritten) {
+            (Some(replacement), true) => format!(
+                "Model '{}' was retired{}; request served by '{}'",
+                self.model, sunset, replacement
+            ),
+            (Some(replacement), false) => format!(
+                "Model '{}' is deprecated and will be retired{}; use '{}' instead",
+                self.model, sunset, replacement
+            ),
+            (None, _) => format!(
+                "Model '{}' is deprecated and will be retired{}",
+                self.model, sunset
+            ),
+        }
+    }
+
+    /// Add `Deprecation`, `Sunset` and `Warning` to a response.
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        // RFC 9745 wants `@<epoch seconds>`; without a date, the draft's
+        // `true` still tells clients the model is deprecated.
+        let deprecation = match self.since {
+            Some(date) => format!("@{}", midnight_utc(date).timestamp()),
+            None => "true".to_string(),
+        };
+        if let Ok(value) = HeaderValue::from_str(&deprecation) {
+            headers.insert("deprecation", value);
+        }
+        if let Some(date) = self.sunset
+            && let Ok(value) = HeaderValue::from_str(
+                &midnight_utc(date)
+                    .format("%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string(),
+            )
+        {
+            headers.insert("sunset", value);
+        }
+        if let Ok(value) = HeaderValue::from_str(&format!("299 acr \"{}\"", self.message())) {
+            headers.insert("warning", value);
+        }
+    }
+}
+
+fn midnight_utc(date: NaiveDate) -> chrono::DateTime<chrono::Utc> {
+    date.and_time(chrono::NaiveTime::MIN).and_utc()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FallbackModels, Model};
+    use crate::token::TokenManager;
+
+    fn registry_with(deprecation: ModelDeprecation) -> ModelRegistry {
+        let models = vec![
+            Model {
+                name: "claude-sonnet-4-5".to_string(),
+                aicore_model_name: None,
+                aliases: vec!["claude-sonnet-4-5-*".to_string()],
+                pricing: None,
+                default_max_tokens: None,
+                deprecation: Some(deprecation),
+                safety_settings: vec![],
+                url_template: None,
+                disabled: None,
+            },
+            Model {
+                name: "claude-sonnet-4-6".to_string(),
+                aicore_model_name: None,
+                aliases: vec![],
+                pricing: None,
+                default_max_tokens: None,
+                deprecation: None,
+                safety_settings: vec![],
+                url_template: None,
+                disabled: None,
+            },
+        ];
+        ModelRegistry::new(
+            models,
+            FallbackModels::default(),
+            vec![],
+            TokenManager::new(vec![]),
+            300,
+        )
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn deprecated_models_carry_notice_headers() {
+        let registry = registry_with(ModelDeprecation {
+            since: Some(date("2026-06-01")),
+            sunset: Some(date("2026-12-01")),
+            replacement: Some("claude-sonnet-4-6".to_string()),
+            rewrite_after_sunset: true,
+        });
+        assert!(
+            DeprecationNotice::check(&registry, "claude-sonnet-4-6", date("2026-07-01")).is_none()
+        );
+
+        // Aliases resolve to the deprecated model too.
+        let notice =
+            DeprecationNotice::check(&registry, "claude-sonnet-4-5-20250929", date("2026-07-01"))
+                .unwrap();
+        assert_eq!(notice.rewrite_to(), None);
+        let mut headers = HeaderMap::new();
+        notice.insert_headers(&mut headers);
+        assert_eq!(headers["deprecation"], "@1780272000");
+        assert_eq!(headers["sunset"], "Tue, 01 Dec 2026 00:00:00 GMT");
+        assert_eq!(
+            headers["warning"],
+            "299 acr \"Model 'claude-sonnet-4-5' is deprecated and will be retired on 2026-12-01; use 'claude-sonnet-4-6' instead\""
+        );
+    }
+
+    #[test]
+    fn requests_are_rewritten_only_after_sunset_when_enabled() {
+        let deprecation = ModelDeprecation {
+            since: None,
+            sunset: Some(date("2026-12-01")),
+            replacement: Some("claude-sonnet-4-6".to_string()),
+            rewrite_after_sunset: true,
+        };
+        let registry = registry_with(deprecation.clone());
+        let before = DeprecationNotice::check(&registry, "claude-sonnet-4-5", date("2026-11-30"));
+        assert_eq!(before.unwrap().rewrite_to(), None);
+        let after =
+            DeprecationNotice::check(&registry, "claude-sonnet-4-5", date("2026-12-01")).unwrap();
+        assert_eq!(after.rewrite_to(), Some("claude-sonnet-4-6"));
+        let mut headers = HeaderMap::new();
+        after.insert_headers(&mut headers);
+        assert_eq!(headers["deprecation"], "true");
+        assert!(
+            headers["warning"]
+                .to_str()
+                .unwrap()
+                .contains("request served by 'claude-sonnet-4-6'")
+        );
+
+        // Without the opt-in, a retired model is still requested as-is.
+        let registry = registry_with(ModelDeprecation {
+            rewrite_after_sunset: false,
+            ..deprecation
+        });
+        let notice =
+            DeprecationNotice::check(&registry, "claude-sonnet-4-5", date("2027-01-01")).unwrap();
+        assert_eq!(notice.rewrite_to(), None);
+    }
+}
@@ -0,0 +1,161 @@
+//! Golden-file tests for request preparation.
+//!
+//! Each case in `tests/fixtures/transforms/` is a pair of files:
+//! `<case>.input.json` holds a client request and how it arrived (client
+//! family, model, URL action), and `<case>.expected.json` holds the upstream
+//! request acr prepares from it: upstream family, stream flag, URL, dropped
+//! parameters and body. Cases go through the steps of
+//! `ProxyRequestBuilder::build_for_provider` that don't need credentials or
+//! live deployments: family detection, cross-family translation,
+//! `prepare_body` and URL building.
+//!
+//! Run with `UPDATE_GOLDEN=1` to rewrite the expected files from the current
+//! output, then review the diff.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::config::ReasoningContent;
+use crate::constants::api::DEFAULT_API_VERSION;
+use crate::proxy::{LlmFamily, build_url, determine_family, extract_stream_flag, prepare_body};
+use crate::transforms::translate::Translation;
+
+const FIXTURES_DIR: &str = "tests/fixtures/transforms";
+const DEPLOYMENT_ID: &str = "d0123456789abcdef";
+const BASE_URL: &str = "https://api.ai.example.com";
+const DEFAULT_MAX_TOKENS: u64 = crate::constants::api::ANTHROPIC_DEFAULT_MAX_TOKENS;
+
+#[derive(Debug, Deserialize)]
+struct Case {
+    /// API shape the client used
+    client_family: LlmFamily,
+    model: String,
+    /// Set by routes tied to one upstream shape, like `/v1/responses`
+    #[serde(default)]
+    force_family: Option<LlmFamily>,
+    /// URL action, e.g. `generateContent`
+    #[serde(default)]
+    action: Option<String>,
+    #[serde(default)]
+    reasoning: ReasoningContent,
+    body: Value,
+}
+
+/// The upstream request for `case`, as stored in the expected file.
+fn prepare(case: Case) -> anyhow::Result<Value> {
+    let family = match case.force_family {
+        Some(family) => family,
+        None => determine_family(&case.model, &[])?,
+    };
+    let translation = Translation::select(case.client_family, family, &case.body, case.reasoning);
+    let (stream, action) = match translation {
+        Some(_) => {
+            let stream = extract_stream_flag(&case.body, &case.client_family, &case.action);
+            let action = match family {
+                LlmFamily::Gemini => Some(
+                    if stream {
+                        "streamGenerateContent"
+                    } else {
+                        "generateContent"
+                    }
+                    .to_string(),
+                ),
+                _ => case.action.clone(),
+            };
+            (stream, action)
+        }
+        None => (
+            extract_stream_flag(&case.body, &family, &case.action),
+            case.action.clone(),
+        ),
+    };
+
+    let mut body = case.body;
+    let dropped_params = match translation {
+        Some(translation) => translation.request(&mut body)?,
+        None => Vec::new(),
+    };
+    prepare_body(
+        &mut body,
+        &family,
+        stream,
+        &case.model,
+        &case.model,
+        &action,
+        DEFAULT_MAX_TOKENS,
+    )?;
+    let url = build_url(
+        &case.model,
+        DEPLOYMENT_ID,
+        &action,
+        BASE_URL,
+        &family,
+        stream,
+        DEFAULT_API_VERSION,
+    )?;
+
+    Ok(json!({
+        "family": family,
+        "stream": stream,
+        "url": url,
+        "dropped_params": dropped_params,
+        "body": body,
+    }))
+}
+
+fn read_json(path: &Path) -> Value {
+    let content = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {e}", path.display()));
+    serde_json::from_str(&content)
+        .unwrap_or_else(|e| panic!("{} is not valid JSON: {e}", path.display()))
+}
+
+fn input_files() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURES_DIR);
+    let mut inputs: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("Failed to list {}: {e}", dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_string_lossy().ends_with(".input.json"))
+        .collect();
+    inputs.sort();
+    inputs
+}
+
+#[test]
+fn prepared_requests_match_golden_files() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let inputs = input_files();
+    assert!(!inputs.is_empty(), "no fixtures in {FIXTURES_DIR}");
+
+    let mut failures = Vec::new();
+    for input in inputs {
+        let name = input.to_string_lossy().replace(".input.json", "");
+        let expected_path = PathBuf::from(format!("{name}.expected.json"));
+        let case: Case = serde_json::from_value(read_json(&input))
+            .unwrap_or_else(|e| panic!("{}: {e}", input.display()));
+        let actual = prepare(case).unwrap_or_else(|e| json!({ "error": format!("{e:#}") }));
+
+        if update {
+            let pretty = serde_json::to_string_pretty(&actual).unwrap();
+            std::fs::write(&expected_path, format!("{pretty}\n")).unwrap();
+            continue;
+        }
+        if !expected_path.exists() {
+            failures.push(format!(
+                "{}: missing (run with UPDATE_GOLDEN=1)",
+                expected_path.display()
+            ));
+            continue;
+        }
+        if read_json(&expected_path) != actual {
+            failures.push(format!(
+                "{}: prepared request differs\n{}",
+                expected_path.display(),
+                serde_json::to_string_pretty(&actual).unwrap()
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
@@ -0,0 +1,354 @@
+//! Signed (HMAC) request authentication.
+//!
+//! Some environments don't allow long-lived bearer keys. A client configured
+//! under `hmac_auth.clients` signs each request instead, with three headers:
+//!
+//! - `x-acr-key-id`: the client's `id`
+//! - `x-acr-timestamp`: Unix time in seconds
+//! - `x-acr-signature`: hex HMAC-SHA256, keyed with the client's `secret`,
+//!   of `{timestamp}\n{METHOD}\n{path and query}\n{hex SHA-256 of the body}`,
+//!   the path as called, `mount.path_prefix` included
+//!
+//! A valid signature stands in for the API key the client is mapped to: the
+//! request continues as if it carried that key, so the key's quotas, tenant
+//! and model allowlist apply. A timestamp more than `max_skew_secs` from the
+//! server clock is rejected, and so is a signature already used within that
+//! window, so a captured request can't be replayed.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, OriginalUri, Request, State},
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::config::HmacAuthConfig;
+use crate::constants::api::{ACR_KEY_ID_HEADER, ACR_SIGNATURE_HEADER, ACR_TIMESTAMP_HEADER};
+use crate::routes::{AppError, AppState};
+
+const BLOCK_SIZE: usize = 64;
+
+/// HMAC-SHA256 (RFC 2104).
+pub fn hmac_sha256(secret: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; BLOCK_SIZE];
+    if secret.len() > BLOCK_SIZE {
+        key[..32].copy_from_slice(&Sha256::digest(secret));
+    } else {
+        key[..secret.len()].copy_from_slice(secret);
+    }
+    let pad = |byte: u8| key.map(|k| k ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The string a client signs.
+pub fn string_to_sign(timestamp: &str, method: &str, path_and_query: &str, body: &[u8]) -> String {
+    format!(
+        "{timestamp}\n{method}\n{path_and_query}\n{}",
+        hex(&Sha256::digest(body))
+    )
+}
+
+/// The hex signature of a request.
+pub fn sign(
+    secret: &str,
+    timestamp: &str,
+    method: &str,
+    path_and_query: &str,
+    body: &[u8],
+) -> String {
+    let message = string_to_sign(timestamp, method, path_and_query, body);
+    hex(&hmac_sha256(secret.as_bytes(), message.as_bytes()))
+}
+
+struct Client {
+    secret: String,
+    api_key: String,
+}
+
+/// Configured signing clients and recently seen signatures; cheap to clone.
+#[derive(Clone)]
+pub struct HmacAuth {
+    clients: Arc<HashMap<String, Client>>,
+    max_skew_secs: i64,
+    /// Signatures accepted within the replay window, with their timestamps.
+    seen: Arc<Mutex<HashMap<String, i64>>>,
+}
+
+impl HmacAuth {
+    /// `None` when no client is configured.
+    pub fn from_config(config: &HmacAuthConfig) -> Option<Self> {
+        if config.clients.is_empty() {
+            return None;
+        }
+        let clients = config
+            .clients
+            .iter()
+            .map(|c| {
+                (
+                    c.id.clone(),
+                    Client {
+                        secret: c.secret.clone(),
+                        api_key: c.api_key.clone(),
+                    },
+                )
+            })
+            .collect();
+        Some(Self {
+            clients: Arc::new(clients),
+            max_skew_secs: config.max_skew_secs as i64,
+            seen: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Check a signed request, returning the API key it stands in for.
+    pub fn verify(
+        &self,
+        headers: &HeaderMap,
+        method: &str,
+        path_and_query: &str,
+        body: &[u8],
+        now: i64,
+    ) -> Result<&str, &'static str> {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        let (Some(id), Some(timestamp), Some(signature)) = (
+            header(ACR_KEY_ID_HEADER),
+            header(ACR_TIMESTAMP_HEADER),
+            header(ACR_SIGNATURE_HEADER),
+        ) else {
+            return Err("x-acr-key-id, x-acr-timestamp and x-acr-signature are all required");
+        };
+        let client = self.clients.get(id).ok_or("unknown key ID")?;
+        let signed_at: i64 = timestamp.parse().map_err(|_| "malformed timestamp")?;
+        if (now - signed_at).abs() > self.max_skew_secs {
+            return Err("timestamp outside the allowed window");
+        }
+        let expected = sign(&client.secret, timestamp, method, path_and_query, body);
+        if !bool::from(
+            expected
+                .as_bytes()
+                .ct_eq(signature.to_ascii_lowercase().as_bytes()),
+        ) {
+            return Err("signature mismatch");
+        }
+        let mut seen = self.seen.lock().map_err(|_| "replay cache unavailable")?;
+        seen.retain(|_, at| (now - *at).abs() <= self.max_skew_secs);
+        if seen.insert(expected, signed_at).is_some() {
+            return Err("signature already used");
+        }
+        Ok(&client.api_key)
+    }
+}
+
+/// Middleware authenticating signed requests. Requests without
+/// `x-acr-signature` pass through to the usual API key check.
+pub async fn authenticate(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(ref hmac) = state.hmac_auth else {
+        return next.run(request).await;
+    };
+    if !request.headers().contains_key(ACR_SIGNATURE_HEADER) {
+        return next.run(request).await;
+    }
+
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<std::net::SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string());
+    if let Some(ref ip) = client_ip
+        && let Some(remaining) = state.rate_limiter.is_rate_limited(ip).await
+    {
+        return AppError::RateLimitedAuth {
+            retry_after_secs: remaining.as_secs(),
+        }
+        .into_response();
+    }
+
+    let max_body_bytes = state.config.max_request_body_bytes();
+    let (mut parts, body) = request.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, max_body_bytes).await else {
+        return crate::body_limit::too_large(max_body_bytes);
+    };
+    // Under `mount.path_prefix` the route sees the path without the prefix;
+    // the client signed the one it called.
+    let uri = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map_or(&parts.uri, |OriginalUri(uri)| uri)
+        .clone();
+    let path_and_query = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let now = chrono::Utc::now().timestamp();
+    let api_key = match hmac.verify(
+        &parts.headers,
+        parts.method.as_str(),
+        path_and_query,
+        &bytes,
+        now,
+    ) {
+        Ok(api_key) => api_key,
+        Err(reason) => {
+            tracing::warn!(
+                "Rejected signed {} {}: {}",
+                parts.method,
+                uri.path(),
+                reason
+            );
+            if let Some(ref ip) = client_ip {
+                state.rate_limiter.record_failure(ip).await;
+            }
+            return AppError::InvalidSignature(reason.to_string()).into_response();
+        }
+    };
+
+    // Any key the client also sent must not win over the signed identity.
+    for name in ["api-key", "x-api-key", "x-goog-api-key"] {
+        parts.headers.remove(name);
+    }
+    match HeaderValue::from_str(&format!("Bearer {api_key}")) {
+        Ok(value) => {
+            parts.headers.insert("authorization", value);
+        }
+        Err(e) => return AppError::Internal(e.into()).into_response(),
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HmacClient;
+
+    #[test]
+    fn hmac_matches_rfc_4231_test_case_2() {
+        assert_eq!(
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn verifies_signatures_within_the_window_once() {
+        let hmac = HmacAuth::from_config(&HmacAuthConfig {
+            clients: vec![HmacClient {
+                id: "ci".to_string(),
+                secret: "s3cret".to_string(),
+                api_key: "team-key".to_string(),
+            }],
+            ..Default::default()
+        })
+        .unwrap();
+        let body = br#"{"model":"gpt-4o"}"#;
+        let headers = |timestamp: i64, signature: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(ACR_KEY_ID_HEADER, HeaderValue::from_static("ci"));
+            headers.insert(ACR_TIMESTAMP_HEADER, timestamp.to_string().parse().unwrap());
+            headers.insert(ACR_SIGNATURE_HEADER, signature.parse().unwrap());
+            headers
+        };
+        let now = 1_760_000_000;
+        let signature = sign(
+            "s3cret",
+            &now.to_string(),
+            "POST",
+            "/v1/chat/completions",
+            body,
+        );
+        let signed = headers(now, &signature);
+
+        assert_eq!(
+            hmac.verify(&signed, "POST", "/v1/chat/completions", body, now + 10),
+            Ok("team-key")
+        );
+        assert_eq!(
+            hmac.verify(&signed, "POST", "/v1/chat/completions", body, now + 20),
+            Err("signature already used")
+        );
+        assert_eq!(
+            hmac.verify(&signed, "POST", "/v1/embeddings", body, now),
+            Err("signature mismatch")
+        );
+        let stale = headers(now - 301, &signature);
+        assert_eq!(
+            hmac.verify(&stale, "POST", "/v1/chat/completions", body, now),
+            Err("timestamp outside the allowed window")
+        );
+    }
+
+    #[tokio::test]
+    async fn signatures_cover_the_mount_prefix() {
+        use tower::ServiceExt;
+
+        let config: crate::config::Config = serde_yaml_ng::from_str(
+            r#"
+providers:
+  - name: primary
+    uaa_token_url: http://127.0.0.1:9/oauth/token
+    uaa_client_id: id
+    uaa_client_secret: secret
+    genai_api_url: http://127.0.0.1:9
+api_keys:
+  - key: team-key
+    allowed_models: [gpt-4.1-mini]
+hmac_auth:
+  clients:
+    - id: ci
+      secret: s3cret
+      api_key: team-key
+mount:
+  path_prefix: /llm
+"#,
+        )
+        .unwrap();
+        let mut state = crate::routes::state_for_tests(config);
+        state.hmac_auth = HmacAuth::from_config(&state.config.hmac_auth);
+        let router = crate::routes::create_router(state);
+
+        let body = r#"{"model":"gpt-4o","messages":[]}"#;
+        let now = chrono::Utc::now().timestamp().to_string();
+        let signed_status = |signed_path: &str| {
+            let signature = sign("s3cret", &now, "POST", signed_path, body.as_bytes());
+            let mut request = Request::post("/llm/v1/chat/completions")
+                .header("content-type", "application/json")
+                .header(ACR_KEY_ID_HEADER, "ci")
+                .header(ACR_TIMESTAMP_HEADER, &now)
+                .header(ACR_SIGNATURE_HEADER, signature)
+                .body(Body::from(body))
+                .unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(std::net::SocketAddr::from((
+                    [127, 0, 0, 1],
+                    4242,
+                ))));
+            let router = router.clone();
+            async move { router.oneshot(request).await.unwrap().status() }
+        };
+
+        // Authenticated as team-key, which may not use gpt-4o.
+        assert_eq!(
+            signed_status("/llm/v1/chat/completions").await,
+            axum::http::StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            signed_status("/v1/chat/completions").await,
+            axum::http::StatusCode::UNAUTHORIZED
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! HTTP/2 on the listener and the upstream client.
+//!
+//! The listener serves HTTP/1.1 and HTTP/2 side by side: hyper recognises the
+//! HTTP/2 connection preface, so clients opt in with prior knowledge (h2c),
+//! or through ALPN at a TLS proxy in front of acr. `axum::serve` has no switch
+//! to turn HTTP/2 off, so with `http2.server: false` the middleware here
+//! answers HTTP/2 requests with 505 and clients fall back to HTTP/1.1.
+//!
+//! Towards AI Core the client offers `h2` in ALPN, so concurrent streams to
+//! one provider share a connection. `http2.upstream: false` pins it to
+//! HTTP/1.1 for networks whose proxies mishandle HTTP/2.
+
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{StatusCode, Version},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+
+use crate::config::Http2Config;
+
+/// Apply `http2.upstream` to the proxy's HTTP client.
+pub fn configure_client(
+    builder: reqwest::ClientBuilder,
+    config: &Http2Config,
+) -> reqwest::ClientBuilder {
+    if config.upstream {
+        builder
+    } else {
+        builder.http1_only()
+    }
+}
+
+/// Middleware refusing HTTP/2 requests when `http2.server` is off.
+pub async fn refuse_when_disabled(
+    State(enabled): State<bool>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if enabled || request.version() != Version::HTTP_2 {
+        return next.run(request).await;
+    }
+    (
+        StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+        Json(json!({ "error": "HTTP/2 is disabled on this server; use HTTP/1.1" })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, routing::get};
+
+    async fn serve(server: bool) -> std::net::SocketAddr {
+        let app = Router::new()
+            .route("/health", get(|| async { "OK" }))
+            .layer(axum::middleware::from_fn_with_state(
+                server,
+                refuse_when_disabled,
+            ));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    #[tokio::test]
+    async fn listener_speaks_http2_unless_disabled() {
+        let h2c = reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap();
+        let http1 = configure_client(
+            reqwest::Client::builder(),
+            &Http2Config {
+                upstream: false,
+                ..Default::default()
+            },
+        )
+        .build()
+        .unwrap();
+
+        let enabled = serve(true).await;
+        let response = h2c
+            .get(format!("http://{enabled}/health"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.version(), Version::HTTP_2);
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let disabled = serve(false).await;
+        let response = h2c
+            .get(format!("http://{disabled}/health"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::HTTP_VERSION_NOT_SUPPORTED);
+        let response = http1
+            .get(format!("http://{disabled}/health"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.version(), Version::HTTP_11);
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,428 @@
+//! Server-side fetching of remote images for translated requests.
+//!
+//! Claude and Gemini deployments only accept inline (base64) images, so an
+//! OpenAI `image_url` pointing at `https://…` — or an Anthropic `image` block
+//! with a `url` source bound for Gemini — can't be forwarded as-is. When
+//! `image_fetch.enabled` is set, acr downloads such images before translating
+//! the request, within the scheme and host allowlists, a size cap and a
+//! timeout. Fetched images are kept for `cache_ttl_secs`, so provider
+//! fallbacks and later turns of the same conversation reuse them.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use futures::StreamExt;
+use serde_json::{Value, json};
+
+use crate::config::ImageFetchConfig;
+use crate::constants::image_fetch::MAX_REDIRECTS;
+use crate::proxy::LlmFamily;
+use crate::registry::glob_matches;
+
+/// A downloaded image, base64-encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedImage {
+    pub media_type: String,
+    pub data: String,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    image: Arc<FetchedImage>,
+    fetched_at: Instant,
+}
+
+/// Shared image fetcher; cheap to clone.
+#[derive(Debug, Clone)]
+pub struct ImageFetcher {
+    client: reqwest::Client,
+    config: Arc<ImageFetchConfig>,
+    cache: Arc<Mutex<HashMap<String, CacheEntry>>>,
+}
+
+impl ImageFetcher {
+    /// Build a fetcher from config; `None` when fetching is disabled.
+    pub fn from_config(config: &ImageFetchConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        // Every redirect hop has to pass the same checks as the original URL.
+        let policy_config = config.clone();
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    return attempt.error("too many redirects");
+                }
+                match check_url(&policy_config, attempt.url()) {
+                    Ok(()) => attempt.follow(),
+                    Err(e) => attempt.error(e.to_string()),
+                }
+            }))
+            .build()
+            .context("Failed to build image fetch HTTP client")?;
+        Ok(Some(Self {
+            client,
+            config: Arc::new(config.clone()),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }))
+    }
+
+    /// Replace remote image references in a request written in
+    /// `client_family`'s schema with inline base64 data, so the translators
+    /// can map them.
+    pub async fn inline_remote_images(
+        &self,
+        body: &mut Value,
+        client_family: LlmFamily,
+    ) -> Result<()> {
+        let Some(messages) = body.get_mut("messages").and_then(|m| m.as_array_mut()) else {
+            return Ok(());
+        };
+        for message in messages {
+            let Some(parts) = message.get_mut("content").and_then(|c| c.as_array_mut()) else {
+                continue;
+            };
+            for part in parts {
+                match client_family {
+                    LlmFamily::OpenAi => self.inline_openai_part(part).await?,
+                    LlmFamily::Claude => self.inline_claude_block(part).await?,
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn inline_openai_part(&self, part: &mut Value) -> Result<()> {
+        if part.get("type").and_then(|v| v.as_str()) != Some("image_url") {
+            return Ok(());
+        }
+        // `image_url` is an object with a `url`, or leniently the URL itself.
+        let slot = match part.get_mut("image_url") {
+            Some(Value::Object(image_url)) => match image_url.get_mut("url") {
+                Some(url) => url,
+                None => return Ok(()),
+            },
+            Some(url) => url,
+            None => return Ok(()),
+        };
+        let Some(url) = slot.as_str().filter(|u| !u.starts_with("data:")) else {
+            return Ok(());
+        };
+        let image = self.fetch(url).await?;
+        *slot = json!(format!("data:{};base64,{}", image.media_type, image.data));
+        Ok(())
+    }
+
+    async fn inline_claude_block(&self, block: &mut Value) -> Result<()> {
+        if block.get("type").and_then(|v| v.as_str()) != Some("image") {
+            return Ok(());
+        }
+        let Some(source) = block.get_mut("source") else {
+            return Ok(());
+        };
+        if source.get("type").and_then(|v| v.as_str()) != Some("url") {
+            return Ok(());
+        }
+        let url = source
+            .get("url")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        let image = self.fetch(&url).await?;
+        *source = json!({"type": "base64", "media_type": image.media_type, "data": image.data});
+        Ok(())
+    }
+
+    /// Download an image, or return the cached copy.
+    pub async fn fetch(&self, url: &str) -> Result<Arc<FetchedImage>> {
+        if let Some(image) = self.cached(url) {
+            return Ok(image);
+        }
+        let parsed =
+            reqwest::Url::parse(url).with_context(|| format!("Invalid image URL '{url}'"))?;
+        check_url(&self.config, &parsed)?;
+
+        let response = self
+            .client
+            .get(parsed)
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch image {url}"))?;
+        if !response.status().is_success() {
+            bail!(
+                "Fetching image {url} failed with status {}",
+                response.status()
+            );
+        }
+        let media_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        if !media_type.starts_with("image/") {
+            bail!("{url} is not an image (content type '{media_type}')");
+        }
+
+        let max_bytes = self.config.max_bytes;
+        let too_large = || {
+            anyhow::anyhow!("Image {url} is larger than image_fetch.max_bytes ({max_bytes} bytes)")
+        };
+        if response
+            .content_length()
+            .is_some_and(|len| len > max_bytes as u64)
+        {
+            return Err(too_large());
+        }
+        let mut bytes = Vec::new();
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.with_context(|| format!("Failed to read image {url}"))?;
+            if bytes.len() + chunk.len() > max_bytes {
+                return Err(too_large());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        let image = Arc::new(FetchedImage {
+            media_type,
+            data: base64::engine::general_purpose::STANDARD.encode(&bytes),
+        });
+        self.store(url, image.clone());
+        Ok(image)
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.config.cache_ttl_secs)
+    }
+
+    fn cached(&self, url: &str) -> Option<Arc<FetchedImage>> {
+        let cache = self.cache.lock().ok()?;
+        cache
+            .get(url)
+            .filter(|entry| entry.fetched_at.elapsed() < self.ttl())
+            .map(|entry| entry.image.clone())
+    }
+
+    fn store(&self, url: &str, image: Arc<FetchedImage>) {
+        let max_entries = self.config.cache_max_entries;
+        if max_entries == 0 || self.config.cache_ttl_secs == 0 {
+            return;
+        }
+        let Ok(mut cache) = self.cache.lock() else {
+            return;
+        };
+        let ttl = self.ttl();
+        cache.retain(|_, entry| entry.fetched_at.elapsed() < ttl);
+        while cache.len() >= max_entries {
+            let Some(oldest) = cache
+                .iter()
+                .min_by_key(|(_, entry)| entry.fetched_at)
+                .map(|(url, _)| url.clone())
+            else {
+                break;
+            };
+            cache.remove(&oldest);
+        }
+        cache.insert(
+            url.to_string(),
+            CacheEntry {
+                image,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+}
+
+/// Refuse URLs outside the scheme and host allowlists. Without a host
+/// allowlist, `localhost` and loopback, private and link-local address
+/// literals are refused so clients can't point acr at internal services.
+/// Host names aren't resolved here; set `allowed_hosts` to lock fetching
+/// down fully.
+fn check_url(config: &ImageFetchConfig, url: &reqwest::Url) -> Result<()> {
+    let scheme = url.scheme();
+    if !config
+        .allowed_schemes
+        .iter()
+        .any(|s| s.eq_ignore_ascii_case(scheme))
+    {
+        bail!("Image URL scheme '{scheme}' is not allowed (see image_fetch.allowed_schemes)");
+    }
+    let Some(host) = url.host_str() else {
+        bail!("Image URL {url} has no host");
+    };
+    let host = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    let allowed = if config.allowed_hosts.is_empty() {
+        !is_internal_host(&host)
+    } else {
+        config
+            .allowed_hosts
+            .iter()
+            .any(|pattern| glob_matches(&pattern.to_ascii_lowercase(), &host).is_some())
+    };
+    if !allowed {
+        bail!("Image host '{host}' is not allowed (see image_fetch.allowed_hosts)");
+    }
+    Ok(())
+}
+
+fn is_internal_host(host: &str) -> bool {
+    if host == "localhost" || host.ends_with(".localhost") {
+        return true;
+    }
+    let internal_v4 = |ip: std::net::Ipv4Addr| {
+        ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()
+    };
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => internal_v4(ip),
+        Ok(IpAddr::V6(ip)) => {
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local (fc00::/7) and link-local (fe80::/10).
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+                || ip.to_ipv4_mapped().is_some_and(internal_v4)
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::Router;
+    use axum::http::header::CONTENT_TYPE;
+    use axum::routing::get;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\nfake";
+
+    fn config(allowed_hosts: &[&str]) -> ImageFetchConfig {
+        ImageFetchConfig {
+            enabled: true,
+            allowed_schemes: vec!["http".to_string()],
+            allowed_hosts: allowed_hosts.iter().map(|h| h.to_string()).collect(),
+            max_bytes: 64,
+            ..ImageFetchConfig::default()
+        }
+    }
+
+    /// Serves `/a.png`, an oversized `/big.png` and a non-image `/page`,
+    /// counting image downloads.
+    async fn image_server() -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = Router::new()
+            .route(
+                "/a.png",
+                get(move || {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async { ([(CONTENT_TYPE, "image/png")], PNG) }
+                }),
+            )
+            .route(
+                "/big.png",
+                get(|| async { ([(CONTENT_TYPE, "image/png")], vec![0u8; 1024]) }),
+            )
+            .route(
+                "/page",
+                get(|| async { ([(CONTENT_TYPE, "text/html")], "<html>") }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{addr}"), hits)
+    }
+
+    #[test]
+    fn urls_are_checked_against_the_allowlists() {
+        let check = |config: &ImageFetchConfig, url: &str| {
+            check_url(config, &reqwest::Url::parse(url).unwrap()).is_ok()
+        };
+        let open = ImageFetchConfig::default();
+        assert!(check(&open, "https://images.example.com/a.png"));
+        assert!(!check(&open, "http://images.example.com/a.png"));
+        assert!(!check(&open, "https://localhost/a.png"));
+        assert!(!check(&open, "https://10.0.0.5/a.png"));
+        assert!(!check(&open, "https://169.254.169.254/latest"));
+        assert!(!check(&open, "https://[::1]/a.png"));
+        assert!(!check(&open, "https://[::ffff:192.168.1.1]/a.png"));
+
+        let restricted = ImageFetchConfig {
+            allowed_hosts: vec!["*.example.com".to_string()],
+            ..ImageFetchConfig::default()
+        };
+        assert!(check(&restricted, "https://cdn.Example.com/a.png"));
+        assert!(!check(&restricted, "https://example.org/a.png"));
+    }
+
+    #[tokio::test]
+    async fn openai_and_anthropic_images_are_inlined_and_cached() {
+        let (base, hits) = image_server().await;
+        let fetcher = ImageFetcher::from_config(&config(&["127.0.0.1"]))
+            .unwrap()
+            .unwrap();
+        let url = format!("{base}/a.png");
+        let data = base64::engine::general_purpose::STANDARD.encode(PNG);
+
+        let mut body = json!({"messages": [{"role": "user", "content": [
+            {"type": "text", "text": "what is this?"},
+            {"type": "image_url", "image_url": {"url": url, "detail": "high"}}
+        ]}]});
+        fetcher
+            .inline_remote_images(&mut body, LlmFamily::OpenAi)
+            .await
+            .unwrap();
+        assert_eq!(
+            body["messages"][0]["content"][1]["image_url"],
+            json!({"url": format!("data:image/png;base64,{data}"), "detail": "high"})
+        );
+
+        let mut body = json!({"messages": [{"role": "user", "content": [
+            {"type": "image", "source": {"type": "url", "url": url}}
+        ]}]});
+        fetcher
+            .inline_remote_images(&mut body, LlmFamily::Claude)
+            .await
+            .unwrap();
+        assert_eq!(
+            body["messages"][0]["content"][0]["source"],
+            json!({"type": "base64", "media_type": "image/png", "data": data})
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn oversized_non_image_and_disallowed_urls_are_rejected() {
+        let (base, _) = image_server().await;
+        let fetcher = ImageFetcher::from_config(&config(&["127.0.0.1"]))
+            .unwrap()
+            .unwrap();
+        let err = fetcher.fetch(&format!("{base}/big.png")).await.unwrap_err();
+        assert!(err.to_string().contains("max_bytes"), "{err}");
+        let err = fetcher.fetch(&format!("{base}/page")).await.unwrap_err();
+        assert!(err.to_string().contains("not an image"), "{err}");
+
+        let fetcher = ImageFetcher::from_config(&config(&[])).unwrap().unwrap();
+        let err = fetcher.fetch(&format!("{base}/a.png")).await.unwrap_err();
+        assert!(err.to_string().contains("allowed_hosts"), "{err}");
+
+        assert!(
+            ImageFetcher::from_config(&ImageFetchConfig::default())
+                .unwrap()
+                .is_none()
+        );
+    }
+}
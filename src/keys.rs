@@ -0,0 +1,203 @@
+//! API keys managed with `acr keys`.
+//!
+//! `acr keys add` generates a random key, appends its SHA-256 to the keys
+//! file and prints the key once: only the hash is stored, so a copied config
+//! doesn't leak working keys. The keys file uses the `api_keys` format and is
+//! merged into the config's keys at startup, so limits added to a generated
+//! key by hand keep working. Keys are named by their short hash, the ID
+//! `acr keys list` shows and the request log records.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_yaml_ng::{Mapping, Value};
+use sha2::{Digest, Sha256};
+
+use crate::config::{key_digest, short_key_hash};
+use crate::constants::config::{GENERATED_KEY_PREFIX, HASHED_KEY_PREFIX};
+
+/// A new random key: the prefix and 64 hex digits (244 random bits).
+pub fn generate() -> String {
+    format!(
+        "{GENERATED_KEY_PREFIX}{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// The `api_keys[].key` value storing `key` as its hash.
+pub fn hashed(key: &str) -> String {
+    format!("{HASHED_KEY_PREFIX}{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// Short hash of a keys file entry, plain string or `{key: ...}`.
+fn entry_hash(entry: &Value) -> Option<String> {
+    let key = match entry {
+        Value::String(key) => key.as_str(),
+        Value::Mapping(map) => map.get("key")?.as_str()?,
+        _ => return None,
+    };
+    key_digest(key).map(|digest| short_key_hash(&digest))
+}
+
+/// What a new key is issued for.
+#[derive(Debug, Default)]
+pub struct NewKey<'a> {
+    pub client: Option<&'a str>,
+    pub tenant: Option<&'a str>,
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+/// The keys file, edited as YAML so hand-added fields survive.
+pub struct KeysFile {
+    path: PathBuf,
+    entries: Vec<Value>,
+}
+
+impl KeysFile {
+    /// Read the keys file; a missing file has no keys.
+    pub fn open(path: &str) -> Result<Self> {
+        let path = PathBuf::from(path);
+        let entries = if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read keys file: {}", path.display()))?;
+            let doc: Option<Value> = serde_yaml_ng::from_str(&content)
+                .with_context(|| format!("Failed to parse keys file: {}", path.display()))?;
+            match doc.as_ref().and_then(|d| d.get("api_keys")) {
+                Some(Value::Sequence(entries)) => entries.clone(),
+                Some(_) => anyhow::bail!("{}: api_keys must be a list", path.display()),
+                None => Vec::new(),
+            }
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, entries })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the key with short hash `key_hash` is stored here.
+    pub fn contains(&self, key_hash: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|e| entry_hash(e).as_deref() == Some(key_hash))
+    }
+
+    /// Store a new key by its hash.
+    pub fn add(&mut self, key: &str, new: &NewKey<'_>) {
+        let mut entry = Mapping::new();
+        entry.insert("key".into(), hashed(key).into());
+        if let Some(client) = new.client {
+            entry.insert("client".into(), client.into());
+        }
+        if let Some(tenant) = new.tenant {
+            entry.insert("tenant".into(), tenant.into());
+        }
+        if let Some(until) = new.valid_until {
+            entry.insert(
+                "valid_until".into(),
+                until.to_rfc3339_opts(SecondsFormat::Secs, true).into(),
+            );
+        }
+        self.entries.push(Value::Mapping(entry));
+    }
+
+    /// Remove the key whose short hash starts with `id`, returning its full
+    /// short hash. The prefix must name exactly one key.
+    pub fn revoke(&mut self, id: &str) -> Result<String> {
+        let matches: Vec<(usize, String)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| entry_hash(e).map(|hash| (i, hash)))
+            .filter(|(_, hash)| !id.is_empty() && hash.starts_with(id))
+            .collect();
+        match matches.as_slice() {
+            [(i, hash)] => {
+                let hash = hash.clone();
+                self.entries.remove(*i);
+                Ok(hash)
+            }
+            [] => anyhow::bail!("No key '{}' in {}", id, self.path.display()),
+            _ => anyhow::bail!(
+                "'{}' matches {} keys; use more of the ID",
+                id,
+                matches.len()
+            ),
+        }
+    }
+
+    /// Write the file back, readable only by its owner.
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let mut doc = Mapping::new();
+        doc.insert("api_keys".into(), Value::Sequence(self.entries.clone()));
+        let content = format!(
+            "# Managed by `acr keys`; keys are stored as their SHA-256.\n{}",
+            serde_yaml_ng::to_string(&doc)?
+        );
+        let tmp = self.path.with_extension("yaml.tmp");
+        std::fs::write(&tmp, content)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))?;
+        }
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApiKeyConfig;
+    use crate::quota::hash_api_key;
+    use tempfile::TempDir;
+
+    #[test]
+    fn added_keys_are_stored_hashed_and_revoked_by_id() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("keys.yaml");
+        let path = path.to_str().unwrap();
+
+        let (first, second) = (generate(), generate());
+        assert!(first.starts_with(GENERATED_KEY_PREFIX) && first.len() == 68);
+        assert_ne!(first, second);
+
+        let mut keys = KeysFile::open(path).unwrap();
+        keys.add(
+            &first,
+            &NewKey {
+                client: Some("ci"),
+                ..Default::default()
+            },
+        );
+        keys.add(&second, &NewKey::default());
+        keys.save().unwrap();
+
+        let content = std::fs::read_to_string(path).unwrap();
+        assert!(!content.contains(&first));
+        let doc: Value = serde_yaml_ng::from_str(&content).unwrap();
+        let stored: Vec<ApiKeyConfig> = serde_yaml_ng::from_value(doc["api_keys"].clone()).unwrap();
+        assert!(stored[0].matches(&first));
+        assert_eq!(stored[0].client.as_deref(), Some("ci"));
+
+        let mut keys = KeysFile::open(path).unwrap();
+        let id = hash_api_key(&first);
+        assert!(keys.contains(&id));
+        assert_eq!(keys.revoke(&id[..8]).unwrap(), id);
+        assert!(!keys.contains(&id));
+        assert!(keys.revoke(&id).is_err());
+        assert!(keys.contains(&hash_api_key(&second)));
+    }
+}
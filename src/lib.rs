@@ -1,23 +1,68 @@
+pub mod admission;
 pub mod balancer;
+pub mod batches;
+pub mod body_limit;
+pub mod cached_content;
+pub mod capture;
 pub mod cli;
 pub mod client;
+pub mod client_ip;
+#[cfg(feature = "cluster")]
+pub mod cluster;
 pub mod commands;
 pub mod config;
+pub mod config_migrate;
+pub mod connections;
 pub mod constants;
+pub mod continuation;
+pub mod daemon;
 #[cfg(feature = "db")]
 pub mod database;
+pub mod dead_letter;
+pub mod deployment_health;
+pub mod deprecation;
+#[cfg(test)]
+mod golden_tests;
+pub mod hmac_auth;
+pub mod http2;
+pub mod image_fetch;
+pub mod keys;
+pub mod log_level;
 pub mod metrics;
+pub mod model_switch;
+pub mod mount;
 pub mod proxy;
 pub mod quota;
 pub mod rate_limit;
+pub mod redact;
 pub mod registry;
+pub mod reload;
+pub mod request_id;
 pub mod request_limiter;
+pub mod response_cache;
+pub mod retry_budget;
 pub mod routes;
+pub mod secrets;
+pub mod sentry;
+pub mod session;
+pub mod slo;
+pub mod statsd;
+pub mod stream_limit;
+pub mod stream_pace;
+pub mod systemd;
 pub mod table;
+pub mod templates;
+pub mod tenant_quota;
+pub mod timeout;
+pub mod timing;
 pub mod token;
 pub mod transforms;
 #[cfg(feature = "tui")]
 pub mod tui;
+pub mod upstream_limits;
+#[cfg(feature = "db")]
+pub mod usage_export;
+pub mod warmup;
 
 /// Format a cost value with adaptive precision: 4 decimal places below $1, 2 above.
 pub(crate) fn format_cost_value(cost: f64) -> String {
@@ -0,0 +1,199 @@
+//! Runtime log level changes.
+//!
+//! The tracing filter is installed behind a reload layer, so
+//! `PUT /admin/log-level` can raise acr's level to `debug` during an incident
+//! and put it back later without a restart, which would drop in-memory state
+//! (session pins, recent requests, upstream back-offs). A change can carry a
+//! revert delay; the level then falls back to the configured one on its own,
+//! unless another change has been made in the meantime.
+
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+/// The filter for `level`: acr's own crates at that level, everything else
+/// at `info`.
+pub fn env_filter(level: LevelFilter) -> Result<EnvFilter> {
+    EnvFilter::try_new(format!("aicore_router={level},acr={level},info"))
+        .context("Failed to build log filter")
+}
+
+/// Parse a level name (`trace`, `debug`, `info`, `warn`, `error`, `off`),
+/// case-insensitively.
+pub fn parse_level(raw: &str) -> Option<LevelFilter> {
+    LevelFilter::from_str(raw.trim()).ok()
+}
+
+#[derive(Debug)]
+struct State {
+    configured: LevelFilter,
+    current: LevelFilter,
+    /// Bumped on every change, so a pending revert can tell whether it has
+    /// been superseded.
+    generation: u64,
+}
+
+/// Handle to the installed log filter.
+#[derive(Clone)]
+pub struct LogLevelControl {
+    handle: reload::Handle<EnvFilter, Registry>,
+    state: Arc<Mutex<State>>,
+}
+
+impl LogLevelControl {
+    /// The control and the filter layer it drives, starting at the
+    /// configured `log_level`. The layer goes directly on the registry.
+    pub fn new(log_level: &str) -> Result<(Self, reload::Layer<EnvFilter, Registry>)> {
+        let configured = parse_level(log_level).with_context(|| {
+            format!(
+                "Invalid log_level '{}'. Valid options: trace, debug, info, warn, error",
+                log_level
+            )
+        })?;
+        let (layer, handle) = reload::Layer::new(env_filter(configured)?);
+        let control = Self {
+            handle,
+            state: Arc::new(Mutex::new(State {
+                configured,
+                current: configured,
+                generation: 0,
+            })),
+        };
+        Ok((control, layer))
+    }
+
+    /// The level in effect now.
+    pub fn current(&self) -> LevelFilter {
+        self.state
+            .lock()
+            .map(|s| s.current)
+            .unwrap_or(LevelFilter::INFO)
+    }
+
+    /// The level from config (or `--log-level`), which reverts return to.
+    pub fn configured(&self) -> LevelFilter {
+        self.state
+            .lock()
+            .map(|s| s.configured)
+            .unwrap_or(LevelFilter::INFO)
+    }
+
+    /// Make `level` the configured level, after a config reload. The
+    /// current level follows unless a runtime change is in effect.
+    pub fn set_configured(&self, level: LevelFilter) -> Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Log level state poisoned"))?;
+        if state.current == state.configured && state.current != level {
+            self.handle
+                .reload(env_filter(level)?)
+                .context("Failed to reload log filter")?;
+            state.current = level;
+            state.generation += 1;
+        }
+        state.configured = level;
+        Ok(())
+    }
+
+    /// Switch to `level` and return the level it replaces. With
+    /// `revert_after`, the configured level comes back once that much time
+    /// has passed, unless the level is changed again before then.
+    pub fn set(&self, level: LevelFilter, revert_after: Option<Duration>) -> Result<LevelFilter> {
+        let (previous, generation) = {
+            let mut state = self
+                .state
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Log level state poisoned"))?;
+            let previous = state.current;
+            self.handle
+                .reload(env_filter(level)?)
+                .context("Failed to reload log filter")?;
+            state.current = level;
+            state.generation += 1;
+            (previous, state.generation)
+        };
+
+        if let Some(delay) = revert_after {
+            let control = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                control.revert(generation);
+            });
+        }
+        Ok(previous)
+    }
+
+    /// Return to the configured level if nothing has changed since
+    /// `generation`.
+    fn revert(&self, generation: u64) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if state.generation != generation || state.current == state.configured {
+            return;
+        }
+        let configured = state.configured;
+        match env_filter(configured)
+            .and_then(|f| self.handle.reload(f).context("Failed to reload log filter"))
+        {
+            Ok(()) => {
+                state.current = configured;
+                state.generation += 1;
+                tracing::info!("Log level reverted to {}", configured);
+            }
+            Err(e) => tracing::warn!("Failed to revert log level: {:#}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_parse_case_insensitively() {
+        assert_eq!(parse_level("DEBUG"), Some(LevelFilter::DEBUG));
+        assert_eq!(parse_level(" warn "), Some(LevelFilter::WARN));
+        assert_eq!(parse_level("off"), Some(LevelFilter::OFF));
+        assert_eq!(parse_level("verbose"), None);
+        assert!(LogLevelControl::new("loud").is_err());
+    }
+
+    #[tokio::test]
+    async fn changes_revert_unless_superseded() {
+        let (control, _layer) = LogLevelControl::new("info").unwrap();
+
+        let previous = control
+            .set(LevelFilter::DEBUG, Some(Duration::from_millis(20)))
+            .unwrap();
+        assert_eq!(previous, LevelFilter::INFO);
+        assert_eq!(control.current(), LevelFilter::DEBUG);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(control.current(), LevelFilter::INFO);
+
+        // A later change cancels the earlier change's revert.
+        control
+            .set(LevelFilter::TRACE, Some(Duration::from_millis(20)))
+            .unwrap();
+        control.set(LevelFilter::WARN, None).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(control.current(), LevelFilter::WARN);
+        assert_eq!(control.configured(), LevelFilter::INFO);
+    }
+    #[test]
+    fn reloaded_level_leaves_runtime_changes_alone() {
+        let (control, _layer) = LogLevelControl::new("info").unwrap();
+        control.set_configured(LevelFilter::WARN).unwrap();
+        assert_eq!(control.current(), LevelFilter::WARN);
+
+        control.set(LevelFilter::DEBUG, None).unwrap();
+        control.set_configured(LevelFilter::ERROR).unwrap();
+        assert_eq!(control.current(), LevelFilter::DEBUG);
+        assert_eq!(control.configured(), LevelFilter::ERROR);
+    }
+}
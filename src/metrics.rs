@@ -3,13 +3,22 @@
 //! Tracks request counts (active, total, successful, failed) and
 //! token usage (input, output, cache_read, cache_write) with
 //! thread-safe atomic counters and a broadcast pub/sub channel.
-//! Also tracks per-model token usage for cost estimation.
+//! Also tracks per-model token usage for cost estimation, and per-attempt
+//! request / error / latency / token series labeled by model, family,
+//! provider, route and stream, rendered in the Prometheus text format.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use tokio::sync::{RwLock, broadcast};
 
+use crate::constants::metrics::{
+    ATTEMPT_BUCKETS, LATENCY_BUCKETS_SECS, MAX_SUMMARY_ERROR_LEN, RECENT_REQUESTS_CAPACITY,
+};
+use crate::proxy::LlmFamily;
+
 /// Accumulated token usage across all requests.
 #[derive(Debug, Clone, Default)]
 pub struct UsageMetrics {
@@ -20,12 +29,16 @@ pub struct UsageMetrics {
 }
 
 /// Per-model token counts for cost estimation.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct TokenCounts {
     pub input: u64,
     pub output: u64,
     pub cache_read: u64,
     pub cache_write: u64,
+    /// Reasoning tokens, a subset of `output`
+    pub reasoning: u64,
+    /// Image input tokens, a subset of `input`
+    pub image: u64,
 }
 
 /// A point-in-time snapshot of all metrics.
@@ -38,11 +51,196 @@ pub struct MetricsSnapshot {
     pub usage: UsageMetrics,
 }
 
+/// Dimensions of the labeled series — the same ones the `Proxy done` log
+/// line reports.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct RequestLabels {
+    /// Resolved model name.
+    pub model: String,
+    /// Upstream API family.
+    #[serde(serialize_with = "serialize_family")]
+    pub family: LlmFamily,
+    pub provider: String,
+    /// Route template the client called, e.g. `/v1/messages`.
+    pub route: String,
+    pub stream: bool,
+}
+
+/// One provider a client request was sent to.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct UpstreamAttempt {
+    pub provider: String,
+    pub status: u16,
+    pub latency_ms: u64,
+}
+
+/// Accounting record of one upstream attempt: feeds the labeled series and
+/// the recent-requests buffer behind `/admin/recent`, and is broadcast to
+/// subscribers as [`MetricsEvent::UpstreamAttempt`]. The attempt that
+/// answers the client is also the usage store's row for the request.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RequestSummary {
+    pub at: chrono::DateTime<chrono::Utc>,
+    #[serde(flatten)]
+    pub labels: RequestLabels,
+    /// AI Core deployment the attempt went to, once resolved.
+    pub deployment_id: Option<String>,
+    /// HTTP status of the upstream response (200 for a stream that was
+    /// cut short, with `error` set).
+    pub status: u16,
+    pub latency_ms: u64,
+    /// Time to the upstream response headers.
+    pub ttfb_ms: Option<u64>,
+    pub tokens: TokenCounts,
+    /// Estimated cost from the model's `pricing`.
+    pub cost_usd: Option<f64>,
+    pub error: Option<String>,
+    pub session_id: Option<String>,
+    /// This attempt and the ones before it for the same client request,
+    /// oldest first. More than one entry means the request failed over.
+    pub attempts: Vec<UpstreamAttempt>,
+    /// Hash of the API key that sent the request.
+    #[serde(skip)]
+    pub owner: Option<String>,
+    #[serde(skip)]
+    latency: Duration,
+}
+
+impl RequestSummary {
+    pub fn new(labels: RequestLabels, status: u16, latency: Duration) -> Self {
+        Self {
+            at: chrono::Utc::now(),
+            labels,
+            deployment_id: None,
+            status,
+            latency_ms: latency.as_millis() as u64,
+            ttfb_ms: None,
+            tokens: TokenCounts::default(),
+            cost_usd: None,
+            error: None,
+            session_id: None,
+            attempts: Vec::new(),
+            owner: None,
+            latency,
+        }
+    }
+
+    /// Record the attempts made before this one; `attempts` then holds the
+    /// full history up to and including this attempt.
+    pub fn with_prior_attempts(mut self, prior: &[UpstreamAttempt]) -> Self {
+        self.attempts = prior.to_vec();
+        self.attempts.push(UpstreamAttempt {
+            provider: self.labels.provider.clone(),
+            status: self.status,
+            latency_ms: self.latency_ms,
+        });
+        self
+    }
+
+    pub fn with_tokens(mut self, tokens: TokenCounts) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    pub fn with_deployment_id(mut self, deployment_id: impl Into<String>) -> Self {
+        self.deployment_id = Some(deployment_id.into());
+        self
+    }
+
+    pub fn with_ttfb(mut self, ttfb: Option<Duration>) -> Self {
+        self.ttfb_ms = ttfb.map(|d| d.as_millis() as u64);
+        self
+    }
+
+    pub fn with_cost(mut self, cost_usd: Option<f64>) -> Self {
+        self.cost_usd = cost_usd;
+        self
+    }
+
+    pub fn with_error(mut self, error: impl Into<String>) -> Self {
+        let mut error: String = error.into();
+        if error.len() > MAX_SUMMARY_ERROR_LEN {
+            let mut end = MAX_SUMMARY_ERROR_LEN;
+            while !error.is_char_boundary(end) {
+                end -= 1;
+            }
+            error.truncate(end);
+            error.push('…');
+        }
+        self.error = Some(error);
+        self
+    }
+
+    pub fn with_session_id(mut self, session_id: Option<String>) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    pub fn with_owner(mut self, owner: Option<String>) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    pub fn success(&self) -> bool {
+        (200..300).contains(&self.status) && self.error.is_none()
+    }
+
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+}
+
+/// Counters and latency histogram of one label set.
+#[derive(Debug, Clone, Default)]
+pub struct LabeledStats {
+    pub requests: u64,
+    pub errors: u64,
+    /// Cumulative counts per `LATENCY_BUCKETS_SECS` bound.
+    pub latency_buckets: Vec<u64>,
+    pub latency_sum_secs: f64,
+    pub tokens: TokenCounts,
+}
+
+/// Distribution of upstream attempts per client request.
+#[derive(Debug, Clone, Default)]
+struct FanIn {
+    /// Non-cumulative counts per `ATTEMPT_BUCKETS` bound.
+    buckets: Vec<u64>,
+    sum: u64,
+    count: u64,
+}
+
+impl FanIn {
+    fn record(&mut self, attempts: u64) {
+        self.buckets.resize(ATTEMPT_BUCKETS.len(), 0);
+        if let Some(i) = ATTEMPT_BUCKETS.iter().position(|b| attempts <= *b) {
+            self.buckets[i] += 1;
+        }
+        self.sum += attempts;
+        self.count += 1;
+    }
+}
+
+/// Connection and stream counts since startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub client_open: u64,
+    pub client_accepted: u64,
+    pub client_closed: u64,
+    pub upstream_opened: u64,
+    pub open_streams: u64,
+}
+
 /// Events broadcast to subscribers when metrics change.
 #[derive(Debug, Clone)]
 pub enum MetricsEvent {
     RequestStarted,
-    RequestCompleted { success: bool, tokens: TokenCounts },
+    RequestCompleted {
+        success: bool,
+        tokens: TokenCounts,
+    },
+    /// One upstream attempt finished (see `record_request`).
+    UpstreamAttempt(Box<RequestSummary>),
 }
 
 struct MetricsInner {
@@ -50,11 +248,33 @@ struct MetricsInner {
     total_requests: AtomicU64,
     successful_requests: AtomicU64,
     failed_requests: AtomicU64,
+    stream_panics: AtomicU64,
+    /// Failovers refused because the retry budget was spent.
+    retries_denied: AtomicU64,
+    /// Requests answered from the response cache, and requests it missed.
+    response_cache_hits: AtomicU64,
+    response_cache_misses: AtomicU64,
+    /// Streaming responses currently being sent to clients.
+    open_streams: AtomicU64,
+    client_connections_open: AtomicU64,
+    client_connections_accepted: AtomicU64,
+    client_connections_closed: AtomicU64,
+    /// Connections the HTTP client has opened to AI Core.
+    upstream_connections_opened: AtomicU64,
+    /// Client requests by the number of upstream attempts they took; counts
+    /// per `ATTEMPT_BUCKETS` bound, then the overflow.
+    attempts_per_request: std::sync::Mutex<FanIn>,
     total_input_tokens: AtomicU64,
     total_output_tokens: AtomicU64,
     total_cache_read_tokens: AtomicU64,
     total_cache_write_tokens: AtomicU64,
     model_usage: RwLock<HashMap<String, TokenCounts>>,
+    labeled: RwLock<HashMap<RequestLabels, LabeledStats>>,
+    /// Most recent attempts, oldest first, capped at `RECENT_REQUESTS_CAPACITY`.
+    recent: std::sync::Mutex<VecDeque<RequestSummary>>,
+    /// Per-conversation usage keyed by session ID, with last-update time for
+    /// eviction once `AFFINITY_CAPACITY` sessions are tracked.
+    conversation_usage: RwLock<HashMap<String, (TokenCounts, std::time::Instant)>>,
     sender: broadcast::Sender<MetricsEvent>,
 }
 
@@ -94,11 +314,24 @@ impl MetricsService {
                 total_requests: AtomicU64::new(0),
                 successful_requests: AtomicU64::new(0),
                 failed_requests: AtomicU64::new(0),
+                stream_panics: AtomicU64::new(0),
+                retries_denied: AtomicU64::new(0),
+                response_cache_hits: AtomicU64::new(0),
+                response_cache_misses: AtomicU64::new(0),
+                open_streams: AtomicU64::new(0),
+                client_connections_open: AtomicU64::new(0),
+                client_connections_accepted: AtomicU64::new(0),
+                client_connections_closed: AtomicU64::new(0),
+                upstream_connections_opened: AtomicU64::new(0),
+                attempts_per_request: std::sync::Mutex::new(FanIn::default()),
                 total_input_tokens: AtomicU64::new(0),
                 total_output_tokens: AtomicU64::new(0),
                 total_cache_read_tokens: AtomicU64::new(0),
                 total_cache_write_tokens: AtomicU64::new(0),
                 model_usage: RwLock::new(HashMap::new()),
+                labeled: RwLock::new(HashMap::new()),
+                recent: std::sync::Mutex::new(VecDeque::new()),
+                conversation_usage: RwLock::new(HashMap::new()),
                 sender,
             }),
         }
@@ -166,6 +399,8 @@ impl MetricsService {
             counts.output = counts.output.saturating_add(tokens.output);
             counts.cache_read = counts.cache_read.saturating_add(tokens.cache_read);
             counts.cache_write = counts.cache_write.saturating_add(tokens.cache_write);
+            counts.reasoning = counts.reasoning.saturating_add(tokens.reasoning);
+            counts.image = counts.image.saturating_add(tokens.image);
             drop(model_map);
         }
 
@@ -175,6 +410,312 @@ impl MetricsService {
         });
     }
 
+    /// Record how many providers a finished client request was sent to.
+    pub fn record_fan_in(&self, attempts: usize) {
+        if attempts == 0 {
+            return;
+        }
+        if let Ok(mut fan_in) = self.inner.attempts_per_request.lock() {
+            fan_in.record(attempts as u64);
+        }
+    }
+
+    /// Count a streaming forwarder that panicked and was cut short.
+    pub fn record_stream_panic(&self) {
+        self.inner.stream_panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a failover refused by the retry budget.
+    pub fn record_retry_denied(&self) {
+        self.inner.retries_denied.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a response cache lookup.
+    pub fn record_response_cache(&self, hit: bool) {
+        let counter = if hit {
+            &self.inner.response_cache_hits
+        } else {
+            &self.inner.response_cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a new connection from the HTTP client to AI Core.
+    pub fn record_upstream_connect(&self) {
+        self.inner
+            .upstream_connections_opened
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Current connection and stream counts.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        ConnectionStats {
+            client_open: load(&self.inner.client_connections_open),
+            client_accepted: load(&self.inner.client_connections_accepted),
+            client_closed: load(&self.inner.client_connections_closed),
+            upstream_opened: load(&self.inner.upstream_connections_opened),
+            open_streams: load(&self.inner.open_streams),
+        }
+    }
+
+    /// Record one upstream attempt under its labels. Every attempt counts,
+    /// including 429s that fail over to the next provider, so error rates
+    /// can be broken down per provider.
+    pub async fn record_request(&self, summary: RequestSummary) {
+        let RequestSummary {
+            ref labels,
+            ref tokens,
+            latency,
+            ..
+        } = summary;
+        let success = summary.success();
+        let mut labeled = self.inner.labeled.write().await;
+        let stats = labeled.entry(labels.clone()).or_default();
+        stats.requests += 1;
+        if !success {
+            stats.errors += 1;
+        }
+        let secs = latency.as_secs_f64();
+        stats.latency_buckets.resize(LATENCY_BUCKETS_SECS.len(), 0);
+        for (count, bound) in stats.latency_buckets.iter_mut().zip(LATENCY_BUCKETS_SECS) {
+            if secs <= *bound {
+                *count += 1;
+            }
+        }
+        stats.latency_sum_secs += secs;
+        stats.tokens.input = stats.tokens.input.saturating_add(tokens.input);
+        stats.tokens.output = stats.tokens.output.saturating_add(tokens.output);
+        stats.tokens.cache_read = stats.tokens.cache_read.saturating_add(tokens.cache_read);
+        stats.tokens.cache_write = stats.tokens.cache_write.saturating_add(tokens.cache_write);
+        stats.tokens.reasoning = stats.tokens.reasoning.saturating_add(tokens.reasoning);
+        stats.tokens.image = stats.tokens.image.saturating_add(tokens.image);
+        drop(labeled);
+
+        let _ = self
+            .inner
+            .sender
+            .send(MetricsEvent::UpstreamAttempt(Box::new(summary.clone())));
+
+        if let Ok(mut recent) = self.inner.recent.lock() {
+            if recent.len() >= RECENT_REQUESTS_CAPACITY {
+                recent.pop_front();
+            }
+            recent.push_back(summary);
+        }
+    }
+
+    /// Recently finished upstream attempts, newest first.
+    pub fn recent_requests(&self) -> Vec<RequestSummary> {
+        self.inner
+            .recent
+            .lock()
+            .map(|recent| recent.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Labeled series recorded so far.
+    pub async fn labeled_stats(&self) -> Vec<(RequestLabels, LabeledStats)> {
+        self.inner
+            .labeled
+            .read()
+            .await
+            .iter()
+            .map(|(labels, stats)| (labels.clone(), stats.clone()))
+            .collect()
+    }
+
+    /// All metrics in the Prometheus text exposition format.
+    pub async fn render_prometheus(&self) -> String {
+        let snapshot = self.snapshot_sync();
+        let mut series = self.labeled_stats().await;
+        series.sort_by(|(a, _), (b, _)| {
+            (&a.model, &a.provider, &a.route, a.stream).cmp(&(
+                &b.model,
+                &b.provider,
+                &b.route,
+                b.stream,
+            ))
+        });
+
+        let connections = self.connection_stats();
+
+        let mut out = String::new();
+        for (name, help, kind, value) in [
+            (
+                "acr_active_requests",
+                "Requests currently in flight.",
+                "gauge",
+                snapshot.active_requests,
+            ),
+            (
+                "acr_open_streams",
+                "Streaming responses currently being sent to clients.",
+                "gauge",
+                connections.open_streams,
+            ),
+            (
+                "acr_client_connections",
+                "Client connections currently open.",
+                "gauge",
+                connections.client_open,
+            ),
+            (
+                "acr_client_connections_accepted_total",
+                "Client connections accepted.",
+                "counter",
+                connections.client_accepted,
+            ),
+            (
+                "acr_client_connections_closed_total",
+                "Client connections closed.",
+                "counter",
+                connections.client_closed,
+            ),
+            (
+                "acr_upstream_connections_opened_total",
+                "Connections opened to AI Core; pooled connections are reused.",
+                "counter",
+                connections.upstream_opened,
+            ),
+            (
+                "acr_client_requests_total",
+                "Client requests received.",
+                "counter",
+                snapshot.total_requests,
+            ),
+            (
+                "acr_client_requests_failed_total",
+                "Client requests that ended in an error.",
+                "counter",
+                snapshot.failed_requests,
+            ),
+            (
+                "acr_stream_panics_total",
+                "Streaming responses cut short by a panic in acr.",
+                "counter",
+                self.inner.stream_panics.load(Ordering::Relaxed),
+            ),
+            (
+                "acr_retries_denied_total",
+                "Failovers to another provider refused because the retry budget was spent.",
+                "counter",
+                self.inner.retries_denied.load(Ordering::Relaxed),
+            ),
+            (
+                "acr_response_cache_hits_total",
+                "Requests answered from the response cache.",
+                "counter",
+                self.inner.response_cache_hits.load(Ordering::Relaxed),
+            ),
+            (
+                "acr_response_cache_misses_total",
+                "Cacheable requests not found in the response cache.",
+                "counter",
+                self.inner.response_cache_misses.load(Ordering::Relaxed),
+            ),
+        ] {
+            let _ = writeln!(
+                out,
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}"
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP acr_requests_total Upstream request attempts.\n# TYPE acr_requests_total counter"
+        );
+        for (labels, stats) in &series {
+            let _ = writeln!(
+                out,
+                "acr_requests_total{{{}}} {}",
+                prometheus_labels(labels),
+                stats.requests
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP acr_request_errors_total Upstream request attempts that failed.\n# TYPE acr_request_errors_total counter"
+        );
+        for (labels, stats) in &series {
+            let _ = writeln!(
+                out,
+                "acr_request_errors_total{{{}}} {}",
+                prometheus_labels(labels),
+                stats.errors
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP acr_request_duration_seconds Upstream request duration, through the end of the stream.\n# TYPE acr_request_duration_seconds histogram"
+        );
+        for (labels, stats) in &series {
+            let labels = prometheus_labels(labels);
+            for (count, bound) in stats.latency_buckets.iter().zip(LATENCY_BUCKETS_SECS) {
+                let _ = writeln!(
+                    out,
+                    "acr_request_duration_seconds_bucket{{{labels},le=\"{bound}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "acr_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                stats.requests
+            );
+            let _ = writeln!(
+                out,
+                "acr_request_duration_seconds_sum{{{labels}}} {}",
+                stats.latency_sum_secs
+            );
+            let _ = writeln!(
+                out,
+                "acr_request_duration_seconds_count{{{labels}}} {}",
+                stats.requests
+            );
+        }
+        let fan_in = self
+            .inner
+            .attempts_per_request
+            .lock()
+            .map(|f| f.clone())
+            .unwrap_or_default();
+        let _ = writeln!(
+            out,
+            "# HELP acr_upstream_attempts_per_request Providers each client request was sent to; above 1 the request failed over.\n# TYPE acr_upstream_attempts_per_request histogram"
+        );
+        let mut cumulative = 0;
+        for (i, bound) in ATTEMPT_BUCKETS.iter().enumerate() {
+            cumulative += fan_in.buckets.get(i).copied().unwrap_or(0);
+            let _ = writeln!(
+                out,
+                "acr_upstream_attempts_per_request_bucket{{le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "acr_upstream_attempts_per_request_bucket{{le=\"+Inf\"}} {}\nacr_upstream_attempts_per_request_sum {}\nacr_upstream_attempts_per_request_count {}",
+            fan_in.count, fan_in.sum, fan_in.count
+        );
+        let _ = writeln!(
+            out,
+            "# HELP acr_tokens_total Tokens reported by upstream usage.\n# TYPE acr_tokens_total counter"
+        );
+        for (labels, stats) in &series {
+            let labels = prometheus_labels(labels);
+            for (kind, value) in [
+                ("input", stats.tokens.input),
+                ("output", stats.tokens.output),
+                ("cache_read", stats.tokens.cache_read),
+                ("cache_write", stats.tokens.cache_write),
+                ("reasoning", stats.tokens.reasoning),
+                ("image", stats.tokens.image),
+            ] {
+                let _ = writeln!(out, "acr_tokens_total{{{labels},type=\"{kind}\"}} {value}");
+            }
+        }
+        out
+    }
+
     /// Non-blocking snapshot for synchronous contexts (e.g. TUI rendering).
     pub fn snapshot_sync(&self) -> MetricsSnapshot {
         MetricsSnapshot {
@@ -199,6 +740,43 @@ impl MetricsService {
         self.inner.model_usage.read().await.clone()
     }
 
+    /// Add a finished request's tokens to its conversation's running total.
+    /// When the table is full the least recently updated conversation is
+    /// dropped to make room.
+    pub async fn record_session_usage(&self, session_id: &str, tokens: &TokenCounts) {
+        let mut sessions = self.inner.conversation_usage.write().await;
+        if !sessions.contains_key(session_id)
+            && sessions.len() >= crate::constants::session::AFFINITY_CAPACITY
+            && let Some(oldest) = sessions
+                .iter()
+                .min_by_key(|(_, (_, updated))| *updated)
+                .map(|(k, _)| k.clone())
+        {
+            sessions.remove(&oldest);
+        }
+        let (counts, updated) = sessions
+            .entry(session_id.to_string())
+            .or_insert_with(|| (TokenCounts::default(), std::time::Instant::now()));
+        counts.input = counts.input.saturating_add(tokens.input);
+        counts.output = counts.output.saturating_add(tokens.output);
+        counts.cache_read = counts.cache_read.saturating_add(tokens.cache_read);
+        counts.cache_write = counts.cache_write.saturating_add(tokens.cache_write);
+        counts.reasoning = counts.reasoning.saturating_add(tokens.reasoning);
+        counts.image = counts.image.saturating_add(tokens.image);
+        *updated = std::time::Instant::now();
+    }
+
+    /// Token usage per conversation (session ID) since startup.
+    pub async fn usage_by_session_id(&self) -> HashMap<String, TokenCounts> {
+        self.inner
+            .conversation_usage
+            .read()
+            .await
+            .iter()
+            .map(|(id, (counts, _))| (id.clone(), counts.clone()))
+            .collect()
+    }
+
     /// Non-blocking per-model usage for synchronous contexts.
     /// Returns None if the lock is contended.
     pub fn session_usage_by_model_sync(&self) -> Option<HashMap<String, TokenCounts>> {
@@ -211,6 +789,41 @@ impl MetricsService {
     }
 }
 
+pub(crate) fn family_label(family: LlmFamily) -> &'static str {
+    match family {
+        LlmFamily::OpenAi => "openai",
+        LlmFamily::OpenAiResponses => "openai_responses",
+        LlmFamily::Claude => "claude",
+        LlmFamily::Gemini => "gemini",
+        LlmFamily::OpenWeight => "open_weight",
+        LlmFamily::Passthrough => "passthrough",
+    }
+}
+
+fn serialize_family<S: serde::Serializer>(
+    family: &LlmFamily,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(family_label(*family))
+}
+
+/// `model="...",family="...",...` with label values escaped.
+fn prometheus_labels(labels: &RequestLabels) -> String {
+    let escape = |v: &str| {
+        v.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    };
+    format!(
+        "model=\"{}\",family=\"{}\",provider=\"{}\",route=\"{}\",stream=\"{}\"",
+        escape(&labels.model),
+        family_label(labels.family),
+        escape(&labels.provider),
+        escape(&labels.route),
+        labels.stream
+    )
+}
+
 /// RAII handle for an in-flight request.
 ///
 /// Constructing the guard increments `active_requests` (and `total_requests`,
@@ -220,6 +833,12 @@ impl MetricsService {
 /// upstream-drain task. Mirrors `tower_http::metrics::InFlightRequests`.
 pub struct ActiveRequestGuard {
     metrics: MetricsService,
+    /// Admission slot, released together with the request.
+    admission: Option<crate::admission::AdmissionPermit>,
+    /// Open-stream slot, released together with the request.
+    stream_slot: Option<crate::stream_limit::StreamSlot>,
+    /// Counted in `open_streams` (see `mark_stream`).
+    streaming: bool,
 }
 
 impl ActiveRequestGuard {
@@ -232,6 +851,31 @@ impl ActiveRequestGuard {
         let _ = metrics.inner.sender.send(MetricsEvent::RequestStarted);
         Self {
             metrics: metrics.clone(),
+            admission: None,
+            stream_slot: None,
+            streaming: false,
+        }
+    }
+
+    /// Keep an admission slot for as long as this guard lives, so a streamed
+    /// response holds its slot until the client is done with the body.
+    pub fn hold_admission(&mut self, permit: crate::admission::AdmissionPermit) {
+        self.admission = Some(permit);
+    }
+
+    /// Keep an open-stream slot until the client is done with the body.
+    pub fn hold_stream_slot(&mut self, slot: crate::stream_limit::StreamSlot) {
+        self.stream_slot = Some(slot);
+    }
+
+    /// Count the request in `open_streams` until the guard drops.
+    pub fn mark_stream(&mut self) {
+        if !self.streaming {
+            self.streaming = true;
+            self.metrics
+                .inner
+                .open_streams
+                .fetch_add(1, Ordering::Relaxed);
         }
     }
 }
@@ -239,6 +883,46 @@ impl ActiveRequestGuard {
 impl Drop for ActiveRequestGuard {
     fn drop(&mut self) {
         self.metrics.decrement_active();
+        if self.streaming {
+            self.metrics
+                .inner
+                .open_streams
+                .fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// RAII handle for an open client connection: counted as accepted and open
+/// on construction, as closed when dropped.
+pub struct ClientConnectionGuard {
+    metrics: MetricsService,
+}
+
+impl ClientConnectionGuard {
+    pub fn new(metrics: &MetricsService) -> Self {
+        metrics
+            .inner
+            .client_connections_accepted
+            .fetch_add(1, Ordering::Relaxed);
+        metrics
+            .inner
+            .client_connections_open
+            .fetch_add(1, Ordering::Relaxed);
+        Self {
+            metrics: metrics.clone(),
+        }
+    }
+}
+
+impl Drop for ClientConnectionGuard {
+    fn drop(&mut self) {
+        let inner = &self.metrics.inner;
+        inner
+            .client_connections_open
+            .fetch_sub(1, Ordering::Relaxed);
+        inner
+            .client_connections_closed
+            .fetch_add(1, Ordering::Relaxed);
     }
 }
 
@@ -264,6 +948,128 @@ mod tests {
         assert_eq!(snap.failed_requests, 1);
     }
 
+    #[tokio::test]
+    async fn test_labeled_series_render_as_prometheus() {
+        let ms = MetricsService::new();
+        let labels = RequestLabels {
+            model: "anthropic--claude-4.5-sonnet".to_string(),
+            family: LlmFamily::Claude,
+            provider: "primary".to_string(),
+            route: "/v1/messages".to_string(),
+            stream: true,
+        };
+        let tokens = TokenCounts {
+            input: 10,
+            output: 5,
+            cache_read: 0,
+            cache_write: 0,
+            ..Default::default()
+        };
+        ms.record_request(
+            RequestSummary::new(labels.clone(), 200, Duration::from_millis(300))
+                .with_tokens(tokens),
+        )
+        .await;
+        ms.record_request(
+            RequestSummary::new(labels, 504, Duration::from_secs(20)).with_error("timed out"),
+        )
+        .await;
+
+        let text = ms.render_prometheus().await;
+        let set = r#"model="anthropic--claude-4.5-sonnet",family="claude",provider="primary",route="/v1/messages",stream="true""#;
+        for line in [
+            format!("acr_requests_total{{{set}}} 2"),
+            format!("acr_request_errors_total{{{set}}} 1"),
+            format!(r#"acr_request_duration_seconds_bucket{{{set},le="0.25"}} 0"#),
+            format!(r#"acr_request_duration_seconds_bucket{{{set},le="0.5"}} 1"#),
+            format!(r#"acr_request_duration_seconds_bucket{{{set},le="30"}} 2"#),
+            format!(r#"acr_request_duration_seconds_bucket{{{set},le="+Inf"}} 2"#),
+            format!(r#"acr_tokens_total{{{set},type="input"}} 10"#),
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {line} in\n{text}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fan_in_histogram_and_attempt_trail() {
+        let ms = MetricsService::new();
+        ms.record_fan_in(0);
+        ms.record_fan_in(1);
+        ms.record_fan_in(3);
+        ms.record_fan_in(9);
+        let text = ms.render_prometheus().await;
+        for line in [
+            r#"acr_upstream_attempts_per_request_bucket{le="1"} 1"#,
+            r#"acr_upstream_attempts_per_request_bucket{le="2"} 1"#,
+            r#"acr_upstream_attempts_per_request_bucket{le="3"} 2"#,
+            r#"acr_upstream_attempts_per_request_bucket{le="5"} 2"#,
+            r#"acr_upstream_attempts_per_request_bucket{le="+Inf"} 3"#,
+            "acr_upstream_attempts_per_request_sum 13",
+            "acr_upstream_attempts_per_request_count 3",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {line} in\n{text}");
+        }
+
+        let labels = RequestLabels {
+            model: "gpt-4o".to_string(),
+            family: LlmFamily::OpenAi,
+            provider: "secondary".to_string(),
+            route: "/v1/chat/completions".to_string(),
+            stream: false,
+        };
+        let prior = [UpstreamAttempt {
+            provider: "primary".to_string(),
+            status: 429,
+            latency_ms: 40,
+        }];
+        let summary = RequestSummary::new(labels, 200, Duration::from_millis(120))
+            .with_prior_attempts(&prior);
+        let providers: Vec<_> = summary
+            .attempts
+            .iter()
+            .map(|a| (a.provider.as_str(), a.status))
+            .collect();
+        assert_eq!(providers, [("primary", 429), ("secondary", 200)]);
+        assert_eq!(summary.attempts[1].latency_ms, 120);
+    }
+
+    #[tokio::test]
+    async fn test_recent_requests_are_bounded_and_newest_first() {
+        let ms = MetricsService::new();
+        let labels = RequestLabels {
+            model: "gpt-4o".to_string(),
+            family: LlmFamily::OpenAi,
+            provider: "primary".to_string(),
+            route: "/v1/chat/completions".to_string(),
+            stream: false,
+        };
+        for i in 0..RECENT_REQUESTS_CAPACITY + 5 {
+            ms.record_request(RequestSummary::new(
+                labels.clone(),
+                200,
+                Duration::from_millis(i as u64),
+            ))
+            .await;
+        }
+        ms.record_request(
+            RequestSummary::new(labels, 502, Duration::from_millis(1))
+                .with_error("x".repeat(MAX_SUMMARY_ERROR_LEN * 2)),
+        )
+        .await;
+
+        let recent = ms.recent_requests();
+        assert_eq!(recent.len(), RECENT_REQUESTS_CAPACITY);
+        assert_eq!(recent[0].status, 502);
+        assert!(!recent[0].success());
+        assert!(recent[0].error.as_ref().unwrap().chars().count() <= MAX_SUMMARY_ERROR_LEN + 1);
+        assert_eq!(recent[1].latency_ms, (RECENT_REQUESTS_CAPACITY + 4) as u64);
+        assert!(recent[1].success());
+
+        let json = serde_json::to_value(&recent[1]).unwrap();
+        assert_eq!(json["family"], "openai");
+        assert_eq!(json["route"], "/v1/chat/completions");
+    }
+
     #[tokio::test]
     async fn test_no_model_not_tracked() {
         let ms = MetricsService::new();
@@ -275,6 +1081,7 @@ mod tests {
                 output: 50,
                 cache_read: 0,
                 cache_write: 0,
+                ..Default::default()
             },
         )
         .await;
@@ -283,6 +1090,27 @@ mod tests {
         assert!(model_usage.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_session_usage_accumulates_per_conversation() {
+        let ms = MetricsService::new();
+        let tokens = TokenCounts {
+            input: 10,
+            output: 5,
+            cache_read: 100,
+            cache_write: 0,
+            ..Default::default()
+        };
+        ms.record_session_usage("conv-a", &tokens).await;
+        ms.record_session_usage("conv-a", &tokens).await;
+        ms.record_session_usage("conv-b", &tokens).await;
+
+        let usage = ms.usage_by_session_id().await;
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage["conv-a"].input, 20);
+        assert_eq!(usage["conv-a"].cache_read, 200);
+        assert_eq!(usage["conv-b"].output, 5);
+    }
+
     #[tokio::test]
     async fn test_subscribe_receives_events() {
         let ms = MetricsService::new();
@@ -292,6 +1120,36 @@ mod tests {
         assert!(matches!(event, MetricsEvent::RequestStarted));
     }
 
+    #[tokio::test]
+    async fn subscribers_receive_the_attempt_record() {
+        let ms = MetricsService::new();
+        let mut rx = ms.subscribe();
+        let labels = RequestLabels {
+            model: "gpt-5".to_string(),
+            family: LlmFamily::OpenAi,
+            provider: "primary".to_string(),
+            route: "/v1/chat/completions".to_string(),
+            stream: false,
+        };
+        ms.record_request(
+            RequestSummary::new(labels.clone(), 200, Duration::from_millis(900))
+                .with_deployment_id("d123")
+                .with_ttfb(Some(Duration::from_millis(400)))
+                .with_cost(Some(0.0125))
+                .with_owner(Some("hash".to_string())),
+        )
+        .await;
+        let MetricsEvent::UpstreamAttempt(summary) = rx.recv().await.unwrap() else {
+            panic!("expected an upstream attempt");
+        };
+        assert_eq!(summary.labels, labels);
+        assert_eq!(summary.deployment_id.as_deref(), Some("d123"));
+        assert_eq!((summary.latency_ms, summary.ttfb_ms), (900, Some(400)));
+        assert_eq!(summary.cost_usd, Some(0.0125));
+        assert_eq!(summary.owner.as_deref(), Some("hash"));
+        assert!(summary.success());
+    }
+
     #[tokio::test]
     async fn guard_increments_on_new_decrements_on_drop() {
         let ms = MetricsService::new();
@@ -0,0 +1,141 @@
+//! Per-model kill switch.
+//!
+//! A disabled model is answered with 503 and an explanation before anything
+//! is sent upstream, while every other model stays live: for when one
+//! deployment misbehaves. Models start disabled when `models[].disabled` is
+//! set, and the internal key switches them at runtime through
+//! `/admin/models/{model}/disabled`. Runtime changes last until a restart.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use crate::config::Model;
+
+/// Why and since when a model is out of service.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Disabled {
+    pub reason: String,
+    pub since: DateTime<Utc>,
+    /// `config` or `admin`
+    pub source: &'static str,
+}
+
+/// Disabled models by configured name.
+#[derive(Clone, Default)]
+pub struct DisabledModels(Arc<RwLock<HashMap<String, Disabled>>>);
+
+impl DisabledModels {
+    pub fn from_config(models: &[Model]) -> Self {
+        let since = Utc::now();
+        let disabled = models
+            .iter()
+            .filter_map(|m| {
+                let reason = m.disabled.clone()?;
+                Some((
+                    m.name.clone(),
+                    Disabled {
+                        reason,
+                        since,
+                        source: "config",
+                    },
+                ))
+            })
+            .collect();
+        Self(Arc::new(RwLock::new(disabled)))
+    }
+
+    /// Whether `model` (a configured name) is disabled, and why.
+    pub fn get(&self, model: &str) -> Option<Disabled> {
+        self.0.read().unwrap().get(model).cloned()
+    }
+
+    /// Take `model` out of service; returns the previous state, if any.
+    pub fn disable(&self, model: &str, reason: String) -> Option<Disabled> {
+        let disabled = Disabled {
+            reason,
+            since: Utc::now(),
+            source: "admin",
+        };
+        self.0.write().unwrap().insert(model.to_string(), disabled)
+    }
+
+    /// Put `model` back in service; returns the state it had, if any.
+    pub fn enable(&self, model: &str) -> Option<Disabled> {
+        self.0.write().unwrap().remove(model)
+    }
+
+    /// Apply `models[].disabled` from a reloaded config. Switches made
+    /// through the admin API stay as they are.
+    pub fn reconfigure(&self, models: &[Model]) {
+        let configured = Self::from_config(models);
+        let configured = configured.0.read().unwrap();
+        let mut disabled = self.0.write().unwrap();
+        disabled.retain(|model, d| d.source == "admin" || configured.contains_key(model));
+        for (model, d) in configured.iter() {
+            if !disabled.contains_key(model) {
+                disabled.insert(model.clone(), d.clone());
+            }
+        }
+    }
+
+    pub fn list(&self) -> BTreeMap<String, Disabled> {
+        self.0
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(model, disabled)| (model.clone(), disabled.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_sets_the_starting_state_and_the_admin_api_changes_it() {
+        let models: Vec<Model> = serde_yaml_ng::from_str(
+            "- name: gpt-4o\n  disabled: bad outputs since 09:00\n- name: gpt-4.1\n",
+        )
+        .unwrap();
+        let switch = DisabledModels::from_config(&models);
+        let disabled = switch.get("gpt-4o").unwrap();
+        assert_eq!(disabled.reason, "bad outputs since 09:00");
+        assert_eq!(disabled.source, "config");
+        assert!(switch.get("gpt-4.1").is_none());
+
+        assert!(switch.disable("gpt-4.1", "testing".to_string()).is_none());
+        assert_eq!(switch.get("gpt-4.1").unwrap().source, "admin");
+        assert_eq!(
+            switch.list().keys().collect::<Vec<_>>(),
+            ["gpt-4.1", "gpt-4o"]
+        );
+        assert_eq!(switch.enable("gpt-4o").unwrap().source, "config");
+        assert!(switch.enable("gpt-4o").is_none());
+        assert_eq!(switch.list().len(), 1);
+    }
+
+    #[test]
+    fn reconfigure_keeps_admin_switches() {
+        let models: Vec<Model> =
+            serde_yaml_ng::from_str("- name: gpt-4o\n  disabled: flaky\n- name: gpt-4.1\n")
+                .unwrap();
+        let switch = DisabledModels::from_config(&models);
+        switch.disable("o3", "testing".to_string());
+        let since = switch.get("gpt-4o").unwrap().since;
+
+        let models: Vec<Model> = serde_yaml_ng::from_str(
+            "- name: gpt-4o\n  disabled: flaky\n- name: gpt-4.1\n  disabled: slow\n",
+        )
+        .unwrap();
+        switch.reconfigure(&models);
+        assert_eq!(switch.get("gpt-4o").unwrap().since, since);
+        assert_eq!(switch.get("gpt-4.1").unwrap().reason, "slow");
+        assert_eq!(switch.get("o3").unwrap().source, "admin");
+
+        switch.reconfigure(&[]);
+        assert_eq!(switch.list().keys().collect::<Vec<_>>(), ["o3"]);
+    }
+}
@@ -0,0 +1,150 @@
+//! Mounting the router behind a shared gateway.
+//!
+//! `mount.path_prefix` serves every route under a prefix, e.g. `/llm` for
+//! `/llm/v1/chat/completions`, so acr can share a gateway path space with
+//! other services. The routes are nested under the prefix rather than
+//! rewritten, so they can't collide with each other whatever the prefix is.
+//!
+//! `mount.hosts` limits the router to requests for the listed host names,
+//! for gateways that forward several virtual hosts to one listener. Other
+//! hosts get 404, as for an unknown path.
+
+use axum::{
+    Router,
+    extract::{Request, State},
+    http::{StatusCode, header::HOST, uri::Authority},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+use crate::config::MountConfig;
+use crate::registry::glob_matches;
+
+/// Apply `mount` to the finished router.
+pub fn apply(router: Router, config: &MountConfig) -> Router {
+    let router = match config.path_prefix.as_deref() {
+        Some(prefix) => Router::new().nest(prefix, router),
+        None => router,
+    };
+    if config.hosts.is_empty() {
+        return router;
+    }
+    let hosts: Arc<[String]> = config
+        .hosts
+        .iter()
+        .map(|h| h.to_ascii_lowercase())
+        .collect();
+    router.layer(axum::middleware::from_fn_with_state(hosts, match_host))
+}
+
+/// Middleware answering 404 for requests whose host isn't in `mount.hosts`.
+async fn match_host(State(hosts): State<Arc<[String]>>, request: Request, next: Next) -> Response {
+    let host = request_host(&request);
+    if host
+        .as_deref()
+        .is_some_and(|host| hosts.iter().any(|p| glob_matches(p, host).is_some()))
+    {
+        return next.run(request).await;
+    }
+    StatusCode::NOT_FOUND.into_response()
+}
+
+/// The request's host name, lowercase and without the port: from the URI
+/// (HTTP/2 `:authority`, absolute-form requests) or else the `Host` header.
+fn request_host(request: &Request) -> Option<String> {
+    let host = match request.uri().host() {
+        Some(host) => host.to_string(),
+        None => {
+            let header = request.headers().get(HOST)?.to_str().ok()?;
+            header.parse::<Authority>().ok()?.host().to_string()
+        }
+    };
+    Some(host.to_ascii_lowercase())
+}
+
+/// Check `mount.path_prefix`: a path starting with `/`, without a trailing
+/// `/` or route syntax.
+pub fn validate_path_prefix(prefix: &str) -> anyhow::Result<()> {
+    if prefix == "/" || prefix.is_empty() {
+        anyhow::bail!("mount.path_prefix is empty; leave it out to serve at the root");
+    }
+    if !prefix.starts_with('/') || prefix.ends_with('/') {
+        anyhow::bail!("mount.path_prefix '{prefix}' must start with '/' and not end with one");
+    }
+    if prefix.contains("//") || prefix.contains(['{', '}', '*', '?', '#', ' ']) {
+        anyhow::bail!(
+            "mount.path_prefix '{prefix}' must be a plain path, without '//', '{{', '}}', '*', '?', '#' or spaces"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get};
+    use tower::ServiceExt;
+
+    fn mounted(yaml: &str) -> Router {
+        let config: MountConfig = serde_yaml_ng::from_str(yaml).unwrap();
+        let router = Router::new()
+            .route("/health", get(|| async { "OK" }))
+            .route("/v1/models", get(|| async { "models" }));
+        apply(router, &config)
+    }
+
+    async fn status(router: Router, uri: &str, host: &str) -> StatusCode {
+        let request = Request::builder()
+            .uri(uri)
+            .header(HOST, host)
+            .body(Body::empty())
+            .unwrap();
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn path_prefix_moves_every_route() {
+        let router = mounted("path_prefix: /llm");
+        assert_eq!(
+            status(router.clone(), "/llm/v1/models", "a").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(router.clone(), "/llm/health", "a").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(router, "/v1/models", "a").await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn hosts_limit_which_requests_are_served() {
+        let router = mounted("hosts: [llm.example.com, '*.llm.internal']");
+        for host in ["llm.example.com", "LLM.example.com:8443", "eu.llm.internal"] {
+            assert_eq!(
+                status(router.clone(), "/health", host).await,
+                StatusCode::OK,
+                "{host}"
+            );
+        }
+        for host in ["api.example.com", "llm.internal"] {
+            assert_eq!(
+                status(router.clone(), "/health", host).await,
+                StatusCode::NOT_FOUND,
+                "{host}"
+            );
+        }
+    }
+
+    #[test]
+    fn path_prefix_must_be_a_plain_path() {
+        assert!(validate_path_prefix("/llm").is_ok());
+        assert!(validate_path_prefix("/gateway/llm").is_ok());
+        for bad in ["", "/", "llm", "/llm/", "/a//b", "/{tenant}", "/llm*"] {
+            assert!(validate_path_prefix(bad).is_err(), "{bad}");
+        }
+    }
+}
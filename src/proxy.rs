@@ -1,23 +1,34 @@
 use anyhow::{Context, Result};
 use axum::{
     body::Body,
-    http::{HeaderMap, HeaderValue, Method, StatusCode},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
     response::Response,
 };
-use futures::stream::StreamExt;
+use futures::{FutureExt, stream::StreamExt};
 use reqwest::Client;
 use serde_json::{Value, json};
+use std::collections::HashMap;
 use std::fmt;
 use std::time::{Duration, Instant};
 use tokio_stream::wrappers::ReceiverStream;
 
 use crate::balancer::LoadBalancer;
-use crate::config::{Config, Provider};
+use crate::capture::{CaptureTarget, StreamCapture};
+use crate::config::{
+    Config, FamilyName, ModelFamilyRule, ModelPricing, Provider, ReasoningContent,
+};
 use crate::constants::{api::*, models::*};
-use crate::metrics::MetricsService;
+use crate::image_fetch::ImageFetcher;
+use crate::metrics::{MetricsService, RequestLabels, RequestSummary, TokenCounts, UpstreamAttempt};
 use crate::registry::ModelRegistry;
 use crate::routes::AppError;
+use crate::stream_pace::OutputPacer;
+use crate::timing::PhaseTimings;
 use crate::token::TokenManager;
+use crate::transforms::documents::UnsupportedContent;
+use crate::transforms::translate::{StreamTranslator, Translation};
+use crate::upstream_limits::UpstreamLimits;
+use tracing::Instrument;
 
 pub fn extract_api_key(headers: &axum::http::HeaderMap) -> Option<String> {
     headers
@@ -34,12 +45,25 @@ pub fn extract_api_key(headers: &axum::http::HeaderMap) -> Option<String> {
         })
 }
 
+/// Whether an opt-in request header (`x-acr-capture`, `x-acr-dry-run`,
+/// `x-acr-metadata`) is set to `true` or `1`.
+pub(crate) fn header_flag(headers: &axum::http::HeaderMap, name: &str) -> bool {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("true") || v.trim() == "1")
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct TokenStats {
     pub input_tokens: Option<u64>,
     pub output_tokens: Option<u64>,
     pub cache_read: Option<u64>,
     pub cache_write: Option<u64>,
+    /// Reasoning tokens, already counted in `output_tokens`
+    pub reasoning: Option<u64>,
+    /// Image input tokens, already counted in `input_tokens`
+    pub image: Option<u64>,
 }
 
 impl fmt::Display for TokenStats {
@@ -64,23 +88,47 @@ impl fmt::Display for TokenStats {
             Some(t) => write!(f, "{}", t)?,
             None => write!(f, "N/A")?,
         }
+        if let Some(t) = self.reasoning {
+            write!(f, ", reasoning: {}", t)?;
+        }
+        if let Some(t) = self.image {
+            write!(f, ", image: {}", t)?;
+        }
         Ok(())
     }
 }
 
 impl TokenStats {
     /// Convert optional token stats to concrete counts (defaulting None to 0).
-    pub fn to_counts(&self) -> crate::metrics::TokenCounts {
-        crate::metrics::TokenCounts {
+    pub fn to_counts(&self) -> TokenCounts {
+        TokenCounts {
             input: self.input_tokens.unwrap_or(0),
             output: self.output_tokens.unwrap_or(0),
             cache_read: self.cache_read.unwrap_or(0),
             cache_write: self.cache_write.unwrap_or(0),
+            reasoning: self.reasoning.unwrap_or(0),
+            image: self.image.unwrap_or(0),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Estimated USD cost of a request. `None` when the model has no price table
+/// or the upstream reported no usage at all.
+pub(crate) fn estimate_cost(pricing: Option<&ModelPricing>, stats: &TokenStats) -> Option<f64> {
+    let pricing = pricing?;
+    if stats.input_tokens.is_none() && stats.output_tokens.is_none() {
+        return None;
+    }
+    Some(pricing.calculate_cost(&stats.to_counts()))
+}
+
+/// Fixed six-decimal rendering: small requests cost fractions of a cent.
+pub(crate) fn format_cost_usd(cost: f64) -> String {
+    format!("{cost:.6}")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum LlmFamily {
     OpenAi,
     /// OpenAI Responses API (`/v1/responses`) — different request shape (`input`
@@ -90,11 +138,34 @@ pub enum LlmFamily {
     OpenAiResponses,
     Claude,
     Gemini,
+    /// Open-weight models (Mistral, Llama) behind AI Core's OpenAI-compatible
+    /// chat endpoint: OpenAI request and response shapes, prepared by
+    /// `transforms::open_weight`, at a URL without `api-version`.
+    OpenWeight,
+    /// A model routed by `model_families` to `passthrough`: the body goes
+    /// upstream as the client sent it, to the model's `url_template`, and
+    /// the response comes back untouched. Never a client family.
+    Passthrough,
+}
+
+impl From<FamilyName> for LlmFamily {
+    fn from(name: FamilyName) -> Self {
+        match name {
+            FamilyName::Openai => LlmFamily::OpenAi,
+            FamilyName::Claude => LlmFamily::Claude,
+            FamilyName::Gemini => LlmFamily::Gemini,
+            FamilyName::OpenWeight => LlmFamily::OpenWeight,
+            FamilyName::Passthrough => LlmFamily::Passthrough,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct ProxyRequest {
     pub family: LlmFamily,
+    /// API shape the client called (fixed by the route). Differs from `family`
+    /// when e.g. an Anthropic client asks `/v1/messages` for a GPT model.
+    pub client_family: LlmFamily,
     pub method: Method,
     pub body: Value,
     pub stream: bool,
@@ -104,7 +175,39 @@ pub struct ProxyRequest {
     pub original_model: String, // Original requested model name
     pub provider_name: String,  // Provider handling this request
     pub resource_group: String,
+    /// Static headers configured for the provider (`providers[].headers`).
+    pub provider_headers: HashMap<String, String>,
     pub anthropic_beta: Vec<String>, // Bedrock-mapped beta features from Anthropic-Beta header
+    /// Set when `client_family` and `family` differ and acr bridges the pair;
+    /// drives response / stream translation back into the client's schema.
+    pub translation: Option<Translation>,
+    /// Conversation this request belongs to, if known (see `session`).
+    pub session_id: Option<String>,
+    /// Configured price table for the model, used for `x-acr-cost-usd`.
+    pub pricing: Option<ModelPricing>,
+    /// Route the client called; a metrics label.
+    pub route: String,
+    /// Set when the client asked for a stream transcript (see `capture`).
+    pub capture: Option<CaptureTarget>,
+    /// Client parameters the translation dropped; echoed in
+    /// `x-acr-dropped-params`.
+    pub dropped_params: Vec<&'static str>,
+    /// Providers this client request already failed on, set by the fallback
+    /// loop before each attempt.
+    pub prior_attempts: Vec<UpstreamAttempt>,
+    /// AI Core deployment the request is sent to.
+    pub deployment_id: String,
+    /// Where upstream rate-limit headers are recorded, when tracked.
+    pub upstream_limits: Option<UpstreamLimits>,
+    /// Paces streamed output per API key, when configured.
+    pub output_pacer: Option<OutputPacer>,
+    /// How long building this request took, phase by phase.
+    pub timings: PhaseTimings,
+    /// The client asked for `x-acr` metadata in the response body.
+    pub metadata: bool,
+    /// Most continuation requests for a truncated non-streaming response;
+    /// `None` unless `continuation.enabled`.
+    pub max_continuations: Option<u32>,
 }
 
 /// Input parameters for building a ProxyRequest
@@ -120,11 +223,35 @@ pub struct ProxyRequestParams<'a> {
     pub model_registry: &'a ModelRegistry,
     pub load_balancer: &'a LoadBalancer,
     /// Route-determined family override. When set, takes priority over
-    /// `determine_family(model)`. Used when the route uniquely determines the
+    /// `determine_family(model, &[])`. Used when the route uniquely determines the
     /// family regardless of model name — e.g. `/v1/responses` is OpenAI-only
     /// (Responses API), so `handle_openai_responses` sets this to
     /// `Some(LlmFamily::OpenAiResponses)`. Other routes leave it `None`.
     pub force_family: Option<LlmFamily>,
+    /// API shape the client speaks, determined by the route. Used to re-shape
+    /// upstream error bodies when the upstream family differs (see
+    /// `transforms::error_shape`).
+    pub client_family: LlmFamily,
+    /// Client-supplied Azure `api-version` (from the Azure-style routes'
+    /// query string). Overrides `config.openai_api_version` for this request
+    /// so Azure SDKs get the API surface they were built against.
+    pub api_version: Option<String>,
+    /// Conversation ID resolved by `session::extract_session_id`.
+    pub session_id: Option<String>,
+    /// Route template the request came in on (e.g. `/v1/messages`).
+    pub route: &'a str,
+    /// Stream transcript requested with `x-acr-capture`.
+    pub capture: Option<CaptureTarget>,
+    /// Downloads remote images for translated requests; `None` unless
+    /// `image_fetch.enabled`.
+    pub image_fetcher: Option<&'a ImageFetcher>,
+    /// Whether translated responses may carry the model's reasoning, from
+    /// the API key's `reasoning` setting.
+    pub reasoning: ReasoningContent,
+    /// Upstream rate-limit tracker; `None` unless `upstream_limits.enabled`.
+    pub upstream_limits: Option<&'a UpstreamLimits>,
+    /// Streamed output pacing; `None` unless an output rate is configured.
+    pub output_pacer: Option<&'a OutputPacer>,
 }
 
 /// Builder for ProxyRequest with step-by-step validation
@@ -140,27 +267,119 @@ impl<'a> ProxyRequestBuilder<'a> {
     /// Build a proxy request for a specific provider.
     /// This is used for 429 fallback - try providers in order until one succeeds.
     pub async fn build_for_provider(&self, provider: &Provider) -> Result<ProxyRequest, AppError> {
+        let mut timings = PhaseTimings::default();
+
         // Step 1: Extract and validate API key
+        let auth_start = Instant::now();
         let api_key = self.extract_api_key()?;
+        if !self.params.token_manager.is_valid_api_key(&api_key) {
+            return Err(AppError::InvalidApiKey);
+        }
+        timings.auth = Some(auth_start.elapsed());
 
         // Step 2: Get authentication token for this provider
-        let token = self.get_auth_token(&api_key, provider).await?;
+        let (token, elapsed) = PhaseTimings::time(self.get_auth_token(&api_key, provider)).await;
+        let token = token?;
+        timings.token = Some(elapsed);
 
         // Step 3: Resolve model and deployment for this provider
-        let (normalized_model, deployment_id) = self.resolve_model_for_provider(provider).await?;
+        let (resolved, elapsed) =
+            PhaseTimings::time(self.resolve_model_for_provider(provider)).await;
+        let (normalized_model, deployment_id) = resolved?;
+        timings.resolve = Some(elapsed);
 
         // Step 4: Determine LLM family and stream flag.
         // Route-driven override takes priority — used by routes that are tied
         // to a specific API shape regardless of model name (e.g. /v1/responses).
         let family = match self.params.force_family {
             Some(f) => f,
-            None => determine_family(&normalized_model)?,
+            None => determine_family(&normalized_model, &self.params.config.model_families)?,
+        };
+
+        // Step 4a: Chat Completions clients get their `logprobs` / `seed`
+        // checked whichever backend serves them. Legacy Completions has an
+        // integer `logprobs` of its own.
+        if matches!(self.params.client_family, LlmFamily::OpenAi)
+            && self.params.action.as_deref() != Some(LEGACY_COMPLETIONS_ACTION)
+        {
+            crate::transforms::openai::validate_sampling_params(&self.params.body)
+                .map_err(AppError::BadRequest)?;
+        }
+
+        // Step 4b: Bridge client and upstream schemas when they differ. The
+        // stream flag then follows the client's conventions, and a Gemini
+        // upstream gets its action from that flag instead of from the URL.
+        let translation = Translation::select(
+            self.params.client_family,
+            family,
+            &self.params.body,
+            self.params.reasoning,
+        );
+        let (stream, action) = match translation {
+            Some(_) => {
+                let stream = extract_stream_flag(
+                    &self.params.body,
+                    &self.params.client_family,
+                    &self.params.action,
+                );
+                let action = match family {
+                    LlmFamily::Gemini => Some(
+                        if stream {
+                            STREAM_GENERATE_CONTENT_ACTION
+                        } else {
+                            GENERATE_CONTENT_ACTION
+                        }
+                        .to_string(),
+                    ),
+                    _ => self.params.action.clone(),
+                };
+                (stream, action)
+            }
+            None => (
+                extract_stream_flag(&self.params.body, &family, &self.params.action),
+                self.params.action.clone(),
+            ),
         };
-        let stream = extract_stream_flag(&self.params.body, &family, &self.params.action);
 
         // Step 5: Prepare request body
         let mut body = self.params.body.clone();
-        prepare_body(&mut body, &family, stream, &normalized_model)?;
+        let mut dropped_params = Vec::new();
+        if let Some(translation) = translation {
+            if let Some(fetcher) = self.params.image_fetcher {
+                fetcher
+                    .inline_remote_images(&mut body, self.params.client_family)
+                    .await
+                    .map_err(|e| AppError::BadRequest(format!("{e:#}")))?;
+            }
+            dropped_params = translation.request(&mut body).map_err(|e| match e
+                .downcast_ref::<UnsupportedContent>()
+            {
+                Some(_) => AppError::UnsupportedMediaType(e.to_string()),
+                None => AppError::BadRequest(e.to_string()),
+            })?;
+            if !dropped_params.is_empty() {
+                tracing::warn!(
+                    "Dropped parameters with no {:?} equivalent: {}",
+                    family,
+                    dropped_params.join(", ")
+                );
+            }
+        }
+        if matches!(family, LlmFamily::Gemini) {
+            crate::transforms::gemini::apply_safety_settings(
+                &mut body,
+                self.params.config.safety_settings(&normalized_model),
+            );
+        }
+        prepare_body(
+            &mut body,
+            &family,
+            stream,
+            &normalized_model,
+            self.params.config.aicore_model_name(&normalized_model),
+            &action,
+            self.params.config.default_max_tokens(&normalized_model),
+        )?;
 
         // Step 6: Extract Anthropic-Beta header and convert to Bedrock beta features
         let mut anthropic_beta = if matches!(family, LlmFamily::Claude) {
@@ -196,19 +415,48 @@ impl<'a> ProxyRequestBuilder<'a> {
             obj.insert("anthropic_beta".to_string(), json!(anthropic_beta));
         }
 
-        // Step 8: Build target URL using the provider's API URL
-        let url = build_url(
-            &normalized_model,
-            &deployment_id,
-            &self.params.action,
-            &provider.genai_api_url,
-            &family,
-            stream,
-            &self.params.config.openai_api_version,
-        )?;
+        // Step 8: Build target URL using the provider's API URL, from the
+        // model's template when it has one
+        let api_version = self
+            .params
+            .api_version
+            .as_deref()
+            .unwrap_or(&self.params.config.openai_api_version);
+        let url = match self.params.config.url_template(&normalized_model) {
+            Some(template) => {
+                let path = render_url_template(
+                    template,
+                    &[
+                        ("deployment_id", &deployment_id),
+                        ("model", &normalized_model),
+                        ("action", template_action(&family, &action, stream)),
+                        ("api_version", api_version),
+                    ],
+                )
+                .map_err(AppError::Internal)?;
+                format!("{}{path}", provider.genai_api_url)
+            }
+            None => build_url(
+                &normalized_model,
+                &deployment_id,
+                &action,
+                &provider.genai_api_url,
+                &family,
+                stream,
+                api_version,
+            )?,
+        };
+
+        let pricing = self
+            .params
+            .config
+            .get_model_pricing(&normalized_model)
+            .or_else(|| self.params.config.get_model_pricing(&self.params.model))
+            .cloned();
 
         Ok(ProxyRequest {
             family,
+            client_family: self.params.client_family,
             method: self.params.method.clone(),
             body,
             stream,
@@ -218,7 +466,26 @@ impl<'a> ProxyRequestBuilder<'a> {
             original_model: self.params.model.clone(),
             provider_name: provider.name.clone(),
             resource_group: provider.resource_group.clone(),
+            provider_headers: provider.headers.clone(),
             anthropic_beta,
+            translation,
+            session_id: self.params.session_id.clone(),
+            pricing,
+            route: self.params.route.to_string(),
+            capture: self.params.capture.clone(),
+            dropped_params,
+            prior_attempts: Vec::new(),
+            deployment_id,
+            upstream_limits: self.params.upstream_limits.cloned(),
+            output_pacer: self.params.output_pacer.cloned(),
+            timings,
+            metadata: header_flag(self.params.headers, ACR_METADATA_HEADER),
+            max_continuations: self
+                .params
+                .config
+                .continuation
+                .enabled
+                .then_some(self.params.config.continuation.max_continuations),
         })
     }
 
@@ -263,51 +530,131 @@ impl<'a> ProxyRequestBuilder<'a> {
 }
 
 /// Result of executing a proxy request, indicating if fallback should be attempted
+#[allow(clippy::large_enum_variant)]
 pub enum ProxyExecuteResult {
     /// Request succeeded or failed with non-retriable error
     Response {
         response: Response,
         token_stats: TokenStats,
     },
-    /// Got 429 rate limit - should try next provider
-    RateLimited,
+    /// Got 429 rate limit - should try next provider. Carries the upstream
+    /// `Retry-After` hint (in seconds) when one was sent, so the error
+    /// surfaced to the client after fallback can preserve it.
+    RateLimited { retry_after_secs: Option<u64> },
+}
+
+/// Headers every upstream call carries: the provider's static headers, then
+/// the provider token, resource group and client type.
+pub(crate) fn upstream_headers(
+    token: &str,
+    resource_group: &str,
+    provider_headers: &HashMap<String, String>,
+) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in provider_headers {
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    headers.insert(
+        "authorization",
+        HeaderValue::from_str(&format!("Bearer {token}"))?,
+    );
+    headers.insert("ai-resource-group", HeaderValue::from_str(resource_group)?);
+    headers.insert("content-type", HeaderValue::from_static("application/json"));
+    headers.insert(
+        AI_CLIENT_TYPE_HEADER,
+        HeaderValue::from_static(AI_CLIENT_TYPE_VALUE),
+    );
+    Ok(headers)
 }
 
-/// Optional database context for request logging.
+/// Write the summary of the attempt that answered the client to the usage
+/// store, if one is configured. Attempts the router failed over from are
+/// only in metrics.
 #[cfg(feature = "db")]
-#[derive(Clone)]
-pub struct DbContext {
-    pub database: crate::database::Database,
-    pub request_path: String,
-    pub api_key_hash: Option<String>,
+fn log_request(database: Option<&crate::database::Database>, summary: &RequestSummary) {
+    let Some(db) = database.cloned() else {
+        return;
+    };
+    let record = crate::database::RequestRecord::from(summary);
+    tokio::spawn(async move {
+        if let Err(e) = db.insert_request(record).await {
+            tracing::warn!("Failed to log request to database: {}", e);
+        }
+    });
 }
 
 impl ProxyRequest {
+    /// What would be sent upstream, for `x-acr-dry-run`. The token is left
+    /// out, and so are the AI Core URL, resource group and deployment
+    /// unless `show_upstream`: like in error responses, they're only for
+    /// the operator.
+    pub fn dry_run(&self, show_upstream: bool) -> Value {
+        let mut dry_run = json!({
+            "provider": self.provider_name,
+            "model": self.model,
+            "family": crate::metrics::family_label(self.family),
+            "stream": self.stream,
+            "method": self.method.as_str(),
+            "dropped_params": self.dropped_params,
+            "body": self.body,
+        });
+        if show_upstream {
+            dry_run["resource_group"] = json!(self.resource_group);
+            dry_run["deployment_id"] = json!(self.deployment_id);
+            dry_run["url"] = json!(self.url);
+        }
+        dry_run
+    }
+
+    /// Summary of an attempt on this request, for metrics and `/admin/recent`.
+    pub fn summary(
+        &self,
+        status: u16,
+        latency: Duration,
+        owner: &Option<String>,
+    ) -> RequestSummary {
+        RequestSummary::new(self.metric_labels(), status, latency)
+            .with_deployment_id(&self.deployment_id)
+            .with_session_id(self.session_id.clone())
+            .with_owner(owner.clone())
+            .with_prior_attempts(&self.prior_attempts)
+    }
+
+    /// This client request's attempts so far, ending with this one.
+    pub fn attempts(&self, status: u16, latency: Duration) -> Vec<UpstreamAttempt> {
+        let mut attempts = self.prior_attempts.clone();
+        attempts.push(UpstreamAttempt {
+            provider: self.provider_name.clone(),
+            status,
+            latency_ms: latency.as_millis() as u64,
+        });
+        attempts
+    }
+
+    pub fn metric_labels(&self) -> RequestLabels {
+        RequestLabels {
+            model: self.model.clone(),
+            family: self.family,
+            provider: self.provider_name.clone(),
+            route: self.route.clone(),
+            stream: self.stream,
+        }
+    }
+
     pub async fn execute(
         &self,
         client: &Client,
         metrics: &MetricsService,
         active_guard: &mut Option<crate::metrics::ActiveRequestGuard>,
-        #[cfg(feature = "db")] db_context: Option<DbContext>,
+        #[cfg(feature = "db")] database: Option<crate::database::Database>,
         quota_manager: Option<crate::quota::QuotaManager>,
         api_key_hash: Option<String>,
     ) -> Result<ProxyExecuteResult> {
         let start_time = Instant::now();
-
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            "authorization",
-            HeaderValue::from_str(&format!("Bearer {}", self.token))?,
-        );
-        headers.insert(
-            "ai-resource-group",
-            HeaderValue::from_str(&self.resource_group)?,
-        );
-        headers.insert("content-type", HeaderValue::from_static("application/json"));
-        headers.insert(
-            AI_CLIENT_TYPE_HEADER,
-            HeaderValue::from_static(AI_CLIENT_TYPE_VALUE),
-        );
+        let headers = self.upstream_headers()?;
 
         tracing::debug!(
             "Proxying request to: {} (model: {}, stream: {})",
@@ -315,6 +662,12 @@ impl ProxyRequest {
             self.model,
             self.stream
         );
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            tracing::debug!(
+                "Upstream request body: {}",
+                body_preview(&crate::redact::for_log(&self.body))
+            );
+        }
 
         let response = client
             .request(self.method.clone(), &self.url)
@@ -323,6 +676,19 @@ impl ProxyRequest {
             .send()
             .await
             .context("Failed to send proxy request")?;
+        let timings = PhaseTimings {
+            ttfb: Some(start_time.elapsed()),
+            ..self.timings
+        };
+
+        if let Some(ref limits) = self.upstream_limits {
+            limits.observe(
+                &self.provider_name,
+                &self.deployment_id,
+                response.status(),
+                response.headers(),
+            );
+        }
 
         if !response.status().is_success() {
             let elapsed = start_time.elapsed();
@@ -330,16 +696,25 @@ impl ProxyRequest {
 
             // Check for rate limiting - signal to try next provider
             if status == StatusCode::TOO_MANY_REQUESTS {
+                let retry_after_secs = parse_retry_after(response.headers());
                 let body = response.text().await.unwrap_or_default();
                 tracing::warn!(
-                    "Rate limited (429) on original_model: {}, resolved_model: {}, provider: {}, time: {:.2}ms, body_len: {}",
+                    "Rate limited (429) on original_model: {}, resolved_model: {}, provider: {}, time: {:.2}ms, body_len: {}, retry_after: {:?}",
                     self.original_model,
                     self.model,
                     self.provider_name,
                     elapsed.as_secs_f64() * 1000.0,
-                    body.len()
+                    body.len(),
+                    retry_after_secs
                 );
-                return Ok(ProxyExecuteResult::RateLimited);
+                metrics
+                    .record_request(
+                        self.summary(429, elapsed, &api_key_hash)
+                            .with_ttfb(timings.ttfb)
+                            .with_error("Rate limited by upstream"),
+                    )
+                    .await;
+                return Ok(ProxyExecuteResult::RateLimited { retry_after_secs });
             }
 
             // Preserve the upstream content-type instead of hardcoding JSON
@@ -350,20 +725,44 @@ impl ProxyRequest {
             });
 
             tracing::error!("Proxy request failed: {} - {}", status, text);
+
+            // Re-shape the error into the client's schema when the upstream
+            // family differs; non-JSON bodies still pass through verbatim.
+            let (content_type, text) = match crate::transforms::error_shape::translate(
+                status,
+                &text,
+                self.family,
+                self.client_family,
+            ) {
+                Some(translated) => ("application/json".to_string(), translated.to_string()),
+                None => (content_type, text),
+            };
             tracing::info!(
-                "Proxy done - original_model: {}, resolved_model: {}, provider: {}, time: {:.2}ms, status: {}, stream: {}",
+                "Proxy done - original_model: {}, resolved_model: {}, provider: {}, time: {:.2}ms, status: {}, stream: {}, session: {}, attempts: {}, timings: {}",
                 self.original_model,
                 self.model,
                 self.provider_name,
                 elapsed.as_secs_f64() * 1000.0,
                 status,
-                self.stream
+                self.stream,
+                self.session_id.as_deref().unwrap_or("-"),
+                self.prior_attempts.len() + 1,
+                timings
             );
+            let summary = self
+                .summary(status.as_u16(), elapsed, &api_key_hash)
+                .with_ttfb(timings.ttfb)
+                .with_error(text.clone());
+            #[cfg(feature = "db")]
+            log_request(database.as_ref(), &summary);
+            metrics.record_request(summary).await;
+            let mut response = Response::builder()
+                .status(status)
+                .header("content-type", content_type)
+                .body(Body::from(text))?;
+            timings.insert_header(response.headers_mut());
             return Ok(ProxyExecuteResult::Response {
-                response: Response::builder()
-                    .status(status)
-                    .header("content-type", content_type)
-                    .body(Body::from(text))?,
+                response,
                 token_stats: TokenStats::default(),
             });
         }
@@ -391,18 +790,36 @@ impl ProxyRequest {
                         self.provider_name,
                         start_time.elapsed().as_secs_f64() * 1000.0
                     );
-                    return Ok(ProxyExecuteResult::RateLimited);
+                    if let Some(ref limits) = self.upstream_limits {
+                        limits.throttled(&self.provider_name, &self.deployment_id, None);
+                    }
+                    metrics
+                        .record_request(
+                            self.summary(429, start_time.elapsed(), &api_key_hash)
+                                .with_ttfb(timings.ttfb)
+                                .with_error("Rate limited mid-stream by upstream"),
+                        )
+                        .await;
+                    // In-band throttling events arrive on a 200 response, so
+                    // there is no `Retry-After` header to preserve.
+                    return Ok(ProxyExecuteResult::RateLimited {
+                        retry_after_secs: None,
+                    });
                 }
                 PeekOutcome::Transport(e) => {
-                    return Err(anyhow::anyhow!("upstream stream error during peek: {}", e));
+                    // Keep the `reqwest::Error` in the chain (rather than
+                    // formatting it into a string) so the route layer can
+                    // classify it as a timeout vs. connection failure.
+                    return Err(anyhow::Error::new(e).context("upstream stream error during peek"));
                 }
                 PeekOutcome::Committed | PeekOutcome::PeekTimeout | PeekOutcome::StreamEnded => {}
             }
 
-            let response = self.handle_streaming_response(
+            let mut response = self.handle_streaming_response(
                 PreparedStream {
                     stream: byte_stream,
                     prebuffered,
+                    timings,
                 },
                 start_time,
                 metrics,
@@ -410,10 +827,12 @@ impl ProxyRequest {
                     .take()
                     .expect("active_guard must be Some on streaming success path"),
                 #[cfg(feature = "db")]
-                db_context,
+                database,
                 quota_manager,
                 api_key_hash,
             )?;
+            self.mark_dropped_params(&mut response);
+            timings.insert_header(response.headers_mut());
             // The body now owns the guard; `active_requests` decrements when
             // axum drops the body (client done, disconnect, or error).
             // Token-stat / quota recording still happens inside the spawned
@@ -423,17 +842,37 @@ impl ProxyRequest {
                 token_stats: TokenStats::default(),
             })
         } else {
-            let (result, token_stats) = self.handle_regular_response(response).await?;
+            let (mut result, token_stats) = self.handle_regular_response(client, response).await?;
             let elapsed = start_time.elapsed();
+            let cost = estimate_cost(self.pricing.as_ref(), &token_stats);
+            if let Some(cost) = cost
+                && let Ok(value) = HeaderValue::from_str(&format_cost_usd(cost))
+            {
+                result.headers_mut().insert(ACR_COST_USD_HEADER, value);
+            }
+            self.mark_dropped_params(&mut result);
+            timings.insert_header(result.headers_mut());
             tracing::info!(
-                "Proxy done - original_model: {}, resolved_model: {}, provider: {}, time: {:.2}ms, status: 200, stream: {}, {}",
+                "Proxy done - original_model: {}, resolved_model: {}, provider: {}, time: {:.2}ms, status: 200, stream: {}, session: {}, attempts: {}, {}, cost_usd: {}, timings: {}",
                 self.original_model,
                 self.model,
                 self.provider_name,
                 elapsed.as_secs_f64() * 1000.0,
                 self.stream,
-                token_stats
+                self.session_id.as_deref().unwrap_or("-"),
+                self.prior_attempts.len() + 1,
+                token_stats,
+                cost.map(format_cost_usd).as_deref().unwrap_or("-"),
+                timings
             );
+            let summary = self
+                .summary(200, elapsed, &api_key_hash)
+                .with_ttfb(timings.ttfb)
+                .with_tokens(token_stats.to_counts())
+                .with_cost(cost);
+            #[cfg(feature = "db")]
+            log_request(database.as_ref(), &summary);
+            metrics.record_request(summary).await;
             Ok(ProxyExecuteResult::Response {
                 response: result,
                 token_stats,
@@ -441,18 +880,69 @@ impl ProxyRequest {
         }
     }
 
+    pub(crate) fn upstream_headers(&self) -> Result<HeaderMap> {
+        upstream_headers(&self.token, &self.resource_group, &self.provider_headers)
+    }
+
+    /// The `x-acr` object added for `x-acr-metadata`.
+    fn response_metadata(&self, token_stats: &TokenStats) -> Value {
+        let cache_read = token_stats.cache_read.unwrap_or(0);
+        let cache_write = token_stats.cache_write.unwrap_or(0);
+        let cache_status = if cache_read > 0 {
+            "hit"
+        } else if cache_write > 0 {
+            "write"
+        } else {
+            "miss"
+        };
+        json!({
+            "model": self.model,
+            "provider": self.provider_name,
+            "deployment_id": self.deployment_id,
+            "retries": self.prior_attempts.len(),
+            "cache": {
+                "status": cache_status,
+                "read_tokens": cache_read,
+                "write_tokens": cache_write,
+            },
+        })
+    }
+
+    fn mark_dropped_params(&self, response: &mut Response) {
+        if !self.dropped_params.is_empty()
+            && let Ok(value) = HeaderValue::from_str(&self.dropped_params.join(", "))
+        {
+            response
+                .headers_mut()
+                .insert(ACR_DROPPED_PARAMS_HEADER, value);
+        }
+    }
+
     async fn handle_regular_response(
         &self,
+        client: &Client,
         response: reqwest::Response,
     ) -> Result<(Response, TokenStats)> {
         let content_type = extract_content_type(&response);
 
         let body = response.bytes().await?;
+        let (body, continuations) = match self.max_continuations {
+            Some(max) => self.continue_truncated(client, body, max).await,
+            None => (body, 0),
+        };
 
         // Extract token stats from non-streaming response
         let token_stats = match std::str::from_utf8(&body) {
             Ok(body_str) => {
                 tracing::debug!("Response received: {} bytes", body.len());
+                if tracing::enabled!(tracing::Level::DEBUG)
+                    && let Ok(json) = serde_json::from_str::<Value>(body_str)
+                {
+                    tracing::debug!(
+                        "Upstream response body: {}",
+                        body_preview(&crate::redact::for_log(&json))
+                    );
+                }
                 match extract_token_stats_from_body(body_str, &self.family) {
                     Some(stats) => stats,
                     None => {
@@ -475,13 +965,93 @@ impl ProxyRequest {
             }
         };
 
-        Ok((
-            Response::builder()
-                .status(StatusCode::OK)
-                .header("content-type", content_type)
-                .body(Body::from(body))?,
-            token_stats,
-        ))
+        let (content_type, body) = match self.translation {
+            Some(translation) => (
+                "application/json".to_string(),
+                axum::body::Bytes::from(translation.response(&body, &self.original_model)),
+            ),
+            None => (content_type, body),
+        };
+        let body = if self.metadata {
+            insert_metadata(body, self.response_metadata(&token_stats))
+        } else {
+            body
+        };
+
+        let mut response = Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", content_type);
+        if continuations > 0 {
+            response = response.header(ACR_CONTINUATIONS_HEADER, continuations);
+        }
+        Ok((response.body(Body::from(body))?, token_stats))
+    }
+
+    /// Continue a response cut off at the output token limit, up to `max`
+    /// times, and stitch the pieces together (see `continuation`). Returns
+    /// the body and the number of continuations. A failed continuation ends
+    /// the answer where it got to.
+    async fn continue_truncated(
+        &self,
+        client: &Client,
+        body: axum::body::Bytes,
+        max: u32,
+    ) -> (axum::body::Bytes, u32) {
+        let Ok(mut stitched) = serde_json::from_slice::<Value>(&body) else {
+            return (body, 0);
+        };
+        let mut continuations = 0;
+        while continuations < max {
+            let Some(request) =
+                crate::continuation::next_request(self.family, &self.body, &stitched)
+            else {
+                break;
+            };
+            match self.send_continuation(client, &request).await {
+                Ok(next) => crate::continuation::stitch(self.family, &mut stitched, &next),
+                Err(e) => {
+                    tracing::warn!(
+                        "Continuation of truncated response from provider '{}' failed: {:#}",
+                        self.provider_name,
+                        e
+                    );
+                    break;
+                }
+            }
+            continuations += 1;
+        }
+        if continuations == 0 {
+            return (body, 0);
+        }
+        tracing::info!(
+            "Continued truncated response for model '{}' {} time(s)",
+            self.model,
+            continuations
+        );
+        (axum::body::Bytes::from(stitched.to_string()), continuations)
+    }
+
+    async fn send_continuation(&self, client: &Client, body: &Value) -> Result<Value> {
+        let response = client
+            .request(self.method.clone(), &self.url)
+            .headers(self.upstream_headers()?)
+            .json(body)
+            .send()
+            .await
+            .context("Failed to send continuation request")?;
+        if let Some(ref limits) = self.upstream_limits {
+            limits.observe(
+                &self.provider_name,
+                &self.deployment_id,
+                response.status(),
+                response.headers(),
+            );
+        }
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("upstream answered {status}");
+        }
+        Ok(response.json().await?)
     }
 
     // Eight parameters — each is a distinct request-scoped concern (upstream
@@ -494,28 +1064,52 @@ impl ProxyRequest {
         prepared: PreparedStream,
         start_time: Instant,
         metrics: &MetricsService,
-        active_guard: crate::metrics::ActiveRequestGuard,
-        #[cfg(feature = "db")] db_context: Option<DbContext>,
+        mut active_guard: crate::metrics::ActiveRequestGuard,
+        #[cfg(feature = "db")] database: Option<crate::database::Database>,
         quota_manager: Option<crate::quota::QuotaManager>,
         api_key_hash: Option<String>,
     ) -> Result<Response> {
+        active_guard.mark_stream();
         let (tx, rx) = tokio::sync::mpsc::channel::<Result<axum::body::Bytes, reqwest::Error>>(64);
         let is_claude = matches!(self.family, LlmFamily::Claude);
         let model = self.model.clone();
         let original_model = self.original_model.clone();
         let provider_name = self.provider_name.clone();
         let family = self.family;
+        let session_id = self.session_id.clone();
+        let pricing = self.pricing.clone();
+        let labels = self.metric_labels();
+        let deployment_id = self.deployment_id.clone();
+        let prior_attempts = self.prior_attempts.clone();
+        let panic_context = labels.clone();
+        let client_family = self.client_family;
+        let capture_id = self.capture.as_ref().map(|c| c.id.clone());
+        let pace = self
+            .output_pacer
+            .as_ref()
+            .zip(api_key_hash.as_deref())
+            .and_then(|(pacer, kh)| pacer.for_key(kh));
+        let mut capture = self
+            .capture
+            .clone()
+            .map(|target| StreamCapture::new(target, &labels, client_family));
+        let panic_tx = tx.clone();
+        let panic_session = self.session_id.clone();
+        let panic_owner = api_key_hash.clone();
+        let panic_metrics = metrics.clone();
+        let mut translator = self.translation.map(|t| t.stream(&self.original_model));
         let metrics = metrics.clone();
         let PreparedStream {
             mut stream,
             prebuffered,
+            mut timings,
         } = prepared;
 
-        tokio::spawn(async move {
-            // Seed `byte_buf` with whatever the peek phase pulled from the
+        let forwarder = async move {
+            // Seed `lines` with whatever the peek phase pulled from the
             // upstream stream — those bytes were not consumed destructively
             // and the same line-extraction logic below picks them up first.
-            let mut byte_buf: Vec<u8> = prebuffered;
+            let mut lines = SseLines::new(prebuffered);
             let mut token_stats = TokenStats::default();
             let mut client_gone = false;
             let mut stream_error = false;
@@ -525,29 +1119,32 @@ impl ProxyRequest {
             // signals replaced post-peek by a normal event, etc.) could be
             // mistaken for an idle stall.
             loop {
-                while let Some(pos) = byte_buf.iter().position(|&b| b == b'\n') {
-                    let line_bytes = byte_buf[..pos].to_vec();
-                    byte_buf.drain(..pos + 1);
-
-                    let line = match String::from_utf8(line_bytes) {
-                        Ok(s) => s,
-                        Err(e) => {
-                            tracing::warn!("Non-UTF-8 line in stream, skipping: {}", e);
-                            continue;
-                        }
-                    };
-                    let line = line.trim();
-
-                    if let Some(data) = line.strip_prefix(STREAM_DATA_PREFIX)
-                        && !data.is_empty()
-                    {
-                        let bytes = format_sse_event(data, &family, is_claude, &mut token_stats);
-                        if tx.send(Ok(bytes)).await.is_err() {
-                            tracing::debug!("Client disconnected during streaming");
-                            client_gone = true;
-                            break;
+                while let Some(data) = lines.next_data() {
+                    let bytes = format_sse_event(
+                        &data,
+                        &family,
+                        is_claude,
+                        &mut token_stats,
+                        translator.as_mut(),
+                    );
+                    if let Some(ref mut capture) = capture {
+                        capture.upstream(&data);
+                        capture.client(&bytes);
+                    }
+                    if bytes.is_empty() {
+                        continue;
+                    }
+                    if let Some(ref pace) = pace {
+                        let wait = pace.reserve(crate::stream_pace::estimate_output_tokens(&data));
+                        if !wait.is_zero() {
+                            tokio::time::sleep(wait).await;
                         }
                     }
+                    if tx.send(Ok(bytes)).await.is_err() {
+                        tracing::debug!("Client disconnected during streaming");
+                        client_gone = true;
+                        break;
+                    }
                 }
                 if client_gone {
                     break;
@@ -559,7 +1156,7 @@ impl ProxyRequest {
                 };
                 match chunk_result {
                     Ok(chunk) => {
-                        byte_buf.extend_from_slice(&chunk);
+                        lines.push(&chunk);
                     }
                     Err(e) => {
                         tracing::error!("Stream error: {}", e);
@@ -573,16 +1170,35 @@ impl ProxyRequest {
             // Flush any remaining buffered data — a tail without a trailing
             // newline. Mirrors the main-loop formatting so a final partial
             // Claude event still gets its `event: <type>` prefix.
+            if !client_gone && let Some(data) = lines.finish() {
+                let bytes = format_sse_event(
+                    &data,
+                    &family,
+                    is_claude,
+                    &mut token_stats,
+                    translator.as_mut(),
+                );
+                if let Some(ref mut capture) = capture {
+                    capture.upstream(&data);
+                    capture.client(&bytes);
+                }
+                if !bytes.is_empty() {
+                    let _ = tx.send(Ok(bytes)).await;
+                }
+            }
+
+            // Translated streams close with the client schema's own trailer
+            // (e.g. OpenAI's usage chunk and `[DONE]` sentinel).
             if !client_gone
-                && !byte_buf.is_empty()
-                && let Ok(remaining) = String::from_utf8(byte_buf)
+                && !stream_error
+                && let Some(translator) = translator.as_mut()
             {
-                let line = remaining.trim();
-                if let Some(data) = line.strip_prefix(STREAM_DATA_PREFIX)
-                    && !data.is_empty()
-                {
-                    let bytes = format_sse_event(data, &family, is_claude, &mut token_stats);
-                    let _ = tx.send(Ok(bytes)).await;
+                let tail = translator.finish();
+                if let Some(ref mut capture) = capture {
+                    capture.client(tail.as_bytes());
+                }
+                if !tail.is_empty() {
+                    let _ = tx.send(Ok(axum::body::Bytes::from(tail))).await;
                 }
             }
 
@@ -595,41 +1211,100 @@ impl ProxyRequest {
             metrics
                 .record_completion(success, Some(&model), &counts)
                 .await;
+            if let Some(ref sid) = session_id {
+                metrics.record_session_usage(sid, &counts).await;
+            }
 
             // Log completion when streaming is done
             let elapsed = start_time.elapsed();
+            timings.stream = Some(elapsed.saturating_sub(timings.ttfb.unwrap_or_default()));
+            let cost = estimate_cost(pricing.as_ref(), &token_stats);
             tracing::info!(
-                "Proxy done - original_model: {}, resolved_model: {}, provider: {}, time: {:.2}ms, status: 200, stream: true, success: {}, {}",
+                "Proxy done - original_model: {}, resolved_model: {}, provider: {}, time: {:.2}ms, status: 200, stream: true, success: {}, session: {}, attempts: {}, {}, cost_usd: {}, timings: {}",
                 original_model,
                 model,
                 provider_name,
                 elapsed.as_secs_f64() * 1000.0,
                 success,
-                token_stats
+                session_id.as_deref().unwrap_or("-"),
+                prior_attempts.len() + 1,
+                token_stats,
+                cost.map(format_cost_usd).as_deref().unwrap_or("-"),
+                timings
             );
+            let mut summary = RequestSummary::new(labels, 200, elapsed)
+                .with_deployment_id(deployment_id)
+                .with_ttfb(timings.ttfb)
+                .with_tokens(counts.clone())
+                .with_cost(cost)
+                .with_session_id(session_id.clone())
+                .with_owner(api_key_hash.clone())
+                .with_prior_attempts(&prior_attempts);
+            if stream_error {
+                summary = summary.with_error("Upstream stream error");
+            }
+            #[cfg(feature = "db")]
+            log_request(database.as_ref(), &summary);
+            metrics.record_request(summary).await;
+            if let Some(capture) = capture {
+                capture.finish(success).await;
+            }
 
-            // Log streaming request to database and record quota usage
+            // Record quota usage for the streamed request
             if let (Some(qm), Some(kh)) = (&quota_manager, &api_key_hash) {
                 qm.record_usage_hashed(kh, &counts).await;
             }
-
-            #[cfg(feature = "db")]
-            if let Some(ctx) = db_context {
-                let record = crate::database::RequestRecord::new(
-                    ctx.request_path,
-                    model.clone(),
-                    provider_name.clone(),
-                    elapsed,
-                    200,
-                    success,
-                    &token_stats,
-                    ctx.api_key_hash,
-                );
-                if let Err(e) = ctx.database.insert_request(record).await {
-                    tracing::warn!("Failed to log streaming request to database: {}", e);
+        };
+        // A panic in chunk handling must not leave the client waiting on a
+        // stream that will never finish: catch it, end the stream with an
+        // error event in the client's schema, and count the failure.
+        // The task keeps the request's span, so its logs carry the request ID.
+        tokio::spawn(
+            async move {
+                if let Err(payload) = std::panic::AssertUnwindSafe(forwarder).catch_unwind().await {
+                    let message = crate::sentry::panic_message(&*payload);
+                    let _ = panic_tx
+                        .send(Ok(stream_error_event(
+                            client_family,
+                            "Internal error while streaming the response",
+                        )))
+                        .await;
+                    panic_metrics.record_stream_panic();
+                    panic_metrics
+                        .record_completion(
+                            false,
+                            Some(&panic_context.model),
+                            &TokenCounts::default(),
+                        )
+                        .await;
+                    panic_metrics
+                        .record_request(
+                            RequestSummary::new(panic_context.clone(), 200, start_time.elapsed())
+                                .with_error(format!("Panicked while streaming: {message}"))
+                                .with_session_id(panic_session)
+                                .with_owner(panic_owner),
+                        )
+                        .await;
+                    tracing::error!(
+                        "Streaming task for model '{}' on provider '{}' panicked: {}",
+                        panic_context.model,
+                        panic_context.provider,
+                        message
+                    );
+                    let stream = panic_context.stream.to_string();
+                    crate::sentry::capture(
+                        &format!("Streaming task panicked: {message}"),
+                        &[
+                            ("model", &panic_context.model),
+                            ("provider", &panic_context.provider),
+                            ("route", &panic_context.route),
+                            ("stream", &stream),
+                        ],
+                    );
                 }
             }
-        });
+            .in_current_span(),
+        );
 
         let stream = GuardedStream {
             inner: ReceiverStream::new(rx),
@@ -637,29 +1312,43 @@ impl ProxyRequest {
         };
         let body = Body::from_stream(stream);
 
-        Ok(Response::builder()
+        let mut response = Response::builder()
             .status(StatusCode::OK)
             .header("content-type", "text/event-stream")
             .header("cache-control", "no-cache")
-            .header("connection", "keep-alive")
-            .body(body)?)
+            .header("connection", "keep-alive");
+        if let Some(id) = capture_id {
+            response = response.header(ACR_CAPTURE_ID_HEADER, id);
+        }
+        Ok(response.body(body)?)
     }
 }
 
+/// Terminal SSE error event in the client family's error schema.
+fn stream_error_event(client_family: LlmFamily, message: &str) -> axum::body::Bytes {
+    let error = crate::transforms::error_shape::internal_error(message, client_family);
+    let event = match client_family {
+        LlmFamily::Claude => format!("event: error\n{STREAM_DATA_PREFIX}{error}\n\n"),
+        _ => format!("{STREAM_DATA_PREFIX}{error}\n\n"),
+    };
+    axum::body::Bytes::from(event)
+}
+
 /// Resolve a client-supplied model name to a configured model name.
 ///
 /// Strips the cosmetic `[1m]` suffix if present (silently accepted as a no-op
 /// for backward compat — capability-driven beta injection is what actually
 /// enables max context now), then resolves via:
-/// 1. Exact match against configured `models[].name`
+/// 1. Exact match against configured `models[].name` or an auto-discovered name
 /// 2. Alias pattern match against configured `models[].aliases`
 /// 3. Family-fallback (claude/gemini/gpt/text) to a configured default
 /// 4. Pass-through unchanged
-fn normalize_model(model: &str, registry: &ModelRegistry) -> Result<String> {
+pub(crate) fn normalize_model(model: &str, registry: &ModelRegistry) -> Result<String> {
     let base_model = model.strip_suffix(EXTENDED_CONTEXT_SUFFIX).unwrap_or(model);
 
-    // 1. Exact match - if the model exists in config, use it directly
-    if registry.find_model_config(base_model).is_some() {
+    // 1. Exact match - if the model exists in config or was auto-discovered,
+    // use it directly
+    if registry.find_model_config(base_model).is_some() || registry.is_discovered(base_model) {
         return Ok(base_model.to_string());
     }
 
@@ -709,15 +1398,21 @@ fn normalize_model(model: &str, registry: &ModelRegistry) -> Result<String> {
 static O_SERIES_RE: std::sync::LazyLock<regex::Regex> =
     std::sync::LazyLock::new(|| regex::Regex::new(r"^o\d+(-[a-z]+)?$").unwrap());
 
-/// Map a normalized model name to one of the three LLM families acr supports.
+/// Map a normalized model name to the LLM family serving it.
 ///
-/// Strict allowlist: Claude (Anthropic via Bedrock), Gemini (Google via Vertex),
-/// OpenAI (GPT / o-series / text-embedding via Azure). acr is purpose-built for
-/// SAP AI Core's three-family deployment surface; routing requests for other
-/// AI Core backends (Mistral, Cohere, Nova, RPT, Perplexity, etc.) is explicitly
-/// out of scope — those clients should use the AI Core SDK directly.
-fn determine_family(model: &str) -> Result<LlmFamily, AppError> {
-    if model.starts_with(CLAUDE_PREFIX) {
+/// The first configured `model_families` rule matching the name wins. Without
+/// one, a strict allowlist applies: Claude (Anthropic via Bedrock), Gemini
+/// (Google via Vertex), OpenAI (GPT / o-series / text-embedding via Azure),
+/// and the open-weight Mistral and Llama models. Other AI Core backends
+/// (Cohere, Nova, RPT, Perplexity, etc.) are only routed when a rule names
+/// them — typically as `passthrough`.
+pub(crate) fn determine_family(
+    model: &str,
+    rules: &[ModelFamilyRule],
+) -> Result<LlmFamily, AppError> {
+    if let Some(rule) = rules.iter().find(|rule| rule.matches(model)) {
+        Ok(rule.family.into())
+    } else if model.starts_with(CLAUDE_PREFIX) || model.starts_with(ANTHROPIC_PREFIX) {
         Ok(LlmFamily::Claude)
     } else if model.starts_with(GEMINI_PREFIX) {
         Ok(LlmFamily::Gemini)
@@ -726,34 +1421,104 @@ fn determine_family(model: &str) -> Result<LlmFamily, AppError> {
         || O_SERIES_RE.is_match(model)
     {
         Ok(LlmFamily::OpenAi)
+    } else if model.starts_with(MISTRAL_PREFIX)
+        || model.starts_with(META_LLAMA_PREFIX)
+        || model.starts_with(LLAMA_PREFIX)
+    {
+        Ok(LlmFamily::OpenWeight)
     } else {
         Err(AppError::BadRequest(format!(
             "Model '{model}' is not in a family acr supports. acr only routes \
-             Claude (Anthropic), Gemini (Google), and OpenAI GPT / o-series / \
-             text-embedding-* models. Use the SAP AI Core SDK directly for other \
-             backends (Mistral, Cohere, Nova, RPT, Perplexity, etc.)."
+             Claude (Anthropic), Gemini (Google), OpenAI GPT / o-series / \
+             text-embedding-*, Mistral and Llama models unless `model_families` \
+             routes others. Use the SAP AI Core SDK directly for other backends \
+             (Cohere, Nova, RPT, Perplexity, etc.)."
         )))
     }
 }
 
-fn extract_stream_flag(body: &Value, family: &LlmFamily, action: &Option<String>) -> bool {
+/// Add `metadata` to a JSON object body under `x-acr`. Other bodies are
+/// returned as they are.
+fn insert_metadata(body: axum::body::Bytes, metadata: Value) -> axum::body::Bytes {
+    match serde_json::from_slice::<Value>(&body) {
+        Ok(Value::Object(mut object)) => {
+            object.insert(ACR_METADATA_KEY.to_string(), metadata);
+            serde_json::to_vec(&object).map_or(body, axum::body::Bytes::from)
+        }
+        _ => body,
+    }
+}
+
+/// Compact JSON of `body` for debug logs, cut off after
+/// `DEBUG_BODY_PREVIEW_BYTES`. Serialization stops at the cut, so a
+/// multi-megabyte multimodal request costs no more to log than a small one.
+pub(crate) fn body_preview(body: &Value) -> String {
+    struct Capped(Vec<u8>);
+
+    impl std::io::Write for Capped {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let room = DEBUG_BODY_PREVIEW_BYTES - self.0.len();
+            if room == 0 {
+                return Err(std::io::ErrorKind::WriteZero.into());
+            }
+            let n = room.min(buf.len());
+            self.0.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut out = Capped(Vec::new());
+    let complete = serde_json::to_writer(&mut out, body).is_ok();
+    let mut preview = String::from_utf8_lossy(&out.0).into_owned();
+    if !complete {
+        preview.push_str("… (truncated)");
+    }
+    preview
+}
+
+pub(crate) fn extract_stream_flag(
+    body: &Value,
+    family: &LlmFamily,
+    action: &Option<String>,
+) -> bool {
     match family {
         LlmFamily::Claude => body
             .get("stream")
             .and_then(|v| v.as_bool())
             .unwrap_or(false),
         LlmFamily::Gemini => action.as_deref() == Some(STREAM_GENERATE_CONTENT_ACTION),
-        LlmFamily::OpenAi | LlmFamily::OpenAiResponses => body
+        LlmFamily::OpenAi
+        | LlmFamily::OpenAiResponses
+        | LlmFamily::OpenWeight
+        | LlmFamily::Passthrough => body
             .get("stream")
             .and_then(|v| v.as_bool())
             .unwrap_or(false),
     }
 }
 
-fn prepare_body(body: &mut Value, family: &LlmFamily, stream: bool, model: &str) -> Result<()> {
+/// Apply the upstream family's body rules to `body` in place: model and
+/// stream fields, default `max_tokens`, and parameters the upstream rejects.
+/// Public so the benchmarks can time it.
+pub fn prepare_body(
+    body: &mut Value,
+    family: &LlmFamily,
+    stream: bool,
+    model: &str,
+    aicore_model: &str,
+    action: &Option<String>,
+    default_max_tokens: u64,
+) -> Result<()> {
     match family {
-        LlmFamily::Claude => crate::transforms::anthropic::prepare(body, model),
+        LlmFamily::Claude => crate::transforms::anthropic::prepare(body, model, default_max_tokens),
         LlmFamily::Gemini => crate::transforms::gemini::prepare(body),
+        LlmFamily::OpenAi if action.as_deref() == Some(LEGACY_COMPLETIONS_ACTION) => {
+            crate::transforms::openai::prepare_legacy_completions(body, stream)
+        }
         LlmFamily::OpenAi => crate::transforms::openai::prepare(body, stream),
         // Responses API: filter `tools[]` to types AI Core / Azure currently
         // accepts (`function`-only allowlist, mirrors what the upstream itself
@@ -764,9 +1529,40 @@ fn prepare_body(body: &mut Value, family: &LlmFamily, stream: bool, model: &str)
         // Re-probe AI Core periodically; if newer types become accepted,
         // broaden `ALLOWED_TOOL_TYPES` in `transforms::openai_responses`.
         LlmFamily::OpenAiResponses => crate::transforms::openai_responses::prepare(body),
+        LlmFamily::OpenWeight => {
+            crate::transforms::open_weight::prepare(body, aicore_model, stream)
+        }
+        LlmFamily::Passthrough => Ok(()),
     }
 }
 
+/// Parse an upstream `Retry-After` hint into whole seconds.
+///
+/// Accepts both forms allowed by RFC 9110 (delta-seconds and HTTP-date), and
+/// falls back to Azure OpenAI's non-standard `retry-after-ms`, rounded up so a
+/// sub-second hint never becomes `0`. Returns `None` when no usable hint is
+/// present — callers then omit the header rather than inventing a value.
+pub(crate) fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    if let Some(raw) = headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+    {
+        if let Ok(secs) = raw.parse::<u64>() {
+            return Some(secs);
+        }
+        if let Ok(when) = chrono::DateTime::parse_from_rfc2822(raw) {
+            let delta = when.with_timezone(&chrono::Utc) - chrono::Utc::now();
+            return Some(delta.num_seconds().max(0) as u64);
+        }
+    }
+    headers
+        .get(RETRY_AFTER_MS_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(|ms| ms.div_ceil(1000))
+}
+
 fn extract_content_type(response: &reqwest::Response) -> String {
     response
         .headers()
@@ -776,23 +1572,33 @@ fn extract_content_type(response: &reqwest::Response) -> String {
         .to_string()
 }
 
-/// Extract OpenAI token stats from a `usage` JSON object.
+/// Extract OpenAI token stats from a `usage` JSON object, including the
+/// `prompt_tokens_details` (cached, image) and `completion_tokens_details`
+/// (reasoning) breakdowns.
 fn extract_openai_tokens(usage: &Value) -> TokenStats {
+    let prompt_detail = |field: &str| {
+        usage
+            .get("prompt_tokens_details")
+            .and_then(|d| d.get(field))
+            .and_then(|v| v.as_u64())
+    };
     TokenStats {
         input_tokens: usage.get("prompt_tokens").and_then(|v| v.as_u64()),
         output_tokens: usage.get("completion_tokens").and_then(|v| v.as_u64()),
-        cache_read: usage
-            .get("prompt_tokens_details")
-            .and_then(|d| d.get("cached_tokens"))
-            .and_then(|v| v.as_u64()),
+        cache_read: prompt_detail("cached_tokens"),
         cache_write: None,
+        reasoning: usage
+            .get("completion_tokens_details")
+            .and_then(|d| d.get("reasoning_tokens"))
+            .and_then(|v| v.as_u64()),
+        image: prompt_detail("image_tokens"),
     }
 }
 
 /// Extract OpenAI Responses-API token stats from a `usage` JSON object.
 /// Field names differ from Chat Completions: `input_tokens` / `output_tokens` /
-/// `input_tokens_details.cached_tokens`. The Responses API has no cache-write
-/// concept; `output_tokens_details.reasoning_tokens` is not currently tracked.
+/// `input_tokens_details.cached_tokens` / `output_tokens_details.reasoning_tokens`.
+/// The Responses API has no cache-write concept.
 fn extract_responses_tokens(usage: &Value) -> TokenStats {
     TokenStats {
         input_tokens: usage.get("input_tokens").and_then(|v| v.as_u64()),
@@ -802,6 +1608,11 @@ fn extract_responses_tokens(usage: &Value) -> TokenStats {
             .and_then(|d| d.get("cached_tokens"))
             .and_then(|v| v.as_u64()),
         cache_write: None,
+        reasoning: usage
+            .get("output_tokens_details")
+            .and_then(|d| d.get("reasoning_tokens"))
+            .and_then(|v| v.as_u64()),
+        image: None,
     }
 }
 
@@ -835,6 +1646,17 @@ fn extract_gemini_tokens(usage_metadata: &Value, streaming: bool) -> Option<Toke
             .get("cachedContentTokenCount")
             .and_then(|v| v.as_u64()),
         cache_write: None,
+        reasoning: (thoughts > 0).then_some(thoughts),
+        image: usage_metadata
+            .get("promptTokensDetails")
+            .and_then(|d| d.as_array())
+            .and_then(|details| {
+                details
+                    .iter()
+                    .find(|d| d.get("modality").and_then(|m| m.as_str()) == Some("IMAGE"))
+            })
+            .and_then(|d| d.get("tokenCount"))
+            .and_then(|v| v.as_u64()),
     })
 }
 
@@ -868,6 +1690,8 @@ enum PeekOutcome {
 struct PreparedStream {
     stream: futures::stream::BoxStream<'static, reqwest::Result<axum::body::Bytes>>,
     prebuffered: Vec<u8>,
+    /// Phase timings so far; the forwarder adds the stream phase.
+    timings: PhaseTimings,
 }
 
 /// Wraps the per-request response stream so that the `ActiveRequestGuard`
@@ -951,21 +1775,79 @@ async fn peek_classify_stream(
     }
 }
 
+/// Reassembles upstream chunks into SSE `data:` payloads. Chunks can split
+/// anywhere, including inside a UTF-8 sequence or the `data:` prefix, so a
+/// line is only decoded once its newline has arrived. Public so the
+/// benchmarks can time it.
+pub struct SseLines {
+    buf: Vec<u8>,
+}
+
+impl SseLines {
+    pub fn new(prebuffered: Vec<u8>) -> Self {
+        Self { buf: prebuffered }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// The next non-empty `data:` payload among the complete lines buffered
+    /// so far. Other lines (`event:`, comments, blanks) are skipped, and so
+    /// are lines that aren't UTF-8.
+    pub fn next_data(&mut self) -> Option<String> {
+        while let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+            let line_bytes: Vec<u8> = self.buf.drain(..pos + 1).take(pos).collect();
+            let line = match String::from_utf8(line_bytes) {
+                Ok(s) => s,
+                Err(e) => {
+                    tracing::warn!("Non-UTF-8 line in stream, skipping: {}", e);
+                    continue;
+                }
+            };
+            if let Some(data) = data_payload(&line) {
+                return Some(data.to_string());
+            }
+        }
+        None
+    }
+
+    /// The payload of a final line the stream ended without a newline after.
+    pub fn finish(self) -> Option<String> {
+        let remaining = String::from_utf8(self.buf).ok()?;
+        data_payload(&remaining).map(str::to_string)
+    }
+}
+
+fn data_payload(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix(STREAM_DATA_PREFIX)
+        .filter(|data| !data.is_empty())
+}
+
 /// Format a single SSE `data:` payload for the downstream client. Updates
 /// `token_stats` in place when the payload carries usage. For Claude, prefixes
 /// the formatted output with an explicit `event: <type>` line so SSE clients
 /// that key off named events (rather than parsing JSON) see the right event
 /// type — the upstream Bedrock invoke-with-response-stream encoding only
 /// embeds the type as a JSON field.
-fn format_sse_event(
+///
+/// With a `translator`, usage is still read from the upstream payload but the
+/// output is the translated client-schema frames (possibly empty, in which
+/// case the caller sends nothing).
+pub fn format_sse_event(
     data: &str,
     family: &LlmFamily,
     is_claude: bool,
     token_stats: &mut TokenStats,
+    translator: Option<&mut StreamTranslator>,
 ) -> axum::body::Bytes {
     if let Some(stats) = extract_token_stats(data, family) {
         *token_stats = stats;
     }
+    if let Some(translator) = translator {
+        return axum::body::Bytes::from(translator.event(data));
+    }
 
     let mut output = String::new();
     if is_claude
@@ -993,12 +1875,16 @@ pub fn extract_token_stats(data: &str, family: &LlmFamily) -> Option<TokenStats>
                     output_tokens: metrics.get("outputTokenCount")?.as_u64(),
                     cache_read: metrics.get("cacheReadInputTokenCount")?.as_u64(),
                     cache_write: metrics.get("cacheWriteInputTokenCount")?.as_u64(),
+                    reasoning: None,
+                    image: None,
                 })
             } else {
                 None
             }
         }
-        LlmFamily::OpenAi => {
+        // A passthrough upstream's usage is read when it uses OpenAI's
+        // field names, as most OpenAI-compatible servers do.
+        LlmFamily::OpenAi | LlmFamily::OpenWeight | LlmFamily::Passthrough => {
             let usage = parsed.get("usage")?;
             Some(extract_openai_tokens(usage))
         }
@@ -1049,9 +1935,13 @@ fn extract_token_stats_from_body(body: &str, family: &LlmFamily) -> Option<Token
                 cache_write: usage
                     .get("cache_creation_input_tokens")
                     .and_then(|v| v.as_u64()),
+                reasoning: None,
+                image: None,
             })
         }
-        LlmFamily::OpenAi => {
+        // A passthrough upstream's usage is read when it uses OpenAI's
+        // field names, as most OpenAI-compatible servers do.
+        LlmFamily::OpenAi | LlmFamily::OpenWeight | LlmFamily::Passthrough => {
             let usage = parsed.get("usage")?;
             Some(extract_openai_tokens(usage))
         }
@@ -1066,7 +1956,50 @@ fn extract_token_stats_from_body(body: &str, family: &LlmFamily) -> Option<Token
     }
 }
 
-fn build_url(
+/// Placeholders a model's `url_template` may use.
+pub(crate) const URL_TEMPLATE_PLACEHOLDERS: [&str; 4] =
+    ["deployment_id", "model", "action", "api_version"];
+
+/// Fill the `{name}` placeholders of a `url_template`. Unknown placeholders
+/// and unbalanced braces are errors.
+pub(crate) fn render_url_template(template: &str, values: &[(&str, &str)]) -> Result<String> {
+    let mut url = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        url.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = match (rest.as_bytes()[start], after.find('}')) {
+            (b'{', Some(end)) => end,
+            _ => anyhow::bail!("unbalanced braces in '{template}'"),
+        };
+        let name = &after[..end];
+        let value = values
+            .iter()
+            .find(|(placeholder, _)| *placeholder == name)
+            .map(|(_, value)| *value)
+            .with_context(|| format!("unknown placeholder '{{{name}}}' in '{template}'"))?;
+        url.push_str(value);
+        rest = &after[end + 1..];
+    }
+    url.push_str(rest);
+    Ok(url)
+}
+
+/// `{action}` in a `url_template`: the action the family's built-in URL
+/// would use.
+fn template_action<'a>(family: &LlmFamily, action: &'a Option<String>, stream: bool) -> &'a str {
+    match family {
+        LlmFamily::Claude if stream => INVOKE_STREAM_ACTION,
+        LlmFamily::Claude => INVOKE_ACTION,
+        LlmFamily::Gemini => action.as_deref().unwrap_or(GENERATE_CONTENT_ACTION),
+        LlmFamily::OpenAi
+        | LlmFamily::OpenAiResponses
+        | LlmFamily::OpenWeight
+        | LlmFamily::Passthrough => action.as_deref().unwrap_or(""),
+    }
+}
+
+pub(crate) fn build_url(
     model: &str,
     deployment_id: &str,
     action: &Option<String>,
@@ -1093,7 +2026,11 @@ fn build_url(
             ))
         }
         LlmFamily::OpenAi => {
-            if model.starts_with(TEXT_PREFIX) {
+            if action.as_deref() == Some(LEGACY_COMPLETIONS_ACTION) {
+                Ok(format!(
+                    "{base_url}{INFERENCE_DEPLOYMENTS_PATH}/{deployment_id}{COMPLETIONS_PATH}?api-version={openai_api_version}"
+                ))
+            } else if model.starts_with(TEXT_PREFIX) {
                 Ok(format!(
                     "{base_url}{INFERENCE_DEPLOYMENTS_PATH}/{deployment_id}{EMBEDDINGS_PATH}?api-version={openai_api_version}"
                 ))
@@ -1117,6 +2054,14 @@ fn build_url(
                 "{base_url}{INFERENCE_DEPLOYMENTS_PATH}/{deployment_id}{path}?api-version={openai_api_version}"
             ))
         }
+        LlmFamily::OpenWeight => Ok(format!(
+            "{base_url}{INFERENCE_DEPLOYMENTS_PATH}/{deployment_id}{CHAT_COMPLETIONS_PATH}"
+        )),
+        // Passthrough has no URL of its own; config validation requires a
+        // `url_template` for every configured passthrough model.
+        LlmFamily::Passthrough => {
+            anyhow::bail!("Passthrough model '{model}' has no url_template")
+        }
     }
 }
 
@@ -1137,6 +2082,19 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_body_preview_is_capped() {
+        let small = json!({"model": "gpt-4o"});
+        assert_eq!(body_preview(&small), r#"{"model":"gpt-4o"}"#);
+
+        let image = "A".repeat(5 * 1024 * 1024);
+        let large = json!({"image": image});
+        let preview = body_preview(&large);
+        assert!(preview.starts_with(r#"{"image":"AAA"#));
+        assert!(preview.ends_with("… (truncated)"));
+        assert!(preview.len() < DEBUG_BODY_PREVIEW_BYTES + 32);
+    }
+
     #[test]
     fn test_normalize_model_with_1m_suffix() {
         // The `[1m]` suffix is silently stripped (no error, no flag returned).
@@ -1146,6 +2104,11 @@ mod tests {
             aicore_model_name: None,
             aliases: vec![],
             pricing: None,
+            default_max_tokens: None,
+            deprecation: None,
+            safety_settings: vec![],
+            url_template: None,
+            disabled: None,
         }];
         let registry = create_test_registry(models);
 
@@ -1160,6 +2123,11 @@ mod tests {
             aicore_model_name: None,
             aliases: vec![],
             pricing: None,
+            default_max_tokens: None,
+            deprecation: None,
+            safety_settings: vec![],
+            url_template: None,
+            disabled: None,
         }];
         let registry = create_test_registry(models);
 
@@ -1174,6 +2142,11 @@ mod tests {
             aicore_model_name: None,
             aliases: vec!["claude-opus-4-7-*".to_string()],
             pricing: None,
+            default_max_tokens: None,
+            deprecation: None,
+            safety_settings: vec![],
+            url_template: None,
+            disabled: None,
         }];
         let registry = create_test_registry(models);
 
@@ -1189,6 +2162,11 @@ mod tests {
             aicore_model_name: None,
             aliases: vec![],
             pricing: None,
+            default_max_tokens: None,
+            deprecation: None,
+            safety_settings: vec![],
+            url_template: None,
+            disabled: None,
         }];
         let registry = ModelRegistry::new(
             models,
@@ -1216,6 +2194,11 @@ mod tests {
             aicore_model_name: None,
             aliases: vec![],
             pricing: None,
+            default_max_tokens: None,
+            deprecation: None,
+            safety_settings: vec![],
+            url_template: None,
+            disabled: None,
         }];
         let registry = create_test_registry(models);
 
@@ -1230,28 +2213,40 @@ mod tests {
     #[test]
     fn determine_family_routes_known_prefixes() {
         assert!(matches!(
-            determine_family("claude-sonnet-4-6").unwrap(),
+            determine_family("claude-sonnet-4-6", &[]).unwrap(),
             LlmFamily::Claude
         ));
         assert!(matches!(
-            determine_family("gemini-2.5-pro").unwrap(),
+            determine_family("gemini-2.5-pro", &[]).unwrap(),
             LlmFamily::Gemini
         ));
         assert!(matches!(
-            determine_family("gpt-5.4").unwrap(),
+            determine_family("gpt-5.4", &[]).unwrap(),
             LlmFamily::OpenAi
         ));
         assert!(matches!(
-            determine_family("text-embedding-3-small").unwrap(),
+            determine_family("text-embedding-3-small", &[]).unwrap(),
             LlmFamily::OpenAi
         ));
+        for model in [
+            "mistralai--mistral-large-instruct",
+            "mistral-small",
+            "meta--llama3.1-70b-instruct",
+            "llama-3.3-70b",
+        ] {
+            assert_eq!(
+                determine_family(model, &[]).unwrap(),
+                LlmFamily::OpenWeight,
+                "{model}"
+            );
+        }
     }
 
     #[test]
     fn determine_family_o_series_via_regex() {
         for model in ["o1", "o3", "o3-mini", "o4-mini", "o5", "o6-preview"] {
             assert!(
-                matches!(determine_family(model).unwrap(), LlmFamily::OpenAi),
+                matches!(determine_family(model, &[]).unwrap(), LlmFamily::OpenAi),
                 "{model} should route to OpenAi via o-series regex"
             );
         }
@@ -1262,12 +2257,11 @@ mod tests {
         for model in [
             "nova-lite",
             "amazon--nova-pro",
-            "mistralai--mistral-large-instruct",
             "cohere--command-a-reasoning",
             "sap-rpt-1-large",
             "sonar-pro",
         ] {
-            let err = determine_family(model).unwrap_err();
+            let err = determine_family(model, &[]).unwrap_err();
             assert!(
                 matches!(err, AppError::BadRequest(_)),
                 "{model} should be rejected with BadRequest, got {err:?}"
@@ -1275,6 +2269,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn determine_family_applies_configured_rules_first() {
+        let rules: Vec<ModelFamilyRule> = serde_yaml_ng::from_str(
+            r#"
+- prefix: cohere--
+  family: passthrough
+- pattern: "^gpt-5\\.4-(mini|nano)$"
+  family: claude
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            determine_family("cohere--command-a-reasoning", &rules).unwrap(),
+            LlmFamily::Passthrough
+        );
+        assert_eq!(
+            determine_family("gpt-5.4-mini", &rules).unwrap(),
+            LlmFamily::Claude
+        );
+        assert_eq!(
+            determine_family("gpt-5.4", &rules).unwrap(),
+            LlmFamily::OpenAi
+        );
+        assert!(determine_family("sonar-pro", &rules).is_err());
+    }
+
     // -------------------------------------------------------------------------
     // OpenAI Responses API (`/v1/responses`)
     // -------------------------------------------------------------------------
@@ -1296,6 +2316,29 @@ mod tests {
         assert_eq!(stats.cache_write, None);
     }
 
+    #[test]
+    fn extract_openai_tokens_reads_usage_details() {
+        let usage = json!({
+            "prompt_tokens": 1200,
+            "prompt_tokens_details": { "cached_tokens": 200, "image_tokens": 765 },
+            "completion_tokens": 300,
+            "completion_tokens_details": { "reasoning_tokens": 256 },
+            "total_tokens": 1500
+        });
+        let stats = extract_openai_tokens(&usage);
+        assert_eq!(stats.input_tokens, Some(1200));
+        assert_eq!(stats.cache_read, Some(200));
+        assert_eq!(stats.image, Some(765));
+        assert_eq!(stats.reasoning, Some(256));
+        assert_eq!(
+            stats.to_string(),
+            "input_tokens: 1200, output_tokens: 300, cache_read: 200, cache_write: N/A, reasoning: 256, image: 765"
+        );
+
+        let stats = extract_openai_tokens(&json!({"prompt_tokens": 5, "completion_tokens": 1}));
+        assert_eq!((stats.reasoning, stats.image), (None, None));
+    }
+
     #[test]
     fn extract_token_stats_responses_completed_event_yields_usage() {
         let event = r#"{
@@ -1374,6 +2417,114 @@ mod tests {
         assert_eq!(stats.cache_read, Some(4));
     }
 
+    #[test]
+    fn extract_token_stats_from_body_claude_openai_gemini() {
+        let claude = r#"{
+            "type": "message",
+            "content": [{"type": "text", "text": "hi"}],
+            "usage": {
+                "input_tokens": 20,
+                "output_tokens": 9,
+                "cache_read_input_tokens": 15,
+                "cache_creation_input_tokens": 5
+            }
+        }"#;
+        let stats = extract_token_stats_from_body(claude, &LlmFamily::Claude).unwrap();
+        assert_eq!(stats.input_tokens, Some(20));
+        assert_eq!(stats.output_tokens, Some(9));
+        assert_eq!(stats.cache_read, Some(15));
+        assert_eq!(stats.cache_write, Some(5));
+
+        let openai = r#"{
+            "object": "chat.completion",
+            "choices": [],
+            "usage": { "prompt_tokens": 11, "completion_tokens": 3 }
+        }"#;
+        let stats = extract_token_stats_from_body(openai, &LlmFamily::OpenAi).unwrap();
+        assert_eq!(stats.input_tokens, Some(11));
+        assert_eq!(stats.output_tokens, Some(3));
+
+        // Thinking tokens are billed as output alongside the candidates
+        let gemini = r#"{
+            "candidates": [],
+            "usageMetadata": {
+                "promptTokenCount": 300,
+                "candidatesTokenCount": 40,
+                "thoughtsTokenCount": 60,
+                "cachedContentTokenCount": 100,
+                "promptTokensDetails": [
+                    {"modality": "TEXT", "tokenCount": 42},
+                    {"modality": "IMAGE", "tokenCount": 258}
+                ]
+            }
+        }"#;
+        let stats = extract_token_stats_from_body(gemini, &LlmFamily::Gemini).unwrap();
+        assert_eq!(stats.input_tokens, Some(300));
+        assert_eq!(stats.output_tokens, Some(100));
+        assert_eq!(stats.cache_read, Some(100));
+        assert_eq!(stats.reasoning, Some(60));
+        assert_eq!(stats.image, Some(258));
+
+        // A prompt blocked before generation reports no output
+        let blocked = r#"{"usageMetadata": {"promptTokenCount": 8}}"#;
+        let stats = extract_token_stats_from_body(blocked, &LlmFamily::Gemini).unwrap();
+        assert_eq!(stats.input_tokens, Some(8));
+        assert_eq!(stats.output_tokens, None);
+
+        assert!(extract_token_stats_from_body("{}", &LlmFamily::Gemini).is_none());
+    }
+
+    #[test]
+    fn metadata_is_added_to_json_objects_only() {
+        let metadata = json!({"provider": "eu", "retries": 1});
+        let body = insert_metadata(
+            axum::body::Bytes::from_static(br#"{"id":"chatcmpl-1","choices":[]}"#),
+            metadata.clone(),
+        );
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["id"], "chatcmpl-1");
+        assert_eq!(body["x-acr"], metadata);
+
+        for other in [&b"[1,2]"[..], b"not json"] {
+            let body = insert_metadata(axum::body::Bytes::from(other), metadata.clone());
+            assert_eq!(&body[..], other);
+        }
+
+        let mut headers = HeaderMap::new();
+        assert!(!header_flag(&headers, ACR_METADATA_HEADER));
+        headers.insert(ACR_METADATA_HEADER, HeaderValue::from_static(" TRUE "));
+        assert!(header_flag(&headers, ACR_METADATA_HEADER));
+    }
+
+    #[test]
+    fn url_templates_fill_placeholders() {
+        let values = [
+            ("deployment_id", "d1"),
+            ("model", "gpt-oss-120b"),
+            ("action", ""),
+            ("api_version", "2024-05-01"),
+        ];
+        assert_eq!(
+            render_url_template(
+                "/v2/inference/deployments/{deployment_id}/v1/chat/completions?model={model}&api-version={api_version}",
+                &values
+            )
+            .unwrap(),
+            "/v2/inference/deployments/d1/v1/chat/completions?model=gpt-oss-120b&api-version=2024-05-01"
+        );
+        assert!(render_url_template("/x/{deployment}", &values).is_err());
+        assert!(render_url_template("/x/{model", &values).is_err());
+        assert!(render_url_template("/x/model}", &values).is_err());
+        assert_eq!(
+            template_action(&LlmFamily::Claude, &None, true),
+            INVOKE_STREAM_ACTION
+        );
+        assert_eq!(
+            template_action(&LlmFamily::Gemini, &Some("countTokens".into()), false),
+            "countTokens"
+        );
+    }
+
     #[test]
     fn build_url_routes_responses_to_responses_endpoint() {
         let url = build_url(
@@ -1455,6 +2606,42 @@ mod tests {
         assert!(!url.contains("/compact"));
     }
 
+    #[test]
+    fn build_url_legacy_completions_action_targets_completions_path() {
+        let url = build_url(
+            "gpt-35-turbo-instruct",
+            "d1",
+            &Some(LEGACY_COMPLETIONS_ACTION.to_string()),
+            "https://x",
+            &LlmFamily::OpenAi,
+            false,
+            "2024-02-01",
+        )
+        .unwrap();
+        assert_eq!(
+            url,
+            "https://x/v2/inference/deployments/d1/completions?api-version=2024-02-01"
+        );
+    }
+
+    #[test]
+    fn build_url_open_weight_has_no_api_version() {
+        let url = build_url(
+            "mistralai--mistral-large-instruct",
+            "d1",
+            &None,
+            "https://x",
+            &LlmFamily::OpenWeight,
+            true,
+            "2025-04-01-preview",
+        )
+        .unwrap();
+        assert_eq!(
+            url,
+            "https://x/v2/inference/deployments/d1/chat/completions"
+        );
+    }
+
     /// Build a synthetic `BoxStream` from a list of pre-baked chunks for
     /// driving `peek_classify_stream` in tests. Each chunk is delivered as
     /// `Ok(Bytes)`; no transport errors are simulated.
@@ -1633,4 +2820,230 @@ mod tests {
         drop(wrapped);
         assert_eq!(metrics.snapshot_sync().active_requests, 0);
     }
+
+    fn retry_headers(pairs: &[(&'static str, &str)]) -> reqwest::header::HeaderMap {
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn parse_retry_after_reads_delta_seconds() {
+        assert_eq!(
+            parse_retry_after(&retry_headers(&[("retry-after", "17")])),
+            Some(17)
+        );
+    }
+
+    #[test]
+    fn parse_retry_after_reads_http_date() {
+        let when = (chrono::Utc::now() + chrono::Duration::seconds(120)).to_rfc2822();
+        let secs = parse_retry_after(&retry_headers(&[("retry-after", &when)])).unwrap();
+        assert!((118..=120).contains(&secs), "got {secs}");
+    }
+
+    #[test]
+    fn parse_retry_after_clamps_past_http_date_to_zero() {
+        let when = (chrono::Utc::now() - chrono::Duration::seconds(60)).to_rfc2822();
+        assert_eq!(
+            parse_retry_after(&retry_headers(&[("retry-after", &when)])),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn parse_retry_after_falls_back_to_azure_ms_header_rounding_up() {
+        assert_eq!(
+            parse_retry_after(&retry_headers(&[("retry-after-ms", "1500")])),
+            Some(2)
+        );
+        assert_eq!(
+            parse_retry_after(&retry_headers(&[("retry-after-ms", "1")])),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn parse_retry_after_none_when_absent_or_garbage() {
+        assert_eq!(parse_retry_after(&retry_headers(&[])), None);
+        assert_eq!(
+            parse_retry_after(&retry_headers(&[("retry-after", "soon")])),
+            None
+        );
+    }
+
+    #[test]
+    fn estimate_cost_uses_price_table_per_million_tokens() {
+        let pricing = ModelPricing {
+            input: Some(3.0),
+            output: Some(15.0),
+            cache_read: Some(0.3),
+            cache_write: None,
+            reasoning: None,
+            image: None,
+        };
+        let stats = TokenStats {
+            input_tokens: Some(1_000),
+            output_tokens: Some(200),
+            cache_read: Some(10_000),
+            cache_write: Some(500),
+            ..Default::default()
+        };
+        let cost = estimate_cost(Some(&pricing), &stats).unwrap();
+        assert_eq!(format_cost_usd(cost), "0.009000");
+    }
+
+    #[test]
+    fn estimate_cost_none_without_pricing_or_usage() {
+        let stats = TokenStats {
+            input_tokens: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(estimate_cost(None, &stats), None);
+        let pricing = ModelPricing {
+            input: Some(1.0),
+            output: Some(1.0),
+            cache_read: None,
+            cache_write: None,
+            reasoning: None,
+            image: None,
+        };
+        assert_eq!(estimate_cost(Some(&pricing), &TokenStats::default()), None);
+    }
+
+    #[test]
+    fn stream_error_event_matches_client_schema() {
+        let claude = stream_error_event(LlmFamily::Claude, "boom");
+        let claude = std::str::from_utf8(&claude).unwrap();
+        assert!(claude.starts_with("event: error\ndata: "), "{claude}");
+        assert!(claude.ends_with("\n\n"));
+        let data: Value = serde_json::from_str(
+            claude
+                .lines()
+                .nth(1)
+                .unwrap()
+                .strip_prefix(STREAM_DATA_PREFIX)
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(data["error"]["type"], "api_error");
+        assert_eq!(data["error"]["message"], "boom");
+
+        let openai = stream_error_event(LlmFamily::OpenAi, "boom");
+        let openai = std::str::from_utf8(&openai).unwrap();
+        let data: Value =
+            serde_json::from_str(openai.trim_end().strip_prefix(STREAM_DATA_PREFIX).unwrap())
+                .unwrap();
+        assert_eq!(data["error"]["type"], "server_error");
+    }
+
+    /// Recorded upstream streams, replayed by the reassembly tests.
+    const SSE_TRANSCRIPTS: [(LlmFamily, &[u8]); 3] = [
+        (
+            LlmFamily::OpenAi,
+            include_bytes!("../tests/fixtures/sse/openai_chat.sse"),
+        ),
+        (
+            LlmFamily::Claude,
+            include_bytes!("../tests/fixtures/sse/claude_messages.sse"),
+        ),
+        (
+            LlmFamily::Gemini,
+            include_bytes!("../tests/fixtures/sse/gemini_crlf_unterminated.sse"),
+        ),
+    ];
+
+    /// The forwarder's output for an upstream stream arriving as `chunks`,
+    /// the first of which the peek phase buffered, and the usage it read.
+    fn forward_sse(chunks: &[&[u8]], family: LlmFamily) -> (Vec<u8>, String) {
+        let is_claude = matches!(family, LlmFamily::Claude);
+        let mut token_stats = TokenStats::default();
+        let mut lines = SseLines::new(chunks.first().copied().unwrap_or_default().to_vec());
+        let mut output = Vec::new();
+        for chunk in chunks.iter().skip(1).chain([&&b""[..]]) {
+            lines.push(chunk);
+            while let Some(data) = lines.next_data() {
+                output.extend(format_sse_event(
+                    &data,
+                    &family,
+                    is_claude,
+                    &mut token_stats,
+                    None,
+                ));
+            }
+        }
+        if let Some(data) = lines.finish() {
+            output.extend(format_sse_event(
+                &data,
+                &family,
+                is_claude,
+                &mut token_stats,
+                None,
+            ));
+        }
+        (output, format!("{token_stats:?}"))
+    }
+
+    fn split_at_cuts(bytes: &[u8], mut cuts: Vec<usize>) -> Vec<&[u8]> {
+        cuts.iter_mut().for_each(|cut| *cut %= bytes.len() + 1);
+        cuts.sort_unstable();
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        for cut in cuts.into_iter().chain([bytes.len()]) {
+            chunks.push(&bytes[start..cut]);
+            start = cut;
+        }
+        chunks
+    }
+
+    #[test]
+    fn sse_transcripts_forward_every_event() {
+        let (openai, stats) = forward_sse(&[SSE_TRANSCRIPTS[0].1], LlmFamily::OpenAi);
+        let openai = String::from_utf8(openai).unwrap();
+        assert_eq!(openai.matches("data: ").count(), 5);
+        assert!(openai.contains("東京 🗼") && openai.ends_with("data: [DONE]\n\n"));
+        assert!(!openai.contains("keep-alive"));
+        assert!(stats.contains("output_tokens: Some(7)"), "{stats}");
+
+        let (claude, stats) = forward_sse(&[SSE_TRANSCRIPTS[1].1], LlmFamily::Claude);
+        let claude = String::from_utf8(claude).unwrap();
+        assert!(claude.starts_with("event: message_start\ndata: {"));
+        assert!(claude.contains("👋🏽 नमस्ते"));
+        assert!(stats.contains("output_tokens: Some(9)"), "{stats}");
+
+        // CRLF line endings, and a last event without a newline after it.
+        let (gemini, stats) = forward_sse(&[SSE_TRANSCRIPTS[2].1], LlmFamily::Gemini);
+        let gemini = String::from_utf8(gemini).unwrap();
+        assert_eq!(gemini.matches("data: ").count(), 3);
+        assert!(!gemini.contains('\r'));
+        assert!(gemini.ends_with("\"totalTokenCount\":9}}\n\n"));
+        assert!(stats.contains("output_tokens: Some(4)"), "{stats}");
+    }
+
+    #[test]
+    fn sse_reassembly_survives_every_single_split() {
+        for (family, transcript) in SSE_TRANSCRIPTS {
+            let whole = forward_sse(&[transcript], family);
+            for cut in 0..=transcript.len() {
+                let (head, tail) = transcript.split_at(cut);
+                assert_eq!(forward_sse(&[head, tail], family), whole, "split at {cut}");
+            }
+            let bytewise: Vec<&[u8]> = transcript.chunks(1).collect();
+            assert_eq!(forward_sse(&bytewise, family), whole);
+        }
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn sse_reassembly_is_independent_of_chunking(
+            transcript in 0..SSE_TRANSCRIPTS.len(),
+            cuts in proptest::collection::vec(proptest::num::usize::ANY, 0..32),
+        ) {
+            let (family, bytes) = SSE_TRANSCRIPTS[transcript];
+            let chunks = split_at_cuts(bytes, cuts);
+            proptest::prop_assert_eq!(forward_sse(&chunks, family), forward_sse(&[bytes], family));
+        }
+    }
 }
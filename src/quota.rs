@@ -1,20 +1,43 @@
 //! Per-API-key token usage quota enforcement.
 //!
-//! Tracks daily and monthly token usage per API key in memory,
+//! Tracks daily and monthly token usage per API key in memory, in calendar
+//! windows that reset at midnight UTC (daily) and on the 1st UTC (monthly),
 //! with baselines derived from the requests table on startup and
-//! on day/month rollover. No separate persistence needed — the
-//! requests table (written per-request) is the source of truth.
-
-use chrono::{Datelike, Local, NaiveDate};
+//! on day/month rollover. With request logging on, the requests table
+//! (written per-request) is the source of truth; without it, counters are
+//! saved to `quotas.state_file` (see [`QuotaManager::save_state`]) so a
+//! restart doesn't hand every key a fresh allowance. In cluster mode the
+//! counters live in Redis, shared by every router (see `cluster.rs`), and
+//! the local ones follow them.
+//!
+//! The remaining allowance is reported to clients through the
+//! `x-ratelimit-*-tokens-{day,month}` response headers, and the tightest of
+//! the two windows through OpenAI's `x-ratelimit-*-tokens`.
+
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use axum::http::{HeaderMap, HeaderValue};
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::RwLock;
 
 use crate::config::{ApiKeyConfig, QuotaConfig};
+use crate::constants::api::{
+    RATELIMIT_LIMIT_TOKENS_DAY_HEADER, RATELIMIT_LIMIT_TOKENS_HEADER,
+    RATELIMIT_LIMIT_TOKENS_MONTH_HEADER, RATELIMIT_REMAINING_TOKENS_DAY_HEADER,
+    RATELIMIT_REMAINING_TOKENS_HEADER, RATELIMIT_REMAINING_TOKENS_MONTH_HEADER,
+    RATELIMIT_RESET_TOKENS_DAY_HEADER, RATELIMIT_RESET_TOKENS_HEADER,
+    RATELIMIT_RESET_TOKENS_MONTH_HEADER,
+};
 #[cfg(feature = "db")]
 use crate::database::Database;
 use crate::metrics::TokenCounts;
+use crate::request_limiter::reset_duration;
 
 /// Which quota period was exceeded.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -51,6 +74,111 @@ pub enum QuotaCheckResult {
     },
 }
 
+impl QuotaCheckResult {
+    /// Add `x-ratelimit-{limit,remaining,reset}-tokens-{day,month}` headers
+    /// for every window that has a limit. Remaining is as of the start of the
+    /// request; reset is in seconds. An exceeded window reports 0 remaining.
+    ///
+    /// The window with the least left is also reported as OpenAI's
+    /// `x-ratelimit-{limit,remaining,reset}-tokens`, with the reset written
+    /// as a duration like `5h3m20s`.
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        let now = chrono::Utc::now().timestamp();
+        if let Some((limit, remaining, reset_secs)) = self.tightest_window(now) {
+            if let Some(limit) = limit {
+                headers.insert(RATELIMIT_LIMIT_TOKENS_HEADER, HeaderValue::from(limit));
+            }
+            headers.insert(
+                RATELIMIT_REMAINING_TOKENS_HEADER,
+                HeaderValue::from(remaining),
+            );
+            if let Ok(reset) =
+                HeaderValue::from_str(&reset_duration(std::time::Duration::from_secs(reset_secs)))
+            {
+                headers.insert(RATELIMIT_RESET_TOKENS_HEADER, reset);
+            }
+        }
+        let mut insert = |name: &'static str, value: u64| {
+            headers.insert(name, HeaderValue::from(value));
+        };
+        match *self {
+            Self::Allowed {
+                daily_remaining,
+                monthly_remaining,
+                daily_limit,
+                monthly_limit,
+                daily_reset,
+                monthly_reset,
+            } => {
+                if let (Some(limit), Some(remaining)) = (daily_limit, daily_remaining) {
+                    insert(RATELIMIT_LIMIT_TOKENS_DAY_HEADER, limit);
+                    insert(RATELIMIT_REMAINING_TOKENS_DAY_HEADER, remaining);
+                    insert(
+                        RATELIMIT_RESET_TOKENS_DAY_HEADER,
+                        (daily_reset - now).max(0) as u64,
+                    );
+                }
+                if let (Some(limit), Some(remaining)) = (monthly_limit, monthly_remaining) {
+                    insert(RATELIMIT_LIMIT_TOKENS_MONTH_HEADER, limit);
+                    insert(RATELIMIT_REMAINING_TOKENS_MONTH_HEADER, remaining);
+                    insert(
+                        RATELIMIT_RESET_TOKENS_MONTH_HEADER,
+                        (monthly_reset - now).max(0) as u64,
+                    );
+                }
+            }
+            Self::Exceeded {
+                retry_after_secs,
+                limit_type,
+            } => {
+                let (remaining, reset) = match limit_type {
+                    LimitType::Daily => (
+                        RATELIMIT_REMAINING_TOKENS_DAY_HEADER,
+                        RATELIMIT_RESET_TOKENS_DAY_HEADER,
+                    ),
+                    LimitType::Monthly => (
+                        RATELIMIT_REMAINING_TOKENS_MONTH_HEADER,
+                        RATELIMIT_RESET_TOKENS_MONTH_HEADER,
+                    ),
+                };
+                insert(remaining, 0);
+                insert(reset, retry_after_secs);
+            }
+        }
+    }
+
+    /// Limit (unknown once exceeded), remaining tokens and seconds to reset
+    /// of the window with the fewest tokens left.
+    fn tightest_window(&self, now: i64) -> Option<(Option<u64>, u64, u64)> {
+        match *self {
+            Self::Allowed {
+                daily_remaining,
+                monthly_remaining,
+                daily_limit,
+                monthly_limit,
+                daily_reset,
+                monthly_reset,
+            } => {
+                let day = daily_limit
+                    .zip(daily_remaining)
+                    .map(|(l, r)| (l, r, daily_reset));
+                let month = monthly_limit
+                    .zip(monthly_remaining)
+                    .map(|(l, r)| (l, r, monthly_reset));
+                day.into_iter()
+                    .chain(month)
+                    .min_by_key(|(_, remaining, _)| *remaining)
+                    .map(|(limit, remaining, reset)| {
+                        (Some(limit), remaining, (reset - now).max(0) as u64)
+                    })
+            }
+            Self::Exceeded {
+                retry_after_secs, ..
+            } => Some((None, 0, retry_after_secs)),
+        }
+    }
+}
+
 /// Resolved limits for a specific API key (merged from per-key and global defaults).
 #[derive(Debug, Clone)]
 struct ResolvedLimits {
@@ -58,15 +186,35 @@ struct ResolvedLimits {
     monthly: Option<u64>,
 }
 
+/// The limits of every configured key, and of the keys that aren't.
+#[derive(Debug)]
+struct Limits {
+    per_key: HashMap<String, ResolvedLimits>,
+    global_daily: Option<u64>,
+    global_monthly: Option<u64>,
+}
+
+impl Limits {
+    fn for_key(&self, key_hash: &str) -> ResolvedLimits {
+        self.per_key
+            .get(key_hash)
+            .cloned()
+            .unwrap_or(ResolvedLimits {
+                daily: self.global_daily,
+                monthly: self.global_monthly,
+            })
+    }
+}
+
 /// Token usage for a single time period.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 struct PeriodUsage {
     total_tokens: u64,
     period_start: NaiveDate,
 }
 
 /// Per-key usage accumulator (daily + monthly).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct KeyUsage {
     daily: PeriodUsage,
     monthly: PeriodUsage,
@@ -76,14 +224,19 @@ struct KeyUsage {
 #[derive(Debug, Clone)]
 pub struct QuotaManager {
     inner: Arc<QuotaManagerInner>,
+    /// Counters shared with the other routers, used instead of the local
+    /// ones while Redis is reachable.
+    #[cfg(feature = "cluster")]
+    cluster: Option<crate::cluster::Cluster>,
 }
 
 #[derive(Debug)]
 struct QuotaManagerInner {
     usage: RwLock<HashMap<String, KeyUsage>>,
-    limits: HashMap<String, ResolvedLimits>,
-    global_daily: Option<u64>,
-    global_monthly: Option<u64>,
+    /// Set when usage changes, cleared by `save_state`.
+    dirty: AtomicBool,
+    /// Replaced when a reload changes the keys or the default limits.
+    limits: ArcSwap<Limits>,
     #[cfg(feature = "db")]
     database: Option<Database>,
 }
@@ -109,22 +262,24 @@ fn resolve_limit(per_key: Option<u64>, global: Option<u64>) -> Option<u64> {
     }
 }
 
-/// Build the per-key limits map from config (shared between feature-gated constructors).
-fn build_limits(
-    api_keys: &[ApiKeyConfig],
-    quotas: &QuotaConfig,
-) -> HashMap<String, ResolvedLimits> {
-    api_keys
+/// Build the per-key limits from config (shared between feature-gated constructors).
+fn build_limits(api_keys: &[ApiKeyConfig], quotas: &QuotaConfig) -> Limits {
+    let per_key = api_keys
         .iter()
         .map(|key_config| {
-            let key_hash = hash_api_key(&key_config.key);
+            let key_hash = key_config.key_hash();
             let limits = ResolvedLimits {
                 daily: resolve_limit(key_config.daily_token_limit, quotas.daily_token_limit),
                 monthly: resolve_limit(key_config.monthly_token_limit, quotas.monthly_token_limit),
             };
             (key_hash, limits)
         })
-        .collect()
+        .collect();
+    Limits {
+        per_key,
+        global_daily: quotas.daily_token_limit,
+        global_monthly: quotas.monthly_token_limit,
+    }
 }
 
 impl QuotaManager {
@@ -139,11 +294,12 @@ impl QuotaManager {
         Self {
             inner: Arc::new(QuotaManagerInner {
                 usage: RwLock::new(HashMap::new()),
-                limits: build_limits(api_keys, quotas),
-                global_daily: quotas.daily_token_limit,
-                global_monthly: quotas.monthly_token_limit,
+                dirty: AtomicBool::new(false),
+                limits: ArcSwap::from_pointee(build_limits(api_keys, quotas)),
                 database,
             }),
+            #[cfg(feature = "cluster")]
+            cluster: None,
         }
     }
 
@@ -153,13 +309,30 @@ impl QuotaManager {
         Self {
             inner: Arc::new(QuotaManagerInner {
                 usage: RwLock::new(HashMap::new()),
-                limits: build_limits(api_keys, quotas),
-                global_daily: quotas.daily_token_limit,
-                global_monthly: quotas.monthly_token_limit,
+                dirty: AtomicBool::new(false),
+                limits: ArcSwap::from_pointee(build_limits(api_keys, quotas)),
             }),
+            #[cfg(feature = "cluster")]
+            cluster: None,
         }
     }
 
+    /// Count tokens in counters shared through `cluster`.
+    #[cfg(feature = "cluster")]
+    pub fn with_cluster(mut self, cluster: crate::cluster::Cluster) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Enforce the limits of a reloaded config. Usage counted so far is
+    /// kept, so a key whose limit changed is checked against what it
+    /// already used.
+    pub fn reconfigure(&self, api_keys: &[ApiKeyConfig], quotas: &QuotaConfig) {
+        self.inner
+            .limits
+            .store(Arc::new(build_limits(api_keys, quotas)));
+    }
+
     /// Check whether the given API key is within quota limits.
     pub async fn check_quota(&self, api_key: &str) -> QuotaCheckResult {
         self.check_quota_hashed(&hash_api_key(api_key)).await
@@ -167,18 +340,32 @@ impl QuotaManager {
 
     /// Check quota using a pre-computed key hash (avoids redundant SHA-256).
     pub async fn check_quota_hashed(&self, key_hash: &str) -> QuotaCheckResult {
-        let today = Local::now().date_naive();
+        let today = Utc::now().date_naive();
         let this_month_start = start_of_month(today);
 
-        let limits = self
-            .inner
-            .limits
-            .get(key_hash)
-            .cloned()
-            .unwrap_or(ResolvedLimits {
-                daily: self.inner.global_daily,
-                monthly: self.inner.global_monthly,
-            });
+        let limits = self.inner.limits.load().for_key(key_hash);
+
+        #[cfg(feature = "cluster")]
+        if let Some(ref cluster) = self.cluster {
+            match cluster.token_usage(key_hash, today).await {
+                Ok((daily, monthly)) => {
+                    return Self::evaluate_limits(
+                        &limits,
+                        &KeyUsage {
+                            daily: PeriodUsage {
+                                total_tokens: daily,
+                                period_start: today,
+                            },
+                            monthly: PeriodUsage {
+                                total_tokens: monthly,
+                                period_start: this_month_start,
+                            },
+                        },
+                    );
+                }
+                Err(e) => cluster.report_error(&e),
+            }
+        }
 
         // Fast path: try read lock first — avoids write contention on every request
         {
@@ -234,18 +421,10 @@ impl QuotaManager {
     /// Returns None only if the lock is contended.
     pub fn check_quota_sync(&self, api_key: &str) -> Option<QuotaCheckResult> {
         let key_hash = hash_api_key(api_key);
-        let limits = self
-            .inner
-            .limits
-            .get(&key_hash)
-            .cloned()
-            .unwrap_or(ResolvedLimits {
-                daily: self.inner.global_daily,
-                monthly: self.inner.global_monthly,
-            });
+        let limits = self.inner.limits.load().for_key(&key_hash);
 
         let usage_map = self.inner.usage.try_read().ok()?;
-        let today = Local::now().date_naive();
+        let today = Utc::now().date_naive();
         let this_month_start = start_of_month(today);
         let zero_usage = KeyUsage {
             daily: PeriodUsage {
@@ -419,9 +598,35 @@ impl QuotaManager {
             return;
         }
 
-        let today = Local::now().date_naive();
+        let today = Utc::now().date_naive();
         let this_month_start = start_of_month(today);
 
+        // The shared counters are the totals; the local ones mirror them for
+        // the TUI and for when Redis is unreachable.
+        #[cfg(feature = "cluster")]
+        if let Some(ref cluster) = self.cluster {
+            match cluster.add_tokens(key_hash, today, total).await {
+                Ok((daily, monthly)) => {
+                    self.inner.usage.write().await.insert(
+                        key_hash.to_string(),
+                        KeyUsage {
+                            daily: PeriodUsage {
+                                total_tokens: daily,
+                                period_start: today,
+                            },
+                            monthly: PeriodUsage {
+                                total_tokens: monthly,
+                                period_start: this_month_start,
+                            },
+                        },
+                    );
+                    self.inner.dirty.store(true, Ordering::Relaxed);
+                    return;
+                }
+                Err(e) => cluster.report_error(&e),
+            }
+        }
+
         // Pre-fetch DB baseline outside the write lock if a period rollover is
         // likely. Holding the global usage write lock across a DB round-trip
         // would serialize every concurrent quota update behind it; the peek
@@ -453,6 +658,68 @@ impl QuotaManager {
 
         usage.daily.total_tokens += total;
         usage.monthly.total_tokens += total;
+        self.inner.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Restore counters written by [`save_state`](Self::save_state). Periods
+    /// that have rolled over since the file was written start from zero. A
+    /// missing file is not an error. Returns the number of keys restored.
+    pub async fn load_state(&self, path: &Path) -> anyhow::Result<usize> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let saved: HashMap<String, KeyUsage> = serde_json::from_slice(&data)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        let today = Utc::now().date_naive();
+        let this_month_start = start_of_month(today);
+        let mut usage_map = self.inner.usage.write().await;
+        let mut restored = 0;
+        for (key_hash, mut usage) in saved {
+            Self::zero_stale_periods(&mut usage, today, this_month_start);
+            if usage.daily.total_tokens == 0 && usage.monthly.total_tokens == 0 {
+                continue;
+            }
+            usage_map.insert(key_hash, usage);
+            restored += 1;
+        }
+        Ok(restored)
+    }
+
+    /// Write current counters to `path` (atomically, via a temp file) if
+    /// anything changed since the last save.
+    pub async fn save_state(&self, path: &Path) -> anyhow::Result<()> {
+        if !self.inner.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let snapshot = self.inner.usage.read().await.clone();
+        let path = path.to_path_buf();
+        let written = tokio::task::spawn_blocking(move || write_state(&path, &snapshot))
+            .await
+            .context("Quota state writer panicked")
+            .and_then(|r| r);
+        if written.is_err() {
+            // Try again on the next save rather than losing the update.
+            self.inner.dirty.store(true, Ordering::Relaxed);
+        }
+        written
+    }
+
+    fn zero_stale_periods(usage: &mut KeyUsage, today: NaiveDate, this_month_start: NaiveDate) {
+        if usage.daily.period_start != today {
+            usage.daily = PeriodUsage {
+                total_tokens: 0,
+                period_start: today,
+            };
+        }
+        if usage.monthly.period_start != this_month_start {
+            usage.monthly = PeriodUsage {
+                total_tokens: 0,
+                period_start: this_month_start,
+            };
+        }
     }
 
     /// Load baseline quota usage from the requests table.
@@ -460,12 +727,12 @@ impl QuotaManager {
     #[cfg(feature = "db")]
     pub async fn load_baselines(&self, db: &Database) -> anyhow::Result<()> {
         let rows = db.load_quota_baselines().await?;
-        let today = Local::now().date_naive();
+        let today = Utc::now().date_naive();
         let this_month_start = start_of_month(today);
 
         let mut usage_map = self.inner.usage.write().await;
 
-        for (key_hash, daily_tokens, monthly_tokens) in rows {
+        for (key_hash, daily_tokens, monthly_tokens) in rows.iter().cloned() {
             let usage = usage_map.entry(key_hash).or_insert_with(|| KeyUsage {
                 daily: PeriodUsage {
                     total_tokens: 0,
@@ -482,11 +749,35 @@ impl QuotaManager {
             usage.monthly.total_tokens = monthly_tokens;
             usage.monthly.period_start = this_month_start;
         }
+        drop(usage_map);
+
+        // The first router to start seeds the shared counters.
+        #[cfg(feature = "cluster")]
+        if let Some(ref cluster) = self.cluster {
+            for (key_hash, daily_tokens, monthly_tokens) in &rows {
+                cluster
+                    .seed_tokens(key_hash, today, *daily_tokens, *monthly_tokens)
+                    .await?;
+            }
+        }
 
         Ok(())
     }
 }
 
+fn write_state(path: &Path, usage: &HashMap<String, KeyUsage>) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(usage)?)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to rename {} into place", tmp.display()))?;
+    Ok(())
+}
+
 /// Returns the first day of the month containing `date`.
 pub(crate) fn start_of_month(date: NaiveDate) -> NaiveDate {
     NaiveDate::from_ymd_opt(date.year(), date.month(), 1).unwrap()
@@ -501,51 +792,31 @@ fn next_month_start(date: NaiveDate) -> NaiveDate {
     }
 }
 
-/// Seconds until next midnight local time.
+/// Seconds until next midnight UTC.
 fn seconds_until_next_day() -> u64 {
-    let now = Local::now();
-    let tomorrow = (now.date_naive() + chrono::Duration::days(1))
-        .and_hms_opt(0, 0, 0)
-        .unwrap();
-    let tomorrow_local = tomorrow.and_local_timezone(now.timezone()).unwrap();
-    let diff = tomorrow_local - now;
-    diff.num_seconds().max(1) as u64
+    (next_day_timestamp() - Utc::now().timestamp()).max(1) as u64
 }
 
-/// Seconds until the first of next month local time.
+/// Seconds until the first of next month UTC.
 fn seconds_until_next_month() -> u64 {
-    let now = Local::now();
-    let next_month = next_month_start(now.date_naive());
-    let next_month_local = next_month
-        .and_hms_opt(0, 0, 0)
-        .unwrap()
-        .and_local_timezone(now.timezone())
-        .unwrap();
-    let diff = next_month_local - now;
-    diff.num_seconds().max(1) as u64
+    (next_month_timestamp() - Utc::now().timestamp()).max(1) as u64
 }
 
-/// Unix timestamp of next midnight local time.
+/// Unix timestamp of next midnight UTC.
 fn next_day_timestamp() -> i64 {
-    let now = Local::now();
-    let tomorrow = (now.date_naive() + chrono::Duration::days(1))
+    (Utc::now().date_naive() + chrono::Duration::days(1))
         .and_hms_opt(0, 0, 0)
-        .unwrap();
-    tomorrow
-        .and_local_timezone(now.timezone())
         .unwrap()
+        .and_utc()
         .timestamp()
 }
 
-/// Unix timestamp of first of next month local time.
+/// Unix timestamp of first of next month UTC.
 fn next_month_timestamp() -> i64 {
-    let now = Local::now();
-    let next_month = next_month_start(now.date_naive());
-    next_month
+    next_month_start(Utc::now().date_naive())
         .and_hms_opt(0, 0, 0)
         .unwrap()
-        .and_local_timezone(now.timezone())
-        .unwrap()
+        .and_utc()
         .timestamp()
 }
 
@@ -559,6 +830,16 @@ mod tests {
             daily_token_limit: None,
             monthly_token_limit: None,
             requests_per_minute: None,
+            max_open_streams: None,
+            output_tokens_per_sec: None,
+            allowed_models: None,
+            model_aliases: Default::default(),
+            priority: Default::default(),
+            reasoning: Default::default(),
+            tenant: None,
+            client: None,
+            valid_from: None,
+            valid_until: None,
         }];
         let quotas = QuotaConfig {
             enabled: true,
@@ -593,6 +874,7 @@ mod tests {
                 output: 100,
                 cache_read: 0,
                 cache_write: 0,
+                ..Default::default()
             },
         )
         .await;
@@ -619,6 +901,7 @@ mod tests {
                 output: 200,
                 cache_read: 0,
                 cache_write: 0,
+                ..Default::default()
             },
         )
         .await; // 500 total = at limit
@@ -643,6 +926,7 @@ mod tests {
                 output: 500,
                 cache_read: 0,
                 cache_write: 0,
+                ..Default::default()
             },
         )
         .await; // 1100 > 1000
@@ -667,6 +951,7 @@ mod tests {
                 output: 999999,
                 cache_read: 0,
                 cache_write: 0,
+                ..Default::default()
             },
         )
         .await;
@@ -692,12 +977,32 @@ mod tests {
                 daily_token_limit: Some(100),
                 monthly_token_limit: None,
                 requests_per_minute: None,
+                max_open_streams: None,
+                output_tokens_per_sec: None,
+                allowed_models: None,
+                model_aliases: Default::default(),
+                priority: Default::default(),
+                reasoning: Default::default(),
+                tenant: None,
+                client: None,
+                valid_from: None,
+                valid_until: None,
             },
             ApiKeyConfig {
                 key: "unlimited-key".to_string(),
                 daily_token_limit: None,
                 monthly_token_limit: None,
                 requests_per_minute: None,
+                max_open_streams: None,
+                output_tokens_per_sec: None,
+                allowed_models: None,
+                model_aliases: Default::default(),
+                priority: Default::default(),
+                reasoning: Default::default(),
+                tenant: None,
+                client: None,
+                valid_from: None,
+                valid_until: None,
             },
         ];
         let quotas = QuotaConfig {
@@ -715,6 +1020,7 @@ mod tests {
                 output: 60,
                 cache_read: 0,
                 cache_write: 0,
+                ..Default::default()
             },
         )
         .await; // 110 > 100
@@ -734,6 +1040,7 @@ mod tests {
                 output: 60,
                 cache_read: 0,
                 cache_write: 0,
+                ..Default::default()
             },
         )
         .await;
@@ -760,6 +1067,7 @@ mod tests {
                 output: 100,
                 cache_read: 200,
                 cache_write: 100,
+                ..Default::default()
             },
         )
         .await;
@@ -779,6 +1087,16 @@ mod tests {
             daily_token_limit: Some(0),   // explicitly unlimited
             monthly_token_limit: Some(0), // explicitly unlimited
             requests_per_minute: None,
+            max_open_streams: None,
+            output_tokens_per_sec: None,
+            allowed_models: None,
+            model_aliases: Default::default(),
+            priority: Default::default(),
+            reasoning: Default::default(),
+            tenant: None,
+            client: None,
+            valid_from: None,
+            valid_until: None,
         }];
         let quotas = QuotaConfig {
             enabled: true,
@@ -796,6 +1114,7 @@ mod tests {
                 output: 5000,
                 cache_read: 0,
                 cache_write: 0,
+                ..Default::default()
             },
         )
         .await;
@@ -849,6 +1168,130 @@ mod tests {
         assert!(secs <= 31 * 86400);
     }
 
+    #[tokio::test]
+    async fn test_rate_limit_headers() {
+        let (keys, quotas) = make_config(Some(1000), None);
+        let qm = make_qm(&keys, &quotas);
+        qm.record_usage(
+            "test-key",
+            &TokenCounts {
+                input: 300,
+                output: 0,
+                cache_read: 0,
+                cache_write: 0,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let mut headers = HeaderMap::new();
+        qm.check_quota("test-key")
+            .await
+            .insert_headers(&mut headers);
+        assert_eq!(headers[RATELIMIT_LIMIT_TOKENS_DAY_HEADER], "1000");
+        assert_eq!(headers[RATELIMIT_REMAINING_TOKENS_DAY_HEADER], "700");
+        let reset: u64 = headers[RATELIMIT_RESET_TOKENS_DAY_HEADER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(reset <= 86400);
+        // No monthly limit configured → no monthly headers
+        assert!(!headers.contains_key(RATELIMIT_LIMIT_TOKENS_MONTH_HEADER));
+        // The daily window is the only one, so it's the OpenAI-style one too.
+        assert_eq!(headers[RATELIMIT_LIMIT_TOKENS_HEADER], "1000");
+        assert_eq!(headers[RATELIMIT_REMAINING_TOKENS_HEADER], "700");
+        assert!(
+            headers[RATELIMIT_RESET_TOKENS_HEADER]
+                .to_str()
+                .unwrap()
+                .ends_with('s')
+        );
+
+        let mut headers = HeaderMap::new();
+        QuotaCheckResult::Exceeded {
+            retry_after_secs: 42,
+            limit_type: LimitType::Monthly,
+        }
+        .insert_headers(&mut headers);
+        assert_eq!(headers[RATELIMIT_REMAINING_TOKENS_MONTH_HEADER], "0");
+        assert_eq!(headers[RATELIMIT_RESET_TOKENS_MONTH_HEADER], "42");
+        assert_eq!(headers[RATELIMIT_REMAINING_TOKENS_HEADER], "0");
+        assert_eq!(headers[RATELIMIT_RESET_TOKENS_HEADER], "42s");
+    }
+
+    #[tokio::test]
+    async fn test_state_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("quota_usage.json");
+        let (keys, quotas) = make_config(Some(1000), Some(5000));
+
+        let qm = make_qm(&keys, &quotas);
+        assert_eq!(qm.load_state(&path).await.unwrap(), 0);
+        qm.record_usage(
+            "test-key",
+            &TokenCounts {
+                input: 400,
+                output: 0,
+                cache_read: 0,
+                cache_write: 0,
+                ..Default::default()
+            },
+        )
+        .await;
+        qm.save_state(&path).await.unwrap();
+
+        // A "restarted" manager picks up where the old one left off.
+        let restarted = make_qm(&keys, &quotas);
+        assert_eq!(restarted.load_state(&path).await.unwrap(), 1);
+        match restarted.check_quota("test-key").await {
+            QuotaCheckResult::Allowed {
+                daily_remaining,
+                monthly_remaining,
+                ..
+            } => {
+                assert_eq!(daily_remaining, Some(600));
+                assert_eq!(monthly_remaining, Some(4600));
+            }
+            QuotaCheckResult::Exceeded { .. } => panic!("Should be allowed"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_state_file_drops_rolled_over_periods() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quota_usage.json");
+        let today = Utc::now().date_naive();
+        let yesterday = today - chrono::Duration::days(1);
+        let saved = serde_json::json!({
+            hash_api_key("test-key"): {
+                "daily": {"total_tokens": 900, "period_start": yesterday},
+                "monthly": {"total_tokens": 900, "period_start": start_of_month(today)},
+            },
+            hash_api_key("old-key"): {
+                "daily": {"total_tokens": 50, "period_start": "2020-01-01"},
+                "monthly": {"total_tokens": 50, "period_start": "2020-01-01"},
+            },
+        });
+        std::fs::write(&path, saved.to_string()).unwrap();
+
+        let (keys, quotas) = make_config(Some(1000), Some(5000));
+        let qm = make_qm(&keys, &quotas);
+        // old-key has nothing left in the current periods
+        assert_eq!(qm.load_state(&path).await.unwrap(), 1);
+        match qm.check_quota("test-key").await {
+            QuotaCheckResult::Allowed {
+                daily_remaining,
+                monthly_remaining,
+                ..
+            } => {
+                assert_eq!(daily_remaining, Some(1000));
+                assert_eq!(monthly_remaining, Some(4100));
+            }
+            QuotaCheckResult::Exceeded { .. } => panic!("Should be allowed"),
+        }
+    }
+
     #[cfg(feature = "db")]
     #[tokio::test]
     async fn test_load_baselines_from_requests() {
@@ -879,6 +1322,8 @@ mod tests {
                 cache_read_tokens: Some(200),
                 cache_write_tokens: Some(10),
                 api_key_hash: Some(key_hash.clone()),
+                session_id: None,
+                attempts: Vec::new(),
             };
             db.insert_request(record).await.unwrap();
         }
@@ -936,12 +1381,14 @@ mod tests {
                 cache_read_tokens: Some(200),
                 cache_write_tokens: Some(10),
                 api_key_hash: Some(key_hash.clone()),
+                session_id: None,
+                attempts: Vec::new(),
             };
             db.insert_request(record).await.unwrap();
         }
 
         // Query by day
-        let today = format!("{} 00:00:00", Local::now().date_naive());
+        let today = format!("{} 00:00:00", chrono::Local::now().date_naive());
         let rows = db
             .query_usage(Some(&key_hash), &today, crate::database::GroupBy::Day)
             .await
@@ -981,6 +1428,8 @@ mod tests {
             cache_read_tokens: None,
             cache_write_tokens: None,
             api_key_hash: Some("abc123".to_string()),
+            session_id: None,
+            attempts: Vec::new(),
         };
         db.insert_request(record).await.unwrap();
 
@@ -989,7 +1438,7 @@ mod tests {
         assert_eq!(deleted, 0);
 
         // Verify record still exists
-        let today = format!("{} 00:00:00", Local::now().date_naive());
+        let today = format!("{} 00:00:00", chrono::Local::now().date_naive());
         let rows = db
             .query_usage(None, &today, crate::database::GroupBy::Day)
             .await
@@ -0,0 +1,163 @@
+//! Redaction of request and response bodies in debug logs.
+//!
+//! Debug logging shows the bodies acr sends upstream and gets back, which
+//! is what makes it useful and also what makes it unsafe to turn on in
+//! production: the bodies carry users' prompts and, now and then, secrets
+//! pasted into them. Before a body is logged, values at the configured
+//! `log_redaction.paths` (message contents by default) are masked, as are
+//! fields named like credentials (`api_key`, `authorization`, ...) anywhere
+//! in the body and `Bearer` tokens inside any string. Like Sentry reporting,
+//! the redactor is process-global: [`init`] once at startup, then
+//! [`for_log`] from anywhere; before `init`, the default config applies.
+
+use std::borrow::Cow;
+use std::sync::{LazyLock, OnceLock};
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::config::LogRedactionConfig;
+use crate::constants::redaction::{MASK, MASK_BEARER, SECRET_KEYS};
+
+static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+
+static BEARER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bbearer\s+[A-Za-z0-9._~+/=-]+").unwrap());
+
+#[derive(Debug, Clone)]
+struct Redactor {
+    enabled: bool,
+    /// `messages.*.content` split into segments; `*` matches any key or index.
+    paths: Vec<Vec<String>>,
+}
+
+impl Redactor {
+    fn from_config(config: &LogRedactionConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            paths: config
+                .paths
+                .iter()
+                .map(|path| path.split('.').map(str::to_string).collect())
+                .collect(),
+        }
+    }
+
+    fn redact(&self, value: &Value, path: &mut Vec<String>) -> Value {
+        if self.masks(path) {
+            return Value::String(MASK.to_string());
+        }
+        match value {
+            Value::Object(map) => {
+                let mut out = Map::with_capacity(map.len());
+                for (key, value) in map {
+                    let value = if is_secret_key(key) {
+                        Value::String(MASK.to_string())
+                    } else {
+                        path.push(key.clone());
+                        let value = self.redact(value, path);
+                        path.pop();
+                        value
+                    };
+                    out.insert(key.clone(), value);
+                }
+                Value::Object(out)
+            }
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| {
+                        path.push(i.to_string());
+                        let item = self.redact(item, path);
+                        path.pop();
+                        item
+                    })
+                    .collect(),
+            ),
+            Value::String(s) => Value::String(BEARER.replace_all(s, MASK_BEARER).into_owned()),
+            other => other.clone(),
+        }
+    }
+
+    fn masks(&self, path: &[String]) -> bool {
+        !path.is_empty()
+            && self.paths.iter().any(|pattern| {
+                pattern.len() == path.len()
+                    && pattern
+                        .iter()
+                        .zip(path)
+                        .all(|(want, got)| want == "*" || want == got)
+            })
+    }
+}
+
+fn is_secret_key(key: &str) -> bool {
+    SECRET_KEYS.iter().any(|k| k.eq_ignore_ascii_case(key))
+}
+
+/// Install the redaction settings. Later calls are ignored.
+pub fn init(config: &LogRedactionConfig) {
+    let _ = REDACTOR.set(Redactor::from_config(config));
+}
+
+/// `body` as it may appear in logs. With redaction off, the body itself.
+pub fn for_log(body: &Value) -> Cow<'_, Value> {
+    let redactor = REDACTOR.get_or_init(|| Redactor::from_config(&LogRedactionConfig::default()));
+    if !redactor.enabled {
+        return Cow::Borrowed(body);
+    }
+    Cow::Owned(redactor.redact(body, &mut Vec::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn redact(config: &LogRedactionConfig, body: &Value) -> Value {
+        Redactor::from_config(config).redact(body, &mut Vec::new())
+    }
+
+    #[test]
+    fn masks_prompts_and_secrets_by_default() {
+        let body = json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "system", "content": "You are helpful"},
+                {"role": "user", "content": [{"type": "text", "text": "my secret plan"}]}
+            ],
+            "metadata": {"API_KEY": "sk-123", "note": "sent with Bearer abc.def-ghi"},
+            "max_tokens": 100
+        });
+        let redacted = redact(&LogRedactionConfig::default(), &body);
+        assert_eq!(
+            redacted,
+            json!({
+                "model": "gpt-4o",
+                "messages": [
+                    {"role": "system", "content": "[REDACTED]"},
+                    {"role": "user", "content": "[REDACTED]"}
+                ],
+                "metadata": {"API_KEY": "[REDACTED]", "note": "sent with Bearer [REDACTED]"},
+                "max_tokens": 100
+            })
+        );
+    }
+
+    #[test]
+    fn configured_paths_replace_defaults() {
+        let config = LogRedactionConfig {
+            paths: vec!["tools.*.description".to_string()],
+            ..Default::default()
+        };
+        let body = json!({
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [{"name": "search", "description": "internal index"}]
+        });
+        let redacted = redact(&config, &body);
+        assert_eq!(redacted["messages"][0]["content"], "hi");
+        assert_eq!(redacted["tools"][0]["name"], "search");
+        assert_eq!(redacted["tools"][0]["description"], "[REDACTED]");
+    }
+}
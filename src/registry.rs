@@ -1,22 +1,99 @@
 //! Model registry that tracks deployments across multiple providers.
 
 use anyhow::{Result, anyhow};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
 use tracing::{error, info, warn};
 
-use crate::client::AiCoreClient;
-use crate::config::{FallbackModels, Model, Provider};
+use crate::client::{AiCoreClient, Deployment};
+use crate::config::{AutoDiscoverConfig, FallbackModels, Model, Provider};
 use crate::token::TokenManager;
 
 /// Resolved deployment information including which provider hosts it
-#[derive(Debug, Clone)]
-struct ResolvedDeployment {
-    deployment_id: String,
-    provider_name: String,
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ResolvedDeployment {
+    pub deployment_id: String,
+    #[serde(rename = "provider")]
+    pub provider_name: String,
+}
+
+/// A model name `/v1/models` lists, with what it resolves to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedModel {
+    pub id: String,
+    /// The model this name stands in for: the configured model for an
+    /// alias, the group's root for a model sharing its deployments, and
+    /// `None` for a root itself.
+    pub parent: Option<String>,
+    /// The first configured model served by the same deployments.
+    pub root: String,
+}
+
+/// A deployment problem found while resolving models.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DeploymentConflict {
+    /// Several running deployments on one provider serve the same AI Core
+    /// model. acr routes to `chosen`, the lowest deployment ID.
+    DuplicateModel {
+        provider: String,
+        aicore_model_name: String,
+        deployment_ids: Vec<String>,
+        chosen: String,
+    },
+    /// A configured model no running deployment serves on any provider.
+    MissingDeployment {
+        model: String,
+        aicore_model_name: String,
+    },
+}
+
+impl std::fmt::Display for DeploymentConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeploymentConflict::DuplicateModel {
+                provider,
+                aicore_model_name,
+                deployment_ids,
+                chosen,
+            } => write!(
+                f,
+                "provider '{provider}' has {} running deployments of '{aicore_model_name}' ({}); using {chosen}",
+                deployment_ids.len(),
+                deployment_ids.join(", ")
+            ),
+            DeploymentConflict::MissingDeployment {
+                model,
+                aicore_model_name,
+            } => write!(
+                f,
+                "model '{model}' has no running deployment of '{aicore_model_name}'"
+            ),
+        }
+    }
+}
+
+/// Running deployments by AI Core model name, each list sorted by ID.
+fn running_deployments(deployments: &[Deployment]) -> HashMap<String, Vec<String>> {
+    let mut running: HashMap<String, Vec<String>> = HashMap::new();
+    for deployment in deployments {
+        if deployment.status != crate::constants::deployment::RUNNING_STATUS {
+            continue;
+        }
+        if let Some(model_name) = deployment.get_aicore_model_name() {
+            running
+                .entry(model_name)
+                .or_default()
+                .push(deployment.id.clone());
+        }
+    }
+    for ids in running.values_mut() {
+        ids.sort();
+    }
+    running
 }
 
 /// Runtime model registry that manages resolved deployment IDs across multiple providers
@@ -34,6 +111,13 @@ pub struct ModelRegistry {
     token_manager: TokenManager,
     /// Refresh interval for background updates
     refresh_interval: Duration,
+    /// Which unconfigured deployments are published
+    auto_discover: AutoDiscoverConfig,
+    /// Names published by auto-discovery at the last refresh. Behind a
+    /// blocking lock so request-time model resolution can stay synchronous.
+    discovered: Arc<std::sync::RwLock<HashSet<String>>>,
+    /// Problems found at the last refresh
+    conflicts: Arc<RwLock<Vec<DeploymentConflict>>>,
 }
 
 impl ModelRegistry {
@@ -52,9 +136,18 @@ impl ModelRegistry {
             providers,
             token_manager,
             refresh_interval: Duration::from_secs(refresh_interval_secs),
+            auto_discover: AutoDiscoverConfig::default(),
+            discovered: Arc::new(std::sync::RwLock::new(HashSet::new())),
+            conflicts: Arc::new(RwLock::new(Vec::new())),
         }
     }
 
+    /// Also publish running deployments missing from the configured models.
+    pub fn with_auto_discover(mut self, auto_discover: AutoDiscoverConfig) -> Self {
+        self.auto_discover = auto_discover;
+        self
+    }
+
     /// Start the registry: validate config, do an initial deployment fetch,
     /// then spawn the background refresh task.
     ///
@@ -127,6 +220,85 @@ impl ModelRegistry {
         models
     }
 
+    /// Every name clients can request, sorted by id. Configured models served
+    /// by the same deployments share a `root`: the first of them in config
+    /// order. Exact (wildcard-free) aliases are listed too, with the model
+    /// they resolve to as `parent`, so clients can fold them together in a
+    /// model picker.
+    pub async fn list_models(&self) -> Vec<ListedModel> {
+        let resolved = self.resolved_models.read().await;
+        let mut names: Vec<&String> = resolved.keys().collect();
+        names.sort_by_key(|name| {
+            (
+                self.config_models
+                    .iter()
+                    .position(|m| &m.name == *name)
+                    .unwrap_or(usize::MAX),
+                name.as_str(),
+            )
+        });
+
+        let mut roots: Vec<(Vec<(&str, &str)>, &String)> = Vec::new();
+        let mut listed = Vec::new();
+        for name in names {
+            let mut deployments: Vec<(&str, &str)> = resolved[name]
+                .iter()
+                .map(|d| (d.provider_name.as_str(), d.deployment_id.as_str()))
+                .collect();
+            deployments.sort_unstable();
+            let root = match roots.iter().find(|(d, _)| *d == deployments) {
+                Some((_, root)) => *root,
+                None => {
+                    roots.push((deployments, name));
+                    name
+                }
+            };
+            listed.push(ListedModel {
+                id: name.clone(),
+                parent: (root != name).then(|| root.clone()),
+                root: root.clone(),
+            });
+
+            let Some(model) = self.find_model_config(name) else {
+                continue;
+            };
+            for alias in model.aliases.iter().filter(|a| !a.contains('*')) {
+                // A configured name shadows an alias, and an alias claimed by
+                // more than one model goes to whichever resolution picks.
+                if self.find_model_config(alias).is_some()
+                    || self.find_model_by_alias(alias).map(|m| &m.name) != Some(name)
+                {
+                    continue;
+                }
+                listed.push(ListedModel {
+                    id: alias.clone(),
+                    parent: Some(name.clone()),
+                    root: root.clone(),
+                });
+            }
+        }
+        listed.sort_by(|a, b| a.id.cmp(&b.id));
+        listed
+    }
+
+    /// Deployment conflicts found at the last refresh.
+    pub async fn conflicts(&self) -> Vec<DeploymentConflict> {
+        self.conflicts.read().await.clone()
+    }
+
+    /// Query every provider again now, rather than at the next background
+    /// refresh.
+    pub async fn refresh(&self) -> Result<()> {
+        self.refresh_deployments().await
+    }
+
+    /// Query every provider once and return the conflicts found, without
+    /// starting the background refresh.
+    pub async fn check_deployments(&self) -> Result<Vec<DeploymentConflict>> {
+        self.refresh_deployments().await?;
+        Ok(self.conflicts().await)
+    }
+
     /// Non-blocking count of resolved models for synchronous contexts (e.g. TUI rendering).
     /// Returns `None` if the lock is contended (e.g. during a refresh).
     pub fn resolved_model_count_sync(&self) -> Option<usize> {
@@ -138,6 +310,13 @@ impl ModelRegistry {
         self.config_models.iter().find(|m| m.name == model_name)
     }
 
+    /// Whether auto-discovery published `model_name` at the last refresh.
+    pub fn is_discovered(&self, model_name: &str) -> bool {
+        self.discovered
+            .read()
+            .is_ok_and(|discovered| discovered.contains(model_name))
+    }
+
     /// Get fallback model for a given model prefix/family
     pub fn get_fallback_model(&self, prefix: &str) -> Option<&str> {
         use crate::constants::models::*;
@@ -174,6 +353,17 @@ impl ModelRegistry {
         best_match.map(|(model, _)| model)
     }
 
+    /// Whether a deployment of `aicore_model_name` is published by
+    /// auto-discovery: allowed by its filters and claimed by no configured
+    /// model, neither by name nor by `aicore_model_name`.
+    fn discovers(&self, aicore_model_name: &str) -> bool {
+        self.auto_discover.publishes(aicore_model_name)
+            && !self.config_models.iter().any(|m| {
+                m.name == aicore_model_name
+                    || m.aicore_model_name.as_deref() == Some(aicore_model_name)
+            })
+    }
+
     async fn background_refresh(&self) {
         let mut interval = tokio::time::interval(self.refresh_interval);
 
@@ -184,10 +374,116 @@ impl ModelRegistry {
             interval.tick().await;
             if let Err(e) = self.refresh_deployments().await {
                 error!("Failed to refresh deployments: {}", e);
+                crate::sentry::capture(&format!("Failed to refresh deployments: {e:#}"), &[]);
             }
         }
     }
 
+    /// Re-resolve one model against every provider right away, e.g. after
+    /// redeploying it, leaving the rest of the catalog alone. A provider that
+    /// can't be queried keeps its previous deployment of the model. Returns
+    /// `None` when `model_name` is neither configured nor auto-discoverable.
+    pub async fn refresh_model(&self, model_name: &str) -> Result<Option<Vec<ResolvedDeployment>>> {
+        let aicore_model_name = match self.find_model_config(model_name) {
+            Some(model) => model.aicore_model_name.as_deref().unwrap_or(&model.name),
+            None if self.discovers(model_name) => model_name,
+            None => return Ok(None),
+        };
+
+        let previous = self
+            .resolved_models
+            .read()
+            .await
+            .get(model_name)
+            .cloned()
+            .unwrap_or_default();
+        let mut resolved = Vec::new();
+        let mut duplicates = Vec::new();
+        for provider in self.providers.iter().filter(|p| p.enabled) {
+            let client = AiCoreClient::from_provider(provider.clone(), self.token_manager.clone());
+            match client
+                .list_deployments(Some(&provider.resource_group))
+                .await
+            {
+                Ok(deployments) => {
+                    let running = running_deployments(&deployments.resources);
+                    let Some(ids) = running.get(aicore_model_name) else {
+                        continue;
+                    };
+                    if ids.len() > 1 {
+                        duplicates.push(DeploymentConflict::DuplicateModel {
+                            provider: provider.name.clone(),
+                            aicore_model_name: aicore_model_name.to_string(),
+                            deployment_ids: ids.clone(),
+                            chosen: ids[0].clone(),
+                        });
+                    }
+                    resolved.push(ResolvedDeployment {
+                        deployment_id: ids[0].clone(),
+                        provider_name: provider.name.clone(),
+                    });
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to query provider '{}' while refreshing '{}': {}",
+                        provider.name, model_name, e
+                    );
+                    resolved.extend(
+                        previous
+                            .iter()
+                            .filter(|d| d.provider_name == provider.name)
+                            .cloned(),
+                    );
+                }
+            }
+        }
+
+        info!(
+            "Refreshed model '{}': {} deployment(s)",
+            model_name,
+            resolved.len()
+        );
+        for conflict in &duplicates {
+            warn!("Deployment conflict: {}", conflict);
+        }
+        {
+            let mut resolved_models = self.resolved_models.write().await;
+            if resolved.is_empty() {
+                resolved_models.remove(model_name);
+            } else {
+                resolved_models.insert(model_name.to_string(), resolved.clone());
+            }
+        }
+        if self.find_model_config(model_name).is_none()
+            && let Ok(mut discovered) = self.discovered.write()
+        {
+            if resolved.is_empty() {
+                discovered.remove(model_name);
+            } else {
+                discovered.insert(model_name.to_string());
+            }
+        }
+        {
+            let mut conflicts = self.conflicts.write().await;
+            conflicts.retain(|c| match c {
+                DeploymentConflict::DuplicateModel {
+                    aicore_model_name: name,
+                    ..
+                } => name != aicore_model_name,
+                DeploymentConflict::MissingDeployment { model, .. } => model != model_name,
+            });
+            conflicts.extend(duplicates);
+            if resolved.is_empty() && self.find_model_config(model_name).is_some() {
+                conflicts.push(DeploymentConflict::MissingDeployment {
+                    model: model_name.to_string(),
+                    aicore_model_name: aicore_model_name.to_string(),
+                });
+            }
+        }
+
+        Ok(Some(resolved))
+    }
+
     async fn refresh_deployments(&self) -> Result<()> {
         info!(
             "Refreshing deployment mappings for {} providers...",
@@ -195,6 +491,8 @@ impl ModelRegistry {
         );
 
         let mut all_resolved: HashMap<String, Vec<ResolvedDeployment>> = HashMap::new();
+        let mut discovered: HashSet<String> = HashSet::new();
+        let mut conflicts: Vec<DeploymentConflict> = Vec::new();
 
         // Collect rows for the summary table: (provider, deployment_id, status, deployed_model, config_model)
         let mut table_rows: Vec<(String, String, String, String, String)> = Vec::new();
@@ -213,16 +511,21 @@ impl ModelRegistry {
                 .await
             {
                 Ok(deployments) => {
-                    // Build mapping from aicore model name -> (deployment_id, status)
-                    let mut aicore_map: HashMap<String, (String, String)> = HashMap::new();
-                    for deployment in &deployments.resources {
-                        if let Some(model_name) = deployment.get_aicore_model_name() {
-                            aicore_map.insert(
-                                model_name,
-                                (deployment.id.clone(), deployment.status.clone()),
-                            );
-                        }
-                    }
+                    // Running deployments by aicore model name; a model with
+                    // more than one goes to the lowest ID, and is reported
+                    let running = running_deployments(&deployments.resources);
+                    let mut duplicates: Vec<_> = running
+                        .iter()
+                        .filter(|(_, ids)| ids.len() > 1)
+                        .map(|(name, ids)| DeploymentConflict::DuplicateModel {
+                            provider: provider.name.clone(),
+                            aicore_model_name: name.clone(),
+                            deployment_ids: ids.clone(),
+                            chosen: ids[0].clone(),
+                        })
+                        .collect();
+                    duplicates.sort_by_key(|c| c.to_string());
+                    conflicts.extend(duplicates);
 
                     // Log all deployments from this provider
                     for deployment in &deployments.resources {
@@ -238,7 +541,13 @@ impl ModelRegistry {
                                 aicore_name == &deployed_model
                             })
                             .map(|m| m.name.clone())
-                            .unwrap_or_else(|| "-".to_string());
+                            .unwrap_or_else(|| {
+                                if self.discovers(&deployed_model) {
+                                    format!("{deployed_model} (discovered)")
+                                } else {
+                                    "-".to_string()
+                                }
+                            });
 
                         table_rows.push((
                             provider.name.clone(),
@@ -256,8 +565,8 @@ impl ModelRegistry {
                             .as_ref()
                             .unwrap_or(&model_config.name);
 
-                        if let Some((deployment_id, status)) = aicore_map.get(aicore_model_name)
-                            && status == crate::constants::deployment::RUNNING_STATUS
+                        if let Some(deployment_id) =
+                            running.get(aicore_model_name).and_then(|ids| ids.first())
                         {
                             all_resolved
                                 .entry(model_config.name.clone())
@@ -268,12 +577,31 @@ impl ModelRegistry {
                                 });
                         }
                     }
+
+                    // Publish running deployments no configured model claims
+                    for (aicore_model_name, ids) in &running {
+                        if self.discovers(aicore_model_name) {
+                            let deployment_id = &ids[0];
+                            discovered.insert(aicore_model_name.clone());
+                            all_resolved
+                                .entry(aicore_model_name.clone())
+                                .or_default()
+                                .push(ResolvedDeployment {
+                                    deployment_id: deployment_id.clone(),
+                                    provider_name: provider.name.clone(),
+                                });
+                        }
+                    }
                 }
                 Err(e) => {
                     error!(
                         "Failed to query provider '{}': {}. Skipping this provider.",
                         provider.name, e
                     );
+                    crate::sentry::capture(
+                        &format!("Failed to query deployments: {e:#}"),
+                        &[("provider", &provider.name)],
+                    );
                 }
             }
         }
@@ -335,12 +663,33 @@ impl ModelRegistry {
             .filter(|m| !all_resolved.contains_key(&m.name))
             .map(|m| m.name.as_str())
             .collect();
+        conflicts.extend(
+            self.config_models
+                .iter()
+                .filter(|m| unresolved.contains(&m.name.as_str()))
+                .map(|m| DeploymentConflict::MissingDeployment {
+                    model: m.name.clone(),
+                    aicore_model_name: m.aicore_model_name.clone().unwrap_or(m.name.clone()),
+                }),
+        );
 
         // Update the resolved models
         {
             let mut resolved_models = self.resolved_models.write().await;
             *resolved_models = all_resolved;
         }
+        if self.auto_discover.enabled {
+            info!("Auto-discovered {} unconfigured models", discovered.len());
+        }
+        if let Ok(mut published) = self.discovered.write() {
+            *published = discovered;
+        }
+        for conflict in &conflicts {
+            if matches!(conflict, DeploymentConflict::DuplicateModel { .. }) {
+                warn!("Deployment conflict: {}", conflict);
+            }
+        }
+        *self.conflicts.write().await = conflicts;
 
         info!(
             "Deployment refresh complete: {} models resolved across {} provider deployments",
@@ -388,7 +737,7 @@ impl ModelRegistry {
 /// - `*-haiku-*` matches `claude-haiku-4-5` with specificity 7
 /// - `claude-*` matches `claude-anything` with specificity 7 (trailing-only is the common case)
 /// - `claude-opus-4-7` exact-matches only `claude-opus-4-7` with specificity 15
-fn glob_matches(pattern: &str, input: &str) -> Option<usize> {
+pub(crate) fn glob_matches(pattern: &str, input: &str) -> Option<usize> {
     // Fast paths first.
     if !pattern.contains('*') {
         return (pattern == input).then_some(pattern.len());
@@ -507,6 +856,146 @@ mod tests {
         )
     }
 
+    fn model(name: &str, aicore_model_name: Option<&str>, aliases: &[&str]) -> Model {
+        Model {
+            name: name.to_string(),
+            aicore_model_name: aicore_model_name.map(str::to_string),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            pricing: None,
+            default_max_tokens: None,
+            deprecation: None,
+            safety_settings: vec![],
+            url_template: None,
+            disabled: None,
+        }
+    }
+
+    #[test]
+    fn test_running_deployments_keeps_every_duplicate() {
+        let deployment = |id: &str, model: &str, status: &str| -> Deployment {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "createdAt": "2026-01-01T00:00:00Z",
+                "modifiedAt": "2026-01-01T00:00:00Z",
+                "status": status,
+                "scenarioId": "foundation-models",
+                "configurationId": "c",
+                "details": {"resources": {"backendDetails": {"model": {"name": model}}}}
+            }))
+            .unwrap()
+        };
+        let running = running_deployments(&[
+            deployment("d2", "gpt-4o", "RUNNING"),
+            deployment("d1", "gpt-4o", "RUNNING"),
+            deployment("d3", "gemini-2.5-pro", "STOPPED"),
+            deployment("d4", "gemini-2.5-pro", "RUNNING"),
+        ]);
+        // Duplicates sort by ID; a stopped deployment never hides a running one.
+        assert_eq!(running["gpt-4o"], ["d1", "d2"]);
+        assert_eq!(running["gemini-2.5-pro"], ["d4"]);
+
+        let conflict = DeploymentConflict::DuplicateModel {
+            provider: "eu".to_string(),
+            aicore_model_name: "gpt-4o".to_string(),
+            deployment_ids: running["gpt-4o"].clone(),
+            chosen: "d1".to_string(),
+        };
+        assert_eq!(
+            conflict.to_string(),
+            "provider 'eu' has 2 running deployments of 'gpt-4o' (d1, d2); using d1"
+        );
+        assert_eq!(
+            serde_json::to_value(&conflict).unwrap()["kind"],
+            "duplicate_model"
+        );
+    }
+
+    #[test]
+    fn test_auto_discover_skips_configured_and_filtered_models() {
+        let auto_discover: AutoDiscoverConfig = serde_yaml_ng::from_str(
+            r#"
+include: ["gpt-*", "anthropic--*"]
+exclude: ["*-mini"]
+"#,
+        )
+        .unwrap();
+        let registry = create_test_registry(vec![
+            model("sonnet", Some("anthropic--claude-4.6-sonnet"), &[]),
+            model("gpt-5", None, &[]),
+        ])
+        .with_auto_discover(auto_discover);
+
+        assert!(registry.discovers("gpt-4o"));
+        assert!(registry.discovers("anthropic--claude-4.7-opus"));
+        assert!(!registry.discovers("gpt-5"));
+        assert!(!registry.discovers("anthropic--claude-4.6-sonnet"));
+        assert!(!registry.discovers("gpt-5-mini"));
+        assert!(!registry.discovers("gemini-2.5-pro"));
+
+        let disabled = create_test_registry(vec![]);
+        assert!(!disabled.discovers("gpt-4o"));
+        let flag: AutoDiscoverConfig = serde_yaml_ng::from_str("true").unwrap();
+        assert!(
+            create_test_registry(vec![])
+                .with_auto_discover(flag)
+                .discovers("gemini-2.5-pro")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_models_groups_aliases_and_shared_deployments() {
+        let registry = create_test_registry(vec![
+            model(
+                "claude-opus-4-7",
+                Some("anthropic--claude-4.7-opus"),
+                &["claude-opus-4-7-*", "claude-opus", "gpt-4o"],
+            ),
+            model("claude-opus", Some("anthropic--claude-4.7-opus"), &[]),
+            model("opus-latest", Some("anthropic--claude-4.7-opus"), &[]),
+            model("gpt-4o", None, &[]),
+            model("unresolved", None, &["not-listed"]),
+        ]);
+        {
+            let mut resolved = registry.resolved_models.write().await;
+            for (name, deployment) in [
+                ("claude-opus-4-7", "d-opus"),
+                ("claude-opus", "d-opus"),
+                ("opus-latest", "d-opus"),
+                ("gpt-4o", "d-gpt"),
+            ] {
+                resolved.insert(
+                    name.to_string(),
+                    vec![ResolvedDeployment {
+                        deployment_id: deployment.to_string(),
+                        provider_name: "primary".to_string(),
+                    }],
+                );
+            }
+        }
+
+        let listed = registry.list_models().await;
+        let entry = |id: &str| {
+            listed
+                .iter()
+                .find(|m| m.id == id)
+                .map(|m| (m.parent.as_deref(), m.root.as_str()))
+        };
+        assert_eq!(entry("claude-opus-4-7"), Some((None, "claude-opus-4-7")));
+        assert_eq!(
+            entry("opus-latest"),
+            Some((Some("claude-opus-4-7"), "claude-opus-4-7"))
+        );
+        assert_eq!(entry("gpt-4o"), Some((None, "gpt-4o")));
+        // Configured names win over aliases, and wildcard aliases and
+        // unresolved models aren't listed.
+        assert_eq!(listed.iter().filter(|m| m.id == "claude-opus").count(), 1);
+        assert_eq!(
+            entry("claude-opus"),
+            Some((Some("claude-opus-4-7"), "claude-opus-4-7"))
+        );
+        assert_eq!(listed.len(), 4);
+    }
+
     #[test]
     fn test_find_model_by_alias_exact() {
         let models = vec![Model {
@@ -514,6 +1003,11 @@ mod tests {
             aicore_model_name: None,
             aliases: vec!["claude-4-sonnet".to_string()],
             pricing: None,
+            default_max_tokens: None,
+            deprecation: None,
+            safety_settings: vec![],
+            url_template: None,
+            disabled: None,
         }];
         let registry = create_test_registry(models);
 
@@ -529,6 +1023,11 @@ mod tests {
             aicore_model_name: None,
             aliases: vec!["claude-sonnet-4-5-*".to_string()],
             pricing: None,
+            default_max_tokens: None,
+            deprecation: None,
+            safety_settings: vec![],
+            url_template: None,
+            disabled: None,
         }];
         let registry = create_test_registry(models);
 
@@ -545,12 +1044,22 @@ mod tests {
                 aicore_model_name: None,
                 aliases: vec!["claude-*".to_string()],
                 pricing: None,
+                default_max_tokens: None,
+                deprecation: None,
+                safety_settings: vec![],
+                url_template: None,
+                disabled: None,
             },
             Model {
                 name: "claude-sonnet-4-5".to_string(),
                 aicore_model_name: None,
                 aliases: vec!["claude-sonnet-4-5-*".to_string()],
                 pricing: None,
+                default_max_tokens: None,
+                deprecation: None,
+                safety_settings: vec![],
+                url_template: None,
+                disabled: None,
             },
         ];
         let registry = create_test_registry(models);
@@ -573,6 +1082,11 @@ mod tests {
             aicore_model_name: None,
             aliases: vec!["claude-sonnet-4-5-*".to_string()],
             pricing: None,
+            default_max_tokens: None,
+            deprecation: None,
+            safety_settings: vec![],
+            url_template: None,
+            disabled: None,
         }];
         let registry = create_test_registry(models);
 
@@ -591,6 +1105,11 @@ mod tests {
                 "sonnet-4.5".to_string(),
             ],
             pricing: None,
+            default_max_tokens: None,
+            deprecation: None,
+            safety_settings: vec![],
+            url_template: None,
+            disabled: None,
         }];
         let registry = create_test_registry(models);
 
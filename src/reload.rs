@@ -0,0 +1,707 @@
+//! Applying config edits without a restart.
+//!
+//! acr watches the config file and the keys file. When either changes, the
+//! config is read again and the sections that can change while serving take
+//! effect: `providers`, `api_keys`, `models`, `fallback_models`,
+//! `load_balancing`, `log_level`, the limits in `quotas` and `streams`. The
+//! token manager, model registry, load balancer, per-key limiters and
+//! tenant quotas are rebuilt for the new config and the router is swapped
+//! in one step, so a request keeps the state it started with and the next
+//! one sees the new config. Cached OAuth tokens for credentials that are
+//! gone are dropped; the limiters keep what keys already used, the tenant
+//! quotas what was sent to each provider, and streams already open stay
+//! counted.
+//!
+//! Every other section, and `quotas.enabled` and `quotas.state_file`, keep
+//! the values acr started with; a reload that changes one says it needs a
+//! restart. A config that doesn't load, or whose
+//! models can't be resolved, is rejected and the running one stays.
+//!
+//! On Unix, SIGHUP does the same whether or not the files changed, and also
+//! resolves the models against AI Core right away instead of at the next
+//! refresh. Each reload logs what changed: models added and removed, API
+//! keys added and revoked, and providers with new credentials.
+
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use axum::Router;
+use axum::extract::Request;
+use axum::response::Response;
+use axum::routing::future::RouteFuture;
+use notify::{RecursiveMode, Watcher};
+use tokio::task::JoinHandle;
+use tower::Service;
+
+use crate::balancer::LoadBalancer;
+use crate::config::Config;
+use crate::constants::reload::DEBOUNCE_MS;
+use crate::registry::ModelRegistry;
+use crate::request_limiter::RequestLimiter;
+use crate::routes::AppState;
+use crate::stream_limit::StreamLimiter;
+use crate::stream_pace::OutputPacer;
+use crate::tenant_quota::TenantQuotas;
+
+/// Top-level config sections a reload applies.
+pub const RELOADABLE: &[&str] = &[
+    "providers",
+    "api_keys",
+    "models",
+    "fallback_models",
+    "load_balancing",
+    "log_level",
+    "quotas",
+    "streams",
+];
+
+/// A router that can be replaced while serving. Each request is handled by
+/// the router current when it arrived.
+#[derive(Clone)]
+pub struct LiveRouter(Arc<ArcSwap<Router>>);
+
+impl LiveRouter {
+    pub fn new(router: Router) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(router)))
+    }
+
+    pub fn replace(&self, router: Router) {
+        self.0.store(Arc::new(router));
+    }
+}
+
+impl Service<Request> for LiveRouter {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = RouteFuture<Infallible>;
+
+    fn poll_ready(&mut self, _cx: &mut std::task::Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        Router::clone(&self.0.load()).call(request)
+    }
+}
+
+/// What a reload changed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+    /// Reloadable sections now in effect
+    pub applied: Vec<&'static str>,
+    /// Sections that changed but keep their running values until a restart
+    pub needs_restart: Vec<String>,
+    /// Models that can now be served
+    pub models_added: Vec<String>,
+    /// Models that can no longer be served
+    pub models_removed: Vec<String>,
+    /// Hashes of the API keys added
+    pub keys_added: Vec<String>,
+    /// Hashes of the API keys revoked
+    pub keys_removed: Vec<String>,
+    /// Providers whose UAA credentials changed
+    pub credentials_rotated: Vec<String>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty()
+            && self.needs_restart.is_empty()
+            && self.models_added.is_empty()
+            && self.models_removed.is_empty()
+    }
+
+    /// Record the difference between the models served before and after.
+    fn diff_models(&mut self, before: Vec<String>, after: Vec<String>) {
+        let (added, removed) = diff(before, after);
+        self.models_added = added;
+        self.models_removed = removed;
+    }
+}
+
+/// Entries of `after` missing from `before`, and of `before` missing from
+/// `after`, each sorted.
+fn diff(
+    before: impl IntoIterator<Item = String>,
+    after: impl IntoIterator<Item = String>,
+) -> (Vec<String>, Vec<String>) {
+    let before: HashSet<String> = before.into_iter().collect();
+    let after: HashSet<String> = after.into_iter().collect();
+    let mut added: Vec<String> = after.difference(&before).cloned().collect();
+    let mut removed: Vec<String> = before.difference(&after).cloned().collect();
+    added.sort();
+    removed.sort();
+    (added, removed)
+}
+
+/// The top-level sections that differ between `running` and `loaded`.
+pub fn changes(running_config: &Config, loaded_config: &Config) -> Result<Changes> {
+    let running = serde_json::to_value(running_config)?;
+    let loaded = serde_json::to_value(loaded_config)?;
+    let (Some(running), Some(loaded)) = (running.as_object(), loaded.as_object()) else {
+        anyhow::bail!("config doesn't serialize to a map");
+    };
+    let mut changes = Changes::default();
+    let sections: HashSet<&String> = running.keys().chain(loaded.keys()).collect();
+    for section in sections {
+        if running.get(section) == loaded.get(section) {
+            continue;
+        }
+        match RELOADABLE.iter().find(|s| *s == section) {
+            Some(reloadable) => changes.applied.push(reloadable),
+            None => changes.needs_restart.push(section.clone()),
+        }
+    }
+    // The quota counters and where they are kept are set up at startup;
+    // only the limits follow a reload.
+    let (running_quotas, loaded_quotas) = (&running_config.quotas, &loaded_config.quotas);
+    for (field, differs) in [
+        (
+            "quotas.enabled",
+            running_quotas.enabled != loaded_quotas.enabled,
+        ),
+        (
+            "quotas.state_file",
+            running_quotas.state_file != loaded_quotas.state_file,
+        ),
+    ] {
+        if differs {
+            changes.needs_restart.push(field.to_string());
+        }
+    }
+    if running_quotas.daily_token_limit == loaded_quotas.daily_token_limit
+        && running_quotas.monthly_token_limit == loaded_quotas.monthly_token_limit
+        && running_quotas.requests_per_minute == loaded_quotas.requests_per_minute
+    {
+        changes.applied.retain(|s| *s != "quotas");
+    }
+    changes
+        .applied
+        .sort_by_key(|s| RELOADABLE.iter().position(|r| r == s));
+    changes.needs_restart.sort();
+
+    (changes.keys_added, changes.keys_removed) = diff(
+        running_config.api_keys.iter().map(|k| k.key_hash()),
+        loaded_config.api_keys.iter().map(|k| k.key_hash()),
+    );
+    changes.credentials_rotated = loaded_config
+        .providers
+        .iter()
+        .filter(|new| {
+            running_config.providers.iter().any(|old| {
+                old.name == new.name
+                    && (old.uaa_token_url != new.uaa_token_url
+                        || old.uaa_client_id != new.uaa_client_id
+                        || old.uaa_client_secret != new.uaa_client_secret)
+            })
+        })
+        .map(|p| p.name.clone())
+        .collect();
+    Ok(changes)
+}
+
+/// The state being served and the background task refreshing its models.
+struct Running {
+    state: AppState,
+    registry_task: JoinHandle<()>,
+}
+
+/// Re-reads the config file and swaps in what changed.
+pub struct ConfigReloader {
+    path: String,
+    /// Command-line flags that override the file, applied to every reload
+    overrides: Box<dyn Fn(&mut Config) + Send + Sync>,
+    app: LiveRouter,
+    admin: Option<LiveRouter>,
+    /// Redis shared with other routers, for a request limiter a reload
+    /// turns on
+    #[cfg(feature = "cluster")]
+    cluster: Option<crate::cluster::Cluster>,
+    /// Held for the whole reload, so reloads don't interleave.
+    running: tokio::sync::Mutex<Running>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        path: String,
+        overrides: impl Fn(&mut Config) + Send + Sync + 'static,
+        state: AppState,
+        registry_task: JoinHandle<()>,
+        app: LiveRouter,
+        admin: Option<LiveRouter>,
+    ) -> Self {
+        Self {
+            path,
+            overrides: Box::new(overrides),
+            app,
+            admin,
+            #[cfg(feature = "cluster")]
+            cluster: None,
+            running: tokio::sync::Mutex::new(Running {
+                state,
+                registry_task,
+            }),
+        }
+    }
+
+    /// Count the requests of a limiter turned on by a reload in windows
+    /// shared through `cluster`.
+    #[cfg(feature = "cluster")]
+    pub fn with_cluster(mut self, cluster: crate::cluster::Cluster) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Read the config file again and apply the reloadable sections that
+    /// changed.
+    pub async fn reload(&self) -> Result<Changes> {
+        self.apply(false).await
+    }
+
+    /// Like [`reload`](Self::reload), and also resolve the models against
+    /// AI Core now even if the config didn't change.
+    pub async fn reload_and_refresh(&self) -> Result<Changes> {
+        self.apply(true).await
+    }
+
+    async fn apply(&self, refresh: bool) -> Result<Changes> {
+        let mut loaded = Config::load(Some(self.path.as_str()))?;
+        (self.overrides)(&mut loaded);
+
+        let mut running = self.running.lock().await;
+        let state = &running.state;
+        let mut changes = changes(&state.config, &loaded)?;
+        let served = state.model_registry.get_available_models().await;
+        if changes.applied.is_empty() {
+            if refresh {
+                state.model_registry.refresh().await?;
+                let now_served = state.model_registry.get_available_models().await;
+                changes.diff_models(served, now_served);
+            }
+            return Ok(changes);
+        }
+        let changed = |section: &str| changes.applied.contains(&section);
+
+        let mut config = state.config.clone();
+        config.providers = loaded.providers;
+        config.api_keys = loaded.api_keys;
+        config.models = loaded.models;
+        config.fallback_models = loaded.fallback_models;
+        config.load_balancing = loaded.load_balancing;
+        config.log_level = loaded.log_level;
+        config.quotas = crate::config::QuotaConfig {
+            enabled: config.quotas.enabled,
+            state_file: config.quotas.state_file.take(),
+            ..loaded.quotas
+        };
+        config.streams = loaded.streams;
+
+        let log_level = crate::log_level::parse_level(&config.log_level)
+            .with_context(|| format!("Invalid log_level '{}'", config.log_level))?;
+        let load_balancer = if changed("providers") || changed("load_balancing") {
+            LoadBalancer::new(config.providers.clone(), config.load_balancing.clone())?
+        } else {
+            state.load_balancer.clone()
+        };
+        let token_manager = if changed("api_keys") || changed("providers") {
+            state
+                .token_manager
+                .reconfigure(&config.api_keys, &config.providers)
+                .await
+        } else {
+            state.token_manager.clone()
+        };
+        let (model_registry, registry_task) =
+            if changed("models") || changed("fallback_models") || changed("providers") {
+                let registry = ModelRegistry::new(
+                    config.models.clone(),
+                    config.fallback_models.clone(),
+                    config.providers.clone(),
+                    token_manager.clone(),
+                    config.refresh_interval_secs,
+                )
+                .with_auto_discover(config.auto_discover.clone());
+                let task = registry
+                    .start()
+                    .await
+                    .context("Failed to resolve the reloaded models")?;
+                (registry, Some(task))
+            } else {
+                if refresh {
+                    state.model_registry.refresh().await?;
+                }
+                (state.model_registry.clone(), None)
+            };
+
+        let (mut quota_manager, mut request_limiter) = (None, None);
+        if changed("api_keys") || changed("quotas") {
+            if let Some(ref qm) = state.quota_manager {
+                qm.reconfigure(&config.api_keys, &config.quotas);
+            }
+            request_limiter = Some(self.request_limiter(state, &config));
+            quota_manager = Some(state.quota_manager.clone());
+        }
+        let (mut stream_limiter, mut output_pacer) = (None, None);
+        if changed("api_keys") || changed("streams") {
+            stream_limiter = Some(match state.stream_limiter {
+                Some(ref limiter) => limiter.reconfigure(&config.api_keys, &config.streams),
+                None => StreamLimiter::from_config(&config.api_keys, &config.streams),
+            });
+            output_pacer = Some(match state.output_pacer {
+                Some(ref pacer) => pacer.reconfigure(&config.api_keys, &config.streams),
+                None => OutputPacer::from_config(&config.api_keys, &config.streams),
+            });
+        }
+
+        let tenant_quotas = if changed("providers") {
+            match state.tenant_quotas {
+                Some(ref quotas) => quotas.reconfigure(&config.providers),
+                None => TenantQuotas::from_config(&config.providers),
+            }
+        } else {
+            state.tenant_quotas.clone()
+        };
+
+        if changed("models") {
+            state.disabled_models.reconfigure(&config.models);
+        }
+        if let Some(ref control) = state.log_level {
+            control.set_configured(log_level)?;
+        }
+
+        let state = AppState {
+            config,
+            model_registry,
+            token_manager,
+            load_balancer,
+            quota_manager: quota_manager.unwrap_or_else(|| state.quota_manager.clone()),
+            request_limiter: request_limiter.unwrap_or_else(|| state.request_limiter.clone()),
+            stream_limiter: stream_limiter.unwrap_or_else(|| state.stream_limiter.clone()),
+            output_pacer: output_pacer.unwrap_or_else(|| state.output_pacer.clone()),
+            tenant_quotas,
+            ..state.clone()
+        };
+        self.app
+            .replace(crate::routes::create_router(state.clone()));
+        if let Some(ref admin) = self.admin {
+            admin.replace(crate::routes::create_admin_router(state.clone()));
+        }
+        running.state = state;
+        if let Some(task) = registry_task {
+            std::mem::replace(&mut running.registry_task, task).abort();
+        }
+        let now_served = running.state.model_registry.get_available_models().await;
+        changes.diff_models(served, now_served);
+        Ok(changes)
+    }
+
+    /// The request limiter for the reloaded `config`, keeping the windows of
+    /// the running one.
+    fn request_limiter(&self, state: &AppState, config: &Config) -> Option<Arc<RequestLimiter>> {
+        let limiter = match state.request_limiter {
+            Some(ref limiter) => limiter.reconfigure(&config.api_keys, &config.quotas),
+            None => {
+                let limiter = RequestLimiter::from_config(&config.api_keys, &config.quotas);
+                #[cfg(feature = "cluster")]
+                let limiter = match self.cluster {
+                    Some(ref cluster) => limiter.map(|rl| rl.with_cluster(cluster.clone())),
+                    None => limiter,
+                };
+                limiter
+            }
+        };
+        limiter.map(Arc::new)
+    }
+
+    /// Reload and log the outcome; a failed reload keeps the running config.
+    /// With `refresh`, the models are resolved again too.
+    pub async fn reload_logged(&self, refresh: bool) {
+        match self.apply(refresh).await {
+            Ok(changes) if changes.is_empty() => {
+                if refresh {
+                    tracing::info!("Config and models unchanged");
+                } else {
+                    tracing::debug!("Config file unchanged");
+                }
+            }
+            Ok(changes) => {
+                if !changes.applied.is_empty() {
+                    tracing::info!("Config reloaded: {}", changes.applied.join(", "));
+                }
+                for (what, list) in [
+                    ("Models added", &changes.models_added),
+                    ("Models removed", &changes.models_removed),
+                    ("API keys added", &changes.keys_added),
+                    ("API keys revoked", &changes.keys_removed),
+                    (
+                        "New credentials for providers",
+                        &changes.credentials_rotated,
+                    ),
+                ] {
+                    if !list.is_empty() {
+                        tracing::info!("{}: {}", what, list.join(", "));
+                    }
+                }
+                if !changes.needs_restart.is_empty() {
+                    tracing::warn!(
+                        "Config changes to {} take effect after a restart",
+                        changes.needs_restart.join(", ")
+                    );
+                }
+            }
+            Err(e) => tracing::warn!("Config reload failed, keeping the running config: {:#}", e),
+        }
+    }
+
+    /// Reload, with the models resolved again, whenever the process gets
+    /// SIGHUP.
+    #[cfg(unix)]
+    pub fn reload_on_hangup(self: Arc<Self>) -> Result<()> {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut hangups = signal(SignalKind::hangup()).context("Failed to listen for SIGHUP")?;
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                tracing::info!("Received SIGHUP, reloading the config");
+                self.reload_logged(true).await;
+            }
+        });
+        Ok(())
+    }
+
+    /// Reload whenever the config file or the keys file changes.
+    pub async fn watch(self: Arc<Self>) -> Result<()> {
+        let keys_file = self.running.lock().await.state.config.keys_file.clone();
+        let files: Vec<PathBuf> = std::iter::once(self.path.clone())
+            .chain(keys_file)
+            .map(PathBuf::from)
+            .collect();
+        let names: HashSet<OsString> = files
+            .iter()
+            .filter_map(|f| f.file_name().map(|n| n.to_os_string()))
+            .collect();
+        let dirs: HashSet<&Path> = files
+            .iter()
+            .map(|f| match f.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            })
+            .collect();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else { return };
+                // Kubernetes swaps mounted ConfigMaps through `..data` links.
+                let relevant = event
+                    .paths
+                    .iter()
+                    .filter_map(|p| p.file_name())
+                    .any(|name| names.contains(name) || name.to_string_lossy().starts_with(".."));
+                if relevant {
+                    let _ = tx.try_send(());
+                }
+            })
+            .context("Failed to watch the config file")?;
+        // Watching the directories sees files replaced by a rename, the way
+        // most editors save.
+        for dir in dirs.into_iter().filter(|dir| dir.is_dir()) {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .with_context(|| format!("Failed to watch {}", dir.display()))?;
+        }
+
+        let read = move || {
+            files
+                .iter()
+                .map(|f| std::fs::read(f).ok())
+                .collect::<Vec<_>>()
+        };
+        tokio::spawn(async move {
+            let _watcher = watcher;
+            let mut seen = read();
+            while rx.recv().await.is_some() {
+                // Saves can take several writes; let them settle.
+                tokio::time::sleep(Duration::from_millis(DEBOUNCE_MS)).await;
+                while rx.try_recv().is_ok() {}
+                let contents = read();
+                if contents == seen {
+                    continue;
+                }
+                seen = contents;
+                self.reload_logged(false).await;
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upstream_limits::Pressure;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    fn config(dir: &tempfile::TempDir, yaml: &str) -> Config {
+        let path = dir.path().join("config.yaml");
+        std::fs::write(&path, yaml).unwrap();
+        Config::load(path.to_str()).unwrap()
+    }
+
+    const BASE: &str = "providers:
+  - name: default
+    uaa_token_url: https://uaa.example.com/oauth/token
+    uaa_client_id: client
+    uaa_client_secret: secret
+    genai_api_url: https://api.example.com
+    resource_group: default
+api_keys:
+  - key: key-1
+";
+
+    #[test]
+    fn changes_sort_sections_into_applied_and_needs_restart() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let running = config(&dir, BASE);
+        assert!(changes(&running, &config(&dir, BASE)).unwrap().is_empty());
+
+        let edited = BASE.replace("key-1", "key-2")
+            + "log_level: debug\nbind: 127.0.0.1:9000\nmax_request_body_mb: 20\n";
+        assert_eq!(
+            changes(&running, &config(&dir, &edited)).unwrap(),
+            Changes {
+                applied: vec!["api_keys", "log_level"],
+                needs_restart: vec!["bind".to_string(), "max_request_body_mb".to_string()],
+                keys_added: vec![crate::quota::hash_api_key("key-2")],
+                keys_removed: vec![crate::quota::hash_api_key("key-1")],
+                ..Changes::default()
+            }
+        );
+
+        let rotated = BASE.replace("uaa_client_secret: secret", "uaa_client_secret: secret-2");
+        let changes = changes(&running, &config(&dir, &rotated)).unwrap();
+        assert_eq!(changes.applied, ["providers"]);
+        assert_eq!(changes.credentials_rotated, ["default"]);
+        assert!(changes.keys_added.is_empty() && changes.keys_removed.is_empty());
+
+        let quotas = BASE.to_string() + "quotas:\n  enabled: true\n  requests_per_minute: 10\n";
+        let changes = super::changes(&running, &config(&dir, &quotas)).unwrap();
+        assert_eq!(changes.applied, ["quotas"]);
+        assert_eq!(changes.needs_restart, ["quotas.enabled"]);
+    }
+
+    #[tokio::test]
+    async fn reload_enforces_the_limits_of_added_keys() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::fs::write(&path, BASE).unwrap();
+        let state = crate::routes::state_for_tests(Config::load(path.to_str()).unwrap());
+        let app = LiveRouter::new(crate::routes::create_router(state.clone()));
+        let reloader = ConfigReloader::new(
+            path.to_str().unwrap().to_string(),
+            |_| {},
+            state,
+            tokio::spawn(async {}),
+            app.clone(),
+            None,
+        );
+        let app = Router::new().fallback_service(app);
+        let status = |key: &'static str| {
+            let app = app.clone();
+            async move {
+                let mut request = Request::post("/v1/chat/completions")
+                    .header("authorization", format!("Bearer {key}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"model":"gpt-4o","messages":[]}"#))
+                    .unwrap();
+                request.extensions_mut().insert(axum::extract::ConnectInfo(
+                    std::net::SocketAddr::from(([127, 0, 0, 1], 4242)),
+                ));
+                app.oneshot(request).await.unwrap().status()
+            }
+        };
+        assert_eq!(status("key-2").await, StatusCode::UNAUTHORIZED);
+
+        std::fs::write(
+            &path,
+            format!("{BASE}  - key: key-2\n    requests_per_minute: 1\n"),
+        )
+        .unwrap();
+        assert_eq!(reloader.reload().await.unwrap().applied, ["api_keys"]);
+        assert_ne!(status("key-2").await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(status("key-2").await, StatusCode::TOO_MANY_REQUESTS);
+        assert_ne!(status("key-1").await, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn reload_applies_provider_quotas() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::fs::write(&path, BASE).unwrap();
+        let state = crate::routes::state_for_tests(Config::load(path.to_str()).unwrap());
+        let reloader = ConfigReloader::new(
+            path.to_str().unwrap().to_string(),
+            |_| {},
+            state,
+            tokio::spawn(async {}),
+            LiveRouter::new(Router::new()),
+            None,
+        );
+        let quotas = || async { reloader.running.lock().await.state.tenant_quotas.clone() };
+        let with_quota = |limit: u64| {
+            BASE.replace(
+                "    resource_group: default\n",
+                &format!(
+                    "    resource_group: default\n    quota: {{requests_per_minute: {limit}}}\n"
+                ),
+            )
+        };
+
+        std::fs::write(&path, with_quota(2)).unwrap();
+        assert_eq!(reloader.reload().await.unwrap().applied, ["providers"]);
+        let added = quotas().await.expect("the quota applies");
+        added.charge("default", 100);
+        assert_eq!(added.pressure("default"), Pressure::Clear);
+
+        // A lower limit counts what was already sent.
+        std::fs::write(&path, with_quota(1)).unwrap();
+        reloader.reload().await.unwrap();
+        let lowered = quotas().await.unwrap();
+        assert!(matches!(
+            lowered.pressure("default"),
+            Pressure::CoolingDown(_)
+        ));
+
+        std::fs::write(&path, BASE).unwrap();
+        reloader.reload().await.unwrap();
+        assert!(quotas().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn live_router_serves_the_replacement_to_later_requests() {
+        let live = LiveRouter::new(Router::new().route("/", get(|| async { "old" })));
+        let app = Router::new().fallback_service(live.clone());
+        let body = |app: Router| async move {
+            let response = app
+                .oneshot(Request::get("/").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap()
+        };
+
+        assert_eq!(body(app.clone()).await, "old");
+        live.replace(Router::new().route("/", get(|| async { "new" })));
+        assert_eq!(body(app).await, "new");
+    }
+}
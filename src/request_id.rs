@@ -0,0 +1,40 @@
+//! Request IDs.
+//!
+//! Every request gets an ID, returned in `x-acr-request-id` whatever the
+//! outcome and attached to every log line written while handling it (as the
+//! `request` span), so a client's log entry can be matched with acr's. The
+//! ID is always generated here; a client-supplied header is ignored.
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::HeaderValue,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::net::SocketAddr;
+use tracing::Instrument;
+
+use crate::constants::api::{ACR_REQUEST_ID_HEADER, REQUEST_ID_PREFIX};
+
+pub fn generate() -> String {
+    format!("{REQUEST_ID_PREFIX}{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Middleware assigning the ID.
+pub async fn assign(request: Request, next: Next) -> Response {
+    let id = generate();
+    // The client address, after `trusted_proxies` are resolved.
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let span = match client {
+        Some(client) => tracing::info_span!("request", id = %id, client = %client),
+        None => tracing::info_span!("request", id = %id),
+    };
+    let mut response = next.run(request).instrument(span).await.into_response();
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(ACR_REQUEST_ID_HEADER, value);
+    }
+    response
+}
@@ -7,35 +7,99 @@
 //! * `quota.rs` tracks cumulative *budgets* against calendar windows
 //!   (midnight / month-end UTC), backed by the requests DB on startup.
 //! * This module tracks instantaneous *rate* via `governor`'s GCRA token
-//!   bucket, in memory only.
+//!   bucket, in memory, or in a window shared through Redis when several
+//!   routers serve the same keys (see `cluster.rs`).
 //!
 //! Keying is the same SHA-256 hash used by `quota::hash_api_key`, so a single
 //! request lookup costs at most one DashMap probe per check.
+//!
+//! The key's window is reported to clients through OpenAI's
+//! `x-ratelimit-{limit,remaining,reset}-requests` response headers, so SDK
+//! backoff logic sees the limit before it hits it.
 
 use std::collections::HashMap;
 use std::num::NonZeroU32;
 use std::sync::Arc;
+use std::time::Duration;
 
+use axum::http::{HeaderMap, HeaderValue};
 use governor::{
     Quota, RateLimiter,
     clock::{Clock, DefaultClock},
-    middleware::NoOpMiddleware,
+    middleware::StateInformationMiddleware,
     state::keyed::DefaultKeyedStateStore,
 };
 
 use crate::config::{ApiKeyConfig, QuotaConfig};
+use crate::constants::api::{
+    RATELIMIT_LIMIT_REQUESTS_HEADER, RATELIMIT_REMAINING_REQUESTS_HEADER,
+    RATELIMIT_RESET_REQUESTS_HEADER,
+};
 
-type Limiter = RateLimiter<
-    String,
-    DefaultKeyedStateStore<String>,
-    DefaultClock,
-    NoOpMiddleware<<DefaultClock as governor::clock::Clock>::Instant>,
->;
+type Limiter =
+    RateLimiter<String, DefaultKeyedStateStore<String>, DefaultClock, StateInformationMiddleware>;
 
 /// Result of a request-rate check.
 pub enum RequestLimitResult {
-    Allowed,
-    Exceeded { retry_after_secs: u64 },
+    /// `rate` is `None` for keys without a limit.
+    Allowed {
+        rate: Option<RequestRate>,
+    },
+    Exceeded {
+        retry_after_secs: u64,
+        limit: u32,
+    },
+}
+
+/// A key's requests-per-minute window as of a check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestRate {
+    pub limit: u32,
+    /// Requests that would be let through right now
+    pub remaining: u32,
+    /// Until the window is back to `limit`
+    pub reset: Duration,
+}
+
+impl RequestRate {
+    /// Add `x-ratelimit-{limit,remaining,reset}-requests`.
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(
+            RATELIMIT_LIMIT_REQUESTS_HEADER,
+            HeaderValue::from(self.limit),
+        );
+        headers.insert(
+            RATELIMIT_REMAINING_REQUESTS_HEADER,
+            HeaderValue::from(self.remaining),
+        );
+        if let Ok(reset) = HeaderValue::from_str(&reset_duration(self.reset)) {
+            headers.insert(RATELIMIT_RESET_REQUESTS_HEADER, reset);
+        }
+    }
+}
+
+/// A duration the way OpenAI's `x-ratelimit-reset-*` headers write it:
+/// `250ms`, `1.5s`, `6m0s`, `3h20m5s`.
+pub fn reset_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if millis < 1000 {
+        return format!("{millis}ms");
+    }
+    let (hours, rest) = (millis / 3_600_000, millis % 3_600_000);
+    let (minutes, rest) = (rest / 60_000, rest % 60_000);
+    let seconds = match rest % 1000 {
+        0 => format!("{}s", rest / 1000),
+        frac => format!(
+            "{}.{}s",
+            rest / 1000,
+            format!("{frac:03}").trim_end_matches('0')
+        ),
+    };
+    match (hours, minutes) {
+        (0, 0) => seconds,
+        (0, m) => format!("{m}m{seconds}"),
+        (h, m) => format!("{h}h{m}m{seconds}"),
+    }
 }
 
 /// Per-API-key requests-per-minute limiter.
@@ -51,6 +115,9 @@ pub struct RequestLimiter {
     key_rpm: HashMap<String, Option<NonZeroU32>>,
     /// Default rpm applied to keys without a per-key override (None = unlimited).
     default_rpm: Option<NonZeroU32>,
+    /// Windows shared with the other routers, checked before the local ones.
+    #[cfg(feature = "cluster")]
+    cluster: Option<crate::cluster::Cluster>,
 }
 
 impl RequestLimiter {
@@ -68,7 +135,7 @@ impl RequestLimiter {
                     Some(n) => NonZeroU32::new(n),
                     None => default_rpm,
                 };
-                (crate::quota::hash_api_key(&k.key), resolved)
+                (k.key_hash(), resolved)
             })
             .collect();
 
@@ -81,30 +148,77 @@ impl RequestLimiter {
         let mut by_rpm: HashMap<NonZeroU32, Arc<Limiter>> = HashMap::new();
         let distinct_rpms = key_rpm.values().filter_map(|v| *v).chain(default_rpm);
         for rpm in distinct_rpms {
-            by_rpm
-                .entry(rpm)
-                .or_insert_with(|| Arc::new(RateLimiter::keyed(Quota::per_minute(rpm))));
+            by_rpm.entry(rpm).or_insert_with(|| {
+                Arc::new(
+                    RateLimiter::keyed(Quota::per_minute(rpm))
+                        .with_middleware::<StateInformationMiddleware>(),
+                )
+            });
         }
 
         Some(Self {
             by_rpm,
             key_rpm,
             default_rpm,
+            #[cfg(feature = "cluster")]
+            cluster: None,
         })
     }
 
-    /// Check whether a request from this key is allowed right now.
-    /// On `Exceeded`, returns the wall-clock seconds until the next request would
-    /// succeed (rounded up, minimum 1) for use as `Retry-After`.
-    pub fn check(&self, key_hash: &str) -> RequestLimitResult {
-        let rpm = self
-            .key_rpm
+    /// Count requests in windows shared through `cluster`.
+    #[cfg(feature = "cluster")]
+    pub fn with_cluster(mut self, cluster: crate::cluster::Cluster) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// The same limiter for a reloaded config, or `None` if it no longer
+    /// limits any key. Windows of a rate that is still in use carry over,
+    /// so a key keeps the requests it already made.
+    pub fn reconfigure(&self, api_keys: &[ApiKeyConfig], quotas: &QuotaConfig) -> Option<Self> {
+        let mut limiter = Self::from_config(api_keys, quotas)?;
+        for (rpm, window) in limiter.by_rpm.iter_mut() {
+            if let Some(running) = self.by_rpm.get(rpm) {
+                *window = running.clone();
+            }
+        }
+        #[cfg(feature = "cluster")]
+        {
+            limiter.cluster = self.cluster.clone();
+        }
+        Some(limiter)
+    }
+
+    fn rpm(&self, key_hash: &str) -> Option<NonZeroU32> {
+        self.key_rpm
             .get(key_hash)
             .copied()
-            .unwrap_or(self.default_rpm);
+            .unwrap_or(self.default_rpm)
+    }
+
+    /// Like [`check`](Self::check), but against the window shared by the
+    /// cluster when there is one. Falls back to this router's own window
+    /// when Redis can't be reached.
+    pub async fn check_shared(&self, key_hash: &str) -> RequestLimitResult {
+        #[cfg(feature = "cluster")]
+        if let Some(ref cluster) = self.cluster
+            && let Some(rpm) = self.rpm(key_hash)
+        {
+            match cluster.take_request(key_hash, rpm).await {
+                Ok(result) => return result,
+                Err(e) => cluster.report_error(&e),
+            }
+        }
+        self.check(key_hash)
+    }
 
-        let Some(rpm) = rpm else {
-            return RequestLimitResult::Allowed;
+    /// Check whether a request from this key is allowed right now.
+    /// On `Exceeded`, returns the wall-clock seconds until the next request would
+    /// succeed (rounded up, minimum 1) for use as `Retry-After`, and the
+    /// key's limit.
+    pub fn check(&self, key_hash: &str) -> RequestLimitResult {
+        let Some(rpm) = self.rpm(key_hash) else {
+            return RequestLimitResult::Allowed { rate: None };
         };
 
         // The limiter for `rpm` is guaranteed present because `from_config`
@@ -115,12 +229,24 @@ impl RequestLimiter {
             .expect("limiter for configured rpm must exist");
 
         match limiter.check_key(&key_hash.to_string()) {
-            Ok(()) => RequestLimitResult::Allowed,
+            Ok(snapshot) => {
+                let remaining = snapshot.remaining_burst_capacity();
+                // Each used request comes back after a minute / rpm.
+                let reset = Duration::from_secs(60) * (rpm.get() - remaining) / rpm.get();
+                RequestLimitResult::Allowed {
+                    rate: Some(RequestRate {
+                        limit: rpm.get(),
+                        remaining,
+                        reset,
+                    }),
+                }
+            }
             Err(not_until) => {
                 let wait = not_until.wait_time_from(DefaultClock::default().now());
                 let secs = wait.as_secs().max(1);
                 RequestLimitResult::Exceeded {
                     retry_after_secs: secs,
+                    limit: rpm.get(),
                 }
             }
         }
@@ -145,6 +271,16 @@ mod tests {
             daily_token_limit: None,
             monthly_token_limit: None,
             requests_per_minute: rpm,
+            max_open_streams: None,
+            output_tokens_per_sec: None,
+            allowed_models: None,
+            model_aliases: Default::default(),
+            priority: Default::default(),
+            reasoning: Default::default(),
+            tenant: None,
+            client: None,
+            valid_from: None,
+            valid_until: None,
         }
     }
 
@@ -154,6 +290,7 @@ mod tests {
             daily_token_limit: None,
             monthly_token_limit: None,
             requests_per_minute: rpm,
+            state_file: None,
             unknown: Default::default(),
         }
     }
@@ -171,19 +308,55 @@ mod tests {
         let h = crate::quota::hash_api_key("burst");
 
         // Two should pass (burst capacity = rpm at minute granularity).
-        assert!(matches!(limiter.check(&h), RequestLimitResult::Allowed));
-        assert!(matches!(limiter.check(&h), RequestLimitResult::Allowed));
+        assert!(matches!(
+            limiter.check(&h),
+            RequestLimitResult::Allowed { .. }
+        ));
+        assert!(matches!(
+            limiter.check(&h),
+            RequestLimitResult::Allowed { .. }
+        ));
 
         // Third should be rejected with a positive Retry-After.
         match limiter.check(&h) {
-            RequestLimitResult::Exceeded { retry_after_secs } => {
+            RequestLimitResult::Exceeded {
+                retry_after_secs,
+                limit,
+            } => {
                 assert!(retry_after_secs >= 1);
                 assert!(retry_after_secs <= 60);
+                assert_eq!(limit, 2);
             }
-            RequestLimitResult::Allowed => panic!("expected rate-limit"),
+            RequestLimitResult::Allowed { .. } => panic!("expected rate-limit"),
         }
     }
 
+    #[test]
+    fn allowed_requests_report_the_window() {
+        let keys = vec![key_cfg("sdk", Some(4))];
+        let limiter = RequestLimiter::from_config(&keys, &quotas(None)).unwrap();
+        let h = crate::quota::hash_api_key("sdk");
+
+        let RequestLimitResult::Allowed { rate: Some(rate) } = limiter.check(&h) else {
+            panic!("expected an allowed request with a window");
+        };
+        assert_eq!((rate.limit, rate.remaining), (4, 3));
+        assert!(rate.reset <= Duration::from_secs(15), "{:?}", rate.reset);
+
+        let mut headers = HeaderMap::new();
+        rate.insert_headers(&mut headers);
+        assert_eq!(headers[RATELIMIT_LIMIT_REQUESTS_HEADER], "4");
+        assert_eq!(headers[RATELIMIT_REMAINING_REQUESTS_HEADER], "3");
+    }
+
+    #[test]
+    fn reset_durations_use_openai_notation() {
+        assert_eq!(reset_duration(Duration::from_millis(250)), "250ms");
+        assert_eq!(reset_duration(Duration::from_millis(1500)), "1.5s");
+        assert_eq!(reset_duration(Duration::from_secs(360)), "6m0s");
+        assert_eq!(reset_duration(Duration::from_secs(12_005)), "3h20m5s");
+    }
+
     #[test]
     fn per_key_zero_means_unlimited_even_when_global_set() {
         let keys = vec![key_cfg("admin", Some(0))];
@@ -192,7 +365,10 @@ mod tests {
 
         // Many requests in a row succeed — admin is opted out of the global limit.
         for _ in 0..20 {
-            assert!(matches!(limiter.check(&h), RequestLimitResult::Allowed));
+            assert!(matches!(
+                limiter.check(&h),
+                RequestLimitResult::Allowed { .. }
+            ));
         }
     }
 
@@ -203,7 +379,10 @@ mod tests {
 
         // An unknown key still gets the global default.
         let h = crate::quota::hash_api_key("never-configured");
-        assert!(matches!(limiter.check(&h), RequestLimitResult::Allowed));
+        assert!(matches!(
+            limiter.check(&h),
+            RequestLimitResult::Allowed { .. }
+        ));
         assert!(matches!(
             limiter.check(&h),
             RequestLimitResult::Exceeded { .. }
@@ -220,9 +399,15 @@ mod tests {
 
         // VIP has a private rpm=5 limiter, plebe shares the global rpm=1.
         for _ in 0..5 {
-            assert!(matches!(limiter.check(&vip), RequestLimitResult::Allowed));
+            assert!(matches!(
+                limiter.check(&vip),
+                RequestLimitResult::Allowed { .. }
+            ));
         }
-        assert!(matches!(limiter.check(&plebe), RequestLimitResult::Allowed));
+        assert!(matches!(
+            limiter.check(&plebe),
+            RequestLimitResult::Allowed { .. }
+        ));
         assert!(matches!(
             limiter.check(&plebe),
             RequestLimitResult::Exceeded { .. }
@@ -0,0 +1,571 @@
+//! Response cache for non-streaming requests.
+//!
+//! With `response_cache.enabled`, a successful non-streaming response is kept
+//! for `ttl_secs`, and a later request from the same API key with the same
+//! normalized body — same route, model, parameters and `anthropic-beta`
+//! features, object keys in any order, `stream: false` or absent — is
+//! answered from the cache without going upstream, so it spends no tokens.
+//! Responses carry `x-acr-cache: hit` or `miss`.
+//!
+//! Entries live in memory by default, or as one JSON file each under
+//! `response_cache.dir` with `backend: disk`, which survives restarts.
+//! `response_cache.models` limits caching to matching models and can give
+//! them their own TTL and size limit.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::body::Body;
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::Response;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::config::{ResponseCacheBackend, ResponseCacheConfig};
+use crate::constants::api::{
+    ACR_CACHE_HEADER, ACR_CONTINUATIONS_HEADER, ACR_DROPPED_PARAMS_HEADER,
+};
+use crate::constants::response_cache::IGNORED_FIELDS;
+use crate::registry::glob_matches;
+
+/// Response headers kept with a cached body. Cost and timings describe the
+/// original upstream call, so they aren't replayed.
+const KEPT_HEADERS: &[&str] = &[
+    "content-type",
+    ACR_CONTINUATIONS_HEADER,
+    ACR_DROPPED_PARAMS_HEADER,
+];
+
+/// How a model's responses are cached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachePolicy {
+    pub ttl: Duration,
+    pub max_bytes: usize,
+}
+
+/// What identifies a request in the cache, besides its body.
+#[derive(Debug)]
+pub struct RequestKey<'a> {
+    /// Hash of the sending API key; entries are never shared between keys.
+    pub api_key_hash: Option<&'a str>,
+    pub route: &'a str,
+    pub model: &'a str,
+    pub action: Option<&'a str>,
+    pub api_version: Option<&'a str>,
+    /// The client asked for `x-acr-metadata`, which changes the body.
+    pub metadata: bool,
+    /// Beta features from `anthropic-beta`, normalized and sorted: they
+    /// are forwarded upstream and change the answer.
+    pub anthropic_beta: &'a [String],
+}
+
+/// A cached response and the upstream that produced it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    pub model: String,
+    pub provider: String,
+    pub deployment_id: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl CachedResponse {
+    /// The response to send for a cache hit.
+    pub fn into_response(self) -> Result<Response> {
+        let mut response = Response::builder();
+        for (name, value) in &self.headers {
+            response = response.header(name, value);
+        }
+        let mut response = response.body(Body::from(self.body))?;
+        response
+            .headers_mut()
+            .insert(ACR_CACHE_HEADER, HeaderValue::from_static("hit"));
+        Ok(response)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Store {
+    Memory(Arc<Mutex<HashMap<String, CachedResponse>>>),
+    /// One `<key>.json` file per entry.
+    Disk(PathBuf),
+}
+
+/// Shared response cache; cheap to clone.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    config: Arc<ResponseCacheConfig>,
+    store: Store,
+}
+
+impl ResponseCache {
+    /// Open the cache, creating the disk backend's directory. `None` when
+    /// caching is disabled.
+    pub fn open(config: &ResponseCacheConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let store = match config.backend {
+            ResponseCacheBackend::Memory => Store::Memory(Arc::new(Mutex::new(HashMap::new()))),
+            ResponseCacheBackend::Disk => {
+                std::fs::create_dir_all(&config.dir).with_context(|| {
+                    format!("Failed to create response cache directory {}", config.dir)
+                })?;
+                Store::Disk(PathBuf::from(&config.dir))
+            }
+        };
+        Ok(Some(Self {
+            config: Arc::new(config.clone()),
+            store,
+        }))
+    }
+
+    /// How responses for the first of `models` a `response_cache.models`
+    /// pattern matches are cached; `None` when the model isn't cached.
+    pub fn policy(&self, models: &[&str]) -> Option<CachePolicy> {
+        let config = &self.config;
+        let overrides = if config.models.is_empty() {
+            Default::default()
+        } else {
+            models.iter().find_map(|model| {
+                config
+                    .models
+                    .iter()
+                    .filter_map(|(pattern, o)| glob_matches(pattern, model).map(|spec| (spec, o)))
+                    .max_by_key(|(spec, _)| *spec)
+                    .map(|(_, o)| o.clone())
+            })?
+        };
+        let ttl_secs = overrides.ttl_secs.unwrap_or(config.ttl_secs);
+        (ttl_secs > 0).then(|| CachePolicy {
+            ttl: Duration::from_secs(ttl_secs),
+            max_bytes: overrides.max_bytes.unwrap_or(config.max_bytes),
+        })
+    }
+
+    /// The cached response for `key`, if there is one that hasn't expired.
+    pub async fn get(&self, key: &str) -> Option<CachedResponse> {
+        match &self.store {
+            Store::Memory(entries) => {
+                let mut entries = entries.lock().ok()?;
+                match entries.get(key) {
+                    Some(entry) if entry.expires_at > Utc::now() => Some(entry.clone()),
+                    Some(_) => {
+                        entries.remove(key);
+                        None
+                    }
+                    None => None,
+                }
+            }
+            Store::Disk(dir) => {
+                let path = entry_path(dir, key);
+                tokio::task::spawn_blocking(move || read_entry(&path))
+                    .await
+                    .ok()
+                    .flatten()
+            }
+        }
+    }
+
+    /// Keep a successful response under `key` and return it for sending,
+    /// marked as a miss. Bodies over the policy's `max_bytes` are passed on
+    /// without being cached.
+    pub async fn store(
+        &self,
+        key: String,
+        policy: CachePolicy,
+        upstream: (&str, &str, &str),
+        response: Response,
+    ) -> Result<Response> {
+        let (mut parts, body) = response.into_parts();
+        let bytes = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .context("Failed to read the response for the cache")?;
+        parts
+            .headers
+            .insert(ACR_CACHE_HEADER, HeaderValue::from_static("miss"));
+
+        if bytes.len() <= policy.max_bytes
+            && let Ok(body) = std::str::from_utf8(&bytes)
+        {
+            let (model, provider, deployment_id) = upstream;
+            let entry = CachedResponse {
+                headers: kept_headers(&parts.headers),
+                body: body.to_string(),
+                model: model.to_string(),
+                provider: provider.to_string(),
+                deployment_id: deployment_id.to_string(),
+                expires_at: chrono::Duration::from_std(policy.ttl)
+                    .ok()
+                    .and_then(|ttl| Utc::now().checked_add_signed(ttl))
+                    .unwrap_or(DateTime::<Utc>::MAX_UTC),
+            };
+            self.insert(key, entry).await;
+        }
+        Ok(Response::from_parts(parts, Body::from(bytes)))
+    }
+
+    async fn insert(&self, key: String, entry: CachedResponse) {
+        let max_entries = self.config.max_entries;
+        match &self.store {
+            Store::Memory(entries) => {
+                let Ok(mut entries) = entries.lock() else {
+                    return;
+                };
+                let now = Utc::now();
+                entries.retain(|_, entry| entry.expires_at > now);
+                while entries.len() >= max_entries {
+                    let Some(oldest) = entries
+                        .iter()
+                        .min_by_key(|(_, entry)| entry.expires_at)
+                        .map(|(key, _)| key.clone())
+                    else {
+                        break;
+                    };
+                    entries.remove(&oldest);
+                }
+                entries.insert(key, entry);
+            }
+            Store::Disk(dir) => {
+                let dir = dir.clone();
+                let written = tokio::task::spawn_blocking(move || {
+                    write_entry(&dir, &key, &entry)?;
+                    prune(&dir, max_entries)
+                })
+                .await;
+                if let Ok(Err(e)) = written {
+                    tracing::warn!("Failed to write response cache entry: {:#}", e);
+                }
+            }
+        }
+    }
+}
+
+/// The cache key of a request: a SHA-256 over its identity and its body,
+/// with object keys sorted and [`IGNORED_FIELDS`] left out.
+pub fn cache_key(request: &RequestKey<'_>, body: &Value) -> String {
+    let mut hasher = Sha256::new();
+    let anthropic_beta = request.anthropic_beta.join(",");
+    for part in [
+        request.api_key_hash.unwrap_or_default(),
+        request.route,
+        request.model,
+        request.action.unwrap_or_default(),
+        request.api_version.unwrap_or_default(),
+        if request.metadata { "metadata" } else { "" },
+        &anthropic_beta,
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    match body {
+        Value::Object(fields) => hash_object(
+            &mut hasher,
+            fields
+                .iter()
+                .filter(|(name, _)| !IGNORED_FIELDS.contains(&name.as_str())),
+        ),
+        other => hash_value(&mut hasher, other),
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// The request's `anthropic-beta` features as they go upstream, in a fixed
+/// order, for [`RequestKey::anthropic_beta`].
+pub fn normalized_anthropic_beta(headers: &HeaderMap) -> Vec<String> {
+    let mut features = crate::transforms::extract_anthropic_beta(headers);
+    features.sort();
+    features
+}
+
+/// Feed `value` to the hasher as JSON with sorted object keys.
+fn hash_value(hasher: &mut Sha256, value: &Value) {
+    match value {
+        Value::Object(fields) => hash_object(hasher, fields.iter()),
+        Value::Array(items) => {
+            hasher.update(b"[");
+            for item in items {
+                hash_value(hasher, item);
+                hasher.update(b",");
+            }
+            hasher.update(b"]");
+        }
+        scalar => hasher.update(scalar.to_string().as_bytes()),
+    }
+}
+
+fn hash_object<'a>(hasher: &mut Sha256, fields: impl Iterator<Item = (&'a String, &'a Value)>) {
+    let mut fields: Vec<_> = fields.collect();
+    fields.sort_by_key(|(name, _)| *name);
+    hasher.update(b"{");
+    for (name, value) in fields {
+        hasher.update(Value::String(name.clone()).to_string().as_bytes());
+        hasher.update(b":");
+        hash_value(hasher, value);
+        hasher.update(b",");
+    }
+    hasher.update(b"}");
+}
+
+fn kept_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    KEPT_HEADERS
+        .iter()
+        .filter_map(|name| {
+            let value = headers.get(*name)?.to_str().ok()?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn entry_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{key}.json"))
+}
+
+/// Read an entry, deleting it once it has expired.
+fn read_entry(path: &Path) -> Option<CachedResponse> {
+    let bytes = std::fs::read(path).ok()?;
+    let entry: CachedResponse = serde_json::from_slice(&bytes).ok()?;
+    if entry.expires_at <= Utc::now() {
+        let _ = std::fs::remove_file(path);
+        return None;
+    }
+    Some(entry)
+}
+
+fn write_entry(dir: &Path, key: &str, entry: &CachedResponse) -> Result<()> {
+    let path = entry_path(dir, key);
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec(entry)?)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Delete the oldest entries beyond `max_entries`.
+fn prune(dir: &Path, max_entries: usize) -> Result<()> {
+    let mut files: Vec<(std::time::SystemTime, PathBuf)> = std::fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            Some((path.metadata().ok()?.modified().ok()?, path))
+        })
+        .collect();
+    if files.len() <= max_entries {
+        return Ok(());
+    }
+    files.sort();
+    for (_, path) in &files[..files.len() - max_entries] {
+        let _ = std::fs::remove_file(path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request_key(api_key_hash: &str) -> RequestKey<'_> {
+        RequestKey {
+            api_key_hash: Some(api_key_hash),
+            route: "/v1/chat/completions",
+            model: "gpt-4o",
+            action: None,
+            api_version: None,
+            metadata: false,
+            anthropic_beta: &[],
+        }
+    }
+
+    fn cache(backend: ResponseCacheBackend, dir: &Path, max_entries: usize) -> ResponseCache {
+        ResponseCache::open(&ResponseCacheConfig {
+            enabled: true,
+            backend,
+            dir: dir.to_str().unwrap().to_string(),
+            max_entries,
+            ..Default::default()
+        })
+        .unwrap()
+        .unwrap()
+    }
+
+    fn upstream_response(body: &str) -> Response {
+        Response::builder()
+            .header("content-type", "application/json")
+            .header(crate::constants::api::ACR_COST_USD_HEADER, "0.01")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[test]
+    fn key_ignores_field_order_and_stream_flag() {
+        let key = request_key("abc");
+        let a = json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "hi"}], "temperature": 0});
+        let b = json!({"temperature": 0, "stream": false, "messages": [{"content": "hi", "role": "user"}], "model": "gpt-4o"});
+        assert_eq!(cache_key(&key, &a), cache_key(&key, &b));
+
+        let other_prompt = json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "bye"}], "temperature": 0});
+        assert_ne!(cache_key(&key, &a), cache_key(&key, &other_prompt));
+        assert_ne!(cache_key(&key, &a), cache_key(&request_key("def"), &a));
+    }
+
+    #[test]
+    fn key_covers_anthropic_beta_features() {
+        let body = json!({"model": "claude-sonnet-4-6", "messages": []});
+        let beta = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                crate::constants::api::ANTHROPIC_BETA_HEADER,
+                HeaderValue::from_str(value).unwrap(),
+            );
+            normalized_anthropic_beta(&headers)
+        };
+        let key_with = |features: &[String]| {
+            cache_key(
+                &RequestKey {
+                    anthropic_beta: features,
+                    ..request_key("abc")
+                },
+                &body,
+            )
+        };
+
+        let long_context = beta("context-1m-2025-08-07");
+        let long_output = beta("output-128k-2025-02-19");
+        assert_ne!(key_with(&long_context), key_with(&long_output));
+        assert_ne!(key_with(&long_context), key_with(&[]));
+        assert_eq!(
+            key_with(&beta("output-128k-2025-02-19, Context-1m-2025-08-07")),
+            key_with(&beta("context-1m-2025-08-07,output-128k-2025-02-19"))
+        );
+    }
+
+    #[test]
+    fn policy_follows_model_patterns() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut cache = cache(ResponseCacheBackend::Memory, dir.path(), 10);
+        let all = CachePolicy {
+            ttl: Duration::from_secs(300),
+            max_bytes: 1024 * 1024,
+        };
+        assert_eq!(cache.policy(&["anything"]), Some(all));
+
+        let config: ResponseCacheConfig = serde_yaml_ng::from_str(
+            r#"
+enabled: true
+models:
+  "gpt-*": {}
+  gpt-4o: {ttl_secs: 60, max_bytes: 100}
+  gpt-4o-mini: {ttl_secs: 0}
+"#,
+        )
+        .unwrap();
+        cache.config = Arc::new(config);
+        assert_eq!(
+            cache.policy(&["gpt-4o"]),
+            Some(CachePolicy {
+                ttl: Duration::from_secs(60),
+                max_bytes: 100,
+            })
+        );
+        assert_eq!(cache.policy(&["gpt-4.1"]), Some(all));
+        assert_eq!(cache.policy(&["gpt-4o-mini"]), None);
+        assert_eq!(cache.policy(&["claude-sonnet-4-6"]), None);
+        assert_eq!(cache.policy(&["alias", "gpt-4.1"]), Some(all));
+    }
+
+    #[tokio::test]
+    async fn stored_responses_are_served_until_they_expire() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for backend in [ResponseCacheBackend::Memory, ResponseCacheBackend::Disk] {
+            let cache = cache(backend, dir.path(), 10);
+            let policy = cache.policy(&["gpt-4o"]).unwrap();
+            let response = cache
+                .store(
+                    "k1".into(),
+                    policy,
+                    ("gpt-4o", "primary", "d123"),
+                    upstream_response(r#"{"id":"1"}"#),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.headers()[ACR_CACHE_HEADER], "miss");
+
+            let hit = cache.get("k1").await.expect("cached");
+            assert_eq!(
+                (hit.provider.as_str(), hit.body.as_str()),
+                ("primary", r#"{"id":"1"}"#)
+            );
+            let response = hit.into_response().unwrap();
+            assert_eq!(response.headers()[ACR_CACHE_HEADER], "hit");
+            assert_eq!(response.headers()["content-type"], "application/json");
+            assert!(
+                response
+                    .headers()
+                    .get(crate::constants::api::ACR_COST_USD_HEADER)
+                    .is_none()
+            );
+
+            let expired = CachePolicy {
+                ttl: Duration::ZERO,
+                ..policy
+            };
+            cache
+                .store(
+                    "k2".into(),
+                    expired,
+                    ("m", "p", "d"),
+                    upstream_response("{}"),
+                )
+                .await
+                .unwrap();
+            assert!(cache.get("k2").await.is_none(), "{backend:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_responses_and_old_entries_are_dropped() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for backend in [ResponseCacheBackend::Memory, ResponseCacheBackend::Disk] {
+            let dir = dir.path().join(format!("{backend:?}"));
+            let cache = cache(backend, &dir, 2);
+            let policy = CachePolicy {
+                ttl: Duration::from_secs(60),
+                max_bytes: 8,
+            };
+            let response = cache
+                .store(
+                    "big".into(),
+                    policy,
+                    ("m", "p", "d"),
+                    upstream_response("0123456789"),
+                )
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], b"0123456789");
+            assert!(cache.get("big").await.is_none());
+
+            for key in ["a", "b", "c"] {
+                cache
+                    .store(key.into(), policy, ("m", "p", "d"), upstream_response("{}"))
+                    .await
+                    .unwrap();
+                // Distinct expiry times and file modification times.
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            assert!(cache.get("a").await.is_none(), "{backend:?}");
+            assert!(cache.get("b").await.is_some());
+            assert!(cache.get("c").await.is_some());
+        }
+    }
+}
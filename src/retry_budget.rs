@@ -0,0 +1,80 @@
+//! Global budget for failover retries.
+//!
+//! When a provider answers 429 or fails, the request is sent on to the next
+//! provider. That is what keeps a single bad deployment invisible to clients,
+//! but during a wide outage every request fails everywhere and each one is
+//! multiplied by the number of providers, piling load onto upstreams that are
+//! already struggling. With `retry_budget.ratio` set, every client request
+//! deposits into a shared budget and every failover withdraws from it; once
+//! retries exceed `ratio` of the requests in the last `window_secs` (plus a
+//! `min_per_sec` reserve), requests fail with the error they got instead of
+//! failing over.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tower::retry::budget::{Budget, TpsBudget};
+
+use crate::config::RetryBudgetConfig;
+
+/// Shared retry budget; cheap to clone.
+#[derive(Clone)]
+pub struct RetryBudget {
+    budget: Arc<TpsBudget>,
+}
+
+impl std::fmt::Debug for RetryBudget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RetryBudget").finish_non_exhaustive()
+    }
+}
+
+impl RetryBudget {
+    /// `None` unless `ratio` is set. Config validation keeps the values in
+    /// the range `TpsBudget` accepts.
+    pub fn from_config(config: &RetryBudgetConfig) -> Option<Self> {
+        let ratio = config.ratio?;
+        Some(Self {
+            budget: Arc::new(TpsBudget::new(
+                Duration::from_secs(config.window_secs),
+                config.min_per_sec,
+                ratio,
+            )),
+        })
+    }
+
+    /// Count a client request towards the budget.
+    pub fn record_request(&self) {
+        self.budget.deposit();
+    }
+
+    /// Take one retry from the budget; false when it is spent.
+    pub fn try_retry(&self) -> bool {
+        self.budget.withdraw()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn retries_are_capped_at_the_ratio_of_requests() {
+        let budget = RetryBudget::from_config(&RetryBudgetConfig {
+            ratio: Some(0.5),
+            min_per_sec: 0,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!budget.try_retry());
+
+        for _ in 0..4 {
+            budget.record_request();
+        }
+        assert!(budget.try_retry());
+        assert!(budget.try_retry());
+        assert!(!budget.try_retry());
+
+        assert!(RetryBudget::from_config(&RetryBudgetConfig::default()).is_none());
+    }
+}
@@ -1,24 +1,33 @@
 use axum::{
     Router,
-    extract::{ConnectInfo, Path, State},
+    extract::{ConnectInfo, Path, Query, State},
     http::{HeaderMap, Method, StatusCode},
     response::{IntoResponse, Json, Response},
     routing::{get, post},
 };
 use serde_json::{Value, json};
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use thiserror::Error;
 
 use crate::{
+    admission::{AdmissionController, Shed},
     balancer::LoadBalancer,
-    config::Config,
-    metrics::{ActiveRequestGuard, MetricsService},
-    proxy::{ProxyExecuteResult, ProxyRequestBuilder, ProxyRequestParams, extract_api_key},
+    batches::{BatchOutcome, BatchRequest, BatchStore, ListParams},
+    config::{ApiKeyConfig, Config, Priority, Provider, Tenant},
+    dead_letter::{CapturedRequest, DeadLetter, DeadLetterStore},
+    deployment_health::Health,
+    metrics::{ActiveRequestGuard, MetricsService, UpstreamAttempt},
+    proxy::{
+        LlmFamily, ProxyExecuteResult, ProxyRequestBuilder, ProxyRequestParams, extract_api_key,
+    },
     quota::{QuotaCheckResult, QuotaManager},
     rate_limit::AuthRateLimiter,
     registry::ModelRegistry,
     request_limiter::{RequestLimitResult, RequestLimiter},
+    session::SessionAffinity,
     token::TokenManager,
+    upstream_limits::Pressure,
 };
 
 #[derive(Clone)]
@@ -34,11 +43,75 @@ pub struct AppState {
     pub rate_limiter: AuthRateLimiter,
     pub quota_manager: Option<QuotaManager>,
     pub request_limiter: Option<std::sync::Arc<RequestLimiter>>,
+    pub batches: BatchStore,
+    pub session_affinity: SessionAffinity,
+    pub cached_contents: crate::cached_content::CachedContents,
+    pub admission: Option<AdmissionController>,
+    pub stream_limiter: Option<crate::stream_limit::StreamLimiter>,
+    /// Paces streamed output per key; `None` unless an output rate is set.
+    pub output_pacer: Option<crate::stream_pace::OutputPacer>,
+    pub retry_budget: Option<crate::retry_budget::RetryBudget>,
+    pub dead_letters: Option<DeadLetterStore>,
+    pub image_fetcher: Option<crate::image_fetch::ImageFetcher>,
+    /// Non-streaming responses reused for identical requests; `None` unless
+    /// `response_cache.enabled`.
+    pub response_cache: Option<crate::response_cache::ResponseCache>,
+    /// SLO burn-rate alerts, rendered with `/metrics`
+    pub slo: Option<crate::slo::SloMonitor>,
+    pub upstream_limits: Option<crate::upstream_limits::UpstreamLimits>,
+    /// Traffic sent per provider against `providers[].quota`; `None` unless
+    /// a provider has one.
+    pub tenant_quotas: Option<crate::tenant_quota::TenantQuotas>,
+    /// Recent errors and latency per deployment; `None` unless enabled.
+    pub deployment_health: Option<crate::deployment_health::DeploymentHealth>,
+    pub log_level: Option<crate::log_level::LogLevelControl>,
+    pub warmup: Option<crate::warmup::Warmup>,
+    pub hmac_auth: Option<crate::hmac_auth::HmacAuth>,
+    pub disabled_models: crate::model_switch::DisabledModels,
 }
 
+/// `request_path` recorded for requests run on behalf of a message batch.
+const BATCH_REQUEST_PATH: &str = "/v1/messages/batches";
+
 pub fn create_router(state: AppState) -> Router {
+    let mut router = inference_routes();
+    if state.config.admin_bind.is_none() {
+        router = router.merge(admin_routes());
+    }
+    let mount = state.config.mount.clone();
+    crate::mount::apply(with_middleware(router, state), &mount)
+}
+
+/// Router for `admin_bind`: the admin routes and the health probes, served
+/// at the root regardless of `mount`.
+pub fn create_admin_router(state: AppState) -> Router {
+    let router = admin_routes()
+        .route("/health", get(health_check))
+        .route("/ready", get(readiness));
+    with_middleware(router, state)
+}
+
+fn admin_routes() -> Router<AppState> {
     Router::new()
+        .route("/metrics", get(get_metrics))
+        .route("/admin/recent", get(get_recent_requests))
+        .route("/admin/deployment-conflicts", get(get_deployment_conflicts))
+        .route("/admin/models/{model}/refresh", post(refresh_model))
+        .route("/admin/models/disabled", get(list_disabled_models))
+        .route(
+            "/admin/models/{model}/disabled",
+            axum::routing::put(disable_model).delete(enable_model),
+        )
+        .route("/admin/log-level", get(get_log_level).put(set_log_level))
+        .route("/admin/dead-letters", get(list_dead_letters))
+        .route("/admin/dead-letters/{id}", get(get_dead_letter))
+        .route("/admin/dead-letters/{id}/replay", post(replay_dead_letter))
+}
+
+fn inference_routes() -> Router<AppState> {
+    let router = Router::new()
         .route("/health", get(health_check))
+        .route("/ready", get(readiness))
         .route("/v1/models", get(get_models))
         .route("/v1/chat/completions", post(handle_openai_chat))
         .route("/litellm/v1/chat/completions", post(handle_openai_chat))
@@ -56,8 +129,39 @@ pub fn create_router(state: AppState) -> Router {
             "/openai/deployments/{model}/embedding",
             post(handle_azure_openai),
         )
+        .route(
+            "/openai/deployments/{model}/embeddings",
+            post(handle_azure_openai),
+        )
+        .route(
+            "/openai/deployments/{model}/completions",
+            post(handle_azure_openai_completions),
+        )
+        .route("/v1/templates", get(list_templates))
+        .route("/v1/templates/{name}/render", post(render_template))
         .route("/v1/messages", post(handle_claude_messages))
         .route("/anthropic/v1/messages", post(handle_claude_messages))
+        .route(
+            "/v1/messages/batches",
+            post(create_message_batch).get(list_message_batches),
+        )
+        .route(
+            "/anthropic/v1/messages/batches",
+            post(create_message_batch).get(list_message_batches),
+        )
+        .route("/v1/messages/batches/{batch_id}", get(get_message_batch))
+        .route(
+            "/anthropic/v1/messages/batches/{batch_id}",
+            get(get_message_batch),
+        )
+        .route(
+            "/v1/messages/batches/{batch_id}/results",
+            get(get_message_batch_results),
+        )
+        .route(
+            "/anthropic/v1/messages/batches/{batch_id}/results",
+            get(get_message_batch_results),
+        )
         .route(
             "/gemini/models/{model_operation}",
             post(handle_gemini_models),
@@ -70,13 +174,99 @@ pub fn create_router(state: AppState) -> Router {
             "/v1beta/models/{model_operation}",
             post(handle_gemini_models),
         )
+        .route("/gemini/cachedContents", post(create_cached_content))
+        .route(
+            "/gemini/cachedContents/{id}",
+            get(get_cached_content).delete(delete_cached_content),
+        )
+        .route(
+            "/gemini/v1beta/cachedContents",
+            post(create_cached_content),
+        )
+        .route(
+            "/gemini/v1beta/cachedContents/{id}",
+            get(get_cached_content).delete(delete_cached_content),
+        )
+        .route("/v1beta/cachedContents", post(create_cached_content))
+        .route(
+            "/v1beta/cachedContents/{id}",
+            get(get_cached_content).delete(delete_cached_content),
+        )
+        .route(
+            "/v1beta/openai/chat/completions",
+            post(handle_gemini_openai_compat),
+        )
+        .route(
+            "/v1/projects/{project}/locations/{location}/publishers/google/models/{model_operation}",
+            post(handle_vertex_models),
+        )
+        .route(
+            "/v1beta1/projects/{project}/locations/{location}/publishers/google/models/{model_operation}",
+            post(handle_vertex_models),
+        );
+    #[cfg(feature = "db")]
+    let router = router.route("/usage/export", get(export_usage));
+    router
+}
+
+fn with_middleware(router: Router<AppState>, state: AppState) -> Router {
+    let trusted_proxies = crate::client_ip::TrustedProxies::new(&state.config.trusted_proxies);
+    router
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::timeout::enforce,
+        ))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::hmac_auth::authenticate,
+        ))
+        .route_layer(axum::middleware::from_fn(crate::request_id::assign))
         .with_state(state)
+        .layer(axum::middleware::from_fn_with_state(
+            trusted_proxies,
+            crate::client_ip::resolve,
+        ))
 }
 
 pub async fn health_check() -> impl IntoResponse {
     "OK"
 }
 
+/// Readiness probe: 503 until the startup warm-up (if enabled) is done. The
+/// warm-up results are included either way.
+pub async fn readiness(State(state): State<AppState>) -> Response {
+    let warmup = state.warmup.as_ref().map(|w| w.snapshot());
+    let ready = warmup.as_ref().is_none_or(|w| w.done);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(json!({ "ready": ready, "warmup": warmup }))).into_response()
+}
+
+/// Prometheus scrape endpoint. Requires an API key like every other route;
+/// scrapers can send it as `Authorization: Bearer <key>`.
+pub async fn get_metrics(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    authenticate_client(&state, &headers, &addr.ip().to_string()).await?;
+    let mut text = state.metrics.render_prometheus().await;
+    if let Some(ref slo) = state.slo {
+        text.push_str(&slo.render_prometheus());
+    }
+    Ok((
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        text,
+    )
+        .into_response())
+}
+
 fn extract_model_from_body(body: &Value) -> Result<String, AppError> {
     body.get("model")
         .and_then(|v| v.as_str())
@@ -113,10 +303,13 @@ async fn record_failure_metrics(metrics: &MetricsService) {
         .await;
 }
 
-#[cfg_attr(not(feature = "db"), allow(unused_variables))]
-// Eight parameters — each is a distinct request-scoped concern (axum-extracted
+// Ten parameters — each is a distinct request-scoped concern (axum-extracted
 // state, request shape, downstream routing). Bundling into a struct would just
 // shift the call-site complexity without reducing it.
+//
+// Runs the request, reports internal errors to Sentry and, when dead-letter
+// capture is on, records the request if it failed upstream (see
+// `dead_letter`).
 #[allow(clippy::too_many_arguments)]
 async fn execute_proxy_request(
     state: &AppState,
@@ -126,7 +319,113 @@ async fn execute_proxy_request(
     action: Option<String>,
     client_ip: &str,
     request_path: &str,
-    force_family: Option<crate::proxy::LlmFamily>,
+    force_family: Option<LlmFamily>,
+    client_family: LlmFamily,
+    api_version: Option<String>,
+) -> Result<Response, AppError> {
+    let captured = state.dead_letters.as_ref().map(|_| CapturedRequest {
+        path: request_path.to_string(),
+        model: model.to_string(),
+        action: action.clone(),
+        force_family,
+        client_family,
+        api_version: api_version.clone(),
+        headers: CapturedRequest::capture_headers(headers),
+        body: body.clone(),
+    });
+    let result = forward_proxy_request(
+        state,
+        headers,
+        body,
+        model,
+        action,
+        client_ip,
+        request_path,
+        force_family,
+        client_family,
+        api_version,
+    )
+    .await;
+
+    if let Err(AppError::Internal(ref e)) = result {
+        crate::sentry::capture(
+            &format!("{e:#}"),
+            &[
+                ("route", request_path),
+                ("model", model),
+                ("client_family", &format!("{client_family:?}")),
+            ],
+        );
+    }
+
+    if let (Some(dead_letters), Some(captured)) = (&state.dead_letters, captured)
+        && let Some((status, error)) = dead_letter_failure(&result)
+    {
+        let owner = extract_api_key(headers).map(|k| crate::quota::hash_api_key(&k));
+        let entry = DeadLetter::new(owner, status.as_u16(), error, captured);
+        let id = entry.id.clone();
+        match dead_letters.record(entry).await {
+            Ok(()) => tracing::info!("Captured failed request as dead letter {}", id),
+            Err(e) => tracing::warn!("Failed to record dead letter: {:#}", e),
+        }
+    }
+    result
+}
+
+/// Add `x-acr-model`, `x-acr-deployment-id` and `x-acr-provider`: the
+/// resolved model and where it ran, for correlating with AI Core.
+fn insert_upstream_identity(
+    headers: &mut HeaderMap,
+    model: &str,
+    deployment_id: &str,
+    provider: &str,
+) {
+    use crate::constants::api::{ACR_DEPLOYMENT_ID_HEADER, ACR_MODEL_HEADER, ACR_PROVIDER_HEADER};
+    for (name, value) in [
+        (ACR_MODEL_HEADER, model),
+        (ACR_DEPLOYMENT_ID_HEADER, deployment_id),
+        (ACR_PROVIDER_HEADER, provider),
+    ] {
+        if let Ok(value) = axum::http::HeaderValue::from_str(value) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+/// Status and message of a result worth dead-lettering: an upstream 5xx,
+/// timeout or connection failure. Client errors and 429s (which the client
+/// is told to retry) are not captured.
+fn dead_letter_failure(result: &Result<Response, AppError>) -> Option<(StatusCode, String)> {
+    match result {
+        Ok(response) if response.status().is_server_error() => Some((
+            response.status(),
+            format!("Upstream returned {}", response.status()),
+        )),
+        Ok(_) => None,
+        Err(e @ AppError::UpstreamTimeout { .. }) => {
+            Some((StatusCode::GATEWAY_TIMEOUT, e.to_string()))
+        }
+        Err(e @ AppError::UpstreamUnavailable { .. }) => {
+            Some((StatusCode::BAD_GATEWAY, e.to_string()))
+        }
+        Err(AppError::Internal(e)) => Some((StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}"))),
+        Err(_) => None,
+    }
+}
+
+#[cfg_attr(not(feature = "db"), allow(unused_variables))]
+#[allow(clippy::too_many_arguments)]
+async fn forward_proxy_request(
+    state: &AppState,
+    headers: &HeaderMap,
+    mut body: Value,
+    model: &str,
+    action: Option<String>,
+    client_ip: &str,
+    request_path: &str,
+    force_family: Option<LlmFamily>,
+    client_family: LlmFamily,
+    api_version: Option<String>,
 ) -> Result<Response, AppError> {
     // Check rate limiting before processing
     if let Some(remaining) = state.rate_limiter.is_rate_limited(client_ip).await {
@@ -135,28 +434,63 @@ async fn execute_proxy_request(
         });
     }
 
-    // Reject the "internal" key from non-loopback IPs
+    // A key's own model aliases come first, so keys can point the same name
+    // at different models; everything below sees the mapped name.
     let request_api_key = extract_api_key(headers);
-    if let Some(ref key) = request_api_key
-        && key == "internal"
+    let model = match request_api_key
+        .as_deref()
+        .and_then(|key| key_model_alias(state, key, model))
     {
-        // Fail closed on parse errors: an unparseable client_ip cannot be
-        // validated as loopback, so refuse to honor the privileged "internal"
-        // key. axum's ConnectInfo always yields a parseable IP, so reaching
-        // this branch implies upstream construction is broken — log and reject.
-        let parsed: Result<std::net::IpAddr, _> = client_ip.parse();
-        match parsed {
-            Ok(ip) if ip.is_loopback() => {}
-            Ok(_) => return Err(AppError::InvalidApiKey),
-            Err(e) => {
-                tracing::warn!(
-                    "Could not parse client IP '{}' while checking 'internal' key: {}",
-                    client_ip,
-                    e
-                );
-                return Err(AppError::InvalidApiKey);
+        Some(mapped) => {
+            tracing::debug!("Key model alias '{}' -> '{}'", model, mapped);
+            if let Some(field) = body.get_mut("model") {
+                *field = json!(mapped);
+            }
+            mapped
+        }
+        None => model,
+    };
+
+    // Deprecated models are served with a notice; past their sunset, an
+    // opted-in model is swapped for its replacement before anything else
+    // (allowlists, quotas, routing) looks at it.
+    let deprecation = crate::deprecation::DeprecationNotice::check(
+        &state.model_registry,
+        model,
+        chrono::Utc::now().date_naive(),
+    );
+    let model = match deprecation.as_ref().and_then(|d| d.rewrite_to()) {
+        Some(replacement) => {
+            tracing::info!(
+                "Rewriting request for retired model '{}' to '{}'",
+                model,
+                replacement
+            );
+            if let Some(field) = body.get_mut("model") {
+                *field = json!(replacement);
             }
+            replacement
         }
+        None => model,
+    };
+
+    // Reject the "internal" key from non-loopback IPs
+    if let Some(ref key) = request_api_key {
+        reject_remote_internal_key(key, client_ip)?;
+        check_model_allowed(state, key, model)?;
+        audit_key_use(state, key);
+    }
+
+    crate::templates::expand(&state.config.templates, &mut body, client_family)
+        .map_err(AppError::BadRequest)?;
+
+    if let Ok(resolved) = crate::proxy::normalize_model(model, &state.model_registry)
+        && let Some(disabled) = state.disabled_models.get(&resolved)
+    {
+        return Err(AppError::ModelDisabled {
+            model: resolved,
+            reason: disabled.reason,
+        });
     }
 
     // Pre-compute API key hash once for quota checks, DB logging, and usage recording
@@ -164,15 +498,32 @@ async fn execute_proxy_request(
         .as_ref()
         .map(|k| crate::quota::hash_api_key(k));
 
+    // A dry run sends nothing upstream, so it doesn't count against the
+    // request rate or take a stream or admission slot.
+    let dry_run = crate::proxy::header_flag(headers, crate::constants::api::ACR_DRY_RUN_HEADER);
+
     // Per-key request-rate check (separate from cumulative token quota below).
+    let mut request_rate = None;
     if let Some(ref rl) = state.request_limiter
         && let Some(ref kh) = api_key_hash
-        && let RequestLimitResult::Exceeded { retry_after_secs } = rl.check(kh)
+        && !dry_run
     {
-        return Err(AppError::RateLimitedRequests { retry_after_secs });
+        match rl.check_shared(kh).await {
+            RequestLimitResult::Allowed { rate } => request_rate = rate,
+            RequestLimitResult::Exceeded {
+                retry_after_secs,
+                limit,
+            } => {
+                return Err(AppError::RateLimitedRequests {
+                    retry_after_secs,
+                    limit,
+                });
+            }
+        }
     }
 
     // Check token quota before processing
+    let mut quota_status = None;
     if let Some(ref qm) = state.quota_manager
         && let Some(ref kh) = api_key_hash
     {
@@ -186,17 +537,158 @@ async fn execute_proxy_request(
                     limit_type,
                 });
             }
-            QuotaCheckResult::Allowed { .. } => {}
+            allowed @ QuotaCheckResult::Allowed { .. } => quota_status = Some(allowed),
+        }
+    }
+
+    // Open streams pin connections for as long as the model keeps talking,
+    // so they are capped apart from the request rate. Checked before
+    // admission: a request over its stream cap shouldn't wait for a slot.
+    let streaming = crate::proxy::extract_stream_flag(&body, &client_family, &action);
+    let quota_cost = state
+        .tenant_quotas
+        .as_ref()
+        .map_or(0, |_| crate::admission::estimate_tokens(&body));
+
+    // An identical non-streaming request from the same key is answered from
+    // the response cache, before it takes a stream or admission slot.
+    let mut cache_entry = match state.response_cache {
+        Some(ref cache) if !streaming && !dry_run => {
+            let resolved = crate::proxy::normalize_model(model, &state.model_registry).ok();
+            let models: Vec<&str> = std::iter::once(model).chain(resolved.as_deref()).collect();
+            cache.policy(&models).map(|policy| {
+                let anthropic_beta = crate::response_cache::normalized_anthropic_beta(headers);
+                let request = crate::response_cache::RequestKey {
+                    api_key_hash: api_key_hash.as_deref(),
+                    route: request_path,
+                    model,
+                    action: action.as_deref(),
+                    api_version: api_version.as_deref(),
+                    metadata: crate::proxy::header_flag(
+                        headers,
+                        crate::constants::api::ACR_METADATA_HEADER,
+                    ),
+                    anthropic_beta: &anthropic_beta,
+                };
+                let key = crate::response_cache::cache_key(&request, &body);
+                (cache, key, policy)
+            })
+        }
+        _ => None,
+    };
+    if let Some((cache, ref key, _)) = cache_entry {
+        // The key is otherwise checked per provider, after a miss: a revoked,
+        // expired or reloaded-away key must not keep getting cached answers.
+        let api_key = request_api_key.as_deref().ok_or(AppError::MissingApiKey)?;
+        if !state.token_manager.is_valid_api_key(api_key) {
+            state.rate_limiter.record_failure(client_ip).await;
+            record_failure_metrics(&state.metrics).await;
+            return Err(AppError::InvalidApiKey);
+        }
+        if let Some(hit) = cache.get(key).await {
+            state.metrics.record_response_cache(true);
+            tracing::debug!(
+                "Answering request for model '{}' from the response cache",
+                model
+            );
+            let (model, provider, deployment_id) = (
+                hit.model.clone(),
+                hit.provider.clone(),
+                hit.deployment_id.clone(),
+            );
+            let mut response = hit.into_response().map_err(AppError::Internal)?;
+            if let Some(ref rate) = request_rate {
+                rate.insert_headers(response.headers_mut());
+            }
+            if let Some(ref status) = quota_status {
+                status.insert_headers(response.headers_mut());
+            }
+            insert_upstream_identity(response.headers_mut(), &model, &deployment_id, &provider);
+            if let Some(ref notice) = deprecation {
+                notice.insert_headers(response.headers_mut());
+            }
+            return Ok(response);
         }
+        state.metrics.record_response_cache(false);
     }
 
+    let stream_slot = match state.stream_limiter {
+        Some(ref limiter) if streaming && !dry_run => {
+            match limiter.try_open(api_key_hash.as_deref()) {
+                Ok(slot) => Some(slot),
+                Err(cap) => {
+                    tracing::warn!("Rejected stream for model '{}': {}", model, cap);
+                    return Err(AppError::TooManyStreams(cap));
+                }
+            }
+        }
+        _ => None,
+    };
+
+    // Wait for an upstream slot when admission control is on. Batch work is
+    // always low priority so it soaks up spare capacity without crowding
+    // out interactive traffic.
+    let admission_permit = match state.admission {
+        Some(ref admission) if !dry_run => {
+            let key_priority = request_api_key
+                .as_deref()
+                .and_then(|key| key_config(state, key))
+                .map(|k| k.priority)
+                .unwrap_or_default();
+            let priority = if request_path == BATCH_REQUEST_PATH {
+                Priority::Low
+            } else {
+                crate::admission::request_priority(headers, key_priority)
+            };
+            let cost = if admission.sheds_by_cost() {
+                crate::admission::estimate_tokens(&body)
+            } else {
+                0
+            };
+            match admission.acquire(priority, cost).await {
+                Ok(permit) => Some(permit),
+                Err(reason) => {
+                    tracing::warn!(
+                        "Shed {} priority request for model '{}': {}",
+                        priority,
+                        model,
+                        reason
+                    );
+                    return Err(AppError::Overloaded { priority, reason });
+                }
+            }
+        }
+        _ => None,
+    };
+
     // The guard increments `active_requests` here and decrements when dropped.
     // For streaming success, we hand it off to the response body so the count
     // tracks the *body's* lifetime — i.e. drops the moment the client is done,
     // not when the spawned upstream-drain task happens to exit. For all other
-    // paths the guard drops on this function's return.
+    // paths the guard drops on this function's return. The admission and
+    // stream slots ride along with it.
     let mut active_guard: Option<ActiveRequestGuard> =
         Some(ActiveRequestGuard::new(&state.metrics));
+    if let (Some(guard), Some(permit)) = (active_guard.as_mut(), admission_permit) {
+        guard.hold_admission(permit);
+    }
+    if let (Some(guard), Some(slot)) = (active_guard.as_mut(), stream_slot) {
+        guard.hold_stream_slot(slot);
+    }
+
+    // A Gemini `cachedContent` reference only resolves on the deployment
+    // that created the cache.
+    let cache_provider = match client_family {
+        LlmFamily::Gemini => state.cached_contents.provider_for(&body),
+        _ => None,
+    };
+
+    let session_id = crate::session::extract_session_id(headers, &body);
+    // Provider affinity follows the session when there is one; otherwise a
+    // request with prompt-cache breakpoints is keyed by its cached prefix.
+    let affinity_key = session_id
+        .clone()
+        .or_else(|| crate::session::prompt_cache_key(&body));
 
     let params = ProxyRequestParams {
         headers,
@@ -209,21 +701,105 @@ async fn execute_proxy_request(
         model_registry: &state.model_registry,
         load_balancer: &state.load_balancer,
         force_family,
+        client_family,
+        api_version,
+        session_id: session_id.clone(),
+        route: request_path,
+        capture: crate::capture::requested(&state.config.capture, headers),
+        image_fetcher: state.image_fetcher.as_ref(),
+        reasoning: request_api_key
+            .as_deref()
+            .and_then(|key| key_config(state, key))
+            .map(|k| k.reasoning)
+            .unwrap_or_default(),
+        upstream_limits: state.upstream_limits.as_ref(),
+        output_pacer: state.output_pacer.as_ref(),
     };
 
     let builder = ProxyRequestBuilder::new(params);
 
-    // Get providers in load-balanced order. `LoadBalancer::new` rejects empty
-    // / all-disabled provider lists at startup, so this iterator is non-empty
-    // by construction.
-    let providers = state.load_balancer.get_ordered_providers();
+    // Get providers in load-balanced order, starting from the provider that
+    // served this conversation's previous turn (its prompt cache lives
+    // there), with scheduled windows applied. `LoadBalancer::new` rejects
+    // empty / all-disabled provider lists at startup, so this list is
+    // non-empty by construction.
+    let preferred = affinity_key
+        .as_deref()
+        .and_then(|key| state.session_affinity.preferred_provider(key));
+    if let Some(ref provider) = preferred {
+        tracing::debug!("Affinity routes request to provider '{}'", provider);
+    }
+    let mut providers = state
+        .load_balancer
+        .get_scheduled_providers(preferred.as_deref());
+    if let Some(tenant) = request_api_key
+        .as_deref()
+        .and_then(|key| tenant_config(state, key))
+        .filter(|t| !t.providers.is_empty())
+    {
+        providers.retain(|p| tenant.providers.contains(&p.name));
+        if providers.is_empty() {
+            return Err(AppError::Forbidden(format!(
+                "None of tenant '{}'s providers is enabled",
+                tenant.name
+            )));
+        }
+    }
+    if let Some(ref cache_provider) = cache_provider
+        && providers.iter().any(|p| &p.name == cache_provider)
+    {
+        tracing::debug!(
+            "Cached content pins request to provider '{}'",
+            cache_provider
+        );
+        providers.retain(|p| &p.name == cache_provider);
+    }
+    let providers = order_by_upstream_state(state, providers.into_iter(), model, streaming).await;
 
     let mut last_error: Option<AppError> = None;
+    // Providers a request was actually sent to (skipped-for-model providers
+    // excluded), reported on the aggregate 429 for diagnostics.
+    let mut providers_tried: Vec<String> = Vec::new();
+    // Shortest upstream `Retry-After` seen across 429s — the soonest moment
+    // *some* provider is expected to accept traffic again.
+    let mut min_retry_after: Option<u64> = None;
+    // Whether every provider tried so far answered 429. Only then is the
+    // client told to back off; after any other failure it gets that error.
+    let mut all_rate_limited = true;
+    // Failed attempts so far, attached to each later attempt's summary and
+    // log record so fallback amplification is visible per request.
+    let mut attempts: Vec<UpstreamAttempt> = Vec::new();
 
     // Try each provider in order until one succeeds or all are exhausted
-    for (i, provider) in providers.enumerate() {
+    for (i, (provider, pressure)) in providers.into_iter().enumerate() {
+        // The upstream asked this deployment to back off, or the provider is
+        // at its tenant quota: don't send it a request it will reject. Its
+        // remaining wait counts toward the aggregate 429's `Retry-After` like
+        // a real upstream hint would.
+        if let Pressure::CoolingDown(wait) = pressure {
+            let secs = (wait.as_millis() as u64).div_ceil(1000);
+            tracing::debug!(
+                "Skipping provider '{}' for model '{}': backing off for {}s after upstream rate limiting or at its tenant quota",
+                provider.name,
+                model,
+                secs
+            );
+            min_retry_after = Some(min_retry_after.map_or(secs, |m| m.min(secs)));
+            if matches!(
+                last_error,
+                None | Some(AppError::RateLimited { .. })
+                    | Some(AppError::ModelNotAvailableOnProvider { .. })
+            ) {
+                last_error = Some(AppError::RateLimited {
+                    provider: provider.name.clone(),
+                    retry_after_secs: Some(secs),
+                });
+            }
+            continue;
+        }
+
         // Try to build the request for this provider
-        let proxy = match builder.build_for_provider(provider).await {
+        let mut proxy = match builder.build_for_provider(provider).await {
             Ok(proxy) => proxy,
             Err(AppError::ModelNotAvailableOnProvider { model, provider }) => {
                 tracing::debug!(
@@ -243,21 +819,42 @@ async fn execute_proxy_request(
             Err(e) => {
                 // Non-recoverable error (auth failure, etc.)
                 record_failure_metrics(&state.metrics).await;
+                state.metrics.record_fan_in(attempts.len());
                 return Err(e);
             }
         };
+        proxy.prior_attempts = attempts.clone();
 
-        #[cfg(feature = "db")]
-        let db_context = {
-            state.database.as_ref().map(|db| crate::proxy::DbContext {
-                database: db.clone(),
-                request_path: request_path.to_string(),
-                api_key_hash: api_key_hash.clone(),
-            })
-        };
+        // A dry run stops at the first provider that can take the request
+        // and shows what it would have been sent; where in AI Core only to
+        // the loopback-only internal key.
+        if dry_run {
+            let show_upstream = request_api_key.as_deref() == Some("internal");
+            return Ok(Json(proxy.dry_run(show_upstream)).into_response());
+        }
+
+        // Every request pays into the retry budget; failing over draws on it.
+        // Once it is spent, the request ends with the error it already got.
+        if let Some(ref budget) = state.retry_budget {
+            if providers_tried.is_empty() {
+                budget.record_request();
+            } else if !budget.try_retry() {
+                tracing::warn!(
+                    "Retry budget exhausted, not failing over to provider '{}' for model '{}'",
+                    provider.name,
+                    model
+                );
+                state.metrics.record_retry_denied();
+                break;
+            }
+        }
+
+        providers_tried.push(provider.name.clone());
+        if let Some(ref quotas) = state.tenant_quotas {
+            quotas.charge(&provider.name, quota_cost);
+        }
 
         // Execute the request
-        #[cfg(feature = "db")]
         let start_time = std::time::Instant::now();
         match proxy
             .execute(
@@ -265,21 +862,36 @@ async fn execute_proxy_request(
                 &state.metrics,
                 &mut active_guard,
                 #[cfg(feature = "db")]
-                db_context,
+                state.database.clone(),
                 state.quota_manager.clone(),
                 api_key_hash.clone(),
             )
             .await
         {
             Ok(ProxyExecuteResult::Response {
-                response,
+                mut response,
                 token_stats,
             }) => {
                 let is_success = response.status().is_success();
+                if let Some(ref health) = state.deployment_health {
+                    if is_success {
+                        health.record_success(
+                            &provider.name,
+                            &proxy.deployment_id,
+                            proxy.stream,
+                            start_time.elapsed(),
+                        );
+                    } else if response.status().is_server_error() {
+                        health.record_failure(&provider.name, &proxy.deployment_id);
+                    }
+                }
 
                 // Record successful auth only after a successful response
                 if is_success {
                     state.rate_limiter.record_success(client_ip).await;
+                    if let Some(ref key) = affinity_key {
+                        state.session_affinity.record(key, &provider.name);
+                    }
                 }
                 if i > 0 && is_success {
                     tracing::info!(
@@ -288,6 +900,7 @@ async fn execute_proxy_request(
                         i
                     );
                 }
+                state.metrics.record_fan_in(attempts.len() + 1);
 
                 // For non-streaming responses, record metrics now.
                 // Streaming responses record metrics when the stream completes,
@@ -301,28 +914,8 @@ async fn execute_proxy_request(
                         .metrics
                         .record_completion(is_success, Some(&proxy.model), &counts)
                         .await;
-
-                    // Log request to database
-                    #[cfg(feature = "db")]
-                    if let Some(ref db) = state.database {
-                        let elapsed = start_time.elapsed();
-                        let response_status = response.status().as_u16();
-                        let record = crate::database::RequestRecord::new(
-                            request_path.to_string(),
-                            proxy.model.clone(),
-                            proxy.provider_name.clone(),
-                            elapsed,
-                            response_status,
-                            false,
-                            &token_stats,
-                            api_key_hash.clone(),
-                        );
-                        let db = db.clone();
-                        tokio::spawn(async move {
-                            if let Err(e) = db.insert_request(record).await {
-                                tracing::warn!("Failed to log request to database: {}", e);
-                            }
-                        });
+                    if let Some(ref sid) = session_id {
+                        state.metrics.record_session_usage(sid, &counts).await;
                     }
 
                     // Record quota usage for non-streaming responses
@@ -333,24 +926,88 @@ async fn execute_proxy_request(
                     }
                 }
 
+                if is_success
+                    && !proxy.stream
+                    && let Some((cache, key, policy)) = cache_entry.take()
+                {
+                    response = cache
+                        .store(
+                            key,
+                            policy,
+                            (&proxy.model, &provider.name, &proxy.deployment_id),
+                            response,
+                        )
+                        .await
+                        .map_err(AppError::Internal)?;
+                }
+
+                if let Some(ref rate) = request_rate {
+                    rate.insert_headers(response.headers_mut());
+                }
+                if let Some(ref status) = quota_status {
+                    status.insert_headers(response.headers_mut());
+                }
+                insert_upstream_identity(
+                    response.headers_mut(),
+                    &proxy.model,
+                    &proxy.deployment_id,
+                    &provider.name,
+                );
+                if let Some(ref notice) = deprecation {
+                    notice.insert_headers(response.headers_mut());
+                }
                 return Ok(response);
             }
-            Ok(ProxyExecuteResult::RateLimited) => {
+            Ok(ProxyExecuteResult::RateLimited { retry_after_secs }) => {
                 tracing::warn!(
                     "Provider '{}' returned 429, trying next provider",
                     provider.name
                 );
-                last_error = Some(AppError::RateLimited(provider.name.clone()));
+                min_retry_after = match (min_retry_after, retry_after_secs) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                attempts = proxy.attempts(429, start_time.elapsed());
+                if all_rate_limited {
+                    last_error = Some(AppError::RateLimited {
+                        provider: provider.name.clone(),
+                        retry_after_secs,
+                    });
+                }
                 continue;
             }
             Err(e) => {
                 // Request failed, try next provider
                 tracing::error!(
-                    "Request failed on provider '{}': {}, trying next",
+                    "Request failed on provider '{}': {:#}, trying next",
                     provider.name,
                     e
                 );
-                last_error = Some(AppError::Internal(e));
+                let error = classify_upstream_error(e, &provider.name);
+                if let Some(ref health) = state.deployment_health
+                    && matches!(
+                        error,
+                        AppError::UpstreamTimeout { .. } | AppError::UpstreamUnavailable { .. }
+                    )
+                {
+                    health.record_failure(&provider.name, &proxy.deployment_id);
+                }
+                let status = match error {
+                    AppError::UpstreamTimeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+                    AppError::UpstreamUnavailable { .. } => StatusCode::BAD_GATEWAY,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                state
+                    .metrics
+                    .record_request(
+                        proxy
+                            .summary(status.as_u16(), start_time.elapsed(), &api_key_hash)
+                            .with_error(error.to_string()),
+                    )
+                    .await;
+                attempts = proxy.attempts(status.as_u16(), start_time.elapsed());
+                all_rate_limited = false;
+                last_error = Some(error);
                 continue;
             }
         }
@@ -358,8 +1015,14 @@ async fn execute_proxy_request(
 
     // All providers exhausted
     record_failure_metrics(&state.metrics).await;
+    state.metrics.record_fan_in(attempts.len());
     match last_error {
-        Some(AppError::RateLimited(_)) => Err(AppError::AllProvidersRateLimited),
+        Some(AppError::RateLimited { .. }) if all_rate_limited => {
+            Err(AppError::AllProvidersRateLimited {
+                retry_after_secs: min_retry_after,
+                providers_tried,
+            })
+        }
         Some(e) => Err(e),
         None => Err(AppError::Internal(anyhow::anyhow!(
             "No providers could handle the request"
@@ -367,20 +1030,275 @@ async fn execute_proxy_request(
     }
 }
 
-pub async fn get_models(State(state): State<AppState>) -> impl IntoResponse {
+/// Pair each provider with the upstream pressure on its deployment of
+/// `model` and on its tenant quota, clear providers first and, under the
+/// same pressure, healthy deployments ahead of slow and failing ones. The
+/// sort is stable, so the balancer's order (and session affinity) still
+/// decides among equals.
+async fn order_by_upstream_state<'a>(
+    state: &AppState,
+    providers: impl Iterator<Item = &'a Provider>,
+    model: &str,
+    streaming: bool,
+) -> Vec<(&'a Provider, Pressure)> {
+    let normalized = if state.upstream_limits.is_some() || state.deployment_health.is_some() {
+        crate::proxy::normalize_model(model, &state.model_registry).ok()
+    } else {
+        None
+    };
+    let mut deployments = Vec::new();
+    for provider in providers {
+        let deployment = match normalized {
+            Some(ref normalized) => {
+                state
+                    .model_registry
+                    .get_deployment_for_provider(normalized, &provider.name)
+                    .await
+            }
+            None => None,
+        };
+        deployments.push((provider, deployment));
+    }
+    let health = match state.deployment_health {
+        Some(ref health) => {
+            let candidates: Vec<(&str, &str)> = deployments
+                .iter()
+                .map(|(p, d)| (p.name.as_str(), d.as_deref().unwrap_or_default()))
+                .collect();
+            health.assess(&candidates, streaming)
+        }
+        None => vec![Health::Healthy; deployments.len()],
+    };
+    let mut ordered: Vec<_> = deployments
+        .into_iter()
+        .zip(health)
+        .map(|((provider, deployment), health)| {
+            let upstream = match (state.upstream_limits.as_ref(), deployment) {
+                (Some(limits), Some(deployment)) => limits.pressure(&provider.name, &deployment),
+                _ => Pressure::Clear,
+            };
+            let quota = state
+                .tenant_quotas
+                .as_ref()
+                .map_or(Pressure::Clear, |quotas| quotas.pressure(&provider.name));
+            (provider, upstream.worse(quota), health)
+        })
+        .collect();
+    ordered.sort_by_key(|(_, pressure, health)| (pressure.rank(), health.rank()));
+    ordered
+        .into_iter()
+        .map(|(provider, pressure, _)| (provider, pressure))
+        .collect()
+}
+
+/// Enforce the key's `allowed_models` and its tenant's `models`, if set. The
+/// check runs against the configured model the request resolves to, so an
+/// alias or family fallback can't carry a restricted key to a model outside
+/// its list.
+fn check_model_allowed(state: &AppState, api_key: &str, model: &str) -> Result<(), AppError> {
+    let lists = [
+        (
+            key_config(state, api_key).and_then(|k| k.allowed_models.as_ref()),
+            "the key's allowed_models",
+        ),
+        (
+            tenant_config(state, api_key).and_then(|t| t.models.as_ref()),
+            "the tenant's models",
+        ),
+    ];
+    for (allowed, source) in lists {
+        let Some(allowed) = allowed else {
+            continue;
+        };
+        let resolved = crate::proxy::normalize_model(model, &state.model_registry)?;
+        if model_listed(allowed, &resolved) {
+            continue;
+        }
+        tracing::warn!(
+            "Rejected request for model '{}' (resolved '{}'): not in {}",
+            model,
+            resolved,
+            source
+        );
+        return Err(AppError::ModelNotAllowed {
+            model: resolved,
+            allowed: allowed.clone(),
+        });
+    }
+    Ok(())
+}
+
+fn model_listed(patterns: &[String], model: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| crate::registry::glob_matches(pattern, model).is_some())
+}
+
+/// The model `model` names under the key's `model_aliases`, if it's one.
+fn key_model_alias<'a>(state: &'a AppState, api_key: &str, model: &str) -> Option<&'a str> {
+    key_config(state, api_key)?
+        .model_aliases
+        .get(model)
+        .map(String::as_str)
+}
+
+/// Per-key settings for a configured API key.
+fn key_config<'a>(state: &'a AppState, api_key: &str) -> Option<&'a ApiKeyConfig> {
+    state.config.api_keys.iter().find(|k| k.matches(api_key))
+}
+
+/// Audit-log which key version a client authenticated with. The version is
+/// the key's hash prefix, which also identifies it in the request log. A
+/// client still using an expiring key while its replacement is active gets a
+/// warning, so stragglers show up before the old key stops working.
+fn audit_key_use(state: &AppState, api_key: &str) {
+    let Some(key) = key_config(state, api_key) else {
+        return;
+    };
+    let Some(ref client) = key.client else {
+        return;
+    };
+    let now = chrono::Utc::now();
+    if !key.is_active_at(now) {
+        return;
+    }
+    let hash = crate::quota::hash_api_key(api_key);
+    let version = &hash[..hash.len().min(12)];
+    let Some(until) = key.valid_until else {
+        tracing::info!("Client '{}' authenticated with key {}", client, version);
+        return;
+    };
+    let replaced = state.config.api_keys.iter().any(|other| {
+        other.key != key.key && other.client.as_ref() == Some(client) && other.is_active_at(now)
+    });
+    if replaced {
+        tracing::warn!(
+            "Client '{}' authenticated with key {}, which expires at {}; its replacement is already active",
+            client,
+            version,
+            until.to_rfc3339()
+        );
+    } else {
+        tracing::info!(
+            "Client '{}' authenticated with key {} (valid until {})",
+            client,
+            version,
+            until.to_rfc3339()
+        );
+    }
+}
+
+/// The tenant a configured API key belongs to.
+fn tenant_config<'a>(state: &'a AppState, api_key: &str) -> Option<&'a Tenant> {
+    let name = key_config(state, api_key)?.tenant.as_ref()?;
+    state.config.tenants.iter().find(|t| &t.name == name)
+}
+
+/// The privileged "internal" key is only honored from loopback addresses.
+fn reject_remote_internal_key(api_key: &str, client_ip: &str) -> Result<(), AppError> {
+    if api_key != "internal" {
+        return Ok(());
+    }
+    // Fail closed on parse errors: an unparseable client_ip cannot be
+    // validated as loopback, so refuse to honor the privileged "internal"
+    // key. axum's ConnectInfo always yields a parseable IP, so reaching
+    // this branch implies upstream construction is broken — log and reject.
+    let parsed: Result<std::net::IpAddr, _> = client_ip.parse();
+    match parsed {
+        Ok(ip) if ip.is_loopback() => Ok(()),
+        Ok(_) => Err(AppError::InvalidApiKey),
+        Err(e) => {
+            tracing::warn!(
+                "Could not parse client IP '{}' while checking 'internal' key: {}",
+                client_ip,
+                e
+            );
+            Err(AppError::InvalidApiKey)
+        }
+    }
+}
+
+/// Authenticate a request served by acr itself rather than proxied (batch
+/// bookkeeping endpoints), applying the same auth rate limiting and
+/// "internal"-key rules as `execute_proxy_request`. Returns the key's hash,
+/// which identifies the caller.
+async fn authenticate_client(
+    state: &AppState,
+    headers: &HeaderMap,
+    client_ip: &str,
+) -> Result<String, AppError> {
+    if let Some(remaining) = state.rate_limiter.is_rate_limited(client_ip).await {
+        return Err(AppError::RateLimitedAuth {
+            retry_after_secs: remaining.as_secs(),
+        });
+    }
+    let api_key = extract_api_key(headers).ok_or(AppError::MissingApiKey)?;
+    reject_remote_internal_key(&api_key, client_ip)?;
+    if !state.token_manager.is_valid_api_key(&api_key) {
+        state.rate_limiter.record_failure(client_ip).await;
+        return Err(AppError::InvalidApiKey);
+    }
+    audit_key_use(state, &api_key);
+    Ok(crate::quota::hash_api_key(&api_key))
+}
+
+/// Map a failed upstream exchange to the status the client should see.
+///
+/// Walks the error chain for the underlying `reqwest::Error`: timeouts become
+/// 504 and connection / transport failures become 502, both naming the
+/// provider so operators can tell which backend misbehaved. Anything else
+/// (header construction, response building) is a genuine acr-side fault and
+/// stays a 500.
+pub(crate) fn classify_upstream_error(err: anyhow::Error, provider: &str) -> AppError {
+    let transport = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<reqwest::Error>());
+    match transport {
+        Some(e) if e.is_timeout() => AppError::UpstreamTimeout {
+            provider: provider.to_string(),
+        },
+        Some(e) if e.is_connect() || e.is_request() || e.is_body() || e.is_decode() => {
+            AppError::UpstreamUnavailable {
+                provider: provider.to_string(),
+            }
+        }
+        _ => AppError::Internal(err),
+    }
+}
+
+pub async fn get_models(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
     use crate::constants::get_context_length;
 
-    let model_names = state.model_registry.get_available_models().await;
+    // A tenant's key sees only the tenant's catalog.
+    let catalog = extract_api_key(&headers)
+        .filter(|key| state.token_manager.is_valid_api_key(key))
+        .and_then(|key| tenant_config(&state, &key))
+        .and_then(|t| t.models.clone());
 
-    let model_data: Vec<serde_json::Value> = model_names
+    // `root` / `parent` follow OpenAI's model objects: aliases and models
+    // served by the same deployments point at the name they stand in for.
+    let model_data: Vec<serde_json::Value> = state
+        .model_registry
+        .list_models()
+        .await
         .into_iter()
-        .map(|model_name| {
+        .filter(|model| {
+            catalog.as_deref().is_none_or(|catalog| {
+                crate::proxy::normalize_model(&model.id, &state.model_registry)
+                    .is_ok_and(|resolved| model_listed(catalog, &resolved))
+            })
+        })
+        .map(|model| {
             let mut obj = serde_json::Map::new();
-            obj.insert("id".into(), json!(model_name));
+            obj.insert("id".into(), json!(model.id));
             obj.insert("object".into(), json!("model"));
-            if let Some(ctx_len) = get_context_length(&model_name) {
+            if let Some(ctx_len) =
+                get_context_length(&model.id).or_else(|| get_context_length(&model.root))
+            {
                 obj.insert("context_length".into(), json!(ctx_len));
             }
+            obj.insert("root".into(), json!(model.root));
+            obj.insert("parent".into(), json!(model.parent));
             serde_json::Value::Object(obj)
         })
         .collect();
@@ -409,6 +1327,8 @@ pub async fn handle_openai_chat(
         &client_ip,
         "/v1/chat/completions",
         None,
+        LlmFamily::OpenAi,
+        None,
     )
     .await
 }
@@ -433,6 +1353,8 @@ pub async fn handle_openai_embeddings(
         &client_ip,
         "/v1/embeddings",
         None,
+        LlmFamily::OpenAi,
+        None,
     )
     .await
 }
@@ -458,7 +1380,9 @@ pub async fn handle_openai_responses(
         None,
         &client_ip,
         "/v1/responses",
-        Some(crate::proxy::LlmFamily::OpenAiResponses),
+        Some(LlmFamily::OpenAiResponses),
+        LlmFamily::OpenAiResponses,
+        None,
     )
     .await
 }
@@ -488,34 +1412,98 @@ pub async fn handle_openai_responses_compact(
         Some("compact".to_string()),
         &client_ip,
         "/v1/responses/compact",
-        Some(crate::proxy::LlmFamily::OpenAiResponses),
+        Some(LlmFamily::OpenAiResponses),
+        LlmFamily::OpenAiResponses,
+        None,
     )
     .await
 }
 
+/// Azure OpenAI deployment-style routes (chat completions and embeddings).
+/// The client's `api-version` query parameter, when present, is forwarded
+/// upstream in place of `openai_api_version` so Azure SDK clients get the API
+/// surface they were built against.
 pub async fn handle_azure_openai(
     State(state): State<AppState>,
     Path(model): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-    Json(mut body): Json<Value>,
+    Json(body): Json<Value>,
 ) -> Result<Response, AppError> {
-    ensure_model_in_body(&mut body, &model);
-    let model = extract_model_from_body(&body)?;
-    let client_ip = addr.ip().to_string();
-    execute_proxy_request(
-        &state,
-        &headers,
-        body,
+    proxy_azure_deployment(&state, &model, &query, addr, &headers, body, None).await
+}
+
+/// Azure OpenAI legacy Completions API (`prompt` in, `choices[].text` out).
+/// Routed to the deployment's `/completions` endpoint rather than
+/// `/chat/completions`.
+pub async fn handle_azure_openai_completions(
+    State(state): State<AppState>,
+    Path(model): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Response, AppError> {
+    proxy_azure_deployment(
+        &state,
         &model,
-        None,
+        &query,
+        addr,
+        &headers,
+        body,
+        Some(crate::constants::api::LEGACY_COMPLETIONS_ACTION.to_string()),
+    )
+    .await
+}
+
+async fn proxy_azure_deployment(
+    state: &AppState,
+    model: &str,
+    query: &HashMap<String, String>,
+    addr: SocketAddr,
+    headers: &HeaderMap,
+    mut body: Value,
+    action: Option<String>,
+) -> Result<Response, AppError> {
+    let api_version = query
+        .get("api-version")
+        .map(|v| validate_api_version(v))
+        .transpose()?;
+    ensure_model_in_body(&mut body, model);
+    let model = extract_model_from_body(&body)?;
+    let client_ip = addr.ip().to_string();
+    execute_proxy_request(
+        state,
+        headers,
+        body,
+        &model,
+        action,
         &client_ip,
         "/openai/deployments",
         None,
+        LlmFamily::OpenAi,
+        api_version,
     )
     .await
 }
 
+/// The client's `api-version` is spliced into the upstream URL, so restrict it
+/// to the characters real versions use (`2024-10-21`, `2025-04-01-preview`).
+fn validate_api_version(version: &str) -> Result<String, AppError> {
+    if !version.is_empty()
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    {
+        Ok(version.to_string())
+    } else {
+        Err(AppError::BadRequest(format!(
+            "Invalid api-version '{version}'"
+        )))
+    }
+}
+
 pub async fn handle_claude_messages(
     State(state): State<AppState>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
@@ -533,27 +1521,705 @@ pub async fn handle_claude_messages(
         &client_ip,
         "/v1/messages",
         None,
+        LlmFamily::Claude,
+        None,
     )
     .await
 }
 
-pub async fn handle_gemini_models(
+/// The configured prompt templates with their variables.
+pub async fn list_templates(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    authenticate_client(&state, &headers, &addr.ip().to_string()).await?;
+    let templates: Vec<Value> = state
+        .config
+        .templates
+        .iter()
+        .map(|template| {
+            let variables: Vec<Value> = crate::templates::variables(template)
+                .into_iter()
+                .map(|(name, default)| json!({"name": name, "default": default}))
+                .collect();
+            json!({
+                "name": template.name,
+                "description": template.description,
+                "variables": variables,
+            })
+        })
+        .collect();
+    Ok(Json(json!({ "templates": templates })).into_response())
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct RenderTemplateParams {
+    #[serde(default)]
+    variables: serde_json::Map<String, Value>,
+}
+
+/// Render a prompt template into chat messages. The body
+/// (`{"variables": {...}}`) is optional for templates without required
+/// variables.
+pub async fn render_template(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Response, AppError> {
+    authenticate_client(&state, &headers, &addr.ip().to_string()).await?;
+    let params: RenderTemplateParams = if body.is_empty() {
+        RenderTemplateParams::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| AppError::BadRequest(format!("Invalid request body: {e}")))?
+    };
+    match crate::templates::render(&state.config.templates, &name, &params.variables) {
+        Ok(messages) => Ok(Json(json!({ "template": name, "messages": messages })).into_response()),
+        Err(e @ crate::templates::RenderError::UnknownTemplate(_)) => {
+            Err(AppError::NotFound(e.to_string()))
+        }
+        Err(e) => Err(AppError::BadRequest(e.to_string())),
+    }
+}
+
+/// Create a Message Batch. The batch is registered and returned immediately;
+/// its requests run in a background task (see `batches`).
+pub async fn create_message_batch(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Response, AppError> {
+    let client_ip = addr.ip().to_string();
+    let owner = authenticate_client(&state, &headers, &client_ip).await?;
+    let requests = crate::batches::parse_requests(body).map_err(AppError::BadRequest)?;
+    let batch = state.batches.create(&owner, &requests).await;
+    tracing::info!(
+        "Created message batch {} with {} request(s)",
+        batch.id,
+        requests.len()
+    );
+    tokio::spawn(run_message_batch(
+        state.clone(),
+        batch.id.clone(),
+        batch.expires_at,
+        headers,
+        client_ip,
+        requests,
+    ));
+    Ok(Json(batch).into_response())
+}
+
+pub async fn list_message_batches(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(params): Query<ListParams>,
+) -> Result<Response, AppError> {
+    let owner = authenticate_client(&state, &headers, &addr.ip().to_string()).await?;
+    Ok(Json(state.batches.list(&owner, &params).await).into_response())
+}
+
+pub async fn get_message_batch(
+    State(state): State<AppState>,
+    Path(batch_id): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let owner = authenticate_client(&state, &headers, &addr.ip().to_string()).await?;
+    let batch = state
+        .batches
+        .get(&owner, &batch_id)
+        .await
+        .ok_or_else(|| AppError::NotFound(format!("Message batch '{batch_id}' not found")))?;
+    Ok(Json(batch).into_response())
+}
+
+/// Stream a finished batch's results as JSONL, one line per request.
+pub async fn get_message_batch_results(
+    State(state): State<AppState>,
+    Path(batch_id): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let owner = authenticate_client(&state, &headers, &addr.ip().to_string()).await?;
+    let (batch, body) = state
+        .batches
+        .results(&owner, &batch_id)
+        .await
+        .ok_or_else(|| AppError::NotFound(format!("Message batch '{batch_id}' not found")))?;
+    if batch.results_url.is_none() {
+        return Err(AppError::BadRequest(format!(
+            "Message batch '{batch_id}' is still processing; results are available once it has ended"
+        )));
+    }
+    Ok((
+        [(axum::http::header::CONTENT_TYPE, "application/x-jsonl")],
+        body,
+    )
+        .into_response())
+}
+
+/// Work through a batch's requests with at most `batches.max_concurrency`
+/// in flight process-wide, then mark the batch ended.
+async fn run_message_batch(
+    state: AppState,
+    batch_id: String,
+    expires_at: chrono::DateTime<chrono::Utc>,
+    headers: HeaderMap,
+    client_ip: String,
+    requests: Vec<BatchRequest>,
+) {
+    let headers = std::sync::Arc::new(headers);
+    let client_ip: std::sync::Arc<str> = client_ip.into();
+    let mut tasks = tokio::task::JoinSet::new();
+    for (index, request) in requests.into_iter().enumerate() {
+        let permit = state.batches.acquire_slot().await;
+        let (state, batch_id) = (state.clone(), batch_id.clone());
+        let (headers, client_ip) = (headers.clone(), client_ip.clone());
+        tasks.spawn(async move {
+            let _permit = permit;
+            let outcome = if chrono::Utc::now() >= expires_at {
+                BatchOutcome::Expired
+            } else {
+                execute_batch_request(&state, &headers, &client_ip, request.params).await
+            };
+            state.batches.record(&batch_id, index, outcome).await;
+        });
+    }
+    while let Some(joined) = tasks.join_next().await {
+        if let Err(e) = joined {
+            tracing::error!("Batch {} request task failed: {}", batch_id, e);
+        }
+    }
+    state.batches.finish(&batch_id).await;
+    tracing::info!("Message batch {} ended", batch_id);
+}
+
+/// Run one batch entry through the regular Messages pipeline and fold the
+/// response into a batch outcome.
+async fn execute_batch_request(
+    state: &AppState,
+    headers: &HeaderMap,
+    client_ip: &str,
+    params: Value,
+) -> BatchOutcome {
+    let response = match extract_model_from_body(&params) {
+        Ok(model) => execute_proxy_request(
+            state,
+            headers,
+            params,
+            &model,
+            None,
+            client_ip,
+            BATCH_REQUEST_PATH,
+            None,
+            LlmFamily::Claude,
+            None,
+        )
+        .await
+        .unwrap_or_else(IntoResponse::into_response),
+        Err(e) => e.into_response(),
+    };
+
+    let status = response.status();
+    let body = match axum::body::to_bytes(response.into_body(), usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            let message = format!("Failed to read upstream response: {e}");
+            return BatchOutcome::Errored(crate::transforms::error_shape::anthropic_envelope(
+                StatusCode::BAD_GATEWAY,
+                &message,
+            ));
+        }
+    };
+    if status.is_success()
+        && let Ok(message) = serde_json::from_slice::<Value>(&body)
+    {
+        return BatchOutcome::Succeeded(message);
+    }
+    BatchOutcome::Errored(crate::transforms::error_shape::anthropic_envelope(
+        status,
+        &String::from_utf8_lossy(&body),
+    ))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct RecentParams {
+    limit: Option<usize>,
+}
+
+/// The last upstream attempts, newest first, for debugging without logs.
+pub async fn get_recent_requests(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(params): Query<RecentParams>,
+) -> Result<Response, AppError> {
+    let caller = authenticate_client(&state, &headers, &addr.ip().to_string()).await?;
+    let entries: Vec<_> = state
+        .metrics
+        .recent_requests()
+        .into_iter()
+        .filter(|s| may_access(&caller, s.owner.as_deref()))
+        .take(params.limit.unwrap_or(usize::MAX))
+        .collect();
+    Ok(Json(json!({ "data": entries })).into_response())
+}
+
+/// Deployment conflicts found at the last deployment refresh.
+pub async fn get_deployment_conflicts(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    authenticate_client(&state, &headers, &addr.ip().to_string()).await?;
+    let conflicts = state.model_registry.conflicts().await;
+    Ok(Json(json!({ "data": conflicts })).into_response())
+}
+
+/// Re-resolve one model's deployments now, for the loopback-only "internal"
+/// key only.
+pub async fn refresh_model(
+    State(state): State<AppState>,
+    Path(model): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    require_internal_key(&state, &headers, addr, "Refreshing a model").await?;
+    let deployments = state
+        .model_registry
+        .refresh_model(&model)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Model '{model}' is not configured")))?;
+    Ok(Json(json!({ "model": model, "deployments": deployments })).into_response())
+}
+
+/// Authenticate an admin call that requires the internal key.
+async fn require_internal_key(
+    state: &AppState,
+    headers: &HeaderMap,
+    addr: SocketAddr,
+    action: &str,
+) -> Result<(), AppError> {
+    let caller = authenticate_client(state, headers, &addr.ip().to_string()).await?;
+    if caller != crate::quota::hash_api_key("internal") {
+        return Err(AppError::Forbidden(format!(
+            "{action} requires the internal key"
+        )));
+    }
+    Ok(())
+}
+
+/// Models taken out of service, from config or at runtime.
+pub async fn list_disabled_models(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    require_internal_key(&state, &headers, addr, "Listing disabled models").await?;
+    Ok(Json(json!({ "disabled": state.disabled_models.list() })).into_response())
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct DisableModelParams {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Kill switch: answer requests for a model with 503 until it's enabled
+/// again or acr restarts. The body (`{"reason": ...}`) is optional.
+pub async fn disable_model(
+    State(state): State<AppState>,
+    Path(model): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Response, AppError> {
+    require_internal_key(&state, &headers, addr, "Disabling a model").await?;
+    if state.model_registry.find_model_config(&model).is_none()
+        && !state.model_registry.is_discovered(&model)
+    {
+        return Err(AppError::NotFound(format!(
+            "Model '{model}' is not configured"
+        )));
+    }
+    let params: DisableModelParams = if body.is_empty() {
+        DisableModelParams::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| AppError::BadRequest(format!("Invalid request body: {e}")))?
+    };
+    let reason = params
+        .reason
+        .filter(|r| !r.trim().is_empty())
+        .unwrap_or_else(|| crate::constants::models::DEFAULT_DISABLED_REASON.to_string());
+    tracing::warn!("Model '{}' disabled: {}", model, reason);
+    state.disabled_models.disable(&model, reason);
+    Ok(
+        Json(json!({ "model": model, "disabled": state.disabled_models.get(&model) }))
+            .into_response(),
+    )
+}
+
+/// Put a disabled model back in service.
+pub async fn enable_model(
+    State(state): State<AppState>,
+    Path(model): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    require_internal_key(&state, &headers, addr, "Enabling a model").await?;
+    let previous = state
+        .disabled_models
+        .enable(&model)
+        .ok_or_else(|| AppError::NotFound(format!("Model '{model}' is not disabled")))?;
+    tracing::info!("Model '{}' enabled again", model);
+    Ok(Json(json!({ "model": model, "disabled": null, "previous": previous })).into_response())
+}
+
+/// Stream the request log as CSV or JSON Lines. Keys export their own
+/// requests; the loopback-only "internal" key exports everyone's.
+#[cfg(feature = "db")]
+pub async fn export_usage(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(params): Query<crate::usage_export::ExportParams>,
+) -> Result<Response, AppError> {
+    let caller = authenticate_client(&state, &headers, &addr.ip().to_string()).await?;
+    let database = state
+        .database
+        .clone()
+        .ok_or_else(|| AppError::NotFound("Request logging is not enabled".to_string()))?;
+    let since = match params.since {
+        Some(since) => crate::usage_export::parse_since(&since).ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Invalid 'since' value '{since}'. Expected an RFC 3339 timestamp or YYYY-MM-DD"
+            ))
+        })?,
+        None => String::new(),
+    };
+    let owner = (caller != crate::quota::hash_api_key("internal")).then_some(caller);
+    crate::usage_export::stream(database, state.config.clone(), owner, since, params.format)
+}
+
+/// The dead-letter store, or 404 when capture is disabled.
+fn dead_letter_store(state: &AppState) -> Result<&DeadLetterStore, AppError> {
+    state
+        .dead_letters
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("Dead-letter capture is not enabled".to_string()))
+}
+
+/// Keys see the records (dead letters, recent requests) of their own
+/// requests; the loopback-only "internal" key sees all of them.
+fn may_access(caller: &str, owner: Option<&str>) -> bool {
+    caller == crate::quota::hash_api_key("internal") || owner == Some(caller)
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct LogLevelParams {
+    level: String,
+    /// Return to the configured level after this many seconds.
+    revert_after_secs: Option<u64>,
+}
+
+/// The log level control, for the loopback-only "internal" key only: the
+/// level applies to every request the router logs, not just the caller's.
+async fn log_level_control<'a>(
+    state: &'a AppState,
+    headers: &HeaderMap,
+    addr: SocketAddr,
+) -> Result<&'a crate::log_level::LogLevelControl, AppError> {
+    require_internal_key(state, headers, addr, "Changing the log level").await?;
+    state.log_level.as_ref().ok_or_else(|| {
+        AppError::NotFound("Runtime log level changes are not available".to_string())
+    })
+}
+
+pub async fn get_log_level(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let control = log_level_control(&state, &headers, addr).await?;
+    Ok(Json(json!({
+        "level": control.current().to_string(),
+        "configured": control.configured().to_string(),
+    }))
+    .into_response())
+}
+
+/// Swap the tracing filter without a restart, optionally reverting to the
+/// configured level after `revert_after_secs`.
+pub async fn set_log_level(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(params): Json<LogLevelParams>,
+) -> Result<Response, AppError> {
+    let control = log_level_control(&state, &headers, addr).await?;
+    let level = crate::log_level::parse_level(&params.level).ok_or_else(|| {
+        AppError::BadRequest(format!(
+            "Invalid log level '{}'. Valid options: trace, debug, info, warn, error, off",
+            params.level
+        ))
+    })?;
+    let revert_after = params
+        .revert_after_secs
+        .filter(|&secs| secs > 0)
+        .map(std::time::Duration::from_secs);
+    tracing::info!(
+        "Log level changed to {}{}",
+        level,
+        revert_after
+            .map(|d| format!(" (reverting in {}s)", d.as_secs()))
+            .unwrap_or_default()
+    );
+    let previous = control.set(level, revert_after)?;
+    Ok(Json(json!({
+        "level": level.to_string(),
+        "previous": previous.to_string(),
+        "configured": control.configured().to_string(),
+        "revert_after_secs": revert_after.map(|d| d.as_secs()),
+    }))
+    .into_response())
+}
+
+pub async fn list_dead_letters(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let caller = authenticate_client(&state, &headers, &addr.ip().to_string()).await?;
+    let entries: Vec<DeadLetter> = dead_letter_store(&state)?
+        .list()
+        .await?
+        .into_iter()
+        .filter(|e| may_access(&caller, e.owner.as_deref()))
+        .collect();
+    Ok(Json(json!({ "data": entries })).into_response())
+}
+
+pub async fn get_dead_letter(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let caller = authenticate_client(&state, &headers, &addr.ip().to_string()).await?;
+    let entry = dead_letter_store(&state)?
+        .get(&id)
+        .await?
+        .filter(|e| may_access(&caller, e.owner.as_deref()))
+        .ok_or_else(|| AppError::NotFound(format!("Dead letter '{id}' not found")))?;
+    Ok(Json(entry).into_response())
+}
+
+/// Re-run a dead letter through the proxy as the calling key and return the
+/// upstream response as-is. The outcome is appended to the entry; a replay
+/// that fails again is not captured a second time.
+pub async fn replay_dead_letter(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let client_ip = addr.ip().to_string();
+    let caller = authenticate_client(&state, &headers, &client_ip).await?;
+    let store = dead_letter_store(&state)?;
+    let entry = store
+        .get(&id)
+        .await?
+        .filter(|e| may_access(&caller, e.owner.as_deref()))
+        .ok_or_else(|| AppError::NotFound(format!("Dead letter '{id}' not found")))?;
+
+    let mut replay_headers = headers.clone();
+    for (name, value) in &entry.request.headers {
+        if let (Ok(name), Ok(value)) = (
+            axum::http::HeaderName::from_bytes(name.as_bytes()),
+            axum::http::HeaderValue::from_str(value),
+        ) {
+            replay_headers.insert(name, value);
+        }
+    }
+
+    let request = entry.request;
+    let response = forward_proxy_request(
+        &state,
+        &replay_headers,
+        request.body,
+        &request.model,
+        request.action,
+        &client_ip,
+        &request.path,
+        request.force_family,
+        request.client_family,
+        request.api_version,
+    )
+    .await
+    .unwrap_or_else(IntoResponse::into_response);
+
+    let status = response.status();
+    tracing::info!("Replayed dead letter {}: {}", id, status);
+    if let Err(e) = store.record_replay(&id, status.as_u16()).await {
+        tracing::warn!("Failed to record replay of dead letter {}: {:#}", id, e);
+    }
+    Ok(response)
+}
+
+pub async fn handle_gemini_models(
+    State(state): State<AppState>,
+    Path(model_operation): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Response, AppError> {
+    proxy_gemini_operation(
+        &state,
+        &model_operation,
+        addr,
+        &headers,
+        body,
+        "/gemini/models",
+    )
+    .await
+}
+
+/// Vertex AI publisher-model paths
+/// (`/v1/projects/{p}/locations/{l}/publishers/google/models/{model}:{action}`),
+/// so clients built against Vertex only need a base-URL change. The project
+/// and location segments are accepted and ignored — AI Core's deployment for
+/// the model decides where the request actually runs.
+pub async fn handle_vertex_models(
+    State(state): State<AppState>,
+    Path((_project, _location, model_operation)): Path<(String, String, String)>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Response, AppError> {
+    proxy_gemini_operation(
+        &state,
+        &model_operation,
+        addr,
+        &headers,
+        body,
+        "/vertex/publishers/google/models",
+    )
+    .await
+}
+
+async fn proxy_gemini_operation(
+    state: &AppState,
+    model_operation: &str,
+    addr: SocketAddr,
+    headers: &HeaderMap,
+    body: Value,
+    request_path: &str,
+) -> Result<Response, AppError> {
+    let (model, action) = parse_model_operation(model_operation)?;
+    let client_ip = addr.ip().to_string();
+    execute_proxy_request(
+        state,
+        headers,
+        body,
+        &model,
+        Some(action),
+        &client_ip,
+        request_path,
+        None,
+        LlmFamily::Gemini,
+        None,
+    )
+    .await
+}
+
+/// Gemini `cachedContents.create`; see `cached_content`.
+pub async fn create_cached_content(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Result<Response, AppError> {
+    let client_ip = addr.ip().to_string();
+    let owner = authenticate_client(&state, &headers, &client_ip).await?;
+    let api_key = extract_api_key(&headers).ok_or(AppError::MissingApiKey)?;
+    if let Some(model) = body.get("model").and_then(Value::as_str) {
+        check_model_allowed(&state, &api_key, model.rsplit('/').next().unwrap_or(model))?;
+    }
+    crate::cached_content::create(&state, &api_key, &owner, body).await
+}
+
+pub async fn get_cached_content(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    forward_cached_content(&state, &id, addr, &headers, Method::GET).await
+}
+
+pub async fn delete_cached_content(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    forward_cached_content(&state, &id, addr, &headers, Method::DELETE).await
+}
+
+/// Send a get or delete to the deployment holding cache `id`. Caches are
+/// visible only to the key that created them (and the "internal" key).
+async fn forward_cached_content(
+    state: &AppState,
+    id: &str,
+    addr: SocketAddr,
+    headers: &HeaderMap,
+    method: Method,
+) -> Result<Response, AppError> {
+    let caller = authenticate_client(state, headers, &addr.ip().to_string()).await?;
+    let api_key = extract_api_key(headers).ok_or(AppError::MissingApiKey)?;
+    let entry = state
+        .cached_contents
+        .get(id)
+        .filter(|entry| may_access(&caller, Some(&entry.owner)))
+        .ok_or_else(|| AppError::NotFound(format!("Cached content '{id}' not found")))?;
+    crate::cached_content::forward(state, &api_key, &entry, id, method).await
+}
+
+/// Google's OpenAI-compatibility surface (`/v1beta/openai/chat/completions`),
+/// so tools configured for Gemini's OpenAI layer only need a base-URL change.
+/// The body is OpenAI Chat Completions; for Gemini models it is translated to
+/// `generateContent` by `transforms::openai_gemini` (selected because the
+/// client family is OpenAI and the upstream is Gemini). Google's surface also
+/// accepts resource-style names (`models/gemini-2.5-flash`), so that prefix is
+/// stripped before resolution.
+pub async fn handle_gemini_openai_compat(
     State(state): State<AppState>,
-    Path(model_operation): Path<String>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-    Json(body): Json<Value>,
+    Json(mut body): Json<Value>,
 ) -> Result<Response, AppError> {
-    let (model, action) = parse_model_operation(&model_operation)?;
+    let model = extract_model_from_body(&body)?;
+    let model = model.strip_prefix("models/").unwrap_or(&model).to_string();
+    if let Some(obj) = body.as_object_mut() {
+        obj.insert("model".to_string(), json!(model));
+    }
     let client_ip = addr.ip().to_string();
     execute_proxy_request(
         &state,
         &headers,
         body,
         &model,
-        Some(action),
+        None,
         &client_ip,
-        "/gemini/models",
+        "/v1beta/openai/chat/completions",
+        None,
+        LlmFamily::OpenAi,
         None,
     )
     .await
@@ -563,25 +2229,53 @@ pub async fn handle_gemini_models(
 pub enum AppError {
     #[error("Bad request: {0}")]
     BadRequest(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Unsupported media type: {0}")]
+    UnsupportedMediaType(String),
     #[error("API key not found in headers")]
     MissingApiKey,
     #[error("Invalid API key")]
     InvalidApiKey,
+    #[error("Invalid request signature: {0}")]
+    InvalidSignature(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("API key is not allowed to use model '{model}'")]
+    ModelNotAllowed { model: String, allowed: Vec<String> },
     #[error("Model '{model}' not available on provider '{provider}'")]
     ModelNotAvailableOnProvider { model: String, provider: String },
-    #[error("Rate limited by provider: {0}")]
-    RateLimited(String),
+    #[error("Rate limited by provider: {provider}")]
+    RateLimited {
+        provider: String,
+        retry_after_secs: Option<u64>,
+    },
     #[error("All providers are rate limited")]
-    AllProvidersRateLimited,
+    AllProvidersRateLimited {
+        retry_after_secs: Option<u64>,
+        providers_tried: Vec<String>,
+    },
+    #[error("Upstream provider '{provider}' timed out")]
+    UpstreamTimeout { provider: String },
+    #[error("Upstream provider '{provider}' is unreachable")]
+    UpstreamUnavailable { provider: String },
+    #[error("Request timed out after {timeout_secs}s")]
+    RequestTimeout { timeout_secs: u64 },
     #[error("Too many failed authentication attempts")]
     RateLimitedAuth { retry_after_secs: u64 },
+    #[error("Too many open streams: {0}")]
+    TooManyStreams(crate::stream_limit::StreamCap),
     #[error("Per-key request rate limit exceeded")]
-    RateLimitedRequests { retry_after_secs: u64 },
+    RateLimitedRequests { retry_after_secs: u64, limit: u32 },
     #[error("Token quota exceeded ({limit_type} limit)")]
     QuotaExceeded {
         retry_after_secs: u64,
         limit_type: crate::quota::LimitType,
     },
+    #[error("Server overloaded ({reason})")]
+    Overloaded { priority: Priority, reason: Shed },
+    #[error("Model '{model}' is disabled: {reason}")]
+    ModelDisabled { model: String, reason: String },
     #[error("Internal server error")]
     Internal(#[from] anyhow::Error),
 }
@@ -590,20 +2284,37 @@ impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, message) = match &self {
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
+            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
+            AppError::UnsupportedMediaType(msg) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, msg.clone())
+            }
             AppError::MissingApiKey => (
                 StatusCode::UNAUTHORIZED,
                 "API key not found in headers".to_string(),
             ),
             AppError::InvalidApiKey => (StatusCode::UNAUTHORIZED, "Invalid API key".to_string()),
+            AppError::InvalidSignature(reason) => (
+                StatusCode::UNAUTHORIZED,
+                format!("Invalid request signature: {reason}"),
+            ),
+            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg.clone()),
+            AppError::ModelNotAllowed { model, allowed } => (
+                StatusCode::FORBIDDEN,
+                format!(
+                    "API key is not allowed to use model '{}'. Allowed models: {}",
+                    model,
+                    allowed.join(", ")
+                ),
+            ),
             AppError::ModelNotAvailableOnProvider { model, provider } => (
                 StatusCode::BAD_REQUEST,
                 format!("Model '{}' not available on provider '{}'", model, provider),
             ),
-            AppError::RateLimited(provider) => (
+            AppError::RateLimited { provider, .. } => (
                 StatusCode::TOO_MANY_REQUESTS,
                 format!("Rate limited by provider: {}", provider),
             ),
-            AppError::AllProvidersRateLimited => (
+            AppError::AllProvidersRateLimited { .. } => (
                 StatusCode::TOO_MANY_REQUESTS,
                 "All providers are rate limited. Please try again later.".to_string(),
             ),
@@ -614,13 +2325,22 @@ impl IntoResponse for AppError {
                     retry_after_secs
                 ),
             ),
-            AppError::RateLimitedRequests { retry_after_secs } => (
+            AppError::RateLimitedRequests {
+                retry_after_secs, ..
+            } => (
                 StatusCode::TOO_MANY_REQUESTS,
                 format!(
                     "Per-key request rate limit exceeded. Retry after {} seconds.",
                     retry_after_secs
                 ),
             ),
+            AppError::TooManyStreams(cap) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                format!(
+                    "Too many open streams: limit of {}. Close a stream or retry later.",
+                    cap
+                ),
+            ),
             AppError::QuotaExceeded {
                 retry_after_secs,
                 limit_type,
@@ -631,6 +2351,32 @@ impl IntoResponse for AppError {
                     limit_type, retry_after_secs
                 ),
             ),
+            AppError::Overloaded { priority, reason } => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!(
+                    "Server overloaded: {} priority request shed ({})",
+                    priority, reason
+                ),
+            ),
+            AppError::ModelDisabled { model, reason } => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!(
+                    "Model '{}' is temporarily disabled: {}. Other models are unaffected.",
+                    model, reason
+                ),
+            ),
+            AppError::UpstreamTimeout { provider } => (
+                StatusCode::GATEWAY_TIMEOUT,
+                format!("Upstream provider '{}' timed out", provider),
+            ),
+            AppError::UpstreamUnavailable { provider } => (
+                StatusCode::BAD_GATEWAY,
+                format!("Upstream provider '{}' is unreachable", provider),
+            ),
+            AppError::RequestTimeout { timeout_secs } => (
+                StatusCode::GATEWAY_TIMEOUT,
+                format!("Request timed out after {}s", timeout_secs),
+            ),
             AppError::Internal(err) => {
                 tracing::error!("Internal error: {}", err);
                 (
@@ -640,14 +2386,43 @@ impl IntoResponse for AppError {
             }
         };
 
-        let mut response = (status, Json(json!({ "error": message }))).into_response();
+        // Provider names are operator-chosen labels from config, so they are
+        // safe to echo. Upstream URLs and raw transport errors are not — they
+        // stay in the server log only.
+        let provider = match &self {
+            AppError::RateLimited { provider, .. }
+            | AppError::UpstreamTimeout { provider }
+            | AppError::UpstreamUnavailable { provider } => Some(provider.as_str()),
+            _ => None,
+        };
+        let body = match (&self, provider) {
+            (_, Some(provider)) => json!({ "error": message, "provider": provider }),
+            (AppError::RequestTimeout { timeout_secs }, None) => {
+                json!({ "error": message, "timeout_secs": timeout_secs })
+            }
+            (AppError::ModelDisabled { model, .. }, None) => {
+                json!({ "error": message, "model": model })
+            }
+            (_, None) => json!({ "error": message }),
+        };
+        let mut response = (status, Json(body)).into_response();
 
         let retry_after = match &self {
             AppError::RateLimitedAuth { retry_after_secs }
-            | AppError::RateLimitedRequests { retry_after_secs }
+            | AppError::RateLimitedRequests {
+                retry_after_secs, ..
+            }
             | AppError::QuotaExceeded {
                 retry_after_secs, ..
             } => Some(*retry_after_secs),
+            AppError::Overloaded { .. } => Some(crate::constants::admission::SHED_RETRY_AFTER_SECS),
+            AppError::TooManyStreams(_) => Some(crate::constants::streams::CAP_RETRY_AFTER_SECS),
+            AppError::RateLimited {
+                retry_after_secs, ..
+            }
+            | AppError::AllProvidersRateLimited {
+                retry_after_secs, ..
+            } => *retry_after_secs,
             _ => None,
         };
         if let Some(secs) = retry_after
@@ -656,13 +2431,211 @@ impl IntoResponse for AppError {
             response.headers_mut().insert("retry-after", val);
         }
 
+        if let AppError::RateLimitedRequests {
+            retry_after_secs,
+            limit,
+        } = &self
+        {
+            crate::request_limiter::RequestRate {
+                limit: *limit,
+                remaining: 0,
+                reset: std::time::Duration::from_secs(*retry_after_secs),
+            }
+            .insert_headers(response.headers_mut());
+        }
+
+        if let AppError::QuotaExceeded {
+            retry_after_secs,
+            limit_type,
+        } = &self
+        {
+            QuotaCheckResult::Exceeded {
+                retry_after_secs: *retry_after_secs,
+                limit_type: *limit_type,
+            }
+            .insert_headers(response.headers_mut());
+        }
+
+        if let AppError::AllProvidersRateLimited {
+            providers_tried, ..
+        } = &self
+            && !providers_tried.is_empty()
+            && let Ok(val) = axum::http::HeaderValue::from_str(&providers_tried.join(","))
+        {
+            response
+                .headers_mut()
+                .insert(crate::constants::api::ACR_PROVIDERS_TRIED_HEADER, val);
+        }
+
         response
     }
 }
 
+/// State for `config` with no resolved models and every optional feature
+/// off, for tests that serve requests in-process.
+#[cfg(test)]
+pub(crate) fn state_for_tests(config: Config) -> AppState {
+    let token_manager = TokenManager::from_api_keys(&config.api_keys);
+    AppState {
+        model_registry: ModelRegistry::new(
+            config.models.clone(),
+            config.fallback_models.clone(),
+            config.providers.clone(),
+            token_manager.clone(),
+            config.refresh_interval_secs,
+        ),
+        load_balancer: LoadBalancer::new(config.providers.clone(), config.load_balancing.clone())
+            .unwrap(),
+        token_manager,
+        client: reqwest::Client::new(),
+        metrics: MetricsService::new(),
+        #[cfg(feature = "db")]
+        database: None,
+        rate_limiter: AuthRateLimiter::new(),
+        quota_manager: None,
+        request_limiter: None,
+        batches: BatchStore::in_memory(2),
+        session_affinity: SessionAffinity::default(),
+        cached_contents: Default::default(),
+        admission: None,
+        stream_limiter: None,
+        output_pacer: None,
+        retry_budget: None,
+        dead_letters: None,
+        image_fetcher: None,
+        response_cache: None,
+        slo: None,
+        upstream_limits: None,
+        tenant_quotas: None,
+        deployment_health: None,
+        log_level: None,
+        warmup: None,
+        hmac_auth: None,
+        disabled_models: crate::model_switch::DisabledModels::from_config(&config.models),
+        config,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tower::ServiceExt;
+
+    /// Router wired to an unreachable provider with no resolved models — enough
+    /// to exercise routing and the pre-upstream request checks in-process.
+    fn test_router() -> Router {
+        create_router(test_state())
+    }
+
+    fn test_state() -> AppState {
+        let config: Config = serde_yaml_ng::from_str(
+            r#"
+providers:
+  - name: primary
+    uaa_token_url: http://127.0.0.1:9/oauth/token
+    uaa_client_id: id
+    uaa_client_secret: secret
+    genai_api_url: http://127.0.0.1:9
+api_keys:
+  - key: test-key
+  - key: intern-key
+    allowed_models: [gpt-4.1-mini]
+    model_aliases: {default: gpt-4.1-mini, big: gpt-4o}
+  - key: team-key
+    tenant: team-a
+    model_aliases: {default: claude-sonnet-4-6}
+tenants:
+  - name: team-a
+    models: ["claude-*"]
+models:
+  - name: gpt-4o
+  - name: gpt-4.1-mini
+    disabled: returns truncated answers
+templates:
+  - name: greet
+    messages:
+      - role: user
+        content: "Say hello to {{name}}."
+"#,
+        )
+        .unwrap();
+        state_for_tests(config)
+    }
+
+    async fn post_json(
+        router: Router,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: Value,
+    ) -> Response {
+        let mut builder = axum::http::Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header("content-type", "application/json");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let mut request = builder
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4242))));
+        router.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn vertex_publisher_paths_reach_gemini_handler() {
+        for prefix in ["/v1", "/v1beta1"] {
+            let uri = format!(
+                "{prefix}/projects/my-proj/locations/us-central1/publishers/google/models/gemini-2.5-flash:generateContent"
+            );
+            // No API key: reaching the handler yields 401, an unmatched route 404.
+            let response = post_json(test_router(), &uri, &[], json!({"contents": []})).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn azure_embeddings_and_completions_paths_are_routed() {
+        for path in ["embedding", "embeddings", "completions", "chat/completions"] {
+            let uri = format!("/openai/deployments/gpt-4o/{path}?api-version=2024-10-21");
+            let response = post_json(test_router(), &uri, &[], json!({"input": "x"})).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn azure_route_rejects_unsafe_api_version() {
+        let response = post_json(
+            test_router(),
+            "/openai/deployments/gpt-4o/chat/completions?api-version=2024%26evil%3D1",
+            &[],
+            json!({"messages": []}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn validate_api_version_accepts_real_versions() {
+        assert_eq!(validate_api_version("2024-10-21").unwrap(), "2024-10-21");
+        assert!(validate_api_version("2025-04-01-preview").is_ok());
+        assert!(validate_api_version("").is_err());
+        assert!(validate_api_version("v1/../x").is_err());
+    }
+
+    #[tokio::test]
+    async fn vertex_path_rejects_malformed_model_operation() {
+        let response = post_json(
+            test_router(),
+            "/v1/projects/p/locations/l/publishers/google/models/gemini-2.5-flash",
+            &[],
+            json!({"contents": []}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
 
     #[test]
     fn parse_model_operation_accepts_well_formed_input() {
@@ -689,4 +2662,758 @@ mod tests {
         // ambiguously assigning it to the model or action.
         assert!(parse_model_operation("foo:bar:baz").is_err());
     }
+
+    async fn body_json(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn classify_upstream_error_maps_connection_refused_to_bad_gateway() {
+        // Bind then drop to get a loopback port with nothing listening.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let err = reqwest::Client::new()
+            .get(format!("http://127.0.0.1:{port}/"))
+            .send()
+            .await
+            .unwrap_err();
+        let app_err = classify_upstream_error(
+            anyhow::Error::new(err).context("Failed to send proxy request"),
+            "primary",
+        );
+        assert!(matches!(
+            app_err,
+            AppError::UpstreamUnavailable { ref provider } if provider == "primary"
+        ));
+    }
+
+    #[tokio::test]
+    async fn classify_upstream_error_maps_timeout_to_gateway_timeout() {
+        // Accepts the connection but never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _hold = tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        let err = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_millis(100))
+            .build()
+            .unwrap()
+            .get(format!("http://{addr}/"))
+            .send()
+            .await
+            .unwrap_err();
+        let app_err = classify_upstream_error(anyhow::Error::new(err), "secondary");
+        assert!(matches!(
+            app_err,
+            AppError::UpstreamTimeout { ref provider } if provider == "secondary"
+        ));
+    }
+
+    /// An AI Core stand-in with one running gpt-4o deployment, answering
+    /// inference requests with `status`.
+    async fn upstream(status: StatusCode, body: Value) -> (String, tokio::task::JoinHandle<()>) {
+        let app = Router::new()
+            .route(
+                "/oauth/token",
+                post(|| async { Json(json!({"access_token": "tok", "expires_in": 3600})) }),
+            )
+            .route(
+                "/v2/lm/deployments",
+                get(|| async {
+                    Json(json!({"count": 1, "resources": [{
+                        "id": "d1",
+                        "createdAt": "2025-01-01T00:00:00Z",
+                        "modifiedAt": "2025-01-01T00:00:00Z",
+                        "status": "RUNNING",
+                        "scenarioId": "foundation-models",
+                        "configurationId": "c1",
+                        "details": {"resources": {"backendDetails": {
+                            "model": {"name": "gpt-4o", "version": "1"}
+                        }}}
+                    }]}))
+                }),
+            )
+            .fallback(move || async move { (status, Json(body)) });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn all_providers_rate_limited_only_when_every_attempt_got_429() {
+        async fn outcome(first: Option<StatusCode>, second: StatusCode) -> StatusCode {
+            let (first_url, first_server) =
+                upstream(first.unwrap_or(StatusCode::TOO_MANY_REQUESTS), json!({})).await;
+            let (second_url, _second_server) = upstream(second, json!({})).await;
+            let config: Config = serde_yaml_ng::from_str(&format!(
+                r#"
+providers:
+  - name: first
+    uaa_token_url: {first_url}/oauth/token
+    uaa_client_id: first
+    uaa_client_secret: secret
+    genai_api_url: {first_url}
+  - name: second
+    uaa_token_url: {second_url}/oauth/token
+    uaa_client_id: second
+    uaa_client_secret: secret
+    genai_api_url: {second_url}
+load_balancing: fallback
+api_keys:
+  - key: test-key
+models:
+  - name: gpt-4o
+"#
+            ))
+            .unwrap();
+            let state = state_for_tests(config);
+            state.model_registry.refresh().await.unwrap();
+            // With no status, the first provider is gone by the time the
+            // request is sent.
+            if first.is_none() {
+                first_server.abort();
+                let _ = first_server.await;
+            }
+            post_json(
+                create_router(state),
+                "/v1/chat/completions",
+                &[("x-api-key", "test-key")],
+                json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "hi"}]}),
+            )
+            .await
+            .status()
+        }
+
+        let rate_limited = StatusCode::TOO_MANY_REQUESTS;
+        assert_eq!(
+            outcome(Some(rate_limited), rate_limited).await,
+            rate_limited
+        );
+        assert_eq!(outcome(None, rate_limited).await, StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn cached_responses_need_a_valid_key() {
+        let answer = json!({"id": "chatcmpl-1", "object": "chat.completion", "choices": []});
+        let (url, _server) = upstream(StatusCode::OK, answer).await;
+        let config: Config = serde_yaml_ng::from_str(&format!(
+            r#"
+providers:
+  - name: primary
+    uaa_token_url: {url}/oauth/token
+    uaa_client_id: id
+    uaa_client_secret: secret
+    genai_api_url: {url}
+api_keys:
+  - key: test-key
+  - key: other-key
+models:
+  - name: gpt-4o
+response_cache:
+  enabled: true
+"#
+        ))
+        .unwrap();
+        let mut state = state_for_tests(config);
+        state.response_cache =
+            crate::response_cache::ResponseCache::open(&state.config.response_cache).unwrap();
+        state.model_registry.refresh().await.unwrap();
+        let request = |state: AppState| {
+            post_json(
+                create_router(state),
+                "/v1/chat/completions",
+                &[("x-api-key", "test-key")],
+                json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "hi"}]}),
+            )
+        };
+
+        let response = request(state.clone()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-acr-cache"], "miss");
+        assert_eq!(request(state.clone()).await.headers()["x-acr-cache"], "hit");
+
+        // Revoked, as by a reload of the keys file.
+        let remaining = &state.config.api_keys[1..];
+        let revoked = AppState {
+            token_manager: state
+                .token_manager
+                .reconfigure(remaining, &state.config.providers)
+                .await,
+            ..state
+        };
+        let response = request(revoked).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().get("x-acr-cache").is_none());
+    }
+
+    #[test]
+    fn classify_upstream_error_keeps_non_transport_errors_internal() {
+        let app_err = classify_upstream_error(anyhow::anyhow!("bad header value"), "primary");
+        assert!(matches!(app_err, AppError::Internal(_)));
+    }
+
+    #[tokio::test]
+    async fn upstream_timeout_renders_504_with_provider() {
+        let response = AppError::UpstreamTimeout {
+            provider: "primary".to_string(),
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let body = body_json(response).await;
+        assert_eq!(body["provider"], json!("primary"));
+        assert_eq!(
+            body["error"],
+            json!("Upstream provider 'primary' timed out")
+        );
+    }
+
+    #[tokio::test]
+    async fn upstream_unavailable_renders_502() {
+        let response = AppError::UpstreamUnavailable {
+            provider: "primary".to_string(),
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn all_providers_rate_limited_preserves_upstream_retry_after() {
+        let response = AppError::AllProvidersRateLimited {
+            retry_after_secs: Some(12),
+            providers_tried: vec!["primary".to_string()],
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "12");
+
+        let response = AppError::AllProvidersRateLimited {
+            retry_after_secs: None,
+            providers_tried: vec!["primary".to_string()],
+        }
+        .into_response();
+        assert!(response.headers().get("retry-after").is_none());
+    }
+
+    #[tokio::test]
+    async fn all_providers_rate_limited_lists_providers_tried() {
+        let response = AppError::AllProvidersRateLimited {
+            retry_after_secs: Some(3),
+            providers_tried: vec!["primary".to_string(), "secondary".to_string()],
+        }
+        .into_response();
+        assert_eq!(
+            response.headers()["x-acr-providers-tried"],
+            "primary,secondary"
+        );
+    }
+
+    #[tokio::test]
+    async fn all_providers_rate_limited_omits_empty_providers_header() {
+        let response = AppError::AllProvidersRateLimited {
+            retry_after_secs: None,
+            providers_tried: vec![],
+        }
+        .into_response();
+        assert!(response.headers().get("x-acr-providers-tried").is_none());
+    }
+
+    async fn get_with_key(router: Router, uri: &str, api_key: Option<&str>) -> Response {
+        let mut builder = axum::http::Request::builder().method(Method::GET).uri(uri);
+        if let Some(key) = api_key {
+            builder = builder.header("x-api-key", key);
+        }
+        let mut request = builder.body(axum::body::Body::empty()).unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4242))));
+        router.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn message_batch_endpoints_require_a_valid_key() {
+        let router = test_router();
+        let batch = json!({"requests": [{"custom_id": "a", "params": {"model": "m"}}]});
+        let response = post_json(router.clone(), "/v1/messages/batches", &[], batch.clone()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = post_json(
+            router.clone(),
+            "/v1/messages/batches",
+            &[("x-api-key", "wrong")],
+            batch,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = get_with_key(router, "/v1/messages/batches", None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn cached_contents_are_routed_and_private_to_their_creator() {
+        let response = get_with_key(test_router(), "/v1beta/cachedContents/abc", None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        for prefix in ["/v1beta", "/gemini", "/gemini/v1beta"] {
+            let uri = format!("{prefix}/cachedContents/abc");
+            let response = get_with_key(test_router(), &uri, Some("test-key")).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+        }
+        let response = post_json(
+            test_router(),
+            "/v1beta/cachedContents",
+            &[("x-api-key", "test-key")],
+            json!({"contents": []}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn unknown_message_batch_is_not_found() {
+        let response = get_with_key(
+            test_router(),
+            "/v1/messages/batches/msgbatch_missing",
+            Some("test-key"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn message_batch_runs_to_completion_with_errored_results() {
+        // Nothing is deployed on the test provider, so every request fails —
+        // the batch must still end with one errored result per request.
+        let router = test_router();
+        let response = post_json(
+            router.clone(),
+            "/anthropic/v1/messages/batches",
+            &[("x-api-key", "test-key")],
+            json!({"requests": [
+                {"custom_id": "one", "params": {"model": "claude-sonnet-4-5", "max_tokens": 8, "messages": []}},
+                {"custom_id": "two", "params": {"model": "claude-sonnet-4-5", "max_tokens": 8, "messages": []}},
+            ]}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let created = body_json(response).await;
+        assert_eq!(created["type"], json!("message_batch"));
+        let id = created["id"].as_str().unwrap().to_string();
+        assert!(id.starts_with("msgbatch_"));
+
+        let mut batch = created;
+        for _ in 0..200 {
+            let response = get_with_key(
+                router.clone(),
+                &format!("/v1/messages/batches/{id}"),
+                Some("test-key"),
+            )
+            .await;
+            batch = body_json(response).await;
+            if batch["processing_status"] == json!("ended") {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+        }
+        assert_eq!(batch["processing_status"], json!("ended"));
+        assert_eq!(batch["request_counts"]["errored"], json!(2));
+
+        let response = get_with_key(
+            router.clone(),
+            batch["results_url"].as_str().unwrap(),
+            Some("test-key"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let lines: Vec<Value> = String::from_utf8(bytes.to_vec())
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["custom_id"], json!("one"));
+        assert_eq!(lines[0]["result"]["type"], json!("errored"));
+        assert_eq!(lines[0]["result"]["error"]["type"], json!("error"));
+
+        let listed =
+            body_json(get_with_key(router, "/v1/messages/batches", Some("test-key")).await).await;
+        assert_eq!(listed["data"][0]["id"], json!(id));
+    }
+
+    #[tokio::test]
+    async fn internal_error_body_omits_provider() {
+        let response = AppError::Internal(anyhow::anyhow!("boom")).into_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = body_json(response).await;
+        assert!(body.get("provider").is_none());
+    }
+
+    #[tokio::test]
+    async fn dry_runs_leave_the_request_rate_alone() {
+        let mut state = test_state();
+        let quotas = crate::config::QuotaConfig {
+            requests_per_minute: Some(1),
+            ..Default::default()
+        };
+        state.request_limiter =
+            RequestLimiter::from_config(&state.config.api_keys, &quotas).map(std::sync::Arc::new);
+        let router = create_router(state);
+        let request = |headers: &'static [(&'static str, &'static str)]| {
+            post_json(
+                router.clone(),
+                "/v1/chat/completions",
+                headers,
+                json!({"model": "gpt-4o", "messages": []}),
+            )
+        };
+        let dry_run = &[("x-api-key", "test-key"), ("x-acr-dry-run", "true")];
+
+        for _ in 0..3 {
+            let response = request(dry_run).await;
+            assert_ne!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+        let response = request(&[("x-api-key", "test-key")]).await;
+        assert_ne!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let response = request(&[("x-api-key", "test-key")]).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn allowed_models_rejects_other_models_with_403() {
+        let response = post_json(
+            test_router(),
+            "/v1/chat/completions",
+            &[("x-api-key", "intern-key")],
+            json!({"model": "gpt-4o", "messages": []}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = body_json(response).await;
+        let message = body["error"].as_str().unwrap();
+        assert!(message.contains("'gpt-4o'"), "{message}");
+        assert!(message.contains("gpt-4.1-mini"), "{message}");
+
+        // The allowed model gets past the check (and fails upstream instead).
+        let response = post_json(
+            test_router(),
+            "/v1/chat/completions",
+            &[("x-api-key", "intern-key")],
+            json!({"model": "gpt-4.1-mini", "messages": []}),
+        )
+        .await;
+        assert_ne!(response.status(), StatusCode::FORBIDDEN);
+
+        // Keys without an allowlist are unrestricted.
+        let response = post_json(
+            test_router(),
+            "/v1/chat/completions",
+            &[("x-api-key", "test-key")],
+            json!({"model": "gpt-4o", "messages": []}),
+        )
+        .await;
+        assert_ne!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn key_model_aliases_apply_before_the_allowlists() {
+        // The same name reaches a model each key is allowed to use.
+        for (key, route) in [
+            ("intern-key", "/v1/chat/completions"),
+            ("team-key", "/v1/messages"),
+        ] {
+            let response = post_json(
+                test_router(),
+                route,
+                &[("x-api-key", key)],
+                json!({"model": "default", "messages": []}),
+            )
+            .await;
+            assert_ne!(response.status(), StatusCode::FORBIDDEN, "{key}");
+        }
+
+        // An alias can't step around the key's own allowlist.
+        let response = post_json(
+            test_router(),
+            "/v1/chat/completions",
+            &[("x-api-key", "intern-key")],
+            json!({"model": "big", "messages": []}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let message = body_json(response).await["error"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(message.contains("'gpt-4o'"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn templates_render_and_expand_inline() {
+        let router = test_router();
+        let response = post_json(
+            router.clone(),
+            "/v1/templates/greet/render",
+            &[("x-api-key", "test-key")],
+            json!({"variables": {"name": "Ada"}}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_json(response).await["messages"],
+            json!([{"role": "user", "content": "Say hello to Ada."}])
+        );
+
+        let response = post_json(
+            router.clone(),
+            "/v1/templates/greet/render",
+            &[("x-api-key", "test-key")],
+            json!({}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = post_json(
+            router.clone(),
+            "/v1/templates/farewell/render",
+            &[("x-api-key", "test-key")],
+            json!({}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response =
+            post_json(router.clone(), "/v1/templates/greet/render", &[], json!({})).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // An inline reference is checked before the request goes anywhere.
+        let response = post_json(
+            router,
+            "/v1/chat/completions",
+            &[("x-api-key", "test-key")],
+            json!({"model": "gpt-4o", "template": {"name": "greet"}, "messages": []}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(
+            body_json(response).await["error"]
+                .as_str()
+                .unwrap()
+                .contains("Missing template variables: name")
+        );
+    }
+
+    #[tokio::test]
+    async fn disabled_models_get_503_until_enabled() {
+        let router = test_router();
+        let chat = |router: Router, model: &str| {
+            let body = json!({"model": model, "messages": []});
+            async move {
+                post_json(
+                    router,
+                    "/v1/chat/completions",
+                    &[("x-api-key", "test-key")],
+                    body,
+                )
+                .await
+            }
+        };
+        let admin = |router: Router, method: Method, key: &str| {
+            let request = axum::http::Request::builder()
+                .method(method)
+                .uri("/admin/models/gpt-4.1-mini/disabled")
+                .header("x-api-key", key)
+                .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4242))))
+                .body(axum::body::Body::empty())
+                .unwrap();
+            router.oneshot(request)
+        };
+
+        // Disabled in config.
+        let response = chat(router.clone(), "gpt-4.1-mini").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = body_json(response).await;
+        assert_eq!(body["model"], "gpt-4.1-mini");
+        assert!(
+            body["error"]
+                .as_str()
+                .unwrap()
+                .contains("returns truncated answers"),
+            "{body}"
+        );
+        let response = chat(router.clone(), "gpt-4o").await;
+        assert_ne!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Only the internal key flips the switch.
+        let response = admin(router.clone(), Method::DELETE, "test-key")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = admin(router.clone(), Method::DELETE, "internal")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = chat(router.clone(), "gpt-4.1-mini").await;
+        assert_ne!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = admin(router.clone(), Method::PUT, "internal")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_json(response).await;
+        assert_eq!(body["disabled"]["reason"], "disabled by the operator");
+        let response = chat(router, "gpt-4.1-mini").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn tenant_keys_are_limited_to_the_tenant_catalog() {
+        let response = post_json(
+            test_router(),
+            "/v1/chat/completions",
+            &[("x-api-key", "team-key")],
+            json!({"model": "gpt-4o", "messages": []}),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let message = body_json(response).await["error"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(message.contains("claude-*"), "{message}");
+
+        let response = post_json(
+            test_router(),
+            "/v1/messages",
+            &[("x-api-key", "team-key")],
+            json!({"model": "claude-sonnet-4-6", "messages": []}),
+        )
+        .await;
+        assert_ne!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn metrics_endpoint_requires_a_key_and_serves_prometheus_text() {
+        let response = get_with_key(test_router(), "/metrics", None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = get_with_key(test_router(), "/metrics", Some("test-key")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("# TYPE acr_request_duration_seconds histogram"));
+    }
+
+    #[tokio::test]
+    async fn admin_bind_moves_admin_routes_to_their_own_router() {
+        let mut state = test_state();
+        state.config.admin_bind = Some("127.0.0.1:8901".to_string());
+        let main = create_router(state.clone());
+        let admin = create_admin_router(state);
+
+        for uri in ["/metrics", "/admin/recent"] {
+            let response = get_with_key(main.clone(), uri, Some("test-key")).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+            let response = get_with_key(admin.clone(), uri, Some("test-key")).await;
+            assert_eq!(response.status(), StatusCode::OK, "{uri}");
+        }
+        let response = get_with_key(admin.clone(), "/v1/models", Some("test-key")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        for router in [main, admin] {
+            let response = get_with_key(router, "/health", None).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn recent_requests_require_a_key() {
+        let response = get_with_key(test_router(), "/admin/recent", None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = get_with_key(test_router(), "/admin/recent?limit=5", Some("test-key")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["data"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn log_level_changes_require_the_internal_key() {
+        let response = get_with_key(test_router(), "/admin/log-level", None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = get_with_key(test_router(), "/admin/log-level", Some("test-key")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let mut request = axum::http::Request::builder()
+            .method(Method::PUT)
+            .uri("/admin/log-level")
+            .header("content-type", "application/json")
+            .header("x-api-key", "test-key")
+            .body(axum::body::Body::from(r#"{"level":"debug"}"#))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4242))));
+        let response = test_router().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // The test router has no reloadable filter installed.
+        let response = get_with_key(test_router(), "/admin/log-level", Some("internal")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn model_refresh_requires_the_internal_key() {
+        let uri = "/admin/models/not-configured/refresh";
+        let response = post_json(test_router(), uri, &[("x-api-key", "test-key")], json!({})).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = post_json(test_router(), uri, &[("x-api-key", "internal")], json!({})).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn every_response_carries_a_request_id() {
+        let ok = get_with_key(test_router(), "/v1/models", None).await;
+        let denied = get_with_key(test_router(), "/admin/recent", None).await;
+        assert_eq!(denied.status(), StatusCode::UNAUTHORIZED);
+        let ids: Vec<&str> = [&ok, &denied]
+            .iter()
+            .map(|r| r.headers()["x-acr-request-id"].to_str().unwrap())
+            .collect();
+        assert!(ids.iter().all(|id| id.starts_with("req_")));
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[tokio::test]
+    async fn dead_letters_capture_only_upstream_failures() {
+        let timeout = Err(AppError::UpstreamTimeout {
+            provider: "primary".to_string(),
+        });
+        assert_eq!(
+            dead_letter_failure(&timeout).map(|(status, _)| status),
+            Some(StatusCode::GATEWAY_TIMEOUT)
+        );
+        let upstream_500 = Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        assert!(dead_letter_failure(&upstream_500).is_some());
+        assert!(dead_letter_failure(&Ok(StatusCode::OK.into_response())).is_none());
+        let bad_request = Err(AppError::BadRequest("no model".to_string()));
+        assert!(dead_letter_failure(&bad_request).is_none());
+
+        // Admin endpoints are there but report capture as disabled.
+        let response = get_with_key(test_router(), "/admin/dead-letters", Some("test-key")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -0,0 +1,227 @@
+//! Secrets at rest.
+//!
+//! On a laptop running acr as a local sidecar, the config file and anything
+//! acr writes next to it sit in a home directory that backups and sync tools
+//! copy around. With the `secrets` feature, credentials in the config can be
+//! stored age-encrypted (`age:` followed by base64, made by `acr config
+//! encrypt`) and the OAuth token cache can be persisted encrypted.
+//!
+//! Both use one passphrase, taken from `ACR_SECRETS_PASSPHRASE` or, with the
+//! `keychain` feature, from the OS keychain (`acr config set-passphrase`).
+//!
+//! With the `keychain` feature a credential can also be left out of the
+//! config entirely: `keychain:<entry>` is read from the OS keychain (macOS
+//! Keychain, Secret Service on Linux, Windows Credential Manager) at
+//! startup, after `acr config store-secret <entry>` put it there.
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::sync::OnceLock;
+
+use crate::constants::secrets::{KEYCHAIN_PREFIX, PASSPHRASE_ENV, PREFIX};
+
+/// The passphrase, once found; the keychain may prompt, so it is asked once.
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+/// The secrets passphrase: `ACR_SECRETS_PASSPHRASE`, else the OS keychain.
+pub fn passphrase() -> Result<String> {
+    if let Some(passphrase) = PASSPHRASE.get() {
+        return Ok(passphrase.clone());
+    }
+    let passphrase = match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) if !passphrase.is_empty() => passphrase,
+        _ => keychain_passphrase()?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "No secrets passphrase: set {PASSPHRASE_ENV}{}",
+                if cfg!(feature = "keychain") {
+                    " or store one with `acr config set-passphrase`"
+                } else {
+                    ""
+                }
+            )
+        })?,
+    };
+    Ok(PASSPHRASE.get_or_init(|| passphrase).clone())
+}
+
+/// Decrypt `value` if it is an encrypted secret, or look it up if it is a
+/// keychain reference; other values are returned as they are.
+pub fn reveal(value: String) -> Result<String> {
+    if let Some(entry) = value.strip_prefix(KEYCHAIN_PREFIX) {
+        return keychain_secret(entry.trim());
+    }
+    let Some(sealed) = value.strip_prefix(PREFIX) else {
+        return Ok(value);
+    };
+    if !cfg!(feature = "secrets") {
+        anyhow::bail!("Encrypted secret, but acr was built without the 'secrets' feature");
+    }
+    let ciphertext = base64::engine::general_purpose::STANDARD
+        .decode(sealed.trim())
+        .context("Encrypted secret is not valid base64")?;
+    let plaintext = decrypt(&ciphertext, &passphrase()?)?;
+    String::from_utf8(plaintext).context("Encrypted secret is not UTF-8")
+}
+
+/// Encrypt `value` into the `age:` form `reveal` reads.
+pub fn seal(value: &str, passphrase: &str) -> Result<String> {
+    let ciphertext = encrypt(value.as_bytes(), passphrase)?;
+    Ok(format!(
+        "{PREFIX}{}",
+        base64::engine::general_purpose::STANDARD.encode(ciphertext)
+    ))
+}
+
+/// Encrypt `plaintext` with the passphrase.
+#[cfg(feature = "secrets")]
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let recipient = age::scrypt::Recipient::new(passphrase.to_string().into());
+    age::encrypt(&recipient, plaintext).context("Failed to encrypt")
+}
+
+/// Decrypt what `encrypt` made with the same passphrase.
+#[cfg(feature = "secrets")]
+pub fn decrypt(ciphertext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let identity = age::scrypt::Identity::new(passphrase.to_string().into());
+    age::decrypt(&identity, ciphertext).context("Failed to decrypt (wrong passphrase?)")
+}
+
+#[cfg(not(feature = "secrets"))]
+pub fn encrypt(_plaintext: &[u8], _passphrase: &str) -> Result<Vec<u8>> {
+    anyhow::bail!("acr was built without the 'secrets' feature")
+}
+
+#[cfg(not(feature = "secrets"))]
+pub fn decrypt(_ciphertext: &[u8], _passphrase: &str) -> Result<Vec<u8>> {
+    anyhow::bail!("acr was built without the 'secrets' feature")
+}
+
+#[cfg(feature = "keychain")]
+fn keychain_entry() -> Result<keyring::Entry> {
+    use crate::constants::secrets::{KEYCHAIN_SERVICE, KEYCHAIN_USER};
+    keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_USER).context("Failed to open the OS keychain")
+}
+
+#[cfg(feature = "keychain")]
+fn keychain_passphrase() -> Result<Option<String>> {
+    match keychain_entry()?.get_password() {
+        Ok(passphrase) => Ok(Some(passphrase)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e).context("Failed to read the passphrase from the OS keychain"),
+    }
+}
+
+#[cfg(not(feature = "keychain"))]
+fn keychain_passphrase() -> Result<Option<String>> {
+    Ok(None)
+}
+
+/// The secret stored in the OS keychain under `entry`.
+#[cfg(feature = "keychain")]
+fn keychain_secret(entry: &str) -> Result<String> {
+    use crate::constants::secrets::KEYCHAIN_SERVICE;
+    if entry.is_empty() {
+        anyhow::bail!("Empty keychain entry name after '{KEYCHAIN_PREFIX}'");
+    }
+    let found = keyring::Entry::new(KEYCHAIN_SERVICE, entry)
+        .and_then(|e| e.get_password())
+        .map_err(|e| match e {
+            keyring::Error::NoEntry => anyhow::anyhow!(
+                "No keychain entry '{entry}': store it with `acr config store-secret {entry}`"
+            ),
+            e => anyhow::Error::new(e).context(format!("Failed to read keychain entry '{entry}'")),
+        })?;
+    Ok(found)
+}
+
+#[cfg(not(feature = "keychain"))]
+fn keychain_secret(_entry: &str) -> Result<String> {
+    anyhow::bail!("Keychain reference, but acr was built without the 'keychain' feature")
+}
+
+/// `acr config encrypt`: encrypt the secret on stdin and print it in the
+/// form to paste into the config.
+pub fn encrypt_stdin() -> Result<()> {
+    let value = read_stdin()?;
+    if value.is_empty() {
+        anyhow::bail!("Nothing to encrypt: pipe the secret to `acr config encrypt`");
+    }
+    println!("{}", seal(&value, &passphrase()?)?);
+    Ok(())
+}
+
+/// `acr config set-passphrase`: store the passphrase on stdin in the OS
+/// keychain.
+#[cfg(feature = "keychain")]
+pub fn set_passphrase_stdin() -> Result<()> {
+    let passphrase = read_stdin()?;
+    if passphrase.is_empty() {
+        anyhow::bail!("Empty passphrase");
+    }
+    keychain_entry()?
+        .set_password(&passphrase)
+        .context("Failed to store the passphrase in the OS keychain")?;
+    eprintln!("Stored the secrets passphrase in the OS keychain");
+    Ok(())
+}
+
+/// `acr config store-secret <entry>`: store the secret on stdin in the OS
+/// keychain and print the reference to put in the config.
+#[cfg(feature = "keychain")]
+pub fn store_secret_stdin(entry: &str) -> Result<()> {
+    use crate::constants::secrets::{KEYCHAIN_SERVICE, KEYCHAIN_USER};
+    if entry.is_empty() || entry == KEYCHAIN_USER {
+        anyhow::bail!("Invalid keychain entry name '{entry}'");
+    }
+    let secret = read_stdin()?;
+    if secret.is_empty() {
+        anyhow::bail!("Nothing to store: pipe the secret to `acr config store-secret {entry}`");
+    }
+    keyring::Entry::new(KEYCHAIN_SERVICE, entry)
+        .and_then(|e| e.set_password(&secret))
+        .with_context(|| format!("Failed to store keychain entry '{entry}'"))?;
+    eprintln!("Stored '{entry}' in the OS keychain; reference it in the config as:");
+    println!("{KEYCHAIN_PREFIX}{entry}");
+    Ok(())
+}
+
+/// One value from stdin, without its trailing newline.
+fn read_stdin() -> Result<String> {
+    let input = std::io::read_to_string(std::io::stdin()).context("Failed to read stdin")?;
+    Ok(input.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_values_are_left_alone() {
+        assert_eq!(reveal("s3cr3t".to_string()).unwrap(), "s3cr3t");
+    }
+
+    #[cfg(feature = "secrets")]
+    #[test]
+    fn sealed_values_round_trip() {
+        let sealed = seal("s3cr3t", "correct horse").unwrap();
+        let ciphertext = base64::engine::general_purpose::STANDARD
+            .decode(sealed.strip_prefix(PREFIX).unwrap())
+            .unwrap();
+        assert_eq!(decrypt(&ciphertext, "correct horse").unwrap(), b"s3cr3t");
+        assert!(decrypt(&ciphertext, "battery staple").is_err());
+    }
+
+    #[cfg(not(feature = "keychain"))]
+    #[test]
+    fn keychain_references_need_the_feature() {
+        let err = reveal(format!("{KEYCHAIN_PREFIX}uaa-primary")).unwrap_err();
+        assert!(format!("{err:#}").contains("'keychain' feature"));
+    }
+
+    #[cfg(not(feature = "secrets"))]
+    #[test]
+    fn sealed_values_need_the_feature() {
+        let err = reveal(format!("{PREFIX}AAAA")).unwrap_err();
+        assert!(format!("{err:#}").contains("'secrets' feature"));
+    }
+}
@@ -0,0 +1,313 @@
+//! Conversation (session) tracking.
+//!
+//! A session ID groups the turns of one conversation. Clients can set it
+//! explicitly with `x-acr-session-id`; Anthropic clients that don't are keyed
+//! off `metadata.user_id`, which Claude Code fills with a per-session value.
+//! The ID is attached to log lines, request records and per-session metrics,
+//! and drives provider affinity: turns of the same conversation go back to
+//! the provider that served the previous turn, where the upstream prompt
+//! cache for that conversation lives.
+//!
+//! Requests with Anthropic `cache_control` breakpoints but no session ID get
+//! an affinity key derived from their cached prefix instead (see
+//! [`prompt_cache_key`]), so even anonymous clients land where their cache
+//! was written.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::http::HeaderMap;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::constants::api::ACR_SESSION_ID_HEADER;
+use crate::constants::session::{AFFINITY_CAPACITY, AFFINITY_TTL_SECS, MAX_SESSION_ID_LEN};
+
+/// Resolve the session ID for a request, preferring the explicit header.
+/// Header values that aren't short printable ASCII are ignored; IDs derived
+/// from `metadata.user_id` are hashed instead when they don't fit, so an
+/// arbitrary client string never lands in logs verbatim.
+pub fn extract_session_id(headers: &HeaderMap, body: &Value) -> Option<String> {
+    if let Some(value) = headers.get(ACR_SESSION_ID_HEADER) {
+        match value.to_str().map(str::trim) {
+            Ok(id) if is_clean_id(id) => return Some(id.to_string()),
+            _ => tracing::debug!("Ignoring malformed {} header", ACR_SESSION_ID_HEADER),
+        }
+    }
+
+    let user_id = body
+        .get("metadata")
+        .and_then(|m| m.get("user_id"))
+        .and_then(|u| u.as_str())?;
+    let derived = session_from_user_id(user_id);
+    if derived.is_empty() {
+        return None;
+    }
+    if is_clean_id(&derived) {
+        Some(derived)
+    } else {
+        let digest = Sha256::digest(derived.as_bytes());
+        Some(digest[..8].iter().map(|b| format!("{b:02x}")).collect())
+    }
+}
+
+/// Claude Code encodes `user_<hash>_account_<uuid>_session_<uuid>` (older
+/// releases) or a JSON object carrying `session_id` (newer ones). Use the
+/// session part when present so every conversation of one user doesn't
+/// collapse into a single session; otherwise the whole value is the key.
+fn session_from_user_id(user_id: &str) -> String {
+    if let Ok(parsed) = serde_json::from_str::<Value>(user_id)
+        && let Some(session) = parsed.get("session_id").and_then(|s| s.as_str())
+    {
+        return session.to_string();
+    }
+    match user_id.rsplit_once("_session_") {
+        Some((_, session)) => session.to_string(),
+        None => user_id.to_string(),
+    }
+}
+
+/// Affinity key for a request carrying Anthropic prompt-cache breakpoints:
+/// a hash of the model plus everything up to and including the *first*
+/// `cache_control` block, in Anthropic's cache order (tools, system,
+/// messages). The first breakpoint usually sits on the system prompt or tool
+/// definitions, which stay identical from turn to turn, whereas later
+/// breakpoints move forward as the conversation grows. `None` when the body
+/// has no breakpoint.
+pub fn prompt_cache_key(body: &Value) -> Option<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(body.get("model").and_then(|m| m.as_str())?.as_bytes());
+
+    let mut hash_until_breakpoint = |items: &[Value]| {
+        for item in items {
+            hasher.update(b"\0");
+            hasher.update(item.to_string().as_bytes());
+            if item.get("cache_control").is_some() {
+                return true;
+            }
+        }
+        false
+    };
+
+    let empty = Vec::new();
+    let tools = body
+        .get("tools")
+        .and_then(|t| t.as_array())
+        .unwrap_or(&empty);
+    let mut found = hash_until_breakpoint(tools);
+    if !found {
+        found = match body.get("system") {
+            Some(Value::Array(blocks)) => hash_until_breakpoint(blocks),
+            Some(system) => hash_until_breakpoint(std::slice::from_ref(system)),
+            None => false,
+        };
+    }
+    if !found {
+        let messages = body
+            .get("messages")
+            .and_then(|m| m.as_array())
+            .unwrap_or(&empty);
+        for message in messages {
+            let role = message.get("role").cloned().unwrap_or(Value::Null);
+            found = match message.get("content") {
+                Some(Value::Array(blocks)) => {
+                    hash_until_breakpoint(std::slice::from_ref(&role))
+                        || hash_until_breakpoint(blocks)
+                }
+                Some(content) => hash_until_breakpoint(&[role, content.clone()]),
+                None => false,
+            };
+            if found {
+                break;
+            }
+        }
+    }
+    if !found {
+        return None;
+    }
+
+    let digest = hasher.finalize();
+    let hex: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+    Some(format!("cache:{hex}"))
+}
+
+fn is_clean_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_SESSION_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Remembers which provider last served each session, for a bounded time.
+#[derive(Debug, Clone)]
+pub struct SessionAffinity {
+    entries: Arc<Mutex<HashMap<String, (String, Instant)>>>,
+    ttl: Duration,
+    capacity: usize,
+}
+
+impl Default for SessionAffinity {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(AFFINITY_TTL_SECS), AFFINITY_CAPACITY)
+    }
+}
+
+impl SessionAffinity {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+            capacity,
+        }
+    }
+
+    /// The provider that last served `session`, if it did so within the TTL.
+    pub fn preferred_provider(&self, session: &str) -> Option<String> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .get(session)
+            .filter(|(_, seen)| seen.elapsed() < self.ttl)
+            .map(|(provider, _)| provider.clone())
+    }
+
+    /// Pin `session` to `provider`, refreshing its TTL. When the map is full,
+    /// expired entries go first, then the least recently used one.
+    pub fn record(&self, session: &str, provider: &str) {
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        if !entries.contains_key(session) && entries.len() >= self.capacity {
+            let ttl = self.ttl;
+            entries.retain(|_, (_, seen)| seen.elapsed() < ttl);
+            if entries.len() >= self.capacity
+                && let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, (_, seen))| *seen)
+                    .map(|(k, _)| k.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(session.to_string(), (provider.to_string(), Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn headers(session: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACR_SESSION_ID_HEADER, session.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn header_wins_over_metadata() {
+        let body = json!({"metadata": {"user_id": "someone"}});
+        assert_eq!(
+            extract_session_id(&headers("conv-42"), &body).as_deref(),
+            Some("conv-42")
+        );
+    }
+
+    #[test]
+    fn claude_code_user_id_yields_its_session_part() {
+        let body = json!({"metadata": {
+            "user_id": "user_abc123_account_11111111-2222_session_33333333-4444"
+        }});
+        assert_eq!(
+            extract_session_id(&HeaderMap::new(), &body).as_deref(),
+            Some("33333333-4444")
+        );
+
+        let body = json!({"metadata": {"user_id": "{\"device_id\":\"d\",\"session_id\":\"s-1\"}"}});
+        assert_eq!(
+            extract_session_id(&HeaderMap::new(), &body).as_deref(),
+            Some("s-1")
+        );
+    }
+
+    #[test]
+    fn unusable_ids_are_ignored_or_hashed() {
+        assert_eq!(extract_session_id(&HeaderMap::new(), &json!({})), None);
+        assert_eq!(extract_session_id(&headers("has space"), &json!({})), None);
+
+        let long = "x".repeat(MAX_SESSION_ID_LEN + 1);
+        let body = json!({"metadata": {"user_id": long}});
+        let hashed = extract_session_id(&HeaderMap::new(), &body).unwrap();
+        assert_eq!(hashed.len(), 16);
+        assert!(hashed.bytes().all(|b| b.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn prompt_cache_key_is_stable_as_the_conversation_grows() {
+        let system = json!([{"type": "text", "text": "You are helpful.", "cache_control": {"type": "ephemeral"}}]);
+        let turn1 = json!({
+            "model": "claude-sonnet-4-5",
+            "system": system,
+            "messages": [{"role": "user", "content": [{"type": "text", "text": "hi", "cache_control": {"type": "ephemeral"}}]}],
+        });
+        let turn2 = json!({
+            "model": "claude-sonnet-4-5",
+            "system": system,
+            "messages": [
+                {"role": "user", "content": [{"type": "text", "text": "hi"}]},
+                {"role": "assistant", "content": "hello"},
+                {"role": "user", "content": [{"type": "text", "text": "more", "cache_control": {"type": "ephemeral"}}]},
+            ],
+        });
+        let key = prompt_cache_key(&turn1).unwrap();
+        assert!(key.starts_with("cache:"));
+        assert_eq!(prompt_cache_key(&turn2), Some(key.clone()));
+
+        // Same prefix on another model is a different cache.
+        let mut other_model = turn1.clone();
+        other_model["model"] = json!("claude-opus-4-7");
+        assert_ne!(prompt_cache_key(&other_model), Some(key));
+    }
+
+    #[test]
+    fn prompt_cache_key_reaches_into_messages_and_requires_a_breakpoint() {
+        let plain = json!({
+            "model": "claude-sonnet-4-5",
+            "system": "be brief",
+            "messages": [{"role": "user", "content": "hi"}],
+        });
+        assert_eq!(prompt_cache_key(&plain), None);
+
+        let in_messages = json!({
+            "model": "claude-sonnet-4-5",
+            "system": "be brief",
+            "messages": [{"role": "user", "content": [{"type": "text", "text": "doc", "cache_control": {"type": "ephemeral"}}]}],
+        });
+        let mut different_system = in_messages.clone();
+        different_system["system"] = json!("be verbose");
+        assert!(prompt_cache_key(&in_messages).is_some());
+        assert_ne!(
+            prompt_cache_key(&in_messages),
+            prompt_cache_key(&different_system)
+        );
+    }
+
+    #[test]
+    fn affinity_expires_after_ttl() {
+        let affinity = SessionAffinity::new(Duration::from_millis(20), 8);
+        affinity.record("s", "primary");
+        assert_eq!(affinity.preferred_provider("s").as_deref(), Some("primary"));
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(affinity.preferred_provider("s"), None);
+    }
+
+    #[test]
+    fn affinity_evicts_least_recently_used_when_full() {
+        let affinity = SessionAffinity::new(Duration::from_secs(60), 2);
+        affinity.record("a", "p1");
+        std::thread::sleep(Duration::from_millis(2));
+        affinity.record("b", "p2");
+        std::thread::sleep(Duration::from_millis(2));
+        affinity.record("c", "p3");
+        assert_eq!(affinity.preferred_provider("a"), None);
+        assert_eq!(affinity.preferred_provider("b").as_deref(), Some("p2"));
+        assert_eq!(affinity.preferred_provider("c").as_deref(), Some("p3"));
+    }
+}
@@ -0,0 +1,408 @@
+//! In-process SLO burn-rate alerting.
+//!
+//! Small deployments rarely run Prometheus with alerting rules, so acr can
+//! watch two objectives itself: the share of upstream attempts that fail
+//! (5xx, 429, streams cut short; other 4xx are the client's), and, with
+//! `latency_threshold_ms`, the share that are too slow. Attempts are counted
+//! in one-minute buckets. The burn rate of a window is its bad share divided
+//! by the error budget (`1 - objective`). An alert fires while both its long
+//! and short window burn faster than its rate, after the multi-window
+//! scheme of the Google SRE workbook: `page` for 1h and 5m above 14.4,
+//! `ticket` for 6h and 30m above 6.
+//!
+//! Burn rates and alert states are part of `/metrics`. With `webhook_url`,
+//! every alert that fires or resolves is posted as JSON, with a `text`
+//! field so Slack-style incoming webhooks show it as-is.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::SloConfig;
+use crate::constants::slo::{
+    ALERTS, BUCKET_SECS, BUCKETS, EVALUATE_INTERVAL_SECS, WEBHOOK_TIMEOUT_SECS,
+};
+use crate::metrics::{MetricsEvent, MetricsService, RequestSummary};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Slo {
+    ErrorRate,
+    Latency,
+}
+
+impl Slo {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::ErrorRate => "error_rate",
+            Self::Latency => "latency",
+        }
+    }
+}
+
+/// Attempts that finished within one minute.
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    minute: u64,
+    total: u64,
+    errors: u64,
+    slow: u64,
+}
+
+/// An alert that fired or resolved in an evaluation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertChange {
+    pub slo: Slo,
+    pub severity: &'static str,
+    pub firing: bool,
+    pub long_window: u64,
+    pub short_window: u64,
+    pub burn_rate_long: f64,
+    pub burn_rate_short: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SloMonitor {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    config: SloConfig,
+    buckets: Mutex<VecDeque<Bucket>>,
+    /// Alerts currently firing, by SLO and severity.
+    firing: Mutex<HashMap<(Slo, &'static str), bool>>,
+}
+
+impl SloMonitor {
+    /// `None` when `slo.enabled` is off.
+    pub fn new(config: &SloConfig) -> Option<Self> {
+        config.enabled.then(|| Self {
+            inner: Arc::new(Inner {
+                config: config.clone(),
+                buckets: Mutex::new(VecDeque::with_capacity(BUCKETS)),
+                firing: Mutex::new(HashMap::new()),
+            }),
+        })
+    }
+
+    /// The SLOs being tracked.
+    fn slos(&self) -> Vec<Slo> {
+        let mut slos = vec![Slo::ErrorRate];
+        if self.inner.config.latency_threshold_ms.is_some() {
+            slos.push(Slo::Latency);
+        }
+        slos
+    }
+
+    fn objective(&self, slo: Slo) -> f64 {
+        match slo {
+            Slo::ErrorRate => self.inner.config.error_rate_objective,
+            Slo::Latency => self.inner.config.latency_objective,
+        }
+    }
+
+    /// Count one finished upstream attempt.
+    pub fn record(&self, summary: &RequestSummary) {
+        let error = summary.status >= 500 || summary.status == 429 || summary.error.is_some();
+        // A stream's latency runs to its end, which depends on the answer's
+        // length; its first byte is what the client waits for.
+        let latency_ms = if summary.labels.stream {
+            summary.ttfb_ms.unwrap_or(summary.latency_ms)
+        } else {
+            summary.latency_ms
+        };
+        let slow = self
+            .inner
+            .config
+            .latency_threshold_ms
+            .is_some_and(|threshold| latency_ms > threshold);
+        self.record_at(current_minute(), error, slow);
+    }
+
+    fn record_at(&self, minute: u64, error: bool, slow: bool) {
+        let Ok(mut buckets) = self.inner.buckets.lock() else {
+            return;
+        };
+        if buckets.back().is_none_or(|b| b.minute < minute) {
+            buckets.push_back(Bucket {
+                minute,
+                ..Default::default()
+            });
+            while buckets.len() > BUCKETS {
+                buckets.pop_front();
+            }
+        }
+        // An attempt finishing just as the minute turns may land in the
+        // newer bucket; that's close enough.
+        if let Some(bucket) = buckets.back_mut() {
+            bucket.total += 1;
+            bucket.errors += u64::from(error);
+            bucket.slow += u64::from(slow);
+        }
+    }
+
+    /// Burn rate of `slo` over the last `window` minutes: 1 spends the error
+    /// budget exactly as fast as the objective allows.
+    fn burn_rate_at(&self, now: u64, slo: Slo, window: u64) -> f64 {
+        let Ok(buckets) = self.inner.buckets.lock() else {
+            return 0.0;
+        };
+        let (total, bad) = buckets
+            .iter()
+            .rev()
+            .take_while(|b| b.minute + window > now)
+            .fold((0, 0), |(total, bad), b| {
+                let bucket_bad = match slo {
+                    Slo::ErrorRate => b.errors,
+                    Slo::Latency => b.slow,
+                };
+                (total + b.total, bad + bucket_bad)
+            });
+        if total == 0 {
+            return 0.0;
+        }
+        (bad as f64 / total as f64) / (1.0 - self.objective(slo))
+    }
+
+    /// Recompute every alert and return the ones that changed state.
+    fn evaluate_at(&self, now: u64) -> Vec<AlertChange> {
+        let mut changes = Vec::new();
+        let Ok(mut firing) = self.inner.firing.lock() else {
+            return changes;
+        };
+        for slo in self.slos() {
+            for &(severity, long_window, short_window, rate) in ALERTS {
+                let burn_rate_long = self.burn_rate_at(now, slo, long_window);
+                let burn_rate_short = self.burn_rate_at(now, slo, short_window);
+                let now_firing = burn_rate_long > rate && burn_rate_short > rate;
+                let was_firing = firing.insert((slo, severity), now_firing).unwrap_or(false);
+                if now_firing != was_firing {
+                    changes.push(AlertChange {
+                        slo,
+                        severity,
+                        firing: now_firing,
+                        long_window,
+                        short_window,
+                        burn_rate_long,
+                        burn_rate_short,
+                    });
+                }
+            }
+        }
+        changes
+    }
+
+    /// Burn rates per window and alert states, in the Prometheus text format.
+    pub fn render_prometheus(&self) -> String {
+        let now = current_minute();
+        let mut windows: Vec<u64> = ALERTS
+            .iter()
+            .flat_map(|&(_, long, short, _)| [long, short])
+            .collect();
+        windows.sort_unstable();
+        windows.dedup();
+
+        let mut out = String::from(
+            "# HELP acr_slo_burn_rate Error budget burn rate per SLO and window; 1 spends the budget exactly as the objective allows.\n# TYPE acr_slo_burn_rate gauge\n",
+        );
+        for slo in self.slos() {
+            for &window in &windows {
+                let _ = writeln!(
+                    out,
+                    "acr_slo_burn_rate{{slo=\"{}\",window=\"{}\"}} {}",
+                    slo.label(),
+                    window_label(window),
+                    self.burn_rate_at(now, slo, window)
+                );
+            }
+        }
+        out.push_str(
+            "# HELP acr_slo_alert Burn-rate alerts: 1 while firing.\n# TYPE acr_slo_alert gauge\n",
+        );
+        let firing = self
+            .inner
+            .firing
+            .lock()
+            .map(|f| f.clone())
+            .unwrap_or_default();
+        for slo in self.slos() {
+            for &(severity, ..) in ALERTS {
+                let _ = writeln!(
+                    out,
+                    "acr_slo_alert{{slo=\"{}\",severity=\"{severity}\"}} {}",
+                    slo.label(),
+                    u8::from(firing.get(&(slo, severity)).copied().unwrap_or(false))
+                );
+            }
+        }
+        out
+    }
+
+    /// Count attempts and evaluate the alerts until the process exits.
+    pub fn spawn(&self, metrics: &MetricsService, client: reqwest::Client) {
+        let mut events = metrics.subscribe();
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(MetricsEvent::UpstreamAttempt(summary)) => monitor.record(&summary),
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(n)) => {
+                        tracing::debug!("SLO monitor fell behind, dropped {} event(s)", n);
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(EVALUATE_INTERVAL_SECS));
+            loop {
+                interval.tick().await;
+                for change in monitor.evaluate_at(current_minute()) {
+                    monitor.notify(&client, &change).await;
+                }
+            }
+        });
+    }
+
+    async fn notify(&self, client: &reqwest::Client, change: &AlertChange) {
+        let objective = self.objective(change.slo);
+        let text = format!(
+            "acr: {} SLO {} alert {} (burn rate {:.1} over {}, {:.1} over {}; objective {}%)",
+            change.slo.label(),
+            change.severity,
+            if change.firing { "firing" } else { "resolved" },
+            change.burn_rate_long,
+            window_label(change.long_window),
+            change.burn_rate_short,
+            window_label(change.short_window),
+            objective * 100.0
+        );
+        if change.firing {
+            tracing::warn!("{}", text);
+        } else {
+            tracing::info!("{}", text);
+        }
+        let Some(ref url) = self.inner.config.webhook_url else {
+            return;
+        };
+        let payload = json!({
+            "text": text,
+            "alert": {
+                "slo": change.slo.label(),
+                "severity": change.severity,
+                "state": if change.firing { "firing" } else { "resolved" },
+                "objective": objective,
+                "long_window": window_label(change.long_window),
+                "short_window": window_label(change.short_window),
+                "burn_rate_long": change.burn_rate_long,
+                "burn_rate_short": change.burn_rate_short,
+            },
+            "at": chrono::Utc::now().to_rfc3339(),
+        });
+        let result = client
+            .post(url)
+            .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Err(e) = result {
+            tracing::warn!("Failed to post SLO alert to the webhook: {}", e);
+        }
+    }
+}
+
+fn current_minute() -> u64 {
+    chrono::Utc::now().timestamp().max(0) as u64 / BUCKET_SECS
+}
+
+/// `5m`, `1h`.
+fn window_label(minutes: u64) -> String {
+    if minutes.is_multiple_of(60) {
+        format!("{}h", minutes / 60)
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(yaml: &str) -> SloMonitor {
+        let config: SloConfig = serde_yaml_ng::from_str(yaml).unwrap();
+        SloMonitor::new(&config).unwrap()
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        assert!(SloMonitor::new(&SloConfig::default()).is_none());
+    }
+
+    #[test]
+    fn burn_rate_is_the_bad_share_over_the_budget() {
+        let monitor = monitor("enabled: true");
+        for i in 0..100 {
+            monitor.record_at(1000, i < 5, false);
+        }
+        // 5% errors against a 1% budget.
+        assert!((monitor.burn_rate_at(1000, Slo::ErrorRate, 5) - 5.0).abs() < 1e-9);
+        // Outside the window.
+        assert_eq!(monitor.burn_rate_at(1005, Slo::ErrorRate, 5), 0.0);
+    }
+
+    #[test]
+    fn alerts_fire_when_both_windows_burn_and_resolve_after() {
+        let monitor = monitor("enabled: true");
+        for minute in 940..1000 {
+            for i in 0..10 {
+                monitor.record_at(minute, i < 2, false);
+            }
+        }
+        // 20% errors burn a 1% budget at 20: both alerts fire.
+        let changes = monitor.evaluate_at(999);
+        let fired: Vec<_> = changes.iter().map(|c| (c.severity, c.firing)).collect();
+        assert_eq!(fired, [("page", true), ("ticket", true)]);
+        assert!(
+            monitor
+                .render_prometheus()
+                .contains("acr_slo_alert{slo=\"error_rate\",severity=\"page\"} 1")
+        );
+        // Nothing changed since.
+        assert!(monitor.evaluate_at(999).is_empty());
+
+        // A clean short window resolves it even though the hour still burns.
+        for minute in 1000..1005 {
+            for _ in 0..10 {
+                monitor.record_at(minute, false, false);
+            }
+        }
+        // The ticket's 30m window still burns.
+        let changes = monitor.evaluate_at(1004);
+        let fired: Vec<_> = changes.iter().map(|c| (c.severity, c.firing)).collect();
+        assert_eq!(fired, [("page", false)]);
+    }
+
+    #[test]
+    fn latency_slo_counts_slow_attempts_only_when_configured() {
+        let monitor = monitor("enabled: true\nlatency_threshold_ms: 1000\nlatency_objective: 0.75");
+        let now = current_minute();
+        for i in 0..10 {
+            monitor.record_at(now, false, i < 5);
+        }
+        // 50% slow against a 25% budget.
+        assert_eq!(monitor.burn_rate_at(now, Slo::Latency, 5), 2.0);
+        let text = monitor.render_prometheus();
+        assert!(text.contains("acr_slo_burn_rate{slo=\"latency\",window=\"5m\"} 2"));
+        assert!(text.contains("acr_slo_burn_rate{slo=\"error_rate\",window=\"6h\"} 0"));
+
+        let text = self::monitor("enabled: true").render_prometheus();
+        assert!(!text.contains("slo=\"latency\""));
+    }
+}
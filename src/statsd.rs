@@ -0,0 +1,222 @@
+//! StatsD / DogStatsD push exporter.
+//!
+//! Mirrors the labeled Prometheus series for setups that aren't pull-based:
+//! every upstream attempt is sent as counters and a timer over UDP, tagged
+//! DogStatsD-style with the same model / family / provider / route / stream
+//! dimensions, and the in-flight request, open stream and client connection
+//! counts are reported as gauges on a fixed interval. Sends are fire-and-forget; a missing agent costs nothing
+//! but a dropped datagram.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::StatsdConfig;
+use crate::constants::metrics::STATSD_GAUGE_INTERVAL_SECS;
+use crate::metrics::{MetricsEvent, MetricsService, RequestLabels, TokenCounts, family_label};
+
+/// Connect to the agent and forward metrics until the process exits.
+/// Returns `Ok(None)` when the exporter is disabled.
+pub async fn spawn(
+    config: &StatsdConfig,
+    metrics: &MetricsService,
+) -> Result<Option<tokio::task::JoinHandle<()>>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .context("Failed to bind StatsD socket")?;
+    socket
+        .connect((config.host.as_str(), config.port))
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to resolve StatsD agent {}:{}",
+                config.host, config.port
+            )
+        })?;
+
+    let prefix = metric_prefix(&config.prefix);
+    let mut events = metrics.subscribe();
+    let metrics = metrics.clone();
+    Ok(Some(tokio::spawn(async move {
+        let mut gauge = tokio::time::interval(Duration::from_secs(STATSD_GAUGE_INTERVAL_SECS));
+        loop {
+            let packet = tokio::select! {
+                _ = gauge.tick() => gauge_packet(&prefix, &metrics),
+                event = events.recv() => match event {
+                    Ok(MetricsEvent::UpstreamAttempt(summary)) => attempt_packet(
+                        &prefix,
+                        &summary.labels,
+                        summary.success(),
+                        summary.latency(),
+                        &summary.tokens,
+                    ),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(n)) => {
+                        tracing::debug!("StatsD exporter fell behind, dropped {} event(s)", n);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+            };
+            if let Err(e) = socket.send(packet.as_bytes()).await {
+                tracing::debug!("StatsD send failed: {}", e);
+            }
+        }
+    })))
+}
+
+fn metric_prefix(prefix: &str) -> String {
+    let prefix = prefix.trim_end_matches('.');
+    if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("{prefix}.")
+    }
+}
+
+/// The periodic gauges, one metric per line.
+fn gauge_packet(prefix: &str, metrics: &MetricsService) -> String {
+    let connections = metrics.connection_stats();
+    [
+        ("active_requests", metrics.snapshot_sync().active_requests),
+        ("open_streams", connections.open_streams),
+        ("client_connections", connections.client_open),
+    ]
+    .iter()
+    .map(|(name, value)| format!("{prefix}{name}:{value}|g"))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+/// One datagram per attempt, one metric per line.
+fn attempt_packet(
+    prefix: &str,
+    labels: &RequestLabels,
+    success: bool,
+    latency: Duration,
+    tokens: &TokenCounts,
+) -> String {
+    let tags = dogstatsd_tags(labels);
+    let mut lines = vec![
+        format!("{prefix}requests:1|c|#{tags}"),
+        format!(
+            "{prefix}request_duration:{:.3}|ms|#{tags}",
+            latency.as_secs_f64() * 1000.0
+        ),
+    ];
+    if !success {
+        lines.push(format!("{prefix}request_errors:1|c|#{tags}"));
+    }
+    for (kind, value) in [
+        ("input", tokens.input),
+        ("output", tokens.output),
+        ("cache_read", tokens.cache_read),
+        ("cache_write", tokens.cache_write),
+        ("reasoning", tokens.reasoning),
+        ("image", tokens.image),
+    ] {
+        if value > 0 {
+            lines.push(format!("{prefix}tokens:{value}|c|#{tags},type:{kind}"));
+        }
+    }
+    lines.join("\n")
+}
+
+/// `model:...,family:...` — characters that delimit the wire format
+/// (`,`, `|`, `#`, newlines) are replaced so a tag value can't break a line.
+fn dogstatsd_tags(labels: &RequestLabels) -> String {
+    let clean = |v: &str| v.replace([',', '|', '#', '\n'], "_");
+    format!(
+        "model:{},family:{},provider:{},route:{},stream:{}",
+        clean(&labels.model),
+        family_label(labels.family),
+        clean(&labels.provider),
+        clean(&labels.route),
+        labels.stream
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::RequestSummary;
+    use crate::proxy::LlmFamily;
+
+    #[test]
+    fn attempt_packet_is_tagged_dogstatsd() {
+        let labels = RequestLabels {
+            model: "gpt-4.1".to_string(),
+            family: LlmFamily::OpenAi,
+            provider: "primary".to_string(),
+            route: "/v1/chat/completions".to_string(),
+            stream: false,
+        };
+        let tokens = TokenCounts {
+            input: 12,
+            output: 0,
+            cache_read: 0,
+            cache_write: 0,
+            ..Default::default()
+        };
+        let packet = attempt_packet(
+            &metric_prefix("acr."),
+            &labels,
+            false,
+            Duration::from_millis(250),
+            &tokens,
+        );
+        let tags =
+            "model:gpt-4.1,family:openai,provider:primary,route:/v1/chat/completions,stream:false";
+        assert_eq!(
+            packet.lines().collect::<Vec<_>>(),
+            vec![
+                format!("acr.requests:1|c|#{tags}"),
+                format!("acr.request_duration:250.000|ms|#{tags}"),
+                format!("acr.request_errors:1|c|#{tags}"),
+                format!("acr.tokens:12|c|#{tags},type:input"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn exporter_sends_attempts_to_the_agent() {
+        let agent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = StatsdConfig {
+            enabled: true,
+            host: "127.0.0.1".to_string(),
+            port: agent.local_addr().unwrap().port(),
+            prefix: "acr".to_string(),
+            unknown: Default::default(),
+        };
+        let metrics = MetricsService::new();
+        let handle = spawn(&config, &metrics).await.unwrap().unwrap();
+        let labels = RequestLabels {
+            model: "gemini-2.5-pro".to_string(),
+            family: LlmFamily::Gemini,
+            provider: "primary".to_string(),
+            route: "/gemini/models".to_string(),
+            stream: true,
+        };
+        metrics
+            .record_request(RequestSummary::new(labels, 200, Duration::from_secs(1)))
+            .await;
+
+        let mut buf = [0u8; 1024];
+        // The first datagram may be the startup gauge; the attempt follows.
+        let mut received = String::new();
+        while !received.contains("acr.requests:1|c") {
+            let n = tokio::time::timeout(Duration::from_secs(2), agent.recv(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            received.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+        }
+        assert!(received.contains("family:gemini"));
+        handle.abort();
+    }
+}
@@ -0,0 +1,209 @@
+//! Caps on concurrently open streaming responses.
+//!
+//! Every open SSE stream pins a client connection, an upstream connection
+//! and the buffers between them for as long as the model keeps talking. A
+//! runaway agent that opens streams in a loop can exhaust the router's memory
+//! and file descriptors long before it trips a request-rate limit, so streams
+//! are counted separately: globally (`streams.max_open`) and per API key
+//! (`streams.max_open_per_key`, overridable per key). A request that would
+//! exceed either cap is rejected with 429 before it goes upstream; the slot
+//! is released when the client is done with the stream.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::config::{ApiKeyConfig, StreamsConfig};
+
+/// Which cap a rejected stream ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamCap {
+    Global(usize),
+    PerKey(usize),
+}
+
+impl std::fmt::Display for StreamCap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Global(limit) => write!(f, "{limit} open streams in total"),
+            Self::PerKey(limit) => write!(f, "{limit} open streams for this API key"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Open {
+    total: usize,
+    by_key: HashMap<String, usize>,
+}
+
+/// Shared stream counter; cheap to clone.
+#[derive(Debug, Clone)]
+pub struct StreamLimiter {
+    open: Arc<Mutex<Open>>,
+    max_open: Option<usize>,
+    /// key_hash → resolved per-key cap (None = unlimited).
+    key_limits: Arc<HashMap<String, Option<usize>>>,
+    default_per_key: Option<usize>,
+}
+
+/// A held stream slot, released on drop.
+#[derive(Debug)]
+pub struct StreamSlot {
+    limiter: StreamLimiter,
+    key_hash: Option<String>,
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        let mut open = self.limiter.open.lock().unwrap_or_else(|e| e.into_inner());
+        open.total = open.total.saturating_sub(1);
+        if let Some(ref key_hash) = self.key_hash
+            && let Some(count) = open.by_key.get_mut(key_hash)
+        {
+            *count -= 1;
+            if *count == 0 {
+                open.by_key.remove(key_hash);
+            }
+        }
+    }
+}
+
+impl StreamLimiter {
+    /// Build a limiter from the global caps and per-key overrides. Returns
+    /// `None` if no cap is configured anywhere.
+    pub fn from_config(api_keys: &[ApiKeyConfig], config: &StreamsConfig) -> Option<Self> {
+        let default_per_key = config.max_open_per_key;
+        let key_limits: HashMap<String, Option<usize>> = api_keys
+            .iter()
+            .map(|k| {
+                let resolved = match k.max_open_streams {
+                    Some(0) => None, // explicit unlimited override
+                    Some(n) => Some(n),
+                    None => default_per_key,
+                };
+                (k.key_hash(), resolved)
+            })
+            .collect();
+
+        let any_limited = config.max_open.is_some()
+            || default_per_key.is_some()
+            || key_limits.values().any(|v| v.is_some());
+        if !any_limited {
+            return None;
+        }
+        Some(Self {
+            open: Arc::new(Mutex::new(Open::default())),
+            max_open: config.max_open,
+            key_limits: Arc::new(key_limits),
+            default_per_key,
+        })
+    }
+
+    /// The same limiter for a reloaded config, or `None` if it no longer
+    /// caps anything. Streams already open stay counted.
+    pub fn reconfigure(&self, api_keys: &[ApiKeyConfig], config: &StreamsConfig) -> Option<Self> {
+        Some(Self {
+            open: self.open.clone(),
+            ..Self::from_config(api_keys, config)?
+        })
+    }
+
+    /// Take a stream slot for the key with this hash, or report the cap
+    /// that is already reached.
+    pub fn try_open(&self, key_hash: Option<&str>) -> Result<StreamSlot, StreamCap> {
+        let key_limit = key_hash.and_then(|kh| {
+            self.key_limits
+                .get(kh)
+                .copied()
+                .unwrap_or(self.default_per_key)
+        });
+
+        let mut open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(max) = self.max_open
+            && open.total >= max
+        {
+            return Err(StreamCap::Global(max));
+        }
+        if let (Some(limit), Some(kh)) = (key_limit, key_hash)
+            && open.by_key.get(kh).copied().unwrap_or(0) >= limit
+        {
+            return Err(StreamCap::PerKey(limit));
+        }
+        open.total += 1;
+        if let Some(kh) = key_hash {
+            *open.by_key.entry(kh.to_string()).or_default() += 1;
+        }
+        Ok(StreamSlot {
+            limiter: self.clone(),
+            key_hash: key_hash.map(str::to_string),
+        })
+    }
+
+    /// Streams currently open.
+    pub fn open_streams(&self) -> usize {
+        self.open.lock().unwrap_or_else(|e| e.into_inner()).total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quota::hash_api_key;
+
+    fn key(name: &str, max_open_streams: Option<usize>) -> ApiKeyConfig {
+        ApiKeyConfig {
+            key: name.to_string(),
+            daily_token_limit: None,
+            monthly_token_limit: None,
+            requests_per_minute: None,
+            max_open_streams,
+            output_tokens_per_sec: None,
+            allowed_models: None,
+            model_aliases: Default::default(),
+            priority: Default::default(),
+            reasoning: Default::default(),
+            tenant: None,
+            client: None,
+            valid_from: None,
+            valid_until: None,
+        }
+    }
+
+    #[test]
+    fn unconfigured_limiter_is_none() {
+        let keys = vec![key("a", None)];
+        assert!(StreamLimiter::from_config(&keys, &StreamsConfig::default()).is_none());
+    }
+
+    #[test]
+    fn per_key_and_global_caps_release_on_drop() {
+        let keys = vec![key("agent", None), key("ci", Some(0))];
+        let config = StreamsConfig {
+            max_open: Some(3),
+            max_open_per_key: Some(1),
+            ..Default::default()
+        };
+        let limiter = StreamLimiter::from_config(&keys, &config).unwrap();
+        let agent = hash_api_key("agent");
+        let ci = hash_api_key("ci");
+
+        let first = limiter.try_open(Some(&agent)).unwrap();
+        assert_eq!(
+            limiter.try_open(Some(&agent)).unwrap_err(),
+            StreamCap::PerKey(1)
+        );
+
+        // `ci` opted out of the per-key cap but still counts globally.
+        let _ci_1 = limiter.try_open(Some(&ci)).unwrap();
+        let _ci_2 = limiter.try_open(Some(&ci)).unwrap();
+        assert_eq!(
+            limiter.try_open(Some(&ci)).unwrap_err(),
+            StreamCap::Global(3)
+        );
+        assert_eq!(limiter.open_streams(), 3);
+
+        drop(first);
+        assert_eq!(limiter.open_streams(), 2);
+        assert!(limiter.try_open(Some(&agent)).is_ok());
+    }
+}
@@ -0,0 +1,215 @@
+//! Output-token pacing for streaming responses.
+//!
+//! A deployment produces a limited number of tokens per second, shared by
+//! everyone streaming from it. With `streams.output_tokens_per_sec_per_key`
+//! (overridable per key) the forwarder holds back SSE events once a key has
+//! received more than its rate, so one greedy consumer can't take all of a
+//! shared deployment's throughput. The budget is per key, not per stream:
+//! ten parallel streams from one key share one rate.
+//!
+//! Upstream usage only arrives at the end of a stream, so each event's output
+//! is estimated from the text it carries (about four characters a token).
+//! A key may run one second's worth ahead before events are held back, so
+//! short answers aren't slowed down at all.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::{ApiKeyConfig, StreamsConfig};
+use crate::constants::streams::{PACE_BURST, PACE_CHARS_PER_TOKEN};
+
+/// Per-key output pacing; cheap to clone.
+#[derive(Debug, Clone)]
+pub struct OutputPacer {
+    /// key_hash → resolved rate (None = unlimited).
+    key_rates: Arc<HashMap<String, Option<u32>>>,
+    default_rate: Option<u32>,
+    /// key_hash → when the key's budget is next free (theoretical arrival
+    /// time of its next token).
+    next_free: Arc<Mutex<HashMap<String, Arc<Mutex<Instant>>>>>,
+}
+
+/// The pacing budget of one key, handed to a stream's forwarder.
+#[derive(Debug)]
+pub struct KeyPace {
+    rate: u32,
+    next_free: Arc<Mutex<Instant>>,
+}
+
+impl OutputPacer {
+    /// Build a pacer from the global rate and per-key overrides. Returns
+    /// `None` if no rate is configured anywhere.
+    pub fn from_config(api_keys: &[ApiKeyConfig], config: &StreamsConfig) -> Option<Self> {
+        let default_rate = config.output_tokens_per_sec_per_key;
+        let key_rates: HashMap<String, Option<u32>> = api_keys
+            .iter()
+            .map(|k| {
+                let resolved = match k.output_tokens_per_sec {
+                    Some(0) => None, // explicit unlimited override
+                    Some(n) => Some(n),
+                    None => default_rate,
+                };
+                (k.key_hash(), resolved)
+            })
+            .collect();
+        if default_rate.is_none() && key_rates.values().all(Option::is_none) {
+            return None;
+        }
+        Some(Self {
+            key_rates: Arc::new(key_rates),
+            default_rate,
+            next_free: Arc::default(),
+        })
+    }
+
+    /// The same pacer for a reloaded config, or `None` if it no longer
+    /// paces any key. Keys keep the output they were already charged.
+    pub fn reconfigure(&self, api_keys: &[ApiKeyConfig], config: &StreamsConfig) -> Option<Self> {
+        Some(Self {
+            next_free: self.next_free.clone(),
+            ..Self::from_config(api_keys, config)?
+        })
+    }
+
+    /// The budget for the key with this hash; `None` when it isn't paced.
+    pub fn for_key(&self, key_hash: &str) -> Option<KeyPace> {
+        let rate = self
+            .key_rates
+            .get(key_hash)
+            .copied()
+            .unwrap_or(self.default_rate)?;
+        let next_free = self
+            .next_free
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(key_hash.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(Instant::now())))
+            .clone();
+        Some(KeyPace { rate, next_free })
+    }
+}
+
+impl KeyPace {
+    /// Charge `tokens` to the key and return how long to hold them back.
+    pub fn reserve(&self, tokens: u64) -> Duration {
+        self.reserve_at(tokens, Instant::now())
+    }
+
+    fn reserve_at(&self, tokens: u64, now: Instant) -> Duration {
+        let cost =
+            Duration::from_nanos(tokens.saturating_mul(1_000_000_000) / u64::from(self.rate));
+        let mut next_free = self.next_free.lock().unwrap_or_else(|e| e.into_inner());
+        *next_free = (*next_free).max(now) + cost;
+        next_free
+            .saturating_duration_since(now)
+            .saturating_sub(PACE_BURST)
+    }
+}
+
+/// Estimated output tokens in one upstream SSE event: the text, reasoning
+/// and tool arguments it carries, in any of the supported families' shapes.
+pub fn estimate_output_tokens(data: &str) -> u64 {
+    fn text_chars(value: &Value) -> usize {
+        match value {
+            Value::Array(items) => items.iter().map(text_chars).sum(),
+            Value::Object(map) => map
+                .iter()
+                .map(|(key, value)| match (key.as_str(), value) {
+                    // Responses API lifecycle events repeat the whole response.
+                    ("response", _) => 0,
+                    (
+                        "text" | "content" | "delta" | "thinking" | "reasoning_content"
+                        | "partial_json" | "arguments",
+                        Value::String(s),
+                    ) => s.chars().count(),
+                    _ => text_chars(value),
+                })
+                .sum(),
+            _ => 0,
+        }
+    }
+    let Ok(event) = serde_json::from_str::<Value>(data) else {
+        return 0;
+    };
+    (text_chars(&event) as u64).div_ceil(PACE_CHARS_PER_TOKEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quota::hash_api_key;
+
+    fn key(name: &str, output_tokens_per_sec: Option<u32>) -> ApiKeyConfig {
+        ApiKeyConfig {
+            key: name.to_string(),
+            daily_token_limit: None,
+            monthly_token_limit: None,
+            requests_per_minute: None,
+            max_open_streams: None,
+            output_tokens_per_sec,
+            allowed_models: None,
+            model_aliases: Default::default(),
+            priority: Default::default(),
+            reasoning: Default::default(),
+            tenant: None,
+            client: None,
+            valid_from: None,
+            valid_until: None,
+        }
+    }
+
+    #[test]
+    fn keys_resolve_to_the_default_or_their_override() {
+        let keys = vec![
+            key("agent", None),
+            key("ci", Some(0)),
+            key("bulk", Some(10)),
+        ];
+        assert!(OutputPacer::from_config(&keys[..2], &StreamsConfig::default()).is_none());
+
+        let config = StreamsConfig {
+            output_tokens_per_sec_per_key: Some(50),
+            ..Default::default()
+        };
+        let pacer = OutputPacer::from_config(&keys, &config).unwrap();
+        assert_eq!(pacer.for_key(&hash_api_key("agent")).unwrap().rate, 50);
+        assert!(pacer.for_key(&hash_api_key("ci")).is_none());
+        assert_eq!(pacer.for_key(&hash_api_key("bulk")).unwrap().rate, 10);
+        assert_eq!(pacer.for_key(&hash_api_key("unlisted")).unwrap().rate, 50);
+    }
+
+    #[test]
+    fn streams_of_one_key_share_its_rate_after_a_burst() {
+        let keys = vec![key("agent", Some(100))];
+        let pacer = OutputPacer::from_config(&keys, &StreamsConfig::default()).unwrap();
+        let first = pacer.for_key(&hash_api_key("agent")).unwrap();
+        let second = pacer.for_key(&hash_api_key("agent")).unwrap();
+        let now = Instant::now();
+        // One second's worth goes through at once…
+        assert_eq!(first.reserve_at(60, now), Duration::ZERO);
+        assert_eq!(second.reserve_at(40, now), Duration::ZERO);
+        // …after which either stream waits for the shared budget.
+        assert_eq!(first.reserve_at(50, now), Duration::from_millis(500));
+        assert_eq!(second.reserve_at(50, now), Duration::from_secs(1));
+        // Idle time doesn't bank more than the burst.
+        let later = now + Duration::from_secs(60);
+        assert_eq!(first.reserve_at(100, later), Duration::ZERO);
+        assert_eq!(first.reserve_at(10, later), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn output_is_estimated_from_the_text_an_event_carries() {
+        let openai = r#"{"id":"chatcmpl-123","model":"gpt-4o","choices":[{"delta":{"content":"Hello, world!!"}}]}"#;
+        assert_eq!(estimate_output_tokens(openai), 4);
+        let claude = r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"{\"city\":"}}"#;
+        assert_eq!(estimate_output_tokens(claude), 2);
+        let gemini =
+            r#"{"candidates":[{"content":{"parts":[{"text":"Bonjour"}],"role":"model"}}]}"#;
+        assert_eq!(estimate_output_tokens(gemini), 2);
+        let responses_done = r#"{"type":"response.completed","response":{"output":[{"content":[{"text":"a long answer"}]}]}}"#;
+        assert_eq!(estimate_output_tokens(responses_done), 0);
+        assert_eq!(estimate_output_tokens("[DONE]"), 0);
+    }
+}
@@ -0,0 +1,130 @@
+//! Running under systemd: socket activation and readiness notification.
+//!
+//! With a `.socket` unit, systemd binds the port and passes the listener to
+//! acr (`LISTEN_PID`/`LISTEN_FDS`), so the port accepts connections while
+//! acr restarts and `bind` in the config doesn't apply. With
+//! `Type=notify`, acr reports `READY=1` once the model registry has
+//! resolved the deployments and the listener is up, so units ordered after
+//! acr start only when it can route requests.
+//!
+//! Both are no-ops when the variables aren't set, and on platforms without
+//! Unix sockets.
+
+use anyhow::Result;
+
+use crate::constants::systemd::{LISTEN_FDS_ENV, LISTEN_PID_ENV, NOTIFY_SOCKET_ENV};
+
+/// How many sockets systemd passed to process `pid`, given `LISTEN_PID` and
+/// `LISTEN_FDS`. Variables meant for another process (a parent that didn't
+/// clear them) count as none.
+pub fn passed_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    match (listen_pid, listen_fds) {
+        (Some(listen_pid), Some(listen_fds)) if listen_pid.trim() == pid.to_string() => {
+            listen_fds.trim().parse().unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+/// The listener systemd passed to this process, if any.
+#[cfg(unix)]
+pub fn inherited_listener() -> Result<Option<tokio::net::TcpListener>> {
+    use anyhow::Context;
+    use std::os::fd::FromRawFd;
+
+    let fds = passed_fds(
+        std::env::var(LISTEN_PID_ENV).ok().as_deref(),
+        std::env::var(LISTEN_FDS_ENV).ok().as_deref(),
+        std::process::id(),
+    );
+    if fds == 0 {
+        return Ok(None);
+    }
+    if fds > 1 {
+        tracing::warn!(
+            "systemd passed {} sockets; acr serves on the first and ignores the rest",
+            fds
+        );
+    }
+    // SAFETY: with LISTEN_PID naming this process, systemd guarantees the
+    // passed sockets are open from fd 3 on, and nothing else in acr claims
+    // that fd.
+    let listener =
+        unsafe { std::net::TcpListener::from_raw_fd(crate::constants::systemd::LISTEN_FDS_START) };
+    // Fails for anything but a TCP socket, e.g. `ListenStream=/run/acr.sock`.
+    listener
+        .local_addr()
+        .context("The socket systemd passed isn't a TCP listener")?;
+    listener.set_nonblocking(true)?;
+    Ok(Some(tokio::net::TcpListener::from_std(listener)?))
+}
+
+#[cfg(not(unix))]
+pub fn inherited_listener() -> Result<Option<tokio::net::TcpListener>> {
+    Ok(None)
+}
+
+/// Report `state` (e.g. `READY=1`) to systemd, when running as a
+/// `Type=notify` service. Failures are logged, never fatal.
+pub fn notify(state: &str) {
+    let Some(socket) = std::env::var_os(NOTIFY_SOCKET_ENV) else {
+        return;
+    };
+    if let Err(e) = send(&socket, state) {
+        tracing::warn!("Failed to notify systemd ({}): {:#}", state, e);
+    }
+}
+
+#[cfg(unix)]
+fn send(socket: &std::ffi::OsStr, state: &str) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let datagram = UnixDatagram::unbound()?;
+    match socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => anyhow::bail!("abstract sockets are only supported on Linux"),
+        None => {
+            datagram.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &std::ffi::OsStr, _state: &str) -> Result<()> {
+    anyhow::bail!("systemd notifications are only supported on Unix")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passed_fds_must_name_this_process() {
+        assert_eq!(passed_fds(Some("42"), Some("1"), 42), 1);
+        assert_eq!(passed_fds(Some("42"), Some("2"), 42), 2);
+        assert_eq!(passed_fds(Some("41"), Some("1"), 42), 0);
+        assert_eq!(passed_fds(None, Some("1"), 42), 0);
+        assert_eq!(passed_fds(Some("42"), None, 42), 0);
+        assert_eq!(passed_fds(Some("42"), Some("x"), 42), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn send_writes_the_state_to_the_notify_socket() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("notify");
+        let receiver = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+        send(path.as_os_str(), "READY=1").unwrap();
+        let mut buf = [0u8; 64];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+    }
+}
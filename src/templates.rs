@@ -0,0 +1,352 @@
+//! Prompt templates.
+//!
+//! `templates` in the config defines named message lists with `{{variable}}`
+//! placeholders, so a small team keeps its prompts in the router instead of
+//! in every client. A template is rendered with `POST
+//! /v1/templates/{name}/render`, or inline: a chat completions or messages
+//! request with `"template": {"name": ..., "variables": {...}}` gets the
+//! rendered messages put in front of its own before it is forwarded.
+
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::collections::BTreeSet;
+
+use crate::config::PromptTemplate;
+use crate::proxy::LlmFamily;
+
+const ROLES: [&str; 3] = ["system", "user", "assistant"];
+
+/// Why a template couldn't be rendered; shown to the client as a 400.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenderError {
+    UnknownTemplate(String),
+    MissingVariables(Vec<String>),
+    UnknownVariables(Vec<String>),
+    /// A variable value that isn't a string, number or boolean.
+    InvalidValue(String),
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownTemplate(name) => write!(f, "Template '{name}' is not configured"),
+            Self::MissingVariables(names) => {
+                write!(f, "Missing template variables: {}", names.join(", "))
+            }
+            Self::UnknownVariables(names) => {
+                write!(f, "Unknown template variables: {}", names.join(", "))
+            }
+            Self::InvalidValue(name) => write!(
+                f,
+                "Template variable '{name}' must be a string, number or boolean"
+            ),
+        }
+    }
+}
+
+/// A request's inline `template` field.
+#[derive(Debug, Deserialize)]
+pub struct TemplateRef {
+    pub name: String,
+    #[serde(default)]
+    pub variables: Map<String, Value>,
+}
+
+/// Check a template's roles and placeholders.
+pub fn validate(template: &PromptTemplate) -> anyhow::Result<()> {
+    if template.messages.is_empty() {
+        anyhow::bail!("messages is empty");
+    }
+    for (i, message) in template.messages.iter().enumerate() {
+        if !ROLES.contains(&message.role.as_str()) {
+            anyhow::bail!(
+                "messages[{i}].role '{}' is not one of {}",
+                message.role,
+                ROLES.join(", ")
+            );
+        }
+        placeholders(&message.content)
+            .map_err(|e| anyhow::anyhow!("messages[{i}].content: {e}"))?;
+    }
+    Ok(())
+}
+
+/// The template's variables and their defaults, in name order.
+pub fn variables(template: &PromptTemplate) -> Vec<(String, Option<String>)> {
+    variables_of(template)
+        .into_iter()
+        .map(|name| {
+            let default = template.defaults.get(name).cloned();
+            (name.to_string(), default)
+        })
+        .collect()
+}
+
+/// Render the template `name` with `variables` into OpenAI-style messages.
+pub fn render(
+    templates: &[PromptTemplate],
+    name: &str,
+    variables: &Map<String, Value>,
+) -> Result<Vec<Value>, RenderError> {
+    let template = templates
+        .iter()
+        .find(|t| t.name == name)
+        .ok_or_else(|| RenderError::UnknownTemplate(name.to_string()))?;
+    let declared = variables_of(template);
+    let unknown: Vec<String> = variables
+        .keys()
+        .filter(|k| !declared.contains(k.as_str()))
+        .cloned()
+        .collect();
+    if !unknown.is_empty() {
+        return Err(RenderError::UnknownVariables(unknown));
+    }
+    let missing: Vec<String> = declared
+        .iter()
+        .filter(|v| !variables.contains_key(**v) && !template.defaults.contains_key(**v))
+        .map(|v| v.to_string())
+        .collect();
+    if !missing.is_empty() {
+        return Err(RenderError::MissingVariables(missing));
+    }
+    let value = |name: &str| -> Result<String, RenderError> {
+        match variables.get(name) {
+            Some(Value::String(s)) => Ok(s.clone()),
+            Some(v @ (Value::Number(_) | Value::Bool(_))) => Ok(v.to_string()),
+            Some(_) => Err(RenderError::InvalidValue(name.to_string())),
+            None => Ok(template.defaults[name].clone()),
+        }
+    };
+    template
+        .messages
+        .iter()
+        .map(|message| {
+            let content = substitute(&message.content, &value)?;
+            Ok(json!({"role": message.role, "content": content}))
+        })
+        .collect()
+}
+
+/// Expand a request's `template` field, if it has one, into messages in
+/// front of the request's own: `messages` for OpenAI chat completions, and
+/// `system` plus `messages` for Anthropic messages.
+pub fn expand(
+    templates: &[PromptTemplate],
+    body: &mut Value,
+    client_family: LlmFamily,
+) -> Result<(), String> {
+    let Some(reference) = body.as_object_mut().and_then(|o| o.remove("template")) else {
+        return Ok(());
+    };
+    if !matches!(client_family, LlmFamily::OpenAi | LlmFamily::Claude) {
+        return Err("'template' is only supported on chat completions and messages".to_string());
+    }
+    let reference: TemplateRef =
+        serde_json::from_value(reference).map_err(|e| format!("Invalid 'template': {e}"))?;
+    let rendered =
+        render(templates, &reference.name, &reference.variables).map_err(|e| e.to_string())?;
+    let (system, mut messages): (Vec<Value>, Vec<Value>) = match client_family {
+        LlmFamily::Claude => rendered.into_iter().partition(|m| m["role"] == "system"),
+        _ => (Vec::new(), rendered),
+    };
+    if !system.is_empty() {
+        let text = system
+            .iter()
+            .filter_map(|m| m["content"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        body["system"] = match body.get("system").cloned() {
+            None | Some(Value::Null) => Value::String(text),
+            Some(Value::String(own)) => Value::String(format!("{text}\n\n{own}")),
+            Some(Value::Array(mut blocks)) => {
+                blocks.insert(0, json!({"type": "text", "text": text}));
+                Value::Array(blocks)
+            }
+            Some(_) => return Err("Invalid 'system'".to_string()),
+        };
+    }
+    match body.get_mut("messages") {
+        Some(Value::Array(own)) => {
+            messages.append(own);
+            *own = messages;
+        }
+        None => body["messages"] = Value::Array(messages),
+        Some(_) => return Err("Invalid 'messages'".to_string()),
+    }
+    Ok(())
+}
+
+fn variables_of(template: &PromptTemplate) -> BTreeSet<&str> {
+    template
+        .messages
+        .iter()
+        .flat_map(|m| placeholders(&m.content).unwrap_or_default())
+        .collect()
+}
+
+/// The variable names in `content`: `{{name}}`, spaces inside the braces
+/// allowed, names made of letters, digits, `_` and `-`.
+fn placeholders(content: &str) -> Result<Vec<&str>, String> {
+    let mut names = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| "unclosed '{{'".to_string())?;
+        let name = after[..end].trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!("invalid placeholder '{{{{{}}}}}'", &after[..end]));
+        }
+        names.push(name);
+        rest = &after[end + 2..];
+    }
+    Ok(names)
+}
+
+fn substitute(
+    content: &str,
+    value: &impl Fn(&str) -> Result<String, RenderError>,
+) -> Result<String, RenderError> {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    // Templates are validated at startup, so every `{{` here is closed.
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        out.push_str(&value(after[..end].trim())?);
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates() -> Vec<PromptTemplate> {
+        serde_yaml_ng::from_str(
+            r#"
+- name: summarize
+  messages:
+    - role: system
+      content: "You summarize {{kind}} in {{ language }}."
+    - role: user
+      content: "Summarize this:\n{{text}}"
+  defaults:
+    language: English
+"#,
+        )
+        .unwrap()
+    }
+
+    fn vars(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn variables_are_filled_in_with_defaults_for_the_rest() {
+        let templates = templates();
+        assert_eq!(
+            variables(&templates[0]),
+            [
+                ("kind".to_string(), None),
+                ("language".to_string(), Some("English".to_string())),
+                ("text".to_string(), None),
+            ]
+        );
+        let messages = render(
+            &templates,
+            "summarize",
+            &vars(json!({"kind": "tickets", "text": "Printer on fire {{x}}"})),
+        )
+        .unwrap();
+        assert_eq!(messages[0]["content"], "You summarize tickets in English.");
+        // Values aren't expanded again.
+        assert_eq!(
+            messages[1]["content"],
+            "Summarize this:\nPrinter on fire {{x}}"
+        );
+    }
+
+    #[test]
+    fn bad_variables_are_reported_by_name() {
+        let templates = templates();
+        assert_eq!(
+            render(&templates, "summarize", &vars(json!({"kind": "x"}))),
+            Err(RenderError::MissingVariables(vec!["text".to_string()]))
+        );
+        assert_eq!(
+            render(
+                &templates,
+                "summarize",
+                &vars(json!({"kind": "x", "text": "y", "tone": "dry"}))
+            ),
+            Err(RenderError::UnknownVariables(vec!["tone".to_string()]))
+        );
+        assert_eq!(
+            render(
+                &templates,
+                "summarize",
+                &vars(json!({"kind": [], "text": "y"}))
+            ),
+            Err(RenderError::InvalidValue("kind".to_string()))
+        );
+        assert_eq!(
+            render(&templates, "translate", &Map::new()),
+            Err(RenderError::UnknownTemplate("translate".to_string()))
+        );
+    }
+
+    #[test]
+    fn inline_templates_go_in_front_of_the_request_messages() {
+        let templates = templates();
+        let template = json!({"name": "summarize", "variables": {"kind": "logs", "text": "..."}});
+        let mut openai = json!({
+            "model": "gpt-4o",
+            "template": template,
+            "messages": [{"role": "user", "content": "Be brief."}]
+        });
+        expand(&templates, &mut openai, LlmFamily::OpenAi).unwrap();
+        assert!(openai.get("template").is_none());
+        let roles: Vec<_> = openai["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, ["system", "user", "user"]);
+
+        let mut claude = json!({"template": template, "system": "Answer in bullets."});
+        expand(&templates, &mut claude, LlmFamily::Claude).unwrap();
+        assert_eq!(
+            claude["system"],
+            "You summarize logs in English.\n\nAnswer in bullets."
+        );
+        assert_eq!(claude["messages"].as_array().unwrap().len(), 1);
+
+        let mut gemini = json!({"template": template});
+        assert!(expand(&templates, &mut gemini, LlmFamily::Gemini).is_err());
+    }
+
+    #[test]
+    fn templates_are_validated() {
+        let mut template = templates().remove(0);
+        assert!(validate(&template).is_ok());
+        template.messages[0].content = "Hello {{name".to_string();
+        assert!(validate(&template).is_err());
+        template.messages[0].content = "Hello {{first name}}".to_string();
+        assert!(validate(&template).is_err());
+        template.messages[0].content = "Hello".to_string();
+        template.messages[0].role = "tool".to_string();
+        assert!(validate(&template).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+//! Pre-emptive throttling against AI Core tenant quotas.
+//!
+//! An AI Core tenant has a cap on inference traffic across all of its
+//! deployments, which acr otherwise only discovers through upstream 429s.
+//! AI Core doesn't expose the cap through its API, so it is configured as
+//! `providers[].quota`. acr counts what it sends to each provider over the
+//! last minute: a provider past `LOW_SHARE` of either limit goes behind the
+//! others, and one at its limit is skipped until enough of the window has
+//! passed, exactly as if it had answered 429.
+//!
+//! Tokens are charged when a request is sent, estimated from its text and
+//! requested output, so the count leads the upstream's own. Other clients
+//! of the same tenant aren't seen; leave them room when setting the limits.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::config::{Provider, ProviderQuota};
+use crate::constants::tenant_quota::{LOW_SHARE, WINDOW};
+use crate::upstream_limits::Pressure;
+
+/// Requests sent to one provider: when, and their estimated tokens; oldest
+/// first.
+type Sent = VecDeque<(Instant, u64)>;
+
+/// Requests sent per provider over the last minute; cheap to clone.
+#[derive(Debug, Clone)]
+pub struct TenantQuotas {
+    limits: Arc<HashMap<String, ProviderQuota>>,
+    sent: Arc<Mutex<HashMap<String, Sent>>>,
+}
+
+impl TenantQuotas {
+    /// `None` when no provider has a quota configured.
+    pub fn from_config(providers: &[Provider]) -> Option<Self> {
+        let limits: HashMap<String, ProviderQuota> = providers
+            .iter()
+            .filter(|p| {
+                p.quota.requests_per_minute.is_some() || p.quota.tokens_per_minute.is_some()
+            })
+            .map(|p| (p.name.clone(), p.quota))
+            .collect();
+        if limits.is_empty() {
+            return None;
+        }
+        Some(Self {
+            limits: Arc::new(limits),
+            sent: Arc::default(),
+        })
+    }
+
+    /// The same counts for a reloaded config, or `None` if no provider has
+    /// a quota any more. Requests already sent to a provider that keeps its
+    /// quota still count toward the new limits.
+    pub fn reconfigure(&self, providers: &[Provider]) -> Option<Self> {
+        let reconfigured = Self {
+            sent: self.sent.clone(),
+            ..Self::from_config(providers)?
+        };
+        reconfigured
+            .sent
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|provider, _| reconfigured.limits.contains_key(provider));
+        Some(reconfigured)
+    }
+
+    /// Count a request of `tokens` estimated tokens against `provider`.
+    pub fn charge(&self, provider: &str, tokens: u64) {
+        self.charge_at(provider, tokens, Instant::now());
+    }
+
+    /// How close `provider` is to its quota.
+    pub fn pressure(&self, provider: &str) -> Pressure {
+        self.pressure_at(provider, Instant::now())
+    }
+
+    fn charge_at(&self, provider: &str, tokens: u64, now: Instant) {
+        if !self.limits.contains_key(provider) {
+            return;
+        }
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        let window = sent.entry(provider.to_string()).or_default();
+        expire(window, now);
+        window.push_back((now, tokens));
+    }
+
+    fn pressure_at(&self, provider: &str, now: Instant) -> Pressure {
+        let Some(quota) = self.limits.get(provider) else {
+            return Pressure::Clear;
+        };
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(window) = sent.get_mut(provider) else {
+            return Pressure::Clear;
+        };
+        expire(window, now);
+        let requests = quota.requests_per_minute.map_or(Pressure::Clear, |limit| {
+            limit_pressure(window, limit, |_| 1, now)
+        });
+        let tokens = quota.tokens_per_minute.map_or(Pressure::Clear, |limit| {
+            limit_pressure(window, limit, |t| t, now)
+        });
+        requests.worse(tokens)
+    }
+}
+
+/// Pressure from one limit, with each sent request costing `cost(tokens)`.
+/// At the limit, the wait is until enough of the oldest requests have left
+/// the window to go below it.
+fn limit_pressure(window: &Sent, limit: u64, cost: impl Fn(u64) -> u64, now: Instant) -> Pressure {
+    let mut used: u64 = window.iter().map(|&(_, tokens)| cost(tokens)).sum();
+    if (used as f64) < limit as f64 * LOW_SHARE {
+        return Pressure::Clear;
+    }
+    if used < limit {
+        return Pressure::Low;
+    }
+    for &(at, tokens) in window {
+        used -= cost(tokens);
+        if used < limit {
+            return Pressure::CoolingDown((at + WINDOW).saturating_duration_since(now));
+        }
+    }
+    Pressure::Clear
+}
+
+fn expire(window: &mut Sent, now: Instant) {
+    while let Some(&(at, _)) = window.front() {
+        if now.saturating_duration_since(at) < WINDOW {
+            break;
+        }
+        window.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn quotas(requests_per_minute: Option<u64>, tokens_per_minute: Option<u64>) -> TenantQuotas {
+        let providers: Vec<Provider> = serde_yaml_ng::from_str(
+            r#"
+- name: eu
+  uaa_token_url: https://eu.example.com/oauth/token
+  uaa_client_id: id
+  uaa_client_secret: secret
+  genai_api_url: https://api.eu.example.com
+- name: us
+  uaa_token_url: https://us.example.com/oauth/token
+  uaa_client_id: id
+  uaa_client_secret: secret
+  genai_api_url: https://api.us.example.com
+"#,
+        )
+        .unwrap();
+        assert!(TenantQuotas::from_config(&providers).is_none());
+        let mut providers = providers;
+        providers[0].quota = ProviderQuota {
+            requests_per_minute,
+            tokens_per_minute,
+        };
+        TenantQuotas::from_config(&providers).unwrap()
+    }
+
+    #[test]
+    fn requests_near_the_limit_demote_and_at_it_skip() {
+        let quotas = quotas(Some(10), None);
+        let now = Instant::now();
+        for i in 0..8 {
+            quotas.charge_at("eu", 100, now + Duration::from_secs(i));
+        }
+        let later = now + Duration::from_secs(10);
+        assert_eq!(quotas.pressure_at("eu", later), Pressure::Clear);
+        quotas.charge_at("eu", 100, later);
+        assert_eq!(quotas.pressure_at("eu", later), Pressure::Low);
+        quotas.charge_at("eu", 100, later);
+        // The first request leaves the window 50s from now.
+        assert_eq!(
+            quotas.pressure_at("eu", later),
+            Pressure::CoolingDown(Duration::from_secs(50))
+        );
+        assert_eq!(
+            quotas.pressure_at("eu", now + Duration::from_secs(60)),
+            Pressure::Low
+        );
+        // Providers without a quota aren't tracked.
+        quotas.charge_at("us", 100, now);
+        assert_eq!(quotas.pressure_at("us", now), Pressure::Clear);
+    }
+
+    #[test]
+    fn reconfigured_limits_keep_what_was_sent() {
+        let quotas = quotas(Some(2), None);
+        let now = Instant::now();
+        quotas.charge_at("eu", 100, now);
+        assert_eq!(quotas.pressure_at("eu", now), Pressure::Clear);
+
+        let mut providers: Vec<Provider> = Vec::new();
+        for name in ["eu", "us"] {
+            let mut provider: Provider = serde_yaml_ng::from_str(&format!(
+                "{{name: {name}, uaa_token_url: u, uaa_client_id: i, uaa_client_secret: s, genai_api_url: g}}"
+            ))
+            .unwrap();
+            provider.quota.requests_per_minute = Some(1);
+            providers.push(provider);
+        }
+        let lowered = quotas.reconfigure(&providers).unwrap();
+        assert!(matches!(
+            lowered.pressure_at("eu", now),
+            Pressure::CoolingDown(_)
+        ));
+        // A provider that gains a quota starts with nothing sent.
+        assert_eq!(lowered.pressure_at("us", now), Pressure::Clear);
+
+        providers
+            .iter_mut()
+            .for_each(|p| p.quota = ProviderQuota::default());
+        assert!(lowered.reconfigure(&providers).is_none());
+    }
+
+    #[test]
+    fn tokens_wait_until_enough_of_the_window_has_passed() {
+        let quotas = quotas(None, Some(10_000));
+        let now = Instant::now();
+        quotas.charge_at("eu", 4_000, now);
+        quotas.charge_at("eu", 4_000, now + Duration::from_secs(20));
+        quotas.charge_at("eu", 4_000, now + Duration::from_secs(40));
+        let at = now + Duration::from_secs(45);
+        // Dropping the first request is enough: 8,000 < 10,000.
+        assert_eq!(
+            quotas.pressure_at("eu", at),
+            Pressure::CoolingDown(Duration::from_secs(15))
+        );
+        assert_eq!(
+            quotas.pressure_at("eu", now + Duration::from_secs(61)),
+            Pressure::Clear
+        );
+    }
+}
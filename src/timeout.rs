@@ -0,0 +1,161 @@
+//! Per-route and per-model request timeouts.
+//!
+//! A middleware on every route answers with 504 once a request has gone
+//! longer than its budget without producing a response, instead of leaving
+//! the client waiting until the HTTP client's own timeout fires. The budget
+//! comes from `timeouts` in config: a `models` pattern matching the requested
+//! model wins over the route's entry, which wins over `default_secs`. For
+//! streaming responses the budget covers the wait for the stream to start;
+//! once events flow, the response is no longer subject to it.
+
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{FromRequestParts, MatchedPath, RawPathParams, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+use crate::config::TimeoutsConfig;
+use crate::registry::glob_matches;
+use crate::routes::{AppError, AppState};
+
+/// The budget for a request to `route` for the first of `models` that a
+/// `timeouts.models` pattern matches; `None` when the timeout is disabled.
+pub fn budget(config: &TimeoutsConfig, route: &str, models: &[&str]) -> Option<Duration> {
+    let model_secs = models.iter().find_map(|model| {
+        config
+            .models
+            .iter()
+            .filter_map(|(pattern, secs)| glob_matches(pattern, model).map(|spec| (spec, *secs)))
+            .max()
+            .map(|(_, secs)| secs)
+    });
+    let secs = model_secs
+        .or_else(|| config.routes.get(route).copied())
+        .unwrap_or(config.default_secs);
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Middleware enforcing the request's budget.
+pub async fn enforce(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    // `timeouts.routes` is keyed by route without `mount.path_prefix`.
+    let route = match route.strip_prefix(state.config.path_prefix()) {
+        Some(unprefixed) if unprefixed.starts_with('/') => unprefixed.to_string(),
+        _ => route,
+    };
+
+    // Finding the model can mean buffering the body, so only look when a
+    // per-model timeout could apply.
+    let (request, model) = if state.config.timeouts.models.is_empty() {
+        (request, None)
+    } else {
+        match requested_model(request, state.config.max_request_body_bytes()).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        }
+    };
+    let resolved = model
+        .as_deref()
+        .and_then(|model| crate::proxy::normalize_model(model, &state.model_registry).ok());
+    let models: Vec<&str> = model
+        .iter()
+        .chain(resolved.iter())
+        .map(String::as_str)
+        .collect();
+
+    let Some(budget) = budget(&state.config.timeouts, &route, &models) else {
+        return next.run(request).await;
+    };
+    match tokio::time::timeout(budget, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!(
+                "Request to {} ({}) timed out after {}s",
+                route,
+                model.as_deref().unwrap_or("no model"),
+                budget.as_secs()
+            );
+            AppError::RequestTimeout {
+                timeout_secs: budget.as_secs(),
+            }
+            .into_response()
+        }
+    }
+}
+
+/// The model named in the path (`{model}`, `{model_operation}`) or in the
+/// JSON body's `model` field. The body is buffered and put back.
+async fn requested_model(
+    request: Request,
+    max_body_bytes: usize,
+) -> Result<(Request, Option<String>), Response> {
+    let (mut parts, body) = request.into_parts();
+    let from_path = RawPathParams::from_request_parts(&mut parts, &())
+        .await
+        .ok()
+        .and_then(|params| {
+            params.iter().find_map(|(key, value)| match key {
+                "model" => Some(value.to_string()),
+                "model_operation" => value.split_once(':').map(|(model, _)| model.to_string()),
+                _ => None,
+            })
+        });
+    if from_path.is_some() {
+        return Ok((Request::from_parts(parts, body), from_path));
+    }
+
+    let bytes = axum::body::to_bytes(body, max_body_bytes)
+        .await
+        .map_err(|_| crate::body_limit::too_large(max_body_bytes))?;
+    let model = serde_json::from_slice::<Value>(&bytes)
+        .ok()
+        .and_then(|body| body.get("model")?.as_str().map(str::to_string));
+    Ok((Request::from_parts(parts, Body::from(bytes)), model))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_specific_setting_wins() {
+        let mut config = TimeoutsConfig::default();
+        config.models.insert("gpt-*".to_string(), 120);
+        config.models.insert("gpt-5-pro".to_string(), 0);
+
+        // Built-in route defaults: short for embeddings, long otherwise.
+        assert_eq!(
+            budget(&config, "/v1/embeddings", &[]),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            budget(&config, "/v1/messages", &["claude-sonnet-4-6"]),
+            Some(Duration::from_secs(600))
+        );
+
+        // A model pattern beats the route; the closer pattern beats `gpt-*`,
+        // and 0 turns the timeout off.
+        assert_eq!(
+            budget(&config, "/v1/embeddings", &["gpt-4o"]),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            budget(&config, "/v1/chat/completions", &["gpt-5-pro"]),
+            None
+        );
+
+        // The resolved name counts when the requested one matches nothing.
+        assert_eq!(
+            budget(&config, "/v1/chat/completions", &["my-alias", "gpt-4o"]),
+            Some(Duration::from_secs(120))
+        );
+    }
+}
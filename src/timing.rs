@@ -0,0 +1,108 @@
+//! Per-phase request timings.
+//!
+//! A slow request can be slow in acr (key validation, a provider token
+//! refresh, deployment resolution) or upstream. Each attempt records how long
+//! each phase took; the breakdown is logged on the `Proxy done` line and
+//! returned in a `server-timing` header (`auth;dur=0.02, token;dur=412.80,
+//! ...`), which browser dev tools and curl users can read directly. The
+//! stream phase only ends after the headers are sent, so it is only logged.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use axum::http::{HeaderMap, HeaderValue};
+
+use crate::constants::api::SERVER_TIMING_HEADER;
+
+/// Phase durations of one upstream attempt; unset phases didn't run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PhaseTimings {
+    /// Client API key validation
+    pub auth: Option<Duration>,
+    /// Provider OAuth token, from cache or UAA
+    pub token: Option<Duration>,
+    /// Model and deployment resolution
+    pub resolve: Option<Duration>,
+    /// Upstream time to response headers
+    pub ttfb: Option<Duration>,
+    /// Upstream response headers to end of stream
+    pub stream: Option<Duration>,
+}
+
+impl PhaseTimings {
+    /// Run `phase` and return its result with how long it took.
+    pub async fn time<T>(phase: impl Future<Output = T>) -> (T, Duration) {
+        let start = Instant::now();
+        let result = phase.await;
+        (result, start.elapsed())
+    }
+
+    fn phases(&self) -> impl Iterator<Item = (&'static str, Duration)> {
+        [
+            ("auth", self.auth),
+            ("token", self.token),
+            ("resolve", self.resolve),
+            ("ttfb", self.ttfb),
+            ("stream", self.stream),
+        ]
+        .into_iter()
+        .filter_map(|(name, duration)| duration.map(|d| (name, d)))
+    }
+
+    /// The `server-timing` header value.
+    pub fn server_timing(&self) -> String {
+        self.phases()
+            .map(|(name, d)| format!("{name};dur={:.2}", millis(d)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn insert_header(&self, headers: &mut HeaderMap) {
+        if self.phases().next().is_some()
+            && let Ok(value) = HeaderValue::from_str(&self.server_timing())
+        {
+            headers.insert(SERVER_TIMING_HEADER, value);
+        }
+    }
+}
+
+/// `auth=0.02ms token=412.80ms ...` for log lines.
+impl fmt::Display for PhaseTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, d)) in self.phases().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{name}={:.2}ms", millis(d))?;
+        }
+        Ok(())
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_only_recorded_phases() {
+        let timings = PhaseTimings {
+            auth: Some(Duration::from_micros(20)),
+            token: Some(Duration::from_micros(412_800)),
+            ttfb: Some(Duration::from_millis(950)),
+            ..Default::default()
+        };
+        assert_eq!(
+            timings.server_timing(),
+            "auth;dur=0.02, token;dur=412.80, ttfb;dur=950.00"
+        );
+        assert_eq!(
+            timings.to_string(),
+            "auth=0.02ms token=412.80ms ttfb=950.00ms"
+        );
+        assert_eq!(PhaseTimings::default().server_timing(), "");
+    }
+}
@@ -3,14 +3,15 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use reqwest::Client;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
 use subtle::ConstantTimeEq;
 use tokio::sync::{Mutex, RwLock};
 
-use crate::config::Provider;
+use crate::config::{ApiKeyConfig, Provider};
 
 #[derive(Debug, Deserialize)]
 struct TokenResponse {
@@ -18,7 +19,7 @@ struct TokenResponse {
     expires_in: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct TokenInfo {
     token: String,
     expires_at: DateTime<Utc>,
@@ -30,47 +31,218 @@ impl TokenInfo {
     }
 }
 
+/// A client API key, by its SHA-256, and the window it is accepted in.
+#[derive(Debug, Clone)]
+struct ClientKey {
+    digest: [u8; 32],
+    valid_from: Option<DateTime<Utc>>,
+    valid_until: Option<DateTime<Utc>>,
+}
+
+impl ClientKey {
+    fn is_active_at(&self, now: DateTime<Utc>) -> bool {
+        self.valid_from.is_none_or(|from| from <= now)
+            && self.valid_until.is_none_or(|until| now < until)
+    }
+}
+
+/// The token cache file, encrypted with the secrets passphrase.
+struct CacheFile {
+    path: PathBuf,
+    passphrase: String,
+    /// Held while writing, so the last writer leaves the newest tokens.
+    write_lock: std::sync::Mutex<()>,
+}
+
+impl std::fmt::Debug for CacheFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CacheFile")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CacheFile {
+    fn load(&self) -> Result<HashMap<String, TokenInfo>> {
+        let sealed = std::fs::read(&self.path)?;
+        let plaintext = crate::secrets::decrypt(&sealed, &self.passphrase)?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    /// Write the current tokens, readable only by the owner. Blocks for
+    /// the passphrase's key derivation.
+    fn save(&self, tokens: &RwLock<HashMap<String, TokenInfo>>) -> Result<()> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let snapshot = serde_json::to_vec(&*tokens.blocking_read())?;
+        let sealed = crate::secrets::encrypt(&snapshot, &self.passphrase)?;
+        if let Some(dir) = self.path.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, sealed)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))?;
+        }
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// Cache key of the token for `provider`'s credentials.
+fn token_key(provider: &Provider) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(provider.uaa_token_url.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(provider.uaa_client_id.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(provider.uaa_client_secret.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
 /// Token manager that handles OAuth tokens for multiple providers.
 #[derive(Debug, Clone)]
 pub struct TokenManager {
     /// Set of valid API keys for request authentication
-    api_keys: Vec<String>,
+    api_keys: Vec<ClientKey>,
     /// Cached tokens keyed by provider credentials hash
     tokens: Arc<RwLock<HashMap<String, TokenInfo>>>,
     /// Per-key mutexes to serialize concurrent refresh attempts for the same provider
     refresh_locks: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
     /// HTTP client for token requests
     client: Client,
+    /// Where tokens are persisted between restarts, if anywhere
+    cache_file: Option<Arc<CacheFile>>,
+    /// Redis where tokens are shared with other routers, if anywhere
+    #[cfg(feature = "cluster")]
+    cluster: Option<crate::cluster::Cluster>,
 }
 
 impl TokenManager {
-    /// Create a new token manager with the given API keys.
+    /// Create a new token manager with the given API keys, valid at all times.
     pub fn new(api_keys: Vec<String>) -> Self {
+        let api_keys = api_keys
+            .into_iter()
+            .map(|key| ClientKey {
+                digest: Sha256::digest(key.as_bytes()).into(),
+                valid_from: None,
+                valid_until: None,
+            })
+            .collect();
+        Self::with_keys(api_keys)
+    }
+
+    /// Create a token manager for the configured API keys, plaintext or
+    /// hashed, honoring their `valid_from` / `valid_until` windows.
+    pub fn from_api_keys(api_keys: &[ApiKeyConfig]) -> Self {
+        let api_keys = api_keys
+            .iter()
+            .filter_map(|k| {
+                Some(ClientKey {
+                    digest: k.digest()?,
+                    valid_from: k.valid_from,
+                    valid_until: k.valid_until,
+                })
+            })
+            .collect();
+        Self::with_keys(api_keys)
+    }
+
+    fn with_keys(api_keys: Vec<ClientKey>) -> Self {
         Self {
             api_keys,
             tokens: Arc::new(RwLock::new(HashMap::new())),
             refresh_locks: Arc::new(Mutex::new(HashMap::new())),
             client: Client::new(),
+            cache_file: None,
+            #[cfg(feature = "cluster")]
+            cluster: None,
+        }
+    }
+
+    /// Persist tokens to `path`, encrypted with `passphrase`, and start
+    /// with the still-valid tokens already there. A file that can't be
+    /// read is replaced on the next refresh.
+    pub fn with_cache_file(mut self, path: &str, passphrase: String) -> Self {
+        let cache_file = CacheFile {
+            path: PathBuf::from(path),
+            passphrase,
+            write_lock: std::sync::Mutex::new(()),
+        };
+        if cache_file.path.exists() {
+            match cache_file.load() {
+                Ok(mut tokens) => {
+                    tokens.retain(|_, token| token.is_valid());
+                    tracing::info!("Loaded {} cached OAuth tokens from {}", tokens.len(), path);
+                    self.tokens = Arc::new(RwLock::new(tokens));
+                }
+                Err(e) => tracing::warn!("Ignoring token cache {}: {:#}", path, e),
+            }
+        }
+        self.cache_file = Some(Arc::new(cache_file));
+        self
+    }
+
+    /// Share tokens with the other routers using `cluster`: a token one of
+    /// them fetched is used instead of asking UAA for another.
+    #[cfg(feature = "cluster")]
+    pub fn with_cluster(mut self, cluster: crate::cluster::Cluster) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// The same manager for a reloaded config: `api_keys` replace the
+    /// accepted keys, and cached tokens for credentials no longer among
+    /// `providers` are dropped, so a rotated secret is never used again.
+    /// Tokens for unchanged credentials carry over, shared with `self`.
+    pub async fn reconfigure(&self, api_keys: &[ApiKeyConfig], providers: &[Provider]) -> Self {
+        let current: HashSet<String> = providers.iter().map(token_key).collect();
+        let dropped = {
+            let mut tokens = self.tokens.write().await;
+            let before = tokens.len();
+            tokens.retain(|key, _| current.contains(key));
+            before - tokens.len()
+        };
+        if dropped > 0 {
+            tracing::info!(
+                "Dropped {} cached OAuth token(s) for changed credentials",
+                dropped
+            );
+        }
+        Self {
+            api_keys: Self::from_api_keys(api_keys).api_keys,
+            ..self.clone()
         }
     }
 
-    /// Check if an API key is valid using constant-time comparison.
-    /// The special "internal" key and all stored keys are checked in a
-    /// single uniform loop to avoid timing side-channels.
+    /// Check if an API key is valid using constant-time comparison of
+    /// SHA-256 digests, so keys stored hashed check the same way as
+    /// plaintext ones. The special "internal" key and all stored keys are
+    /// checked in a single uniform loop to avoid timing side-channels.
+    /// Stored keys outside their validity window don't match.
     pub fn is_valid_api_key(&self, api_key: &str) -> bool {
-        let input_bytes = api_key.as_bytes();
+        self.is_valid_api_key_at(api_key, Utc::now())
+    }
+
+    fn is_valid_api_key_at(&self, api_key: &str, now: DateTime<Utc>) -> bool {
+        let input: [u8; 32] = Sha256::digest(api_key.as_bytes()).into();
         let mut found = 0u8;
 
         // Build a single iterator over "internal" + all stored keys
-        let internal_key: &str = "internal";
-        let all_keys =
-            std::iter::once(internal_key).chain(self.api_keys.iter().map(|s| s.as_str()));
-
-        for stored_key in all_keys {
-            let stored_bytes = stored_key.as_bytes();
-            if input_bytes.len() == stored_bytes.len() {
-                found |= input_bytes.ct_eq(stored_bytes).unwrap_u8();
-            }
+        let internal: [u8; 32] = Sha256::digest(b"internal").into();
+        let all_keys = std::iter::once((internal, true)).chain(
+            self.api_keys
+                .iter()
+                .map(|k| (k.digest, k.is_active_at(now))),
+        );
+
+        for (stored, active) in all_keys {
+            found |= input.ct_eq(&stored).unwrap_u8() & u8::from(active);
         }
         found != 0
     }
@@ -86,15 +258,7 @@ impl TokenManager {
             return Ok(None);
         }
 
-        let token_key = {
-            let mut hasher = Sha256::new();
-            hasher.update(provider.uaa_token_url.as_bytes());
-            hasher.update(b"\0");
-            hasher.update(provider.uaa_client_id.as_bytes());
-            hasher.update(b"\0");
-            hasher.update(provider.uaa_client_secret.as_bytes());
-            format!("{:x}", hasher.finalize())
-        };
+        let token_key = token_key(provider);
 
         // Fast path: check cache under read lock
         {
@@ -126,13 +290,7 @@ impl TokenManager {
         }
 
         // Refresh token
-        let new_token = self
-            .refresh_token(
-                &provider.uaa_token_url,
-                &provider.uaa_client_id,
-                &provider.uaa_client_secret,
-            )
-            .await?;
+        let new_token = self.fetch_token(&token_key, provider).await?;
 
         let token_value = new_token.token.clone();
 
@@ -141,10 +299,98 @@ impl TokenManager {
             let mut tokens = self.tokens.write().await;
             tokens.insert(token_key, new_token);
         }
+        if let Some(cache_file) = &self.cache_file {
+            let cache_file = cache_file.clone();
+            let tokens = self.tokens.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = cache_file.save(&tokens) {
+                    tracing::warn!("Failed to update the token cache: {:#}", e);
+                }
+            });
+        }
 
         Ok(Some(token_value))
     }
 
+    /// A new token for `provider`, from UAA or, in cluster mode, from the
+    /// router that fetched it.
+    async fn fetch_token(&self, token_key: &str, provider: &Provider) -> Result<TokenInfo> {
+        #[cfg(feature = "cluster")]
+        if let Some(cluster) = &self.cluster {
+            return self.fetch_shared_token(cluster, token_key, provider).await;
+        }
+        #[cfg(not(feature = "cluster"))]
+        let _ = token_key;
+        self.refresh_token(
+            &provider.uaa_token_url,
+            &provider.uaa_client_id,
+            &provider.uaa_client_secret,
+        )
+        .await
+    }
+
+    /// Take the shared token from Redis if it is still valid. Otherwise
+    /// fetch one from UAA and publish it, unless another router is already
+    /// fetching one, in which case wait a little for that one. Redis errors
+    /// make this router fetch its own.
+    #[cfg(feature = "cluster")]
+    async fn fetch_shared_token(
+        &self,
+        cluster: &crate::cluster::Cluster,
+        token_key: &str,
+        provider: &Provider,
+    ) -> Result<TokenInfo> {
+        use crate::constants::cluster::{TOKEN_POLL_INTERVAL_MS, TOKEN_WAIT_MS};
+        use std::time::Duration;
+
+        let owner = uuid::Uuid::new_v4().to_string();
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(TOKEN_WAIT_MS);
+        loop {
+            match cluster.shared_token(token_key).await {
+                Ok(Some((token, expires_at))) => {
+                    let shared = TokenInfo { token, expires_at };
+                    if shared.is_valid() {
+                        tracing::debug!(
+                            "Using the shared token for client id: {}",
+                            provider.uaa_client_id
+                        );
+                        return Ok(shared);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    cluster.report_error(&e);
+                    break;
+                }
+            }
+            match cluster.claim_token_refresh(token_key, &owner).await {
+                Ok(false) if tokio::time::Instant::now() < deadline => {
+                    tokio::time::sleep(Duration::from_millis(TOKEN_POLL_INTERVAL_MS)).await;
+                }
+                Ok(_) => break,
+                Err(e) => {
+                    cluster.report_error(&e);
+                    break;
+                }
+            }
+        }
+
+        let token = self
+            .refresh_token(
+                &provider.uaa_token_url,
+                &provider.uaa_client_id,
+                &provider.uaa_client_secret,
+            )
+            .await?;
+        if let Err(e) = cluster
+            .publish_token(token_key, &token.token, token.expires_at, &owner)
+            .await
+        {
+            cluster.report_error(&e);
+        }
+        Ok(token)
+    }
+
     async fn refresh_token(
         &self,
         url: &str,
@@ -224,6 +470,111 @@ mod tests {
         assert!(tm.is_valid_api_key("internal"));
     }
 
+    #[test]
+    fn test_keys_only_valid_within_their_window() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let keys: Vec<ApiKeyConfig> = serde_yaml_ng::from_str(
+            r#"
+- key: old-key
+  client: ci
+  valid_until: 2026-11-01T00:00:00Z
+- key: new-key
+  client: ci
+  valid_from: 2026-10-15T00:00:00Z
+"#,
+        )
+        .unwrap();
+        let tm = TokenManager::from_api_keys(&keys);
+
+        let before = at("2026-10-01T00:00:00Z");
+        assert!(tm.is_valid_api_key_at("old-key", before));
+        assert!(!tm.is_valid_api_key_at("new-key", before));
+
+        let overlap = at("2026-10-20T00:00:00Z");
+        assert!(tm.is_valid_api_key_at("old-key", overlap));
+        assert!(tm.is_valid_api_key_at("new-key", overlap));
+
+        let after = at("2026-11-01T00:00:00Z");
+        assert!(!tm.is_valid_api_key_at("old-key", after));
+        assert!(tm.is_valid_api_key_at("new-key", after));
+    }
+
+    #[test]
+    fn test_hashed_keys_match_their_plaintext() {
+        let keys: Vec<ApiKeyConfig> = serde_yaml_ng::from_str(&format!(
+            "- key: sha256:{:x}",
+            Sha256::digest(b"acr-secret")
+        ))
+        .unwrap();
+        let tm = TokenManager::from_api_keys(&keys);
+        assert!(tm.is_valid_api_key("acr-secret"));
+        assert!(!tm.is_valid_api_key(&keys[0].key));
+        assert_eq!(keys[0].key_hash(), crate::quota::hash_api_key("acr-secret"));
+    }
+
+    #[tokio::test]
+    async fn test_reconfigure_drops_tokens_for_rotated_credentials() {
+        let provider = |secret: &str| -> Provider {
+            serde_yaml_ng::from_str(&format!(
+                "name: {secret}\nuaa_token_url: https://uaa.example.com/oauth/token\n\
+                 uaa_client_id: client\nuaa_client_secret: {secret}\n\
+                 genai_api_url: https://api.example.com\nresource_group: default"
+            ))
+            .unwrap()
+        };
+        let (kept, rotated) = (provider("kept"), provider("rotated"));
+        let tm = TokenManager::new(vec!["old-key".into()]);
+        for p in [&kept, &rotated] {
+            tm.tokens.write().await.insert(
+                token_key(p),
+                TokenInfo {
+                    token: format!("{}-token", p.name),
+                    expires_at: Utc::now() + chrono::Duration::seconds(3600),
+                },
+            );
+        }
+
+        let keys: Vec<ApiKeyConfig> = serde_yaml_ng::from_str("- key: new-key").unwrap();
+        let reconfigured = tm
+            .reconfigure(&keys, &[kept.clone(), provider("new")])
+            .await;
+        assert!(reconfigured.is_valid_api_key("new-key"));
+        assert!(!reconfigured.is_valid_api_key("old-key"));
+        let tokens = reconfigured.tokens.read().await;
+        assert!(tokens.contains_key(&token_key(&kept)));
+        assert!(!tokens.contains_key(&token_key(&rotated)));
+        // The running manager shares the cache, so it stops using them too.
+        assert!(Arc::ptr_eq(&tm.tokens, &reconfigured.tokens));
+    }
+
+    #[cfg(feature = "secrets")]
+    #[test]
+    fn test_cached_tokens_survive_a_restart() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("tokens.age");
+        let path = path.to_str().unwrap();
+        let tm = TokenManager::new(vec![]).with_cache_file(path, "passphrase".into());
+        {
+            let mut tokens = tm.tokens.blocking_write();
+            for (key, expires_in) in [("fresh", 3600), ("stale", 30)] {
+                let token = TokenInfo {
+                    token: format!("{key}-token"),
+                    expires_at: Utc::now() + chrono::Duration::seconds(expires_in),
+                };
+                tokens.insert(key.to_string(), token);
+            }
+        }
+        tm.cache_file.as_ref().unwrap().save(&tm.tokens).unwrap();
+
+        let restarted = TokenManager::new(vec![]).with_cache_file(path, "passphrase".into());
+        let tokens = restarted.tokens.blocking_read();
+        assert_eq!(tokens.keys().collect::<Vec<_>>(), ["fresh"]);
+        assert_eq!(tokens["fresh"].token, "fresh-token");
+
+        let wrong = TokenManager::new(vec![]).with_cache_file(path, "guess".into());
+        assert!(wrong.tokens.blocking_read().is_empty());
+    }
+
     #[test]
     fn test_empty_api_keys() {
         let tm = TokenManager::new(vec![]);
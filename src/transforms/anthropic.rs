@@ -14,8 +14,8 @@ use axum::http::HeaderMap;
 use serde_json::{Map, Value, json};
 
 use crate::constants::api::{
-    ANTHROPIC_BETA_HEADER, ANTHROPIC_DEFAULT_MAX_TOKENS, ANTHROPIC_TO_BEDROCK_BETA_REMAP,
-    ANTHROPIC_VERSION, BUDGET_RESERVE_MARGIN, MIN_BUDGET_TOKENS_FOR_THINKING,
+    ANTHROPIC_BETA_HEADER, ANTHROPIC_TO_BEDROCK_BETA_REMAP, ANTHROPIC_VERSION,
+    BUDGET_RESERVE_MARGIN, MIN_BUDGET_TOKENS_FOR_THINKING,
 };
 use crate::constants::models::{CLAUDE_OPUS_4_7, CLAUDE_OPUS_4_8};
 
@@ -23,13 +23,15 @@ use crate::constants::models::{CLAUDE_OPUS_4_7, CLAUDE_OPUS_4_8};
 ///
 /// Steps (order is load-bearing):
 /// 1. Validate the messages array (fail fast on obvious client bugs).
-/// 2. Stamp `anthropic_version`, drop fields Bedrock doesn't accept, default `max_tokens`.
+/// 2. Stamp `anthropic_version`, drop fields Bedrock doesn't accept, repair
+///    `max_tokens` (taken from an OpenAI-style `max_completion_tokens` when a
+///    client sends that instead, else `default_max_tokens`).
 /// 3. Strip `cache_control.scope` (sent by Claude Code 2.1.88+, rejected by Bedrock).
 /// 4. Inject `ttl: "1h"` into ephemeral cache_control blocks (extends Bedrock's prompt
 ///    cache from 5min default to 1h — net win for acr's interactive workload).
 /// 5. Clamp / disable `thinking` to satisfy Bedrock's budget constraints.
 /// 6. Apply adaptive-thinking model overrides last so they see the post-clamp `thinking`.
-pub fn prepare(body: &mut Value, model: &str) -> Result<()> {
+pub fn prepare(body: &mut Value, model: &str, default_max_tokens: u64) -> Result<()> {
     validate_messages(body)?;
 
     let Some(obj) = body.as_object_mut() else {
@@ -41,12 +43,7 @@ pub fn prepare(body: &mut Value, model: &str) -> Result<()> {
     obj.remove("model");
     obj.remove("context_management");
 
-    if !obj.contains_key("max_tokens") {
-        obj.insert(
-            "max_tokens".to_string(),
-            json!(ANTHROPIC_DEFAULT_MAX_TOKENS),
-        );
-    }
+    repair_max_tokens(obj, default_max_tokens);
 
     strip_cache_control_scope(obj);
     inject_cache_ttl(obj);
@@ -59,6 +56,24 @@ pub fn prepare(body: &mut Value, model: &str) -> Result<()> {
     Ok(())
 }
 
+/// Make sure `max_tokens`, which Anthropic requires, is a positive integer.
+/// Bedrock rejects `max_completion_tokens`, so it is always removed and only
+/// used when `max_tokens` itself is unusable.
+fn repair_max_tokens(obj: &mut Map<String, Value>, default_max_tokens: u64) {
+    let completion = obj
+        .remove("max_completion_tokens")
+        .and_then(|v| v.as_u64())
+        .filter(|&n| n > 0);
+    let valid = obj
+        .get("max_tokens")
+        .and_then(|v| v.as_u64())
+        .is_some_and(|n| n > 0);
+    if !valid {
+        let value = completion.unwrap_or(default_max_tokens);
+        obj.insert("max_tokens".to_string(), json!(value));
+    }
+}
+
 /// Parse the `Anthropic-Beta` header into a list of beta features for the Bedrock
 /// request body, applying the Anthropic→Bedrock policy in
 /// [`ANTHROPIC_TO_BEDROCK_BETA_REMAP`]: rename, drop (Bedrock-incompatible), or
@@ -305,6 +320,7 @@ fn apply_adaptive_thinking_overrides(obj: &mut Map<String, Value>) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::api::ANTHROPIC_DEFAULT_MAX_TOKENS;
 
     #[test]
     fn requires_adaptive_thinking_predicate() {
@@ -396,7 +412,7 @@ mod tests {
             "thinking": {"type": "enabled", "budget_tokens": 2000},
             "messages": [{"role": "user", "content": "hi"}],
         });
-        prepare(&mut body, "claude-opus-4-7").unwrap();
+        prepare(&mut body, "claude-opus-4-7", ANTHROPIC_DEFAULT_MAX_TOKENS).unwrap();
 
         let obj = body.as_object().unwrap();
         assert!(!obj.contains_key("temperature"));
@@ -417,7 +433,7 @@ mod tests {
             "thinking": {"type": "enabled", "budget_tokens": 2000},
             "messages": [{"role": "user", "content": "hi"}],
         });
-        prepare(&mut body, "claude-opus-4-8").unwrap();
+        prepare(&mut body, "claude-opus-4-8", ANTHROPIC_DEFAULT_MAX_TOKENS).unwrap();
 
         let obj = body.as_object().unwrap();
         assert!(!obj.contains_key("temperature"));
@@ -438,7 +454,7 @@ mod tests {
             "thinking": {"type": "enabled", "budget_tokens": 2000},
             "messages": [{"role": "user", "content": "hi"}],
         });
-        prepare(&mut body, "claude-opus-4-6").unwrap();
+        prepare(&mut body, "claude-opus-4-6", ANTHROPIC_DEFAULT_MAX_TOKENS).unwrap();
 
         let obj = body.as_object().unwrap();
         assert_eq!(obj["temperature"], json!(0.7));
@@ -449,6 +465,31 @@ mod tests {
         assert_eq!(thinking["budget_tokens"], json!(2000));
     }
 
+    #[test]
+    fn prepare_repairs_missing_or_unusable_max_tokens() {
+        let mut body = json!({"messages": [{"role": "user", "content": "hi"}]});
+        prepare(&mut body, "claude-sonnet-4-6", 8192).unwrap();
+        assert_eq!(body["max_tokens"], json!(8192));
+
+        let mut body = json!({
+            "max_tokens": null,
+            "max_completion_tokens": 300,
+            "messages": [{"role": "user", "content": "hi"}],
+        });
+        prepare(&mut body, "claude-sonnet-4-6", 8192).unwrap();
+        assert_eq!(body["max_tokens"], json!(300));
+        assert!(body.get("max_completion_tokens").is_none());
+
+        let mut body = json!({
+            "max_tokens": 100,
+            "max_completion_tokens": 300,
+            "messages": [{"role": "user", "content": "hi"}],
+        });
+        prepare(&mut body, "claude-sonnet-4-6", 8192).unwrap();
+        assert_eq!(body["max_tokens"], json!(100));
+        assert!(body.get("max_completion_tokens").is_none());
+    }
+
     #[test]
     fn strip_cache_control_scope_removes_field_from_block_array() {
         let mut body = json!({
@@ -733,7 +774,7 @@ mod tests {
             }],
             "messages": [{"role": "user", "content": "hi"}]
         });
-        prepare(&mut body, "claude-sonnet-4-6").unwrap();
+        prepare(&mut body, "claude-sonnet-4-6", ANTHROPIC_DEFAULT_MAX_TOKENS).unwrap();
 
         let cc = body["tools"][0]["cache_control"].as_object().unwrap();
         assert!(!cc.contains_key("scope"));
@@ -749,7 +790,7 @@ mod tests {
                 ]}
             ]
         });
-        prepare(&mut body, "claude-sonnet-4-6").unwrap();
+        prepare(&mut body, "claude-sonnet-4-6", ANTHROPIC_DEFAULT_MAX_TOKENS).unwrap();
 
         assert_eq!(
             body["messages"][0]["content"][0]["cache_control"]["ttl"],
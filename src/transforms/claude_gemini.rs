@@ -0,0 +1,593 @@
+//! Anthropic Messages ⇄ Gemini `generateContent` translation.
+//!
+//! Lets Anthropic clients on `/v1/messages` target Gemini deployments. The
+//! emphasis is on agent workloads: `tools` / `tool_choice` become
+//! `functionDeclarations` / `toolConfig`, `tool_use` / `tool_result` blocks
+//! become `functionCall` / `functionResponse` parts, and `functionCall` parts
+//! come back as `tool_use` blocks — as complete `content_block_start` /
+//! `input_json_delta` / `content_block_stop` triples when streaming.
+//!
+//! Covered besides tools: system prompts, text, base64 images and the
+//! sampling knobs. Thinking blocks in the history are dropped (Gemini can't
+//! verify Anthropic signatures); server tools and other block types are
+//! rejected. Gemini thought summaries are likewise never returned, whatever
+//! the key's `reasoning` setting: an Anthropic `thinking` block without a
+//! valid signature would be rejected when the client sends it back.
+//!
+//! Source-of-truth references:
+//! * Messages API: <https://docs.anthropic.com/en/api/messages>
+//! * Streaming Messages: <https://docs.anthropic.com/en/docs/build-with-claude/streaming>
+//! * Function calling: <https://ai.google.dev/gemini-api/docs/function-calling>
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use serde_json::{Map, Value, json};
+
+use crate::transforms::documents::unsupported;
+use crate::transforms::openai_gemini::{
+    candidate_function_calls, candidate_text, document_part, function_call_part,
+    function_declaration, function_response_part, push_content, tool_config,
+};
+
+/// Rewrite an Anthropic Messages request body into a Gemini
+/// `generateContent` body.
+pub fn request_to_gemini(body: &mut Value) -> Result<()> {
+    let Some(obj) = body.as_object() else {
+        return Ok(());
+    };
+
+    let mut contents: Vec<Value> = Vec::new();
+    // `functionResponse` needs the function name; `tool_result` only carries
+    // the id of the `tool_use` it answers.
+    let mut call_names: HashMap<String, String> = HashMap::new();
+    for (i, message) in obj
+        .get("messages")
+        .and_then(|v| v.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+    {
+        let role = match message.get("role").and_then(|v| v.as_str()) {
+            Some("user") => "user",
+            Some("assistant") => "model",
+            other => bail!(
+                "message at index {i}: role '{}' cannot be translated to Gemini",
+                other.unwrap_or("<missing>")
+            ),
+        };
+        let parts = blocks_to_parts(message.get("content"), i, &mut call_names)?;
+        push_content(&mut contents, role, parts);
+    }
+
+    let mut out = Map::new();
+    out.insert("contents".to_string(), Value::Array(contents));
+    let system = match obj.get("system") {
+        Some(Value::String(text)) => vec![json!({"text": text})],
+        Some(Value::Array(blocks)) => blocks
+            .iter()
+            .filter_map(|b| b.get("text").and_then(|v| v.as_str()))
+            .map(|text| json!({"text": text}))
+            .collect(),
+        _ => Vec::new(),
+    };
+    if !system.is_empty() {
+        out.insert("systemInstruction".to_string(), json!({"parts": system}));
+    }
+
+    if let Some(tools) = obj.get("tools").and_then(|v| v.as_array()) {
+        let declarations = tools
+            .iter()
+            .map(|tool| {
+                // Client tools have no type or `custom`; server tools
+                // (`web_search_*`, `bash_*`, ...) have no Gemini equivalent.
+                if let Some(kind) = tool.get("type").and_then(|v| v.as_str())
+                    && kind != "custom"
+                {
+                    bail!("tool type '{kind}' cannot be translated to Gemini");
+                }
+                let Some(name) = tool.get("name").and_then(|v| v.as_str()) else {
+                    bail!("tool without a name");
+                };
+                Ok(function_declaration(
+                    name,
+                    tool.get("description"),
+                    tool.get("input_schema"),
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if !declarations.is_empty() {
+            out.insert(
+                "tools".to_string(),
+                json!([{"functionDeclarations": declarations}]),
+            );
+        }
+    }
+    if let Some(choice) = obj.get("tool_choice") {
+        let config = match choice.get("type").and_then(|v| v.as_str()) {
+            Some("any") => tool_config("ANY", None),
+            Some("none") => tool_config("NONE", None),
+            Some("tool") => tool_config("ANY", choice.get("name").and_then(|v| v.as_str())),
+            _ => tool_config("AUTO", None),
+        };
+        out.insert("toolConfig".to_string(), config);
+    }
+
+    let mut config = Map::new();
+    for (from, to) in [
+        ("max_tokens", "maxOutputTokens"),
+        ("temperature", "temperature"),
+        ("top_p", "topP"),
+        ("top_k", "topK"),
+        ("stop_sequences", "stopSequences"),
+    ] {
+        if let Some(v) = obj.get(from).filter(|v| !v.is_null()) {
+            config.insert(to.to_string(), v.clone());
+        }
+    }
+    if !config.is_empty() {
+        out.insert("generationConfig".to_string(), Value::Object(config));
+    }
+
+    *body = Value::Object(out);
+    Ok(())
+}
+
+/// Map Anthropic message `content` (string or block array) to Gemini parts.
+fn blocks_to_parts(
+    content: Option<&Value>,
+    index: usize,
+    call_names: &mut HashMap<String, String>,
+) -> Result<Vec<Value>> {
+    let blocks = match content {
+        None | Some(Value::Null) => return Ok(Vec::new()),
+        Some(Value::String(text)) => return Ok(vec![json!({"text": text})]),
+        Some(Value::Array(blocks)) => blocks,
+        Some(_) => bail!("message at index {index}: content must be a string or an array"),
+    };
+    let mut parts = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        match block.get("type").and_then(|v| v.as_str()) {
+            Some("text") => parts.push(json!({
+                "text": block.get("text").and_then(|v| v.as_str()).unwrap_or("")
+            })),
+            Some("image") => {
+                let source = block.get("source").unwrap_or(&Value::Null);
+                if source.get("type").and_then(|v| v.as_str()) != Some("base64") {
+                    bail!(
+                        "message at index {index}: only base64 images can be translated to Gemini (enable image_fetch to inline remote images)"
+                    );
+                }
+                parts.push(json!({"inlineData": {
+                    "mimeType": source.get("media_type").cloned().unwrap_or(Value::Null),
+                    "data": source.get("data").cloned().unwrap_or(Value::Null),
+                }}));
+            }
+            Some("document") => parts.push(document_to_part(block, index)?),
+            Some("tool_use") => {
+                let Some(name) = block.get("name").and_then(|v| v.as_str()) else {
+                    bail!("message at index {index}: tool_use block without a name");
+                };
+                if let Some(id) = block.get("id").and_then(|v| v.as_str()) {
+                    call_names.insert(id.to_string(), name.to_string());
+                }
+                parts.push(function_call_part(
+                    name,
+                    block.get("input").cloned().unwrap_or_else(|| json!({})),
+                ));
+            }
+            Some("tool_result") => {
+                let Some(name) = block
+                    .get("tool_use_id")
+                    .and_then(|v| v.as_str())
+                    .and_then(|id| call_names.get(id))
+                else {
+                    bail!("message at index {index}: tool_result does not answer a known tool_use");
+                };
+                let output = match block.get("content") {
+                    Some(Value::String(text)) => text.clone(),
+                    Some(Value::Array(items)) => items
+                        .iter()
+                        .filter_map(|b| b.get("text").and_then(|v| v.as_str()))
+                        .collect(),
+                    _ => String::new(),
+                };
+                let is_error = block.get("is_error").and_then(|v| v.as_bool()) == Some(true);
+                parts.push(function_response_part(name, &output, is_error));
+            }
+            Some("thinking" | "redacted_thinking") => {}
+            other => bail!(
+                "message at index {index}: content block type {:?} cannot be translated to Gemini",
+                other.unwrap_or("<missing>")
+            ),
+        }
+    }
+    Ok(parts)
+}
+
+/// Map an Anthropic `document` block to a Gemini part. Remote (`url`) and
+/// Files API (`file`) sources would need fetching and are rejected.
+fn document_to_part(block: &Value, index: usize) -> Result<Value> {
+    let source = block.get("source").unwrap_or(&Value::Null);
+    let field = |key: &str| source.get(key).and_then(|v| v.as_str()).unwrap_or("");
+    match source.get("type").and_then(|v| v.as_str()) {
+        Some("base64") => document_part(field("media_type"), field("data"), index),
+        Some("text") => Ok(json!({"text": field("data")})),
+        Some("content") => {
+            let text: String = match source.get("content") {
+                Some(Value::String(text)) => text.clone(),
+                Some(Value::Array(blocks)) => blocks
+                    .iter()
+                    .filter_map(|b| b.get("text").and_then(|v| v.as_str()))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                _ => String::new(),
+            };
+            Ok(json!({"text": text}))
+        }
+        other => unsupported(format!(
+            "message at index {index}: {} document sources cannot be translated to Gemini",
+            other.unwrap_or("<missing>")
+        )),
+    }
+}
+
+/// Map a Gemini `finishReason` to Anthropic's `stop_reason`.
+fn stop_reason(reason: &str, called_tools: bool) -> &'static str {
+    match reason {
+        "MAX_TOKENS" => "max_tokens",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY" => {
+            "refusal"
+        }
+        _ if called_tools => "tool_use",
+        _ => "end_turn",
+    }
+}
+
+/// Anthropic usage from Gemini's. Gemini counts cached tokens inside
+/// `promptTokenCount`; Anthropic reports them separately.
+fn usage_from_metadata(metadata: &Value) -> Value {
+    let get = |k: &str| metadata.get(k).and_then(|v| v.as_u64()).unwrap_or(0);
+    let cached = get("cachedContentTokenCount");
+    json!({
+        "input_tokens": get("promptTokenCount").saturating_sub(cached),
+        "output_tokens": get("candidatesTokenCount") + get("thoughtsTokenCount"),
+        "cache_read_input_tokens": cached,
+    })
+}
+
+fn message_id() -> String {
+    format!("msg_{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Translate a non-streaming Gemini response into an Anthropic message. Only
+/// the first candidate is kept; Messages has no `n`.
+pub fn response_to_claude(body: &Value, model: &str) -> Value {
+    let candidate = body
+        .get("candidates")
+        .and_then(|c| c.get(0))
+        .unwrap_or(&Value::Null);
+    let text = candidate_text(candidate);
+    let calls = candidate_function_calls(candidate);
+
+    let mut content = Vec::new();
+    if !text.is_empty() {
+        content.push(json!({"type": "text", "text": text}));
+    }
+    for (id, name, args) in &calls {
+        content.push(json!({"type": "tool_use", "id": id, "name": name, "input": args}));
+    }
+
+    json!({
+        "id": message_id(),
+        "type": "message",
+        "role": "assistant",
+        "model": model,
+        "content": content,
+        "stop_reason": stop_reason(
+            candidate.get("finishReason").and_then(|v| v.as_str()).unwrap_or("STOP"),
+            !calls.is_empty(),
+        ),
+        "stop_sequence": null,
+        "usage": usage_from_metadata(body.get("usageMetadata").unwrap_or(&Value::Null)),
+    })
+}
+
+/// Per-stream state for translating Gemini `streamGenerateContent` chunks
+/// into Anthropic stream events. Text accumulates in one open text block;
+/// each `functionCall` arrives whole and becomes its own `tool_use` block.
+#[derive(Debug)]
+pub struct StreamState {
+    model: String,
+    started: bool,
+    next_index: u64,
+    open_text: Option<u64>,
+    called_tools: bool,
+    finish_reason: Option<String>,
+    usage: Value,
+}
+
+impl StreamState {
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            started: false,
+            next_index: 0,
+            open_text: None,
+            called_tools: false,
+            finish_reason: None,
+            usage: usage_from_metadata(&Value::Null),
+        }
+    }
+
+    fn start(&mut self, events: &mut Vec<Value>) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        events.push(json!({
+            "type": "message_start",
+            "message": {
+                "id": message_id(),
+                "type": "message",
+                "role": "assistant",
+                "model": self.model,
+                "content": [],
+                "stop_reason": null,
+                "stop_sequence": null,
+                "usage": self.usage,
+            },
+        }));
+    }
+
+    fn close_text(&mut self, events: &mut Vec<Value>) {
+        if let Some(index) = self.open_text.take() {
+            events.push(json!({"type": "content_block_stop", "index": index}));
+        }
+    }
+
+    /// Translate one upstream `data:` payload. Returns the event payloads to
+    /// emit, in order (possibly none).
+    pub fn event(&mut self, parsed: &Value) -> Vec<Value> {
+        if let Some(metadata) = parsed.get("usageMetadata") {
+            self.usage = usage_from_metadata(metadata);
+        }
+        let mut events = Vec::new();
+        self.start(&mut events);
+        let Some(candidate) = parsed.get("candidates").and_then(|c| c.get(0)) else {
+            return events;
+        };
+
+        let text = candidate_text(candidate);
+        if !text.is_empty() {
+            let index = match self.open_text {
+                Some(index) => index,
+                None => {
+                    let index = self.next_index;
+                    self.next_index += 1;
+                    self.open_text = Some(index);
+                    events.push(json!({
+                        "type": "content_block_start",
+                        "index": index,
+                        "content_block": {"type": "text", "text": ""},
+                    }));
+                    index
+                }
+            };
+            events.push(json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": {"type": "text_delta", "text": text},
+            }));
+        }
+
+        for (id, name, args) in candidate_function_calls(candidate) {
+            self.close_text(&mut events);
+            self.called_tools = true;
+            let index = self.next_index;
+            self.next_index += 1;
+            events.push(json!({
+                "type": "content_block_start",
+                "index": index,
+                "content_block": {"type": "tool_use", "id": id, "name": name, "input": {}},
+            }));
+            events.push(json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": {"type": "input_json_delta", "partial_json": args.to_string()},
+            }));
+            events.push(json!({"type": "content_block_stop", "index": index}));
+        }
+
+        if let Some(reason) = candidate.get("finishReason").and_then(|v| v.as_str()) {
+            self.finish_reason = Some(reason.to_string());
+        }
+        events
+    }
+
+    /// Closing events after the upstream ends: the final `message_delta`
+    /// with stop reason and usage, then `message_stop`.
+    pub fn finish(&mut self) -> Vec<Value> {
+        let mut events = Vec::new();
+        self.start(&mut events);
+        self.close_text(&mut events);
+        events.push(json!({
+            "type": "message_delta",
+            "delta": {
+                "stop_reason": stop_reason(
+                    self.finish_reason.as_deref().unwrap_or("STOP"),
+                    self.called_tools,
+                ),
+                "stop_sequence": null,
+            },
+            "usage": self.usage,
+        }));
+        events.push(json!({"type": "message_stop"}));
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_maps_tool_use_round_trip_and_tool_choice() {
+        let mut body = json!({
+            "model": "gemini-2.5-pro",
+            "system": [{"type": "text", "text": "be brief"}],
+            "max_tokens": 512,
+            "top_k": 40,
+            "messages": [
+                {"role": "user", "content": "weather in Paris?"},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "...", "signature": "sig"},
+                    {"type": "tool_use", "id": "toolu_1", "name": "weather", "input": {"city": "Paris"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": [{"type": "text", "text": "sunny"}]},
+                    {"type": "text", "text": "and tomorrow?"}
+                ]}
+            ],
+            "tools": [{"name": "weather", "description": "Get the weather", "input_schema": {"type": "object"}}],
+            "tool_choice": {"type": "tool", "name": "weather"}
+        });
+        request_to_gemini(&mut body).unwrap();
+
+        assert_eq!(
+            body["systemInstruction"],
+            json!({"parts": [{"text": "be brief"}]})
+        );
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], json!(512));
+        assert_eq!(body["generationConfig"]["topK"], json!(40));
+        assert_eq!(
+            body["tools"][0]["functionDeclarations"][0]["parametersJsonSchema"],
+            json!({"type": "object"})
+        );
+        assert_eq!(
+            body["toolConfig"]["functionCallingConfig"],
+            json!({"mode": "ANY", "allowedFunctionNames": ["weather"]})
+        );
+        let contents = body["contents"].as_array().unwrap();
+        assert_eq!(
+            contents[1],
+            json!({"role": "model", "parts": [
+                {"functionCall": {"name": "weather", "args": {"city": "Paris"}}}
+            ]})
+        );
+        assert_eq!(
+            contents[2]["parts"][0],
+            json!({"functionResponse": {"name": "weather", "response": {"content": "sunny"}}})
+        );
+        assert_eq!(contents[2]["parts"][1], json!({"text": "and tomorrow?"}));
+    }
+
+    #[test]
+    fn request_maps_document_sources() {
+        let mut body = json!({"messages": [{"role": "user", "content": [
+            {"type": "document", "source": {"type": "base64", "media_type": "application/pdf", "data": "JVBERi0="}},
+            {"type": "document", "source": {"type": "text", "media_type": "text/plain", "data": "notes"}},
+            {"type": "document", "source": {"type": "content", "content": [
+                {"type": "text", "text": "first"}, {"type": "text", "text": "second"}
+            ]}}
+        ]}]});
+        request_to_gemini(&mut body).unwrap();
+        assert_eq!(
+            body["contents"][0]["parts"],
+            json!([
+                {"inlineData": {"mimeType": "application/pdf", "data": "JVBERi0="}},
+                {"text": "notes"},
+                {"text": "first\nsecond"}
+            ])
+        );
+
+        let mut body = json!({"messages": [{"role": "user", "content": [
+            {"type": "document", "source": {"type": "url", "url": "https://example.com/a.pdf"}}
+        ]}]});
+        let err = request_to_gemini(&mut body).unwrap_err();
+        assert!(
+            err.downcast_ref::<crate::transforms::documents::UnsupportedContent>()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn request_rejects_server_tools_and_orphan_results() {
+        let mut body = json!({
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [{"type": "web_search_20250305", "name": "web_search"}]
+        });
+        assert!(request_to_gemini(&mut body).is_err());
+
+        let mut body = json!({"messages": [{"role": "user", "content": [
+            {"type": "tool_result", "tool_use_id": "toolu_x", "content": "42"}
+        ]}]});
+        assert!(request_to_gemini(&mut body).is_err());
+    }
+
+    #[test]
+    fn response_maps_function_calls_to_tool_use() {
+        let body = json!({
+            "candidates": [{"content": {"parts": [
+                {"text": "Checking."},
+                {"functionCall": {"id": "fc_1", "name": "weather", "args": {"city": "Paris"}}}
+            ]}, "finishReason": "STOP"}],
+            "usageMetadata": {"promptTokenCount": 100, "cachedContentTokenCount": 60, "candidatesTokenCount": 7}
+        });
+        let out = response_to_claude(&body, "gemini-2.5-pro");
+        assert_eq!(out["stop_reason"], json!("tool_use"));
+        assert_eq!(
+            out["content"][0],
+            json!({"type": "text", "text": "Checking."})
+        );
+        assert_eq!(
+            out["content"][1],
+            json!({"type": "tool_use", "id": "fc_1", "name": "weather", "input": {"city": "Paris"}})
+        );
+        assert_eq!(out["usage"]["input_tokens"], json!(40));
+        assert_eq!(out["usage"]["cache_read_input_tokens"], json!(60));
+    }
+
+    #[test]
+    fn stream_emits_anthropic_block_lifecycle() {
+        let mut state = StreamState::new("gemini-2.5-pro");
+        let mut events =
+            state.event(&json!({"candidates": [{"content": {"parts": [{"text": "Let me "}]}}]}));
+        events.extend(
+            state.event(&json!({"candidates": [{"content": {"parts": [{"text": "check."}]}}]})),
+        );
+        events.extend(state.event(&json!({
+            "candidates": [{"content": {"parts": [
+                {"functionCall": {"name": "weather", "args": {"city": "Paris"}}}
+            ]}, "finishReason": "STOP"}],
+            "usageMetadata": {"promptTokenCount": 10, "candidatesTokenCount": 5}
+        })));
+        events.extend(state.finish());
+
+        let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            types,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop"
+            ]
+        );
+        assert_eq!(events[5]["index"], json!(1));
+        assert_eq!(events[5]["content_block"]["name"], json!("weather"));
+        assert_eq!(
+            events[6]["delta"]["partial_json"],
+            json!("{\"city\":\"Paris\"}")
+        );
+        assert_eq!(events[8]["delta"]["stop_reason"], json!("tool_use"));
+        assert_eq!(events[8]["usage"]["output_tokens"], json!(5));
+    }
+}
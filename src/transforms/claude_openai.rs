@@ -0,0 +1,736 @@
+//! Anthropic Messages ⇄ OpenAI Chat Completions translation.
+//!
+//! Lets Anthropic clients on `/v1/messages` target GPT (and open-weight)
+//! deployments, which speak the Chat Completions shape. The mirror image of
+//! `openai_claude`: `system` becomes a system message, `tool_use` blocks
+//! become assistant `tool_calls`, and each `tool_result` block its own `tool`
+//! message. On the way back `tool_calls` become `tool_use` blocks —
+//! incrementally when streaming, each call opening its own
+//! `content_block_start` with its `function.arguments` fragments forwarded as
+//! `input_json_delta`s.
+//!
+//! Thinking blocks in the history are dropped, and so is a `thinking`
+//! request (reported back like other dropped parameters): OpenAI reasoning
+//! can't be turned into signed Anthropic thinking blocks. Server tools and
+//! block types Chat Completions has no equivalent for are rejected.
+//!
+//! Source-of-truth references:
+//! * Messages API: <https://docs.anthropic.com/en/api/messages>
+//! * Streaming Messages: <https://docs.anthropic.com/en/docs/build-with-claude/streaming>
+//! * Chat Completions API: <https://platform.openai.com/docs/api-reference/chat/create>
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use serde_json::{Map, Value, json};
+
+use crate::transforms::documents::{PDF_MEDIA_TYPE, unsupported};
+
+/// Rewrite an Anthropic Messages request body into an OpenAI chat request.
+/// `openai::prepare` (or `open_weight::prepare`) runs afterwards. Returns
+/// the names of the parameters that had to be dropped.
+pub fn request_to_openai(body: &mut Value) -> Result<Vec<&'static str>> {
+    let Some(obj) = body.as_object() else {
+        return Ok(Vec::new());
+    };
+
+    let mut messages: Vec<Value> = Vec::new();
+    let system = match obj.get("system") {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(blocks)) => blocks
+            .iter()
+            .filter_map(|b| b.get("text").and_then(|v| v.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        _ => String::new(),
+    };
+    if !system.is_empty() {
+        messages.push(json!({"role": "system", "content": system}));
+    }
+    for (i, message) in obj
+        .get("messages")
+        .and_then(|v| v.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+    {
+        match message.get("role").and_then(|v| v.as_str()) {
+            Some("user") => push_user(&mut messages, message.get("content"), i)?,
+            Some("assistant") => messages.push(assistant_message(message.get("content"), i)?),
+            other => bail!(
+                "message at index {i}: role '{}' cannot be translated to OpenAI",
+                other.unwrap_or("<missing>")
+            ),
+        }
+    }
+
+    let mut out = Map::new();
+    out.insert("messages".to_string(), Value::Array(messages));
+    for key in ["max_tokens", "temperature", "top_p", "stream"] {
+        if let Some(v) = obj.get(key).filter(|v| !v.is_null()) {
+            out.insert(key.to_string(), v.clone());
+        }
+    }
+    if let Some(stop) = obj.get("stop_sequences").and_then(|v| v.as_array())
+        && !stop.is_empty()
+    {
+        out.insert("stop".to_string(), Value::Array(stop.clone()));
+    }
+    if let Some(user) = obj
+        .get("metadata")
+        .and_then(|m| m.get("user_id"))
+        .and_then(|v| v.as_str())
+    {
+        out.insert("user".to_string(), json!(user));
+    }
+    let dropped: Vec<&'static str> = ["top_k", "thinking"]
+        .into_iter()
+        .filter(|key| obj.get(*key).is_some_and(|v| !v.is_null()))
+        .collect();
+
+    if let Some(tools) = obj.get("tools").and_then(|v| v.as_array()) {
+        let tools = tools
+            .iter()
+            .map(tool_to_openai)
+            .collect::<Result<Vec<_>>>()?;
+        if !tools.is_empty() {
+            out.insert("tools".to_string(), Value::Array(tools));
+        }
+    }
+    if let Some(choice) = obj.get("tool_choice") {
+        let mapped = match choice.get("type").and_then(|v| v.as_str()) {
+            Some("any") => json!("required"),
+            Some("none") => json!("none"),
+            Some("tool") => json!({
+                "type": "function",
+                "function": {"name": choice.get("name").cloned().unwrap_or(Value::Null)},
+            }),
+            _ => json!("auto"),
+        };
+        out.insert("tool_choice".to_string(), mapped);
+        if choice
+            .get("disable_parallel_tool_use")
+            .and_then(|v| v.as_bool())
+            == Some(true)
+        {
+            out.insert("parallel_tool_calls".to_string(), json!(false));
+        }
+    }
+
+    *body = Value::Object(out);
+    Ok(dropped)
+}
+
+/// Append a user turn. Its `tool_result` blocks become `tool` messages,
+/// which OpenAI wants right after the assistant's `tool_calls`, so they go
+/// first and the rest of the turn follows as a user message.
+fn push_user(messages: &mut Vec<Value>, content: Option<&Value>, index: usize) -> Result<()> {
+    let blocks = match content {
+        None | Some(Value::Null) => return Ok(()),
+        Some(Value::String(text)) => {
+            messages.push(json!({"role": "user", "content": text}));
+            return Ok(());
+        }
+        Some(Value::Array(blocks)) => blocks,
+        Some(_) => bail!("message at index {index}: content must be a string or an array"),
+    };
+    let mut parts = Vec::new();
+    for block in blocks {
+        match block.get("type").and_then(|v| v.as_str()) {
+            Some("tool_result") => {
+                let Some(id) = block.get("tool_use_id").and_then(|v| v.as_str()) else {
+                    bail!("message at index {index}: tool_result without a tool_use_id");
+                };
+                let mut output = match block.get("content") {
+                    Some(Value::String(text)) => text.clone(),
+                    Some(Value::Array(items)) => items
+                        .iter()
+                        .map(|item| match item.get("type").and_then(|v| v.as_str()) {
+                            Some("text") => {
+                                Ok(item.get("text").and_then(|v| v.as_str()).unwrap_or(""))
+                            }
+                            _ => bail!(
+                                "message at index {index}: only text tool results can be translated to OpenAI"
+                            ),
+                        })
+                        .collect::<Result<String>>()?,
+                    _ => String::new(),
+                };
+                // Tool messages have no error flag; tell the model in the text.
+                if block.get("is_error").and_then(|v| v.as_bool()) == Some(true) {
+                    output = format!("Error: {output}");
+                }
+                messages.push(json!({"role": "tool", "tool_call_id": id, "content": output}));
+            }
+            _ => parts.push(user_part(block, index)?),
+        }
+    }
+    if !parts.is_empty() {
+        messages.push(json!({"role": "user", "content": parts}));
+    }
+    Ok(())
+}
+
+/// Map a user content block to an OpenAI content part.
+fn user_part(block: &Value, index: usize) -> Result<Value> {
+    let source = block.get("source").unwrap_or(&Value::Null);
+    let field = |key: &str| source.get(key).and_then(|v| v.as_str()).unwrap_or("");
+    match block.get("type").and_then(|v| v.as_str()) {
+        Some("text") => Ok(json!({
+            "type": "text",
+            "text": block.get("text").and_then(|v| v.as_str()).unwrap_or(""),
+        })),
+        Some("image") => {
+            let url = match source.get("type").and_then(|v| v.as_str()) {
+                Some("base64") => format!("data:{};base64,{}", field("media_type"), field("data")),
+                Some("url") => field("url").to_string(),
+                _ => bail!("message at index {index}: image source cannot be translated to OpenAI"),
+            };
+            Ok(json!({"type": "image_url", "image_url": {"url": url}}))
+        }
+        Some("document") => match source.get("type").and_then(|v| v.as_str()) {
+            Some("base64") if field("media_type") == PDF_MEDIA_TYPE => Ok(json!({
+                "type": "file",
+                "file": {
+                    "filename": block.get("title").and_then(|v| v.as_str()).unwrap_or("document.pdf"),
+                    "file_data": format!("data:{PDF_MEDIA_TYPE};base64,{}", field("data")),
+                },
+            })),
+            Some("text") => Ok(json!({"type": "text", "text": field("data")})),
+            Some("content") => {
+                let text = match source.get("content") {
+                    Some(Value::String(text)) => text.clone(),
+                    Some(Value::Array(blocks)) => blocks
+                        .iter()
+                        .filter_map(|b| b.get("text").and_then(|v| v.as_str()))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    _ => String::new(),
+                };
+                Ok(json!({"type": "text", "text": text}))
+            }
+            other => unsupported(format!(
+                "message at index {index}: {} document sources cannot be translated to OpenAI",
+                other.unwrap_or("<missing>")
+            )),
+        },
+        other => bail!(
+            "message at index {index}: content block type {:?} cannot be translated to OpenAI",
+            other.unwrap_or("<missing>")
+        ),
+    }
+}
+
+/// Map an assistant turn: text blocks become `content`, `tool_use` blocks
+/// `tool_calls`; thinking is dropped.
+fn assistant_message(content: Option<&Value>, index: usize) -> Result<Value> {
+    let blocks = match content {
+        None | Some(Value::Null) => return Ok(json!({"role": "assistant", "content": ""})),
+        Some(Value::String(text)) => return Ok(json!({"role": "assistant", "content": text})),
+        Some(Value::Array(blocks)) => blocks,
+        Some(_) => bail!("message at index {index}: content must be a string or an array"),
+    };
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    for block in blocks {
+        match block.get("type").and_then(|v| v.as_str()) {
+            Some("text") => text.push_str(block.get("text").and_then(|v| v.as_str()).unwrap_or("")),
+            Some("tool_use") => {
+                let Some(name) = block.get("name").and_then(|v| v.as_str()) else {
+                    bail!("message at index {index}: tool_use block without a name");
+                };
+                tool_calls.push(json!({
+                    "id": block.get("id").cloned().unwrap_or(Value::Null),
+                    "type": "function",
+                    "function": {
+                        "name": name,
+                        "arguments": block.get("input").unwrap_or(&json!({})).to_string(),
+                    },
+                }));
+            }
+            Some("thinking" | "redacted_thinking") => {}
+            other => bail!(
+                "message at index {index}: content block type {:?} cannot be translated to OpenAI",
+                other.unwrap_or("<missing>")
+            ),
+        }
+    }
+    let mut message = json!({
+        "role": "assistant",
+        "content": if text.is_empty() && !tool_calls.is_empty() { Value::Null } else { json!(text) },
+    });
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+    Ok(message)
+}
+
+fn tool_to_openai(tool: &Value) -> Result<Value> {
+    // Client tools have no type or `custom`; server tools (`web_search_*`,
+    // `bash_*`, ...) run on Anthropic's side and have no OpenAI equivalent.
+    if let Some(kind) = tool.get("type").and_then(|v| v.as_str())
+        && kind != "custom"
+    {
+        bail!("tool type '{kind}' cannot be translated to OpenAI");
+    }
+    let Some(name) = tool.get("name").and_then(|v| v.as_str()) else {
+        bail!("tool without a name");
+    };
+    let mut function = json!({
+        "name": name,
+        "parameters": tool
+            .get("input_schema")
+            .cloned()
+            .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+    });
+    if let Some(description) = tool.get("description") {
+        function["description"] = description.clone();
+    }
+    Ok(json!({"type": "function", "function": function}))
+}
+
+/// Map an OpenAI `finish_reason` to Anthropic's `stop_reason`.
+fn stop_reason(reason: &str) -> &'static str {
+    match reason {
+        "length" => "max_tokens",
+        "tool_calls" | "function_call" => "tool_use",
+        "content_filter" => "refusal",
+        _ => "end_turn",
+    }
+}
+
+/// Anthropic usage from OpenAI's. OpenAI counts cached tokens inside
+/// `prompt_tokens`; Anthropic reports them separately.
+fn usage_to_claude(usage: &Value) -> Value {
+    let get = |k: &str| usage.get(k).and_then(|v| v.as_u64()).unwrap_or(0);
+    let cached = usage
+        .pointer("/prompt_tokens_details/cached_tokens")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(0);
+    json!({
+        "input_tokens": get("prompt_tokens").saturating_sub(cached),
+        "output_tokens": get("completion_tokens"),
+        "cache_read_input_tokens": cached,
+    })
+}
+
+fn message_id() -> String {
+    format!("msg_{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Tool call arguments as an object; arguments that aren't valid JSON (a
+/// truncated call) come back empty.
+fn parse_arguments(arguments: Option<&Value>) -> Value {
+    arguments
+        .and_then(|v| v.as_str())
+        .and_then(|raw| serde_json::from_str(raw).ok())
+        .unwrap_or_else(|| json!({}))
+}
+
+/// Translate a non-streaming OpenAI `chat.completion` into an Anthropic
+/// message. Only the first choice is kept; Messages has no `n`.
+pub fn response_to_claude(body: &Value, model: &str) -> Value {
+    let choice = body
+        .get("choices")
+        .and_then(|c| c.get(0))
+        .unwrap_or(&Value::Null);
+    let message = choice.get("message").unwrap_or(&Value::Null);
+
+    let mut content = Vec::new();
+    if let Some(text) = message.get("content").and_then(|v| v.as_str())
+        && !text.is_empty()
+    {
+        content.push(json!({"type": "text", "text": text}));
+    }
+    for call in message
+        .get("tool_calls")
+        .and_then(|v| v.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+    {
+        let function = call.get("function").unwrap_or(&Value::Null);
+        content.push(json!({
+            "type": "tool_use",
+            "id": call.get("id").cloned().unwrap_or(Value::Null),
+            "name": function.get("name").cloned().unwrap_or(Value::Null),
+            "input": parse_arguments(function.get("arguments")),
+        }));
+    }
+
+    json!({
+        "id": message_id(),
+        "type": "message",
+        "role": "assistant",
+        "model": model,
+        "content": content,
+        "stop_reason": stop_reason(
+            choice.get("finish_reason").and_then(|v| v.as_str()).unwrap_or("stop")
+        ),
+        "stop_sequence": null,
+        "usage": usage_to_claude(body.get("usage").unwrap_or(&Value::Null)),
+    })
+}
+
+/// Per-stream state for translating OpenAI `chat.completion.chunk` events
+/// into Anthropic stream events. One content block is open at a time: text
+/// accumulates in a text block until a tool call starts, and each tool call
+/// gets a `tool_use` block that stays open until the next one.
+///
+/// OpenAI numbers tool calls on their own, while Anthropic numbers content
+/// blocks across text and tool use alike, so `tool_blocks` maps each
+/// `tool_calls[].index` to the block index it was opened with.
+#[derive(Debug)]
+pub struct StreamState {
+    model: String,
+    started: bool,
+    next_index: u64,
+    open_block: Option<u64>,
+    /// Whether the open block is the text block.
+    text_open: bool,
+    tool_blocks: HashMap<u64, u64>,
+    finish_reason: Option<String>,
+    usage: Value,
+}
+
+impl StreamState {
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            started: false,
+            next_index: 0,
+            open_block: None,
+            text_open: false,
+            tool_blocks: HashMap::new(),
+            finish_reason: None,
+            usage: usage_to_claude(&Value::Null),
+        }
+    }
+
+    fn start(&mut self, events: &mut Vec<Value>) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        events.push(json!({
+            "type": "message_start",
+            "message": {
+                "id": message_id(),
+                "type": "message",
+                "role": "assistant",
+                "model": self.model,
+                "content": [],
+                "stop_reason": null,
+                "stop_sequence": null,
+                "usage": self.usage,
+            },
+        }));
+    }
+
+    fn close_block(&mut self, events: &mut Vec<Value>) {
+        if let Some(index) = self.open_block.take() {
+            events.push(json!({"type": "content_block_stop", "index": index}));
+        }
+        self.text_open = false;
+    }
+
+    fn open_block(&mut self, events: &mut Vec<Value>, block: Value) -> u64 {
+        self.close_block(events);
+        let index = self.next_index;
+        self.next_index += 1;
+        self.open_block = Some(index);
+        events.push(json!({
+            "type": "content_block_start",
+            "index": index,
+            "content_block": block,
+        }));
+        index
+    }
+
+    /// Translate one upstream `data:` payload. Returns the event payloads to
+    /// emit, in order (possibly none).
+    pub fn event(&mut self, parsed: &Value) -> Vec<Value> {
+        let mut events = Vec::new();
+        if let Some(error) = parsed.get("error") {
+            events.push(json!({"type": "error", "error": {
+                "type": error.get("type").cloned().unwrap_or(json!("api_error")),
+                "message": error.get("message").cloned().unwrap_or(json!("Upstream stream error")),
+            }}));
+            return events;
+        }
+        if let Some(usage) = parsed.get("usage").filter(|u| !u.is_null()) {
+            self.usage = usage_to_claude(usage);
+        }
+        self.start(&mut events);
+        let Some(choice) = parsed.get("choices").and_then(|c| c.get(0)) else {
+            return events;
+        };
+        let delta = choice.get("delta").unwrap_or(&Value::Null);
+
+        if let Some(text) = delta.get("content").and_then(|v| v.as_str())
+            && !text.is_empty()
+        {
+            let index = match self.open_block {
+                Some(index) if self.text_open => index,
+                _ => {
+                    let index = self.open_block(&mut events, json!({"type": "text", "text": ""}));
+                    self.text_open = true;
+                    index
+                }
+            };
+            events.push(json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": {"type": "text_delta", "text": text},
+            }));
+        }
+
+        for call in delta
+            .get("tool_calls")
+            .and_then(|v| v.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+        {
+            let tool_index = call.get("index").and_then(|v| v.as_u64()).unwrap_or(0);
+            let function = call.get("function").unwrap_or(&Value::Null);
+            let index = match self.tool_blocks.get(&tool_index) {
+                Some(&index) => index,
+                None => {
+                    let index = self.open_block(
+                        &mut events,
+                        json!({
+                            "type": "tool_use",
+                            "id": call.get("id").cloned().unwrap_or(Value::Null),
+                            "name": function.get("name").cloned().unwrap_or(Value::Null),
+                            "input": {},
+                        }),
+                    );
+                    self.tool_blocks.insert(tool_index, index);
+                    index
+                }
+            };
+            if let Some(arguments) = function.get("arguments").and_then(|v| v.as_str())
+                && !arguments.is_empty()
+            {
+                events.push(json!({
+                    "type": "content_block_delta",
+                    "index": index,
+                    "delta": {"type": "input_json_delta", "partial_json": arguments},
+                }));
+            }
+        }
+
+        if let Some(reason) = choice.get("finish_reason").and_then(|v| v.as_str()) {
+            self.finish_reason = Some(reason.to_string());
+        }
+        events
+    }
+
+    /// Closing events after the upstream ends: the final `message_delta`
+    /// with stop reason and usage, then `message_stop`.
+    pub fn finish(&mut self) -> Vec<Value> {
+        let mut events = Vec::new();
+        self.start(&mut events);
+        self.close_block(&mut events);
+        events.push(json!({
+            "type": "message_delta",
+            "delta": {
+                "stop_reason": stop_reason(self.finish_reason.as_deref().unwrap_or("stop")),
+                "stop_sequence": null,
+            },
+            "usage": self.usage,
+        }));
+        events.push(json!({"type": "message_stop"}));
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_maps_system_tools_and_tool_round_trip() {
+        let mut body = json!({
+            "model": "gpt-4.1",
+            "system": [{"type": "text", "text": "be brief"}],
+            "max_tokens": 512,
+            "top_k": 40,
+            "stop_sequences": ["END"],
+            "metadata": {"user_id": "u-1"},
+            "messages": [
+                {"role": "user", "content": "weather in Paris and Rome?"},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "...", "signature": "sig"},
+                    {"type": "tool_use", "id": "toolu_1", "name": "weather", "input": {"city": "Paris"}},
+                    {"type": "tool_use", "id": "toolu_2", "name": "weather", "input": {"city": "Rome"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": [{"type": "text", "text": "sunny"}]},
+                    {"type": "tool_result", "tool_use_id": "toolu_2", "content": "no data", "is_error": true},
+                    {"type": "text", "text": "and tomorrow?"}
+                ]}
+            ],
+            "tools": [{"name": "weather", "description": "Get the weather", "input_schema": {"type": "object"}}],
+            "tool_choice": {"type": "tool", "name": "weather", "disable_parallel_tool_use": true}
+        });
+        let dropped = request_to_openai(&mut body).unwrap();
+        assert_eq!(dropped, ["top_k"]);
+
+        assert!(body.get("model").is_none());
+        assert_eq!(body["max_tokens"], json!(512));
+        assert_eq!(body["stop"], json!(["END"]));
+        assert_eq!(body["user"], json!("u-1"));
+        assert_eq!(
+            body["tools"][0],
+            json!({"type": "function", "function": {
+                "name": "weather", "description": "Get the weather", "parameters": {"type": "object"}
+            }})
+        );
+        assert_eq!(
+            body["tool_choice"],
+            json!({"type": "function", "function": {"name": "weather"}})
+        );
+        assert_eq!(body["parallel_tool_calls"], json!(false));
+
+        let messages = body["messages"].as_array().unwrap();
+        let roles: Vec<&str> = messages
+            .iter()
+            .map(|m| m["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            roles,
+            ["system", "user", "assistant", "tool", "tool", "user"]
+        );
+        assert_eq!(messages[0]["content"], json!("be brief"));
+        assert_eq!(messages[2]["content"], Value::Null);
+        assert_eq!(
+            messages[2]["tool_calls"][1]["function"],
+            json!({"name": "weather", "arguments": "{\"city\":\"Rome\"}"})
+        );
+        assert_eq!(
+            messages[3],
+            json!({"role": "tool", "tool_call_id": "toolu_1", "content": "sunny"})
+        );
+        assert_eq!(messages[4]["content"], json!("Error: no data"));
+        assert_eq!(
+            messages[5]["content"],
+            json!([{"type": "text", "text": "and tomorrow?"}])
+        );
+    }
+
+    #[test]
+    fn request_maps_images_and_documents_and_rejects_server_tools() {
+        let mut body = json!({"messages": [{"role": "user", "content": [
+            {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBO"}},
+            {"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}},
+            {"type": "document", "title": "report.pdf", "source": {"type": "base64", "media_type": "application/pdf", "data": "JVBERi0="}},
+            {"type": "document", "source": {"type": "text", "media_type": "text/plain", "data": "notes"}}
+        ]}]});
+        request_to_openai(&mut body).unwrap();
+        assert_eq!(
+            body["messages"][0]["content"],
+            json!([
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBO"}},
+                {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}},
+                {"type": "file", "file": {"filename": "report.pdf", "file_data": "data:application/pdf;base64,JVBERi0="}},
+                {"type": "text", "text": "notes"}
+            ])
+        );
+
+        let mut body = json!({"messages": [{"role": "user", "content": [
+            {"type": "document", "source": {"type": "file", "file_id": "file_1"}}
+        ]}]});
+        let err = request_to_openai(&mut body).unwrap_err();
+        assert!(
+            err.downcast_ref::<crate::transforms::documents::UnsupportedContent>()
+                .is_some()
+        );
+
+        let mut body = json!({
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [{"type": "web_search_20250305", "name": "web_search"}]
+        });
+        assert!(request_to_openai(&mut body).is_err());
+    }
+
+    #[test]
+    fn response_maps_tool_calls_to_tool_use() {
+        let body = json!({
+            "choices": [{"message": {
+                "role": "assistant",
+                "content": "Checking.",
+                "tool_calls": [{"id": "call_1", "type": "function", "function": {"name": "weather", "arguments": "{\"city\":\"Paris\"}"}}]
+            }, "finish_reason": "tool_calls"}],
+            "usage": {"prompt_tokens": 100, "completion_tokens": 7, "prompt_tokens_details": {"cached_tokens": 60}}
+        });
+        let out = response_to_claude(&body, "gpt-4.1");
+        assert_eq!(out["stop_reason"], json!("tool_use"));
+        assert_eq!(
+            out["content"],
+            json!([
+                {"type": "text", "text": "Checking."},
+                {"type": "tool_use", "id": "call_1", "name": "weather", "input": {"city": "Paris"}}
+            ])
+        );
+        assert_eq!(
+            out["usage"],
+            json!({"input_tokens": 40, "output_tokens": 7, "cache_read_input_tokens": 60})
+        );
+    }
+
+    #[test]
+    fn stream_emits_anthropic_block_lifecycle() {
+        let mut state = StreamState::new("gpt-4.1");
+        let chunks = [
+            json!({"choices": [{"index": 0, "delta": {"role": "assistant", "content": ""}}]}),
+            json!({"choices": [{"index": 0, "delta": {"content": "Let me "}}]}),
+            json!({"choices": [{"index": 0, "delta": {"content": "check."}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 0, "id": "call_a", "type": "function", "function": {"name": "weather", "arguments": ""}}
+            ]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "function": {"arguments": "{\"city\":"}}]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "function": {"arguments": "\"Paris\"}"}}]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 1, "id": "call_b", "type": "function", "function": {"name": "time", "arguments": "{}"}}
+            ]}}]}),
+            json!({"choices": [{"index": 0, "delta": {}, "finish_reason": "tool_calls"}]}),
+            json!({"choices": [], "usage": {"prompt_tokens": 10, "completion_tokens": 5}}),
+        ];
+        let mut events: Vec<Value> = chunks.iter().flat_map(|c| state.event(c)).collect();
+        events.extend(state.finish());
+
+        let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            types,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop"
+            ]
+        );
+        assert_eq!(events[5]["index"], json!(1));
+        assert_eq!(events[5]["content_block"]["id"], json!("call_a"));
+        let arguments: String = events[6..8]
+            .iter()
+            .map(|e| e["delta"]["partial_json"].as_str().unwrap())
+            .collect();
+        assert_eq!(arguments, "{\"city\":\"Paris\"}");
+        assert_eq!(events[9]["index"], json!(2));
+        assert_eq!(events[9]["content_block"]["name"], json!("time"));
+        assert_eq!(events[12]["delta"]["stop_reason"], json!("tool_use"));
+        assert_eq!(events[12]["usage"]["input_tokens"], json!(10));
+        assert_eq!(events[12]["usage"]["output_tokens"], json!(5));
+    }
+}
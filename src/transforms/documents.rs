@@ -0,0 +1,96 @@
+//! Document content (PDFs and text files) across schemas.
+//!
+//! Each family carries documents differently: OpenAI Chat Completions as
+//! `file` content parts with a `data:` URL in `file_data`, Anthropic as
+//! `document` blocks (base64 PDF, or plain text), Gemini as `inlineData`
+//! parts. The translators map between them where the target accepts the
+//! media type natively. Text-like files the target can't take as-is
+//! (`text/csv` for Claude, `application/json` for either) are decoded and
+//! inlined as text when acr is built with the `document-text` feature;
+//! anything else is rejected with [`UnsupportedContent`], which the proxy
+//! answers with `415 Unsupported Media Type`.
+
+use anyhow::Result;
+
+pub const PDF_MEDIA_TYPE: &str = "application/pdf";
+
+/// A request carries content the upstream family can't accept.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct UnsupportedContent(pub String);
+
+/// Shorthand for returning [`UnsupportedContent`] through `anyhow`.
+pub(crate) fn unsupported<T>(message: String) -> Result<T> {
+    Err(UnsupportedContent(message).into())
+}
+
+/// Media types whose payload is readable text.
+pub(crate) fn is_text_media(media_type: &str) -> bool {
+    let essence = media_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    essence.starts_with("text/")
+        || matches!(
+            essence.as_str(),
+            "application/json" | "application/xml" | "application/yaml" | "application/x-yaml"
+        )
+}
+
+/// Decode a base64 text document, or `None` when it isn't valid UTF-8 text
+/// or acr was built without the `document-text` feature.
+#[cfg(feature = "document-text")]
+pub(crate) fn extract_text(media_type: &str, data: &str) -> Option<String> {
+    use base64::Engine;
+
+    if !is_text_media(media_type) {
+        return None;
+    }
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data.trim())
+        .ok()?;
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(not(feature = "document-text"))]
+pub(crate) fn extract_text(_media_type: &str, _data: &str) -> Option<String> {
+    None
+}
+
+/// Why a text document couldn't be inlined, for the rejection message.
+pub(crate) fn extraction_hint() -> &'static str {
+    if cfg!(feature = "document-text") {
+        "it is not valid UTF-8 text"
+    } else {
+        "acr was built without the `document-text` feature"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_media_types_are_recognized() {
+        assert!(is_text_media("text/csv"));
+        assert!(is_text_media("text/plain; charset=utf-8"));
+        assert!(is_text_media("Application/JSON"));
+        assert!(!is_text_media(PDF_MEDIA_TYPE));
+        assert!(!is_text_media("image/png"));
+    }
+
+    #[test]
+    fn extraction_follows_the_feature() {
+        // "a,b\n1,2" in base64.
+        let extracted = extract_text("text/csv", "YSxiCjEsMg==");
+        if cfg!(feature = "document-text") {
+            assert_eq!(extracted.as_deref(), Some("a,b\n1,2"));
+            assert_eq!(extract_text(PDF_MEDIA_TYPE, "YSxiCjEsMg=="), None);
+            assert_eq!(extract_text("text/plain", "not base64!"), None);
+        } else {
+            assert_eq!(extracted, None);
+        }
+    }
+}
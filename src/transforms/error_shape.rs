@@ -0,0 +1,413 @@
+//! Re-shape upstream error bodies into the wire format the *client* speaks.
+//!
+//! The route a client calls fixes the API shape it expects back (`/v1/messages`
+//! → Anthropic, `/v1/chat/completions` → OpenAI, `/gemini/...` → Gemini), but
+//! the upstream family is picked from the model name. When the two differ, a
+//! verbatim upstream error would arrive in a schema the client's SDK can't
+//! parse — e.g. the Anthropic SDK looks for `{"type":"error","error":{...}}`
+//! and surfaces an opaque "unknown error" for an Azure `{"error":{"code":...}}`.
+//!
+//! Translation goes through a small canonical [`ErrorKind`], derived from the
+//! HTTP status first and refined by the upstream's own type / code / status
+//! string, then rendered in the client family's schema. The human-readable
+//! message is carried across unchanged.
+//!
+//! Source-of-truth references:
+//! * Anthropic error shapes: <https://docs.claude.com/en/api/errors>
+//! * OpenAI error codes: <https://platform.openai.com/docs/guides/error-codes>
+//! * Gemini / Google API error model:
+//!   <https://ai.google.dev/gemini-api/docs/troubleshooting#error-codes>
+
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use crate::proxy::LlmFamily;
+
+/// Family-neutral error category. Variants mirror Anthropic's error `type`
+/// vocabulary, which is the richest of the three.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    InvalidRequest,
+    Authentication,
+    Permission,
+    NotFound,
+    RequestTooLarge,
+    RateLimit,
+    Overloaded,
+    Api,
+}
+
+impl ErrorKind {
+    fn from_status(status: StatusCode) -> Self {
+        match status.as_u16() {
+            401 => ErrorKind::Authentication,
+            403 => ErrorKind::Permission,
+            404 => ErrorKind::NotFound,
+            413 => ErrorKind::RequestTooLarge,
+            429 => ErrorKind::RateLimit,
+            503 | 529 => ErrorKind::Overloaded,
+            400..=499 => ErrorKind::InvalidRequest,
+            _ => ErrorKind::Api,
+        }
+    }
+
+    /// Refine from an upstream type / code / status string. Returns `None` for
+    /// markers we don't recognise so the status-derived kind stands.
+    fn from_marker(marker: &str) -> Option<Self> {
+        let kind = match marker.to_ascii_lowercase().as_str() {
+            // OpenAI / Azure codes that Anthropic reports as invalid requests.
+            "context_length_exceeded" | "string_above_max_length" | "content_filter" => {
+                ErrorKind::InvalidRequest
+            }
+            "invalid_request_error"
+            | "validationexception"
+            | "invalid_argument"
+            | "failed_precondition"
+            | "out_of_range" => ErrorKind::InvalidRequest,
+            "authentication_error" | "invalid_api_key" | "unauthenticated" => {
+                ErrorKind::Authentication
+            }
+            "permission_error" | "permission_denied" | "accessdeniedexception" => {
+                ErrorKind::Permission
+            }
+            "not_found_error"
+            | "not_found"
+            | "model_not_found"
+            | "deploymentnotfound"
+            | "resourcenotfoundexception" => ErrorKind::NotFound,
+            "request_too_large" => ErrorKind::RequestTooLarge,
+            "rate_limit_error"
+            | "rate_limit_exceeded"
+            | "too_many_requests"
+            | "throttlingexception"
+            | "resource_exhausted" => ErrorKind::RateLimit,
+            "overloaded_error" | "unavailable" | "serviceunavailableexception" => {
+                ErrorKind::Overloaded
+            }
+            "api_error" | "server_error" | "internal" => ErrorKind::Api,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    fn anthropic_type(self) -> &'static str {
+        match self {
+            ErrorKind::InvalidRequest => "invalid_request_error",
+            ErrorKind::Authentication => "authentication_error",
+            ErrorKind::Permission => "permission_error",
+            ErrorKind::NotFound => "not_found_error",
+            ErrorKind::RequestTooLarge => "request_too_large",
+            ErrorKind::RateLimit => "rate_limit_error",
+            ErrorKind::Overloaded => "overloaded_error",
+            ErrorKind::Api => "api_error",
+        }
+    }
+
+    fn openai_type(self) -> &'static str {
+        match self {
+            ErrorKind::InvalidRequest | ErrorKind::RequestTooLarge | ErrorKind::NotFound => {
+                "invalid_request_error"
+            }
+            ErrorKind::Authentication => "authentication_error",
+            ErrorKind::Permission => "permission_error",
+            ErrorKind::RateLimit => "rate_limit_error",
+            ErrorKind::Overloaded | ErrorKind::Api => "server_error",
+        }
+    }
+
+    fn gemini_status(self) -> &'static str {
+        match self {
+            ErrorKind::InvalidRequest | ErrorKind::RequestTooLarge => "INVALID_ARGUMENT",
+            ErrorKind::Authentication => "UNAUTHENTICATED",
+            ErrorKind::Permission => "PERMISSION_DENIED",
+            ErrorKind::NotFound => "NOT_FOUND",
+            ErrorKind::RateLimit => "RESOURCE_EXHAUSTED",
+            ErrorKind::Overloaded => "UNAVAILABLE",
+            ErrorKind::Api => "INTERNAL",
+        }
+    }
+}
+
+/// True when the two families share an error schema (OpenAI Chat Completions
+/// and the Responses API both use the `{"error":{"message","type","code"}}`
+/// envelope), so no translation is needed. A passthrough upstream's errors
+/// are forwarded as they are, like the rest of its responses.
+pub fn same_error_shape(a: LlmFamily, b: LlmFamily) -> bool {
+    let is_openai = |f| {
+        matches!(
+            f,
+            LlmFamily::OpenAi | LlmFamily::OpenAiResponses | LlmFamily::OpenWeight
+        )
+    };
+    a == b
+        || (is_openai(a) && is_openai(b))
+        || a == LlmFamily::Passthrough
+        || b == LlmFamily::Passthrough
+}
+
+/// Translate an upstream error body from `upstream`'s schema into `client`'s.
+///
+/// Returns `None` when no translation is needed (same schema) or when the
+/// body isn't JSON — callers then forward the upstream bytes verbatim, which
+/// is still the most useful thing to show the client.
+pub fn translate(
+    status: StatusCode,
+    body: &str,
+    upstream: LlmFamily,
+    client: LlmFamily,
+) -> Option<Value> {
+    if same_error_shape(upstream, client) {
+        return None;
+    }
+    let parsed: Value = serde_json::from_str(body).ok()?;
+    let (message, marker) = extract_message_and_marker(&parsed);
+    let kind = marker
+        .as_deref()
+        .and_then(ErrorKind::from_marker)
+        .unwrap_or_else(|| ErrorKind::from_status(status));
+    let message = message.unwrap_or_else(|| {
+        status
+            .canonical_reason()
+            .unwrap_or("Upstream error")
+            .to_string()
+    });
+    Some(render(kind, status, &message, marker.as_deref(), client))
+}
+
+/// Normalise any error body — upstream, or acr's own `{"error": "..."}` — into
+/// Anthropic's `{"type":"error","error":{...}}` envelope. Unlike [`translate`]
+/// this always returns a value: bodies already in the Anthropic shape pass
+/// through, and non-JSON bodies become the message text.
+pub fn anthropic_envelope(status: StatusCode, body: &str) -> Value {
+    let parsed = serde_json::from_str::<Value>(body).ok();
+    if let Some(ref parsed) = parsed
+        && parsed.get("type").and_then(|t| t.as_str()) == Some("error")
+        && parsed.get("error").is_some_and(Value::is_object)
+    {
+        return parsed.clone();
+    }
+    let (message, marker) = parsed
+        .as_ref()
+        .map(extract_message_and_marker)
+        .unwrap_or((None, None));
+    let kind = marker
+        .as_deref()
+        .and_then(ErrorKind::from_marker)
+        .unwrap_or_else(|| ErrorKind::from_status(status));
+    let message = message
+        .or_else(|| (parsed.is_none() && !body.trim().is_empty()).then(|| body.trim().to_string()))
+        .unwrap_or_else(|| {
+            status
+                .canonical_reason()
+                .unwrap_or("Upstream error")
+                .to_string()
+        });
+    render(kind, status, &message, None, LlmFamily::Claude)
+}
+
+/// An acr-side failure (status 500) in `client`'s error schema.
+pub fn internal_error(message: &str, client: LlmFamily) -> Value {
+    render(
+        ErrorKind::Api,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        message,
+        None,
+        client,
+    )
+}
+
+/// Pull the message and the most specific type/code marker out of any of the
+/// three upstream shapes, plus Bedrock's bare `{"message": ...}` form that AI
+/// Core passes through for Claude validation errors.
+fn extract_message_and_marker(parsed: &Value) -> (Option<String>, Option<String>) {
+    let as_string = |v: Option<&Value>| v.and_then(|v| v.as_str()).map(str::to_string);
+    let err = parsed.get("error");
+    let message = as_string(err.and_then(|e| e.get("message")))
+        .or_else(|| as_string(parsed.get("message")))
+        .or_else(|| as_string(err));
+    // `code` is more specific than `type` on OpenAI (`context_length_exceeded`
+    // vs `invalid_request_error`); Gemini's `status` is its only string marker.
+    let marker = err
+        .and_then(|e| {
+            as_string(e.get("code"))
+                .or_else(|| as_string(e.get("type")))
+                .or_else(|| as_string(e.get("status")))
+        })
+        .or_else(|| as_string(parsed.get("__type")));
+    (message, marker)
+}
+
+fn render(
+    kind: ErrorKind,
+    status: StatusCode,
+    message: &str,
+    upstream_code: Option<&str>,
+    client: LlmFamily,
+) -> Value {
+    match client {
+        LlmFamily::Claude => json!({
+            "type": "error",
+            "error": {"type": kind.anthropic_type(), "message": message},
+        }),
+        LlmFamily::OpenAi
+        | LlmFamily::OpenAiResponses
+        | LlmFamily::OpenWeight
+        | LlmFamily::Passthrough => json!({
+            "error": {
+                "message": message,
+                "type": kind.openai_type(),
+                "param": null,
+                "code": upstream_code,
+            },
+        }),
+        LlmFamily::Gemini => json!({
+            "error": {
+                "code": status.as_u16(),
+                "message": message,
+                "status": kind.gemini_status(),
+            },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn azure_context_length_becomes_anthropic_invalid_request() {
+        let body = r#"{"error":{"message":"This model's maximum context length is 128000 tokens.","type":"invalid_request_error","param":"messages","code":"context_length_exceeded"}}"#;
+        let out = translate(
+            StatusCode::BAD_REQUEST,
+            body,
+            LlmFamily::OpenAi,
+            LlmFamily::Claude,
+        )
+        .unwrap();
+        assert_eq!(out["type"], json!("error"));
+        assert_eq!(out["error"]["type"], json!("invalid_request_error"));
+        assert_eq!(
+            out["error"]["message"],
+            json!("This model's maximum context length is 128000 tokens.")
+        );
+    }
+
+    #[test]
+    fn bedrock_bare_message_becomes_openai_envelope() {
+        let body = r#"{"message":"messages: text content blocks must be non-empty"}"#;
+        let out = translate(
+            StatusCode::BAD_REQUEST,
+            body,
+            LlmFamily::Claude,
+            LlmFamily::OpenAi,
+        )
+        .unwrap();
+        assert_eq!(
+            out["error"]["message"],
+            json!("messages: text content blocks must be non-empty")
+        );
+        assert_eq!(out["error"]["type"], json!("invalid_request_error"));
+    }
+
+    #[test]
+    fn gemini_status_maps_to_anthropic_type() {
+        let body = r#"{"error":{"code":403,"message":"denied","status":"PERMISSION_DENIED"}}"#;
+        let out = translate(
+            StatusCode::FORBIDDEN,
+            body,
+            LlmFamily::Gemini,
+            LlmFamily::Claude,
+        )
+        .unwrap();
+        assert_eq!(out["error"]["type"], json!("permission_error"));
+    }
+
+    #[test]
+    fn anthropic_overloaded_renders_as_gemini_unavailable() {
+        let body = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let out = translate(
+            StatusCode::from_u16(529).unwrap(),
+            body,
+            LlmFamily::Claude,
+            LlmFamily::Gemini,
+        )
+        .unwrap();
+        assert_eq!(out["error"]["status"], json!("UNAVAILABLE"));
+        assert_eq!(out["error"]["code"], json!(529));
+        assert_eq!(out["error"]["message"], json!("Overloaded"));
+    }
+
+    #[test]
+    fn unknown_marker_falls_back_to_status() {
+        let body = r#"{"error":{"message":"nope","code":"something_new"}}"#;
+        let out = translate(
+            StatusCode::NOT_FOUND,
+            body,
+            LlmFamily::OpenAi,
+            LlmFamily::Claude,
+        )
+        .unwrap();
+        assert_eq!(out["error"]["type"], json!("not_found_error"));
+    }
+
+    #[test]
+    fn same_schema_is_not_translated() {
+        let body = r#"{"error":{"message":"x"}}"#;
+        assert!(
+            translate(
+                StatusCode::BAD_REQUEST,
+                body,
+                LlmFamily::OpenAi,
+                LlmFamily::OpenAiResponses
+            )
+            .is_none()
+        );
+        assert!(
+            translate(
+                StatusCode::BAD_REQUEST,
+                body,
+                LlmFamily::Claude,
+                LlmFamily::Claude
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn anthropic_envelope_wraps_acr_and_plain_text_errors() {
+        let out = anthropic_envelope(
+            StatusCode::TOO_MANY_REQUESTS,
+            r#"{"error":"Per-key request rate limit exceeded. Retry after 3 seconds."}"#,
+        );
+        assert_eq!(out["type"], json!("error"));
+        assert_eq!(out["error"]["type"], json!("rate_limit_error"));
+        assert_eq!(
+            out["error"]["message"],
+            json!("Per-key request rate limit exceeded. Retry after 3 seconds.")
+        );
+
+        let out = anthropic_envelope(StatusCode::BAD_GATEWAY, "upstream went away");
+        assert_eq!(out["error"]["type"], json!("api_error"));
+        assert_eq!(out["error"]["message"], json!("upstream went away"));
+
+        let native =
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert_eq!(
+            anthropic_envelope(StatusCode::from_u16(529).unwrap(), native),
+            serde_json::from_str::<Value>(native).unwrap()
+        );
+    }
+
+    #[test]
+    fn non_json_body_is_not_translated() {
+        assert!(
+            translate(
+                StatusCode::BAD_GATEWAY,
+                "<html>bad gateway</html>",
+                LlmFamily::OpenAi,
+                LlmFamily::Claude
+            )
+            .is_none()
+        );
+    }
+}
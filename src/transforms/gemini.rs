@@ -7,6 +7,8 @@
 use anyhow::Result;
 use serde_json::{Map, Value, json};
 
+use crate::config::SafetySetting;
+
 /// Prepare a Gemini request body for AI Core.
 ///
 /// Drops fields the upstream wrapper doesn't expect (`model`, `stream`),
@@ -66,6 +68,44 @@ fn fix_thinking_budget(obj: &mut Map<String, Value>) {
     }
 }
 
+/// Merge operator-configured `safetySettings` into the request: a setting
+/// fills in its category when the client didn't set it, and with `enforce`
+/// replaces the client's threshold.
+pub fn apply_safety_settings(body: &mut Value, settings: &[SafetySetting]) {
+    if settings.is_empty() {
+        return;
+    }
+    let Some(obj) = body.as_object_mut() else {
+        return;
+    };
+    let entry = obj
+        .entry("safetySettings")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    let Some(client) = entry.as_array_mut() else {
+        return;
+    };
+    for setting in settings {
+        let existing = client
+            .iter_mut()
+            .find(|s| s.get("category").and_then(Value::as_str) == Some(&setting.category));
+        let configured = json!({"category": setting.category, "threshold": setting.threshold});
+        match existing {
+            Some(existing) if setting.enforce => {
+                if existing.get("threshold") != configured.get("threshold") {
+                    tracing::debug!(
+                        "Enforcing Gemini safety setting {} = {}",
+                        setting.category,
+                        setting.threshold
+                    );
+                }
+                *existing = configured;
+            }
+            Some(_) => {}
+            None => client.push(configured),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -133,4 +173,42 @@ mod tests {
             json!(1024)
         );
     }
+
+    #[test]
+    fn safety_settings_fill_defaults_and_enforce_overrides() {
+        let setting = |category: &str, threshold: &str, enforce| SafetySetting {
+            category: category.to_string(),
+            threshold: threshold.to_string(),
+            enforce,
+        };
+        let settings = [
+            setting("HARM_CATEGORY_HARASSMENT", "BLOCK_ONLY_HIGH", false),
+            setting("HARM_CATEGORY_HATE_SPEECH", "BLOCK_ONLY_HIGH", false),
+            setting(
+                "HARM_CATEGORY_DANGEROUS_CONTENT",
+                "BLOCK_LOW_AND_ABOVE",
+                true,
+            ),
+        ];
+        let mut body = json!({
+            "contents": [],
+            "safetySettings": [
+                {"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_NONE"},
+                {"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_NONE"}
+            ]
+        });
+        apply_safety_settings(&mut body, &settings);
+        assert_eq!(
+            body["safetySettings"],
+            json!([
+                {"category": "HARM_CATEGORY_HARASSMENT", "threshold": "BLOCK_NONE"},
+                {"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_LOW_AND_ABOVE"},
+                {"category": "HARM_CATEGORY_HATE_SPEECH", "threshold": "BLOCK_ONLY_HIGH"}
+            ])
+        );
+
+        let mut body = json!({"contents": []});
+        apply_safety_settings(&mut body, &[]);
+        assert!(body.get("safetySettings").is_none());
+    }
 }
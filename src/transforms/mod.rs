@@ -2,12 +2,22 @@
 //!
 //! Each submodule shapes outgoing JSON for one upstream provider's request format.
 //! The dispatcher sits in `proxy::prepare_body`; see each submodule's doc-comments
-//! for the source-of-truth references.
+//! for the source-of-truth references. `error_shape` runs in the other direction,
+//! re-shaping upstream error bodies for the client's schema, and `translate`
+//! bridges requests whose client schema differs from the upstream family's.
 
 pub mod anthropic;
+pub mod claude_gemini;
+pub mod claude_openai;
+pub mod documents;
+pub mod error_shape;
 pub mod gemini;
+pub mod open_weight;
 pub mod openai;
+pub mod openai_claude;
+pub mod openai_gemini;
 pub mod openai_responses;
 pub mod stream_classify;
+pub mod translate;
 
 pub use anthropic::extract_anthropic_beta;
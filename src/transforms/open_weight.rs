@@ -0,0 +1,67 @@
+//! Open-weight models on SAP AI Core — Mistral (`aicore-mistralai`) and Llama
+//! (`aicore-opensource`) — served behind an OpenAI-compatible
+//! `/chat/completions` endpoint.
+//!
+//! The request and response shapes are OpenAI Chat Completions, with three
+//! differences from Azure OpenAI:
+//! * the body must name the AI Core model in `model`; the deployment alone
+//!   doesn't select it,
+//! * the output cap is `max_tokens` (these servers don't know
+//!   `max_completion_tokens`),
+//! * the URL takes no `api-version`.
+
+use anyhow::Result;
+use serde_json::{Value, json};
+
+use crate::transforms::openai::inject_include_usage;
+
+/// Prepare a chat request for an open-weight model.
+///
+/// * Sets `model` to `aicore_model`, the name AI Core knows the model by.
+/// * Renames `max_completion_tokens` → `max_tokens` unless the client sent both.
+/// * For streaming requests, sets `stream_options.include_usage = true` so the
+///   final chunk carries token counts.
+pub fn prepare(body: &mut Value, aicore_model: &str, stream: bool) -> Result<()> {
+    let Some(obj) = body.as_object_mut() else {
+        return Ok(());
+    };
+
+    obj.insert("model".to_string(), json!(aicore_model));
+
+    if !obj.contains_key("max_tokens")
+        && let Some(max_tokens) = obj.remove("max_completion_tokens")
+    {
+        obj.insert("max_tokens".to_string(), max_tokens);
+    }
+
+    if stream {
+        inject_include_usage(obj);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prepare_names_the_model_and_uses_max_tokens() {
+        let mut body = json!({
+            "model": "mistral-large",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "max_completion_tokens": 256,
+            "stream": true
+        });
+        prepare(&mut body, "mistralai--mistral-large-instruct", true).unwrap();
+        assert_eq!(body["model"], "mistralai--mistral-large-instruct");
+        assert_eq!(body["max_tokens"], 256);
+        assert!(body.get("max_completion_tokens").is_none());
+        assert_eq!(body["stream_options"]["include_usage"], true);
+
+        let mut body = json!({"max_tokens": 64, "messages": []});
+        prepare(&mut body, "meta--llama3.1-70b-instruct", false).unwrap();
+        assert_eq!(body["max_tokens"], 64);
+        assert!(body.get("stream_options").is_none());
+    }
+}
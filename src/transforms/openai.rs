@@ -17,6 +17,8 @@
 use anyhow::Result;
 use serde_json::{Map, Value, json};
 
+use crate::constants::api::MAX_TOP_LOGPROBS;
+
 /// Prepare an OpenAI request body.
 ///
 /// * Renames legacy `max_tokens` → `max_completion_tokens` (the canonical field since
@@ -38,16 +40,7 @@ pub fn prepare(body: &mut Value, stream: bool) -> Result<()> {
     }
 
     if stream {
-        match obj.get_mut("stream_options") {
-            Some(existing_options) => {
-                if let Some(options_obj) = existing_options.as_object_mut() {
-                    options_obj.insert("include_usage".to_string(), json!(true));
-                }
-            }
-            None => {
-                obj.insert("stream_options".to_string(), json!({"include_usage": true}));
-            }
-        }
+        inject_include_usage(obj);
     }
 
     normalize_messages(obj);
@@ -55,6 +48,75 @@ pub fn prepare(body: &mut Value, stream: bool) -> Result<()> {
     Ok(())
 }
 
+/// Prepare a legacy Completions API (`/completions`) body. Only the usage
+/// injection applies: the legacy API still takes `max_tokens` (it has no
+/// `max_completion_tokens`) and carries a `prompt`, not `messages`.
+pub fn prepare_legacy_completions(body: &mut Value, stream: bool) -> Result<()> {
+    let Some(obj) = body.as_object_mut() else {
+        return Ok(());
+    };
+    if stream {
+        inject_include_usage(obj);
+    }
+    Ok(())
+}
+
+/// Check `logprobs`, `top_logprobs` and `seed` against the Chat Completions
+/// schema, so a malformed value is rejected here rather than by whichever
+/// backend the request ends up on (or silently ignored by a translation).
+pub fn validate_sampling_params(body: &Value) -> Result<(), String> {
+    let get = |key: &str| body.get(key).filter(|v| !v.is_null());
+    if let Some(v) = get("logprobs")
+        && !v.is_boolean()
+    {
+        return Err("'logprobs' must be a boolean".to_string());
+    }
+    if let Some(v) = get("top_logprobs") {
+        if v.as_u64().is_none_or(|n| n > MAX_TOP_LOGPROBS) {
+            return Err(format!(
+                "'top_logprobs' must be an integer between 0 and {MAX_TOP_LOGPROBS}"
+            ));
+        }
+        if get("logprobs").and_then(Value::as_bool) != Some(true) {
+            return Err("'top_logprobs' requires 'logprobs' to be true".to_string());
+        }
+    }
+    if let Some(v) = get("seed")
+        && !(v.is_i64() || v.is_u64())
+    {
+        return Err("'seed' must be an integer".to_string());
+    }
+    Ok(())
+}
+
+/// The log-probability parameters a request actually asks for; translated
+/// backends can't return log probabilities in OpenAI's shape.
+pub fn requested_logprobs(body: &Value) -> Vec<&'static str> {
+    let mut requested = Vec::new();
+    if body.get("logprobs").and_then(Value::as_bool) == Some(true) {
+        requested.push("logprobs");
+    }
+    if body.get("top_logprobs").is_some_and(|v| !v.is_null()) {
+        requested.push("top_logprobs");
+    }
+    requested
+}
+
+/// Set `stream_options.include_usage = true`, merging into any client-provided
+/// `stream_options`, so the final chunk carries token counts.
+pub(crate) fn inject_include_usage(obj: &mut Map<String, Value>) {
+    match obj.get_mut("stream_options") {
+        Some(existing_options) => {
+            if let Some(options_obj) = existing_options.as_object_mut() {
+                options_obj.insert("include_usage".to_string(), json!(true));
+            }
+        }
+        None => {
+            obj.insert("stream_options".to_string(), json!({"include_usage": true}));
+        }
+    }
+}
+
 /// Detect the Codex-CLI preamble pattern:
 /// `assistant(tool_calls)` → `assistant(content preamble)` → `tool(response with matching id)`.
 fn is_preamble_pattern(msg: &Value, preamble: &Value, tool_msg: &Value) -> bool {
@@ -209,6 +271,15 @@ mod tests {
         assert!(!body.as_object().unwrap().contains_key("stream_options"));
     }
 
+    #[test]
+    fn legacy_completions_keeps_max_tokens_and_injects_usage() {
+        let mut body = json!({"prompt": "hi", "max_tokens": 16, "stream": true});
+        prepare_legacy_completions(&mut body, true).unwrap();
+        assert_eq!(body["max_tokens"], json!(16));
+        assert!(body.get("max_completion_tokens").is_none());
+        assert_eq!(body["stream_options"], json!({"include_usage": true}));
+    }
+
     #[test]
     fn normalize_merges_codex_preamble() {
         let mut body = json!({
@@ -238,4 +309,23 @@ mod tests {
         prepare(&mut body, false).unwrap();
         assert_eq!(body, original);
     }
+
+    #[test]
+    fn sampling_params_are_validated() {
+        assert!(
+            validate_sampling_params(&json!({"logprobs": true, "top_logprobs": 5, "seed": -3}))
+                .is_ok()
+        );
+        assert!(validate_sampling_params(&json!({"logprobs": null, "seed": null})).is_ok());
+        assert!(validate_sampling_params(&json!({"logprobs": "yes"})).is_err());
+        assert!(validate_sampling_params(&json!({"logprobs": true, "top_logprobs": 21})).is_err());
+        assert!(validate_sampling_params(&json!({"top_logprobs": 2})).is_err());
+        assert!(validate_sampling_params(&json!({"seed": 1.5})).is_err());
+
+        assert_eq!(
+            requested_logprobs(&json!({"logprobs": true, "top_logprobs": 2})),
+            ["logprobs", "top_logprobs"]
+        );
+        assert!(requested_logprobs(&json!({"logprobs": false})).is_empty());
+    }
 }
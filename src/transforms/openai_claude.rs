@@ -0,0 +1,870 @@
+//! OpenAI Chat Completions ⇄ Anthropic Messages translation.
+//!
+//! Lets clients that speak the OpenAI chat schema on `/v1/chat/completions`
+//! target Claude deployments, which AI Core only exposes through Bedrock's
+//! native Messages shape. Agents are the main users, so tool calling is
+//! bridged in both directions: `tools` / `tool_choice` / assistant
+//! `tool_calls` / `tool` messages on the way out, and `tool_use` blocks back
+//! as `tool_calls` — incrementally when streaming, with each `tool_use` block
+//! getting its own stable `tool_calls[].index` and its `input_json_delta`
+//! fragments forwarded as `function.arguments` deltas.
+//!
+//! Extended-thinking blocks have no standard Chat Completions field. When the
+//! API key opts in (`reasoning: include`) their text is surfaced as
+//! `reasoning_content`, the field DeepSeek-style clients read; otherwise it is
+//! stripped. Redacted thinking and signatures are always dropped — they are
+//! only meaningful when replayed to Anthropic verbatim.
+//!
+//! Source-of-truth references:
+//! * Messages API: <https://docs.anthropic.com/en/api/messages>
+//! * Streaming Messages: <https://docs.anthropic.com/en/docs/build-with-claude/streaming>
+//! * Chat Completions API: <https://platform.openai.com/docs/api-reference/chat/create>
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use serde_json::{Map, Value, json};
+
+use crate::transforms::documents::{
+    PDF_MEDIA_TYPE, extract_text, extraction_hint, is_text_media, unsupported,
+};
+use crate::transforms::openai_gemini::parse_data_url;
+
+/// OpenAI sampling parameters Claude has no equivalent for. They are dropped
+/// (and reported back to the client) when set to anything but their neutral
+/// value.
+const UNSUPPORTED_PARAMS: &[&str] = &["frequency_penalty", "presence_penalty"];
+
+/// Rewrite an OpenAI chat request body into an Anthropic Messages body.
+/// `anthropic::prepare` runs afterwards and fills in the Bedrock specifics.
+/// Returns the names of the parameters that had to be dropped.
+pub fn request_to_claude(body: &mut Value) -> Result<Vec<&'static str>> {
+    let Some(obj) = body.as_object() else {
+        return Ok(Vec::new());
+    };
+
+    let mut system: Vec<String> = Vec::new();
+    let mut messages: Vec<Value> = Vec::new();
+    for (i, message) in obj
+        .get("messages")
+        .and_then(|v| v.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+    {
+        let role = message.get("role").and_then(|v| v.as_str()).unwrap_or("");
+        match role {
+            "system" | "developer" => system.push(content_text(message.get("content"), i)?),
+            "user" => push_message(
+                &mut messages,
+                "user",
+                content_blocks(message.get("content"), i)?,
+            ),
+            "assistant" => {
+                let mut blocks = content_blocks(message.get("content"), i)?;
+                for call in message
+                    .get("tool_calls")
+                    .and_then(|v| v.as_array())
+                    .map(Vec::as_slice)
+                    .unwrap_or_default()
+                {
+                    blocks.push(tool_call_to_block(call, i)?);
+                }
+                push_message(&mut messages, "assistant", blocks);
+            }
+            "tool" => {
+                let Some(id) = message.get("tool_call_id").and_then(|v| v.as_str()) else {
+                    bail!("message at index {i}: tool messages need a tool_call_id");
+                };
+                let result = json!({
+                    "type": "tool_result",
+                    "tool_use_id": id,
+                    "content": content_text(message.get("content"), i)?,
+                });
+                push_message(&mut messages, "user", vec![result]);
+            }
+            other => bail!("message at index {i}: role '{other}' cannot be translated to Claude"),
+        }
+    }
+
+    let mut out = Map::new();
+    out.insert("messages".to_string(), Value::Array(messages));
+    if !system.is_empty() {
+        out.insert("system".to_string(), json!(system.join("\n\n")));
+    }
+    // The canonical field wins when a client sends both.
+    for key in ["max_tokens", "max_completion_tokens"] {
+        if let Some(v) = obj.get(key).filter(|v| !v.is_null()) {
+            out.insert("max_tokens".to_string(), v.clone());
+        }
+    }
+    // OpenAI's temperature range is 0–2, Claude's 0–1.
+    if let Some(t) = obj.get("temperature").and_then(|v| v.as_f64()) {
+        out.insert("temperature".to_string(), json!(t.clamp(0.0, 1.0)));
+    }
+    // `top_k` isn't part of the OpenAI schema, but clients written against
+    // several providers send it anyway.
+    for key in ["top_p", "top_k"] {
+        if let Some(v) = obj.get(key).filter(|v| !v.is_null()) {
+            out.insert(key.to_string(), v.clone());
+        }
+    }
+    let mut dropped: Vec<&'static str> = UNSUPPORTED_PARAMS
+        .iter()
+        .copied()
+        .filter(|key| {
+            obj.get(*key)
+                .and_then(|v| v.as_f64())
+                .is_some_and(|v| v != 0.0)
+        })
+        .collect();
+    dropped.extend(crate::transforms::openai::requested_logprobs(body));
+    if obj.get("seed").is_some_and(|v| !v.is_null()) {
+        dropped.push("seed");
+    }
+    match obj.get("stop") {
+        Some(Value::String(s)) => {
+            out.insert("stop_sequences".to_string(), json!([s]));
+        }
+        Some(Value::Array(a)) if !a.is_empty() => {
+            out.insert("stop_sequences".to_string(), Value::Array(a.clone()));
+        }
+        _ => {}
+    }
+
+    if let Some(tools) = obj.get("tools").and_then(|v| v.as_array()) {
+        let tools = tools
+            .iter()
+            .map(tool_to_claude)
+            .collect::<Result<Vec<_>>>()?;
+        if !tools.is_empty() {
+            out.insert("tools".to_string(), Value::Array(tools));
+        }
+    }
+    if let Some(choice) = tool_choice(obj) {
+        out.insert("tool_choice".to_string(), choice);
+    }
+
+    *body = Value::Object(out);
+    Ok(dropped)
+}
+
+/// Append a message, merging it into the previous one when the roles match:
+/// Claude requires strict user/assistant alternation, while OpenAI sends one
+/// `tool` message per call result.
+fn push_message(messages: &mut Vec<Value>, role: &str, blocks: Vec<Value>) {
+    if let Some(last) = messages.last_mut()
+        && last["role"] == role
+        && let Some(content) = last["content"].as_array_mut()
+    {
+        content.extend(blocks);
+        return;
+    }
+    messages.push(json!({"role": role, "content": blocks}));
+}
+
+/// Map OpenAI message `content` (string or content-part array) to Claude blocks.
+fn content_blocks(content: Option<&Value>, index: usize) -> Result<Vec<Value>> {
+    match content {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::String(text)) if text.is_empty() => Ok(Vec::new()),
+        Some(Value::String(text)) => Ok(vec![json!({"type": "text", "text": text})]),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item.get("type").and_then(|v| v.as_str()) {
+                Some("text") => Ok(json!({
+                    "type": "text",
+                    "text": item.get("text").and_then(|v| v.as_str()).unwrap_or(""),
+                })),
+                Some("image_url") => {
+                    let url = item
+                        .get("image_url")
+                        .and_then(|u| u.get("url").or(Some(u)))
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("");
+                    let Some((media_type, data)) = parse_data_url(url) else {
+                        bail!(
+                            "message at index {index}: only base64 data: image URLs can be translated to Claude (enable image_fetch to inline remote images)"
+                        );
+                    };
+                    Ok(json!({
+                        "type": "image",
+                        "source": {"type": "base64", "media_type": media_type, "data": data},
+                    }))
+                }
+                Some("file") => file_to_document(item, index),
+                other => bail!(
+                    "message at index {index}: content part type {:?} cannot be translated to Claude",
+                    other.unwrap_or("<missing>")
+                ),
+            })
+            .collect(),
+        Some(_) => bail!("message at index {index}: content must be a string or an array"),
+    }
+}
+
+/// Map an OpenAI `file` content part to a Claude `document` block. Claude
+/// takes PDFs as base64 and other documents only as plain text.
+fn file_to_document(item: &Value, index: usize) -> Result<Value> {
+    let file = item.get("file").unwrap_or(&Value::Null);
+    let Some((media_type, data)) = file
+        .get("file_data")
+        .and_then(|v| v.as_str())
+        .and_then(parse_data_url)
+    else {
+        return unsupported(format!(
+            "message at index {index}: only files sent inline as base64 `file_data` can be translated to Claude"
+        ));
+    };
+    let mut block = if media_type == PDF_MEDIA_TYPE {
+        json!({
+            "type": "document",
+            "source": {"type": "base64", "media_type": media_type, "data": data},
+        })
+    } else if let Some(text) = extract_text(media_type, data) {
+        json!({
+            "type": "document",
+            "source": {"type": "text", "media_type": "text/plain", "data": text},
+        })
+    } else if is_text_media(media_type) {
+        return unsupported(format!(
+            "message at index {index}: {media_type} file cannot be inlined as text for Claude: {}",
+            extraction_hint()
+        ));
+    } else {
+        return unsupported(format!(
+            "message at index {index}: {media_type} files cannot be translated to Claude (PDF and text only)"
+        ));
+    };
+    if let Some(name) = file.get("filename").and_then(|v| v.as_str()) {
+        block["title"] = json!(name);
+    }
+    Ok(block)
+}
+
+/// Flatten text-only content (system prompts, tool results) to a string.
+fn content_text(content: Option<&Value>, index: usize) -> Result<String> {
+    Ok(content_blocks(content, index)?
+        .iter()
+        .map(|block| match block.get("text").and_then(|v| v.as_str()) {
+            Some(text) => Ok(text),
+            None => bail!("message at index {index}: only text content is allowed here"),
+        })
+        .collect::<Result<Vec<_>>>()?
+        .join(""))
+}
+
+fn tool_call_to_block(call: &Value, index: usize) -> Result<Value> {
+    let function = call.get("function").unwrap_or(&Value::Null);
+    let Some(name) = function.get("name").and_then(|v| v.as_str()) else {
+        bail!("message at index {index}: tool call without a function name");
+    };
+    // `arguments` is a JSON-encoded string; Claude wants the object itself.
+    let input = match function.get("arguments").and_then(|v| v.as_str()) {
+        None | Some("") => json!({}),
+        Some(raw) => serde_json::from_str(raw).map_err(|e| {
+            anyhow::anyhow!("message at index {index}: tool call arguments are not valid JSON: {e}")
+        })?,
+    };
+    Ok(json!({
+        "type": "tool_use",
+        "id": call.get("id").and_then(|v| v.as_str()).unwrap_or_default(),
+        "name": name,
+        "input": input,
+    }))
+}
+
+fn tool_to_claude(tool: &Value) -> Result<Value> {
+    if tool.get("type").and_then(|v| v.as_str()) != Some("function") {
+        bail!("only function tools can be translated to Claude");
+    }
+    let function = tool.get("function").unwrap_or(&Value::Null);
+    let Some(name) = function.get("name").and_then(|v| v.as_str()) else {
+        bail!("function tool without a name");
+    };
+    let mut out = json!({
+        "name": name,
+        "input_schema": function
+            .get("parameters")
+            .cloned()
+            .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+    });
+    if let Some(description) = function.get("description") {
+        out["description"] = description.clone();
+    }
+    Ok(out)
+}
+
+/// `tool_choice` plus `parallel_tool_calls: false`, in Claude's shape.
+fn tool_choice(obj: &Map<String, Value>) -> Option<Value> {
+    let mut choice = match obj.get("tool_choice") {
+        Some(Value::String(s)) => match s.as_str() {
+            "none" => json!({"type": "none"}),
+            "required" => json!({"type": "any"}),
+            _ => json!({"type": "auto"}),
+        },
+        Some(Value::Object(o)) => match o
+            .get("function")
+            .and_then(|f| f.get("name"))
+            .and_then(|v| v.as_str())
+        {
+            Some(name) => json!({"type": "tool", "name": name}),
+            None => json!({"type": "auto"}),
+        },
+        _ => json!({"type": "auto"}),
+    };
+    if obj.get("parallel_tool_calls").and_then(|v| v.as_bool()) == Some(false) {
+        choice["disable_parallel_tool_use"] = json!(true);
+    } else if obj.get("tool_choice").is_none() {
+        return None;
+    }
+    Some(choice)
+}
+
+/// Map a Claude `stop_reason` to OpenAI's `finish_reason`.
+fn finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "max_tokens" | "model_context_window_exceeded" => "length",
+        "tool_use" => "tool_calls",
+        "refusal" => "content_filter",
+        _ => "stop",
+    }
+}
+
+/// OpenAI usage from Claude's, counting cached prompt tokens as prompt tokens
+/// the way OpenAI does.
+fn usage_to_openai(input: u64, output: u64, cache_read: u64, cache_write: u64) -> Value {
+    let prompt = input + cache_read + cache_write;
+    json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": output,
+        "total_tokens": prompt + output,
+        "prompt_tokens_details": {"cached_tokens": cache_read},
+    })
+}
+
+fn usage_field(usage: &Value, key: &str) -> Option<u64> {
+    usage.get(key).and_then(|v| v.as_u64())
+}
+
+fn completion_id() -> String {
+    format!("chatcmpl-{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Translate a non-streaming Claude message into an OpenAI `chat.completion`.
+/// `include_reasoning` surfaces `thinking` blocks as `reasoning_content`.
+pub fn response_to_openai(body: &Value, model: &str, include_reasoning: bool) -> Value {
+    let blocks = body
+        .get("content")
+        .and_then(|c| c.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let text: String = blocks
+        .iter()
+        .filter(|b| b.get("type").and_then(|v| v.as_str()) == Some("text"))
+        .filter_map(|b| b.get("text").and_then(|v| v.as_str()))
+        .collect();
+    let tool_calls: Vec<Value> = blocks
+        .iter()
+        .filter(|b| b.get("type").and_then(|v| v.as_str()) == Some("tool_use"))
+        .map(|b| {
+            json!({
+                "id": b.get("id").cloned().unwrap_or(Value::Null),
+                "type": "function",
+                "function": {
+                    "name": b.get("name").cloned().unwrap_or(Value::Null),
+                    "arguments": b.get("input").unwrap_or(&json!({})).to_string(),
+                },
+            })
+        })
+        .collect();
+
+    let mut message = json!({
+        "role": "assistant",
+        "content": if text.is_empty() && !tool_calls.is_empty() { Value::Null } else { json!(text) },
+    });
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+    if include_reasoning {
+        let reasoning: String = blocks
+            .iter()
+            .filter(|b| b.get("type").and_then(|v| v.as_str()) == Some("thinking"))
+            .filter_map(|b| b.get("thinking").and_then(|v| v.as_str()))
+            .collect();
+        if !reasoning.is_empty() {
+            message["reasoning_content"] = json!(reasoning);
+        }
+    }
+
+    let mut out = json!({
+        "id": completion_id(),
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason(
+                body.get("stop_reason").and_then(|v| v.as_str()).unwrap_or("end_turn")
+            ),
+        }],
+    });
+    if let Some(usage) = body.get("usage") {
+        let get = |k: &str| usage_field(usage, k).unwrap_or(0);
+        out["usage"] = usage_to_openai(
+            get("input_tokens"),
+            get("output_tokens"),
+            get("cache_read_input_tokens"),
+            get("cache_creation_input_tokens"),
+        );
+    }
+    out
+}
+
+/// Per-stream state for translating Claude Messages stream events into
+/// OpenAI `chat.completion.chunk` events.
+///
+/// Claude numbers content blocks across text and tool use alike, while
+/// OpenAI numbers tool calls on their own, so `tool_indices` maps each
+/// `tool_use` block index to the `tool_calls[].index` it was announced with.
+#[derive(Debug)]
+pub struct StreamState {
+    id: String,
+    created: i64,
+    model: String,
+    include_usage: bool,
+    include_reasoning: bool,
+    tool_indices: HashMap<u64, u64>,
+    input_tokens: u64,
+    output_tokens: u64,
+    cache_read: u64,
+    cache_write: u64,
+    saw_usage: bool,
+}
+
+impl StreamState {
+    pub fn new(model: &str, include_usage: bool, include_reasoning: bool) -> Self {
+        Self {
+            id: completion_id(),
+            created: chrono::Utc::now().timestamp(),
+            model: model.to_string(),
+            include_usage,
+            include_reasoning,
+            tool_indices: HashMap::new(),
+            input_tokens: 0,
+            output_tokens: 0,
+            cache_read: 0,
+            cache_write: 0,
+            saw_usage: false,
+        }
+    }
+
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        })
+    }
+
+    fn record_usage(&mut self, usage: &Value) {
+        self.saw_usage = true;
+        if let Some(v) = usage_field(usage, "input_tokens") {
+            self.input_tokens = v;
+        }
+        if let Some(v) = usage_field(usage, "output_tokens") {
+            self.output_tokens = v;
+        }
+        if let Some(v) = usage_field(usage, "cache_read_input_tokens") {
+            self.cache_read = v;
+        }
+        if let Some(v) = usage_field(usage, "cache_creation_input_tokens") {
+            self.cache_write = v;
+        }
+    }
+
+    /// Translate one upstream `data:` payload. Returns the chunk payloads to
+    /// emit, in order (possibly none).
+    pub fn event(&mut self, parsed: &Value) -> Vec<Value> {
+        let index = parsed.get("index").and_then(|v| v.as_u64()).unwrap_or(0);
+        match parsed.get("type").and_then(|v| v.as_str()) {
+            Some("message_start") => {
+                if let Some(usage) = parsed.get("message").and_then(|m| m.get("usage")) {
+                    self.record_usage(usage);
+                }
+                vec![self.chunk(json!({"role": "assistant", "content": ""}), None)]
+            }
+            Some("content_block_start") => {
+                let block = parsed.get("content_block").unwrap_or(&Value::Null);
+                if block.get("type").and_then(|v| v.as_str()) != Some("tool_use") {
+                    return Vec::new();
+                }
+                let tool_index = self.tool_indices.len() as u64;
+                self.tool_indices.insert(index, tool_index);
+                // The arguments follow as `input_json_delta`s; a non-empty
+                // `input` here only happens for inputs sent whole.
+                let arguments = match block.get("input") {
+                    Some(Value::Object(input)) if !input.is_empty() => {
+                        Value::Object(input.clone()).to_string()
+                    }
+                    _ => String::new(),
+                };
+                vec![self.chunk(
+                    json!({"tool_calls": [{
+                        "index": tool_index,
+                        "id": block.get("id").cloned().unwrap_or(Value::Null),
+                        "type": "function",
+                        "function": {
+                            "name": block.get("name").cloned().unwrap_or(Value::Null),
+                            "arguments": arguments,
+                        },
+                    }]}),
+                    None,
+                )]
+            }
+            Some("content_block_delta") => {
+                let delta = parsed.get("delta").unwrap_or(&Value::Null);
+                match delta.get("type").and_then(|v| v.as_str()) {
+                    Some("text_delta") => {
+                        let text = delta.get("text").and_then(|v| v.as_str()).unwrap_or("");
+                        if text.is_empty() {
+                            return Vec::new();
+                        }
+                        vec![self.chunk(json!({"content": text}), None)]
+                    }
+                    Some("input_json_delta") => {
+                        let partial = delta
+                            .get("partial_json")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("");
+                        let Some(&tool_index) = self.tool_indices.get(&index) else {
+                            return Vec::new();
+                        };
+                        if partial.is_empty() {
+                            return Vec::new();
+                        }
+                        vec![self.chunk(
+                            json!({"tool_calls": [{
+                                "index": tool_index,
+                                "function": {"arguments": partial},
+                            }]}),
+                            None,
+                        )]
+                    }
+                    Some("thinking_delta") if self.include_reasoning => {
+                        let text = delta.get("thinking").and_then(|v| v.as_str()).unwrap_or("");
+                        if text.is_empty() {
+                            return Vec::new();
+                        }
+                        vec![self.chunk(json!({"reasoning_content": text}), None)]
+                    }
+                    // Stripped thinking and signature deltas.
+                    _ => Vec::new(),
+                }
+            }
+            Some("message_delta") => {
+                if let Some(usage) = parsed.get("usage") {
+                    self.record_usage(usage);
+                }
+                match parsed
+                    .get("delta")
+                    .and_then(|d| d.get("stop_reason"))
+                    .and_then(|v| v.as_str())
+                {
+                    Some(reason) => vec![self.chunk(json!({}), Some(finish_reason(reason)))],
+                    None => Vec::new(),
+                }
+            }
+            Some("error") => {
+                let error = parsed.get("error").unwrap_or(&Value::Null);
+                vec![json!({"error": {
+                    "message": error.get("message").cloned().unwrap_or(json!("Upstream stream error")),
+                    "type": error.get("type").cloned().unwrap_or(json!("api_error")),
+                }})]
+            }
+            // ping, content_block_stop, message_stop
+            _ => Vec::new(),
+        }
+    }
+
+    /// Trailing chunks after the upstream ends: the usage-only chunk when the
+    /// client asked for `stream_options.include_usage`.
+    pub fn finish(&mut self) -> Vec<Value> {
+        if !self.include_usage || !self.saw_usage {
+            return Vec::new();
+        }
+        let mut chunk = self.chunk(json!({}), None);
+        chunk["choices"] = json!([]);
+        chunk["usage"] = usage_to_openai(
+            self.input_tokens,
+            self.output_tokens,
+            self.cache_read,
+            self.cache_write,
+        );
+        vec![chunk]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transforms::documents::UnsupportedContent;
+
+    #[test]
+    fn request_maps_system_tools_and_tool_round_trip() {
+        let mut body = json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": "weather in Paris and Rome?"},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "weather", "arguments": "{\"city\":\"Paris\"}"}},
+                    {"id": "call_2", "type": "function", "function": {"name": "weather", "arguments": "{\"city\":\"Rome\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "sunny"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "rainy"}
+            ],
+            "tools": [{"type": "function", "function": {
+                "name": "weather", "description": "Get the weather",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }}],
+            "tool_choice": "required",
+            "parallel_tool_calls": false,
+            "max_completion_tokens": 256,
+            "stop": "END",
+            "stream": true
+        });
+        assert!(request_to_claude(&mut body).unwrap().is_empty());
+
+        assert_eq!(body["system"], json!("be brief"));
+        assert_eq!(body["max_tokens"], json!(256));
+        assert_eq!(body["stop_sequences"], json!(["END"]));
+        assert_eq!(
+            body["tool_choice"],
+            json!({"type": "any", "disable_parallel_tool_use": true})
+        );
+        assert_eq!(body["tools"][0]["name"], json!("weather"));
+        assert_eq!(body["tools"][0]["input_schema"]["type"], json!("object"));
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"][1]["type"], json!("tool_use"));
+        assert_eq!(messages[1]["content"][1]["input"], json!({"city": "Rome"}));
+        // Both tool results land in a single user turn.
+        assert_eq!(messages[2]["role"], json!("user"));
+        assert_eq!(
+            messages[2]["content"],
+            json!([
+                {"type": "tool_result", "tool_use_id": "call_1", "content": "sunny"},
+                {"type": "tool_result", "tool_use_id": "call_2", "content": "rainy"}
+            ])
+        );
+    }
+
+    #[test]
+    fn request_maps_sampling_params_and_reports_dropped_penalties() {
+        let mut body = json!({
+            "messages": [{"role": "user", "content": "hi"}],
+            "temperature": 1.6,
+            "top_p": 0.9,
+            "top_k": 20,
+            "stop": ["a", "b"],
+            "frequency_penalty": 0.5,
+            "presence_penalty": 0,
+            "logprobs": true,
+            "seed": 7
+        });
+        let dropped = request_to_claude(&mut body).unwrap();
+        assert_eq!(dropped, ["frequency_penalty", "logprobs", "seed"]);
+        assert_eq!(body["temperature"], json!(1.0));
+        assert_eq!(body["top_p"], json!(0.9));
+        assert_eq!(body["top_k"], json!(20));
+        assert_eq!(body["stop_sequences"], json!(["a", "b"]));
+        assert!(body.get("frequency_penalty").is_none());
+        assert!(body.get("presence_penalty").is_none());
+    }
+
+    #[test]
+    fn request_rejects_invalid_tool_arguments_and_remote_images() {
+        let mut body = json!({"messages": [
+            {"role": "assistant", "tool_calls": [{"id": "c", "function": {"name": "f", "arguments": "{oops"}}]}
+        ]});
+        assert!(request_to_claude(&mut body).is_err());
+
+        let mut body = json!({"messages": [{"role": "user", "content": [
+            {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}}
+        ]}]});
+        assert!(request_to_claude(&mut body).is_err());
+    }
+
+    #[test]
+    fn request_maps_pdf_files_to_documents_and_rejects_other_media() {
+        let mut body = json!({"messages": [{"role": "user", "content": [
+            {"type": "file", "file": {"filename": "report.pdf", "file_data": "data:application/pdf;base64,JVBERi0="}},
+            {"type": "text", "text": "summarize"}
+        ]}]});
+        request_to_claude(&mut body).unwrap();
+        assert_eq!(
+            body["messages"][0]["content"][0],
+            json!({
+                "type": "document",
+                "title": "report.pdf",
+                "source": {"type": "base64", "media_type": "application/pdf", "data": "JVBERi0="},
+            })
+        );
+
+        let rejected = |part: Value| {
+            let mut body = json!({"messages": [{"role": "user", "content": [part]}]});
+            let err = request_to_claude(&mut body).unwrap_err();
+            assert!(err.downcast_ref::<UnsupportedContent>().is_some(), "{err}");
+        };
+        rejected(json!({"type": "file", "file": {"file_id": "file-abc"}}));
+        rejected(json!({"type": "file", "file": {
+            "file_data": "data:application/vnd.openxmlformats-officedocument.wordprocessingml.document;base64,UEs="
+        }}));
+
+        // "a,b" in base64: inlined as text only with local extraction.
+        let csv = json!({"type": "file", "file": {"file_data": "data:text/csv;base64,YSxi"}});
+        if cfg!(feature = "document-text") {
+            let mut body = json!({"messages": [{"role": "user", "content": [csv]}]});
+            request_to_claude(&mut body).unwrap();
+            assert_eq!(
+                body["messages"][0]["content"][0]["source"],
+                json!({"type": "text", "media_type": "text/plain", "data": "a,b"})
+            );
+        } else {
+            rejected(csv);
+        }
+    }
+
+    #[test]
+    fn response_translates_tool_use_and_usage() {
+        let body = json!({
+            "content": [
+                {"type": "text", "text": "Checking."},
+                {"type": "tool_use", "id": "toolu_1", "name": "weather", "input": {"city": "Paris"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 10, "output_tokens": 5, "cache_read_input_tokens": 90}
+        });
+        let out = response_to_openai(&body, "claude-sonnet-4-5", false);
+        let choice = &out["choices"][0];
+        assert_eq!(choice["finish_reason"], json!("tool_calls"));
+        assert_eq!(choice["message"]["content"], json!("Checking."));
+        assert_eq!(choice["message"]["tool_calls"][0]["id"], json!("toolu_1"));
+        assert_eq!(
+            choice["message"]["tool_calls"][0]["function"]["arguments"],
+            json!("{\"city\":\"Paris\"}")
+        );
+        assert_eq!(out["usage"]["prompt_tokens"], json!(100));
+        assert_eq!(
+            out["usage"]["prompt_tokens_details"]["cached_tokens"],
+            json!(90)
+        );
+    }
+
+    #[test]
+    fn stream_translates_tool_use_blocks_into_indexed_tool_call_deltas() {
+        let mut state = StreamState::new("claude-sonnet-4-5", true, false);
+        let events = [
+            json!({"type": "message_start", "message": {"usage": {"input_tokens": 12, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Let me check."}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_a", "name": "weather", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": ""}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"city\": "}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"Paris\"}"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "content_block_start", "index": 2, "content_block": {"type": "tool_use", "id": "toolu_b", "name": "time", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 2, "delta": {"type": "input_json_delta", "partial_json": "{}"}}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 40}}),
+            json!({"type": "message_stop"}),
+        ];
+        let chunks: Vec<Value> = events.iter().flat_map(|e| state.event(e)).collect();
+        let deltas: Vec<&Value> = chunks.iter().map(|c| &c["choices"][0]["delta"]).collect();
+
+        assert_eq!(deltas[0]["role"], json!("assistant"));
+        assert_eq!(deltas[1]["content"], json!("Let me check."));
+
+        let first = &deltas[2]["tool_calls"][0];
+        assert_eq!(first["index"], json!(0));
+        assert_eq!(first["id"], json!("toolu_a"));
+        assert_eq!(first["function"]["name"], json!("weather"));
+        assert_eq!(first["function"]["arguments"], json!(""));
+        let arguments: String = deltas[3..5]
+            .iter()
+            .map(|d| {
+                assert_eq!(d["tool_calls"][0]["index"], json!(0));
+                assert!(d["tool_calls"][0].get("id").is_none());
+                d["tool_calls"][0]["function"]["arguments"]
+                    .as_str()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(arguments, "{\"city\": \"Paris\"}");
+
+        assert_eq!(deltas[5]["tool_calls"][0]["index"], json!(1));
+        assert_eq!(deltas[5]["tool_calls"][0]["id"], json!("toolu_b"));
+        assert_eq!(deltas[6]["tool_calls"][0]["index"], json!(1));
+        assert_eq!(
+            chunks[7]["choices"][0]["finish_reason"],
+            json!("tool_calls")
+        );
+        assert_eq!(chunks.len(), 8);
+        assert!(chunks.iter().all(|c| c["id"] == chunks[0]["id"]));
+
+        let tail = state.finish();
+        assert_eq!(tail[0]["choices"], json!([]));
+        assert_eq!(tail[0]["usage"]["prompt_tokens"], json!(12));
+        assert_eq!(tail[0]["usage"]["completion_tokens"], json!(40));
+    }
+
+    #[test]
+    fn thinking_becomes_reasoning_content_only_when_included() {
+        let body = json!({
+            "content": [
+                {"type": "thinking", "thinking": "2+2 is 4", "signature": "sig"},
+                {"type": "redacted_thinking", "data": "opaque"},
+                {"type": "text", "text": "4"}
+            ],
+            "stop_reason": "end_turn"
+        });
+        let message =
+            &response_to_openai(&body, "claude-sonnet-4-5", true)["choices"][0]["message"];
+        assert_eq!(message["reasoning_content"], json!("2+2 is 4"));
+        assert_eq!(message["content"], json!("4"));
+        let message =
+            &response_to_openai(&body, "claude-sonnet-4-5", false)["choices"][0]["message"];
+        assert!(message.get("reasoning_content").is_none());
+
+        let events = [
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "2+2 is 4"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "sig"}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": "4"}}),
+        ];
+        let mut state = StreamState::new("claude-sonnet-4-5", false, true);
+        let deltas: Vec<Value> = events
+            .iter()
+            .flat_map(|e| state.event(e))
+            .map(|c| c["choices"][0]["delta"].clone())
+            .collect();
+        assert_eq!(
+            deltas,
+            vec![
+                json!({"reasoning_content": "2+2 is 4"}),
+                json!({"content": "4"})
+            ]
+        );
+
+        let mut state = StreamState::new("claude-sonnet-4-5", false, false);
+        let chunks: Vec<Value> = events.iter().flat_map(|e| state.event(e)).collect();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0]["choices"][0]["delta"], json!({"content": "4"}));
+    }
+}
@@ -0,0 +1,966 @@
+//! OpenAI Chat Completions ⇄ Gemini `generateContent` translation.
+//!
+//! Lets clients that speak the OpenAI chat schema — including tools pointed at
+//! Google's own OpenAI-compatibility surface (`/v1beta/openai/chat/completions`)
+//! — target Gemini deployments on AI Core, which only expose the native
+//! `generateContent` / `streamGenerateContent` actions.
+//!
+//! Covered: system / developer prompts, user and assistant text, inline
+//! (`data:` URL) images, the common sampling knobs, and function calling —
+//! `tools` / `tool_choice` become `functionDeclarations` / `toolConfig`,
+//! assistant `tool_calls` and `tool` messages become `functionCall` /
+//! `functionResponse` parts, and `functionCall` parts come back as
+//! `tool_calls`. Requests using remote image URLs are rejected with a clear
+//! message rather than being silently degraded.
+//!
+//! Thought summaries (`thought: true` parts) are stripped unless the API key
+//! opts into `reasoning: include`; then they are requested with
+//! `thinkingConfig.includeThoughts` and returned as `reasoning_content`.
+//!
+//! The function-calling helpers are shared with `claude_gemini`, which maps
+//! the same Gemini parts to Anthropic `tool_use` / `tool_result` blocks.
+//!
+//! Source-of-truth references:
+//! * Gemini OpenAI compatibility: <https://ai.google.dev/gemini-api/docs/openai>
+//! * Generate Content API: <https://ai.google.dev/api/generate-content>
+//! * Chat Completions API: <https://platform.openai.com/docs/api-reference/chat/create>
+
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use serde_json::{Map, Value, json};
+
+use crate::transforms::documents::{
+    PDF_MEDIA_TYPE, extract_text, extraction_hint, is_text_media, unsupported,
+};
+
+/// Rewrite an OpenAI chat request body into a Gemini `generateContent` body.
+pub fn request_to_gemini(body: &mut Value) -> Result<()> {
+    let Some(obj) = body.as_object() else {
+        return Ok(());
+    };
+
+    let mut system_parts: Vec<Value> = Vec::new();
+    let mut contents: Vec<Value> = Vec::new();
+    // `functionResponse` needs the function name; OpenAI `tool` messages only
+    // carry the id of the call they answer.
+    let mut call_names: HashMap<String, String> = HashMap::new();
+    for (i, message) in obj
+        .get("messages")
+        .and_then(|v| v.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+    {
+        let role = message.get("role").and_then(|v| v.as_str()).unwrap_or("");
+        match role {
+            "system" | "developer" => {
+                system_parts.extend(content_to_parts(message.get("content"), i)?)
+            }
+            "user" => push_content(
+                &mut contents,
+                "user",
+                content_to_parts(message.get("content"), i)?,
+            ),
+            "assistant" => {
+                let mut parts = content_to_parts(message.get("content"), i)?;
+                for call in message
+                    .get("tool_calls")
+                    .and_then(|v| v.as_array())
+                    .map(Vec::as_slice)
+                    .unwrap_or_default()
+                {
+                    let function = call.get("function").unwrap_or(&Value::Null);
+                    let Some(name) = function.get("name").and_then(|v| v.as_str()) else {
+                        bail!("message at index {i}: tool call without a function name");
+                    };
+                    if let Some(id) = call.get("id").and_then(|v| v.as_str()) {
+                        call_names.insert(id.to_string(), name.to_string());
+                    }
+                    let args = match function.get("arguments").and_then(|v| v.as_str()) {
+                        None | Some("") => json!({}),
+                        Some(raw) => serde_json::from_str(raw).map_err(|e| {
+                            anyhow::anyhow!(
+                                "message at index {i}: tool call arguments are not valid JSON: {e}"
+                            )
+                        })?,
+                    };
+                    parts.push(function_call_part(name, args));
+                }
+                push_content(&mut contents, "model", parts);
+            }
+            "tool" => {
+                let name = message
+                    .get("tool_call_id")
+                    .and_then(|v| v.as_str())
+                    .and_then(|id| call_names.get(id).map(String::as_str))
+                    .or_else(|| message.get("name").and_then(|v| v.as_str()));
+                let Some(name) = name else {
+                    bail!("message at index {i}: tool message does not answer a known tool call");
+                };
+                let output: String = content_to_parts(message.get("content"), i)?
+                    .iter()
+                    .filter_map(|p| p.get("text").and_then(|v| v.as_str()))
+                    .collect();
+                push_content(
+                    &mut contents,
+                    "user",
+                    vec![function_response_part(name, &output, false)],
+                );
+            }
+            other => bail!("message at index {i}: role '{other}' cannot be translated to Gemini"),
+        }
+    }
+
+    let mut out = Map::new();
+    out.insert("contents".to_string(), Value::Array(contents));
+    if !system_parts.is_empty() {
+        out.insert(
+            "systemInstruction".to_string(),
+            json!({"parts": system_parts}),
+        );
+    }
+    if let Some(tools) = obj.get("tools").and_then(|v| v.as_array()) {
+        let declarations = tools
+            .iter()
+            .map(|tool| {
+                if tool.get("type").and_then(|v| v.as_str()) != Some("function") {
+                    bail!("only function tools can be translated to Gemini");
+                }
+                let function = tool.get("function").unwrap_or(&Value::Null);
+                let Some(name) = function.get("name").and_then(|v| v.as_str()) else {
+                    bail!("function tool without a name");
+                };
+                Ok(function_declaration(
+                    name,
+                    function.get("description"),
+                    function.get("parameters"),
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if !declarations.is_empty() {
+            out.insert(
+                "tools".to_string(),
+                json!([{"functionDeclarations": declarations}]),
+            );
+        }
+    }
+    let mode = match obj.get("tool_choice") {
+        Some(Value::String(s)) => match s.as_str() {
+            "none" => Some(("NONE", None)),
+            "required" => Some(("ANY", None)),
+            _ => Some(("AUTO", None)),
+        },
+        Some(Value::Object(o)) => o
+            .get("function")
+            .and_then(|f| f.get("name"))
+            .and_then(|v| v.as_str())
+            .map(|name| ("ANY", Some(name))),
+        _ => None,
+    };
+    if let Some((mode, only)) = mode {
+        out.insert("toolConfig".to_string(), tool_config(mode, only));
+    }
+
+    let generation_config = generation_config(obj);
+    if !generation_config.is_empty() {
+        out.insert(
+            "generationConfig".to_string(),
+            Value::Object(generation_config),
+        );
+    }
+
+    *body = Value::Object(out);
+    Ok(())
+}
+
+/// Ask Gemini to return thought summaries, unless the request already
+/// configures `includeThoughts` itself.
+pub fn request_thoughts(body: &mut Value) {
+    let Some(obj) = body.as_object_mut() else {
+        return;
+    };
+    let thinking = obj
+        .entry("generationConfig")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .map(|config| config.entry("thinkingConfig").or_insert_with(|| json!({})));
+    if let Some(Value::Object(thinking)) = thinking {
+        thinking
+            .entry("includeThoughts")
+            .or_insert(Value::Bool(true));
+    }
+}
+
+/// Append a turn, merging it into the previous one when the roles match, so
+/// the responses to parallel calls land in a single turn as Gemini expects.
+pub(crate) fn push_content(contents: &mut Vec<Value>, role: &str, parts: Vec<Value>) {
+    if let Some(last) = contents.last_mut()
+        && last["role"] == role
+        && let Some(existing) = last["parts"].as_array_mut()
+    {
+        existing.extend(parts);
+        return;
+    }
+    contents.push(json!({"role": role, "parts": parts}));
+}
+
+pub(crate) fn function_call_part(name: &str, args: Value) -> Value {
+    json!({"functionCall": {"name": name, "args": args}})
+}
+
+/// A `functionResponse` part. Gemini wants an object: JSON object output is
+/// passed as-is, anything else is wrapped under `content` (or `error` for a
+/// failed call).
+pub(crate) fn function_response_part(name: &str, output: &str, is_error: bool) -> Value {
+    let response = match serde_json::from_str::<Value>(output) {
+        Ok(Value::Object(obj)) if !is_error => Value::Object(obj),
+        _ if is_error => json!({"error": output}),
+        _ => json!({"content": output}),
+    };
+    json!({"functionResponse": {"name": name, "response": response}})
+}
+
+/// A `functionDeclarations` entry. The schema goes into
+/// `parametersJsonSchema`, which takes full JSON Schema rather than the
+/// OpenAPI subset `parameters` accepts.
+pub(crate) fn function_declaration(
+    name: &str,
+    description: Option<&Value>,
+    schema: Option<&Value>,
+) -> Value {
+    let mut declaration = json!({"name": name});
+    if let Some(description) = description.filter(|v| !v.is_null()) {
+        declaration["description"] = description.clone();
+    }
+    if let Some(schema) = schema.filter(|v| !v.is_null()) {
+        declaration["parametersJsonSchema"] = schema.clone();
+    }
+    declaration
+}
+
+/// `toolConfig.functionCallingConfig` for a mode (`AUTO`, `ANY`, `NONE`),
+/// optionally restricted to one function.
+pub(crate) fn tool_config(mode: &str, only: Option<&str>) -> Value {
+    let mut config = json!({"mode": mode});
+    if let Some(name) = only {
+        config["allowedFunctionNames"] = json!([name]);
+    }
+    json!({"functionCallingConfig": config})
+}
+
+/// The `functionCall` parts of a candidate, as `(id, name, args)`. Gemini
+/// only sometimes assigns call ids, so missing ones are generated.
+pub(crate) fn candidate_function_calls(candidate: &Value) -> Vec<(String, String, Value)> {
+    candidate
+        .get("content")
+        .and_then(|c| c.get("parts"))
+        .and_then(|p| p.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|p| p.get("functionCall"))
+        .map(|call| {
+            (
+                call.get("id")
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple())),
+                call.get("name")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                call.get("args").cloned().unwrap_or_else(|| json!({})),
+            )
+        })
+        .collect()
+}
+
+fn openai_tool_call(id: &str, name: &str, args: &Value) -> Value {
+    json!({
+        "id": id,
+        "type": "function",
+        "function": {"name": name, "arguments": args.to_string()},
+    })
+}
+
+/// Map OpenAI message `content` (string or content-part array) to Gemini parts.
+fn content_to_parts(content: Option<&Value>, index: usize) -> Result<Vec<Value>> {
+    match content {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::String(text)) => Ok(vec![json!({"text": text})]),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item.get("type").and_then(|v| v.as_str()) {
+                Some("text") => Ok(json!({
+                    "text": item.get("text").and_then(|v| v.as_str()).unwrap_or("")
+                })),
+                Some("image_url") => {
+                    let url = item
+                        .get("image_url")
+                        .and_then(|u| u.get("url").or(Some(u)))
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("");
+                    image_url_to_part(url, index)
+                }
+                Some("file") => {
+                    let file = item.get("file").unwrap_or(&Value::Null);
+                    let Some((media_type, data)) = file
+                        .get("file_data")
+                        .and_then(|v| v.as_str())
+                        .and_then(parse_data_url)
+                    else {
+                        return unsupported(format!(
+                            "message at index {index}: only files sent inline as base64 `file_data` can be translated to Gemini"
+                        ));
+                    };
+                    document_part(media_type, data, index)
+                }
+                other => bail!(
+                    "message at index {index}: content part type {:?} cannot be translated to Gemini",
+                    other.unwrap_or("<missing>")
+                ),
+            })
+            .collect(),
+        Some(_) => bail!("message at index {index}: content must be a string or an array"),
+    }
+}
+
+fn image_url_to_part(url: &str, index: usize) -> Result<Value> {
+    let Some((mime_type, data)) = parse_data_url(url) else {
+        bail!(
+            "message at index {index}: only base64 data: image URLs can be translated to Gemini (enable image_fetch to inline remote images)"
+        );
+    };
+    Ok(json!({"inlineData": {"mimeType": mime_type, "data": data}}))
+}
+
+/// Split `data:<mime>;base64,<payload>` into `(mime, payload)`.
+/// Map a base64 document to a Gemini part: PDFs and `text/*` files go inline
+/// as they are, other text-like files are decoded to a text part.
+pub(crate) fn document_part(media_type: &str, data: &str, index: usize) -> Result<Value> {
+    let mime_type = media_type.split(';').next().unwrap_or(media_type).trim();
+    if mime_type == PDF_MEDIA_TYPE || mime_type.starts_with("text/") {
+        return Ok(json!({"inlineData": {"mimeType": mime_type, "data": data}}));
+    }
+    if let Some(text) = extract_text(media_type, data) {
+        return Ok(json!({"text": text}));
+    }
+    if is_text_media(media_type) {
+        return unsupported(format!(
+            "message at index {index}: {media_type} file cannot be inlined as text for Gemini: {}",
+            extraction_hint()
+        ));
+    }
+    unsupported(format!(
+        "message at index {index}: {media_type} files cannot be translated to Gemini (PDF and text only)"
+    ))
+}
+
+pub(crate) fn parse_data_url(url: &str) -> Option<(&str, &str)> {
+    let rest = url.strip_prefix("data:")?;
+    let (meta, data) = rest.split_once(',')?;
+    let mime_type = meta.strip_suffix(";base64")?;
+    Some((mime_type, data))
+}
+
+fn generation_config(obj: &Map<String, Value>) -> Map<String, Value> {
+    let mut config = Map::new();
+    let mut copy = |from: &str, to: &str| {
+        if let Some(v) = obj.get(from).filter(|v| !v.is_null()) {
+            config.insert(to.to_string(), v.clone());
+        }
+    };
+    copy("temperature", "temperature");
+    copy("top_p", "topP");
+    copy("top_k", "topK");
+    copy("frequency_penalty", "frequencyPenalty");
+    copy("presence_penalty", "presencePenalty");
+    copy("n", "candidateCount");
+    copy("seed", "seed");
+    copy("max_tokens", "maxOutputTokens");
+    // The canonical field wins when a client sends both.
+    copy("max_completion_tokens", "maxOutputTokens");
+
+    match obj.get("stop") {
+        Some(Value::String(s)) => {
+            config.insert("stopSequences".to_string(), json!([s]));
+        }
+        Some(Value::Array(a)) if !a.is_empty() => {
+            config.insert("stopSequences".to_string(), Value::Array(a.clone()));
+        }
+        _ => {}
+    }
+
+    if let Some(format) = obj.get("response_format")
+        && matches!(
+            format.get("type").and_then(|v| v.as_str()),
+            Some("json_object" | "json_schema")
+        )
+    {
+        config.insert("responseMimeType".to_string(), json!("application/json"));
+        if let Some(schema) = format.get("json_schema").and_then(|s| s.get("schema")) {
+            config.insert("responseJsonSchema".to_string(), schema.clone());
+        }
+    }
+    config
+}
+
+/// Map a Gemini `finishReason` to OpenAI's `finish_reason`. Gemini reports
+/// `STOP` after function calls too; callers override that with `tool_calls`.
+fn finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY" => {
+            "content_filter"
+        }
+        _ => "stop",
+    }
+}
+
+/// Concatenate a candidate's visible text parts. Thought summaries
+/// (`thought: true`) are not part of the answer and are dropped.
+pub(crate) fn candidate_text(candidate: &Value) -> String {
+    parts_text(candidate, false)
+}
+
+/// Concatenate a candidate's thought summaries.
+fn candidate_thoughts(candidate: &Value) -> String {
+    parts_text(candidate, true)
+}
+
+fn parts_text(candidate: &Value, thought: bool) -> String {
+    candidate
+        .get("content")
+        .and_then(|c| c.get("parts"))
+        .and_then(|p| p.as_array())
+        .map(|parts| {
+            parts
+                .iter()
+                .filter(|p| (p.get("thought").and_then(|v| v.as_bool()) == Some(true)) == thought)
+                .filter_map(|p| p.get("text").and_then(|v| v.as_str()))
+                .collect::<String>()
+        })
+        .unwrap_or_default()
+}
+
+fn usage_from_metadata(metadata: &Value) -> Value {
+    let get = |k: &str| metadata.get(k).and_then(|v| v.as_u64()).unwrap_or(0);
+    let prompt = get("promptTokenCount");
+    // Thinking tokens are billed as output, so fold them into completion.
+    let completion = get("candidatesTokenCount") + get("thoughtsTokenCount");
+    let mut usage = json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": metadata
+            .get("totalTokenCount")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(prompt + completion),
+    });
+    if let Some(cached) = metadata
+        .get("cachedContentTokenCount")
+        .and_then(|v| v.as_u64())
+    {
+        usage["prompt_tokens_details"] = json!({"cached_tokens": cached});
+    }
+    usage
+}
+
+fn completion_id() -> String {
+    format!("chatcmpl-{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Translate a non-streaming Gemini response into an OpenAI `chat.completion`.
+/// `include_reasoning` surfaces thought summaries as `reasoning_content`.
+pub fn response_to_openai(body: &Value, model: &str, include_reasoning: bool) -> Value {
+    let choices: Vec<Value> = body
+        .get("candidates")
+        .and_then(|c| c.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let calls = candidate_function_calls(candidate);
+            let text = candidate_text(candidate);
+            let mut message = json!({"role": "assistant", "content": text});
+            if include_reasoning {
+                let thoughts = candidate_thoughts(candidate);
+                if !thoughts.is_empty() {
+                    message["reasoning_content"] = json!(thoughts);
+                }
+            }
+            let mut finish = finish_reason(
+                candidate
+                    .get("finishReason")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("STOP"),
+            );
+            if !calls.is_empty() {
+                if text.is_empty() {
+                    message["content"] = Value::Null;
+                }
+                message["tool_calls"] = calls
+                    .iter()
+                    .map(|(id, name, args)| openai_tool_call(id, name, args))
+                    .collect();
+                if finish == "stop" {
+                    finish = "tool_calls";
+                }
+            }
+            json!({
+                "index": candidate.get("index").and_then(|v| v.as_u64()).unwrap_or(i as u64),
+                "message": message,
+                "finish_reason": finish,
+            })
+        })
+        .collect();
+
+    let mut out = json!({
+        "id": completion_id(),
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": model,
+        "choices": choices,
+    });
+    if let Some(metadata) = body.get("usageMetadata") {
+        out["usage"] = usage_from_metadata(metadata);
+    }
+    out
+}
+
+/// Per-stream state for translating Gemini `streamGenerateContent` chunks into
+/// OpenAI `chat.completion.chunk` events. One id / timestamp is shared by
+/// every chunk of a response, and the assistant role is announced once.
+/// Gemini sends each `functionCall` whole, so every call becomes a single
+/// `tool_calls` delta carrying its complete arguments.
+#[derive(Debug)]
+pub struct StreamState {
+    id: String,
+    created: i64,
+    model: String,
+    include_usage: bool,
+    include_reasoning: bool,
+    role_sent: bool,
+    usage: Option<Value>,
+    tool_calls: u64,
+}
+
+impl StreamState {
+    pub fn new(model: &str, include_usage: bool, include_reasoning: bool) -> Self {
+        Self {
+            id: completion_id(),
+            created: chrono::Utc::now().timestamp(),
+            model: model.to_string(),
+            include_usage,
+            include_reasoning,
+            role_sent: false,
+            usage: None,
+            tool_calls: 0,
+        }
+    }
+
+    fn chunk(&self, choices: Value) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": choices,
+        })
+    }
+
+    /// Translate one upstream `data:` payload. Returns the chunk payloads to
+    /// emit, in order (possibly none).
+    pub fn event(&mut self, parsed: &Value) -> Vec<Value> {
+        if let Some(metadata) = parsed.get("usageMetadata") {
+            self.usage = Some(usage_from_metadata(metadata));
+        }
+        let Some(candidates) = parsed.get("candidates").and_then(|c| c.as_array()) else {
+            return Vec::new();
+        };
+
+        let mut choices = Vec::with_capacity(candidates.len());
+        for (i, candidate) in candidates.iter().enumerate() {
+            let mut delta = Map::new();
+            if !self.role_sent {
+                delta.insert("role".to_string(), json!("assistant"));
+            }
+            if self.include_reasoning {
+                let thoughts = candidate_thoughts(candidate);
+                if !thoughts.is_empty() {
+                    delta.insert("reasoning_content".to_string(), json!(thoughts));
+                }
+            }
+            let text = candidate_text(candidate);
+            if !text.is_empty() {
+                delta.insert("content".to_string(), json!(text));
+            }
+            let calls = candidate_function_calls(candidate);
+            if !calls.is_empty() {
+                let deltas: Vec<Value> = calls
+                    .iter()
+                    .map(|(id, name, args)| {
+                        let mut call = openai_tool_call(id, name, args);
+                        call["index"] = json!(self.tool_calls);
+                        self.tool_calls += 1;
+                        call
+                    })
+                    .collect();
+                delta.insert("tool_calls".to_string(), Value::Array(deltas));
+            }
+            let finish = candidate
+                .get("finishReason")
+                .and_then(|v| v.as_str())
+                .map(finish_reason)
+                .map(|f| {
+                    if f == "stop" && self.tool_calls > 0 {
+                        "tool_calls"
+                    } else {
+                        f
+                    }
+                });
+            if delta.is_empty() && finish.is_none() {
+                continue;
+            }
+            choices.push(json!({
+                "index": candidate.get("index").and_then(|v| v.as_u64()).unwrap_or(i as u64),
+                "delta": delta,
+                "finish_reason": finish,
+            }));
+        }
+        if choices.is_empty() {
+            return Vec::new();
+        }
+        self.role_sent = true;
+        vec![self.chunk(Value::Array(choices))]
+    }
+
+    /// Trailing chunks after the upstream ends: the usage-only chunk when the
+    /// client asked for `stream_options.include_usage`.
+    pub fn finish(&mut self) -> Vec<Value> {
+        match (self.include_usage, self.usage.take()) {
+            (true, Some(usage)) => {
+                let mut chunk = self.chunk(json!([]));
+                chunk["usage"] = usage;
+                vec![chunk]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_maps_roles_system_and_generation_config() {
+        let mut body = json!({
+            "model": "gemini-2.5-flash",
+            "messages": [
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": "hello"},
+                {"role": "user", "content": [{"type": "text", "text": "again"}]}
+            ],
+            "temperature": 0.2,
+            "top_p": 0.9,
+            "frequency_penalty": 0.3,
+            "max_tokens": 100,
+            "stop": "END",
+            "stream": true
+        });
+        request_to_gemini(&mut body).unwrap();
+
+        assert!(body.get("model").is_none());
+        assert!(body.get("messages").is_none());
+        assert_eq!(
+            body["systemInstruction"],
+            json!({"parts": [{"text": "be brief"}]})
+        );
+        let contents = body["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 3);
+        assert_eq!(contents[1]["role"], json!("model"));
+        assert_eq!(contents[2]["parts"], json!([{"text": "again"}]));
+        let config = &body["generationConfig"];
+        assert_eq!(config["temperature"], json!(0.2));
+        assert_eq!(config["topP"], json!(0.9));
+        assert_eq!(config["frequencyPenalty"], json!(0.3));
+        assert_eq!(config["maxOutputTokens"], json!(100));
+        assert_eq!(config["stopSequences"], json!(["END"]));
+    }
+
+    #[test]
+    fn request_prefers_max_completion_tokens() {
+        let mut body = json!({
+            "messages": [{"role": "user", "content": "hi"}],
+            "max_tokens": 10,
+            "max_completion_tokens": 20
+        });
+        request_to_gemini(&mut body).unwrap();
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], json!(20));
+    }
+
+    #[test]
+    fn request_maps_data_url_images_to_inline_data() {
+        let mut body = json!({
+            "messages": [{"role": "user", "content": [
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+            ]}]
+        });
+        request_to_gemini(&mut body).unwrap();
+        assert_eq!(
+            body["contents"][0]["parts"][0],
+            json!({"inlineData": {"mimeType": "image/png", "data": "AAAA"}})
+        );
+    }
+
+    #[test]
+    fn request_rejects_remote_images() {
+        let mut body = json!({
+            "messages": [{"role": "user", "content": [
+                {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}}
+            ]}]
+        });
+        assert!(request_to_gemini(&mut body).is_err());
+    }
+
+    #[test]
+    fn request_maps_inline_files_to_inline_data() {
+        let mut body = json!({
+            "messages": [{"role": "user", "content": [
+                {"type": "file", "file": {"file_data": "data:application/pdf;base64,JVBERi0="}},
+                {"type": "file", "file": {"file_data": "data:text/csv;charset=utf-8;base64,YSxi"}}
+            ]}]
+        });
+        request_to_gemini(&mut body).unwrap();
+        let parts = &body["contents"][0]["parts"];
+        assert_eq!(
+            parts[0],
+            json!({"inlineData": {"mimeType": "application/pdf", "data": "JVBERi0="}})
+        );
+        assert_eq!(parts[1]["inlineData"]["mimeType"], json!("text/csv"));
+
+        let mut body = json!({
+            "messages": [{"role": "user", "content": [
+                {"type": "file", "file": {"file_data": "data:application/zip;base64,UEs="}}
+            ]}]
+        });
+        let err = request_to_gemini(&mut body).unwrap_err();
+        assert!(
+            err.downcast_ref::<crate::transforms::documents::UnsupportedContent>()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn request_maps_tools_tool_choice_and_call_round_trip() {
+        let mut body = json!({
+            "messages": [
+                {"role": "user", "content": "weather in Paris and Rome?"},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "weather", "arguments": "{\"city\":\"Paris\"}"}},
+                    {"id": "call_2", "type": "function", "function": {"name": "weather", "arguments": "{\"city\":\"Rome\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "{\"sky\":\"sunny\"}"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "rainy"}
+            ],
+            "tools": [{"type": "function", "function": {
+                "name": "weather", "description": "Get the weather",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }}],
+            "tool_choice": {"type": "function", "function": {"name": "weather"}}
+        });
+        request_to_gemini(&mut body).unwrap();
+
+        let declaration = &body["tools"][0]["functionDeclarations"][0];
+        assert_eq!(declaration["name"], json!("weather"));
+        assert_eq!(declaration["parametersJsonSchema"]["type"], json!("object"));
+        assert_eq!(
+            body["toolConfig"],
+            json!({"functionCallingConfig": {"mode": "ANY", "allowedFunctionNames": ["weather"]}})
+        );
+
+        let contents = body["contents"].as_array().unwrap();
+        assert_eq!(contents.len(), 3);
+        assert_eq!(
+            contents[1]["parts"][1],
+            json!({"functionCall": {"name": "weather", "args": {"city": "Rome"}}})
+        );
+        assert_eq!(
+            contents[2]["parts"],
+            json!([
+                {"functionResponse": {"name": "weather", "response": {"sky": "sunny"}}},
+                {"functionResponse": {"name": "weather", "response": {"content": "rainy"}}}
+            ])
+        );
+    }
+
+    #[test]
+    fn request_rejects_tool_messages_for_unknown_calls() {
+        let mut body = json!({"messages": [
+            {"role": "tool", "tool_call_id": "call_x", "content": "42"}
+        ]});
+        assert!(request_to_gemini(&mut body).is_err());
+    }
+
+    #[test]
+    fn function_calls_come_back_as_tool_calls() {
+        let body = json!({"candidates": [{
+            "content": {"role": "model", "parts": [
+                {"functionCall": {"name": "weather", "args": {"city": "Paris"}}}
+            ]},
+            "finishReason": "STOP"
+        }]});
+        let out = response_to_openai(&body, "gemini-2.5-pro", false);
+        let choice = &out["choices"][0];
+        assert_eq!(choice["finish_reason"], json!("tool_calls"));
+        assert_eq!(choice["message"]["content"], Value::Null);
+        let call = &choice["message"]["tool_calls"][0];
+        assert!(call["id"].as_str().unwrap().starts_with("call_"));
+        assert_eq!(call["function"]["name"], json!("weather"));
+        assert_eq!(call["function"]["arguments"], json!("{\"city\":\"Paris\"}"));
+
+        let mut state = StreamState::new("gemini-2.5-pro", false, false);
+        state.event(&json!({"candidates": [{"content": {"parts": [
+            {"functionCall": {"name": "a", "args": {}}}
+        ]}}]}));
+        let second = state.event(&json!({"candidates": [{"content": {"parts": [
+            {"functionCall": {"name": "b", "args": {}}}
+        ]}, "finishReason": "STOP"}]}));
+        let choice = &second[0]["choices"][0];
+        assert_eq!(choice["delta"]["tool_calls"][0]["index"], json!(1));
+        assert_eq!(
+            choice["delta"]["tool_calls"][0]["function"]["name"],
+            json!("b")
+        );
+        assert_eq!(choice["finish_reason"], json!("tool_calls"));
+    }
+
+    #[test]
+    fn request_maps_json_response_format() {
+        let mut body = json!({
+            "messages": [{"role": "user", "content": "hi"}],
+            "response_format": {"type": "json_schema", "json_schema": {"name": "x", "schema": {"type": "object"}}}
+        });
+        request_to_gemini(&mut body).unwrap();
+        assert_eq!(
+            body["generationConfig"]["responseMimeType"],
+            json!("application/json")
+        );
+        assert_eq!(
+            body["generationConfig"]["responseJsonSchema"],
+            json!({"type": "object"})
+        );
+    }
+
+    #[test]
+    fn response_translates_text_finish_reason_and_usage() {
+        let body = json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [
+                    {"text": "thinking...", "thought": true},
+                    {"text": "Hello"},
+                    {"text": " there"}
+                ]},
+                "finishReason": "MAX_TOKENS",
+                "index": 0
+            }],
+            "usageMetadata": {
+                "promptTokenCount": 5,
+                "candidatesTokenCount": 2,
+                "thoughtsTokenCount": 3,
+                "totalTokenCount": 10
+            }
+        });
+        let out = response_to_openai(&body, "gemini-2.5-pro", false);
+        assert_eq!(out["object"], json!("chat.completion"));
+        assert_eq!(out["model"], json!("gemini-2.5-pro"));
+        assert_eq!(
+            out["choices"][0]["message"]["content"],
+            json!("Hello there")
+        );
+        assert_eq!(out["choices"][0]["finish_reason"], json!("length"));
+        assert_eq!(out["usage"]["prompt_tokens"], json!(5));
+        assert_eq!(out["usage"]["completion_tokens"], json!(5));
+        assert_eq!(out["usage"]["total_tokens"], json!(10));
+        assert!(
+            out["choices"][0]["message"]
+                .get("reasoning_content")
+                .is_none()
+        );
+
+        let out = response_to_openai(&body, "gemini-2.5-pro", true);
+        assert_eq!(
+            out["choices"][0]["message"]["reasoning_content"],
+            json!("thinking...")
+        );
+        assert_eq!(
+            out["choices"][0]["message"]["content"],
+            json!("Hello there")
+        );
+    }
+
+    #[test]
+    fn included_reasoning_requests_and_streams_thoughts() {
+        let mut body = json!({"generationConfig": {"temperature": 0.5}});
+        request_thoughts(&mut body);
+        assert_eq!(
+            body["generationConfig"]["thinkingConfig"]["includeThoughts"],
+            json!(true)
+        );
+        let mut body = json!({"generationConfig": {"thinkingConfig": {"includeThoughts": false}}});
+        request_thoughts(&mut body);
+        assert_eq!(
+            body["generationConfig"]["thinkingConfig"]["includeThoughts"],
+            json!(false)
+        );
+
+        let mut state = StreamState::new("gemini-2.5-flash", false, true);
+        let chunks = state.event(&json!({
+            "candidates": [{"content": {"parts": [{"text": "hmm", "thought": true}]}, "index": 0}]
+        }));
+        assert_eq!(
+            chunks[0]["choices"][0]["delta"]["reasoning_content"],
+            json!("hmm")
+        );
+        assert!(chunks[0]["choices"][0]["delta"].get("content").is_none());
+    }
+
+    #[test]
+    fn stream_announces_role_once_and_emits_usage_when_requested() {
+        let mut state = StreamState::new("gemini-2.5-flash", true, false);
+        let first = state.event(&json!({
+            "candidates": [{"content": {"parts": [{"text": "Hel"}]}, "index": 0}]
+        }));
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0]["object"], json!("chat.completion.chunk"));
+        assert_eq!(first[0]["choices"][0]["delta"]["role"], json!("assistant"));
+        assert_eq!(first[0]["choices"][0]["delta"]["content"], json!("Hel"));
+
+        let second = state.event(&json!({
+            "candidates": [{"content": {"parts": [{"text": "lo"}]}, "finishReason": "STOP", "index": 0}],
+            "usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 2, "totalTokenCount": 5}
+        }));
+        assert!(second[0]["choices"][0]["delta"].get("role").is_none());
+        assert_eq!(second[0]["choices"][0]["finish_reason"], json!("stop"));
+        assert_eq!(second[0]["id"], first[0]["id"]);
+
+        let tail = state.finish();
+        assert_eq!(tail.len(), 1);
+        assert_eq!(tail[0]["choices"], json!([]));
+        assert_eq!(tail[0]["usage"]["total_tokens"], json!(5));
+    }
+
+    #[test]
+    fn stream_omits_usage_chunk_unless_requested() {
+        let mut state = StreamState::new("gemini-2.5-flash", false, false);
+        state.event(&json!({
+            "candidates": [{"content": {"parts": [{"text": "x"}]}, "finishReason": "STOP"}],
+            "usageMetadata": {"promptTokenCount": 1}
+        }));
+        assert!(state.finish().is_empty());
+    }
+}
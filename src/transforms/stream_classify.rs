@@ -47,9 +47,10 @@ pub fn classify_first_event(data: &str, family: &LlmFamily) -> EventDisposition
     };
     match family {
         LlmFamily::Claude => classify_claude(&parsed),
-        LlmFamily::OpenAi => classify_openai_chat(&parsed),
+        LlmFamily::OpenAi | LlmFamily::OpenWeight => classify_openai_chat(&parsed),
         LlmFamily::OpenAiResponses => classify_openai_responses(&parsed),
         LlmFamily::Gemini => classify_gemini(&parsed),
+        LlmFamily::Passthrough => EventDisposition::Content,
     }
 }
 
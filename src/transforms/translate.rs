@@ -0,0 +1,275 @@
+//! Cross-family translation dispatch.
+//!
+//! The route fixes the schema the client speaks; `proxy::determine_family`
+//! fixes the schema the upstream deployment speaks. When they differ and acr
+//! knows how to bridge the pair, a [`Translation`] rewrites the request body
+//! on the way out and the response (unary body or SSE stream) on the way back.
+//! Token accounting always reads the *upstream* payloads, before translation,
+//! so the existing per-family extractors keep working unchanged.
+//!
+//! Pairs without a translation fall through to the pre-existing behavior:
+//! the body is forwarded as-is and the upstream decides.
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::config::ReasoningContent;
+use crate::constants::api::STREAM_DATA_PREFIX;
+use crate::proxy::LlmFamily;
+use crate::transforms::{claude_gemini, claude_openai, openai_claude, openai_gemini};
+
+/// A supported (client family → upstream family) bridge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Translation {
+    /// OpenAI Chat Completions client, Gemini upstream.
+    OpenAiToGemini {
+        include_usage: bool,
+        include_reasoning: bool,
+    },
+    /// OpenAI Chat Completions client, Claude upstream.
+    OpenAiToClaude {
+        include_usage: bool,
+        include_reasoning: bool,
+    },
+    /// Anthropic Messages client, Gemini upstream.
+    ClaudeToGemini,
+    /// Anthropic Messages client, OpenAI or open-weight (Chat Completions)
+    /// upstream.
+    ClaudeToOpenAi,
+}
+
+impl Translation {
+    /// Pick the translation for a client/upstream pair, if one exists.
+    /// `body` is the client's request, consulted for per-request options;
+    /// `reasoning` is the API key's reasoning policy.
+    pub fn select(
+        client: LlmFamily,
+        upstream: LlmFamily,
+        body: &Value,
+        reasoning: ReasoningContent,
+    ) -> Option<Self> {
+        let include_usage = body
+            .get("stream_options")
+            .and_then(|o| o.get("include_usage"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let include_reasoning = reasoning == ReasoningContent::Include;
+        match (client, upstream) {
+            (LlmFamily::OpenAi, LlmFamily::Gemini) => Some(Translation::OpenAiToGemini {
+                include_usage,
+                include_reasoning,
+            }),
+            (LlmFamily::OpenAi, LlmFamily::Claude) => Some(Translation::OpenAiToClaude {
+                include_usage,
+                include_reasoning,
+            }),
+            (LlmFamily::Claude, LlmFamily::Gemini) => Some(Translation::ClaudeToGemini),
+            (LlmFamily::Claude, LlmFamily::OpenAi | LlmFamily::OpenWeight) => {
+                Some(Translation::ClaudeToOpenAi)
+            }
+            _ => None,
+        }
+    }
+
+    /// Rewrite the client's request body into the upstream family's shape.
+    /// Runs before the upstream family's own `prepare` step. Returns the
+    /// client parameters the upstream family has no equivalent for, which
+    /// were dropped rather than forwarded.
+    pub fn request(&self, body: &mut Value) -> Result<Vec<&'static str>> {
+        match self {
+            Translation::OpenAiToGemini {
+                include_reasoning, ..
+            } => {
+                // Gemini honours `seed`, but its log probabilities don't map
+                // onto OpenAI's response shape.
+                let dropped = crate::transforms::openai::requested_logprobs(body);
+                openai_gemini::request_to_gemini(body)?;
+                if *include_reasoning {
+                    openai_gemini::request_thoughts(body);
+                }
+                Ok(dropped)
+            }
+            Translation::OpenAiToClaude { .. } => openai_claude::request_to_claude(body),
+            Translation::ClaudeToGemini => {
+                claude_gemini::request_to_gemini(body).map(|()| Vec::new())
+            }
+            Translation::ClaudeToOpenAi => claude_openai::request_to_openai(body),
+        }
+    }
+
+    /// Translate a complete (non-streaming) upstream response body. Bodies
+    /// that aren't JSON are returned unchanged.
+    pub fn response(&self, body: &[u8], model: &str) -> Vec<u8> {
+        let Ok(parsed) = serde_json::from_slice::<Value>(body) else {
+            return body.to_vec();
+        };
+        let translated = match self {
+            Translation::OpenAiToGemini {
+                include_reasoning, ..
+            } => openai_gemini::response_to_openai(&parsed, model, *include_reasoning),
+            Translation::OpenAiToClaude {
+                include_reasoning, ..
+            } => openai_claude::response_to_openai(&parsed, model, *include_reasoning),
+            Translation::ClaudeToGemini => claude_gemini::response_to_claude(&parsed, model),
+            Translation::ClaudeToOpenAi => claude_openai::response_to_claude(&parsed, model),
+        };
+        translated.to_string().into_bytes()
+    }
+
+    /// Start translating an upstream SSE stream.
+    pub fn stream(&self, model: &str) -> StreamTranslator {
+        match *self {
+            Translation::OpenAiToGemini {
+                include_usage,
+                include_reasoning,
+            } => StreamTranslator::OpenAiFromGemini(openai_gemini::StreamState::new(
+                model,
+                include_usage,
+                include_reasoning,
+            )),
+            Translation::OpenAiToClaude {
+                include_usage,
+                include_reasoning,
+            } => StreamTranslator::OpenAiFromClaude(openai_claude::StreamState::new(
+                model,
+                include_usage,
+                include_reasoning,
+            )),
+            Translation::ClaudeToGemini => {
+                StreamTranslator::ClaudeFromGemini(claude_gemini::StreamState::new(model))
+            }
+            Translation::ClaudeToOpenAi => {
+                StreamTranslator::ClaudeFromOpenAi(claude_openai::StreamState::new(model))
+            }
+        }
+    }
+}
+
+/// Stateful per-stream translator. Each upstream `data:` payload becomes zero
+/// or more fully-framed SSE events in the client's schema.
+#[derive(Debug)]
+pub enum StreamTranslator {
+    OpenAiFromGemini(openai_gemini::StreamState),
+    OpenAiFromClaude(openai_claude::StreamState),
+    ClaudeFromGemini(claude_gemini::StreamState),
+    ClaudeFromOpenAi(claude_openai::StreamState),
+}
+
+impl StreamTranslator {
+    /// Translate one upstream `data:` payload into client SSE frames.
+    /// Unparseable payloads are forwarded verbatim rather than dropped.
+    pub fn event(&mut self, data: &str) -> String {
+        let Ok(parsed) = serde_json::from_str::<Value>(data) else {
+            return format!("{STREAM_DATA_PREFIX}{data}\n\n");
+        };
+        match self {
+            StreamTranslator::OpenAiFromGemini(state) => openai_frames(state.event(&parsed)),
+            StreamTranslator::OpenAiFromClaude(state) => openai_frames(state.event(&parsed)),
+            StreamTranslator::ClaudeFromGemini(state) => claude_frames(state.event(&parsed)),
+            StreamTranslator::ClaudeFromOpenAi(state) => claude_frames(state.event(&parsed)),
+        }
+    }
+
+    /// Frames to send after the upstream stream ends.
+    pub fn finish(&mut self) -> String {
+        let chunks = match self {
+            StreamTranslator::OpenAiFromGemini(state) => state.finish(),
+            StreamTranslator::OpenAiFromClaude(state) => state.finish(),
+            // Anthropic streams end with `message_stop`, not a sentinel.
+            StreamTranslator::ClaudeFromGemini(state) => return claude_frames(state.finish()),
+            StreamTranslator::ClaudeFromOpenAi(state) => return claude_frames(state.finish()),
+        };
+        let mut out = openai_frames(chunks);
+        out.push_str(&format!("{STREAM_DATA_PREFIX}[DONE]\n\n"));
+        out
+    }
+}
+
+fn openai_frames(chunks: Vec<Value>) -> String {
+    chunks
+        .into_iter()
+        .map(|chunk| format!("{STREAM_DATA_PREFIX}{chunk}\n\n"))
+        .collect()
+}
+
+/// Anthropic SSE framing: each event is preceded by its `event:` line.
+fn claude_frames(events: Vec<Value>) -> String {
+    events
+        .into_iter()
+        .map(|event| {
+            let kind = event.get("type").and_then(|v| v.as_str()).unwrap_or("");
+            format!("event: {kind}\n{STREAM_DATA_PREFIX}{event}\n\n")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn select_only_bridges_known_pairs() {
+        let body = json!({});
+        let select = |client, upstream| {
+            Translation::select(client, upstream, &body, ReasoningContent::Strip)
+        };
+        assert!(select(LlmFamily::OpenAi, LlmFamily::Gemini).is_some());
+        assert!(select(LlmFamily::OpenAi, LlmFamily::Claude).is_some());
+        assert!(select(LlmFamily::Claude, LlmFamily::Gemini).is_some());
+        assert!(select(LlmFamily::Claude, LlmFamily::OpenAi).is_some());
+        assert!(select(LlmFamily::Claude, LlmFamily::OpenWeight).is_some());
+        assert!(select(LlmFamily::OpenAi, LlmFamily::OpenAi).is_none());
+        assert!(select(LlmFamily::Gemini, LlmFamily::Gemini).is_none());
+        assert!(select(LlmFamily::Claude, LlmFamily::Claude).is_none());
+    }
+
+    #[test]
+    fn select_reads_include_usage_from_client_body_and_reasoning_from_key() {
+        let body = json!({"stream_options": {"include_usage": true}});
+        assert_eq!(
+            Translation::select(
+                LlmFamily::OpenAi,
+                LlmFamily::Gemini,
+                &body,
+                ReasoningContent::Include
+            ),
+            Some(Translation::OpenAiToGemini {
+                include_usage: true,
+                include_reasoning: true,
+            })
+        );
+    }
+
+    #[test]
+    fn gemini_stream_ends_with_done_sentinel() {
+        let translation = Translation::OpenAiToGemini {
+            include_usage: false,
+            include_reasoning: false,
+        };
+        let mut stream = translation.stream("gemini-2.5-flash");
+        let frame = stream.event(r#"{"candidates":[{"content":{"parts":[{"text":"hi"}]}}]}"#);
+        assert!(frame.starts_with("data: {"));
+        assert!(frame.ends_with("\n\n"));
+        assert_eq!(stream.finish(), "data: [DONE]\n\n");
+    }
+
+    #[test]
+    fn gemini_stream_to_claude_uses_event_lines_and_no_sentinel() {
+        let mut stream = Translation::ClaudeToGemini.stream("gemini-2.5-pro");
+        let frames = stream.event(r#"{"candidates":[{"content":{"parts":[{"text":"hi"}]}}]}"#);
+        assert!(frames.starts_with("event: message_start\ndata: {"));
+        let tail = stream.finish();
+        assert!(tail.ends_with("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n"));
+        assert!(!tail.contains("[DONE]"));
+    }
+
+    #[test]
+    fn non_json_response_passes_through() {
+        let translation = Translation::OpenAiToGemini {
+            include_usage: false,
+            include_reasoning: false,
+        };
+        assert_eq!(translation.response(b"oops", "m"), b"oops".to_vec());
+    }
+}
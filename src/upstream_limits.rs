@@ -0,0 +1,338 @@
+//! Upstream rate-limit tracking.
+//!
+//! AI Core passes through the rate-limit headers of the model runtimes
+//! behind it: Azure OpenAI's `x-ratelimit-remaining-requests` /
+//! `x-ratelimit-remaining-tokens` with their `x-ratelimit-reset-*`
+//! companions, a plain `x-ratelimit-remaining` on other runtimes, and
+//! `Retry-After` on 429s. acr keeps the latest figures per provider
+//! deployment and consults them when ordering providers for the next
+//! request: deployments close to their limit go behind those with headroom,
+//! and a deployment that answered 429 is skipped until its back-off expires
+//! instead of being sent another request it will reject.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+
+use crate::config::UpstreamLimitsConfig;
+use crate::constants::upstream_limits::{
+    OBSERVATION_TTL_SECS, REMAINING_HEADER, REMAINING_REQUESTS_HEADER, REMAINING_TOKENS_HEADER,
+    RESET_HEADER, RESET_REQUESTS_HEADER, RESET_TOKENS_HEADER,
+};
+
+/// How close a deployment is to its upstream limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pressure {
+    /// No recent signal, or plenty of quota left.
+    Clear,
+    /// Below `min_remaining_requests` or `min_remaining_tokens`.
+    Low,
+    /// Backing off after a 429 or an exhausted window, for this much longer.
+    CoolingDown(Duration),
+}
+
+impl Pressure {
+    /// Sort key for provider ordering: clear deployments first.
+    pub fn rank(&self) -> u8 {
+        match self {
+            Pressure::Clear => 0,
+            Pressure::Low => 1,
+            Pressure::CoolingDown(_) => 2,
+        }
+    }
+
+    /// The more restrictive of two pressures; the longer of two back-offs.
+    pub fn worse(self, other: Self) -> Self {
+        match (self, other) {
+            (Pressure::CoolingDown(a), Pressure::CoolingDown(b)) => Pressure::CoolingDown(a.max(b)),
+            (a, b) if b.rank() > a.rank() => b,
+            (a, _) => a,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Observation {
+    remaining_requests: Option<u64>,
+    remaining_tokens: Option<u64>,
+    /// When the remaining figures stop being meaningful.
+    expires: Instant,
+    cooldown_until: Option<Instant>,
+}
+
+/// Latest rate-limit state per `(provider, deployment)`.
+#[derive(Debug, Clone)]
+pub struct UpstreamLimits {
+    entries: Arc<Mutex<HashMap<(String, String), Observation>>>,
+    min_remaining_requests: u64,
+    min_remaining_tokens: u64,
+    default_cooldown: Duration,
+    max_cooldown: Duration,
+}
+
+impl UpstreamLimits {
+    /// `None` when `upstream_limits.enabled` is off.
+    pub fn from_config(config: &UpstreamLimitsConfig) -> Option<Self> {
+        config.enabled.then(|| Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            min_remaining_requests: config.min_remaining_requests,
+            min_remaining_tokens: config.min_remaining_tokens,
+            default_cooldown: Duration::from_secs(config.default_cooldown_secs),
+            max_cooldown: Duration::from_secs(config.max_cooldown_secs),
+        })
+    }
+
+    /// Record the rate-limit headers of an upstream response. A 429, or a
+    /// window reported as exhausted, starts a back-off; a success without
+    /// any rate-limit headers clears what was known about the deployment.
+    pub fn observe(
+        &self,
+        provider: &str,
+        deployment: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+    ) {
+        let remaining_requests = header_u64(headers, REMAINING_REQUESTS_HEADER)
+            .or(header_u64(headers, REMAINING_HEADER));
+        let remaining_tokens = header_u64(headers, REMAINING_TOKENS_HEADER);
+        let reset_requests = header_duration(headers, RESET_REQUESTS_HEADER)
+            .or(header_duration(headers, RESET_HEADER));
+        let reset_tokens = header_duration(headers, RESET_TOKENS_HEADER);
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let hint = crate::proxy::parse_retry_after(headers)
+                .map(Duration::from_secs)
+                .or(reset_requests.max(reset_tokens));
+            self.throttled(provider, deployment, hint);
+            return;
+        }
+
+        let exhausted = match (remaining_requests, remaining_tokens) {
+            (Some(0), _) => Some(reset_requests),
+            (_, Some(0)) => Some(reset_tokens),
+            _ => None,
+        };
+        if let Some(reset) = exhausted {
+            self.throttled(provider, deployment, reset);
+            return;
+        }
+
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        let key = (provider.to_string(), deployment.to_string());
+        if remaining_requests.is_none() && remaining_tokens.is_none() {
+            if status.is_success() {
+                entries.remove(&key);
+            }
+            return;
+        }
+        let ttl = Duration::from_secs(OBSERVATION_TTL_SECS);
+        let window = reset_requests.max(reset_tokens).unwrap_or(ttl).min(ttl);
+        entries.insert(
+            key,
+            Observation {
+                remaining_requests,
+                remaining_tokens,
+                expires: Instant::now() + window,
+                cooldown_until: None,
+            },
+        );
+    }
+
+    /// Back a deployment off for `hint` (the upstream's own estimate), or
+    /// for `default_cooldown_secs` without one, capped at
+    /// `max_cooldown_secs`. Also used for throttling signalled in-band on a
+    /// streaming response, which has no headers to read.
+    pub fn throttled(&self, provider: &str, deployment: &str, hint: Option<Duration>) {
+        let wait = hint.unwrap_or(self.default_cooldown).min(self.max_cooldown);
+        if wait.is_zero() {
+            return;
+        }
+        tracing::debug!(
+            "Backing off deployment '{}' on provider '{}' for {:?}",
+            deployment,
+            provider,
+            wait
+        );
+        let until = Instant::now() + wait;
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(
+                (provider.to_string(), deployment.to_string()),
+                Observation {
+                    remaining_requests: None,
+                    remaining_tokens: None,
+                    expires: until,
+                    cooldown_until: Some(until),
+                },
+            );
+        }
+    }
+
+    pub fn pressure(&self, provider: &str, deployment: &str) -> Pressure {
+        let Ok(entries) = self.entries.lock() else {
+            return Pressure::Clear;
+        };
+        let Some(observation) = entries.get(&(provider.to_string(), deployment.to_string())) else {
+            return Pressure::Clear;
+        };
+        let now = Instant::now();
+        if let Some(until) = observation.cooldown_until
+            && until > now
+        {
+            return Pressure::CoolingDown(until - now);
+        }
+        if observation.expires <= now {
+            return Pressure::Clear;
+        }
+        let low_requests = observation
+            .remaining_requests
+            .is_some_and(|r| r < self.min_remaining_requests);
+        let low_tokens = observation
+            .remaining_tokens
+            .is_some_and(|t| t < self.min_remaining_tokens);
+        if low_requests || low_tokens {
+            Pressure::Low
+        } else {
+            Pressure::Clear
+        }
+    }
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+}
+
+fn header_duration(headers: &HeaderMap, name: &str) -> Option<Duration> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_reset)
+}
+
+/// Parse a reset hint: plain seconds (`"12"`, `"0.5"`) or a Go-style
+/// duration as Azure OpenAI sends it (`"20ms"`, `"1s"`, `"6m0s"`).
+fn parse_reset(raw: &str) -> Option<Duration> {
+    let raw = raw.trim();
+    if let Ok(secs) = raw.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).ok();
+    }
+    let mut total = Duration::ZERO;
+    let mut rest = raw;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .filter(|&i| i > 0)?;
+        let value: f64 = rest[..digits].parse().ok()?;
+        let after = &rest[digits..];
+        let unit_len = after
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(after.len());
+        let scale = match &after[..unit_len] {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return None,
+        };
+        total += Duration::try_from_secs_f64(value * scale).ok()?;
+        rest = &after[unit_len..];
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> UpstreamLimits {
+        UpstreamLimits::from_config(&UpstreamLimitsConfig::default()).unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn reset_hints_parse_in_both_forms() {
+        assert_eq!(parse_reset("12"), Some(Duration::from_secs(12)));
+        assert_eq!(parse_reset("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_reset("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_reset("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_reset("soon"), None);
+        assert_eq!(parse_reset("5x"), None);
+    }
+
+    #[test]
+    fn low_remaining_quota_ranks_after_clear_deployments() {
+        let limits = limits();
+        limits.observe(
+            "primary",
+            "d1",
+            StatusCode::OK,
+            &headers(&[
+                (REMAINING_REQUESTS_HEADER, "1"),
+                (REMAINING_TOKENS_HEADER, "90000"),
+                (RESET_REQUESTS_HEADER, "10s"),
+            ]),
+        );
+        limits.observe(
+            "secondary",
+            "d2",
+            StatusCode::OK,
+            &headers(&[(REMAINING_HEADER, "40")]),
+        );
+        assert_eq!(limits.pressure("primary", "d1"), Pressure::Low);
+        assert_eq!(limits.pressure("secondary", "d2"), Pressure::Clear);
+        assert_eq!(limits.pressure("primary", "other"), Pressure::Clear);
+
+        // A success without rate-limit headers forgets the old figures.
+        limits.observe("primary", "d1", StatusCode::OK, &HeaderMap::new());
+        assert_eq!(limits.pressure("primary", "d1"), Pressure::Clear);
+    }
+
+    #[test]
+    fn throttling_backs_off_within_the_cap() {
+        let limits = limits();
+        limits.observe(
+            "primary",
+            "d1",
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers(&[("retry-after", "3600")]),
+        );
+        let Pressure::CoolingDown(wait) = limits.pressure("primary", "d1") else {
+            panic!("expected a back-off");
+        };
+        assert!(wait <= Duration::from_secs(60));
+        assert!(wait > Duration::from_secs(55));
+
+        // An exhausted window backs off until its reset even on a 200.
+        limits.observe(
+            "primary",
+            "d2",
+            StatusCode::OK,
+            &headers(&[(REMAINING_TOKENS_HEADER, "0"), (RESET_TOKENS_HEADER, "20s")]),
+        );
+        assert!(matches!(
+            limits.pressure("primary", "d2"),
+            Pressure::CoolingDown(w) if w <= Duration::from_secs(20)
+        ));
+
+        // In-band throttling falls back to the default back-off.
+        limits.throttled("primary", "d3", None);
+        assert!(matches!(
+            limits.pressure("primary", "d3"),
+            Pressure::CoolingDown(w) if w <= Duration::from_secs(5)
+        ));
+    }
+}
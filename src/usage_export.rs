@@ -0,0 +1,227 @@
+//! Raw usage export (`GET /usage/export`).
+//!
+//! Streams the request log one row per request as CSV or JSON Lines, for
+//! billing spreadsheets and data warehouses. Rows are read from the database
+//! a page at a time, so an export of months of traffic never sits in memory.
+//! Each row carries `cost_usd`, estimated from the model's current `pricing`.
+
+use std::borrow::Cow;
+
+use axum::body::Body;
+use axum::http::{StatusCode, header};
+use axum::response::Response;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use futures::StreamExt;
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::constants::usage_export::PAGE_SIZE;
+use crate::database::{Database, ExportRow};
+use crate::proxy::{TokenStats, estimate_cost, format_cost_usd};
+use crate::routes::AppError;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// RFC 3339 timestamp, or a date meaning local midnight
+    pub since: Option<String>,
+}
+
+const CSV_COLUMNS: &[&str] = &[
+    "id",
+    "created_at",
+    "correlation_id",
+    "path",
+    "model",
+    "provider",
+    "duration_ms",
+    "response_status",
+    "streaming",
+    "input_tokens",
+    "output_tokens",
+    "cache_read_tokens",
+    "cache_write_tokens",
+    "cost_usd",
+    "api_key_hash",
+    "session_id",
+];
+
+/// `since` as the UTC `YYYY-MM-DD HH:MM:SS` form `created_at` is stored in.
+pub fn parse_since(since: &str) -> Option<String> {
+    let utc = match DateTime::parse_from_rfc3339(since) {
+        Ok(t) => t.with_timezone(&Utc),
+        Err(_) => NaiveDate::parse_from_str(since, "%Y-%m-%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)?
+            .and_local_timezone(Local)
+            .earliest()?
+            .with_timezone(&Utc),
+    };
+    Some(utc.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+fn cost(config: &Config, row: &ExportRow) -> Option<f64> {
+    let stats = TokenStats {
+        input_tokens: row.input_tokens,
+        output_tokens: row.output_tokens,
+        cache_read: row.cache_read_tokens,
+        cache_write: row.cache_write_tokens,
+        ..Default::default()
+    };
+    estimate_cost(config.get_model_pricing(&row.model), &stats)
+}
+
+/// Quote a CSV field when it holds a delimiter, quote or line break.
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+fn csv_line(row: &ExportRow, cost: Option<f64>) -> String {
+    let opt = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_default();
+    let fields = [
+        row.id.to_string(),
+        row.created_at.clone(),
+        row.correlation_id.clone(),
+        row.path.clone(),
+        row.model.clone(),
+        row.provider.clone(),
+        format!("{:.2}", row.duration_ms),
+        row.response_status.to_string(),
+        row.streaming.to_string(),
+        opt(row.input_tokens),
+        opt(row.output_tokens),
+        opt(row.cache_read_tokens),
+        opt(row.cache_write_tokens),
+        cost.map(format_cost_usd).unwrap_or_default(),
+        row.api_key_hash.clone().unwrap_or_default(),
+        row.session_id.clone().unwrap_or_default(),
+    ];
+    let mut line = fields
+        .iter()
+        .map(|f| csv_field(f))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push('\n');
+    line
+}
+
+fn jsonl_line(row: &ExportRow, cost: Option<f64>) -> String {
+    let mut value = serde_json::to_value(row).unwrap_or_default();
+    value["cost_usd"] = cost.into();
+    format!("{value}\n")
+}
+
+/// Stream every logged request created at or after `since`, limited to
+/// `owner`'s requests when set.
+pub fn stream(
+    database: Database,
+    config: Config,
+    owner: Option<String>,
+    since: String,
+    format: ExportFormat,
+) -> Result<Response, AppError> {
+    let header_line = match format {
+        ExportFormat::Csv => format!("{}\n", CSV_COLUMNS.join(",")),
+        ExportFormat::Jsonl => String::new(),
+    };
+    let pages = futures::stream::try_unfold(Some(0), move |after_id| {
+        let (database, config, owner, since) = (
+            database.clone(),
+            config.clone(),
+            owner.clone(),
+            since.clone(),
+        );
+        async move {
+            let Some(after_id) = after_id else {
+                return Ok(None);
+            };
+            let rows = database
+                .export_requests(owner.as_deref(), &since, after_id, PAGE_SIZE)
+                .await
+                .inspect_err(|e| tracing::warn!("Usage export failed: {}", e))?;
+            let next = if rows.len() < PAGE_SIZE {
+                None
+            } else {
+                rows.last().map(|r| r.id)
+            };
+            let chunk: String = rows
+                .iter()
+                .map(|row| match format {
+                    ExportFormat::Csv => csv_line(row, cost(&config, row)),
+                    ExportFormat::Jsonl => jsonl_line(row, cost(&config, row)),
+                })
+                .collect();
+            Ok::<_, anyhow::Error>(Some((chunk, next)))
+        }
+    });
+    let body = futures::stream::once(async { Ok(header_line) }).chain(pages);
+    let (content_type, file_name) = match format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "usage.csv"),
+        ExportFormat::Jsonl => ("application/x-ndjson", "usage.jsonl"),
+    };
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{file_name}\""),
+        )
+        .body(Body::from_stream(body))
+        .map_err(|e| AppError::Internal(e.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_rows_quote_only_what_needs_it() {
+        let row = ExportRow {
+            id: 7,
+            created_at: "2026-10-01 09:30:00".to_string(),
+            correlation_id: "c1".to_string(),
+            path: "/v1/messages".to_string(),
+            model: "claude, \"sonnet\"".to_string(),
+            provider: "eu".to_string(),
+            duration_ms: 812.4,
+            response_status: 200,
+            streaming: true,
+            input_tokens: Some(100),
+            output_tokens: Some(20),
+            cache_read_tokens: None,
+            cache_write_tokens: None,
+            api_key_hash: Some("abc".to_string()),
+            session_id: None,
+        };
+        assert_eq!(
+            csv_line(&row, Some(0.0006)),
+            "7,2026-10-01 09:30:00,c1,/v1/messages,\"claude, \"\"sonnet\"\"\",eu,812.40,200,true,100,20,,,0.000600,abc,\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(&jsonl_line(&row, None)).unwrap();
+        assert_eq!(json["input_tokens"], 100);
+        assert!(json["cost_usd"].is_null());
+    }
+
+    #[test]
+    fn since_accepts_timestamps_and_dates() {
+        assert_eq!(
+            parse_since("2026-10-01T12:00:00+02:00").as_deref(),
+            Some("2026-10-01 10:00:00")
+        );
+        assert!(parse_since("2026-10-01").is_some());
+        assert_eq!(parse_since("last week"), None);
+    }
+}
@@ -0,0 +1,262 @@
+//! Startup warm-up of model deployments.
+//!
+//! AI Core scales idle deployments down, and the first request after that
+//! waits for a replica to come back. With `warmup.enabled`, acr sends each
+//! resolved deployment a one-token request once the model registry is up,
+//! so that wait happens before users arrive. Warm-up requests skip metrics,
+//! quotas and the request log; their outcomes are reported by `GET /ready`,
+//! which answers 503 until every warm-up request has finished. A failed
+//! warm-up is only reported: the deployment still takes traffic.
+
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use axum::http::{HeaderMap, HeaderValue, Method};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::config::{Config, Provider};
+use crate::constants::api::GENERATE_CONTENT_ACTION;
+use crate::constants::models::TEXT_PREFIX;
+use crate::constants::warmup::{PROMPT, ROUTE};
+use crate::proxy::{LlmFamily, ProxyRequestBuilder, ProxyRequestParams, determine_family};
+use crate::registry::glob_matches;
+use crate::routes::{AppError, AppState};
+
+/// Outcome of one warm-up request.
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmupResult {
+    pub model: String,
+    pub provider: String,
+    pub deployment_id: Option<String>,
+    /// Upstream status; any answer means the deployment is up, even an error.
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WarmupProgress {
+    pub done: bool,
+    pub results: Vec<WarmupResult>,
+}
+
+/// Shared warm-up progress; cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct Warmup {
+    progress: Arc<RwLock<WarmupProgress>>,
+}
+
+impl Warmup {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.warmup.enabled.then(Self::default)
+    }
+
+    pub fn snapshot(&self) -> WarmupProgress {
+        self.progress
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Warm up every selected model on every enabled provider serving it.
+    pub async fn run(&self, state: &AppState) {
+        let timeout = Duration::from_secs(state.config.warmup.timeout_secs);
+        let probes = selected_models(&state.config)
+            .into_iter()
+            .flat_map(|model| {
+                state
+                    .config
+                    .providers
+                    .iter()
+                    .filter(|p| p.enabled)
+                    .map(move |provider| probe(state, model, provider, timeout))
+            });
+        let results: Vec<WarmupResult> = futures::future::join_all(probes)
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let failed = results.iter().filter(|r| r.status.is_none()).count();
+        if failed > 0 {
+            tracing::warn!(
+                "Warm-up finished: {} of {} deployment(s) did not answer",
+                failed,
+                results.len()
+            );
+        } else {
+            tracing::info!("Warm-up finished: {} deployment(s) answered", results.len());
+        }
+        *self.progress.write().unwrap_or_else(|e| e.into_inner()) = WarmupProgress {
+            done: true,
+            results,
+        };
+    }
+}
+
+/// Configured models matching `warmup.models` (all when it is empty).
+fn selected_models(config: &Config) -> Vec<&str> {
+    let patterns = &config.warmup.models;
+    config
+        .models
+        .iter()
+        .map(|m| m.name.as_str())
+        .filter(|name| {
+            patterns.is_empty() || patterns.iter().any(|p| glob_matches(p, name).is_some())
+        })
+        .collect()
+}
+
+/// The smallest request each family accepts, and the action it goes to.
+fn probe_body(model: &str, family: LlmFamily) -> (Value, Option<String>) {
+    let messages = json!([{"role": "user", "content": PROMPT}]);
+    match family {
+        LlmFamily::Claude => (json!({"messages": messages, "max_tokens": 1}), None),
+        LlmFamily::Gemini => (
+            json!({
+                "contents": [{"role": "user", "parts": [{"text": PROMPT}]}],
+                "generationConfig": {"maxOutputTokens": 1}
+            }),
+            Some(GENERATE_CONTENT_ACTION.to_string()),
+        ),
+        LlmFamily::OpenWeight => (json!({"messages": messages, "max_tokens": 1}), None),
+        LlmFamily::OpenAi if model.starts_with(TEXT_PREFIX) => (json!({"input": PROMPT}), None),
+        // Passthrough deployments are usually OpenAI-compatible servers.
+        LlmFamily::OpenAi | LlmFamily::OpenAiResponses | LlmFamily::Passthrough => (
+            json!({"messages": messages, "max_completion_tokens": 1}),
+            None,
+        ),
+    }
+}
+
+/// Send one warm-up request; `None` when the provider doesn't serve `model`.
+async fn probe(
+    state: &AppState,
+    model: &str,
+    provider: &Provider,
+    timeout: Duration,
+) -> Option<WarmupResult> {
+    let family = determine_family(model, &state.config.model_families).ok()?;
+    let (body, action) = probe_body(model, family);
+    let mut headers = HeaderMap::new();
+    headers.insert("x-api-key", HeaderValue::from_static("internal"));
+    let params = ProxyRequestParams {
+        headers: &headers,
+        method: Method::POST,
+        body,
+        model: model.to_string(),
+        action,
+        config: &state.config,
+        token_manager: &state.token_manager,
+        model_registry: &state.model_registry,
+        load_balancer: &state.load_balancer,
+        force_family: None,
+        client_family: family,
+        api_version: None,
+        session_id: None,
+        route: ROUTE,
+        capture: None,
+        image_fetcher: None,
+        reasoning: Default::default(),
+        upstream_limits: None,
+        output_pacer: None,
+    };
+
+    let start = Instant::now();
+    let mut result = WarmupResult {
+        model: model.to_string(),
+        provider: provider.name.clone(),
+        deployment_id: None,
+        status: None,
+        error: None,
+        elapsed_ms: 0,
+    };
+    let proxy = match ProxyRequestBuilder::new(params)
+        .build_for_provider(provider)
+        .await
+    {
+        Ok(proxy) => proxy,
+        Err(AppError::ModelNotAvailableOnProvider { .. }) => return None,
+        Err(e) => {
+            result.error = Some(e.to_string());
+            return Some(result);
+        }
+    };
+    result.deployment_id = Some(proxy.deployment_id.clone());
+
+    let sent = match proxy.upstream_headers() {
+        Ok(headers) => state
+            .client
+            .post(&proxy.url)
+            .headers(headers)
+            .json(&proxy.body)
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(|e| format!("{e}")),
+        Err(e) => Err(format!("{e:#}")),
+    };
+    result.elapsed_ms = start.elapsed().as_millis() as u64;
+    match sent {
+        Ok(response) => {
+            tracing::debug!(
+                "Warm-up of '{}' on provider '{}' answered {} in {}ms",
+                model,
+                provider.name,
+                response.status(),
+                result.elapsed_ms
+            );
+            result.status = Some(response.status().as_u16());
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Warm-up of '{}' on provider '{}' failed: {}",
+                model,
+                provider.name,
+                e
+            );
+            result.error = Some(e);
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes_are_one_token_requests_in_each_family_shape() {
+        let (claude, action) = probe_body("claude-sonnet-4-6", LlmFamily::Claude);
+        assert_eq!(claude["max_tokens"], 1);
+        assert_eq!(action, None);
+
+        let (gemini, action) = probe_body("gemini-2.5-pro", LlmFamily::Gemini);
+        assert_eq!(gemini["generationConfig"]["maxOutputTokens"], 1);
+        assert_eq!(action.as_deref(), Some(GENERATE_CONTENT_ACTION));
+
+        let (embedding, _) = probe_body("text-embedding-3-small", LlmFamily::OpenAi);
+        assert_eq!(embedding, json!({"input": PROMPT}));
+        let (chat, _) = probe_body("gpt-5", LlmFamily::OpenAi);
+        assert_eq!(chat["max_completion_tokens"], 1);
+    }
+
+    #[test]
+    fn warmup_models_filter_configured_models() {
+        let mut config: Config = serde_yaml_ng::from_str(
+            r#"
+providers: []
+api_keys: []
+models:
+  - name: claude-sonnet-4-6
+  - name: gpt-5
+  - name: gemini-2.5-pro
+"#,
+        )
+        .unwrap();
+        assert_eq!(selected_models(&config).len(), 3);
+        config.warmup.models = vec!["claude-*".to_string(), "gpt-5".to_string()];
+        assert_eq!(selected_models(&config), ["claude-sonnet-4-6", "gpt-5"]);
+    }
+}